@@ -4,3 +4,5 @@ rm $HOME/.multirust/toolchains/stable/cargo/bin/hubris
 cargo install --bin hubris --path .
 mkdir -p $HOME/.hubris
 cp -r lib $HOME/.hubris
+rm -rf $HOME/.hubris/rt
+cp -r src/hubris_rt $HOME/.hubris/rt
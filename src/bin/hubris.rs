@@ -6,11 +6,17 @@ extern crate rustc_serialize;
 extern crate docopt;
 
 use docopt::Docopt;
+use std::env;
 use std::path::PathBuf;
 use std::process;
 use std::io;
 
-use hubris::session::{Session, HasSession, Reportable};
+use hubris::backend::{BackendKind, Evaluation, Optimization};
+use hubris::doc::Format;
+use hubris::feature::Features;
+use hubris::lint::{LintLevel, LintLevels};
+use hubris::session::{Session, HasSession, Reportable, Options, Emit};
+use hubris::typeck::{NormalForm, ReduceOptions, Transparency};
 
 const USAGE: &'static str = r#"
 Hubris, version 0.0.1.
@@ -24,24 +30,118 @@ Hubris, version 0.0.1.
 
 Usage:
     hubris repl [<file>]
+    hubris build [<target>]
     hubris server
-    hubris <file> [--output=<exe> --log=<logfile>]
+    hubris references <file> <line> <column>
+    hubris normalize <file> <line> <column> [--whnf --reduce-steps=<n> --reduce-transparency=<mode>]
+    hubris doc <file> [--output=<exe> --format=<fmt>]
+    hubris check <input>... [--output=<exe> --emit=<kind> --timings --verbose-errors --show-implicits --reduce-steps=<n> --reduce-transparency=<mode> --smt-solver=<command> --deny-sorry --validate-core] [--cfg=<flag>]... [-A <lint>]... [-W <lint>]... [-D <lint>]... [-Z <feature>]...
+    hubris <input>... [--output=<exe> --emit=<kind> --backend=<backend> --target=<triple> --evaluation=<strategy> -O <level> -g --timings --term-stats --verbose-errors --show-implicits --reduce-steps=<n> --reduce-transparency=<mode> --smt-solver=<command> --deny-sorry --validate-core --log=<logfile>] [--cfg=<flag>]... [-A <lint>]... [-W <lint>]... [-D <lint>]... [-Z <feature>]...
     hubris (-h | --help)
     hubris --version
 
+Arguments:
+    <input>          A file to compile, or a directory whose source files
+                     are all compiled. A literate `.hub.md` file is compiled
+                     from its untagged and `hubris` code blocks. The files
+                     of a program are compiled in the order they import
+                     each other.
+
 Options:
-    -h --help    Show this screen.
-    --version    Show version.
+    -h --help        Show this screen.
+    --version        Show version.
+    --emit=<kind>    The output to produce, either `exe`, `lib`, `export`, `image`, `resolutions`, `holes`, `sexp`, or `check`.
+                     `ir-after=<pass>` writes the backend's program once the
+                     optimization pass `inline`, `specialize`, `const-fold`,
+                     `uncurry`, or `dce` has run. `check` only checks the
+                     program, and is the default for `hubris check`,
+                     otherwise `exe` is.
+    --output=<exe>   Where to write the output, `-` for standard output. An
+                     <input> of `-` is read from standard input, and the
+                     output written to standard output by default. The
+                     output of several inputs is named after the file
+                     declaring `main`.
+    --backend=<backend>
+                     The backend used to build executables, either `rust`,
+                     `haskell`, or `js` [default: rust].
+    --target=<triple>
+                     The target triple to build executables for, defaults to
+                     the host.
+    --evaluation=<strategy>
+                     How generated code evaluates arguments, either `strict`
+                     or `lazy` [default: strict].
+    -O <level>       The optimization level, either `0`, `1`, or `2` [default: 0].
+    -g               Build programs that print the Hubris definitions being
+                     evaluated when they panic.
+    --format=<fmt>   The format of generated documentation, either
+                     `markdown` or `html` [default: html].
+    --timings        Report the time spent in each phase of the compiler.
+    --term-stats     Report statistics about core term allocation and cloning.
+    --verbose-errors Print terms in error messages in full.
+    --show-implicits Print the implicit arguments the elaborator inferred.
+    --reduce-steps=<n>
+                     The number of steps `#reduce` may take [default: 100000].
+    --reduce-transparency=<mode>
+                     The definitions `#reduce` unfolds, either `all`,
+                     `reducible`, or `none` [default: all].
+    --whnf           Only reduce the term to weak head normal form.
+    --smt-solver=<command>
+                     The SMT solver `smt` sends its goals to, a program
+                     reading SMT-LIB from standard input, such as `z3 -in`.
+    --deny-sorry     Report each use of `sorry` as an error, the same as
+                     `-D sorry`.
+    --validate-core  Check the invariants of each elaborated module, a
+                     debugging aid for the compiler.
+    -A <lint>        Allow the given lint, `warnings` refers to every lint.
+    -W <lint>        Report the given lint as a warning.
+    -D <lint>        Report the given lint as an error. A lint given more
+                     than one level takes the strictest, and a single lint
+                     overrides `warnings`.
+    --cfg=<flag>     Set the flag, compiling the items marked `@[cfg flag]`.
+    -Z <feature>     Enable an unstable language feature, such as
+                     `induction-recursion`.
 "#;
 
 #[derive(Debug, RustcDecodable)]
+#[allow(non_snake_case)]
 struct Args {
     arg_file: Option<String>,
+    arg_input: Vec<String>,
+    arg_target: Option<String>,
     flag_output: Option<String>,
+    flag_emit: Option<String>,
+    flag_backend: String,
+    flag_target: Option<String>,
+    flag_evaluation: String,
+    flag_O: usize,
+    flag_g: bool,
+    flag_format: String,
+    flag_timings: bool,
+    flag_term_stats: bool,
+    flag_verbose_errors: bool,
+    flag_show_implicits: bool,
+    flag_reduce_steps: usize,
+    flag_reduce_transparency: String,
+    flag_whnf: bool,
+    flag_smt_solver: Option<String>,
+    flag_deny_sorry: bool,
+    flag_validate_core: bool,
+    flag_A: Vec<String>,
+    flag_W: Vec<String>,
+    flag_D: Vec<String>,
+    flag_Z: Vec<String>,
+    flag_cfg: Vec<String>,
     flag_logging: Option<String>,
     flag_version: bool,
     cmd_server: bool,
+    cmd_references: bool,
+    cmd_normalize: bool,
+    arg_line: Option<usize>,
+    arg_column: Option<usize>,
     cmd_repl: bool,
+    cmd_build: bool,
+    cmd_doc: bool,
+    cmd_check: bool,
 }
 
 fn main() {
@@ -55,21 +155,108 @@ fn main() {
     driver(args).unwrap();
 }
 
+/// How `#reduce`, and `normalize`, reduce terms.
+fn reduce_options(args: &Args) -> ReduceOptions {
+    let transparency = match Transparency::from_str(&args.flag_reduce_transparency[..]) {
+        None => {
+            println!("hubris: unknown transparency `{}`", args.flag_reduce_transparency);
+            process::exit(1);
+        }
+        Some(t) => t,
+    };
+
+    ReduceOptions {
+        transparency: transparency,
+        max_steps: Some(args.flag_reduce_steps),
+    }
+}
+
+/// The lints named by `-A`, `-W` and `-D`, in the order they are set.
+/// `warnings` is set first so that a flag naming a single lint overrides it,
+/// and a lint named by more than one flag takes the strictest level.
+fn lint_flags(args: &Args) -> Vec<(LintLevel, String)> {
+    let flags =
+        args.flag_A.iter().map(|name| (LintLevel::Allow, name.clone()))
+            .chain(args.flag_W.iter().map(|name| (LintLevel::Warn, name.clone())))
+            .chain(args.flag_D.iter().map(|name| (LintLevel::Deny, name.clone())));
+
+    let (mut groups, lints): (Vec<_>, Vec<_>) =
+        flags.partition(|&(_, ref name)| name == "warnings");
+
+    groups.extend(lints);
+    groups
+}
+
 fn driver(args: Args) -> io::Result<()> {
-    let session = args.arg_file.clone().map(|file_path| {
+    if let Some(ref file_path) = args.arg_file {
         let file_path = PathBuf::from(file_path);
-        if !file_path.is_file() {
+        if file_path != PathBuf::from(hubris::STDIO) && !file_path.is_file() {
                 println!("hubris: file {} does not exist", file_path.display());
                 process::exit(1);
         }
-        Session::from_root(&file_path)
-    }).unwrap_or(Session::empty());
+    }
+
+    for input in &args.arg_input {
+        let input = PathBuf::from(input);
+        if input != PathBuf::from(hubris::STDIO) && !input.exists() {
+                println!("hubris: file {} does not exist", input.display());
+                process::exit(1);
+        }
+    }
+
+    let session = args.arg_file.iter()
+                               .chain(args.arg_input.iter())
+                               .next()
+                               .map(|file_path| Session::from_root(&PathBuf::from(file_path)))
+                               .unwrap_or(Session::empty());
 
     if args.flag_version {
         println!("hubris 0.1.0");
     } else if args.cmd_server {
         println!("Starting Server...");
         hubris::server::run();
+    } else if args.cmd_build {
+        let project_dir = try!(env::current_dir());
+        let target = args.arg_target.as_ref().map(|t| &t[..]);
+
+        match hubris::build_project(&project_dir, target) {
+            Err(e) => try!(session.report(e)),
+            Ok(_) => {}
+        }
+    } else if args.cmd_references {
+        let file = args.arg_file.clone().unwrap();
+        let line = args.arg_line.unwrap_or(1);
+        let column = args.arg_column.unwrap_or(1);
+
+        match hubris::find_references(&file, line, column) {
+            Err(e) => try!(session.report(e)),
+            Ok(_) => {}
+        }
+    } else if args.cmd_normalize {
+        let file = args.arg_file.clone().unwrap();
+        let line = args.arg_line.unwrap_or(1);
+        let column = args.arg_column.unwrap_or(1);
+        let form = if args.flag_whnf { NormalForm::WeakHead } else { NormalForm::Full };
+
+        match hubris::normalize_at(&file, line, column, form, reduce_options(&args)) {
+            Err(e) => try!(session.report(e)),
+            Ok(_) => {}
+        }
+    } else if args.cmd_doc {
+        let file = args.arg_file.clone().unwrap();
+
+        let format = match Format::from_str(&args.flag_format[..]) {
+            None => {
+                println!("hubris: unknown documentation format `{}`", args.flag_format);
+                process::exit(1);
+            }
+            Some(format) => format,
+        };
+
+        match hubris::document_file(&file, args.flag_output.map(|p| PathBuf::from(p)), format) {
+            Err(e) => try!(session.report(e)),
+            Ok(_) => {}
+        }
     } else if args.cmd_repl {
         match hubris::repl::Repl::from_session(session.clone()) {
             Err(e) => session.report(e).unwrap(),
@@ -79,20 +266,101 @@ fn driver(args: Args) -> io::Result<()> {
             }
         }
     } else {
-        let input = match args.arg_file {
+        let reduce = reduce_options(&args);
+
+        if args.arg_input.is_empty() {
+            println!("hubris: no input files");
+            return Ok(());
+        }
+
+        let inputs: Vec<_> = args.arg_input.iter().map(|i| PathBuf::from(i)).collect();
+
+        debug!("main: compiling {:?} output to {:?}",
+               inputs,
+               args.flag_output);
+
+        let default_emit = if args.cmd_check { "check" } else { "exe" };
+        let kind = args.flag_emit.clone().unwrap_or(default_emit.to_string());
+
+        let emit = match Emit::from_str(&kind[..]) {
             None => {
-                println!("hubris: no input files");
-                return Ok(());
+                println!("hubris: unknown output kind `{}`", kind);
+                process::exit(1);
             }
-            Some(file) => file,
+            Some(emit) => emit,
         };
 
-        debug!("main: compiling {} output to {:?}",
-               &input[..],
-               args.flag_output);
+        let backend = match BackendKind::from_str(&args.flag_backend[..]) {
+            None => {
+                println!("hubris: unknown backend `{}`", args.flag_backend);
+                process::exit(1);
+            }
+            Some(backend) => backend,
+        };
+
+        let evaluation = match Evaluation::from_str(&args.flag_evaluation[..]) {
+            None => {
+                println!("hubris: unknown evaluation strategy `{}`", args.flag_evaluation);
+                process::exit(1);
+            }
+            Some(evaluation) => evaluation,
+        };
+
+        let optimization = match Optimization::from_level(args.flag_O) {
+            None => {
+                println!("hubris: unknown optimization level `{}`", args.flag_O);
+                process::exit(1);
+            }
+            Some(optimization) => optimization,
+        };
+
+        let mut lints = LintLevels::default();
+
+        for (level, name) in lint_flags(&args) {
+            if !lints.set(&name, level) {
+                println!("hubris: unknown lint `{}`", name);
+                process::exit(1);
+            }
+        }
+
+        if args.flag_deny_sorry {
+            lints.set("sorry", LintLevel::Deny);
+        }
+
+        let mut features = Features::default();
+
+        for name in &args.flag_Z {
+            if !features.enable(name) {
+                println!("hubris: unknown feature `{}`", name);
+                process::exit(1);
+            }
+        }
+
+        let options = Options {
+            emit: emit,
+            backend: backend,
+            target: args.flag_target,
+            evaluation: evaluation,
+            optimization: optimization,
+            debug_info: args.flag_g,
+            rust_dependencies: vec![],
+            timings: args.flag_timings,
+            term_stats: args.flag_term_stats,
+            verbose_errors: args.flag_verbose_errors,
+            lints: lints,
+            features: features,
+            index: false,
+            show_implicits: args.flag_show_implicits,
+            reduce: reduce,
+            cfg: args.flag_cfg.into_iter().collect(),
+            smt_solver: args.flag_smt_solver,
+            validate_core: args.flag_validate_core,
+        };
 
-        let result = hubris::compile_file(&input[..],
-                                          args.flag_output.map(|p| PathBuf::from(p)));
+        let result = hubris::compile_files_with_options(
+            &inputs[..],
+            args.flag_output.map(|p| PathBuf::from(p)),
+            options);
 
         match result {
             Err(e) => try!(session.report(e)),
@@ -1,10 +1,12 @@
 extern crate hubris;
 extern crate term;
 
+use hubris::session::{Emit, Session};
+
 use std::env;
-use std::fs::read_dir;
+use std::fs::{read_dir, remove_dir_all, remove_file, File};
 use std::path::{Path, PathBuf};
-use std::io;
+use std::io::{self, Read};
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 enum Outcome {
@@ -24,40 +26,118 @@ fn main() {
 fn run_tests_in_dir(path: &Path, expected_outcome: Outcome) -> io::Result<()> {
     let mut should_of_failed = vec![];
     let mut should_of_passed = vec![];
+    let mut missing_output = vec![];
 
     for file in try!(read_dir(path)) {
         let entry = try!(file);
         let test = entry.path();
 
-        let result = hubris::compile_file(
-            &test,
-            Some(PathBuf::from("/tmp/duh")));
+        // Diagnostics are only shown for tests with the wrong outcome.
+        let (session, diagnostics) = Session::buffered(&test);
+
+        // The modules only imported by tests are kept out of the test
+        // directories.
+        session.add_load_path(path.join("../modules"));
+
+        let mut source = String::new();
+        try!(try!(File::open(&test)).read_to_string(&mut source));
+
+        let mut options = session.options();
+        for feature in features_of(&source) {
+            options.features.enable(&feature);
+        }
+
+        let emit = directives(&source, "emit").pop();
+        if let Some(ref emit) = emit {
+            options.emit = Emit::from_str(emit).expect("an unknown `-- emit:` in a test");
+        }
+        session.set_options(options);
+
+        // An output left by an earlier test must not count as this one's, a
+        // library is a directory.
+        let output = PathBuf::from("/tmp/duh");
+        let _ = if output.is_dir() { remove_dir_all(&output) } else { remove_file(&output) };
+        let result = hubris::compile_file_in_session(&test, Some(output.clone()), session);
+
+        // What the test printed, and what it emitted unless that is an
+        // executable or a library.
+        let mut printed = diagnostics.contents();
+        if emit.is_some() && output.is_file() {
+            if let Ok(mut file) = File::open(&output) {
+                try!(file.read_to_string(&mut printed));
+            }
+        }
+
+        for expected in directives(&source, "expect") {
+            if !printed.contains(&expected[..]) {
+                missing_output.push((test.to_owned(), expected, printed.clone()));
+            }
+        }
 
         match expected_outcome {
             Outcome::Fail => match result {
                 Err(_) => {},
                 Ok(_) => {
-                    should_of_failed.push(test.to_owned());
+                    should_of_failed.push((test.to_owned(), diagnostics.contents()));
                 }
             },
             Outcome::Pass => match result {
                 Err(e) => {
-                    let pair = (e, test.to_owned());
-                    should_of_passed.push(pair);
+                    let triple = (e, test.to_owned(), diagnostics.contents());
+                    should_of_passed.push(triple);
                 }
                 Ok(_) => {}
             }
         }
     }
 
-    for test in should_of_failed {
+    for (test, diagnostics) in should_of_failed {
         println!("{}: should have failed", test.display());
+        print!("{}", diagnostics);
     }
 
-    for (e, test) in should_of_passed {
+    for (e, test, diagnostics) in should_of_passed {
         println!("{}: should have passed", test.display());
+        print!("{}", diagnostics);
         println!("{:?}", e);
     }
 
+    for (test, expected, printed) in missing_output {
+        println!("{}: should have printed `{}`", test.display(), expected);
+        print!("{}", printed);
+    }
+
     Ok(())
 }
+
+/// The unstable features a test enables, named by a comment such as
+/// `-- features: induction-recursion`.
+fn features_of(source: &str) -> Vec<String> {
+    directives(source, "features").iter()
+                                  .flat_map(|features| {
+                                      features.split(',')
+                                              .map(|f| f.trim().to_string())
+                                              .filter(|f| !f.is_empty())
+                                              .collect::<Vec<_>>()
+                                  })
+                                  .collect()
+}
+
+/// The values given by the comments `-- <name>: <value>` of a test, in
+/// order. A test is compiled with `-- emit: <emit>` in place of to an
+/// executable, and only passes if its diagnostics, along with what it
+/// emitted, contain each `-- expect: <text>`.
+fn directives(source: &str, name: &str) -> Vec<String> {
+    let prefix = format!("-- {}:", name);
+
+    source.lines()
+          .filter_map(|line| {
+              let line = line.trim();
+              if line.starts_with(&prefix[..]) {
+                  Some(line[prefix.len()..].trim().to_string())
+              } else {
+                  None
+              }
+          })
+          .collect()
+}
@@ -0,0 +1,213 @@
+//! Support for emitting the output of the Rust backend as a Cargo package.
+//!
+//! For an executable named `example` we produce:
+//!
+//! ```text
+//! rust/example/
+//!     Cargo.toml
+//!     src/main.rs
+//!     src/<module>.rs
+//!     symbols.map
+//! ```
+//!
+//! next to the requested output, one generated Rust module per Hubris module.
+//! Every module is glob imported into the crate root, so references between
+//! definitions do not need to know which module they live in. `symbols.map`
+//! lists the Hubris name behind each generated identifier. The `main` of an
+//! executable installs the runtime's panic hook and calls the Hubris `main`.
+//!
+//! A library is written to the requested output itself, with `src/lib.rs`
+//! in place of `src/main.rs`. Only its `api` module, the wrappers of the
+//! exported definitions, is public.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A generated Cargo package.
+pub struct Package {
+    /// The name of the package and of the binary it builds.
+    pub name: String,
+    /// The directory the package is written to.
+    pub root: PathBuf,
+    /// The Rust code of each module, keyed by the module's Rust name.
+    pub modules: BTreeMap<String, Vec<u8>>,
+    /// Additional crates the program depends on, and their versions.
+    pub dependencies: Vec<(String, String)>,
+    /// Whether the package is a library rather than an executable.
+    pub library: bool,
+    /// The generated function an executable's `main` calls.
+    pub entry: Option<String>,
+}
+
+impl Package {
+    /// Create an empty package that builds `output`.
+    pub fn for_output(output: &Path) -> Package {
+        let name = output.file_stem()
+                         .map(|s| s.to_string_lossy().into_owned())
+                         .unwrap_or("main".to_string());
+
+        let root = output.parent()
+                         .unwrap_or(Path::new("."))
+                         .join("rust")
+                         .join(&name);
+
+        Package {
+            name: name,
+            root: root,
+            modules: BTreeMap::new(),
+            dependencies: vec![],
+            library: false,
+            entry: None,
+        }
+    }
+
+    /// Create an empty library package in the directory `output`.
+    pub fn library(output: &Path) -> Package {
+        let name = output.file_stem()
+                         .map(|s| s.to_string_lossy().into_owned())
+                         .unwrap_or("lib".to_string());
+
+        Package {
+            name: name,
+            root: output.to_path_buf(),
+            modules: BTreeMap::new(),
+            dependencies: vec![],
+            library: true,
+            entry: None,
+        }
+    }
+
+    /// The buffer holding the code for `module`.
+    pub fn module(&mut self, module: String) -> &mut Vec<u8> {
+        self.modules.entry(module).or_insert(vec![])
+    }
+
+    pub fn write(&self) -> io::Result<()> {
+        let src = self.root.join("src");
+        try!(fs::create_dir_all(&src));
+
+        try!(self.write_manifest());
+
+        let root = if self.library { "lib.rs" } else { "main.rs" };
+        let mut main = try!(File::create(src.join(root)));
+
+        try!(writeln!(main, "extern crate hubris_runtime as rt;"));
+
+        for &(ref name, _) in &self.dependencies {
+            try!(writeln!(main, "extern crate {};", name.replace("-", "_")));
+        }
+
+        try!(writeln!(main, ""));
+
+        for module in self.modules.keys() {
+            try!(writeln!(main, "mod {};", module));
+
+            if !self.library || module == "api" {
+                try!(writeln!(main, "pub use self::{}::*;", module));
+            } else {
+                try!(writeln!(main, "use self::{}::*;", module));
+            }
+        }
+
+        if let Some(ref entry) = self.entry {
+            try!(writeln!(main, "\nfn main() {{\n    \
+                                     rt::frame::install_panic_hook();\n    \
+                                     {}();\n\
+                                 }}", entry));
+        }
+
+        for (module, code) in &self.modules {
+            let mut file = try!(File::create(src.join(format!("{}.rs", module))));
+            try!(file.write_all(b"#![allow(non_snake_case, unused)]\n\nuse rt::*;\nuse super::*;\n\n"));
+            try!(file.write_all(&code[..]));
+        }
+
+        Ok(())
+    }
+
+    /// Create a file at `path` relative to the package root.
+    pub fn create_file(&self, path: &str) -> io::Result<File> {
+        File::create(self.root.join(path))
+    }
+
+    fn write_manifest(&self) -> io::Result<()> {
+        let mut manifest = try!(File::create(self.root.join("Cargo.toml")));
+
+        try!(writeln!(manifest, "[package]"));
+        try!(writeln!(manifest, "name = {:?}", self.name));
+        try!(writeln!(manifest, "version = \"0.1.0\"\n"));
+
+        if self.library {
+            try!(writeln!(manifest, "[lib]"));
+            try!(writeln!(manifest, "name = {:?}\n", self.name));
+        }
+        try!(writeln!(manifest, "[dependencies]"));
+        try!(writeln!(manifest, "hubris_runtime = {{ path = {:?} }}",
+                      runtime_dir().display().to_string()));
+
+        for &(ref name, ref version) in &self.dependencies {
+            try!(writeln!(manifest, "{} = {:?}", name, version));
+        }
+
+        Ok(())
+    }
+
+    /// Build the package with Cargo, for `target` when cross-compiling, and
+    /// copy the resulting binary to `output`.
+    pub fn build(&self, target: Option<&str>, output: &Path) -> io::Result<()> {
+        let mut cmd = Command::new("cargo");
+
+        cmd.arg("build")
+           .arg("--manifest-path").arg(self.root.join("Cargo.toml"));
+
+        if let Some(target) = target {
+            cmd.arg("--target").arg(target);
+        }
+
+        debug!("cargo: {:?}", cmd);
+
+        let status = try!(cmd.status());
+
+        if !status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("cargo failed to build {}: {}", self.root.display(), status)));
+        }
+
+        let mut binary = self.root.join("target");
+
+        if let Some(target) = target {
+            binary = binary.join(target);
+        }
+
+        try!(fs::copy(binary.join("debug").join(binary_name(&self.name, target)), output));
+
+        Ok(())
+    }
+}
+
+/// The file Cargo builds the binary `name` to for `target`, or for the host
+/// when no target is given.
+fn binary_name(name: &str, target: Option<&str>) -> String {
+    let windows = target.map_or(cfg!(windows), |target| target.contains("windows"));
+    let wasm = target.map_or(false, |target| target.starts_with("wasm32"));
+
+    if wasm {
+        format!("{}.wasm", name)
+    } else if windows {
+        format!("{}.exe", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// The runtime sources, installed to `~/.hubris/rt` and built by Cargo for
+/// whichever target the program is compiled for.
+fn runtime_dir() -> PathBuf {
+    let home = env::home_dir().unwrap_or(PathBuf::from("."));
+    home.join(".hubris/rt")
+}
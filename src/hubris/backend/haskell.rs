@@ -0,0 +1,541 @@
+//! Extracting programs to GHC Haskell.
+//!
+//! The erased program is written out as a single Haskell module, so verified
+//! definitions can be used from an existing Haskell project. Every value has
+//! the type `Obj`, GHC's `Any`, and is converted with `unsafeCoerce` where a
+//! function or a constructor is needed, as the erased program can not be
+//! given Haskell types in general.
+//!
+//! Each inductive type becomes a `data` declaration with a constructor for
+//! each of its constructors, storing the relevant arguments that follow the
+//! parameters, and its recursor becomes a `case` on the value. Values are
+//! evaluated by need, as Haskell's are, whatever `--evaluation` says.
+//!
+//! Identifiers are those of the Rust backend, see `mangle`, so `Nat.add` is
+//! `_H3Nat3add`. The type and constructors of an inductive type are the
+//! identifiers of their globals prefixed with `T` and `C`, so `List` is
+//! `T_H4List` and builds its values with `C_H3Nil` and `C_H4Cons`.
+//!
+//! Externs are implemented by the Rust runtime, so a program declaring one
+//! can not be extracted.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use ast::HasSpan;
+use core;
+use diagnostic::Diagnostic;
+use timing::{Instant, Phase};
+use typeck::TyCtxt;
+
+use super::{global_types, location, nat, optimize, Backend, Definition, ErasureCx, Pass, Term};
+use super::mangle::Mangler;
+
+/// The definitions in `lib/Nat.hbr` replaced by functions on `Integer`s,
+/// which are declared by `PRELUDE`.
+const NAT_FUNCTIONS: &'static [(&'static str, &'static str, usize)] = &[
+    ("pred", "natPred", 1),
+    ("add", "natAdd", 2),
+    ("mul", "natMul", 2),
+];
+
+/// The declarations every generated module starts with.
+const PRELUDE: &'static str = "import qualified Control.Exception
+import qualified GHC.Base
+import qualified Unsafe.Coerce
+
+-- A value of any type, the types of the program are erased.
+type Obj = GHC.Base.Any
+
+obj :: a -> Obj
+obj = Unsafe.Coerce.unsafeCoerce
+
+fromObj :: Obj -> a
+fromObj = Unsafe.Coerce.unsafeCoerce
+
+app :: Obj -> Obj -> Obj
+app f = fromObj f
+
+lam :: (Obj -> Obj) -> Obj
+lam = obj
+
+-- Passed for the indices of an inductive type to the recursive calls of its
+-- recursor, which never evaluates them.
+erased :: Obj
+erased = error \"an erased value was evaluated\"
+
+-- The standard library's natural numbers are Haskell's.
+natOf :: Integer -> Obj
+natOf = obj
+
+natZero :: Obj
+natZero = natOf 0
+
+natSucc :: Obj -> Obj
+natSucc n = natOf (fromObj n + 1)
+
+natPred :: Obj -> Obj
+natPred n = natOf (max 0 (fromObj n - 1))
+
+natAdd :: Obj -> Obj -> Obj
+natAdd m n = natOf (fromObj m + fromObj n)
+
+natMul :: Obj -> Obj -> Obj
+natMul m n = natOf (fromObj m * fromObj n)
+
+natIsZero :: Obj -> Bool
+natIsZero n = (fromObj n :: Integer) == 0
+";
+
+pub struct Haskell;
+
+impl Backend for Haskell {
+    fn name(&self) -> &'static str {
+        "haskell"
+    }
+
+    fn create_executable(&self, main: core::Definition, ty_cx: TyCtxt, output: Option<PathBuf>)
+                         -> io::Result<()> {
+        let output = output.unwrap_or(ty_cx.session.root_file().with_extension(""));
+
+        let name = output.file_stem()
+                         .map(|s| s.to_string_lossy().into_owned())
+                         .unwrap_or("main".to_string());
+
+        let root = output.parent()
+                         .unwrap_or(Path::new("."))
+                         .join("haskell")
+                         .join(&name);
+
+        try!(fs::create_dir_all(&root));
+
+        let mut mangler = Mangler::new();
+        let entry = mangler.mangle(&main.name);
+        let roots = Some(main.name).into_iter().collect();
+        let mut code = try!(generate(&ty_cx, "Main", &roots, &mut mangler));
+
+        code.push_str(&format!("main :: IO ()
+main = Control.Exception.evaluate {} >> return ()
+", entry));
+
+        let source = root.join("Main.hs");
+        try!(try!(File::create(&source)).write_all(code.as_bytes()));
+
+        build(&root, &source, &output)
+    }
+
+    /// Write a module exporting every definition, named after the output.
+    fn create_library(&self, ty_cx: TyCtxt, output: Option<PathBuf>) -> io::Result<()> {
+        let output = output.unwrap_or(ty_cx.session.root_file().with_extension(""));
+        let module = module_name(&output);
+
+        let mut mangler = Mangler::new();
+        let roots = ty_cx.definitions.keys().cloned().collect();
+        let code = try!(generate(&ty_cx, &module, &roots, &mut mangler));
+
+        let source = output.parent()
+                           .unwrap_or(Path::new("."))
+                           .join(format!("{}.hs", module));
+
+        try!(try!(File::create(&source)).write_all(code.as_bytes()));
+
+        Ok(())
+    }
+
+    fn dump_ir(&self, ty_cx: TyCtxt, after: Pass, out: &mut Write) -> io::Result<()> {
+        // Without a `main` every definition is treated as used.
+        let roots = match ty_cx.get_main() {
+            Ok(main) => Some(main.name.clone()).into_iter().collect(),
+            Err(_) => ty_cx.definitions.keys().cloned().collect(),
+        };
+
+        let definitions = try!(lower(&ty_cx));
+        let definitions = optimize::optimize(&ty_cx.session, definitions, &roots, Some(after));
+
+        for def in &definitions {
+            try!(writeln!(out, "{}\n", def));
+        }
+
+        Ok(())
+    }
+}
+
+/// The Haskell module `module` holding the program in `ty_cx`, `roots` are
+/// the definitions called from outside of it.
+fn generate(ty_cx: &TyCtxt,
+            module: &str,
+            roots: &BTreeSet<core::Name>,
+            mangler: &mut Mangler) -> io::Result<String> {
+    let definitions = try!(lower(ty_cx));
+    let definitions = optimize::optimize(&ty_cx.session, definitions, roots, None);
+
+    let start = Instant::now();
+
+    let mut code = format!("-- Extracted by hubris from {}.
+{{-# OPTIONS_GHC -w #-}}
+module {} where
+
+{}
+", ty_cx.session.root_file().display(), module, PRELUDE);
+
+    let mut declared = BTreeSet::new();
+
+    for (name, data) in &ty_cx.types {
+        declared.insert(name.clone());
+        declared.extend(data.ctors.iter().map(|&(ref ctor, _)| ctor.clone()));
+        declared.insert(name.in_scope("rec".to_string()).unwrap());
+
+        code.push_str(&data_to_haskell(ty_cx, mangler, data));
+    }
+
+    // The axioms that are not types, constructors or recursors can not be
+    // computed with.
+    for name in ty_cx.axioms.keys() {
+        if !declared.contains(name) && !ty_cx.definitions.contains_key(name) {
+            let message = format!("the axiom `{}` declared at {} was evaluated",
+                                  name, location(ty_cx, name));
+            code.push_str(&global(mangler, name, &location(ty_cx, name), &error(&message)));
+        }
+    }
+
+    for def in &definitions {
+        debug!("generate: lowered={}", def);
+        let body = term_to_haskell(mangler, &def.body);
+        code.push_str(&global(mangler, &def.name, &def.location, &body));
+    }
+
+    ty_cx.session.record_time(Phase::Codegen, None, start);
+
+    Ok(code)
+}
+
+/// Lower every definition in `ty_cx` to the untyped language the backend
+/// works with. Recursors are left as calls, they are declared along with
+/// their types.
+fn lower(ty_cx: &TyCtxt) -> io::Result<Vec<Definition>> {
+    let start = Instant::now();
+
+    if !ty_cx.externs.is_empty() {
+        for name in ty_cx.externs.keys() {
+            try!(Diagnostic::error(format!("the extern `{}` has no Haskell implementation", name))
+                     .span(name.get_span())
+                     .emit(&ty_cx.session));
+        }
+
+        let count = ty_cx.externs.len();
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} extern{} can not be extracted to Haskell",
+                    count,
+                    if count == 1 { "" } else { "s" })));
+    }
+
+    let globals = global_types(ty_cx);
+    let projections = BTreeMap::new();
+    let enumerations = BTreeMap::new();
+    let scrutinee = ty_cx.local_with_repr("scrutinee".to_string(), core::Term::Type);
+
+    let definitions = ty_cx.definitions.values().map(|def| {
+        match nat::replacement_from(ty_cx, def, NAT_FUNCTIONS) {
+            Some(replacement) => replacement,
+            None => ErasureCx::new(&globals, &projections, &enumerations, &scrutinee)
+                        .lower_def(def.clone(), location(ty_cx, &def.name)),
+        }
+    }).collect();
+
+    ty_cx.session.record_time(Phase::Erasure, None, start);
+
+    Ok(definitions)
+}
+
+/// The declaration of the global `name`, whose value is `body`.
+fn global(mangler: &mut Mangler, name: &core::Name, location: &str, body: &str) -> String {
+    let ident = mangler.mangle(name);
+    format!("-- {} at {}\n{} :: Obj\n{} = {}\n\n", name, location, ident, ident, body)
+}
+
+/// The `data` declaration of `data`, and the globals for the type, its
+/// constructors and its recursor.
+fn data_to_haskell(ty_cx: &TyCtxt, mangler: &mut Mangler, data: &core::Data) -> String {
+    let type_location = location(ty_cx, &data.name);
+    let message = format!("the type `{}` defined at {} was evaluated", data.name, type_location);
+    let declaration = global(mangler, &data.name, &type_location, &error(&message));
+
+    if nat::is_nat(ty_cx, data) {
+        return declaration + &nat_to_haskell(ty_cx, mangler, data);
+    }
+
+    let ty_ident = format!("T{}", mangler.mangle(&data.name));
+    let ctors: Vec<_> = data.ctors.iter().map(|&(ref name, ref ty)| {
+        (name, ty, format!("C{}", mangler.mangle(name)), fields(data, ty))
+    }).collect();
+
+    let alternatives: Vec<_> = ctors.iter().map(|&(_, _, ref ident, ref fields)| {
+        let mut alternative = ident.clone();
+        for _ in fields {
+            alternative.push_str(" Obj");
+        }
+        alternative
+    }).collect();
+
+    let mut code = if alternatives.is_empty() {
+        format!("data {}\n\n", ty_ident)
+    } else {
+        format!("data {} = {}\n\n", ty_ident, alternatives.join(" | "))
+    };
+
+    code.push_str(&declaration);
+
+    for &(name, ctor_ty, ref ident, ref fields) in &ctors {
+        let mut args = vec![];
+        let mut position = 0;
+        let mut ty = ctor_ty;
+
+        while let &core::Term::Forall { ref binder, ref term, .. } = ty {
+            if !binder.is_irrelevant() {
+                args.push(format!("x{}", position));
+            }
+
+            position += 1;
+            ty = &**term;
+        }
+
+        let mut construct = ident.clone();
+        for &(position, _) in fields {
+            construct.push_str(&format!(" x{}", position));
+        }
+
+        let body = lambdas(&args, &format!("obj ({})", construct));
+        code.push_str(&global(mangler, name, &location(ty_cx, name), &body));
+    }
+
+    let rec = data.name.in_scope("rec".to_string()).unwrap();
+    let body = recursor_to_haskell(ty_cx, mangler, data, &rec, &ty_ident, &ctors);
+    code.push_str(&global(mangler, &rec, &type_location, &body));
+
+    code
+}
+
+/// The relevant arguments of a constructor of `data` stored in its values,
+/// those following the parameters, by their position among the arguments
+/// and whether they are recursive.
+fn fields(data: &core::Data, ctor_ty: &core::Term) -> Vec<(usize, bool)> {
+    let mut fields = vec![];
+    let mut position = 0;
+    let mut ty = ctor_ty;
+
+    while let &core::Term::Forall { ref binder, ref term, .. } = ty {
+        if position >= data.parameters.len() && !binder.is_irrelevant() {
+            let (head, _) = binder.ty.uncurry();
+            fields.push((position, head == data.name.to_term()));
+        }
+
+        position += 1;
+        ty = &**term;
+    }
+
+    fields
+}
+
+/// The recursor of `data` as a `case` on the value, which calls the minor
+/// premise of its constructor with the fields and then the result of the
+/// recursor on each recursive field.
+fn recursor_to_haskell(ty_cx: &TyCtxt,
+                       mangler: &mut Mangler,
+                       data: &core::Data,
+                       rec: &core::Name,
+                       ty_ident: &str,
+                       ctors: &[(&core::Name, &core::Term, String, Vec<(usize, bool)>)])
+                       -> String {
+    let rec_ty = match ty_cx.axioms.get(rec) {
+        Some(axiom) => &axiom.ty,
+        None => return error(&format!("the recursor `{}` was not declared", rec)),
+    };
+
+    let mut relevant = vec![];
+    let mut ty = rec_ty;
+
+    while let &core::Term::Forall { ref binder, ref term, .. } = ty {
+        relevant.push(!binder.is_irrelevant());
+        ty = &**term;
+    }
+
+    let arg = |position: usize| {
+        if relevant[position] { format!("x{}", position) } else { "erased".to_string() }
+    };
+
+    let major = relevant.len() - 1;
+    let indices = data.ty.binders().map_or(0, |bs| bs.len()) - data.parameters.len();
+    let first_index = major - indices;
+    let minor = |tag: usize| data.parameters.len() + 1 + tag;
+
+    let params: Vec<_> = (0..relevant.len()).filter(|&p| relevant[p]).map(|p| format!("x{}", p))
+                                            .collect();
+
+    if !relevant[major] {
+        return lambdas(&params, &error(&format!("the recursor `{}` erases its major premise",
+                                                rec)));
+    }
+
+    let rec_ident = mangler.mangle(rec);
+    let mut alternatives = vec![];
+
+    for (tag, &(_, _, ref ident, ref fields)) in ctors.iter().enumerate() {
+        let mut pattern = ident.clone();
+        let mut call = arg(minor(tag));
+
+        for &(position, _) in fields {
+            pattern.push_str(&format!(" f{}", position));
+            call = format!("app ({}) f{}", call, position);
+        }
+
+        for &(position, _) in fields.iter().filter(|&&(_, recursive)| recursive) {
+            let mut hypothesis = rec_ident.clone();
+
+            for p in (0..major).filter(|&p| relevant[p]) {
+                let value = if p >= first_index { "erased".to_string() } else { arg(p) };
+                hypothesis = format!("app ({}) {}", hypothesis, value);
+            }
+
+            call = format!("app ({}) (app ({}) f{})", call, hypothesis, position);
+        }
+
+        alternatives.push(format!("{} -> {}", pattern, call));
+    }
+
+    // A type without constructors has no values to case on.
+    if alternatives.is_empty() {
+        return lambdas(&params, &error(&format!("a value of the empty type `{}` was evaluated",
+                                                data.name)));
+    }
+
+    let case = format!("case (fromObj x{} :: {}) of {{ {} }}",
+                       major, ty_ident, alternatives.join("; "));
+
+    lambdas(&params, &case)
+}
+
+/// The constructors and recursor of the standard library's `Nat`, on the
+/// `Integer`s of `PRELUDE`.
+fn nat_to_haskell(ty_cx: &TyCtxt, mangler: &mut Mangler, data: &core::Data) -> String {
+    let (zero, succ) = (nat::global("Z"), nat::global("S"));
+    let rec = data.name.in_scope("rec".to_string()).unwrap();
+    let rec_ident = mangler.mangle(&rec);
+
+    // `rec C z s n` is `z` when `n` is zero, and otherwise `s m (rec C z s m)`
+    // where `m` is the number before `n`.
+    let rec_body = lambdas(&["c".to_string(), "z".to_string(), "s".to_string(), "n".to_string()],
+                           &format!("if natIsZero n then z else \
+                                     let m = natPred n in \
+                                     app (app s m) (app (app (app (app {} c) z) s) m)",
+                                    rec_ident));
+
+    global(mangler, &zero, &location(ty_cx, &zero), "natZero") +
+        &global(mangler, &succ, &location(ty_cx, &succ), "lam natSucc") +
+        &global(mangler, &rec, &location(ty_cx, &data.name), &rec_body)
+}
+
+fn term_to_haskell(mangler: &mut Mangler, term: &Term) -> String {
+    match term {
+        // The functions of `PRELUDE` take their arguments directly.
+        &Term::Call(ref f, ref args) if f.is_native() => {
+            let mut call = term_to_haskell(mangler, f);
+            for arg in args {
+                call = format!("{} ({})", call, term_to_haskell(mangler, arg));
+            }
+            call
+        }
+        &Term::Call(ref f, ref args) => {
+            let mut call = term_to_haskell(mangler, f);
+            for arg in args {
+                call = format!("app ({}) ({})", call, term_to_haskell(mangler, arg));
+            }
+            call
+        }
+        &Term::Var(ref name) => mangler.mangle(name),
+        &Term::Lambda(ref ns, ref body) => {
+            let params: Vec<_> = ns.iter().map(|n| mangler.mangle(n)).collect();
+            let body = term_to_haskell(mangler, body);
+            lambdas(&params, &body)
+        }
+        &Term::Native(symbol) => symbol.to_string(),
+        &Term::Panic(ref message) => error(message),
+        t => panic!("{:?}", t),
+    }
+}
+
+/// A function taking `params` one at a time and returning `body`.
+fn lambdas(params: &[String], body: &str) -> String {
+    params.iter().rev().fold(body.to_string(), |body, param| {
+        format!("lam (\\{} -> {})", param, body)
+    })
+}
+
+/// A call of `error` with `message`, which is escaped for Haskell.
+fn error(message: &str) -> String {
+    let mut literal = "\"".to_string();
+
+    for c in message.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            ' '...'~' => literal.push(c),
+            // `\&` ends the escape, in case a digit follows.
+            c => literal.push_str(&format!("\\{}\\&", c as u32)),
+        }
+    }
+
+    literal.push('"');
+    format!("error {}", literal)
+}
+
+/// The name of the module extracted to `output`, its file name with the
+/// first letter capitalized and every other character that can not appear
+/// in a module name replaced by an underscore.
+fn module_name(output: &Path) -> String {
+    let stem = output.file_stem()
+                     .map(|s| s.to_string_lossy().into_owned())
+                     .unwrap_or("Main".to_string());
+
+    let mut name: String = stem.chars().map(|c| match c {
+        'a'...'z' | 'A'...'Z' | '0'...'9' | '\'' => c,
+        _ => '_',
+    }).collect();
+
+    match name.chars().next() {
+        Some('a'...'z') => {
+            let first = name.remove(0);
+            name.insert(0, first.to_uppercase().next().unwrap());
+        }
+        Some('A'...'Z') => {}
+        _ => name.insert(0, 'M'),
+    }
+
+    name
+}
+
+/// Compile the extracted program `source` with GHC to the executable
+/// `output`, keeping its intermediate files in `root`.
+fn build(root: &Path, source: &Path, output: &Path) -> io::Result<()> {
+    let mut cmd = Command::new("ghc");
+
+    cmd.arg("-O")
+       .arg("-outputdir").arg(root.join("build"))
+       .arg("-o").arg(output)
+       .arg(source);
+
+    debug!("ghc: {:?}", cmd);
+
+    let status = try!(cmd.status());
+
+    if !status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("ghc failed to build {}: {}", source.display(), status)));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,571 @@
+//! Generating JavaScript for web applications and Node.
+//!
+//! The program is lowered as for the Rust backend, and each definition
+//! becomes a JavaScript function named after it, so `List.append` is
+//! `List$append`. Locals keep their names followed by their number, as in
+//! `xs$12`. Data is represented as described in the runtime shim,
+//! `src/hubris_rt/js/index.js`, which is copied next to the generated code.
+//!
+//! An executable is a single script run with `node`. A library is a package
+//! exporting every definition, with TypeScript declarations giving each the
+//! runtime's `Obj` type, as the types of the program are erased.
+//!
+//! Only strict evaluation is supported, and externs can only be bound to the
+//! runtime's functions, not to Rust crates.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use ast::HasSpan;
+use core;
+use diagnostic::Diagnostic;
+use timing::{Instant, Phase};
+use typeck::TyCtxt;
+
+use super::{is_enumeration, is_newtype, location, lower, nat, optimize, record_fields,
+            Backend, Definition, Evaluation, Pass, Term};
+
+/// The runtime shim, copied into every program.
+const RUNTIME: &'static str = include_str!("../../hubris_rt/js/index.js");
+
+/// The declarations of the runtime shim's types.
+const RUNTIME_TYPES: &'static str = include_str!("../../hubris_rt/js/index.d.ts");
+
+/// Words that can not name a function, or that would hide a global the
+/// runtime uses.
+const RESERVED: &'static [&'static str] = &[
+    "await", "break", "case", "catch", "class", "const", "continue", "debugger", "default",
+    "delete", "do", "else", "enum", "export", "extends", "false", "finally", "for", "function",
+    "if", "implements", "import", "in", "instanceof", "interface", "let", "new", "null",
+    "package", "private", "protected", "public", "return", "static", "super", "switch", "this",
+    "throw", "true", "try", "typeof", "var", "void", "while", "with", "yield",
+    "arguments", "eval", "undefined", "NaN", "Infinity", "globalThis", "module", "exports",
+    "require", "rt", "Array", "BigInt", "Error", "Math", "Number", "Object",
+];
+
+pub struct JavaScript;
+
+impl Backend for JavaScript {
+    fn name(&self) -> &'static str {
+        "js"
+    }
+
+    fn create_executable(&self, main: core::Definition, ty_cx: TyCtxt, output: Option<PathBuf>)
+                         -> io::Result<()> {
+        let output = output.unwrap_or(ty_cx.session.root_file().with_extension(""))
+                           .with_extension("js");
+
+        let mut names = Names::new();
+        let entry = names.ident(&main.name);
+        let roots = Some(main.name).into_iter().collect();
+        let program = try!(generate(&ty_cx, &roots, &mut names));
+
+        let mut file = try!(File::create(&output));
+
+        try!(write!(file, "#!/usr/bin/env node
+\"use strict\";
+// Generated by hubris from {}.
+
+const rt = (() => {{
+const module = {{ exports: {{}} }};
+{}
+return module.exports;
+}})();
+
+", ty_cx.session.root_file().display(), RUNTIME));
+
+        try!(file.write_all(program.code.as_bytes()));
+        try!(writeln!(file, "{}();", entry));
+
+        Ok(())
+    }
+
+    /// Write a package to the directory `output` exporting every definition.
+    fn create_library(&self, ty_cx: TyCtxt, output: Option<PathBuf>) -> io::Result<()> {
+        let output = output.unwrap_or(ty_cx.session.root_file().with_extension(""));
+
+        let package = output.file_stem()
+                            .map(|s| s.to_string_lossy().into_owned())
+                            .unwrap_or("lib".to_string());
+
+        let mut names = Names::new();
+        let roots = ty_cx.definitions.keys().cloned().collect();
+        let program = try!(generate(&ty_cx, &roots, &mut names));
+
+        try!(fs::create_dir_all(&output));
+
+        try!(try!(File::create(output.join("hubris_rt.js"))).write_all(RUNTIME.as_bytes()));
+        try!(try!(File::create(output.join("hubris_rt.d.ts")))
+                 .write_all(RUNTIME_TYPES.as_bytes()));
+
+        let mut manifest = try!(File::create(output.join("package.json")));
+        try!(write!(manifest, "{{
+  \"name\": {:?},
+  \"version\": \"0.1.0\",
+  \"main\": \"index.js\",
+  \"types\": \"index.d.ts\"
+}}
+", package));
+
+        let mut index = try!(File::create(output.join("index.js")));
+        try!(write!(index, "\"use strict\";
+// Generated by hubris from {}.
+
+const rt = require(\"./hubris_rt.js\");
+
+", ty_cx.session.root_file().display()));
+        try!(index.write_all(program.code.as_bytes()));
+
+        let exports: Vec<_> = program.functions.iter().map(|f| f.0.clone()).collect();
+        try!(writeln!(index, "module.exports = {{\n    {},\n}};", exports.join(",\n    ")));
+
+        let mut types = try!(File::create(output.join("index.d.ts")));
+        try!(writeln!(types, "// Generated by hubris from {}.\n",
+                      ty_cx.session.root_file().display()));
+        try!(writeln!(types, "import {{ Obj }} from \"./hubris_rt\";\n"));
+
+        for &(ref ident, ref name, ref params) in &program.functions {
+            let params: Vec<_> = params.iter().map(|p| format!("{}: Obj", p)).collect();
+            try!(writeln!(types, "/** `{}` */\nexport function {}({}): Obj;\n",
+                          name, ident, params.join(", ")));
+        }
+
+        Ok(())
+    }
+
+    fn dump_ir(&self, ty_cx: TyCtxt, after: Pass, out: &mut Write) -> io::Result<()> {
+        // Without a `main` every definition is treated as used.
+        let roots = match ty_cx.get_main() {
+            Ok(main) => Some(main.name.clone()).into_iter().collect(),
+            Err(_) => ty_cx.definitions.keys().cloned().collect(),
+        };
+
+        let definitions = try!(lower(&ty_cx));
+        let definitions = optimize::optimize(&ty_cx.session, definitions, &roots, Some(after));
+
+        for def in &definitions {
+            try!(writeln!(out, "{}\n", def));
+        }
+
+        Ok(())
+    }
+}
+
+/// The generated code of a program.
+struct Program {
+    code: String,
+    /// The identifier, name and parameters of each function defined.
+    functions: Vec<(String, core::Name, Vec<String>)>,
+}
+
+/// The functions for the definitions in `ty_cx`, and for the recursors and
+/// axioms that have none. `roots` are the definitions called from outside of
+/// the program.
+fn generate(ty_cx: &TyCtxt, roots: &BTreeSet<core::Name>, names: &mut Names)
+            -> io::Result<Program> {
+    if ty_cx.session.options().evaluation != Evaluation::Strict {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "the js backend only supports strict evaluation"));
+    }
+
+    let mut count = 0;
+
+    for (name, convention) in &ty_cx.externs {
+        if let Some(ref crate_name) = convention.crate_name {
+            try!(Diagnostic::error(format!("the extern `{}` is implemented in the Rust crate \
+                                            `{}`, which JavaScript can not call",
+                                           name, crate_name))
+                     .span(name.get_span())
+                     .emit(&ty_cx.session));
+            count += 1;
+        }
+    }
+
+    if count > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} extern{} can not be called from JavaScript",
+                    count,
+                    if count == 1 { "" } else { "s" })));
+    }
+
+    let definitions = try!(lower(ty_cx));
+    let definitions = optimize::optimize(&ty_cx.session, definitions, roots, None);
+
+    let start = Instant::now();
+
+    let mut functions = vec![];
+    let mut defined = BTreeSet::new();
+
+    for def in &definitions {
+        let (params, body) = match def.body {
+            Term::Lambda(ref params, ref body) => (params.clone(), (**body).clone()),
+            ref body => (vec![], body.clone()),
+        };
+
+        // Types are passed to the parameters they were not erased from, but
+        // never inspected, so they are all the same value.
+        let body = if ty_cx.types.contains_key(&def.name) {
+            Body::Code("rt.Type".to_string())
+        } else {
+            Body::Term(body)
+        };
+
+        defined.insert(def.name.clone());
+        functions.push(Function {
+            name: def.name.clone(),
+            location: def.location.clone(),
+            params: params.iter().map(|p| names.ident(p)).collect(),
+            body: body,
+        });
+    }
+
+    for (name, data) in &ty_cx.types {
+        let rec = name.in_scope("rec".to_string()).unwrap();
+
+        if !nat::is_nat(ty_cx, data) && !defined.contains(&rec) {
+            defined.insert(rec.clone());
+            functions.push(recursor(ty_cx, data, &rec, names));
+        }
+    }
+
+    // The constructors and recursors are defined above, any other axiom
+    // has no implementation.
+    for (name, axiom) in &ty_cx.axioms {
+        if defined.contains(name) {
+            continue;
+        }
+
+        let params = relevance(&axiom.ty).into_iter()
+                                         .enumerate()
+                                         .filter(|&(_, relevant)| relevant)
+                                         .map(|(i, _)| format!("x{}", i))
+                                         .collect();
+
+        let message = format!("the axiom `{}` declared at {} was evaluated",
+                              name, location(ty_cx, name));
+
+        functions.push(Function {
+            name: name.clone(),
+            location: location(ty_cx, name),
+            params: params,
+            body: Body::Code(format!("rt.panic({})", string(&message))),
+        });
+    }
+
+    let arities: HashMap<_, _> =
+        functions.iter().map(|f| (f.name.clone(), f.params.len())).collect();
+
+    let mut code = String::new();
+    let mut exported = vec![];
+
+    for function in functions {
+        let ident = names.ident(&function.name);
+        let body = match function.body {
+            Body::Term(ref term) => expression(names, &arities, term),
+            Body::Code(ref code) => code.clone(),
+        };
+
+        code.push_str(&format!("// {} at {}\nfunction {}({}) {{\n    return {};\n}}\n\n",
+                               function.name,
+                               function.location,
+                               ident,
+                               function.params.join(", "),
+                               body));
+
+        exported.push((ident, function.name, function.params));
+    }
+
+    ty_cx.session.record_time(Phase::Codegen, None, start);
+
+    Ok(Program {
+        code: code,
+        functions: exported,
+    })
+}
+
+struct Function {
+    name: core::Name,
+    location: String,
+    params: Vec<String>,
+    body: Body,
+}
+
+enum Body {
+    Term(Term),
+    /// JavaScript written by the backend itself.
+    Code(String),
+}
+
+/// Whether each binder of `ty` is relevant.
+fn relevance(ty: &core::Term) -> Vec<bool> {
+    let mut relevant = vec![];
+    let mut ty = ty;
+
+    while let &core::Term::Forall { ref binder, ref term, .. } = ty {
+        relevant.push(!binder.is_irrelevant());
+        ty = &**term;
+    }
+
+    relevant
+}
+
+/// The recursor of `data`, which calls the minor premise of the value's
+/// constructor with its fields and then the result of the recursor on each
+/// recursive field. The recursors of records and enumerations are usually
+/// lowered to projections and switches, this is only called when one is
+/// passed as a function.
+fn recursor(ty_cx: &TyCtxt, data: &core::Data, rec: &core::Name, names: &mut Names) -> Function {
+    let relevant = ty_cx.axioms.get(rec).map_or(vec![], |axiom| relevance(&axiom.ty));
+    let params: Vec<_> = (0..relevant.len()).filter(|&p| relevant[p])
+                                            .map(|p| format!("x{}", p))
+                                            .collect();
+
+    let function = |body: String| Function {
+        name: rec.clone(),
+        location: location(ty_cx, &data.name),
+        params: params.clone(),
+        body: Body::Code(body),
+    };
+
+    let major = match relevant.len().checked_sub(1) {
+        Some(major) if relevant[major] => major,
+        _ => return function(format!("rt.panic({})", string(&format!(
+            "the recursor `{}` erases its major premise", rec)))),
+    };
+
+    let indices = data.ty.binders().map_or(0, |bs| bs.len()) - data.parameters.len();
+    let rec_ident = names.ident(rec);
+
+    let value = format!("x{}", major);
+    let mut cases = vec![];
+
+    for (tag, &(_, ref ctor_ty)) in data.ctors.iter().enumerate() {
+        let mut fields = vec![];
+        let mut recursive = vec![];
+        let mut position = 0;
+        let mut ty = ctor_ty;
+
+        while let &core::Term::Forall { ref binder, ref term, .. } = ty {
+            if position >= data.parameters.len() && !binder.is_irrelevant() {
+                let field = if is_newtype(data) {
+                    value.clone()
+                } else if record_fields(data).is_some() {
+                    format!("{}[{}]", value, fields.len())
+                } else {
+                    format!("{}.fields[{}]", value, fields.len())
+                };
+
+                if binder.ty.uncurry().0 == data.name.to_term() {
+                    recursive.push(field.clone());
+                }
+
+                fields.push(field);
+            }
+
+            position += 1;
+            ty = &**term;
+        }
+
+        // The indices of a recursive field are never evaluated.
+        for field in recursive {
+            let args: Vec<_> =
+                (0..major).filter(|&p| relevant[p])
+                          .map(|p| if p >= major - indices {
+                              "undefined".to_string()
+                          } else {
+                              format!("x{}", p)
+                          })
+                          .chain(Some(field))
+                          .collect();
+
+            fields.push(format!("{}({})", rec_ident, args.join(", ")));
+        }
+
+        let minor = data.parameters.len() + 1 + tag;
+        let minor = if relevant.get(minor).cloned().unwrap_or(false) {
+            format!("x{}", minor)
+        } else {
+            "undefined".to_string()
+        };
+
+        let result = if fields.is_empty() {
+            minor
+        } else {
+            format!("rt.call({}, [{}])", minor, fields.join(", "))
+        };
+
+        cases.push(format!("case {}: return {};", tag, result));
+    }
+
+    let tag = if is_enumeration(data) {
+        value.clone()
+    } else if record_fields(data).is_some() {
+        "0".to_string()
+    } else {
+        format!("{}.tag", value)
+    };
+
+    function(format!("(($tag) => {{ switch ($tag) {{ {} default: return rt.panic({}); }} }})({})",
+                     cases.join(" "),
+                     string(&format!("a value of `{}` has no constructor", data.name)),
+                     tag))
+}
+
+/// The JavaScript expression for `term`, calling the functions whose number
+/// of parameters is given by `arities` directly when given that many
+/// arguments.
+fn expression(names: &mut Names, arities: &HashMap<core::Name, usize>, term: &Term) -> String {
+    let list = |names: &mut Names, terms: &[Term]| -> String {
+        let terms: Vec<_> = terms.iter().map(|t| expression(names, arities, t)).collect();
+        terms.join(", ")
+    };
+
+    match term {
+        &Term::Call(ref f, ref args) => match **f {
+            Term::Native(symbol) => format!("{}({})", native(symbol), list(names, args)),
+            Term::Var(ref name) if arities.get(name) == Some(&args.len()) =>
+                format!("{}({})", names.ident(name), list(names, args)),
+            // A global is passed as the function itself, which `rt.call`
+            // calls with as many arguments as it takes.
+            Term::Var(ref name) if arities.contains_key(name) =>
+                format!("rt.call({}, [{}])", names.ident(name), list(names, args)),
+            ref f => {
+                let f = expression(names, arities, f);
+                format!("rt.call({}, [{}])", f, list(names, args))
+            }
+        },
+        // A definition without parameters is computed where it is used.
+        &Term::Var(ref name) if arities.get(name) == Some(&0) =>
+            format!("{}()", names.ident(name)),
+        &Term::Var(ref name) => names.ident(name),
+        &Term::Lambda(ref params, ref body) => {
+            let params: Vec<_> = params.iter().map(|p| names.ident(p)).collect();
+            let body = expression(names, arities, body);
+            format!("(({}) => {})", params.join(", "), body)
+        }
+        &Term::Construct(tag, ref fields) =>
+            format!("rt.construct({}, [{}])", tag, list(names, fields)),
+        &Term::Tag(tag) => format!("{}", tag),
+        &Term::Record(_, ref fields) => format!("[{}]", list(names, fields)),
+        &Term::Project(_, index, ref record) =>
+            format!("{}[{}]", expression(names, arities, record), index),
+        // Only the arm that is jumped to is evaluated.
+        &Term::Switch(ref scrut, ref arms) => {
+            let cases: Vec<_> =
+                arms.iter()
+                    .enumerate()
+                    .map(|(tag, arm)| {
+                        format!("case {}: return {};", tag, expression(names, arities, arm))
+                    })
+                    .collect();
+
+            format!("(($tag) => {{ switch ($tag) {{ {} default: \
+                     return rt.panic(\"no constructor \" + $tag); }} }})({})",
+                    cases.join(" "),
+                    expression(names, arities, scrut))
+        }
+        &Term::Native(symbol) => native(symbol),
+        // Only the externs of the runtime reach here, as the program is
+        // rejected when one is implemented in a crate.
+        &Term::Foreign(ref symbol, ref args) =>
+            format!("{}({})", native(symbol), list(names, args)),
+        &Term::Panic(ref message) => format!("rt.panic({})", string(message)),
+        t => panic!("{:?}", t),
+    }
+}
+
+/// The runtime shim's counterpart of the Rust runtime's function `symbol`,
+/// `rt::nat::add` is `rt.nat.add`.
+fn native(symbol: &str) -> String {
+    symbol.replace("::", ".")
+}
+
+/// A JavaScript string literal of `s`.
+fn string(s: &str) -> String {
+    let mut literal = "\"".to_string();
+
+    for c in s.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            ' '...'~' => literal.push(c),
+            c => literal.push_str(&format!("\\u{{{:x}}}", c as u32)),
+        }
+    }
+
+    literal.push('"');
+    literal
+}
+
+/// The identifiers given to names, the same name is always given the same
+/// identifier and different names different ones.
+struct Names {
+    idents: HashMap<core::Name, String>,
+    used: HashSet<String>,
+}
+
+impl Names {
+    fn new() -> Names {
+        Names {
+            idents: HashMap::new(),
+            used: HashSet::new(),
+        }
+    }
+
+    fn ident(&mut self, name: &core::Name) -> String {
+        if let Some(ident) = self.idents.get(name) {
+            return ident.clone();
+        }
+
+        let mut base = match name {
+            &core::Name::Qual { ref components, .. } => {
+                let components: Vec<_> = components.iter().map(|c| escape(c)).collect();
+                components.join("$")
+            }
+            &core::Name::Local { number, ref repr, .. } => format!("{}${}", escape(repr), number),
+            &core::Name::DeBruijn { index, ref repr, .. } =>
+                format!("{}$d{}", escape(repr), index),
+            &core::Name::Meta { number, .. } => format!("$m{}", number),
+        };
+
+        if RESERVED.contains(&&base[..]) || base.starts_with(|c: char| c.is_digit(10)) {
+            base = format!("{}_", base);
+        }
+
+        let mut ident = base.clone();
+        let mut suffix = 0;
+
+        while self.used.contains(&ident) {
+            suffix += 1;
+            ident = format!("{}_{}", base, suffix);
+        }
+
+        self.idents.insert(name.clone(), ident.clone());
+        self.used.insert(ident.clone());
+
+        ident
+    }
+}
+
+/// Replace every character that can not appear in an identifier by `_x`
+/// followed by the hex value of each of its bytes.
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in s.chars() {
+        match c {
+            'a'...'z' | 'A'...'Z' | '0'...'9' | '_' => escaped.push(c),
+            c => {
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    escaped.push_str(&format!("_x{:02x}", byte));
+                }
+            }
+        }
+    }
+
+    escaped
+}
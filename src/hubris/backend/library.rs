@@ -0,0 +1,348 @@
+//! Wrappers exposing the definitions marked `@[export_rust]` to Rust code.
+//!
+//! A wrapper takes and returns ordinary Rust values and converts them to and
+//! from the runtime's objects with `rt::marshal::Marshal`. `UInt32` and
+//! `UInt64` are passed as `u32` and `u64`, and the standard library's `Nat`
+//! as the runtime's `rt::nat::Nat`. A data type without parameters or
+//! indices becomes an enum with a variant for each constructor, or a tuple
+//! struct when it has a single constructor, declared next to the wrappers.
+//! A type that occurs in its own constructors is boxed there.
+//!
+//! The language has no type of strings yet, so there is nothing to pass a
+//! Rust `String` as.
+
+use ast::{HasSpan, Span};
+use core::{Name, Term};
+use typeck::TyCtxt;
+
+use std::collections::BTreeSet;
+
+use super::mangle::Mangler;
+use super::nat;
+
+/// The Rust code of the wrappers and of the types they mention, or the
+/// location and description of each problem that prevents exporting them.
+pub fn wrappers(ty_cx: &TyCtxt, mangler: &mut Mangler) -> Result<String, Vec<(Span, String)>> {
+    let mut bindings = Bindings {
+        ty_cx: ty_cx,
+        declared: BTreeSet::new(),
+        pending: vec![],
+    };
+
+    let mut code = "use rt::marshal::Marshal;\n\n".to_string();
+    let mut errors = vec![];
+
+    for (name, rust_name) in &ty_cx.rust_exports {
+        match bindings.wrapper(name, rust_name, mangler) {
+            Ok(wrapper) => code.push_str(&wrapper),
+            Err(message) => errors.push((name.get_span(), message)),
+        }
+    }
+
+    // Declaring a type may mention further types.
+    while let Some(name) = bindings.pending.pop() {
+        match bindings.declaration(&name, mangler) {
+            Ok(declaration) => code.push_str(&declaration),
+            Err(message) => errors.push((name.get_span(), message)),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(code)
+    } else {
+        Err(errors)
+    }
+}
+
+/// The last component of a global's name, which names its Rust counterpart.
+fn rust_name(name: &Name) -> String {
+    match name {
+        &Name::Qual { ref components, .. } =>
+            components.last().cloned().unwrap_or(String::new()),
+        n => format!("{}", n),
+    }
+}
+
+struct Bindings<'tcx> {
+    ty_cx: &'tcx TyCtxt,
+    /// The data types a Rust type has been declared for.
+    declared: BTreeSet<Name>,
+    /// The data types mentioned whose declarations have not been emitted.
+    pending: Vec<Name>,
+}
+
+impl<'tcx> Bindings<'tcx> {
+    fn location(&self, name: &Name) -> String {
+        let span = name.get_span();
+
+        if span == Span::dummy() {
+            "<unknown>".to_string()
+        } else {
+            self.ty_cx.session.location(span)
+        }
+    }
+
+    /// The Rust type values of `ty` are passed as, `owner` is the data type
+    /// whose constructors are being declared.
+    fn rust_type(&mut self, ty: &Term, owner: Option<&Name>) -> Result<String, String> {
+        let ty_cx = self.ty_cx;
+
+        let name = match ty {
+            &Term::Var { ref name } => name,
+            ty => return Err(format!("values of type `{}` can not be passed to Rust", ty)),
+        };
+
+        if ty_cx.axioms.contains_key(name) && !ty_cx.types.contains_key(name) {
+            match &rust_name(name)[..] {
+                "UInt32" => return Ok("u32".to_string()),
+                "UInt64" => return Ok("u64".to_string()),
+                _ => {}
+            }
+        }
+
+        match ty_cx.types.get(name) {
+            Some(data) if nat::is_nat(ty_cx, data) => Ok("::rt::nat::Nat".to_string()),
+            Some(data) if data.parameters.is_empty() && data.ty.is_sort() => {
+                if self.declared.insert(name.clone()) {
+                    self.pending.push(name.clone());
+                }
+
+                if owner == Some(name) {
+                    Ok(format!("Box<{}>", rust_name(name)))
+                } else {
+                    Ok(rust_name(name))
+                }
+            }
+            Some(_) => Err(format!("`{}` has parameters or indices, only data types without \
+                                    them can be passed to Rust", name)),
+            None => Err(format!("values of type `{}` can not be passed to Rust", name)),
+        }
+    }
+
+    /// The wrapper of the definition `name`, called `wrapper` in Rust.
+    fn wrapper(&mut self, name: &Name, wrapper: &str, mangler: &mut Mangler)
+               -> Result<String, String> {
+        let ty_cx = self.ty_cx;
+
+        let def = match ty_cx.definitions.get(name) {
+            Some(def) => def,
+            None => return Err(format!("`{}` is not a definition", name)),
+        };
+
+        let mut params = vec![];
+        let mut ty = &def.ty;
+
+        while let &Term::Forall { ref binder, ref term, .. } = ty {
+            if !binder.is_irrelevant() {
+                params.push(try!(self.rust_type(&binder.ty, None).map_err(|message| {
+                    format!("can not export `{}`, {}", name, message)
+                })));
+            }
+            ty = &**term;
+        }
+
+        let result = try!(self.rust_type(ty, None).map_err(|message| {
+            format!("can not export `{}`, {}", name, message)
+        }));
+
+        // The generated function takes the arguments its body binds, which
+        // must be all of them for the wrapper to call it directly.
+        let mut arity = 0;
+        let mut body = &def.body;
+
+        while let &Term::Lambda { ref binder, body: ref inner, .. } = body {
+            if !binder.is_irrelevant() {
+                arity += 1;
+            }
+            body = &**inner;
+        }
+
+        if arity != params.len() {
+            return Err(format!("can not export `{}`, its body must bind each of its \
+                                arguments with `fun`", name));
+        }
+
+        let params: Vec<_> = params.iter()
+                                   .enumerate()
+                                   .map(|(i, ty)| format!("x{}: {}", i, ty))
+                                   .collect();
+
+        let args: Vec<_> = (0..params.len()).map(|i| format!("x{}.into_obj()", i)).collect();
+
+        Ok(format!("/// `{}` at {}.\n\
+                    pub fn {}({}) -> {} {{\n    \
+                        Marshal::from_obj(&{}({}))\n\
+                    }}\n\n",
+                   name, self.location(name),
+                   wrapper, params.join(", "), result,
+                   mangler.mangle(name), args.join(", ")))
+    }
+
+    /// The Rust type declared for the data type `name`, and its conversions.
+    fn declaration(&mut self, name: &Name, mangler: &mut Mangler) -> Result<String, String> {
+        let ty_cx = self.ty_cx;
+        let data = &ty_cx.types[name];
+        let ty_name = rust_name(name);
+
+        let mut variants = vec![];
+
+        for &(ref ctor, ref ty) in &data.ctors {
+            let mut fields = vec![];
+            let mut ty = ty;
+
+            while let &Term::Forall { ref binder, ref term, .. } = ty {
+                if !binder.is_irrelevant() {
+                    fields.push(try!(self.rust_type(&binder.ty, Some(name)).map_err(|message| {
+                        format!("can not pass `{}` to Rust, the constructor `{}` has a field \
+                                 that can not be passed, {}", name, ctor, message)
+                    })));
+                }
+                ty = &**term;
+            }
+
+            variants.push((rust_name(ctor), fields));
+        }
+
+        let mut code = format!("/// `{}` at {}.\n#[derive(Clone, Debug, PartialEq, Eq)]\n",
+                               name, self.location(name));
+
+        // A variant is written `Name(x0, ...)` when built or matched on.
+        let pattern = |prefix: &str, variant: &str, fields: &[String]| {
+            if fields.is_empty() {
+                format!("{}{}", prefix, variant)
+            } else {
+                let xs: Vec<_> = (0..fields.len()).map(|i| format!("x{}", i)).collect();
+                format!("{}{}({})", prefix, variant, xs.join(", "))
+            }
+        };
+
+        // The generated code stores the fields of a record in a struct of
+        // its own, and those of other values in a vector.
+        let from_field = |fields: &[String], is_record: bool| {
+            let xs: Vec<_> = (0..fields.len())
+                .map(|i| if is_record {
+                    format!("Marshal::from_obj(&value.{})", i)
+                } else {
+                    format!("Marshal::from_obj(&value.fields[{}])", i)
+                })
+                .collect();
+            xs.join(", ")
+        };
+
+        let into_fields = |fields: &[String]| {
+            let xs: Vec<_> = (0..fields.len()).map(|i| format!("x{}.into_obj()", i)).collect();
+            xs.join(", ")
+        };
+
+        // A value of an enumeration, whose constructors have no fields, is
+        // just its tag.
+        let is_enumeration = super::is_enumeration(data);
+
+        if variants.len() == 1 {
+            let (_, ref fields) = variants[0];
+
+            if fields.is_empty() {
+                code.push_str(&format!("pub struct {};\n\n", ty_name));
+            } else {
+                let pub_fields: Vec<_> = fields.iter().map(|f| format!("pub {}", f)).collect();
+                code.push_str(&format!("pub struct {}({});\n\n", ty_name, pub_fields.join(", ")));
+            }
+
+            let built = if fields.is_empty() {
+                ty_name.clone()
+            } else if super::is_newtype(data) {
+                format!("{}(Marshal::from_obj(obj))", ty_name)
+            } else {
+                format!("{}({})", ty_name, from_field(fields, true))
+            };
+
+            let (read, stored) = if is_enumeration {
+                (String::new(), "::rt::data::tag(0)".to_string())
+            } else if super::is_newtype(data) {
+                // A newtype is represented by its field.
+                (String::new(), into_fields(fields))
+            } else {
+                let record = mangler.record(name);
+                (format!("let value = obj.unbox::<{}>();\n        ", record),
+                 format!("Obj::from({}({}))", record, into_fields(fields)))
+            };
+
+            code.push_str(&format!(
+                "impl Marshal for {ty} {{\n    \
+                     fn from_obj(obj: &Obj) -> {ty} {{\n        \
+                         {read}{built}\n    \
+                     }}\n\n    \
+                     fn into_obj(self) -> Obj {{\n        \
+                         let {pattern} = self;\n        \
+                         {stored}\n    \
+                     }}\n\
+                 }}\n\n",
+                ty = ty_name,
+                read = read,
+                built = built,
+                pattern = pattern("", &ty_name, fields),
+                stored = stored));
+        } else {
+            let decls: Vec<_> = variants.iter().map(|&(ref variant, ref fields)| {
+                if fields.is_empty() {
+                    format!("    {},\n", variant)
+                } else {
+                    format!("    {}({}),\n", variant, fields.join(", "))
+                }
+            }).collect();
+
+            code.push_str(&format!("pub enum {} {{\n{}}}\n\n", ty_name, decls.concat()));
+
+            let mut from_arms = String::new();
+            let mut into_arms = String::new();
+            let prefix = format!("{}::", ty_name);
+
+            for (tag, &(ref variant, ref fields)) in variants.iter().enumerate() {
+                let built = if fields.is_empty() {
+                    format!("{}{}", prefix, variant)
+                } else {
+                    format!("{}{}({})", prefix, variant, from_field(fields, false))
+                };
+
+                let stored = if is_enumeration {
+                    format!("::rt::data::tag({})", tag)
+                } else {
+                    format!("::rt::data::construct({}, vec![{}])", tag, into_fields(fields))
+                };
+
+                from_arms.push_str(&format!("            {} => {},\n", tag, built));
+                into_arms.push_str(&format!("            {} => {},\n",
+                                            pattern(&prefix, variant, fields),
+                                            stored));
+            }
+
+            let read = if is_enumeration {
+                "match ::rt::data::tag_of(obj) {\n"
+            } else {
+                "let value = obj.unbox::<::rt::data::Data>();\n        \
+                 match value.tag {\n"
+            };
+
+            code.push_str(&format!(
+                "impl Marshal for {ty} {{\n    \
+                     fn from_obj(obj: &Obj) -> {ty} {{\n        \
+                         {read}\
+                 {from_arms}            \
+                             tag => panic!(\"`{ty}` has no constructor {{}}\", tag),\n        \
+                         }}\n    \
+                     }}\n\n    \
+                     fn into_obj(self) -> Obj {{\n        \
+                         match self {{\n\
+                 {into_arms}        \
+                         }}\n    \
+                     }}\n\
+                 }}\n\n",
+                ty = ty_name,
+                read = read,
+                from_arms = from_arms,
+                into_arms = into_arms));
+        }
+
+        Ok(code)
+    }
+}
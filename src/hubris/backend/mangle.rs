@@ -0,0 +1,136 @@
+//! Mapping `core::Name`s to Rust identifiers.
+//!
+//! Qualified names are mangled by length prefixing each component, so
+//! `Nat.add` becomes `_H3Nat3add`. Underscores are doubled and characters
+//! that may not appear in a Rust identifier are replaced by `_x` followed by
+//! the hex value of each of their bytes. Locals, de Bruijn indices, and
+//! metavariables get their own prefixes and are identified by their number,
+//! their name is only kept to make the output readable.
+//!
+//! Every identifier starts with an underscore followed by an upper case
+//! letter, so the result can never be a keyword or clash with the runtime.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use super::core;
+
+pub struct Mangler {
+    names: HashMap<core::Name, String>,
+    /// The name each identifier was produced from, written out next to the
+    /// generated code for debugging.
+    symbols: BTreeMap<String, core::Name>,
+}
+
+impl Mangler {
+    pub fn new() -> Mangler {
+        Mangler {
+            names: HashMap::new(),
+            symbols: BTreeMap::new(),
+        }
+    }
+
+    /// The identifier for `name`, the same name is always given the same
+    /// identifier.
+    pub fn mangle(&mut self, name: &core::Name) -> String {
+        if let Some(ident) = self.names.get(name) {
+            return ident.clone();
+        }
+
+        let base = mangle_name(name);
+        let mut ident = base.clone();
+        let mut suffix = 0;
+
+        // The scheme is injective, but names that compare unequal may still
+        // share a rendering, for example a local and a copy of it that was
+        // renumbered, so we disambiguate rather than emit a duplicate.
+        while self.symbols.contains_key(&ident) {
+            suffix += 1;
+            ident = format!("{}_{}", base, suffix);
+        }
+
+        self.names.insert(name.clone(), ident.clone());
+        self.symbols.insert(ident.clone(), name.clone());
+
+        ident
+    }
+
+    /// The struct the values of the record type `name` are stored in, `R`
+    /// followed by the type's identifier, which no other identifier starts
+    /// with.
+    pub fn record(&mut self, name: &core::Name) -> String {
+        format!("R{}", self.mangle(name))
+    }
+
+    /// Add the identifiers `other` produced, used to combine the manglers of
+    /// definitions rendered separately. Globals are given the same identifier
+    /// by every mangler and locals never escape their definition, so an
+    /// identifier already present is kept.
+    pub fn merge(&mut self, other: Mangler) {
+        for (ident, name) in other.symbols {
+            if !self.symbols.contains_key(&ident) {
+                self.names.entry(name.clone()).or_insert(ident.clone());
+                self.symbols.insert(ident, name);
+            }
+        }
+    }
+
+    /// Write each identifier and the name it was produced from, one per line.
+    pub fn write_symbols<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for (ident, name) in &self.symbols {
+            try!(writeln!(out, "{}\t{}", ident, name));
+        }
+
+        Ok(())
+    }
+}
+
+fn mangle_name(name: &core::Name) -> String {
+    match name {
+        &core::Name::Qual { ref components, .. } => {
+            let mut ident = "_H".to_string();
+
+            for component in components {
+                let escaped = escape(component);
+                ident.push_str(&format!("{}", escaped.len()));
+
+                // Separate the length from a component that starts with a
+                // digit, underscores are always doubled so this is unambiguous.
+                if escaped.starts_with(|c: char| c.is_digit(10) || c == '_') {
+                    ident.push('_');
+                }
+
+                ident.push_str(&escaped);
+            }
+
+            ident
+        }
+        &core::Name::Local { number, ref repr, .. } =>
+            format!("_L{}_{}", number, escape(repr)),
+        &core::Name::DeBruijn { index, ref repr, .. } =>
+            format!("_D{}_{}", index, escape(repr)),
+        &core::Name::Meta { number, .. } =>
+            format!("_M{}", number),
+    }
+}
+
+/// Replace every character that is not allowed in an identifier, underscores
+/// are doubled so an escape can never be confused with the original text.
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in s.chars() {
+        if (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || (c >= '0' && c <= '9') {
+            escaped.push(c);
+        } else if c == '_' {
+            escaped.push_str("__");
+        } else {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                escaped.push_str(&format!("_x{:02x}", byte));
+            }
+        }
+    }
+
+    escaped
+}
@@ -1,90 +1,714 @@
-use std::fmt::{self, Debug, Formatter, Display};
-use std::fs::File;
-use std::path::{Path};
-use std::rc::Rc;
-use std::io::Write;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Formatter, Display};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use super::ast::{HasSpan, Span};
 use super::core;
+use super::diagnostic::Diagnostic;
 use super::typeck::TyCtxt;
+use super::timing::{Instant, Phase};
 use pretty::*;
+use rayon::prelude::*;
+
+mod cargo;
+mod haskell;
+mod javascript;
+mod library;
+mod mangle;
+mod nat;
+mod optimize;
+mod primitive;
+
+use self::cargo::Package;
+use self::haskell::Haskell;
+use self::javascript::JavaScript;
+use self::mangle::Mangler;
+
+pub use self::optimize::{Optimization, Pass};
 
 /// A trait that describes the interface to a particular compiler backend.
+///
+/// Backends are used as trait objects so the driver can pick one at runtime,
+/// see `BackendKind`.
 pub trait Backend {
-    fn create_executable<P: AsRef<Path> + Debug>(main: core::Definition, ty_cx: TyCtxt, output: Option<P>);
+    /// The name used to select this backend on the command line.
+    fn name(&self) -> &'static str;
+
+    fn create_executable(&self, main: core::Definition, ty_cx: TyCtxt, output: Option<PathBuf>)
+                         -> io::Result<()>;
+
+    /// Produce a library exposing the definitions marked `@[export_rust]`.
+    fn create_library(&self, ty_cx: TyCtxt, output: Option<PathBuf>) -> io::Result<()>;
+
+    /// Write out the backend's intermediate representation of the program
+    /// as it is once the optimization pass `after` has run.
+    fn dump_ir(&self, ty_cx: TyCtxt, after: Pass, out: &mut Write) -> io::Result<()>;
+}
+
+/// The backends the compiler knows about, selected with `--backend`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BackendKind {
+    /// Generate Rust code linked against `hubris_rt`.
+    Rust,
+    /// Extract a GHC Haskell module.
+    Haskell,
+    /// Generate JavaScript for Node and the browser.
+    JavaScript,
+}
+
+impl BackendKind {
+    pub fn from_str(s: &str) -> Option<BackendKind> {
+        match s {
+            "rust" => Some(BackendKind::Rust),
+            "haskell" => Some(BackendKind::Haskell),
+            "js" => Some(BackendKind::JavaScript),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            BackendKind::Rust => "rust",
+            BackendKind::Haskell => "haskell",
+            BackendKind::JavaScript => "js",
+        }
+    }
+
+    /// The implementation of this backend.
+    pub fn backend(&self) -> Box<Backend> {
+        match *self {
+            BackendKind::Rust => Box::new(Rust),
+            BackendKind::Haskell => Box::new(Haskell),
+            BackendKind::JavaScript => Box::new(JavaScript),
+        }
+    }
+}
+
+impl Default for BackendKind {
+    fn default() -> BackendKind {
+        BackendKind::Rust
+    }
+}
+
+/// How the arguments of a call are evaluated in generated code.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Evaluation {
+    /// Call-by-value, arguments are evaluated before the call.
+    Strict,
+    /// Call-by-need, arguments are passed as thunks from `hubris_rt` and
+    /// evaluated at most once, the first time they are used.
+    Lazy,
+}
+
+impl Evaluation {
+    pub fn from_str(s: &str) -> Option<Evaluation> {
+        match s {
+            "strict" => Some(Evaluation::Strict),
+            "lazy" => Some(Evaluation::Lazy),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Evaluation {
+    fn default() -> Evaluation {
+        Evaluation::Strict
+    }
 }
 
 pub struct Rust;
 
 impl Backend for Rust {
-    fn create_executable<P: AsRef<Path> + Debug>(main: core::Definition, ty_cx: TyCtxt, output: Option<P>) {
-        let mut erasure_cx = ErasureCx::new(&ty_cx);
-        let mut output_file = File::create(output.unwrap()).unwrap();
-
-        // First we declare the runtime as an external crate, and bring all
-        // of its types and functions into scope.
-        output_file.write(&"extern crate rt;\n\nuse rt::*;\n\n".as_bytes()[..]);
-
-        let mut definitions = vec![];
-
-        // We then loop through the types creating definitions for the types and constructors.
-        //
-        // After we fully implement type erasure we should be able to remove the need to ever
-        // have the types exists as runtime values.
-        //
-        // Currently we just generate panics for their bodies since evaluating code like
-        // this should be a bug.
+    fn name(&self) -> &'static str {
+        "rust"
+    }
+
+    fn create_executable(&self, main: core::Definition, ty_cx: TyCtxt, output: Option<PathBuf>)
+                         -> io::Result<()> {
+        let output = output.unwrap_or(ty_cx.session.root_file().with_extension(""));
+
+        // The runtime and any user crates are declared by the package, each
+        // generated module brings the runtime into scope itself.
+        let mut package = Package::for_output(&output);
+        package.dependencies = ty_cx.session.options().rust_dependencies;
+        let mut mangler = Mangler::new();
+        package.entry = Some(mangler.mangle(&main.name));
+
+        let roots = Some(main.name).into_iter().collect();
+        try!(self.generate(&ty_cx, &roots, &mut package, &mut mangler));
+
+        try!(package.write());
+        try!(mangler.write_symbols(&mut try!(package.create_file("symbols.map"))));
+
+        let target = ty_cx.session.options().target;
+        package.build(target.as_ref().map(|t| &t[..]), &output)
+    }
+
+    fn create_library(&self, ty_cx: TyCtxt, output: Option<PathBuf>) -> io::Result<()> {
+        // The wrappers hand values to the generated code directly, they do
+        // not know how to suspend or force them.
+        if ty_cx.session.options().evaluation != Evaluation::Strict {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "a library can only be built with strict evaluation"));
+        }
+
+        let output = output.unwrap_or(ty_cx.session.root_file().with_extension(""));
+
+        let mut package = Package::library(&output);
+        package.dependencies = ty_cx.session.options().rust_dependencies;
+        let mut mangler = Mangler::new();
+
+        // The wrappers call the exported definitions with all of their
+        // arguments, so the optimizer must keep them as they are.
+        let roots = ty_cx.rust_exports.keys().cloned().collect();
+        try!(self.generate(&ty_cx, &roots, &mut package, &mut mangler));
+
+        let start = Instant::now();
+
+        let api = match library::wrappers(&ty_cx, &mut mangler) {
+            Ok(api) => api,
+            Err(errors) => {
+                let count = errors.len();
+
+                for (span, message) in errors {
+                    try!(Diagnostic::error(message).span(span).emit(&ty_cx.session));
+                }
+
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} definition{} can not be exported to Rust",
+                            count,
+                            if count == 1 { "" } else { "s" })));
+            }
+        };
+
+        package.module("api".to_string()).extend(api.into_bytes());
+
+        ty_cx.session.record_time(Phase::Codegen, None, start);
+
+        try!(package.write());
+        mangler.write_symbols(&mut try!(package.create_file("symbols.map")))
+    }
+
+    fn dump_ir(&self, ty_cx: TyCtxt, after: Pass, out: &mut Write) -> io::Result<()> {
+        // Without a `main` every definition is treated as used.
+        let roots = match ty_cx.get_main() {
+            Ok(main) => Some(main.name.clone()).into_iter().collect(),
+            Err(_) => ty_cx.definitions.keys().cloned().collect(),
+        };
+
+        let definitions = try!(lower(&ty_cx));
+        let definitions = optimize::optimize(&ty_cx.session, definitions, &roots, Some(after));
+
+        for def in &definitions {
+            try!(writeln!(out, "{}\n", def));
+        }
+
+        Ok(())
+    }
+}
+
+impl Rust {
+    /// Lower and optimize every global in `ty_cx` and add the Rust code for
+    /// it to the module of `package` it belongs in. `roots` are the
+    /// definitions called from outside the generated code.
+    fn generate(&self,
+                ty_cx: &TyCtxt,
+                roots: &BTreeSet<core::Name>,
+                package: &mut Package,
+                mangler: &mut Mangler) -> io::Result<()> {
+        let evaluation = ty_cx.session.options().evaluation;
+        let debug_info = ty_cx.session.options().debug_info;
+
+        try!(check_crates(ty_cx, &package.dependencies));
+        let definitions = try!(lower(ty_cx));
+        let definitions = optimize::optimize(&ty_cx.session, definitions, roots, None);
+
+        let start = Instant::now();
+
+        // We have now produced a set of definitions that we then convert to
+        // Rust code, in parallel, and place in the module they were defined
+        // in. Each thread mangles with its own mangler, a global is always
+        // given the same identifier so they agree on those.
+        let rendered: Vec<_> = definitions.par_iter().map(|def| {
+            debug!("generate: lowered={}", def);
+            let mut def_mangler = Mangler::new();
+            let rust_code = def_to_rust(&mut def_mangler, evaluation, debug_info, def);
+            let mut code = vec![];
+            Doc::render(&rust_code, 80, &mut code).unwrap();
+            code.push(b'\n');
+            let module = module_to_rust(&mut def_mangler, &def.name);
+            (module, code, def_mangler)
+        }).collect();
+
+        for (module, code, def_mangler) in rendered {
+            package.module(module).extend(code);
+            mangler.merge(def_mangler);
+        }
+
         for (name, data) in &ty_cx.types {
-            println!("data: {}", name);
-            definitions.push(Definition {
-                name: name.clone(),
-                body: Term::Panic("a".to_string())
-            });
+            if let Some(fields) = record_fields(data) {
+                // A newtype is represented by its field.
+                if fields == 1 {
+                    continue;
+                }
+
+                let code = record_to_rust(mangler, name, fields, &location(ty_cx, name));
+                package.module(module_to_rust(mangler, name)).extend(code.into_bytes());
+            }
         }
 
-        for (n, axiom) in &ty_cx.axioms {
-            println!("axiom: {}", n);
+        ty_cx.session.record_time(Phase::Codegen, None, start);
+
+        Ok(())
+    }
+}
+
+/// Check that the crates externs are implemented in are among the
+/// `dependencies` of the package, which give the version of each.
+fn check_crates(ty_cx: &TyCtxt, dependencies: &[(String, String)]) -> io::Result<()> {
+    let mut undeclared = 0;
+
+    for (name, convention) in &ty_cx.externs {
+        let crate_name = match convention.crate_name {
+            Some(ref crate_name) => crate_name,
+            None => continue,
+        };
+
+        if !dependencies.iter().any(|&(ref dependency, _)| dependency == crate_name) {
+            try!(Diagnostic::error(format!("the extern `{}` is implemented in the crate `{}`, \
+                                            which is not a dependency", name, crate_name))
+                     .span(name.get_span())
+                     .help(format!("declare the version of `{}` to use under \
+                                    `[rust_dependencies]` in the project's manifest",
+                                   crate_name))
+                     .emit(&ty_cx.session));
+            undeclared += 1;
         }
+    }
+
+    if undeclared > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} extern{} implemented in undeclared crates",
+                    undeclared,
+                    if undeclared == 1 { " is" } else { "s are" })));
+    }
+
+    Ok(())
+}
 
-        for (n, def) in &ty_cx.definitions {
-            definitions.push(erasure_cx.lower_def(def.clone()));
+/// Lower every global in `ty_cx` to the untyped language the backend
+/// works with.
+fn lower(ty_cx: &TyCtxt) -> io::Result<Vec<Definition>> {
+    let mut definitions = vec![];
+    let start = Instant::now();
+
+    // We then loop through the types creating definitions for the types and constructors.
+    //
+    // After we fully implement type erasure we should be able to remove the need to ever
+    // have the types exists as runtime values.
+    //
+    // Currently we just generate panics for their bodies since evaluating code like
+    // this should be a bug.
+    for (name, data) in &ty_cx.types {
+        debug!("generate: data={}", name);
+        let location = location(ty_cx, name);
+        definitions.push(Definition {
+            name: name.clone(),
+            body: Term::Panic(format!("the type `{}` defined at {} was evaluated",
+                                      name, location)),
+            location: location,
+        });
+
+        if nat::is_nat(ty_cx, data) {
+            definitions.extend(nat::lower(ty_cx, data).into_iter());
+        } else {
+            definitions.extend(lower_constructors(ty_cx, data).into_iter());
         }
+    }
 
-        // We have now produced a set of definitions that we then convert to
-        // Rust code and write to the output file.
-        for def in definitions {
-            println!("-----------(lowered)-----------------");
-            println!("{}", def);
-            println!("-----------(rust)-----------------");
-            let rust_code = def_to_rust(&def);
-            let mut v = Vec::new();
-            Doc::render(&rust_code, 80, &mut v).unwrap();
-            output_file.write(&v[..]);
-            output_file.write(&"\n".as_bytes[..]);
-            println!("{}", String::from_utf8(v).unwrap());
+    // Externs declared with a symbol are bound to the runtime function
+    // implementing them, once we have checked it can be called that way.
+    // Those implemented in another crate are left for rustc to check.
+    let mut invalid_externs = 0;
+    for (n, convention) in &ty_cx.externs {
+        if convention.crate_name.is_some() {
+            definitions.push(lower_extern(ty_cx, n, convention, |args| {
+                Term::Foreign(convention.symbol.clone(), args)
+            }));
+
+            continue;
+        }
+
+        match primitive::resolve(n, convention) {
+            Ok(prim) => definitions.push(lower_extern(ty_cx, n, convention, |args| {
+                Term::Call(Box::new(Term::Native(prim.symbol)), args)
+            })),
+            Err(message) => {
+                try!(Diagnostic::error(message).span(n.get_span()).emit(&ty_cx.session));
+                invalid_externs += 1;
+            }
         }
     }
+
+    if invalid_externs > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} extern{} did not match the runtime",
+                    invalid_externs,
+                    if invalid_externs == 1 { "" } else { "s" })));
+    }
+
+    // Once elaborated the definitions are independent of each other, so
+    // they are lowered in parallel. The typing context can not be shared
+    // between threads, everything lowering needs from it is gathered
+    // first, and the results are collected in the order of the
+    // definitions so the output does not depend on scheduling.
+    let globals = global_types(ty_cx);
+    let projections = projections(ty_cx);
+    let enumerations = enumerations(ty_cx);
+
+    // Projecting from a record that is not a variable binds it first,
+    // always to this local, which an inner binding may safely shadow.
+    let scrutinee = ty_cx.local_with_repr("scrutinee".to_string(), core::Term::Type);
+    let mut pending = vec![];
+
+    for def in ty_cx.definitions.values() {
+        match nat::replacement(ty_cx, def) {
+            Some(replacement) => definitions.push(replacement),
+            None => pending.push((def.clone(), location(ty_cx, &def.name))),
+        }
+    }
+
+    let lowered: Vec<_> = pending.into_par_iter().map(|(def, location)| {
+        ErasureCx::new(&globals, &projections, &enumerations, &scrutinee)
+            .lower_def(def, location)
+    }).collect();
+
+    definitions.extend(lowered.into_iter());
+
+    ty_cx.session.record_time(Phase::Erasure, None, start);
+
+    Ok(definitions)
 }
 
-fn name_to_rust(name: &core::Name) -> Doc {
-    match name {
-        &core::Name::Qual { ref components, .. } => {
-            let pieces: Vec<_> =
-                components.iter()
-                          .map(|c| c.pretty())
-                          .collect();
-            seperate(&pieces[..], &"_".pretty())
+/// The type of every global, which is all lowering a definition needs from
+/// the typing context.
+fn global_types(ty_cx: &TyCtxt) -> BTreeMap<core::Name, core::Term> {
+    let mut types = BTreeMap::new();
+
+    for (name, axiom) in &ty_cx.axioms {
+        types.insert(name.clone(), axiom.ty.clone());
+    }
+
+    // A definition takes priority over an axiom, as in `lookup_global`.
+    for (name, def) in &ty_cx.definitions {
+        types.insert(name.clone(), def.ty.clone());
+    }
+
+    types
+}
+
+/// The source location of the global `name`, as `file:line:column`.
+fn location(ty_cx: &TyCtxt, name: &core::Name) -> String {
+    let span = name.get_span();
+
+    if span == Span::dummy() {
+        "<unknown>".to_string()
+    } else {
+        ty_cx.session.location(span)
+    }
+}
+
+/// Bind the extern `name` to the function implementing it, `call` builds
+/// the call from the arguments that remain once the erased ones are
+/// dropped.
+fn lower_extern<F>(ty_cx: &TyCtxt,
+                   name: &core::Name,
+                   convention: &core::Convention,
+                   call: F) -> Definition
+    where F: FnOnce(Vec<Term>) -> Term
+{
+    let args : Vec<_> =
+        (0..convention.arity)
+            .map(|i| ty_cx.local_with_repr(format!("x{}", i), core::Term::Type))
+            .collect();
+
+    let passed =
+        args.iter()
+            .enumerate()
+            .filter(|&(i, _)| !convention.erased.contains(&i))
+            .map(|(_, arg)| Term::Var(arg.clone()))
+            .collect();
+
+    let call = call(passed);
+
+    // Callers do not pass the arguments of erased parameters at all, unlike
+    // those of implicit ones.
+    let mut erased = vec![];
+    if let Some(axiom) = ty_cx.axioms.get(name) {
+        let mut ty = &axiom.ty;
+        while let &core::Term::Forall { ref binder, ref term, .. } = ty {
+            erased.push(binder.is_irrelevant());
+            ty = &**term;
+        }
+    }
+
+    let params: Vec<_> =
+        args.into_iter()
+            .enumerate()
+            .filter(|&(i, _)| !erased.get(i).cloned().unwrap_or(false))
+            .map(|(_, arg)| arg)
+            .collect();
+
+    Definition {
+        name: name.clone(),
+        body: if params.is_empty() { call } else { Term::Lambda(params, Box::new(call)) },
+        location: location(ty_cx, name),
+    }
+}
+
+/// A definition for each constructor of `data`, taking the constructor's
+/// relevant arguments and storing those following the parameters.
+fn lower_constructors(ty_cx: &TyCtxt, data: &core::Data) -> Vec<Definition> {
+    let is_record = record_fields(data).is_some();
+    let is_newtype = is_newtype(data);
+    let is_enumeration = is_enumeration(data);
+
+    let mut definitions = vec![];
+
+    for (tag, &(ref name, ref ty)) in data.ctors.iter().enumerate() {
+        let mut args = vec![];
+        let mut fields = vec![];
+        let mut ty = ty;
+        let mut position = 0;
+
+        while let &core::Term::Forall { ref binder, ref term, .. } = ty {
+            if !binder.is_irrelevant() {
+                let arg = ty_cx.local_with_repr(format!("x{}", position), core::Term::Type);
+
+                if position >= data.parameters.len() {
+                    fields.push(Term::Var(arg.clone()));
+                }
+
+                args.push(arg);
+            }
+
+            position += 1;
+            ty = &**term;
+        }
+
+        let construct = if is_enumeration {
+            Term::Tag(tag)
+        } else if is_newtype {
+            fields.pop().unwrap()
+        } else if is_record {
+            Term::Record(data.name.clone(), fields)
+        } else {
+            Term::Construct(tag, fields)
+        };
+
+        definitions.push(Definition {
+            name: name.clone(),
+            body: if args.is_empty() { construct } else { Term::Lambda(args, Box::new(construct)) },
+            location: location(ty_cx, name),
+        });
+    }
+
+    definitions
+}
+
+/// The number of fields stored for the constructor of `data` whose type is
+/// `ctor_ty`, those of its relevant arguments following the parameters.
+fn stored_fields(data: &core::Data, ctor_ty: &core::Term) -> usize {
+    let mut ty = ctor_ty;
+    let mut position = 0;
+    let mut fields = 0;
+
+    while let &core::Term::Forall { ref binder, ref term, .. } = ty {
+        if position >= data.parameters.len() && !binder.is_irrelevant() {
+            fields += 1;
         }
-        &core::Name::DeBruijn { ref repr, .. } => repr.pretty(),
-        &core::Name::Local { ref repr, .. } => repr.pretty(),
-        &core::Name::Meta { .. } => panic!(),
+
+        position += 1;
+        ty = &**term;
     }
+
+    fields
+}
+
+/// Whether no constructor of `data` stores a field, its values are then
+/// represented by their tag alone.
+fn is_enumeration(data: &core::Data) -> bool {
+    data.ctors.iter().all(|&(_, ref ty)| stored_fields(data, ty) == 0)
 }
 
-fn def_to_rust(def: &Definition) -> Doc {
+/// The number of fields stored for the only constructor of `data`. `None`
+/// if it has more than one constructor, or none, or is an enumeration.
+fn record_fields(data: &core::Data) -> Option<usize> {
+    if data.ctors.len() != 1 || is_enumeration(data) {
+        None
+    } else {
+        Some(stored_fields(data, &data.ctors[0].1))
+    }
+}
+
+/// Whether `data` is a newtype, a record with a single field, whose values
+/// are represented by the field itself. Building one and projecting from it
+/// do nothing.
+fn is_newtype(data: &core::Data) -> bool {
+    record_fields(data) == Some(1)
+}
+
+/// The shape of an application of a record's recursor, for the records
+/// whose fields are all stored and none of which are recursive. The minor
+/// premise is then given the fields directly, so no switch is needed.
+#[derive(Clone, Debug)]
+struct Projection {
+    /// The record type.
+    name: core::Name,
+    parameters: usize,
+    indices: usize,
+    fields: usize,
+}
+
+impl Projection {
+    /// The arguments the recursor takes, the parameters, the motive, the
+    /// minor premise, the indices and the record.
+    fn arity(&self) -> usize {
+        self.parameters + self.indices + 3
+    }
+}
+
+/// The shape of an application of an enumeration's recursor, which jumps
+/// to the minor premise for the tag of the value.
+#[derive(Clone, Debug)]
+struct Enumeration {
+    parameters: usize,
+    indices: usize,
+    constructors: usize,
+}
+
+impl Enumeration {
+    /// The arguments the recursor takes, the parameters, the motive, a
+    /// minor premise for each constructor, the indices and the value.
+    fn arity(&self) -> usize {
+        self.parameters + self.constructors + self.indices + 2
+    }
+}
+
+/// The enumerations, keyed by the name of their recursor.
+fn enumerations(ty_cx: &TyCtxt) -> BTreeMap<core::Name, Enumeration> {
+    let mut enumerations = BTreeMap::new();
+
+    for (name, data) in &ty_cx.types {
+        if !is_enumeration(data) {
+            continue;
+        }
+
+        let binders = data.ty.binders().map_or(0, |bs| bs.len());
+
+        enumerations.insert(name.in_scope("rec".to_string()).unwrap(), Enumeration {
+            parameters: data.parameters.len(),
+            indices: binders - data.parameters.len(),
+            constructors: data.ctors.len(),
+        });
+    }
+
+    enumerations
+}
+
+/// The records whose recursor applications are lowered to projections,
+/// keyed by the name of the recursor.
+fn projections(ty_cx: &TyCtxt) -> BTreeMap<core::Name, Projection> {
+    let mut projections = BTreeMap::new();
+
+    for (name, data) in &ty_cx.types {
+        let fields = match record_fields(data) {
+            Some(fields) => fields,
+            None => continue,
+        };
+
+        let mut ty = &data.ctors[0].1;
+        let mut arguments = 0;
+        let mut is_recursive = false;
+
+        while let &core::Term::Forall { ref binder, ref term, .. } = ty {
+            let mut field_ty = &*binder.ty;
+            while let &core::Term::Forall { ref term, .. } = field_ty {
+                field_ty = &**term;
+            }
+
+            if let (core::Term::Var { name: ref head }, _) = field_ty.uncurry() {
+                is_recursive = is_recursive || head == name;
+            }
+
+            arguments += 1;
+            ty = &**term;
+        }
+
+        if is_recursive || arguments - data.parameters.len() != fields {
+            continue;
+        }
+
+        let binders = data.ty.binders().map_or(0, |bs| bs.len());
+
+        projections.insert(name.in_scope("rec".to_string()).unwrap(), Projection {
+            name: name.clone(),
+            parameters: data.parameters.len(),
+            indices: binders - data.parameters.len(),
+            fields: fields,
+        });
+    }
+
+    projections
+}
+
+/// The struct the values of the record `name` are stored in, the fields are
+/// held inline rather than in a vector behind a tag.
+fn record_to_rust(mangler: &mut Mangler, name: &core::Name, fields: usize, location: &str)
+                  -> String {
+    let ident = mangler.record(name);
+    let fields: Vec<_> = (0..fields).map(|_| "pub Obj").collect();
+
+    if fields.is_empty() {
+        format!("// {} at {}
+pub struct {};
+
+", name, location, ident)
+    } else {
+        format!("// {} at {}
+pub struct {}({});
+
+", name, location, ident, fields.join(", "))
+    }
+}
+
+/// The Rust module a global is emitted into, the mangled name of the Hubris
+/// module it was defined in, which can not be a keyword or clash with
+/// another module, `root` or `api`.
+fn module_to_rust(mangler: &mut Mangler, name: &core::Name) -> String {
+    match name {
+        &core::Name::Qual { ref components, .. } if components.len() > 1 =>
+            mangler.mangle(&core::Name::qualified(components[..components.len() - 1].to_vec())),
+        _ => "root".to_string(),
+    }
+}
+
+fn name_to_rust(mangler: &mut Mangler, name: &core::Name) -> Doc {
+    Doc::text(mangler.mangle(name))
+}
+
+/// The Rust function for `def`, which enters a frame for the runtime to
+/// report on panic when `debug_info` is set.
+fn def_to_rust(mangler: &mut Mangler, evaluation: Evaluation, debug_info: bool, def: &Definition)
+               -> Doc {
     let (args, body) = match &def.body {
         &Term::Lambda(ref ns, ref body) => {
             let args : Vec<_> =
                 ns.iter()
-                  .map(|n| name_to_rust(n) + ": Obj".pretty())
+                  .map(|n| name_to_rust(mangler, n) + ": Obj".pretty())
                   .collect();
 
             (args, &**body)
@@ -92,10 +716,22 @@ fn def_to_rust(def: &Definition) -> Doc {
         t => (vec![], t)
     };
 
-    "fn ".pretty() +
-    name_to_rust(&def.name) +
+    let frame = if debug_info {
+        format!("let _frame = ::rt::frame::enter({:?}, {:?});\n",
+                format!("{}", def.name), def.location).pretty()
+    } else {
+        "".pretty()
+    };
+
+    // Point readers of the generated code, and the panics it raises, back at
+    // the Hubris source the definition came from.
+    "// ".pretty() + def.name.pretty() + " at ".pretty() + def.location.pretty() +
+    Doc::newline() +
+    "pub fn ".pretty() +
+    name_to_rust(mangler, &def.name) +
     parens(seperate(&args[..], &",".pretty())) + " -> Obj {\n".pretty() +
-        term_to_rust(body) + "\n".pretty() +
+        frame +
+        term_to_rust(mangler, evaluation, body) + "\n".pretty() +
     "}\n".pretty()
 }
 
@@ -109,23 +745,112 @@ fn to_object(value: Doc) -> Doc {
     "Obj::from".pretty() + parens(value)
 }
 
-fn term_to_rust(term: &Term) -> Doc {
+/// Suspend `arg` in a thunk, the closure is given its own copy of each local
+/// it refers to.
+fn delay(mangler: &mut Mangler, arg: &Term) -> Doc {
+    let mut locals = BTreeSet::new();
+    arg.free_locals(&mut vec![], &mut locals);
+
+    let mut captures = "".pretty();
+    for local in &locals {
+        captures = captures + "let ".pretty() + name_to_rust(mangler, local) + " = ".pretty() +
+            name_to_rust(mangler, local) + ".clone();".pretty() + Doc::newline();
+    }
+
+    "Obj::delay".pretty() + parens(block(
+        captures +
+        "move || ".pretty() + term_to_rust(mangler, Evaluation::Lazy, arg) + Doc::newline()))
+}
+
+fn term_to_rust(mangler: &mut Mangler, evaluation: Evaluation, term: &Term) -> Doc {
     match term {
+        // The runtime's functions expect their arguments to be evaluated.
+        &Term::Call(ref f, ref args) if f.is_native() => {
+            let args : Vec<_> =
+                args.iter()
+                    .map(|x| term_to_rust(mangler, evaluation, x))
+                    .collect();
+            term_to_rust(mangler, evaluation, &**f) + parens(seperate(&args[..], &",".pretty()))
+        }
         &Term::Call(ref f, ref args) => {
-            let args : Vec<_> = args.iter().map(|x| term_to_rust(x)).collect();
-            term_to_rust(&**f) + parens(seperate(&args[..], &",".pretty()))
+            let args : Vec<_> = args.iter().map(|x| match evaluation {
+                Evaluation::Strict => term_to_rust(mangler, evaluation, x),
+                Evaluation::Lazy => delay(mangler, x),
+            }).collect();
+            term_to_rust(mangler, evaluation, &**f) + parens(seperate(&args[..], &",".pretty()))
         }
-        &Term::Var(ref name) => name_to_rust(name),
+        // Under lazy evaluation every local is bound to a thunk.
+        &Term::Var(ref name) if evaluation == Evaluation::Lazy && !name.is_qual() =>
+            name_to_rust(mangler, name) + ".force()".pretty(),
+        &Term::Var(ref name) => name_to_rust(mangler, name),
         &Term::Lambda(ref ns, ref body) => {
             let args : Vec<_> =
                 ns.iter()
-                  .map(|n| name_to_rust(n) + ": Obj".pretty())
+                  .map(|n| name_to_rust(mangler, n) + ": Obj".pretty())
                   .collect();
             to_object("|".pretty() + seperate(&args[..], &",".pretty()) + "|".pretty() +
-                block(term_to_rust(body)))
+                block(term_to_rust(mangler, evaluation, body)))
+        }
+        &Term::Construct(tag, ref fields) => {
+            let fields : Vec<_> = fields.iter().map(|x| match evaluation {
+                Evaluation::Strict => term_to_rust(mangler, evaluation, x),
+                Evaluation::Lazy => delay(mangler, x),
+            }).collect();
+            "::rt::data::construct".pretty() +
+                parens(format!("{}", tag).pretty() + ", vec!".pretty() +
+                       "[".pretty() + seperate(&fields[..], &",".pretty()) + "]".pretty())
+        }
+        &Term::Tag(tag) => "::rt::data::tag".pretty() + parens(format!("{}", tag).pretty()),
+        // Only the arm that is jumped to is evaluated.
+        &Term::Switch(ref scrut, ref arms) => {
+            let mut cases = "".pretty();
+            for (tag, arm) in arms.iter().enumerate() {
+                cases = cases + format!("{} => ", tag).pretty() +
+                    block(term_to_rust(mangler, evaluation, arm) + Doc::newline()) +
+                    Doc::newline();
+            }
+
+            "match ::rt::data::tag_of(&".pretty() + term_to_rust(mangler, evaluation, scrut) +
+                ") ".pretty() +
+                block(cases + "tag => panic!(\"no constructor {}\", tag),".pretty() +
+                      Doc::newline())
+        }
+        &Term::Record(ref name, ref fields) => {
+            let fields : Vec<_> = fields.iter().map(|x| match evaluation {
+                Evaluation::Strict => term_to_rust(mangler, evaluation, x),
+                Evaluation::Lazy => delay(mangler, x),
+            }).collect();
+            let ident = mangler.record(name).pretty();
+            if fields.is_empty() {
+                to_object(ident)
+            } else {
+                to_object(ident + parens(seperate(&fields[..], &",".pretty())))
+            }
+        }
+        // Under lazy evaluation the fields are thunks, as they are built.
+        &Term::Project(ref name, index, ref record) => {
+            let ident = mangler.record(name);
+            let field = match evaluation {
+                Evaluation::Strict => "clone",
+                Evaluation::Lazy => "force",
+            };
+            term_to_rust(mangler, evaluation, record) +
+                format!(".unbox::<{}>().{}.{}()", ident, index, field).pretty()
+        }
+        &Term::Native(path) => "::".pretty() + path.pretty(),
+        // The types the function takes and returns are inferred from its
+        // signature, it may take and return `Obj`s as well.
+        &Term::Foreign(ref path, ref args) => {
+            let args : Vec<_> =
+                args.iter()
+                    .map(|x| "::rt::marshal::Marshal::from_obj(&".pretty() +
+                             term_to_rust(mangler, evaluation, x) + ")".pretty())
+                    .collect();
+            "::rt::marshal::Marshal::into_obj".pretty() +
+                parens("::".pretty() + path.clone().pretty() + parens(seperate(&args[..], &",".pretty())))
         }
         &Term::Panic(ref msg) => {
-            "panic!".pretty() + parens("\"".pretty() + msg.pretty() + "\"".pretty())
+            "panic!".pretty() + parens(format!("{:?}", msg).pretty())
         }
         t => panic!("{:?}", t),
     }
@@ -139,6 +864,8 @@ struct Module {
 struct Definition {
     name: core::Name,
     body: Term,
+    /// Where the definition appears in the Hubris source.
+    location: String,
 }
 
 impl Pretty for Definition {
@@ -146,6 +873,7 @@ impl Pretty for Definition {
         let &Definition {
             ref name,
             ref body,
+            ..
         } = self;
 
         "def ".pretty() + name.pretty() + " :=\n".pretty() + body.pretty()
@@ -163,9 +891,27 @@ enum Term {
     Local(core::Name, usize),
     Var(core::Name),
     // Free(core::)
-    Switch(Rc<Term>),
-    Call(Rc<Term>, Vec<Term>),
+    /// A jump on the tag of a value of an enumeration, to the arm for its
+    /// constructor.
+    Switch(Box<Term>, Vec<Term>),
+    Call(Box<Term>, Vec<Term>),
     Lambda(Vec<core::Name>, Box<Term>),
+    /// A value of an inductive type, built by the constructor numbered by
+    /// the tag from its relevant arguments.
+    Construct(usize, Vec<Term>),
+    /// A value of an enumeration, a type whose constructors store no
+    /// fields, represented by the tag alone.
+    Tag(usize),
+    /// A value of the record, a type with a single constructor, named. The
+    /// fields are stored in a struct declared for the type, with no tag.
+    Record(core::Name, Vec<Term>),
+    /// The field numbered of a value of the record named.
+    Project(core::Name, usize, Box<Term>),
+    /// A function provided by the runtime.
+    Native(&'static str),
+    /// A call of a function from another crate, its arguments and result
+    /// are converted with `rt::marshal::Marshal`.
+    Foreign(String, Vec<Term>),
     Panic(String),
 }
 
@@ -176,7 +922,18 @@ impl Pretty for Term {
         match self {
             &Local(_, i) => panic!(),
             &Var(ref name) => name.pretty(),
-            &Switch(ref scrut) => panic!(),
+            &Switch(ref scrut, ref arms) => {
+                let parms =
+                    arms.iter()
+                        .enumerate()
+                        .map(|(tag, arm)| {
+                            format!("{}", tag).pretty() + " => ".pretty() + arm.pretty()
+                        })
+                        .collect::<Vec<_>>();
+
+                "switch ".pretty() + scrut.pretty() + " { ".pretty() +
+                    seperate(&parms[..], &" | ".pretty()) + " }".pretty()
+            }
             &Call(ref f, ref args) => {
                 let pargs =
                     args.iter()
@@ -185,12 +942,99 @@ impl Pretty for Term {
 
                 f.pretty() + parens(seperate(&pargs[..], &",".pretty()))
             }
-            &Lambda(_, ref body) => body.pretty(),
+            &Lambda(ref names, ref body) => {
+                let pnames =
+                    names.iter()
+                         .map(|x| x.pretty())
+                         .collect::<Vec<_>>();
+
+                "fun ".pretty() + seperate(&pnames[..], &" ".pretty()) + " => ".pretty() +
+                    body.pretty()
+            }
+            &Construct(tag, ref fields) => {
+                let pfields =
+                    fields.iter()
+                          .map(|x| x.pretty())
+                          .collect::<Vec<_>>();
+
+                "#".pretty() + format!("{}", tag).pretty() + parens(seperate(&pfields[..], &",".pretty()))
+            }
+            &Tag(tag) => "#".pretty() + format!("{}", tag).pretty(),
+            &Record(ref name, ref fields) => {
+                let pfields =
+                    fields.iter()
+                          .map(|x| x.pretty())
+                          .collect::<Vec<_>>();
+
+                "#".pretty() + name.pretty() + parens(seperate(&pfields[..], &",".pretty()))
+            }
+            &Project(_, index, ref record) =>
+                record.pretty() + ".".pretty() + format!("{}", index).pretty(),
+            &Native(path) => path.pretty(),
+            &Foreign(ref path, ref args) => {
+                let pargs =
+                    args.iter()
+                        .map(|x| x.pretty())
+                        .collect::<Vec<_>>();
+
+                path.clone().pretty() + parens(seperate(&pargs[..], &",".pretty()))
+            }
             &Panic(_) => "panic".pretty(),
         }
     }
 }
 
+impl Term {
+    fn is_native(&self) -> bool {
+        match self {
+            &Term::Native(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Collect the locals referred to in this term that are not bound by
+    /// one of its lambdas.
+    fn free_locals(&self, bound: &mut Vec<core::Name>, locals: &mut BTreeSet<core::Name>) {
+        match self {
+            &Term::Local(ref name, _) |
+            &Term::Var(ref name) => {
+                if !name.is_qual() && !bound.contains(name) {
+                    locals.insert(name.clone());
+                }
+            }
+            &Term::Switch(ref scrut, ref arms) => {
+                scrut.free_locals(bound, locals);
+                for arm in arms {
+                    arm.free_locals(bound, locals);
+                }
+            }
+            &Term::Call(ref f, ref args) => {
+                f.free_locals(bound, locals);
+                for arg in args {
+                    arg.free_locals(bound, locals);
+                }
+            }
+            &Term::Construct(_, ref fields) |
+            &Term::Record(_, ref fields) |
+            &Term::Foreign(_, ref fields) => {
+                for field in fields {
+                    field.free_locals(bound, locals);
+                }
+            }
+            &Term::Project(_, _, ref record) => record.free_locals(bound, locals),
+            &Term::Lambda(ref names, ref body) => {
+                let len = bound.len();
+                bound.extend(names.iter().cloned());
+                body.free_locals(bound, locals);
+                bound.truncate(len);
+            }
+            &Term::Tag(_) |
+            &Term::Native(_) |
+            &Term::Panic(_) => {}
+        }
+    }
+}
+
 impl Display for Term {
     fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
         format(self, formatter)
@@ -199,51 +1043,190 @@ impl Display for Term {
 
 /// This context is used to do type erasure, and lowering of `core::Term` to an
 /// untyped lambda calculus.
-struct ErasureCx<'tcx> {
-    ty_cx: &'tcx TyCtxt
+///
+/// It only refers to the types of the globals, so definitions can be lowered
+/// on several threads at once.
+struct ErasureCx<'g> {
+    globals: &'g BTreeMap<core::Name, core::Term>,
+    /// The records whose recursor applications become projections, keyed by
+    /// the recursor.
+    projections: &'g BTreeMap<core::Name, Projection>,
+    /// The enumerations, whose recursor applications become switches, keyed
+    /// by the recursor.
+    enumerations: &'g BTreeMap<core::Name, Enumeration>,
+    /// The local a record being projected from is bound to.
+    scrutinee: &'g core::Name,
+    /// The binders of the term being lowered that are in scope, innermost
+    /// last, de Bruijn indices count back from the end.
+    binders: Vec<core::Binder>,
 }
 
-impl<'tcx> ErasureCx<'tcx> {
-    pub fn new(ty_cx: &'tcx TyCtxt) -> ErasureCx<'tcx> {
+impl<'g> ErasureCx<'g> {
+    pub fn new(globals: &'g BTreeMap<core::Name, core::Term>,
+               projections: &'g BTreeMap<core::Name, Projection>,
+               enumerations: &'g BTreeMap<core::Name, Enumeration>,
+               scrutinee: &'g core::Name) -> ErasureCx<'g> {
         ErasureCx {
-            ty_cx: ty_cx
+            globals: globals,
+            projections: projections,
+            enumerations: enumerations,
+            scrutinee: scrutinee,
+            binders: vec![],
         }
     }
 
-//     fn lower_module(module: core::Module) -> Module {
-//     Module {
-//         definitions:
-//             module.defs
-//                   .into_iter()
-//                   .filter_map(|i| match i {
-//                       core::Item::Fn(d) => Some(lower_def(d)),
-//                       _ => None,
-//                   })
-//                   .collect()
-//     }
-// }
+    fn bound(&self, index: usize) -> Option<&core::Binder> {
+        if index < self.binders.len() {
+            Some(&self.binders[self.binders.len() - 1 - index])
+        } else {
+            None
+        }
+    }
 
-    fn lower_def(&mut self, def: core::Definition) -> Definition {
+    /// Lower `def`, which appears at `location` in the Hubris source.
+    fn lower_def(&mut self, def: core::Definition, location: String) -> Definition {
         let core::Definition {
             name,
-            args,
-            ty,
             body,
-            reduction,
+            ..
         } = def;
 
-        println!("name: {}", name);
-        println!("ty: {}", ty);
-        println!("body: {}", body);
+        debug!("lower_def: name={} body={}", name, body);
 
-        let def = Definition {
+        Definition {
+            location: location,
             name: name,
             body: self.lower_term(body),
+        }
+    }
+
+    /// Drop the arguments passed to irrelevant binders of a global or a
+    /// local, the lowered function does not take them.
+    fn relevant_args(&self, head: &core::Term, args: Vec<core::Term>) -> Vec<core::Term> {
+        let mut ty = match head {
+            &core::Term::Var { name: core::Name::DeBruijn { index, .. } } =>
+                match self.bound(index) {
+                    Some(binder) => &*binder.ty,
+                    None => return args,
+                },
+            &core::Term::Var { ref name } => match self.globals.get(name) {
+                Some(ty) => ty,
+                None => return args,
+            },
+            _ => return args,
         };
 
-        println!("def: {}", def);
+        let mut relevant = vec![];
 
-        def
+        for arg in args {
+            match ty {
+                &core::Term::Forall { ref binder, ref term, .. } => {
+                    if !binder.is_irrelevant() {
+                        relevant.push(arg);
+                    }
+                    ty = &**term;
+                }
+                _ => relevant.push(arg),
+            }
+        }
+
+        relevant
+    }
+
+    /// How to project from the record `head` is the recursor of, if it is
+    /// applied to at least `args` arguments.
+    fn projection(&self, head: &core::Term, args: usize) -> Option<&'g Projection> {
+        let projections = self.projections;
+
+        match head {
+            &core::Term::Var { ref name } => match projections.get(name) {
+                Some(projection) if args >= projection.arity() => Some(projection),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Lower an application of the recursor of a record, calling the minor
+    /// premise with the fields of the record rather than switching on it.
+    fn lower_projection(&mut self, record: &Projection, mut args: Vec<core::Term>) -> Term {
+        let extra = args.split_off(record.arity());
+        let major = self.lower_term(args.pop().unwrap());
+        let minor = self.lower_term(args.swap_remove(record.parameters + 1));
+
+        // A newtype is its field.
+        if record.fields == 1 {
+            let projected = Term::Call(Box::new(minor), vec![major]);
+            return self.apply_extra(projected, extra);
+        }
+
+        // The record is bound once, instead of being evaluated for each of
+        // its fields.
+        let (scrutinee, bound) = match major {
+            Term::Var(name) => (name, None),
+            major => (self.scrutinee.clone(), Some(major)),
+        };
+
+        let fields =
+            (0..record.fields)
+                .map(|i| Term::Project(record.name.clone(), i,
+                                       Box::new(Term::Var(scrutinee.clone()))))
+                .collect();
+
+        let call = Term::Call(Box::new(minor), fields);
+
+        let projected = match bound {
+            Some(major) =>
+                Term::Call(Box::new(Term::Lambda(vec![scrutinee], Box::new(call))),
+                           vec![major]),
+            None => call,
+        };
+
+        self.apply_extra(projected, extra)
+    }
+
+    /// Apply `term` to the arguments a recursor is given beyond those it
+    /// takes, when the motive is a function type.
+    fn apply_extra(&mut self, term: Term, extra: Vec<core::Term>) -> Term {
+        if extra.is_empty() {
+            term
+        } else {
+            let extra = extra.into_iter().map(|arg| self.lower_term(arg)).collect();
+            Term::Call(Box::new(term), extra)
+        }
+    }
+
+    /// The enumeration `head` is the recursor of, if it is applied to at
+    /// least `args` arguments.
+    fn enumeration(&self, head: &core::Term, args: usize) -> Option<&'g Enumeration> {
+        let enumerations = self.enumerations;
+
+        match head {
+            &core::Term::Var { ref name } => match enumerations.get(name) {
+                Some(enumeration) if args >= enumeration.arity() => Some(enumeration),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Lower an application of the recursor of an enumeration to a switch on
+    /// the tag of the value, the minor premises take no arguments.
+    fn lower_switch(&mut self, enumeration: &Enumeration, mut args: Vec<core::Term>) -> Term {
+        let extra = args.split_off(enumeration.arity());
+        let major = self.lower_term(args.pop().unwrap());
+
+        let minors = enumeration.parameters + 1;
+        let arms: Vec<_> =
+            args.into_iter()
+                .skip(minors)
+                .take(enumeration.constructors)
+                .collect();
+
+        let arms = arms.into_iter().map(|arm| self.lower_term(arm)).collect();
+        let switch = Term::Switch(Box::new(major), arms);
+
+        self.apply_extra(switch, extra)
     }
 
     fn lower_term(&mut self, term: core::Term) -> Term {
@@ -251,28 +1234,53 @@ impl<'tcx> ErasureCx<'tcx> {
             lam @ core::Term::Lambda { .. } => {
                 let mut final_body = lam;
                 let mut names = vec![];
+                let scope = self.binders.len();
                 while let core::Term::Lambda { binder, body, .. } = final_body {
-                    println!("binder: {} {}",
-                    binder.name, binder.ty);
-                    names.push(binder.name.clone());
+                    if !binder.is_irrelevant() {
+                        names.push(binder.name.clone());
+                    }
+                    self.binders.push(binder);
                     final_body = *body;
                 }
-                Term::Lambda(names, Box::new(self.lower_term(final_body)))
+                let body = self.lower_term(final_body);
+                self.binders.truncate(scope);
+                Term::Lambda(names, Box::new(body))
             }
             app @ core::Term::App { .. } => {
                 let (head, args) = app.uncurry();
-                println!("head: {}", head);
-                let lhead = self.lower_term(head);
-                for arg in &args {
-                    println!("args: {}", arg);
+
+                if let Some(projection) = self.projection(&head, args.len()) {
+                    return self.lower_projection(projection, args);
                 }
-            Term::Call(Rc::new(lhead),
+
+                if let Some(enumeration) = self.enumeration(&head, args.len()) {
+                    return self.lower_switch(enumeration, args);
+                }
+
+                let args = self.relevant_args(&head, args);
+                let lhead = self.lower_term(head);
+            Term::Call(Box::new(lhead),
                        args.into_iter()
                            .map(|arg| self.lower_term(arg))
                            .collect())
             }
+            // We keep the sharing a `let` expresses by binding the value
+            // with an immediately applied closure.
+            core::Term::Let { binder, value, body, .. } => {
+                let value = self.lower_term(*value);
+                let name = binder.name.clone();
+                self.binders.push(binder);
+                let body = self.lower_term(*body);
+                self.binders.pop();
+                Term::Call(Box::new(Term::Lambda(vec![name], Box::new(body))),
+                           vec![value])
+            }
+            // The type checker only lets erased variables appear where they
+            // are erased, so reaching one here is a bug.
+            core::Term::Var { name: core::Name::DeBruijn { index, ref repr, .. } }
+                if self.bound(index).map(|b| b.is_irrelevant()).unwrap_or(false) =>
+                panic!("the erased variable `{}` was used at runtime", repr),
             core::Term::Var { name } => {
-                println!("name: {}", name);
                 match name {
                     core::Name::Qual { .. } => {
                         Term::Var(name)
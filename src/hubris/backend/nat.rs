@@ -0,0 +1,138 @@
+//! Compiling the standard library's `Nat` to the runtime's numbers.
+//!
+//! `Nat` is declared with the unary constructors `Z` and `S`, compiled as
+//! written every arithmetic operation would take time linear in the numbers
+//! involved. Its values are instead the runtime's `rt::nat::Nat`, `Z` and
+//! `S` are bound to `zero` and `succ`, the recursor switches on whether a
+//! number is zero, and the arithmetic defined in `lib/Nat.hbr` is replaced
+//! by the runtime's.
+//!
+//! The standard library has no `Int` (nor a `decEq` on `Nat`), so there is
+//! nothing to compile for it here, a runtime integer is out of scope until
+//! `lib/` declares one.
+
+use core;
+use typeck::TyCtxt;
+
+use super::{location, Definition, Term};
+
+/// The definitions in `lib/Nat.hbr` the runtime implements, each with the
+/// function implementing it and the number of arguments it takes.
+const FUNCTIONS: &'static [(&'static str, &'static str, usize)] = &[
+    ("pred", "rt::nat::pred", 1),
+    ("add", "rt::nat::add", 2),
+    ("mul", "rt::nat::mul", 2),
+];
+
+/// The global `name` of the standard library, which like every global is
+/// declared unqualified.
+pub fn global(name: &str) -> core::Name {
+    core::Name::from_str(name)
+}
+
+/// Whether `data` is the standard library's `Nat`.
+pub fn is_nat(ty_cx: &TyCtxt, data: &core::Data) -> bool {
+    data.name == global("Nat") && ty_cx.is_prelude("Nat")
+}
+
+/// The definitions of `Z`, `S` and the recursor of `Nat`, in terms of the
+/// runtime's numbers.
+pub fn lower(ty_cx: &TyCtxt, data: &core::Data) -> Vec<Definition> {
+    let native = |symbol, args| Term::Call(Box::new(Term::Native(symbol)), args);
+    let local = |repr: &str| ty_cx.local_with_repr(repr.to_string(), core::Term::Type);
+    let var = |name: &core::Name| Term::Var(name.clone());
+
+    let zero = global("Z");
+    let succ = global("S");
+    let rec = data.name.in_scope("rec".to_string()).unwrap();
+
+    let n = local("n");
+    let succ_body = Term::Lambda(vec![n.clone()],
+                                 Box::new(native("rt::nat::succ", vec![var(&n)])));
+
+    // `rec C z s n` is `z` when `n` is zero, and otherwise `s m (rec C z s m)`
+    // where `m` is the number before `n`.
+    let (motive, z, s, m) = (local("C"), local("z"), local("s"), local("m"));
+
+    let step = Term::Call(Box::new(var(&s)), vec![
+        var(&m),
+        Term::Call(Box::new(var(&rec)), vec![var(&motive), var(&z), var(&s), var(&m)]),
+    ]);
+
+    let arms = vec![
+        var(&z),
+        Term::Call(Box::new(Term::Lambda(vec![m], Box::new(step))),
+                   vec![native("rt::nat::pred", vec![var(&n)])]),
+    ];
+
+    let rec_body = Term::Lambda(
+        vec![motive, z, s, n.clone()],
+        Box::new(Term::Switch(Box::new(native("rt::nat::tag", vec![var(&n)])), arms)));
+
+    vec![
+        Definition {
+            name: zero.clone(),
+            body: native("rt::nat::zero", vec![]),
+            location: location(ty_cx, &zero),
+        },
+        Definition {
+            name: succ.clone(),
+            body: succ_body,
+            location: location(ty_cx, &succ),
+        },
+        Definition {
+            name: rec.clone(),
+            body: rec_body,
+            location: location(ty_cx, &data.name),
+        },
+    ]
+}
+
+/// The runtime's implementation of `def`, if it is one of the definitions of
+/// `lib/Nat.hbr` the runtime provides.
+pub fn replacement(ty_cx: &TyCtxt, def: &core::Definition) -> Option<Definition> {
+    replacement_from(ty_cx, def, FUNCTIONS)
+}
+
+/// The implementation of `def` in `functions`, which lists definitions of
+/// `lib/Nat.hbr` with the native function implementing each and the number
+/// of arguments it takes.
+pub fn replacement_from(ty_cx: &TyCtxt,
+                        def: &core::Definition,
+                        functions: &[(&'static str, &'static str, usize)]) -> Option<Definition> {
+    let &(name, symbol, arity) = match functions.iter().find(|&&(f, _, _)| def.name == global(f)) {
+        Some(function) => function,
+        None => return None,
+    };
+
+    // Only a definition computing like the standard library's is replaced,
+    // a program may declare a function of the same name.
+    if !ty_cx.is_prelude(name) {
+        return None;
+    }
+
+    let nat = global("Nat").to_term();
+    let mut ty = &def.ty;
+    let mut params = vec![];
+
+    while let &core::Term::Forall { ref binder, ref term, .. } = ty {
+        if *binder.ty != nat {
+            return None;
+        }
+
+        params.push(ty_cx.local_with_repr(format!("x{}", params.len()), core::Term::Type));
+        ty = &**term;
+    }
+
+    if params.len() != arity || *ty != nat {
+        return None;
+    }
+
+    let args = params.iter().map(|p| Term::Var(p.clone())).collect();
+
+    Some(Definition {
+        name: def.name.clone(),
+        body: Term::Lambda(params, Box::new(Term::Call(Box::new(Term::Native(symbol)), args))),
+        location: location(ty_cx, &def.name),
+    })
+}
@@ -0,0 +1,764 @@
+//! Optimizations of the lowered program, run between erasure and rendering.
+//!
+//! The level given with `-O` picks the passes that run and how eagerly they
+//! transform the program:
+//!
+//! - `-O0` runs none of them, and is the default.
+//! - `-O1` inlines very small definitions, folds constants, uncurries calls
+//!   and removes dead code.
+//! - `-O2` also specializes functions to the globals passed to them, and
+//!   inlines larger definitions.
+//!
+//! The passes run in the order of `PASSES`. `--emit=ir-after=<pass>` writes
+//! out the program as it is once the pass has run, which is the easiest way
+//! to see what a pass did to a program it miscompiles.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use core;
+use super::{Definition, Term};
+use session::Session;
+use timing::{Instant, Phase};
+
+/// A pass of the optimizer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pass {
+    /// Replace calls of small definitions with their bodies.
+    Inline,
+    /// Copy functions for the globals passed to them as arguments.
+    Specialize,
+    /// Substitute the constants bound by `let`, simplify arithmetic with
+    /// zero and one, and resolve projections and switches on values built
+    /// in place.
+    ConstantFold,
+    /// Pass every argument of a call at once, instead of calling the result
+    /// of a call.
+    Uncurry,
+    /// Remove the definitions the program never refers to.
+    DeadCode,
+}
+
+/// Every pass, in the order they run in.
+pub const PASSES: &'static [Pass] = &[
+    Pass::Inline,
+    Pass::Specialize,
+    Pass::ConstantFold,
+    Pass::Uncurry,
+    Pass::DeadCode,
+];
+
+impl Pass {
+    pub fn from_str(s: &str) -> Option<Pass> {
+        PASSES.iter().cloned().find(|pass| pass.name() == s)
+    }
+
+    /// The name used to refer to this pass on the command line.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Pass::Inline => "inline",
+            Pass::Specialize => "specialize",
+            Pass::ConstantFold => "const-fold",
+            Pass::Uncurry => "uncurry",
+            Pass::DeadCode => "dce",
+        }
+    }
+}
+
+/// How much the backend optimizes, selected with `-O`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Optimization {
+    pub level: usize,
+    /// The size of the largest body, counted in terms, inlined at a call.
+    pub inline_threshold: usize,
+    /// The size of the largest body copied when specializing a function.
+    pub specialize_threshold: usize,
+}
+
+impl Optimization {
+    /// The settings for `-O<level>`, or `None` if there is no such level.
+    pub fn from_level(level: usize) -> Option<Optimization> {
+        let (inline_threshold, specialize_threshold) = match level {
+            0 => (0, 0),
+            1 => (8, 0),
+            2 => (40, 120),
+            _ => return None,
+        };
+
+        Some(Optimization {
+            level: level,
+            inline_threshold: inline_threshold,
+            specialize_threshold: specialize_threshold,
+        })
+    }
+
+    /// Whether `pass` runs at this level.
+    pub fn runs(&self, pass: Pass) -> bool {
+        match pass {
+            Pass::Specialize => self.level >= 2,
+            _ => self.level >= 1,
+        }
+    }
+}
+
+impl Default for Optimization {
+    fn default() -> Optimization {
+        Optimization::from_level(0).unwrap()
+    }
+}
+
+/// Run the passes the session's optimization level asks for over
+/// `definitions`, stopping once `until` has run. Dead code elimination
+/// keeps the definitions reachable from `roots`, whose number of arguments
+/// is never changed as they are called from outside the program.
+pub fn optimize(session: &Session,
+                mut definitions: Vec<Definition>,
+                roots: &BTreeSet<core::Name>,
+                until: Option<Pass>) -> Vec<Definition> {
+    let optimization = session.options().optimization;
+
+    for &pass in PASSES {
+        if optimization.runs(pass) {
+            let start = Instant::now();
+
+            definitions = match pass {
+                Pass::Inline => inline(definitions, optimization.inline_threshold),
+                Pass::Specialize => specialize(definitions, optimization.specialize_threshold),
+                Pass::ConstantFold =>
+                    definitions.into_iter()
+                               .map(|def| Definition { body: fold(def.body), ..def })
+                               .collect(),
+                Pass::Uncurry => uncurry(definitions, roots),
+                Pass::DeadCode => dead_code(definitions, roots),
+            };
+
+            session.record_time(Phase::Optimize, Some(pass.name().to_string()), start);
+        }
+
+        if until == Some(pass) {
+            break;
+        }
+    }
+
+    definitions
+}
+
+/// Rebuild `term` with `f` applied to each of its immediate subterms.
+fn map_children<F>(term: Term, mut f: F) -> Term
+    where F: FnMut(Term) -> Term
+{
+    match term {
+        Term::Switch(scrut, arms) => {
+            let scrut = f(*scrut);
+            Term::Switch(Box::new(scrut), arms.into_iter().map(&mut f).collect())
+        }
+        Term::Call(head, args) => {
+            let head = f(*head);
+            Term::Call(Box::new(head), args.into_iter().map(&mut f).collect())
+        }
+        Term::Lambda(names, body) => Term::Lambda(names, Box::new(f(*body))),
+        Term::Construct(tag, fields) => Term::Construct(tag, fields.into_iter().map(f).collect()),
+        Term::Record(name, fields) => Term::Record(name, fields.into_iter().map(f).collect()),
+        Term::Project(name, index, record) => Term::Project(name, index, Box::new(f(*record))),
+        Term::Foreign(path, args) => Term::Foreign(path, args.into_iter().map(f).collect()),
+        t @ Term::Local(..) |
+        t @ Term::Tag(_) |
+        t @ Term::Var(_) |
+        t @ Term::Native(_) |
+        t @ Term::Panic(_) => t,
+    }
+}
+
+/// The immediate subterms of `term`.
+fn children(term: &Term) -> Vec<&Term> {
+    match term {
+        &Term::Switch(ref scrut, ref arms) => {
+            let mut children = vec![&**scrut];
+            children.extend(arms.iter());
+            children
+        }
+        &Term::Call(ref head, ref args) => {
+            let mut children = vec![&**head];
+            children.extend(args.iter());
+            children
+        }
+        &Term::Lambda(_, ref body) => vec![&**body],
+        &Term::Construct(_, ref terms) |
+        &Term::Record(_, ref terms) |
+        &Term::Foreign(_, ref terms) => terms.iter().collect(),
+        &Term::Project(_, _, ref record) => vec![&**record],
+        &Term::Local(..) |
+        &Term::Tag(_) |
+        &Term::Var(_) |
+        &Term::Native(_) |
+        &Term::Panic(_) => vec![],
+    }
+}
+
+/// The number of terms in `term`.
+fn size(term: &Term) -> usize {
+    1 + children(term).into_iter().map(size).fold(0, |a, b| a + b)
+}
+
+/// Whether `term` refers to the global `name`.
+fn mentions(term: &Term, name: &core::Name) -> bool {
+    match term {
+        &Term::Var(ref n) => n == name,
+        t => children(t).into_iter().any(|c| mentions(c, name)),
+    }
+}
+
+/// Collect the globals `term` refers to.
+fn globals(term: &Term, names: &mut BTreeSet<core::Name>) {
+    match term {
+        &Term::Var(ref n) if n.is_qual() => { names.insert(n.clone()); }
+        t => for c in children(t) { globals(c, names) },
+    }
+}
+
+/// Collect the locals bound by the lambdas in `term`.
+fn binders(term: &Term, names: &mut BTreeSet<core::Name>) {
+    if let &Term::Lambda(ref ns, _) = term {
+        names.extend(ns.iter().cloned());
+    }
+
+    for c in children(term) {
+        binders(c, names);
+    }
+}
+
+/// How many times `term` uses the local `name`, a use under a lambda counts
+/// as several as the lambda may be called more than once.
+fn occurrences(term: &Term, name: &core::Name) -> usize {
+    match term {
+        &Term::Var(ref n) if n == name => 1,
+        &Term::Lambda(ref ns, _) if ns.contains(name) => 0,
+        &Term::Lambda(_, ref body) => 2 * occurrences(body, name),
+        t => children(t).into_iter().map(|c| occurrences(c, name)).fold(0, |a, b| a + b),
+    }
+}
+
+/// Replace the locals in `term` with the terms `map` gives them. The caller
+/// makes sure that no lambda in `term` captures a local of those terms.
+fn substitute(term: Term, map: &BTreeMap<core::Name, Term>) -> Term {
+    match term {
+        Term::Var(name) => match map.get(&name) {
+            Some(t) => t.clone(),
+            None => Term::Var(name),
+        },
+        Term::Lambda(names, body) => {
+            if names.iter().any(|n| map.contains_key(n)) {
+                let mut inner = map.clone();
+                for n in &names {
+                    inner.remove(n);
+                }
+                Term::Lambda(names, Box::new(substitute(*body, &inner)))
+            } else {
+                Term::Lambda(names, Box::new(substitute(*body, map)))
+            }
+        }
+        t => map_children(t, |c| substitute(c, map)),
+    }
+}
+
+/// Whether `term` is a value that is no more expensive to recompute than to
+/// share, such as a global, a constructor without fields, or a constant of
+/// the runtime.
+fn is_constant(term: &Term) -> bool {
+    match term {
+        &Term::Var(ref n) => n.is_qual(),
+        &Term::Native(_) |
+        &Term::Tag(_) => true,
+        &Term::Construct(_, ref fields) |
+        &Term::Record(_, ref fields) => fields.iter().all(is_constant),
+        &Term::Call(ref head, ref args) => head.is_native() && args.is_empty(),
+        _ => false,
+    }
+}
+
+/// The parameters and body of each definition whose body is a function.
+fn functions(definitions: &[Definition]) -> BTreeMap<core::Name, (Vec<core::Name>, Term)> {
+    let mut functions = BTreeMap::new();
+
+    for def in definitions {
+        if let Term::Lambda(ref params, ref body) = def.body {
+            functions.insert(def.name.clone(), (params.clone(), (**body).clone()));
+        }
+    }
+
+    functions
+}
+
+/// `body` with `params` bound to `args`. An argument is substituted when
+/// that does not duplicate work, the others stay bound by a `let`. `None`
+/// if a lambda in `body` would capture a local of the arguments.
+fn instantiate(params: &[core::Name], body: &Term, args: Vec<Term>) -> Option<Term> {
+    let mut bound = BTreeSet::new();
+    binders(body, &mut bound);
+    bound.extend(params.iter().cloned());
+
+    let mut free = BTreeSet::new();
+    for arg in &args {
+        arg.free_locals(&mut vec![], &mut free);
+    }
+
+    if !bound.is_disjoint(&free) {
+        return None;
+    }
+
+    let mut map = BTreeMap::new();
+    let mut kept_params = vec![];
+    let mut kept_args = vec![];
+
+    for (param, arg) in params.iter().zip(args.into_iter()) {
+        let is_variable = match arg { Term::Var(_) => true, _ => false };
+
+        if is_variable || is_constant(&arg) || occurrences(body, param) == 1 {
+            map.insert(param.clone(), arg);
+        } else {
+            kept_params.push(param.clone());
+            kept_args.push(arg);
+        }
+    }
+
+    let body = substitute(body.clone(), &map);
+
+    if kept_params.is_empty() {
+        Some(body)
+    } else {
+        Some(Term::Call(Box::new(Term::Lambda(kept_params, Box::new(body))), kept_args))
+    }
+}
+
+/// Inline each call of a function whose body is no larger than `threshold`
+/// and does not call itself. The bodies inlined are those from before the
+/// pass, so mutually recursive functions are unfolded only once.
+fn inline(definitions: Vec<Definition>, threshold: usize) -> Vec<Definition> {
+    let candidates: BTreeMap<_, _> =
+        functions(&definitions).into_iter()
+                               .filter(|&(ref name, (_, ref body))| {
+                                   size(body) <= threshold && !mentions(body, name)
+                               })
+                               .collect();
+
+    definitions.into_iter()
+               .map(|def| Definition { body: inline_term(&candidates, def.body), ..def })
+               .collect()
+}
+
+fn inline_term(candidates: &BTreeMap<core::Name, (Vec<core::Name>, Term)>, term: Term) -> Term {
+    match map_children(term, |c| inline_term(candidates, c)) {
+        Term::Call(head, args) => {
+            if let Term::Var(ref name) = *head {
+                if let Some(&(ref params, ref body)) = candidates.get(name) {
+                    if params.len() == args.len() {
+                        if let Some(inlined) = instantiate(params, body, args.clone()) {
+                            return inlined;
+                        }
+                    }
+                }
+            }
+
+            Term::Call(head, args)
+        }
+        t => t,
+    }
+}
+
+/// The functions specialized so far, and the copies that still need
+/// specializing themselves.
+struct Specializer {
+    /// The functions small enough to copy.
+    functions: BTreeMap<core::Name, (Vec<core::Name>, Term)>,
+    /// The copy of a function for globals passed at some of its positions.
+    copies: BTreeMap<(core::Name, Vec<(usize, core::Name)>), core::Name>,
+    /// The copies whose bodies have not been processed, with the function
+    /// they were made from.
+    pending: Vec<(core::Name, core::Name, Term)>,
+}
+
+impl Specializer {
+    /// The name of the copy of `function` with the globals `fixed` passed
+    /// at their positions, making one if there is none.
+    fn copy(&mut self, function: &core::Name, fixed: Vec<(usize, core::Name)>) -> core::Name {
+        let key = (function.clone(), fixed);
+
+        if let Some(name) = self.copies.get(&key) {
+            return name.clone();
+        }
+
+        // The mangler escapes `#`, so this can not clash with a name
+        // written in the source.
+        let name = match function {
+            &core::Name::Qual { ref span, ref components } => {
+                let mut components = components.clone();
+                components.push(format!("spec#{}", self.copies.len()));
+                core::Name::Qual { span: span.clone(), components: components }
+            }
+            n => panic!("specializing the local {}", n),
+        };
+
+        let body = {
+            let (ref params, ref body) = self.functions[function];
+            let map: BTreeMap<_, _> =
+                key.1.iter()
+                     .map(|&(i, ref global)| (params[i].clone(), Term::Var(global.clone())))
+                     .collect();
+
+            let rest: Vec<_> =
+                params.iter()
+                      .enumerate()
+                      .filter(|&(i, _)| !key.1.iter().any(|&(j, _)| i == j))
+                      .map(|(_, p)| p.clone())
+                      .collect();
+
+            let body = substitute(body.clone(), &map);
+
+            if rest.is_empty() { body } else { Term::Lambda(rest, Box::new(body)) }
+        };
+
+        self.copies.insert(key, name.clone());
+        self.pending.push((function.clone(), name.clone(), body));
+
+        name
+    }
+
+    /// Redirect the calls in `term` passing globals to a function to the
+    /// function's copy for those globals.
+    fn rewrite(&mut self, term: Term) -> Term {
+        match map_children(term, |c| self.rewrite(c)) {
+            Term::Call(head, args) => {
+                let function = match *head {
+                    Term::Var(ref name) => match self.functions.get(name) {
+                        Some(&(ref params, _)) if params.len() == args.len() => Some(name.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+
+                let function = match function {
+                    Some(function) => function,
+                    None => return Term::Call(head, args),
+                };
+
+                let fixed: Vec<_> =
+                    args.iter()
+                        .enumerate()
+                        .filter_map(|(i, arg)| match arg {
+                            &Term::Var(ref n) if n.is_qual() => Some((i, n.clone())),
+                            _ => None,
+                        })
+                        .collect();
+
+                if fixed.is_empty() {
+                    return Term::Call(head, args);
+                }
+
+                let rest =
+                    args.into_iter()
+                        .enumerate()
+                        .filter(|&(i, _)| !fixed.iter().any(|&(j, _)| i == j))
+                        .map(|(_, arg)| arg)
+                        .collect();
+
+                let copy = self.copy(&function, fixed);
+                Term::Call(Box::new(Term::Var(copy)), rest)
+            }
+            t => t,
+        }
+    }
+}
+
+/// Copy each function no larger than `threshold` for the globals passed to
+/// it, so a function such as `map` calls the function it was given directly.
+/// The copies are placed after the function they were made from.
+fn specialize(definitions: Vec<Definition>, threshold: usize) -> Vec<Definition> {
+    let mut specializer = Specializer {
+        functions: functions(&definitions).into_iter()
+                                          .filter(|&(_, (_, ref body))| size(body) <= threshold)
+                                          .collect(),
+        copies: BTreeMap::new(),
+        pending: vec![],
+    };
+
+    let mut definitions: Vec<_> =
+        definitions.into_iter()
+                   .map(|def| Definition { body: specializer.rewrite(def.body), ..def })
+                   .collect();
+
+    let mut copies: BTreeMap<core::Name, Vec<Definition>> = BTreeMap::new();
+
+    while let Some((function, name, body)) = specializer.pending.pop() {
+        let body = specializer.rewrite(body);
+        let location = definitions.iter()
+                                  .find(|def| def.name == function)
+                                  .map(|def| def.location.clone())
+                                  .unwrap_or("<unknown>".to_string());
+
+        copies.entry(function).or_insert(vec![]).push(Definition {
+            name: name,
+            body: body,
+            location: location,
+        });
+    }
+
+    let mut specialized = vec![];
+
+    for def in definitions.drain(..) {
+        let copies = copies.remove(&def.name).unwrap_or(vec![]);
+        specialized.push(def);
+        specialized.extend(copies.into_iter().rev());
+    }
+
+    specialized
+}
+
+/// Whether `term` is a call of the runtime's `op` for some integer type,
+/// giving the module it is from.
+fn native_call<'a>(term: &'a Term, op: &str) -> Option<(&'a str, &'a [Term])> {
+    match term {
+        &Term::Call(ref head, ref args) => match **head {
+            Term::Native(symbol) => {
+                let mut parts = symbol.rsplitn(2, "::");
+                match (parts.next(), parts.next()) {
+                    (Some(name), Some(module)) if name == op => Some((module, &args[..])),
+                    _ => None,
+                }
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether `term` is the constant `op`, `zero` or `one`, of the runtime's
+/// integer `module`.
+fn is_native_constant(term: &Term, module: &str, op: &str) -> bool {
+    match native_call(term, op) {
+        Some((m, args)) => m == module && args.is_empty(),
+        None => false,
+    }
+}
+
+/// Substitute the constants bound by `let`, remove additions of zero and
+/// multiplications by one, project fields out of records built in place, and
+/// take the arm of a switch on a known tag.
+fn fold(term: Term) -> Term {
+    let term = map_children(term, fold);
+
+    let identity = {
+        let operands = |op, unit| native_call(&term, op).and_then(|(module, args)| {
+            if args.len() != 2 {
+                None
+            } else if is_native_constant(&args[1], module, unit) {
+                Some(args[0].clone())
+            } else if is_native_constant(&args[0], module, unit) && op != "sub_wrapping" {
+                Some(args[1].clone())
+            } else {
+                None
+            }
+        });
+
+        operands("add_wrapping", "zero")
+            .or_else(|| operands("sub_wrapping", "zero"))
+            .or_else(|| operands("mul_wrapping", "one"))
+    };
+
+    if let Some(operand) = identity {
+        return operand;
+    }
+
+    match term {
+        Term::Project(name, index, record) => match *record {
+            Term::Record(ref built, ref fields) if *built == name && index < fields.len() =>
+                fields[index].clone(),
+            record => Term::Project(name, index, Box::new(record)),
+        },
+        Term::Switch(scrut, mut arms) => match *scrut {
+            Term::Tag(tag) if tag < arms.len() => arms.swap_remove(tag),
+            scrut => Term::Switch(Box::new(scrut), arms),
+        },
+        Term::Call(head, args) => match *head {
+            Term::Lambda(params, body) => {
+                if params.len() != args.len() || !args.iter().any(is_constant) {
+                    return Term::Call(Box::new(Term::Lambda(params, body)), args);
+                }
+
+                let mut map = BTreeMap::new();
+                let mut kept_params = vec![];
+                let mut kept_args = vec![];
+
+                for (param, arg) in params.into_iter().zip(args.into_iter()) {
+                    if is_constant(&arg) {
+                        map.insert(param, arg);
+                    } else {
+                        kept_params.push(param);
+                        kept_args.push(arg);
+                    }
+                }
+
+                // Substituting may have made further constants.
+                let body = fold(substitute(*body, &map));
+
+                if kept_params.is_empty() {
+                    body
+                } else {
+                    Term::Call(Box::new(Term::Lambda(kept_params, Box::new(body))), kept_args)
+                }
+            }
+            head => Term::Call(Box::new(head), args),
+        },
+        t => t,
+    }
+}
+
+/// The function a chain of calls starts with, and the arguments given to
+/// it, from the innermost call out.
+fn spine(term: &Term) -> (&Term, Vec<&[Term]>) {
+    match term {
+        &Term::Call(ref head, ref args) => {
+            let (head, mut calls) = spine(head);
+            calls.push(&args[..]);
+            (head, calls)
+        }
+        t => (t, vec![]),
+    }
+}
+
+/// Rule out the functions in `arities` that `term` refers to other than as
+/// the start of a chain of calls passing at least that many arguments.
+fn saturated_uses(term: &Term,
+                  arities: &BTreeMap<core::Name, usize>,
+                  unsaturated: &mut BTreeSet<core::Name>) {
+    let (head, calls) = spine(term);
+
+    match head {
+        &Term::Var(ref name) => if let Some(&arity) = arities.get(name) {
+            if calls.iter().map(|args| args.len()).fold(0, |a, b| a + b) < arity {
+                unsaturated.insert(name.clone());
+            }
+        },
+        head => for c in children(head) {
+            saturated_uses(c, arities, unsaturated);
+        },
+    }
+
+    for args in calls {
+        for arg in args {
+            saturated_uses(arg, arities, unsaturated);
+        }
+    }
+}
+
+/// Join the nested lambdas of a definition whose every use passes all of
+/// their arguments, and pass the arguments of a chain of calls of a global
+/// at once when it takes that many. The roots keep their arguments.
+fn uncurry(definitions: Vec<Definition>, roots: &BTreeSet<core::Name>) -> Vec<Definition> {
+    let mut nested = BTreeMap::new();
+
+    for def in &definitions {
+        let mut arity = 0;
+        let mut body = &def.body;
+        let mut lambdas = 0;
+
+        while let &Term::Lambda(ref params, ref inner) = body {
+            arity += params.len();
+            lambdas += 1;
+            body = &**inner;
+        }
+
+        if lambdas > 1 && !roots.contains(&def.name) {
+            nested.insert(def.name.clone(), arity);
+        }
+    }
+
+    let mut unsaturated = BTreeSet::new();
+    for def in &definitions {
+        saturated_uses(&def.body, &nested, &mut unsaturated);
+    }
+
+    let definitions: Vec<_> = definitions.into_iter().map(|def| {
+        if !nested.contains_key(&def.name) || unsaturated.contains(&def.name) {
+            return def;
+        }
+
+        let mut params = vec![];
+        let mut body = def.body;
+
+        loop {
+            match body {
+                Term::Lambda(ps, inner) => {
+                    params.extend(ps.into_iter());
+                    body = *inner;
+                }
+                b => {
+                    body = b;
+                    break;
+                }
+            }
+        }
+
+        Definition { body: Term::Lambda(params, Box::new(body)), ..def }
+    }).collect();
+
+    let arities: BTreeMap<_, _> =
+        functions(&definitions).into_iter()
+                               .map(|(name, (params, _))| (name, params.len()))
+                               .collect();
+
+    definitions.into_iter()
+               .map(|def| Definition { body: flatten_calls(&arities, def.body), ..def })
+               .collect()
+}
+
+fn flatten_calls(arities: &BTreeMap<core::Name, usize>, term: Term) -> Term {
+    match map_children(term, |c| flatten_calls(arities, c)) {
+        Term::Call(head, args) => match *head {
+            Term::Call(inner, mut first) => {
+                let arity = match *inner {
+                    Term::Var(ref name) => arities.get(name).cloned(),
+                    _ => None,
+                };
+
+                match arity {
+                    Some(arity) if first.len() + args.len() <= arity => {
+                        first.extend(args.into_iter());
+                        Term::Call(inner, first)
+                    }
+                    _ => Term::Call(Box::new(Term::Call(inner, first)), args),
+                }
+            }
+            head => Term::Call(Box::new(head), args),
+        },
+        t => t,
+    }
+}
+
+/// Keep only the definitions reachable from `roots`, in their order.
+fn dead_code(definitions: Vec<Definition>, roots: &BTreeSet<core::Name>) -> Vec<Definition> {
+    let mut live = BTreeSet::new();
+
+    {
+        let bodies: BTreeMap<_, _> =
+            definitions.iter()
+                       .map(|def| (&def.name, &def.body))
+                       .collect();
+
+        let mut pending: Vec<_> = roots.iter().cloned().collect();
+
+        while let Some(name) = pending.pop() {
+            if !live.insert(name.clone()) {
+                continue;
+            }
+
+            if let Some(body) = bodies.get(&name) {
+                let mut referred = BTreeSet::new();
+                globals(body, &mut referred);
+                pending.extend(referred.into_iter().filter(|n| !live.contains(n)));
+            }
+        }
+    }
+
+    definitions.into_iter()
+               .filter(|def| live.contains(&def.name))
+               .collect()
+}
@@ -0,0 +1,74 @@
+//! The functions the runtime provides for externs.
+//!
+//! The kernel only sees externs as axioms, an extern declared with a symbol
+//! is bound to the runtime function of that name. Before generating code we
+//! check the extern's calling convention against the implementation, so a
+//! typo or a wrong number of arguments is reported here instead of by rustc.
+
+use super::core;
+
+pub struct Primitive {
+    /// The path to the implementation in the generated crate.
+    pub symbol: &'static str,
+    /// The number of arguments the implementation takes.
+    pub arity: usize,
+}
+
+macro_rules! uint_primitives {
+    ($module:expr) => {[
+        Primitive { symbol: concat!("rt::uint::", $module, "::zero"), arity: 0 },
+        Primitive { symbol: concat!("rt::uint::", $module, "::one"), arity: 0 },
+        Primitive { symbol: concat!("rt::uint::", $module, "::add_wrapping"), arity: 2 },
+        Primitive { symbol: concat!("rt::uint::", $module, "::sub_wrapping"), arity: 2 },
+        Primitive { symbol: concat!("rt::uint::", $module, "::mul_wrapping"), arity: 2 },
+        Primitive { symbol: concat!("rt::uint::", $module, "::add_checked"), arity: 2 },
+        Primitive { symbol: concat!("rt::uint::", $module, "::sub_checked"), arity: 2 },
+        Primitive { symbol: concat!("rt::uint::", $module, "::mul_checked"), arity: 2 },
+        Primitive { symbol: concat!("rt::uint::", $module, "::div_checked"), arity: 2 },
+    ]}
+}
+
+const UINT32: &'static [Primitive] = &uint_primitives!("uint32");
+const UINT64: &'static [Primitive] = &uint_primitives!("uint64");
+
+/// The representation of the standard library's `Nat`, see `rt::nat`.
+const NAT: &'static [Primitive] = &[
+    Primitive { symbol: "rt::nat::zero", arity: 0 },
+    Primitive { symbol: "rt::nat::succ", arity: 1 },
+    Primitive { symbol: "rt::nat::pred", arity: 1 },
+    Primitive { symbol: "rt::nat::tag", arity: 1 },
+    Primitive { symbol: "rt::nat::add", arity: 2 },
+    Primitive { symbol: "rt::nat::sub", arity: 2 },
+    Primitive { symbol: "rt::nat::mul", arity: 2 },
+];
+
+const PRIMITIVES: &'static [&'static [Primitive]] = &[UINT32, UINT64, NAT];
+
+/// The runtime function called `symbol`, if there is one.
+pub fn lookup(symbol: &str) -> Option<&'static Primitive> {
+    PRIMITIVES.iter()
+              .flat_map(|ps| ps.iter())
+              .find(|p| p.symbol == symbol)
+}
+
+/// Find the implementation of the extern `name`, checking that it takes
+/// the arguments the extern's convention passes to it.
+pub fn resolve(name: &core::Name, convention: &core::Convention)
+               -> Result<&'static Primitive, String> {
+    let prim = match lookup(&convention.symbol) {
+        None => return Err(format!("the runtime does not provide `{}`, the symbol for `{}`",
+                                   convention.symbol, name)),
+        Some(prim) => prim,
+    };
+
+    if prim.arity != convention.runtime_arity() {
+        return Err(format!("`{}` passes {} argument{} to `{}`, which takes {}",
+                           name,
+                           convention.runtime_arity(),
+                           if convention.runtime_arity() == 1 { "" } else { "s" },
+                           prim.symbol,
+                           prim.arity));
+    }
+
+    Ok(prim)
+}
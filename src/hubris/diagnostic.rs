@@ -0,0 +1,96 @@
+//! A builder for diagnostics made up of a message, a primary span, any number
+//! of secondary labeled spans, and trailing notes and help messages.
+//!
+//! ```ignore
+//! Diagnostic::error(format!("expected type `{}` found `{}`", ty, infer_ty))
+//!     .span(term_span)
+//!     .label(annotation_span, "expected because of this annotation")
+//!     .help("try adding a type ascription")
+//!     .emit(session)
+//! ```
+
+use super::ast::Span;
+use super::session::Session;
+
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+    InternalError,
+}
+
+impl Display for Level {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        let name = match *self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::InternalError => "internal compiler error",
+        };
+
+        write!(formatter, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    /// The location the diagnostic is about, if any.
+    pub primary: Option<Span>,
+    /// Additional locations that explain the diagnostic.
+    pub labels: Vec<(Span, String)>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(level: Level, message: String) -> Diagnostic {
+        Diagnostic {
+            level: level,
+            message: message,
+            primary: None,
+            labels: vec![],
+            notes: vec![],
+            help: vec![],
+        }
+    }
+
+    pub fn error(message: String) -> Diagnostic {
+        Diagnostic::new(Level::Error, message)
+    }
+
+    pub fn warning(message: String) -> Diagnostic {
+        Diagnostic::new(Level::Warning, message)
+    }
+
+    pub fn span(mut self, span: Span) -> Diagnostic {
+        self.primary = Some(span);
+        self
+    }
+
+    /// Attach a secondary span, labels on dummy spans are dropped since
+    /// there is nothing to point at.
+    pub fn label<S: Into<String>>(mut self, span: Span, label: S) -> Diagnostic {
+        if span != Span::dummy() {
+            self.labels.push((span, label.into()));
+        }
+        self
+    }
+
+    pub fn note<S: Into<String>>(mut self, note: S) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn help<S: Into<String>>(mut self, help: S) -> Diagnostic {
+        self.help.push(help.into());
+        self
+    }
+
+    pub fn emit(self, session: &Session) -> io::Result<()> {
+        session.emit(self)
+    }
+}
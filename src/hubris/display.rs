@@ -0,0 +1,211 @@
+//! Rendering of terms in diagnostics.
+//!
+//! Fully elaborated dependent types can be very large, so by default terms
+//! in error messages are cut off below a maximum depth, implicit arguments
+//! are hidden, and the result is truncated to a maximum width. Passing
+//! `--verbose-errors` disables all of this. What remains is laid out by
+//! `printer`, broken across lines that would not fit in the line width.
+
+use core::{Binder, BindingMode, Name, Term};
+use printer::Printer;
+
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, Debug)]
+pub struct DisplayOptions {
+    /// Subterms nested deeper than this are replaced by `...`.
+    pub max_depth: Option<usize>,
+    /// Rendered terms longer than this are truncated.
+    pub max_width: Option<usize>,
+    /// The width terms are laid out in, a term that does not fit on one
+    /// line is broken across several. Terms are kept on one line if `None`.
+    pub line_width: Option<usize>,
+    /// Whether to hide the implicit arguments of known globals.
+    pub hide_implicits: bool,
+}
+
+/// The width terms are laid out in by default.
+pub const LINE_WIDTH: usize = 80;
+
+impl Default for DisplayOptions {
+    fn default() -> DisplayOptions {
+        DisplayOptions {
+            max_depth: Some(8),
+            max_width: Some(240),
+            line_width: Some(LINE_WIDTH),
+            hide_implicits: true,
+        }
+    }
+}
+
+impl DisplayOptions {
+    /// Options that print every term in full.
+    pub fn verbose() -> DisplayOptions {
+        DisplayOptions {
+            max_depth: None,
+            max_width: None,
+            line_width: Some(LINE_WIDTH),
+            hide_implicits: false,
+        }
+    }
+}
+
+/// The number of leading implicit and instance parameters in the type `ty`.
+pub fn implicit_arity(ty: &Term) -> usize {
+    let mut arity = 0;
+    let mut cursor = ty;
+
+    while let &Term::Forall { ref binder, ref term, .. } = cursor {
+        if binder.mode == BindingMode::Explicit {
+            break;
+        }
+
+        arity += 1;
+        cursor = term;
+    }
+
+    arity
+}
+
+/// The numbers of the metavariables in `term`, in the order they appear.
+pub fn metas(term: &Term) -> Vec<usize> {
+    fn collect(term: &Term, numbers: &mut Vec<usize>) {
+        use core::Term::*;
+
+        match term {
+            &Var { name: Name::Meta { number, .. } } => {
+                if !numbers.contains(&number) {
+                    numbers.push(number);
+                }
+            }
+            &App { ref fun, ref arg, .. } => {
+                collect(fun, numbers);
+                collect(arg, numbers);
+            }
+            &Forall { ref binder, term: ref body, .. } |
+            &Lambda { ref binder, ref body, .. } |
+            &Fix { ref binder, ref body, .. } => {
+                collect(&binder.ty, numbers);
+                collect(body, numbers);
+            }
+            &Let { ref binder, ref value, ref body, .. } => {
+                collect(&binder.ty, numbers);
+                collect(value, numbers);
+                collect(body, numbers);
+            }
+            &Var { .. } | &Type => {}
+        }
+    }
+
+    let mut numbers = vec![];
+    collect(term, &mut numbers);
+    numbers
+}
+
+/// The name standing for a subterm that was cut off.
+fn elision_name() -> Name {
+    Name::from_str("...")
+}
+
+fn elision() -> Term {
+    elision_name().to_term()
+}
+
+struct Elide<'a> {
+    options: DisplayOptions,
+    implicits: &'a HashMap<Name, usize>,
+}
+
+impl<'a> Elide<'a> {
+    fn too_deep(&self, depth: usize) -> bool {
+        match self.options.max_depth {
+            None => false,
+            Some(max) => depth > max,
+        }
+    }
+
+    fn binder(&self, binder: &Binder, depth: usize) -> Binder {
+        let mut binder = binder.clone();
+        binder.ty = Box::new(self.term(&binder.ty, depth + 1));
+        binder
+    }
+
+    fn term(&self, term: &Term, depth: usize) -> Term {
+        use core::Term::*;
+
+        if self.too_deep(depth) {
+            return elision();
+        }
+
+        match term {
+            &App { .. } => {
+                let (head, args) = term.uncurry();
+
+                // The elaborator applies a metavariable to the locals in
+                // scope, which only clutter the output.
+                let hidden = match &head {
+                    &Var { name: Name::Meta { .. } } if self.options.hide_implicits => args.len(),
+                    &Var { ref name } if self.options.hide_implicits =>
+                        self.implicits.get(name).cloned().unwrap_or(0),
+                    _ => 0,
+                };
+
+                let args = args.iter()
+                               .skip(hidden)
+                               .map(|a| self.term(a, depth + 1))
+                               .collect();
+
+                Term::apply_all(self.term(&head, depth), args)
+            }
+            // The bodies of binders stay at the same depth so that long
+            // telescopes are not cut off.
+            &Forall { span, ref binder, term: ref body } => Forall {
+                span: span,
+                binder: self.binder(binder, depth),
+                term: Box::new(self.term(body, depth)),
+            },
+            &Lambda { span, ref binder, ref body } => Lambda {
+                span: span,
+                binder: self.binder(binder, depth),
+                body: Box::new(self.term(body, depth)),
+            },
+            &Let { span, ref binder, ref value, ref body } => Let {
+                span: span,
+                binder: self.binder(binder, depth),
+                value: Box::new(self.term(value, depth + 1)),
+                body: Box::new(self.term(body, depth)),
+            },
+            &Fix { span, ref binder, rec_arg, ref body } => Fix {
+                span: span,
+                binder: self.binder(binder, depth),
+                rec_arg: rec_arg,
+                body: Box::new(self.term(body, depth)),
+            },
+            &Var { .. } | &Type => term.clone(),
+        }
+    }
+}
+
+/// Render `term` for a diagnostic. `implicits` maps globals to the number of
+/// leading implicit parameters they take.
+pub fn render(term: &Term, options: DisplayOptions, implicits: &HashMap<Name, usize>) -> String {
+    let elide = Elide {
+        options: options,
+        implicits: implicits,
+    };
+
+    let printer = Printer {
+        is_elided: |name: &Name| *name == elision_name(),
+    };
+
+    let rendered = printer.term(&elide.term(term, 0)).render(options.line_width);
+
+    match options.max_width {
+        Some(width) if rendered.chars().count() > width => {
+            let mut truncated: String = rendered.chars().take(width).collect();
+            truncated.push_str(" ...");
+            truncated
+        }
+        _ => rendered,
+    }
+}
@@ -0,0 +1,219 @@
+//! Documentation generated from the doc comments of a module.
+//!
+//! Every global the module declares is listed with its elaborated type, the
+//! constructors of inductive types, and the text of its doc comment. Names
+//! in a signature that refer to globals of the same module link to their
+//! entry.
+
+use core::{self, Name, Term};
+use display::{self, DisplayOptions};
+use typeck::TyCtxt;
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+impl Format {
+    pub fn from_str(s: &str) -> Option<Format> {
+        match s {
+            "markdown" | "md" => Some(Format::Markdown),
+            "html" => Some(Format::Html),
+            _ => None,
+        }
+    }
+
+    /// The extension of documentation files in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            &Format::Markdown => "md",
+            &Format::Html => "html",
+        }
+    }
+}
+
+/// A documented global, the constructors are only present for inductive
+/// types.
+struct Entry<'a> {
+    kind: &'static str,
+    name: &'a Name,
+    ty: &'a Term,
+    ctors: Vec<(&'a Name, &'a Term)>,
+    doc: Option<&'a str>,
+}
+
+impl<'a> Entry<'a> {
+    fn from_item(ty_cx: &'a TyCtxt, item: &'a core::Item) -> Entry<'a> {
+        let (kind, ty) = match item {
+            &core::Item::Data(ref data) => ("inductive", &data.ty),
+            &core::Item::Fn(ref def) => ("def", &def.ty),
+            &core::Item::Axiom(ref a) => ("axiom", &a.ty),
+            &core::Item::Extern(ref e) => ("extern", &e.term),
+        };
+
+        let ctors = match item {
+            &core::Item::Data(ref data) => data.ctors.iter().map(|c| (&c.0, &c.1)).collect(),
+            _ => vec![],
+        };
+
+        Entry {
+            kind: kind,
+            name: item.name(),
+            ty: ty,
+            ctors: ctors,
+            doc: ty_cx.docs.get(item.name()).map(|doc| &doc[..]),
+        }
+    }
+}
+
+/// Links the names of the globals declared by the module to their entries.
+struct Linker {
+    anchors: HashMap<String, String>,
+}
+
+impl Linker {
+    fn new(module: &core::Module) -> Linker {
+        let mut anchors = HashMap::new();
+        let module_prefix = format!("{}.", module.name);
+
+        {
+            let mut add = |name: &Name| {
+                let anchor = format!("{}", name);
+                // Globals may also be printed without the module's name.
+                if anchor.starts_with(&module_prefix) {
+                    anchors.insert(anchor[module_prefix.len()..].to_string(), anchor.clone());
+                }
+                anchors.insert(anchor.clone(), anchor);
+            };
+
+            for item in &module.defs {
+                add(item.name());
+
+                if let &core::Item::Data(ref data) = item {
+                    for ctor in &data.ctors {
+                        add(&ctor.0);
+                    }
+                }
+            }
+        }
+
+        Linker { anchors: anchors }
+    }
+
+    /// Render `term` as HTML, linking each name with an entry.
+    fn signature(&self, term: &Term) -> String {
+        let text = display::render(term, DisplayOptions::verbose(), &HashMap::new());
+        let mut out = String::new();
+        let mut ident = String::new();
+
+        for c in text.chars() {
+            if c.is_alphanumeric() || c == '_' || c == '.' || c == '\'' {
+                ident.push(c);
+            } else {
+                self.push_ident(&mut out, &ident);
+                ident.clear();
+                push_escaped(&mut out, c);
+            }
+        }
+
+        self.push_ident(&mut out, &ident);
+        out
+    }
+
+    fn push_ident(&self, out: &mut String, ident: &str) {
+        match self.anchors.get(ident) {
+            Some(anchor) => out.push_str(&format!("<a href=\"#{}\">{}</a>", anchor, ident)),
+            None => out.push_str(ident),
+        }
+    }
+}
+
+fn push_escaped(out: &mut String, c: char) {
+    match c {
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '&' => out.push_str("&amp;"),
+        '"' => out.push_str("&quot;"),
+        c => out.push(c),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        push_escaped(&mut out, c);
+    }
+    out
+}
+
+/// Write the documentation for `module`, which must have been elaborated
+/// in `ty_cx`.
+pub fn document_module<W: Write>(ty_cx: &TyCtxt,
+                                 module: &core::Module,
+                                 format: Format,
+                                 out: &mut W) -> io::Result<()> {
+    let linker = Linker::new(module);
+    let entries: Vec<_> = module.defs.iter().map(|item| Entry::from_item(ty_cx, item)).collect();
+
+    match format {
+        Format::Markdown => {
+            try!(writeln!(out, "# Module {}\n", module.name));
+
+            for entry in &entries {
+                try!(writeln!(out, "## <a name=\"{}\"></a>{} `{}`\n", entry.name, entry.kind, entry.name));
+                try!(writeln!(out, "<code>{} : {}</code>\n", entry.name, linker.signature(entry.ty)));
+
+                for &(name, ty) in &entry.ctors {
+                    try!(writeln!(out, "- <a name=\"{}\"></a><code>{} : {}</code>",
+                                  name, name, linker.signature(ty)));
+                }
+
+                if !entry.ctors.is_empty() {
+                    try!(writeln!(out, ""));
+                }
+
+                if let Some(doc) = entry.doc {
+                    try!(writeln!(out, "{}\n", doc.trim()));
+                }
+            }
+        }
+        Format::Html => {
+            try!(writeln!(out, "<!DOCTYPE html>"));
+            try!(writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">"));
+            try!(writeln!(out, "<title>{}</title>\n</head>\n<body>", module.name));
+            try!(writeln!(out, "<h1>Module {}</h1>", module.name));
+
+            for entry in &entries {
+                try!(writeln!(out, "<section id=\"{}\">", entry.name));
+                try!(writeln!(out, "<h2>{} <code>{}</code></h2>", entry.kind, entry.name));
+                try!(writeln!(out, "<pre><code>{} : {}</code></pre>",
+                              entry.name, linker.signature(entry.ty)));
+
+                if !entry.ctors.is_empty() {
+                    try!(writeln!(out, "<ul>"));
+                    for &(name, ty) in &entry.ctors {
+                        try!(writeln!(out, "<li id=\"{}\"><code>{} : {}</code></li>",
+                                      name, name, linker.signature(ty)));
+                    }
+                    try!(writeln!(out, "</ul>"));
+                }
+
+                if let Some(doc) = entry.doc {
+                    for paragraph in doc.trim().split("\n\n") {
+                        try!(writeln!(out, "<p>{}</p>", escape(paragraph.trim())));
+                    }
+                }
+
+                try!(writeln!(out, "</section>"));
+            }
+
+            try!(writeln!(out, "</body>\n</html>"));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,500 @@
+//! Congruence closure, finding the equations that follow from others.
+//!
+//! The terms on either side of the equations, and their subterms, are
+//! partitioned into classes of terms known to be equal. Two classes are
+//! merged when an equation relates them, when they hold applications of
+//! equal functions to equal arguments, or when they hold the same field of
+//! equal applications of a constructor.
+//!
+//! Each merge records why it was made as an edge between two terms, which
+//! form a forest with a single path between the terms of a class. A proof
+//! that two terms are equal follows that path, and the proof of each edge
+//! is built from the recursor of the equality type alone, so no lemma about
+//! it has to be in scope.
+
+use core::{Name, Term};
+use typeck::{NormalForm, ReduceOptions, TyCtxt};
+
+use super::Error;
+use super::equality::Equality;
+
+use std::collections::{HashMap, VecDeque};
+
+/// An application of a function whose result's type does not depend on
+/// its argument.
+struct App {
+    fun: usize,
+    arg: usize,
+    dom: Term,
+    cod: Term,
+}
+
+/// An application of a constructor to all of its fields.
+struct Ctor {
+    name: Name,
+    data: Name,
+    params: Vec<Term>,
+    fields: Vec<usize>,
+    /// The type of each field, unless it depends on the fields before it.
+    field_tys: Vec<Option<Term>>,
+}
+
+struct Node {
+    term: Term,
+    ty: Term,
+    app: Option<App>,
+    ctor: Option<Ctor>,
+}
+
+/// Why two terms were found to be equal.
+enum Reason {
+    /// A hypothesis, proving the equation.
+    Hypothesis(Term),
+    /// Both are applications, of equal functions to equal arguments.
+    Congruence,
+    /// Both are the `field`th field of applications of the same
+    /// constructor, `from` and `to`, which are equal.
+    Injectivity { from: usize, to: usize, field: usize },
+}
+
+pub struct Closure<'tcx> {
+    ty_cx: &'tcx mut TyCtxt,
+    options: ReduceOptions,
+    equality: Equality,
+    nodes: Vec<Node>,
+    ids: HashMap<Term, usize>,
+    /// The union-find forest of the classes.
+    parent: Vec<usize>,
+    /// The edges between terms, each with the reason for it.
+    edges: Vec<(usize, usize, Reason)>,
+    /// The edges at each term.
+    adjacent: Vec<Vec<usize>>,
+}
+
+impl<'tcx> Closure<'tcx> {
+    pub fn new(ty_cx: &'tcx mut TyCtxt,
+               equality: Equality,
+               options: ReduceOptions) -> Closure<'tcx> {
+        Closure {
+            ty_cx: ty_cx,
+            options: options,
+            equality: equality,
+            nodes: vec![],
+            ids: HashMap::new(),
+            parent: vec![],
+            edges: vec![],
+            adjacent: vec![],
+        }
+    }
+
+    /// Assume that `lhs` and `rhs`, of type `ty`, are equal as `proof`
+    /// shows.
+    pub fn assume(&mut self, ty: Term, lhs: Term, rhs: Term, proof: Term) -> Result<(), Error> {
+        let lhs = try!(self.add(&lhs, ty.clone()));
+        let rhs = try!(self.add(&rhs, ty));
+
+        if self.find(lhs) != self.find(rhs) {
+            self.merge(lhs, rhs, Reason::Hypothesis(proof));
+        }
+
+        Ok(())
+    }
+
+    /// A proof that `lhs` and `rhs`, of type `ty`, are equal, if it follows
+    /// from the assumptions.
+    pub fn prove(&mut self, ty: Term, lhs: Term, rhs: Term) -> Result<Option<Term>, Error> {
+        let lhs = try!(self.add(&lhs, ty.clone()));
+        let rhs = try!(self.add(&rhs, ty));
+
+        try!(self.saturate());
+
+        if self.find(lhs) != self.find(rhs) {
+            return Ok(None);
+        }
+
+        let proof = match try!(self.explain(lhs, rhs)) {
+            Some(proof) => proof,
+            None => self.equality.refl(&self.nodes[lhs].ty, &self.nodes[lhs].term),
+        };
+
+        Ok(Some(proof))
+    }
+
+    /// The term `term` of type `ty`, added along with its subterms.
+    fn add(&mut self, term: &Term, ty: Term) -> Result<usize, Error> {
+        if let Some(&id) = self.ids.get(term) {
+            return Ok(id);
+        }
+
+        let app = match term {
+            &Term::App { ref fun, ref arg, .. } => try!(self.add_app(fun, arg)),
+            _ => None,
+        };
+
+        let ctor = try!(self.ctor(term));
+        let id = self.nodes.len();
+
+        self.nodes.push(Node {
+            term: term.clone(),
+            ty: ty,
+            app: app,
+            ctor: ctor,
+        });
+        self.ids.insert(term.clone(), id);
+        self.parent.push(id);
+        self.adjacent.push(vec![]);
+
+        Ok(id)
+    }
+
+    fn add_app(&mut self, fun: &Term, arg: &Term) -> Result<Option<App>, Error> {
+        let (fun_ty, _) = try!(self.ty_cx.type_infer_term(fun));
+        let fun_ty = try!(self.ty_cx.reduce_to(&fun_ty, NormalForm::WeakHead, self.options));
+
+        let (dom, cod) = match fun_ty {
+            Term::Forall { ref binder, ref term, .. } =>
+                ((*binder.ty).clone(), term.instantiate(arg)),
+            _ => return Ok(None),
+        };
+
+        let fun_id = try!(self.add(fun, fun_ty.clone()));
+        let arg_id = try!(self.add(arg, dom.clone()));
+
+        // The type of the result must not depend on the argument, for
+        // applications to equal arguments to have the same type.
+        let x = self.ty_cx.local_with_repr("x".to_string(), dom.clone());
+        let independent = match fun_ty {
+            Term::Forall { ref term, .. } => {
+                let body = term.instantiate(&x.to_term());
+                body.abstr(&x) == body
+            }
+            _ => false,
+        };
+
+        if !independent {
+            return Ok(None);
+        }
+
+        Ok(Some(App {
+            fun: fun_id,
+            arg: arg_id,
+            dom: dom,
+            cod: cod,
+        }))
+    }
+
+    /// The constructor `term` applies, if it applies one to all of its
+    /// fields and the type it builds has no indices.
+    fn ctor(&self, term: &Term) -> Result<Option<Ctor>, Error> {
+        let (head, args) = term.uncurry();
+
+        let name = match head {
+            Term::Var { name } => name,
+            _ => return Ok(None),
+        };
+
+        let data = match self.ty_cx.types.values().find(|d| d.ctors.iter().any(|c| c.0 == name)) {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+
+        let num_params = data.parameters.len();
+
+        if args.len() < num_params {
+            return Ok(None);
+        }
+
+        let mut ty = try!(self.ty_cx.lookup_global(&name)).clone();
+        for param in &args[..num_params] {
+            ty = match ty {
+                Term::Forall { term, .. } => term.instantiate(param),
+                _ => return Ok(None),
+            };
+        }
+
+        let mut locals: Vec<Name> = vec![];
+        let mut field_tys = vec![];
+
+        while let Term::Forall { binder, term, .. } = ty {
+            let field_ty = *binder.ty;
+            let independent = locals.iter().all(|l| field_ty.abstr(l) == field_ty);
+            let local = self.ty_cx.local_with_repr("a".to_string(), field_ty.clone());
+
+            field_tys.push(if independent { Some(field_ty) } else { None });
+            ty = term.instantiate(&local.to_term());
+            locals.push(local);
+        }
+
+        if args.len() != num_params + field_tys.len() || ty.uncurry().1.len() != num_params {
+            return Ok(None);
+        }
+
+        let fields = match args[num_params..].iter().map(|arg| self.ids.get(arg).cloned())
+                                                .collect::<Option<Vec<_>>>() {
+            Some(fields) => fields,
+            None => return Ok(None),
+        };
+
+        Ok(Some(Ctor {
+            name: name,
+            data: data.name.clone(),
+            params: args[..num_params].to_vec(),
+            fields: fields,
+            field_tys: field_tys,
+        }))
+    }
+
+    fn find(&self, mut id: usize) -> usize {
+        while self.parent[id] != id {
+            id = self.parent[id];
+        }
+        id
+    }
+
+    fn merge(&mut self, from: usize, to: usize, reason: Reason) {
+        let (root_from, root_to) = (self.find(from), self.find(to));
+        self.parent[root_from] = root_to;
+
+        let edge = self.edges.len();
+        self.edges.push((from, to, reason));
+        self.adjacent[from].push(edge);
+        self.adjacent[to].push(edge);
+    }
+
+    /// Merge classes by congruence and injectivity until no more can be.
+    fn saturate(&mut self) -> Result<(), Error> {
+        loop {
+            let mut merged = false;
+
+            for i in 0..self.nodes.len() {
+                for j in i + 1..self.nodes.len() {
+                    if self.find(i) != self.find(j) {
+                        if self.congruent(i, j) {
+                            self.merge(i, j, Reason::Congruence);
+                            merged = true;
+                        }
+                    } else {
+                        for (field, a, b) in self.injective(i, j) {
+                            if self.find(a) != self.find(b) {
+                                self.merge(a, b, Reason::Injectivity {
+                                    from: i,
+                                    to: j,
+                                    field: field,
+                                });
+                                merged = true;
+                            }
+                        }
+                    }
+                }
+            }
+
+            if !merged {
+                return Ok(());
+            }
+        }
+    }
+
+    fn congruent(&self, i: usize, j: usize) -> bool {
+        match (&self.nodes[i].app, &self.nodes[j].app) {
+            (&Some(ref a), &Some(ref b)) =>
+                self.find(a.fun) == self.find(b.fun) && self.find(a.arg) == self.find(b.arg),
+            _ => false,
+        }
+    }
+
+    /// The fields of `i` and `j`, with their positions, when both apply the
+    /// same constructor to the same parameters.
+    fn injective(&self, i: usize, j: usize) -> Vec<(usize, usize, usize)> {
+        match (&self.nodes[i].ctor, &self.nodes[j].ctor) {
+            (&Some(ref a), &Some(ref b)) if a.name == b.name && a.params == b.params => {
+                a.fields.iter().zip(b.fields.iter()).enumerate()
+                 .filter(|&(k, _)| a.field_tys[k].is_some())
+                 .map(|(k, (&x, &y))| (k, x, y))
+                 .collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// A proof that the terms `from` and `to` are equal, or `None` when they
+    /// are the same term.
+    fn explain(&self, from: usize, to: usize) -> Result<Option<Term>, Error> {
+        if from == to {
+            return Ok(None);
+        }
+
+        // The edges form a forest, so the path found is the only one.
+        let mut prev: HashMap<usize, usize> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(from);
+
+        while let Some(id) = queue.pop_front() {
+            if id == to {
+                break;
+            }
+
+            for &edge in &self.adjacent[id] {
+                let (a, b, _) = self.edges[edge];
+                let next = if a == id { b } else { a };
+
+                if next != from && !prev.contains_key(&next) {
+                    prev.insert(next, edge);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        let mut path = vec![];
+        let mut id = to;
+        while id != from {
+            let edge = prev[&id];
+            let (a, b, _) = self.edges[edge];
+            let before = if a == id { b } else { a };
+            path.push((before, id, edge));
+            id = before;
+        }
+        path.reverse();
+
+        let ty = &self.nodes[from].ty;
+        let mut proof: Option<Term> = None;
+
+        for (before, after, edge) in path {
+            let step = try!(self.edge_proof(edge, before, after));
+
+            proof = Some(match proof {
+                None => step,
+                Some(proof) => self.equality.trans(self.ty_cx,
+                                                   ty,
+                                                   &self.nodes[from].term,
+                                                   &self.nodes[before].term,
+                                                   &self.nodes[after].term,
+                                                   proof,
+                                                   step),
+            });
+        }
+
+        Ok(proof)
+    }
+
+    /// A proof that `before` equals `after`, by the edge between them.
+    fn edge_proof(&self, edge: usize, before: usize, after: usize) -> Result<Term, Error> {
+        let (a, b, ref reason) = self.edges[edge];
+
+        let proof = match reason {
+            &Reason::Hypothesis(ref proof) => proof.clone(),
+            &Reason::Congruence => try!(self.congruence(a, b)),
+            &Reason::Injectivity { from, to, field } =>
+                try!(self.injectivity(from, to, field)),
+        };
+
+        if (a, b) == (before, after) {
+            Ok(proof)
+        } else {
+            let node = &self.nodes[a];
+            Ok(self.equality.symm(self.ty_cx, &node.ty, &node.term, &self.nodes[b].term, proof))
+        }
+    }
+
+    /// `f x = g y` from `f = g` and `x = y`.
+    fn congruence(&self, a: usize, b: usize) -> Result<Term, Error> {
+        let (app_a, app_b) = match (&self.nodes[a].app, &self.nodes[b].app) {
+            (&Some(ref app_a), &Some(ref app_b)) => (app_a, app_b),
+            _ => unreachable!(),
+        };
+
+        let lhs = &self.nodes[a].term;
+        let (f, g) = (&self.nodes[app_a.fun], &self.nodes[app_b.fun]);
+        let (x, y) = (&self.nodes[app_a.arg], &self.nodes[app_b.arg]);
+        let cod = &app_a.cod;
+        let eq = &self.equality;
+
+        let mut proof = eq.refl(cod, lhs);
+
+        if let Some(fun_eq) = try!(self.explain(app_a.fun, app_b.fun)) {
+            let motive = |z: &Term| eq.eq(cod, lhs, &Term::apply(z.clone(), x.term.clone()));
+            proof = eq.transport(self.ty_cx, &f.ty, &f.term, &g.term, fun_eq, &motive, proof);
+        }
+
+        if let Some(arg_eq) = try!(self.explain(app_a.arg, app_b.arg)) {
+            let motive = |z: &Term| eq.eq(cod, lhs, &Term::apply(g.term.clone(), z.clone()));
+            proof = eq.transport(self.ty_cx, &app_a.dom, &x.term, &y.term, arg_eq, &motive, proof);
+        }
+
+        Ok(proof)
+    }
+
+    /// The `field`th fields of `from` and `to` are equal, as projecting the
+    /// field out of both sides of `from = to` shows.
+    fn injectivity(&self, from: usize, to: usize, field: usize) -> Result<Term, Error> {
+        let ctor = self.nodes[from].ctor.as_ref().unwrap();
+        let field_ty = ctor.field_tys[field].as_ref().unwrap();
+        let lhs = &self.nodes[ctor.fields[field]].term;
+
+        let projection = try!(self.projection(ctor, field, lhs));
+        let ctors_eq = try!(self.explain(from, to)).unwrap();
+
+        let eq = &self.equality;
+        let motive = |z: &Term| eq.eq(field_ty, lhs, &Term::apply(projection.clone(), z.clone()));
+
+        Ok(eq.transport(self.ty_cx,
+                        &self.nodes[from].ty,
+                        &self.nodes[from].term,
+                        &self.nodes[to].term,
+                        ctors_eq,
+                        &motive,
+                        eq.refl(field_ty, lhs)))
+    }
+
+    /// The function taking an application of `ctor`'s constructor to its
+    /// `field`th field, and any other value to `default`.
+    fn projection(&self, ctor: &Ctor, field: usize, default: &Term) -> Result<Term, Error> {
+        let data = &self.ty_cx.types[&ctor.data];
+        let field_ty = ctor.field_tys[field].clone().unwrap();
+        let ty = Term::apply_all(data.name.to_term(), ctor.params.clone());
+
+        let v = self.ty_cx.local_with_repr("v".to_string(), ty.clone());
+        let motive = Term::abstract_lambda(
+            vec![self.ty_cx.local_with_repr("".to_string(), ty)],
+            field_ty.clone());
+
+        let mut args = ctor.params.clone();
+        args.push(motive);
+
+        for &(ref name, _) in &data.ctors {
+            let mut ty = try!(self.ty_cx.lookup_global(name)).clone();
+            for param in &ctor.params {
+                ty = match ty {
+                    Term::Forall { term, .. } => term.instantiate(param),
+                    _ => unreachable!(),
+                };
+            }
+
+            let mut fields = vec![];
+            let mut hyps = vec![];
+
+            while let Term::Forall { binder, term, .. } = ty {
+                if binder.ty.head() == Some(data.name.to_term()) {
+                    hyps.push(self.ty_cx.local_with_repr("ih".to_string(), field_ty.clone()));
+                }
+
+                let local = self.ty_cx.local_with_repr(format!("a{}", fields.len()), *binder.ty);
+                ty = term.instantiate(&local.to_term());
+                fields.push(local);
+            }
+
+            let body = if *name == ctor.name {
+                fields[field].to_term()
+            } else {
+                default.clone()
+            };
+
+            fields.extend(hyps.into_iter());
+            args.push(Term::abstract_lambda(fields, body));
+        }
+
+        args.push(v.to_term());
+
+        let rec = data.name.in_scope("rec".to_string()).unwrap();
+        Ok(Term::abstract_lambda(vec![v], Term::apply_all(rec.to_term(), args)))
+    }
+}
@@ -0,0 +1,124 @@
+//! Proofs of equations, built by elaboration routines.
+//!
+//! The proofs only use the equality type's constructor and recursor, so a
+//! routine works with whichever equality type its goal is stated in and
+//! needs no lemma about it to be in scope.
+
+use core::{Name, Term};
+use typeck::TyCtxt;
+
+/// The equality type of a goal, a type with a single parameter, the type
+/// of the two sides, and a single constructor, reflexivity.
+pub struct Equality {
+    ty: Name,
+    refl: Name,
+    rec: Name,
+}
+
+impl Equality {
+    /// The equality type of `goal`, if it is one.
+    pub fn of(ty_cx: &TyCtxt, goal: &Term) -> Option<Equality> {
+        let (head, args) = goal.uncurry();
+
+        let name = match head {
+            Term::Var { name } => name,
+            _ => return None,
+        };
+
+        match ty_cx.types.get(&name) {
+            Some(data) if args.len() == 3 &&
+                          data.parameters.len() == 1 &&
+                          data.ctors.len() == 1 => {
+                Some(Equality {
+                    rec: name.in_scope("rec".to_string()).unwrap(),
+                    refl: data.ctors[0].0.clone(),
+                    ty: name,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// The type and sides of `term`, if it is an equation.
+    pub fn sides(&self, term: &Term) -> Option<(Term, Term, Term)> {
+        let (head, mut args) = term.uncurry();
+
+        if head != self.ty.to_term() || args.len() != 3 {
+            return None;
+        }
+
+        let rhs = args.pop().unwrap();
+        let lhs = args.pop().unwrap();
+        let ty = args.pop().unwrap();
+
+        Some((ty, lhs, rhs))
+    }
+
+    /// The equation `lhs = rhs` between terms of type `ty`.
+    pub fn eq(&self, ty: &Term, lhs: &Term, rhs: &Term) -> Term {
+        Term::apply_all(self.ty.to_term(), vec![ty.clone(), lhs.clone(), rhs.clone()])
+    }
+
+    pub fn refl(&self, ty: &Term, term: &Term) -> Term {
+        Term::apply_all(self.refl.to_term(), vec![ty.clone(), term.clone()])
+    }
+
+    /// A proof of `motive rhs` from a proof of `motive lhs`, where `eq`
+    /// shows `lhs = rhs`.
+    pub fn transport(&self,
+                     ty_cx: &TyCtxt,
+                     ty: &Term,
+                     lhs: &Term,
+                     rhs: &Term,
+                     eq: Term,
+                     motive: &Fn(&Term) -> Term,
+                     proof: Term) -> Term {
+        let x = ty_cx.local_with_repr("x".to_string(), ty.clone());
+        let y = ty_cx.local_with_repr("y".to_string(), ty.clone());
+        let e = ty_cx.local_with_repr("e".to_string(), self.eq(ty, &x.to_term(), &y.to_term()));
+        let p = ty_cx.local_with_repr("p".to_string(), motive(&x.to_term()));
+
+        let implication = Term::abstract_pi(
+            vec![ty_cx.local_with_repr("".to_string(), motive(&x.to_term()))],
+            motive(&y.to_term()));
+
+        let rec_motive = Term::abstract_lambda(vec![x.clone(), y, e], implication);
+        let minor = Term::abstract_lambda(vec![x, p.clone()], p.to_term());
+
+        Term::apply_all(self.rec.to_term(),
+                        vec![ty.clone(), rec_motive, minor, lhs.clone(), rhs.clone(), eq, proof])
+    }
+
+    /// `rhs = lhs` from `eq : lhs = rhs`.
+    pub fn symm(&self, ty_cx: &TyCtxt, ty: &Term, lhs: &Term, rhs: &Term, eq: Term) -> Term {
+        self.transport(ty_cx, ty, lhs, rhs, eq, &|z: &Term| self.eq(ty, z, lhs), self.refl(ty, lhs))
+    }
+
+    /// `a = c` from `ab : a = b` and `bc : b = c`.
+    pub fn trans(&self,
+                 ty_cx: &TyCtxt,
+                 ty: &Term,
+                 a: &Term,
+                 b: &Term,
+                 c: &Term,
+                 ab: Term,
+                 bc: Term) -> Term {
+        self.transport(ty_cx, ty, b, c, bc, &|z: &Term| self.eq(ty, a, z), ab)
+    }
+
+    /// `f lhs = f rhs` from `eq : lhs = rhs`, where `f` builds terms of type
+    /// `cod` from terms of type `ty`.
+    pub fn congr_arg(&self,
+                     ty_cx: &TyCtxt,
+                     ty: &Term,
+                     cod: &Term,
+                     f: &Fn(&Term) -> Term,
+                     lhs: &Term,
+                     rhs: &Term,
+                     eq: Term) -> Term {
+        let f_lhs = f(lhs);
+        self.transport(ty_cx, ty, lhs, rhs, eq,
+                       &|z: &Term| self.eq(cod, &f_lhs, &f(z)),
+                       self.refl(cod, &f_lhs))
+    }
+}
@@ -0,0 +1,434 @@
+//! Elaboration routines, extensions of the elaborator written in Rust.
+//!
+//! A routine is registered under a name with `ElabCx::register_elab_routine`,
+//! a global marked `@[elab_as name]` has each of its applications handed to
+//! the routine before the default cases of `elaborate_term` run, as is each
+//! use of it that is not applied. A routine may decline an application,
+//! which is then elaborated as usual.
+
+use ast::{self, HasSpan};
+use core;
+use diagnostic::Diagnostic;
+use index::HoleKind;
+use lint::Lint;
+use session::HasSession;
+use smt::{self, Answer, Solver};
+use typeck::NormalForm;
+
+use super::{Error, LocalElabCx};
+use super::congruence::Closure;
+use super::equality::Equality;
+use super::linear::{self, Arithmetic};
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub trait ElabRoutine {
+    /// Elaborate `head` applied to `args`, written at `span`, or return
+    /// `None` to elaborate the application as usual.
+    fn elaborate(&self,
+                 lcx: &mut LocalElabCx,
+                 head: &ast::Term,
+                 args: &[ast::Term],
+                 span: ast::Span,
+                 expected: Option<core::Term>) -> Result<Option<core::Term>, Error>;
+}
+
+/// `@[elab_as quote]`, each argument is quoted, so `f (g x)` is elaborated
+/// as `` f `(g x) ``.
+pub struct QuoteArguments;
+
+impl ElabRoutine for QuoteArguments {
+    fn elaborate(&self,
+                 lcx: &mut LocalElabCx,
+                 head: &ast::Term,
+                 args: &[ast::Term],
+                 span: ast::Span,
+                 _expected: Option<core::Term>) -> Result<Option<core::Term>, Error> {
+        if args.is_empty() {
+            return Ok(None);
+        }
+
+        let args = args.iter().map(|arg| {
+            ast::Term::Quote {
+                span: arg.get_span(),
+                term: Box::new(arg.clone()),
+            }
+        }).collect();
+
+        lcx.elaborate_app(head.clone(), args, span).map(Some)
+    }
+}
+
+/// `@[elab_as rfl]`, a proof by reflexivity is checked against its expected
+/// type by computation rather than left to unification.
+///
+/// The last two arguments of the expected type are the sides of the
+/// equation, once both are known and reduce to the same term the global is
+/// applied to the arguments before them and the left side, so with
+/// `rfl : {A : Type} -> {x : A} -> Eq A x x` a proof of `Eq A a b` is
+/// elaborated as `rfl A a`. When the sides differ the error shows what each
+/// reduces to.
+pub struct Reflexivity;
+
+impl ElabRoutine for Reflexivity {
+    fn elaborate(&self,
+                 lcx: &mut LocalElabCx,
+                 head: &ast::Term,
+                 args: &[ast::Term],
+                 span: ast::Span,
+                 expected: Option<core::Term>) -> Result<Option<core::Term>, Error> {
+        // Without the sides of the equation there is nothing to compute.
+        let (name, ty) = match (head, expected.as_ref()) {
+            (&ast::Term::Var { ref name, .. }, Some(ty))
+                if args.is_empty() && !mentions_meta(ty) => (name.clone(), ty.clone()),
+            _ => return Ok(None),
+        };
+        let options = lcx.cx.session().options().reduce;
+        let ty = try!(lcx.cx.ty_cx.reduce_to(&ty, NormalForm::WeakHead, options));
+        let (_, mut ty_args) = ty.uncurry();
+
+        if ty_args.len() < 2 {
+            return Ok(None);
+        }
+
+        let rhs = ty_args.pop().unwrap();
+        let lhs = try!(lcx.cx.ty_cx.reduce_to(ty_args.last().unwrap(), NormalForm::Full, options));
+        let rhs = try!(lcx.cx.ty_cx.reduce_to(&rhs, NormalForm::Full, options));
+
+        if lhs != rhs {
+            return Err(Error::NotReflexive(span, lhs, rhs));
+        }
+
+        let global = try!(lcx.elaborate_name(name));
+        let mut proof = core::Term::apply_all(global, ty_args);
+        proof.set_span(span);
+
+        Ok(Some(proof))
+    }
+}
+
+/// `@[elab_as decide]`, where a proof of a proposition is expected the
+/// global is applied to it, the instance of its class is found, and the
+/// application is evaluated. If the result is the class's first
+/// constructor its last field is the proof.
+///
+/// Elsewhere, including where a value of the class is expected, the global
+/// is elaborated as usual.
+pub struct Decide;
+
+impl ElabRoutine for Decide {
+    fn elaborate(&self,
+                 lcx: &mut LocalElabCx,
+                 head: &ast::Term,
+                 args: &[ast::Term],
+                 span: ast::Span,
+                 expected: Option<core::Term>) -> Result<Option<core::Term>, Error> {
+        let (name, goal) = match (head, expected.as_ref()) {
+            (&ast::Term::Var { ref name, .. }, Some(ty))
+                if args.is_empty() && !mentions_meta(ty) => (name.clone(), ty.clone()),
+            _ => return Ok(None),
+        };
+
+        let procedure = try!(lcx.elaborate_name(name));
+
+        // The class is the head of the type the global returns.
+        let class = match procedure {
+            core::Term::Var { ref name } => {
+                let mut ty = try!(lcx.cx.ty_cx.lookup_global(name)).clone();
+                while let core::Term::Forall { term, .. } = ty {
+                    ty = *term;
+                }
+                ty.uncurry().0
+            }
+            _ => return Ok(None),
+        };
+
+        if goal.uncurry().0 == class {
+            return Ok(None);
+        }
+
+        let procedure = try!(lcx.apply_implicit_args(core::Term::apply(procedure, goal.clone())));
+        let (procedure, _) = try!(lcx.cx.ty_cx.type_check_term(&procedure, None));
+        let decision = try!(lcx.cx.ty_cx.eval(&procedure));
+
+        let (ctor, fields) = decision.uncurry();
+        let proved = match (&class, &ctor) {
+            (&core::Term::Var { name: ref class }, &core::Term::Var { name: ref ctor }) =>
+                lcx.cx.ty_cx.types.get(class)
+                                  .and_then(|data| data.ctors.first())
+                                  .map(|first| first.0 == *ctor)
+                                  .unwrap_or(false),
+            _ => false,
+        };
+
+        match fields.last() {
+            Some(proof) if proved => {
+                let mut proof = proof.clone();
+                proof.set_span(span);
+                Ok(Some(proof))
+            }
+            _ => Err(Error::Undecided(span, goal, decision)),
+        }
+    }
+}
+
+/// `@[elab_as cc]`, where an equation is expected it is proved by
+/// congruence closure from the equations among the hypotheses in scope,
+/// so `cc` proves `Eq B (f a) (g c)` from `h1 : Eq A a b`,
+/// `h2 : Eq A c b` and `h3 : Eq (A -> B) f g`, and `Eq A a b` from
+/// `h : Eq (List A) (Cons a l) (Cons b l)`.
+///
+/// The equality type is the head of the expected type, any type with one
+/// parameter and a single constructor. Elsewhere the global is elaborated
+/// as usual.
+pub struct CongruenceClosure;
+
+impl ElabRoutine for CongruenceClosure {
+    fn elaborate(&self,
+                 lcx: &mut LocalElabCx,
+                 head: &ast::Term,
+                 args: &[ast::Term],
+                 span: ast::Span,
+                 expected: Option<core::Term>) -> Result<Option<core::Term>, Error> {
+        let goal = match (head, expected.as_ref()) {
+            (&ast::Term::Var { .. }, Some(ty)) if args.is_empty() && !mentions_meta(ty) =>
+                ty.clone(),
+            _ => return Ok(None),
+        };
+
+        let options = lcx.cx.session().options().reduce;
+        let goal = try!(lcx.cx.ty_cx.reduce_to(&goal, NormalForm::WeakHead, options));
+
+        let (equality, (ty, lhs, rhs)) = match Equality::of(&lcx.cx.ty_cx, &goal) {
+            Some(equality) => {
+                let sides = equality.sides(&goal).unwrap();
+                (equality, sides)
+            }
+            None => return Ok(None),
+        };
+
+        let mut hypotheses = vec![];
+        for local in lcx.locals_in_order.clone() {
+            let hyp_ty = match local {
+                core::Name::Local { ref ty, .. } if !mentions_meta(ty) =>
+                    try!(lcx.cx.ty_cx.reduce_to(ty, NormalForm::WeakHead, options)),
+                _ => continue,
+            };
+
+            if let Some(sides) = equality.sides(&hyp_ty) {
+                hypotheses.push((sides, local.to_term()));
+            }
+        }
+
+        let mut closure = Closure::new(&mut lcx.cx.ty_cx, equality, options);
+
+        for ((hyp_ty, a, b), proof) in hypotheses {
+            try!(closure.assume(hyp_ty, a, b, proof));
+        }
+
+        match try!(closure.prove(ty, lhs, rhs)) {
+            Some(mut proof) => {
+                proof.set_span(span);
+                Ok(Some(proof))
+            }
+            None => Err(Error::NotCongruent(span, goal)),
+        }
+    }
+}
+
+/// `@[elab_as omega]`, where an equation between natural numbers is
+/// expected it is proved by normalizing both sides as linear arithmetic, so
+/// `omega` proves
+///
+/// ```text
+/// Eq Nat (add n (S (add m m))) (add (mul (S (S Z)) m) (S n))
+/// ```
+///
+/// from the definitions of `add` and `mul` and a few lemmas about `add`.
+/// Terms other than sums, numerals and multiplications by a numeral are
+/// treated as unknown numbers.
+///
+/// Elsewhere the global is elaborated as usual.
+pub struct Omega;
+
+impl ElabRoutine for Omega {
+    fn elaborate(&self,
+                 lcx: &mut LocalElabCx,
+                 head: &ast::Term,
+                 args: &[ast::Term],
+                 span: ast::Span,
+                 expected: Option<core::Term>) -> Result<Option<core::Term>, Error> {
+        let goal = match (head, expected.as_ref()) {
+            (&ast::Term::Var { .. }, Some(ty)) if args.is_empty() && !mentions_meta(ty) =>
+                ty.clone(),
+            _ => return Ok(None),
+        };
+
+        let options = lcx.cx.session().options().reduce;
+        let ty_cx = &lcx.cx.ty_cx;
+        let goal = try!(ty_cx.reduce_to(&goal, NormalForm::WeakHead, options));
+
+        let equality = match Equality::of(ty_cx, &goal) {
+            Some(equality) => equality,
+            None => return Ok(None),
+        };
+        let (nat, lhs, rhs) = equality.sides(&goal).unwrap();
+
+        let mut arithmetic = match Arithmetic::new(ty_cx, options, &equality, &nat) {
+            Some(arithmetic) => arithmetic,
+            None => return Ok(None),
+        };
+
+        for lemma in linear::LEMMAS {
+            let name = core::Name::from_str(lemma);
+            if ty_cx.lookup_global(&name).is_err() {
+                return Err(Error::MissingLemma(span, "omega", name));
+            }
+        }
+
+        let (lhs_form, lhs_eq) = try!(arithmetic.normalize(&lhs));
+        let (rhs_form, rhs_eq) = try!(arithmetic.normalize(&rhs));
+        let (lhs_nf, rhs_nf) = (arithmetic.term(&lhs_form), arithmetic.term(&rhs_form));
+
+        if lhs_form != rhs_form {
+            return Err(Error::NotLinear(span, goal.clone(), lhs_nf, rhs_nf));
+        }
+
+        let rhs_eq = equality.symm(ty_cx, &nat, &rhs, &rhs_nf, rhs_eq);
+        let mut proof = equality.trans(ty_cx, &nat, &lhs, &lhs_nf, &rhs, lhs_eq, rhs_eq);
+        proof.set_span(span);
+
+        Ok(Some(proof))
+    }
+}
+
+/// `@[elab_as smt]`, a goal is proved by the SMT solver given with
+/// `--smt-solver`, which is sent the goal and the hypotheses in scope that
+/// can be translated to SMT-LIB, so `smt` proves `not (Eq Nat (add n m) Z)`
+/// from `h : Eq Nat m (S k)`.
+///
+/// The solver's proof is not checked. Instead the goal, generalized over
+/// the locals in scope, is declared as an axiom named after the definition
+/// it occurs in, which the proof applies to the locals.
+pub struct Smt;
+
+impl ElabRoutine for Smt {
+    fn elaborate(&self,
+                 lcx: &mut LocalElabCx,
+                 head: &ast::Term,
+                 args: &[ast::Term],
+                 span: ast::Span,
+                 expected: Option<core::Term>) -> Result<Option<core::Term>, Error> {
+        let goal = match (head, expected.as_ref()) {
+            (&ast::Term::Var { .. }, Some(ty)) if args.is_empty() && !mentions_meta(ty) =>
+                ty.clone(),
+            _ => return Ok(None),
+        };
+
+        let locals = lcx.locals_in_order.clone();
+        let mut hypotheses = vec![];
+        for local in &locals {
+            match local {
+                &core::Name::Local { ref ty, .. } if !mentions_meta(ty) =>
+                    hypotheses.push((**ty).clone()),
+                _ => return Ok(None),
+            }
+        }
+
+        let solver = lcx.cx.session().options().smt_solver.and_then(|command| {
+            Solver::from_command(&command)
+        });
+
+        let solver = match solver {
+            Some(solver) => solver,
+            None => return Err(Error::Smt(span, smt::Error::NoSolver)),
+        };
+
+        let options = lcx.cx.session().options().reduce;
+        let script = try!(smt::translate(&lcx.cx.ty_cx, options, &hypotheses, &goal)
+                              .map_err(|e| Error::Smt(span, e)));
+
+        match try!(solver.check(&script).map_err(|e| Error::Smt(span, e))) {
+            Answer::Unsat => {}
+            Answer::Sat(model) => return Err(Error::SmtCounterexample(span, goal, model)),
+            Answer::Unknown => return Err(Error::SmtUnknown(span, goal)),
+        }
+
+        let axiom = core::Axiom {
+            span: span,
+            name: lcx.cx.auxiliary_name("smt"),
+            ty: core::Term::abstract_pi(locals.clone(), goal),
+        };
+
+        lcx.cx.ty_cx.declare_axiom(&axiom);
+
+        let args = locals.iter().map(core::Name::to_term).collect();
+        let mut proof = core::Term::apply_all(axiom.name.to_term(), args);
+        proof.set_span(span);
+
+        lcx.cx.auxiliary.push(core::Item::Axiom(axiom));
+
+        Ok(Some(proof))
+    }
+}
+
+/// `@[elab_as sorry]`, a placeholder for a proof that is not written yet.
+/// The global, an axiom proving anything, is elaborated as usual, so its
+/// type argument is a metavariable solved by the expected type. Each use is
+/// reported by the `sorry` lint, which `--deny-sorry` makes an error, and
+/// the definitions relying on one list it among their axioms.
+pub struct Sorry;
+
+impl ElabRoutine for Sorry {
+    fn elaborate(&self,
+                 lcx: &mut LocalElabCx,
+                 head: &ast::Term,
+                 _args: &[ast::Term],
+                 span: ast::Span,
+                 _expected: Option<core::Term>) -> Result<Option<core::Term>, Error> {
+        let mut diagnostic = Diagnostic::warning(format!("use of `{}`", head)).span(span);
+
+        if let Some(ref owner) = lcx.cx.defining {
+            diagnostic = diagnostic.note(format!("`{}` is not fully proved", owner));
+        }
+
+        try!(lcx.cx.session().lint(Lint::Sorry, diagnostic));
+
+        if lcx.cx.session().indexing() {
+            let context = lcx.locals_in_order.clone();
+            lcx.cx.ty_cx.index.record_hole(span, context, HoleKind::Sorry);
+        }
+
+        Ok(None)
+    }
+}
+
+/// Whether `term` mentions a metavariable, whose solution is not yet known.
+fn mentions_meta(term: &core::Term) -> bool {
+    match term {
+        &core::Term::Var { ref name } => name.is_meta(),
+        &core::Term::App { ref fun, ref arg, .. } => mentions_meta(fun) || mentions_meta(arg),
+        &core::Term::Forall { ref binder, ref term, .. } =>
+            mentions_meta(&binder.ty) || mentions_meta(term),
+        &core::Term::Lambda { ref binder, ref body, .. } |
+        &core::Term::Fix { ref binder, ref body, .. } =>
+            mentions_meta(&binder.ty) || mentions_meta(body),
+        &core::Term::Let { ref binder, ref value, ref body, .. } =>
+            mentions_meta(&binder.ty) || mentions_meta(value) || mentions_meta(body),
+        &core::Term::Type => false,
+    }
+}
+
+/// The routines every elaboration context starts with.
+pub fn builtin_routines() -> HashMap<String, Rc<ElabRoutine>> {
+    let mut routines: HashMap<String, Rc<ElabRoutine>> = HashMap::new();
+    routines.insert("quote".to_string(), Rc::new(QuoteArguments));
+    routines.insert("rfl".to_string(), Rc::new(Reflexivity));
+    routines.insert("decide".to_string(), Rc::new(Decide));
+    routines.insert("cc".to_string(), Rc::new(CongruenceClosure));
+    routines.insert("omega".to_string(), Rc::new(Omega));
+    routines.insert("smt".to_string(), Rc::new(Smt));
+    routines.insert("sorry".to_string(), Rc::new(Sorry));
+    routines
+}
@@ -0,0 +1,262 @@
+//! Linear arithmetic over the natural numbers.
+//!
+//! Each side of an equation is normalized to a sum of atoms followed by a
+//! numeral, where an atom is any subterm other than a sum, a successor, a
+//! numeral, or a product whose left factor computes to a numeral. The
+//! atoms are kept in the order they were first met, so the equation holds
+//! for every value of the atoms exactly when both sides have the same
+//! normal form.
+//!
+//! Alongside the normal form a proof is built that each side equals it,
+//! from the definitions of `add` and `mul` and the lemmas `add_zero`,
+//! `add_assoc` and `add_left_comm` of `lib/Nat.hbr`.
+
+use core::{Name, Term};
+use typeck::{NormalForm, ReduceOptions, TyCtxt};
+
+use super::Error;
+use super::equality::Equality;
+
+/// The definitions the proofs are built from.
+pub const LEMMAS: &'static [&'static str] = &["add", "add_zero", "add_assoc", "add_left_comm"];
+
+/// A sum of atoms, each given by its position in `Arithmetic::atoms` and
+/// in increasing order, plus a constant.
+#[derive(Clone, PartialEq)]
+pub struct Linear {
+    atoms: Vec<usize>,
+    constant: u64,
+}
+
+impl Linear {
+    fn constant(constant: u64) -> Linear {
+        Linear {
+            atoms: vec![],
+            constant: constant,
+        }
+    }
+
+    /// The sum without its first atom.
+    fn rest(&self) -> Linear {
+        Linear {
+            atoms: self.atoms[1..].to_vec(),
+            constant: self.constant,
+        }
+    }
+
+    /// The sum with `atom` in front, which must be no larger than its atoms.
+    fn with_atom(&self, atom: usize) -> Linear {
+        let mut atoms = vec![atom];
+        atoms.extend(self.atoms.iter().cloned());
+
+        Linear {
+            atoms: atoms,
+            constant: self.constant,
+        }
+    }
+}
+
+pub struct Arithmetic<'a> {
+    ty_cx: &'a TyCtxt,
+    options: ReduceOptions,
+    equality: &'a Equality,
+    nat: Term,
+    zero: Term,
+    succ: Term,
+    atoms: Vec<Term>,
+}
+
+impl<'a> Arithmetic<'a> {
+    /// Arithmetic on `nat`, if it is a type of natural numbers, whose first
+    /// constructor is zero and whose second is the successor.
+    pub fn new(ty_cx: &'a TyCtxt,
+               options: ReduceOptions,
+               equality: &'a Equality,
+               nat: &Term) -> Option<Arithmetic<'a>> {
+        let data = match nat {
+            &Term::Var { ref name } => match ty_cx.types.get(name) {
+                Some(data) if data.parameters.is_empty() && data.ctors.len() == 2 => data,
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(Arithmetic {
+            ty_cx: ty_cx,
+            options: options,
+            equality: equality,
+            nat: nat.clone(),
+            zero: data.ctors[0].0.to_term(),
+            succ: data.ctors[1].0.to_term(),
+            atoms: vec![],
+        })
+    }
+
+    /// The normal form of `term`, and a proof that `term` equals the term
+    /// of that form.
+    pub fn normalize(&mut self, term: &Term) -> Result<(Linear, Term), Error> {
+        if let Some(n) = self.value(term) {
+            return Ok((Linear::constant(n), self.equality.refl(&self.nat, term)));
+        }
+
+        let (head, args) = term.uncurry();
+
+        if head == global("add") && args.len() == 2 {
+            return self.normalize_add(&args[0], &args[1]);
+        }
+
+        // `S n` computes from `add (S Z) n`.
+        if head == self.succ && args.len() == 1 {
+            let one = self.numeral(1);
+            return self.normalize_add(&one, &args[0]);
+        }
+
+        // `mul (S k) n` computes to `add n (mul k n)`, and `mul Z n` to `Z`.
+        if head == global("mul") && args.len() == 2 {
+            let factor = try!(self.ty_cx.reduce_to(&args[0], NormalForm::Full, self.options));
+
+            match self.value(&factor) {
+                Some(0) => {
+                    let proof = self.equality.refl(&self.nat, &self.zero);
+                    return Ok((Linear::constant(0), proof));
+                }
+                Some(n) => {
+                    let rest = Term::apply_all(global("mul"),
+                                               vec![self.numeral(n - 1), args[1].clone()]);
+                    return self.normalize_add(&args[1], &rest);
+                }
+                None => {}
+            }
+        }
+
+        let atom = match self.atoms.iter().position(|atom| atom == term) {
+            Some(atom) => atom,
+            None => {
+                self.atoms.push(term.clone());
+                self.atoms.len() - 1
+            }
+        };
+
+        // An atom `x` is `add x Z`, by `add_zero`.
+        let linear = Linear::constant(0).with_atom(atom);
+        let add_zero = Term::apply(global("add_zero"), term.clone());
+        let proof = self.equality.symm(self.ty_cx, &self.nat, &self.term(&linear), term, add_zero);
+
+        Ok((linear, proof))
+    }
+
+    fn normalize_add(&mut self, lhs: &Term, rhs: &Term) -> Result<(Linear, Term), Error> {
+        let (lhs_form, lhs_eq) = try!(self.normalize(lhs));
+        let (rhs_form, rhs_eq) = try!(self.normalize(rhs));
+        let (lhs_nf, rhs_nf) = (self.term(&lhs_form), self.term(&rhs_form));
+
+        let (eq, nat) = (self.equality, &self.nat);
+
+        // `add lhs rhs = add lhs_nf rhs_nf`
+        let lhs_step = eq.congr_arg(self.ty_cx, nat, nat, &|z: &Term| add(z, rhs),
+                                    lhs, &lhs_nf, lhs_eq);
+        let rhs_step = eq.congr_arg(self.ty_cx, nat, nat, &|z: &Term| add(&lhs_nf, z),
+                                    rhs, &rhs_nf, rhs_eq);
+        let sides = eq.trans(self.ty_cx, nat,
+                             &add(lhs, rhs), &add(&lhs_nf, rhs), &add(&lhs_nf, &rhs_nf),
+                             lhs_step, rhs_step);
+
+        let (form, merged) = self.merge(&lhs_form, &rhs_form);
+        let proof = eq.trans(self.ty_cx, nat,
+                             &add(lhs, rhs), &add(&lhs_nf, &rhs_nf), &self.term(&form),
+                             sides, merged);
+
+        Ok((form, proof))
+    }
+
+    /// The normal form of the sum of `lhs` and `rhs`, and a proof that the
+    /// sum of their terms equals its term.
+    fn merge(&self, lhs: &Linear, rhs: &Linear) -> (Linear, Term) {
+        let (eq, nat) = (self.equality, &self.nat);
+
+        match (lhs.atoms.first(), rhs.atoms.first()) {
+            // Adding zero, or two numerals, computes.
+            (None, _) if lhs.constant == 0 =>
+                (rhs.clone(), eq.refl(nat, &self.term(rhs))),
+            (None, None) => {
+                let sum = Linear::constant(lhs.constant + rhs.constant);
+                let proof = eq.refl(nat, &self.term(&sum));
+                (sum, proof)
+            }
+            // `add (add x l) r = add x (add l r)`
+            (Some(&x), next) if next.map(|&y| x <= y).unwrap_or(true) => {
+                let (atom, rest) = (&self.atoms[x], lhs.rest());
+                let (rest_nf, rhs_nf) = (self.term(&rest), self.term(rhs));
+                let assoc = Term::apply_all(global("add_assoc"),
+                                            vec![atom.clone(), rest_nf.clone(), rhs_nf.clone()]);
+                self.merge_under(x, &add(&self.term(lhs), &rhs_nf), &rest, rhs, assoc)
+            }
+            // `add l (add y r) = add y (add l r)`
+            (_, Some(&y)) => {
+                let (atom, rest) = (&self.atoms[y], rhs.rest());
+                let (lhs_nf, rest_nf) = (self.term(lhs), self.term(&rest));
+                let left_comm = Term::apply_all(
+                    global("add_left_comm"),
+                    vec![lhs_nf.clone(), atom.clone(), rest_nf.clone()]);
+                self.merge_under(y, &add(&lhs_nf, &self.term(rhs)), lhs, &rest, left_comm)
+            }
+            (Some(_), None) => unreachable!(),
+        }
+    }
+
+    /// The normal form of `sum`, which `moved` shows equal to the atom
+    /// `atom` added to the sum of `lhs` and `rhs`.
+    fn merge_under(&self,
+                   atom: usize,
+                   sum: &Term,
+                   lhs: &Linear,
+                   rhs: &Linear,
+                   moved: Term) -> (Linear, Term) {
+        let (eq, nat) = (self.equality, &self.nat);
+        let x = &self.atoms[atom];
+
+        let (form, merged) = self.merge(lhs, rhs);
+        let inner = add(&self.term(lhs), &self.term(rhs));
+        let under = eq.congr_arg(self.ty_cx, nat, nat, &|z: &Term| add(x, z),
+                                 &inner, &self.term(&form), merged);
+
+        let form = form.with_atom(atom);
+        let proof = eq.trans(self.ty_cx, nat,
+                             sum, &add(x, &inner), &self.term(&form),
+                             moved, under);
+
+        (form, proof)
+    }
+
+    /// The term of a normal form.
+    pub fn term(&self, linear: &Linear) -> Term {
+        linear.atoms.iter().rev().fold(self.numeral(linear.constant), |sum, &atom| {
+            add(&self.atoms[atom], &sum)
+        })
+    }
+
+    fn numeral(&self, n: u64) -> Term {
+        (0..n).fold(self.zero.clone(), |m, _| Term::apply(self.succ.clone(), m))
+    }
+
+    /// The number `term` writes with the constructors.
+    fn value(&self, term: &Term) -> Option<u64> {
+        if *term == self.zero {
+            return Some(0);
+        }
+
+        match term {
+            &Term::App { ref fun, ref arg, .. } if **fun == self.succ =>
+                self.value(arg).map(|n| n + 1),
+            _ => None,
+        }
+    }
+}
+
+fn global(name: &str) -> Term {
+    Name::from_str(name).to_term()
+}
+
+fn add(lhs: &Term, rhs: &Term) -> Term {
+    Term::apply_all(global("add"), vec![lhs.clone(), rhs.clone()])
+}
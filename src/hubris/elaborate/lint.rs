@@ -0,0 +1,385 @@
+//! Lints run over the surface syntax of a module after it has been
+//! successfully elaborated.
+
+use ast;
+use core;
+use diagnostic::Diagnostic;
+use lint::Lint;
+use session::HasSession;
+use super::ElabCx;
+use super::pattern_matching::resolve_constructor;
+
+use std::collections::BTreeSet;
+use std::io;
+
+struct LintCx<'ecx> {
+    ecx: &'ecx ElabCx,
+    /// Globals referred to by a pattern, these are not resolved through
+    /// `elaborate_name` so we must track them separately for unused imports.
+    pattern_globals: BTreeSet<core::Name>,
+}
+
+pub fn check_module(ecx: &ElabCx) -> io::Result<()> {
+    let mut lcx = LintCx {
+        ecx: ecx,
+        pattern_globals: BTreeSet::new(),
+    };
+
+    for item in &ecx.module.items {
+        try!(lcx.check_item(item));
+    }
+
+    lcx.check_imports()
+}
+
+impl<'ecx> LintCx<'ecx> {
+    fn is_constructor(&self, name: &ast::Name) -> bool {
+        resolve_constructor(self.ecx, name).is_some()
+    }
+
+    /// Whether `n` and `m` name the same constructor, which may be written
+    /// with or without its type's name.
+    fn same_constructor(&self, n: &ast::Name, m: &ast::Name) -> bool {
+        match (resolve_constructor(self.ecx, n), resolve_constructor(self.ecx, m)) {
+            (Some(n), Some(m)) => n == m,
+            _ => n == m,
+        }
+    }
+
+    fn check_item(&mut self, item: &ast::Item) -> io::Result<()> {
+        match item {
+            &ast::Item::Def(ref def) => {
+                let mut body = vec![];
+                match def.body {
+                    ast::DefBody::Term(ref term) => body.push(term),
+                    ast::DefBody::Equations(ref equations) =>
+                        equation_terms(equations, &mut body),
+                    ast::DefBody::Copatterns(ref copatterns) =>
+                        body.extend(copatterns.iter().map(|c| &c.rhs)),
+                }
+
+                let mut scope = vec![&def.ty];
+                scope.extend(body.iter().cloned());
+
+                try!(self.check_binders(&def.args, &scope[..]));
+                try!(self.check_binder_tys(&def.args));
+                try!(self.check_term(&def.ty));
+                for term in body {
+                    try!(self.check_term(term));
+                }
+                Ok(())
+            }
+            &ast::Item::Axiom(ref ax) => self.check_term(&ax.ty),
+            &ast::Item::Extern(ref ext) => {
+                try!(self.check_binder_tys(&ext.args));
+                self.check_term(&ext.term)
+            }
+            &ast::Item::Inductive(ref data) => {
+                try!(self.check_binder_tys(&data.parameters));
+                try!(self.check_term(&data.ty));
+                for ctor in &data.ctors {
+                    try!(self.check_term(&ctor.1));
+                }
+
+                if let Some(ref rec) = data.recursive {
+                    try!(self.check_term(&rec.ty));
+                    try!(self.check_cases(&rec.cases));
+                    for case in &rec.cases {
+                        try!(self.check_term(&case.rhs));
+                    }
+                }
+
+                Ok(())
+            }
+            &ast::Item::Command(ref cmd) => self.check_term(&cmd.term),
+            &ast::Item::Comment(_) |
+            &ast::Item::Import(_) |
+            &ast::Item::Export(_) |
+            &ast::Item::Variables(_) |
+            &ast::Item::Macro(_) |
+            &ast::Item::Exit(_) => Ok(()),
+        }
+    }
+
+    fn check_binder_tys(&mut self, binders: &[ast::Binder]) -> io::Result<()> {
+        for binder in binders {
+            if let Some(ref ty) = binder.ty {
+                try!(self.check_term(ty));
+            }
+        }
+        Ok(())
+    }
+
+    /// Report any name bound by `binders` that does not occur in the types
+    /// of later binders or in `scope`.
+    fn check_binders(&self, binders: &[ast::Binder], scope: &[&ast::Term]) -> io::Result<()> {
+        for (i, binder) in binders.iter().enumerate() {
+            for (j, name) in binder.names.iter().enumerate() {
+                if is_ignored(name) {
+                    continue;
+                }
+
+                // Names later in the same binder shadow this one.
+                if binder.names[j + 1..].contains(name) {
+                    continue;
+                }
+
+                let in_later_binders = occurs_in_binders(name, &binders[i + 1..]);
+                let shadowed = binders[i + 1..].iter().any(|b| b.names.contains(name));
+                let in_scope = !shadowed && scope.iter().any(|t| occurs_free(name, t));
+
+                if !in_later_binders && !in_scope {
+                    try!(self.unused_variable(name));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unused_variable(&self, name: &ast::Name) -> io::Result<()> {
+        self.ecx.session().lint(
+            Lint::UnusedVariables,
+            Diagnostic::warning(format!("unused variable `{}`", name)).span(name.span))
+    }
+
+    fn check_term(&mut self, term: &ast::Term) -> io::Result<()> {
+        use ast::Term::*;
+
+        match term {
+            &App { ref fun, ref arg, .. } => {
+                try!(self.check_term(fun));
+                self.check_term(arg)
+            }
+            &Forall { ref binders, ref term, .. } => {
+                try!(self.check_binder_tys(binders));
+                self.check_term(term)
+            }
+            &Lambda { ref args, ref ret_ty, ref body, .. } => {
+                let mut scope = vec![&**body];
+                if let Some(ref ret_ty) = **ret_ty {
+                    scope.push(ret_ty);
+                }
+
+                try!(self.check_binders(args, &scope[..]));
+                try!(self.check_binder_tys(args));
+                if let Some(ref ret_ty) = **ret_ty {
+                    try!(self.check_term(ret_ty));
+                }
+                self.check_term(body)
+            }
+            &Let { ref bindings, ref body, .. } => {
+                for &(ref binder, ref term) in bindings {
+                    try!(self.check_binder_tys(&[binder.clone()]));
+                    try!(self.check_term(term));
+                }
+                self.check_term(body)
+            }
+            &Fix { ref args, ref ret_ty, ref body, .. } => {
+                try!(self.check_binders(args, &[&**ret_ty, &**body]));
+                try!(self.check_binder_tys(args));
+                try!(self.check_term(ret_ty));
+                self.check_term(body)
+            }
+            &Match { ref scrutinee, ref cases, .. } => {
+                try!(self.check_term(scrutinee));
+                try!(self.check_cases(cases));
+                for case in cases {
+                    try!(self.check_term(&case.rhs));
+                }
+                Ok(())
+            }
+            &Quote { ref term, .. } | &Antiquote { ref term, .. } => self.check_term(term),
+            &Var { .. } | &Literal { .. } | &Type => Ok(()),
+        }
+    }
+
+    fn check_cases(&mut self, cases: &[ast::Case]) -> io::Result<()> {
+        for (i, case) in cases.iter().enumerate() {
+            let mut vars = vec![];
+            self.pattern_variables(&case.pattern, &mut vars);
+
+            for var in vars {
+                if !is_ignored(&var) && !occurs_free(&var, &case.rhs) {
+                    try!(self.unused_variable(&var));
+                }
+            }
+
+            let covering = cases[..i].iter().find(|c| self.subsumes(&c.pattern, &case.pattern));
+
+            if let Some(covering) = covering {
+                try!(self.ecx.session().lint(
+                    Lint::UnreachablePatterns,
+                    Diagnostic::warning("unreachable pattern".to_string())
+                        .span(case.span)
+                        .label(covering.span, "already matched by this pattern")));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pattern_variables(&mut self, pattern: &ast::Pattern, vars: &mut Vec<ast::Name>) {
+        match pattern {
+            &ast::Pattern::Name(ref n) => {
+                match resolve_constructor(self.ecx, n) {
+                    Some(ctor) => { self.pattern_globals.insert(ctor); }
+                    None => vars.push(n.clone()),
+                }
+            }
+            &ast::Pattern::Constructor(ref n, ref ps) => {
+                self.pattern_globals.extend(resolve_constructor(self.ecx, n));
+                for p in ps {
+                    self.pattern_variables(p, vars);
+                }
+            }
+            &ast::Pattern::Placeholder => {}
+        }
+    }
+
+    /// Whether every value matched by `q` is also matched by `p`.
+    fn subsumes(&self, p: &ast::Pattern, q: &ast::Pattern) -> bool {
+        use ast::Pattern::*;
+
+        match (p, q) {
+            (&Placeholder, _) => true,
+            (&Name(ref n), _) if !self.is_constructor(n) => true,
+            (&Name(ref n), &Name(ref m)) => self.same_constructor(n, m),
+            (&Name(ref n), &Constructor(ref m, _)) |
+            (&Constructor(ref n, _), &Name(ref m)) if !self.same_constructor(n, m) => false,
+            (&Name(_), &Constructor(_, ref qs)) => qs.len() == 0,
+            (&Constructor(_, ref ps), &Name(_)) => ps.len() == 0,
+            (&Constructor(ref n, ref ps), &Constructor(ref m, ref qs)) =>
+                self.same_constructor(n, m) && ps.len() == qs.len() &&
+                ps.iter().zip(qs.iter()).all(|(p, q)| self.subsumes(p, q)),
+            _ => false,
+        }
+    }
+
+    fn check_imports(&self) -> io::Result<()> {
+        for &(ref import, ref declared) in &self.ecx.imports {
+            // If the import declared nothing new it was already loaded by
+            // another import and we can't tell whether it was needed.
+            if declared.len() == 0 {
+                continue;
+            }
+
+            let used = declared.iter().any(|n| {
+                self.ecx.used_globals.contains(n) || self.pattern_globals.contains(n)
+            });
+
+            if !used {
+                try!(self.ecx.session().lint(
+                    Lint::UnusedImports,
+                    Diagnostic::warning(format!("unused import `{}`", import))
+                        .span(import.span)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Names starting with an underscore are never reported as unused.
+/// The terms appearing in `equations`, the right hand sides along with the
+/// terms split on by `with`.
+fn equation_terms<'a>(equations: &'a [ast::Equation], terms: &mut Vec<&'a ast::Term>) {
+    for equation in equations {
+        match equation.rhs {
+            ast::EquationRhs::Term(ref rhs) => terms.push(rhs),
+            ast::EquationRhs::With(ref scrutinee, ref equations) => {
+                terms.push(scrutinee);
+                equation_terms(equations, terms);
+            }
+        }
+    }
+}
+
+fn is_ignored(name: &ast::Name) -> bool {
+    match &name.repr {
+        &ast::NameKind::Unqualified(ref s) => s.starts_with("_"),
+        _ => true,
+    }
+}
+
+fn occurs_in_binders(name: &ast::Name, binders: &[ast::Binder]) -> bool {
+    for binder in binders {
+        if binder.ty.as_ref().map_or(false, |ty| occurs_free(name, ty)) {
+            return true;
+        }
+
+        if binder.names.contains(name) {
+            return false;
+        }
+    }
+
+    false
+}
+
+fn binds(pattern: &ast::Pattern, name: &ast::Name) -> bool {
+    match pattern {
+        &ast::Pattern::Name(ref n) => n == name,
+        &ast::Pattern::Constructor(_, ref ps) => ps.iter().any(|p| binds(p, name)),
+        &ast::Pattern::Placeholder => false,
+    }
+}
+
+/// Whether `name` occurs free in `term`.
+fn occurs_free(name: &ast::Name, term: &ast::Term) -> bool {
+    use ast::Term::*;
+
+    match term {
+        &Var { name: ref n, .. } => n == name,
+        &App { ref fun, ref arg, .. } => occurs_free(name, fun) || occurs_free(name, arg),
+        &Forall { ref binders, ref term, .. } =>
+            occurs_in_binders(name, binders) ||
+            !binders.iter().any(|b| b.names.contains(name)) && occurs_free(name, term),
+        &Lambda { ref args, ref ret_ty, ref body, .. } => {
+            if occurs_in_binders(name, args) {
+                return true;
+            }
+
+            if args.iter().any(|b| b.names.contains(name)) {
+                return false;
+            }
+
+            let in_ret_ty = match **ret_ty {
+                Some(ref ret_ty) => occurs_free(name, ret_ty),
+                None => false,
+            };
+
+            in_ret_ty || occurs_free(name, body)
+        }
+        &Let { ref bindings, ref body, .. } => {
+            for &(ref binder, ref term) in bindings {
+                if occurs_free(name, term) || occurs_in_binders(name, &[binder.clone()]) {
+                    return true;
+                }
+
+                if binder.names.contains(name) {
+                    return false;
+                }
+            }
+
+            occurs_free(name, body)
+        }
+        &Fix { name: ref fix, ref args, ref ret_ty, ref body, .. } => {
+            if occurs_in_binders(name, args) {
+                return true;
+            }
+
+            if fix == name || args.iter().any(|b| b.names.contains(name)) {
+                return false;
+            }
+
+            occurs_free(name, ret_ty) || occurs_free(name, body)
+        }
+        &Match { ref scrutinee, ref cases, .. } =>
+            occurs_free(name, scrutinee) ||
+            cases.iter().any(|c| !binds(&c.pattern, name) && occurs_free(name, &c.rhs)),
+        // A name in a quotation is reflected rather than referred to, it
+        // is still counted as a use.
+        &Quote { ref term, .. } | &Antiquote { ref term, .. } => occurs_free(name, term),
+        &Literal { .. } | &Type => false,
+    }
+}
@@ -0,0 +1,432 @@
+//! Term-level macros, declared with `macro m | m p ... => template end`.
+//!
+//! An application of a macro is rewritten by the first rule whose pattern
+//! matches it before the item containing it is elaborated. A name in a
+//! pattern binds the argument in its position, `_` matches anything, and a
+//! parenthesized pattern `(c p ...)` matches an application of the global
+//! `c`. Arguments beyond those of the rule are applied to the expansion,
+//! which is then expanded again.
+//!
+//! Expansion is hygienic, the binders a template introduces are renamed so
+//! they can not capture names in the arguments substituted into it. A
+//! pattern variable may also be used as a binder in the template, in which
+//! case the argument must be a name and the binder is the user's.
+
+use ast::{self, HasSpan, Pattern, Term};
+use syntax::visit::*;
+
+use super::Error;
+
+use std::cell::Cell;
+use std::collections::HashMap;
+
+/// How deeply expansions may be nested before a macro is assumed to not
+/// terminate.
+pub const EXPANSION_LIMIT: usize = 128;
+
+/// The macros declared so far.
+pub struct Macros {
+    rules: HashMap<ast::Name, Vec<ast::MacroRule>>,
+    /// A counter used to generate the names of renamed binders.
+    fresh: Cell<usize>,
+}
+
+impl Macros {
+    pub fn new() -> Macros {
+        Macros {
+            rules: HashMap::new(),
+            fresh: Cell::new(0),
+        }
+    }
+
+    /// Check the rules of `mac` and make it available to later items.
+    pub fn define(&mut self, mac: ast::Macro) -> Result<(), Error> {
+        for rule in &mac.rules {
+            match rule.pattern {
+                Pattern::Constructor(ref head, ref args) if *head == mac.name => {
+                    let mut vars = vec![];
+                    for arg in args {
+                        try!(pattern_variables(arg, &mut vars));
+                    }
+                }
+                _ => return Err(Error::MacroRuleHead(mac.name.clone(), rule.span)),
+            }
+        }
+
+        self.rules.insert(mac.name, mac.rules);
+        Ok(())
+    }
+
+    /// Expand every macro application in `item`.
+    pub fn expand_item(&self,
+                       item: &mut ast::Item,
+                       is_constructor: &Fn(&ast::Name) -> bool) -> Result<(), Error> {
+        if self.rules.is_empty() {
+            return Ok(());
+        }
+
+        let mut expander = Expander::new(self, is_constructor);
+        expander.visit_mut_item(item);
+        expander.finish()
+    }
+
+    /// Expand every macro application in `term`.
+    pub fn expand_term(&self,
+                       term: &mut Term,
+                       is_constructor: &Fn(&ast::Name) -> bool) -> Result<(), Error> {
+        if self.rules.is_empty() {
+            return Ok(());
+        }
+
+        let mut expander = Expander::new(self, is_constructor);
+        expander.visit_mut_term(term);
+        expander.finish()
+    }
+
+    fn fresh_name(&self, name: &ast::Name) -> ast::Name {
+        let n = self.fresh.get();
+        self.fresh.set(n + 1);
+
+        ast::Name {
+            span: name.span,
+            repr: ast::NameKind::Unqualified(format!("{}%{}", name, n)),
+        }
+    }
+}
+
+/// The variables a pattern binds, in order, a variable may only be bound
+/// once.
+fn pattern_variables(pattern: &Pattern, vars: &mut Vec<ast::Name>) -> Result<(), Error> {
+    match pattern {
+        &Pattern::Name(ref n) => {
+            if let ast::NameKind::Unqualified(_) = n.repr {
+                if vars.contains(n) {
+                    return Err(Error::DuplicateMacroVariable(n.clone()));
+                }
+                vars.push(n.clone());
+            }
+        }
+        &Pattern::Constructor(_, ref args) => {
+            for arg in args {
+                try!(pattern_variables(arg, vars));
+            }
+        }
+        &Pattern::Placeholder => {}
+    }
+
+    Ok(())
+}
+
+fn is_name(term: &Term, name: &ast::Name) -> bool {
+    match term {
+        &Term::Var { name: ref n, .. } => n == name,
+        _ => false,
+    }
+}
+
+/// Match `term` against `pattern`, adding the terms pattern variables are
+/// bound to to `bindings`.
+fn match_pattern(pattern: &Pattern, term: &Term, bindings: &mut HashMap<ast::Name, Term>) -> bool {
+    match pattern {
+        &Pattern::Placeholder => true,
+        &Pattern::Name(ref n) => match n.repr {
+            ast::NameKind::Placeholder => true,
+            ast::NameKind::Unqualified(_) => {
+                bindings.insert(n.clone(), term.clone());
+                true
+            }
+            ast::NameKind::Qualified(_) => is_name(term, n),
+        },
+        &Pattern::Constructor(ref head, ref pats) => {
+            let (fun, args) = term.uncurry();
+            is_name(&fun, head) &&
+            args.len() == pats.len() &&
+            pats.iter().zip(args.iter()).all(|(p, a)| match_pattern(p, a, bindings))
+        }
+    }
+}
+
+/// Rewrites the macro applications of the terms it visits, the first error
+/// stops further expansion.
+struct Expander<'m> {
+    macros: &'m Macros,
+    is_constructor: &'m Fn(&ast::Name) -> bool,
+    depth: usize,
+    error: Option<Error>,
+}
+
+impl<'m> Expander<'m> {
+    fn new(macros: &'m Macros, is_constructor: &'m Fn(&ast::Name) -> bool) -> Expander<'m> {
+        Expander {
+            macros: macros,
+            is_constructor: is_constructor,
+            depth: 0,
+            error: None,
+        }
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        match self.error {
+            None => Ok(()),
+            Some(e) => Err(e),
+        }
+    }
+
+    /// The expansion of `term` if it is an application of a macro.
+    fn expand(&self, term: &Term) -> Option<Result<Term, Error>> {
+        let (fun, args) = term.uncurry();
+
+        let name = match fun {
+            Term::Var { ref name, .. } if self.macros.rules.contains_key(name) => name.clone(),
+            _ => return None,
+        };
+
+        let span = term.get_span();
+
+        if self.depth == EXPANSION_LIMIT {
+            return Some(Err(Error::MacroRecursionLimit(span, name)));
+        }
+
+        for rule in &self.macros.rules[&name] {
+            let pats = match rule.pattern {
+                Pattern::Constructor(_, ref pats) => pats,
+                _ => continue,
+            };
+
+            if pats.len() > args.len() {
+                continue;
+            }
+
+            let mut bindings = HashMap::new();
+
+            if pats.iter().zip(args.iter()).all(|(p, a)| match_pattern(p, a, &mut bindings)) {
+                let expansion = self.instantiate(rule, span, bindings);
+                return Some(expansion.map(|expansion| {
+                    args[pats.len()..].iter().fold(expansion, |fun, arg| {
+                        Term::App {
+                            span: span,
+                            fun: Box::new(fun),
+                            arg: Box::new(arg.clone()),
+                        }
+                    })
+                }));
+            }
+        }
+
+        Some(Err(Error::NoMacroRule(span, name)))
+    }
+
+    /// The template of `rule` with its binders renamed and its pattern
+    /// variables replaced by the terms they are bound to.
+    fn instantiate(&self,
+                   rule: &ast::MacroRule,
+                   span: ast::Span,
+                   bindings: HashMap<ast::Name, Term>) -> Result<Term, Error> {
+        let mut template = rule.template.clone();
+
+        // The template is reported at the application it replaces.
+        SetSpan { span: span }.visit_mut_term(&mut template);
+
+        let mut freshen = Freshen {
+            macros: self.macros,
+            is_constructor: self.is_constructor,
+            bindings: &bindings,
+            scope: vec![],
+        };
+        freshen.term(&mut template);
+
+        let mut subst = Substitute { bindings: &bindings, error: None };
+        subst.visit_mut_term(&mut template);
+
+        match subst.error {
+            None => Ok(template),
+            Some(e) => Err(e),
+        }
+    }
+}
+
+impl<'m, 'v> VisitorMut<'v> for Expander<'m> {
+    fn visit_mut_term(&mut self, term: &'v mut Term) {
+        if self.error.is_some() {
+            return;
+        }
+
+        match self.expand(term) {
+            None => walk_mut_term(self, term),
+            Some(Err(e)) => self.error = Some(e),
+            Some(Ok(expansion)) => {
+                *term = expansion;
+                self.depth += 1;
+                self.visit_mut_term(term);
+                self.depth -= 1;
+            }
+        }
+    }
+}
+
+struct SetSpan {
+    span: ast::Span,
+}
+
+impl<'v> VisitorMut<'v> for SetSpan {
+    fn visit_mut_span(&mut self, span: &'v mut ast::Span) {
+        *span = self.span;
+    }
+}
+
+/// Renames the binders a template introduces, binders that are pattern
+/// variables belong to the user and are left alone.
+struct Freshen<'a> {
+    macros: &'a Macros,
+    is_constructor: &'a Fn(&ast::Name) -> bool,
+    bindings: &'a HashMap<ast::Name, Term>,
+    /// The binders in scope and the names they were renamed to.
+    scope: Vec<(ast::Name, ast::Name)>,
+}
+
+impl<'a> Freshen<'a> {
+    fn term(&mut self, term: &mut Term) {
+        match term {
+            &mut Term::Var { ref mut name, .. } => {
+                let fresh = self.scope.iter().rev().find(|&&(ref n, _)| *n == *name).map(|s| s.1.clone());
+                if let Some(fresh) = fresh {
+                    *name = fresh;
+                }
+            }
+            &mut Term::Match { ref mut scrutinee, ref mut cases, .. } => {
+                self.term(scrutinee);
+
+                for case in cases {
+                    let len = self.scope.len();
+                    self.pattern(&mut case.pattern);
+                    self.term(&mut case.rhs);
+                    self.scope.truncate(len);
+                }
+            }
+            &mut Term::App { ref mut fun, ref mut arg, .. } => {
+                self.term(fun);
+                self.term(arg);
+            }
+            &mut Term::Forall { ref mut binders, ref mut term, .. } => {
+                let len = self.scope.len();
+                for binder in binders {
+                    self.binder(binder);
+                }
+                self.term(term);
+                self.scope.truncate(len);
+            }
+            &mut Term::Lambda { ref mut args, ref mut ret_ty, ref mut body, .. } => {
+                let len = self.scope.len();
+                for binder in args {
+                    self.binder(binder);
+                }
+                if let Some(ref mut ret_ty) = **ret_ty {
+                    self.term(ret_ty);
+                }
+                self.term(body);
+                self.scope.truncate(len);
+            }
+            &mut Term::Let { ref mut bindings, ref mut body, .. } => {
+                let len = self.scope.len();
+                for &mut (ref mut binder, ref mut value) in bindings {
+                    self.term(value);
+                    self.binder(binder);
+                }
+                self.term(body);
+                self.scope.truncate(len);
+            }
+            &mut Term::Fix { ref mut name, ref mut args, ref mut ret_ty,
+                             ref mut rec_arg, ref mut body, .. } => {
+                let len = self.scope.len();
+                self.bind(name);
+                for binder in args {
+                    self.binder(binder);
+                }
+                self.term(ret_ty);
+
+                let fresh = self.scope.iter().rev().find(|&&(ref n, _)| *n == *rec_arg).map(|s| s.1.clone());
+                if let Some(fresh) = fresh {
+                    *rec_arg = fresh;
+                }
+
+                self.term(body);
+                self.scope.truncate(len);
+            }
+            &mut Term::Quote { ref mut term, .. } |
+            &mut Term::Antiquote { ref mut term, .. } => self.term(term),
+            &mut Term::Literal { .. } |
+            &mut Term::Type => {}
+        }
+    }
+
+    fn binder(&mut self, binder: &mut ast::Binder) {
+        if let Some(ref mut ty) = binder.ty {
+            self.term(ty);
+        }
+
+        for name in &mut binder.names {
+            self.bind(name);
+        }
+    }
+
+    fn pattern(&mut self, pattern: &mut Pattern) {
+        match pattern {
+            &mut Pattern::Name(ref mut n) => {
+                if !(self.is_constructor)(n) {
+                    self.bind(n);
+                }
+            }
+            &mut Pattern::Constructor(_, ref mut args) => {
+                for arg in args {
+                    self.pattern(arg);
+                }
+            }
+            &mut Pattern::Placeholder => {}
+        }
+    }
+
+    fn bind(&mut self, name: &mut ast::Name) {
+        if name.repr == ast::NameKind::Placeholder || self.bindings.contains_key(name) {
+            return;
+        }
+
+        let fresh = self.macros.fresh_name(name);
+        self.scope.push((name.clone(), fresh.clone()));
+        *name = fresh;
+    }
+}
+
+/// Replaces the pattern variables of a template with the terms they are
+/// bound to.
+struct Substitute<'a> {
+    bindings: &'a HashMap<ast::Name, Term>,
+    error: Option<Error>,
+}
+
+impl<'a, 'v> VisitorMut<'v> for Substitute<'a> {
+    fn visit_mut_term(&mut self, term: &'v mut Term) {
+        let replacement = match term {
+            &mut Term::Var { ref name, .. } => self.bindings.get(name).cloned(),
+            _ => None,
+        };
+
+        match replacement {
+            Some(replacement) => *term = replacement,
+            None => walk_mut_term(self, term),
+        }
+    }
+
+    // Any other name bound to a pattern variable is a binder of the
+    // template, which the argument must name.
+    fn visit_mut_name(&mut self, name: &'v mut ast::Name) {
+        match self.bindings.get(name) {
+            None => {}
+            Some(&Term::Var { name: ref arg, .. }) => *name = arg.clone(),
+            Some(arg) => {
+                if self.error.is_none() {
+                    self.error = Some(Error::MacroBinder(arg.get_span(), name.clone()));
+                }
+            }
+        }
+    }
+}
@@ -1,22 +1,120 @@
+mod congruence;
+mod equality;
+pub mod extension;
+mod linear;
+mod lint;
+mod macros;
 mod pattern_matching;
+mod quote;
 mod util;
+mod variables;
 
 use ast::{self, HasSpan};
-use core;
+use core::{self, stats};
+use hubris_kernel;
 use typeck::{self, TyCtxt};
+use diagnostic::Diagnostic;
+use feature::Feature;
+use index::{HintKind, HoleKind};
+use lint::Lint;
+use query::Elaborated;
 use session::{HasSession, Session, Reportable};
-use self::util::to_qualified_name;
-use self::pattern_matching::elaborate_pattern_match;
+use smt;
+use timing::{Instant, Phase, Counter};
+use self::extension::ElabRoutine;
+use self::util::generalize;
+pub use self::util::to_qualified_name;
+use self::pattern_matching::{elaborate_equations, elaborate_pattern_match,
+                             resolve_constructor};
 
 use std::io::{self};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::rc::Rc;
 
 #[derive(Debug)]
 pub enum Error {
     UnexpectedQualifiedName,
     UnknownVariable(ast::Name),
+    /// A binder naming a qualified name, only unqualified names are bound.
+    QualifiedBinder(ast::Name),
     TypeCk(typeck::Error),
     InvalidImport,
+    /// A re-export from a module that is not imported.
+    NotImported(ast::Name),
+    /// A re-export of a name the module does not provide, the module
+    /// followed by the name.
+    NotExported(ast::Name, ast::Name),
+    /// A pattern naming something that is not a constructor of the type
+    /// being matched on.
+    UnknownConstructor(ast::Name, core::Name),
+    UnknownAttribute(ast::Attribute),
+    MalformedAttribute(ast::Attribute),
+    FeatureGated(ast::Span, Feature),
+    MalformedEquation(ast::Span),
+    /// An equation of the named function, defined along with an inductive
+    /// type, that calls the function on something other than a field.
+    NotStructural(ast::Span, core::Name),
+    /// A definition by equations whose type is not a function type.
+    EquationsNotFunction(ast::Span, core::Term),
+    /// A `with` whose expected type is unknown, so the goal of its
+    /// auxiliary definition can not be computed.
+    UntypedWith(ast::Span),
+    /// Copatterns defining a value of a type without a single constructor.
+    NotARecord(ast::Span, core::Term),
+    /// A field of the constructor no copattern gives.
+    MissingField(ast::Span, String),
+    /// A copattern naming something that is not a field of the type.
+    UnknownField(ast::Name, core::Name),
+    DuplicateField(ast::Name),
+    /// A rule of a macro whose pattern is not an application of the macro,
+    /// the macro followed by the rule.
+    MacroRuleHead(ast::Name, ast::Span),
+    /// A pattern variable bound more than once by a rule of a macro.
+    DuplicateMacroVariable(ast::Name),
+    /// An application of a macro none of its rules match.
+    NoMacroRule(ast::Span, ast::Name),
+    /// An argument of a macro used as a binder that is not a name.
+    MacroBinder(ast::Span, ast::Name),
+    MacroRecursionLimit(ast::Span, ast::Name),
+    /// A quotation of syntax that has no reflection, described by the
+    /// string.
+    CanNotQuote(ast::Span, &'static str),
+    AntiquoteOutsideQuote(ast::Span),
+    /// The recursive argument named by a `fix` that is not one of its
+    /// arguments.
+    FixArgument(ast::Name),
+    /// An `@[elab_as routine]` naming a routine that is not registered.
+    UnknownElabRoutine(ast::Span, String),
+    /// A proof by reflexivity at `span` of an equation whose sides reduce
+    /// to the two different terms.
+    NotReflexive(ast::Span, core::Term, core::Term),
+    /// A `decide` at `span` whose decision procedure for the goal did not
+    /// evaluate to a proof, but to the term given.
+    Undecided(ast::Span, core::Term, core::Term),
+    /// A `cc` at `span` whose goal does not follow from the equations in
+    /// scope.
+    NotCongruent(ast::Span, core::Term),
+    /// An `omega` at `span` whose goal's sides have the two different
+    /// normal forms.
+    NotLinear(ast::Span, core::Term, core::Term, core::Term),
+    /// A routine at `span` that builds its proofs from the undeclared
+    /// definition given.
+    MissingLemma(ast::Span, &'static str, core::Name),
+    /// An `smt` at `span` whose goal the solver could not be asked about.
+    Smt(ast::Span, smt::Error),
+    /// An `smt` at `span` whose goal the solver refuted, with the model it
+    /// gave as a counterexample.
+    SmtCounterexample(ast::Span, core::Term, String),
+    /// An `smt` at `span` whose goal the solver could not decide.
+    SmtUnknown(ast::Span, core::Term),
+    /// An extern implemented in a language other than Rust.
+    UnknownExternLanguage(ast::Name),
+    /// A `rust` extern whose symbol is not a path into a crate.
+    MalformedRustPath(ast::Span, String),
+    /// A `#print` that is not of the form `#print axioms f`.
+    MalformedPrint(ast::Span),
+    Kernel(hubris_kernel::Error),
+    Io(io::Error),
     Many(Vec<Error>),
 }
 
@@ -26,6 +124,18 @@ impl From<typeck::Error> for Error {
     }
 }
 
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<hubris_kernel::Error> for Error {
+    fn from(err: hubris_kernel::Error) -> Error {
+        Error::Kernel(err)
+    }
+}
+
 impl Reportable for Error {
     fn report(self, session: &Session) -> io::Result<()> {
         match self {
@@ -33,9 +143,262 @@ impl Reportable for Error {
                 ty_ck_err.report(session)
             }
             Error::UnknownVariable(n) => {
-                session.span_error(n.span,
-                    format!("unresolved name `{}`", n))
+                Diagnostic::error(format!("unresolved name `{}`", n))
+                    .span(n.span)
+                    .emit(session)
+            }
+            Error::QualifiedBinder(n) => {
+                Diagnostic::error(format!("the qualified name `{}` can not be bound", n))
+                    .span(n.span)
+                    .emit(session)
+            }
+            Error::Kernel(kernel_err) => {
+                let msg = format!("kernel rejected declaration: {}", kernel_err);
+                match kernel_err.name() {
+                    Some(n) => Diagnostic::error(msg).span(n.get_span()).emit(session),
+                    None => session.error(msg),
+                }
+            }
+            Error::NotImported(module) => {
+                Diagnostic::error(format!("can not re-export from `{}`, it is not imported", module))
+                    .span(module.span)
+                    .help(format!("add `import {}`", module))
+                    .emit(session)
+            }
+            Error::NotExported(module, name) => {
+                Diagnostic::error(format!("`{}` is not provided by `{}`", name, module))
+                    .span(name.span)
+                    .emit(session)
+            }
+            Error::UnknownConstructor(name, datatype) => {
+                Diagnostic::error(format!("`{}` is not a constructor of `{}`", name, datatype))
+                    .span(name.span)
+                    .emit(session)
+            }
+            Error::UnknownAttribute(attr) => {
+                Diagnostic::error(format!("unknown attribute `{}`", attr.name))
+                    .span(attr.span)
+                    .emit(session)
+            }
+            Error::MalformedAttribute(attr) => {
+                Diagnostic::error(format!("malformed `{}` attribute", attr.name))
+                    .span(attr.span)
+                    .help(attribute_usage(&attr.name))
+                    .emit(session)
+            }
+            Error::FeatureGated(span, feature) => {
+                Diagnostic::error(format!("`{}` is an unstable feature", feature))
+                    .span(span)
+                    .help(format!("enable it with `-Z {}`", feature))
+                    .emit(session)
+            }
+            Error::MalformedEquation(span) => {
+                Diagnostic::error("malformed equation".to_string())
+                    .span(span)
+                    .help("each equation must match a single constructor of the type \
+                           applied to a variable for each of its arguments")
+                    .emit(session)
+            }
+            Error::NotStructural(span, fun) => {
+                Diagnostic::error(format!("`{}` is called on something other than a field of \
+                                           the constructor", fun))
+                    .span(span)
+                    .note(format!("an equation of `{}` may only call it on the fields of the \
+                                   constructor it matches, so that it can be unfolded", fun))
+                    .emit(session)
+            }
+            Error::EquationsNotFunction(span, ty) => {
+                Diagnostic::error(format!("a definition by equations must have a function type, \
+                                           found `{}`", ty))
+                    .span(span)
+                    .help("the equations match on the first argument of the type")
+                    .emit(session)
+            }
+            Error::UntypedWith(span) => {
+                Diagnostic::error("can not infer the type of this `with`".to_string())
+                    .span(span)
+                    .emit(session)
+            }
+            Error::NotReflexive(span, lhs, rhs) => {
+                Diagnostic::error("the sides of the equation are not equal by \
+                                   computation".to_string())
+                    .span(span)
+                    .note(format!("the left side reduces to `{}`", session.render(&lhs)))
+                    .note(format!("the right side reduces to `{}`", session.render(&rhs)))
+                    .emit(session)
+            }
+            Error::Undecided(span, goal, decision) => {
+                Diagnostic::error(format!("`decide` can not prove `{}`", session.render(&goal)))
+                    .span(span)
+                    .note(format!("its decision procedure evaluates to `{}`",
+                                  session.render(&decision)))
+                    .emit(session)
+            }
+            Error::NotCongruent(span, goal) => {
+                Diagnostic::error(format!("`cc` can not prove `{}`", session.render(&goal)))
+                    .span(span)
+                    .note("it does not follow from the equations among the hypotheses in \
+                           scope by symmetry, transitivity, congruence and the injectivity of \
+                           constructors")
+                    .emit(session)
+            }
+            Error::NotLinear(span, goal, lhs, rhs) => {
+                Diagnostic::error(format!("`omega` can not prove `{}`", session.render(&goal)))
+                    .span(span)
+                    .note(format!("the left side normalizes to `{}`", session.render(&lhs)))
+                    .note(format!("the right side normalizes to `{}`", session.render(&rhs)))
+                    .emit(session)
+            }
+            Error::MissingLemma(span, routine, lemma) => {
+                Diagnostic::error(format!("`{}` needs `{}`, which is not declared", routine, lemma))
+                    .span(span)
+                    .help("import the module of the standard library that declares it")
+                    .emit(session)
+            }
+            Error::Smt(span, smt::Error::NoSolver) => {
+                Diagnostic::error("`smt` needs an SMT solver, but none was given".to_string())
+                    .span(span)
+                    .help("pass the command running one, such as `--smt-solver=\"z3 -in\"`")
+                    .emit(session)
+            }
+            Error::Smt(span, smt::Error::Untranslatable(term)) => {
+                Diagnostic::error(format!("`smt` can not translate `{}` to SMT-LIB",
+                                          session.render(&term)))
+                    .span(span)
+                    .note("only propositions built from `True`, `False`, `And`, `Or`, \
+                           implication and equations between natural numbers are translated")
+                    .emit(session)
             }
+            Error::Smt(span, smt::Error::Spawn(command, err)) => {
+                Diagnostic::error(format!("could not run the SMT solver `{}`: {}", command, err))
+                    .span(span)
+                    .emit(session)
+            }
+            Error::Smt(span, smt::Error::Crashed(command, stderr)) => {
+                let diagnostic =
+                    Diagnostic::error(format!("the SMT solver `{}` exited without answering",
+                                              command))
+                        .span(span);
+
+                if stderr.is_empty() {
+                    diagnostic.emit(session)
+                } else {
+                    diagnostic.note(format!("it reported: {}", stderr)).emit(session)
+                }
+            }
+            Error::Smt(span, smt::Error::UnexpectedOutput(command, output)) => {
+                Diagnostic::error(format!("the SMT solver `{}` answered `{}`", command, output))
+                    .span(span)
+                    .note("expected `sat`, `unsat` or `unknown`")
+                    .emit(session)
+            }
+            Error::SmtCounterexample(span, goal, model) => {
+                Diagnostic::error(format!("`smt` can not prove `{}`", session.render(&goal)))
+                    .span(span)
+                    .note(format!("the solver found a counterexample:\n{}", model))
+                    .emit(session)
+            }
+            Error::SmtUnknown(span, goal) => {
+                Diagnostic::error(format!("the SMT solver could not decide `{}`",
+                                          session.render(&goal)))
+                    .span(span)
+                    .emit(session)
+            }
+            Error::NotARecord(span, ty) => {
+                Diagnostic::error(format!("copatterns can not define a value of type `{}`", ty))
+                    .span(span)
+                    .help("only a type with a single constructor whose fields are named \
+                           is defined by its fields")
+                    .emit(session)
+            }
+            Error::MissingField(span, field) => {
+                Diagnostic::error(format!("missing a copattern for the field `{}`", field))
+                    .span(span)
+                    .emit(session)
+            }
+            Error::UnknownField(field, datatype) => {
+                Diagnostic::error(format!("`{}` is not a field of `{}`", field, datatype))
+                    .span(field.span)
+                    .emit(session)
+            }
+            Error::DuplicateField(field) => {
+                Diagnostic::error(format!("the field `{}` is given more than once", field))
+                    .span(field.span)
+                    .emit(session)
+            }
+            Error::MacroRuleHead(name, span) => {
+                Diagnostic::error(format!("a rule of the macro `{}` must match an \
+                                           application of `{}`", name, name))
+                    .span(span)
+                    .emit(session)
+            }
+            Error::DuplicateMacroVariable(name) => {
+                Diagnostic::error(format!("`{}` is bound more than once in this pattern", name))
+                    .span(name.span)
+                    .emit(session)
+            }
+            Error::NoMacroRule(span, name) => {
+                Diagnostic::error(format!("no rule of the macro `{}` matches this application",
+                                          name))
+                    .span(span)
+                    .emit(session)
+            }
+            Error::MacroBinder(span, name) => {
+                Diagnostic::error(format!("expected a name for the binder `{}` of the macro",
+                                          name))
+                    .span(span)
+                    .emit(session)
+            }
+            Error::MacroRecursionLimit(span, name) => {
+                Diagnostic::error(format!("the expansion of the macro `{}` does not terminate",
+                                          name))
+                    .span(span)
+                    .note(format!("macro applications may only be nested {} deep",
+                                  macros::EXPANSION_LIMIT))
+                    .emit(session)
+            }
+            Error::CanNotQuote(span, what) => {
+                Diagnostic::error(format!("{} can not be quoted", what))
+                    .span(span)
+                    .help("only names, applications, `fun`, `forall`, `Type` and `_` \
+                           have a reflected syntax")
+                    .emit(session)
+            }
+            Error::AntiquoteOutsideQuote(span) => {
+                Diagnostic::error("`$` may only be used inside a quotation".to_string())
+                    .span(span)
+                    .emit(session)
+            }
+            Error::FixArgument(name) => {
+                Diagnostic::error(format!("`{}` is not an argument of the `fix`", name))
+                    .span(name.span)
+                    .help("name one of the arguments after `on`".to_string())
+                    .emit(session)
+            }
+            Error::UnknownElabRoutine(span, routine) => {
+                Diagnostic::error(format!("unknown elaboration routine `{}`", routine))
+                    .span(span)
+                    .emit(session)
+            }
+            Error::UnknownExternLanguage(language) => {
+                Diagnostic::error(format!("externs can not be implemented in `{}`", language))
+                    .span(language.span)
+                    .help("write `rust \"path::to::function\"` to use a function from a Rust \
+                           crate, or only the symbol for one from the runtime")
+                    .emit(session)
+            }
+            Error::MalformedRustPath(span, path) => {
+                Diagnostic::error(format!("`{}` is not a path to a function in a crate", path))
+                    .span(span)
+                    .help("the path must start with the name of the crate, as in `mycrate::read_file`")
+                    .emit(session)
+            }
+            Error::MalformedPrint(span) => {
+                Diagnostic::error("expected `#print axioms <name>`".to_string())
+                    .span(span)
+                    .emit(session)
+            }
+            Error::Io(e) => Err(e),
             Error::Many(es) => {
                 for e in es {
                     try!(e.report(session))
@@ -51,16 +414,33 @@ impl Reportable for Error {
 pub struct ElabCx {
     /// The current module being elaborated.
     module: ast::Module,
-    /// The set of declared constructor names in scope
-    /// we need this to differentiate between a name
-    /// binding or null-ary constructor in pattern
-    /// matching.
-    constructors: HashSet<ast::Name>,
     /// Set of globally translated names.
-    globals: HashMap<ast::Name, core::Name>,
-    /// A global counter for metavariable numbers, this should probably
-    /// be thread specific.
-    metavar_counter: usize,
+    globals: BTreeMap<ast::Name, core::Name>,
+    /// Each import along with the globals it provides.
+    imports: Vec<(ast::Name, BTreeSet<core::Name>)>,
+    /// Every global loaded by an import, including those of modules that
+    /// are only imported indirectly.
+    imported: BTreeSet<core::Name>,
+    /// Imported modules that have already been elaborated, these are loaded
+    /// from their elaboration instead of from their files.
+    provided: BTreeMap<core::Name, Rc<Elaborated>>,
+    /// The globals this module re-exports from its imports.
+    exports: BTreeSet<core::Name>,
+    /// The section variables declared so far.
+    variables: Vec<ast::Binder>,
+    /// The macros declared so far.
+    macros: macros::Macros,
+    /// The elaboration routines `@[elab_as routine]` may name.
+    elab_routines: HashMap<String, Rc<ElabRoutine>>,
+    /// Globals from other modules that have been referred to.
+    used_globals: BTreeSet<core::Name>,
+    /// The global whose definition is being elaborated, auxiliary
+    /// definitions are named after it.
+    defining: Option<core::Name>,
+    /// The auxiliary definitions and axioms generated while elaborating an
+    /// item, each is declared when it is generated and emitted before the
+    /// item.
+    auxiliary: Vec<core::Item>,
     /// Elaboration relies on type checking, the type checker produces
     /// an "inferred" type containing meta variables along with a set
     /// of constraints that must be solved, in order for type checking
@@ -85,46 +465,102 @@ impl ElabCx {
 
         ElabCx {
             module: module,
-            constructors: HashSet::new(),
-            globals: HashMap::new(),
-            metavar_counter: 0,
+            globals: BTreeMap::new(),
+            imports: vec![],
+            imported: BTreeSet::new(),
+            provided: BTreeMap::new(),
+            exports: BTreeSet::new(),
+            variables: vec![],
+            macros: macros::Macros::new(),
+            elab_routines: extension::builtin_routines(),
+            used_globals: BTreeSet::new(),
+            defining: None,
+            auxiliary: vec![],
             ty_cx: ty_cx,
         }
     }
 
     pub fn elaborate_module(&mut self) -> Result<core::Module, Error> {
+        let _site = stats::Site::enter("elaborate_module");
         let module_name = self.module.name.clone();
 
         let name = try!(self.elaborate_global_name(module_name));
 
+        let flags = self.session().options().cfg;
+        let mut items = vec![];
+
+        for item in self.module.items.drain(..) {
+            if try!(is_configured(&item, &flags)) {
+                items.push(item);
+            }
+        }
+
+        // Everything following an `#exit` is dropped before elaboration, so
+        // it is neither checked nor linted.
+        if let Some(exit) = items.iter().position(|item| match item {
+            &ast::Item::Exit(_) => true,
+            _ => false,
+        }) {
+            let span = items[exit].get_span();
+            let skipped = items[exit + 1..].iter()
+                                           .filter(|item| match *item {
+                                               &ast::Item::Comment(_) => false,
+                                               _ => true,
+                                           })
+                                           .count();
+            items.truncate(exit);
+
+            try!(Diagnostic::warning("elaboration stopped at `#exit`".to_string())
+                     .span(span)
+                     .note(format!("{} item{} following it {} not checked",
+                                   skipped,
+                                   if skipped == 1 { "" } else { "s" },
+                                   if skipped == 1 { "was" } else { "were" }))
+                     .emit(self.session()));
+        }
+
+        self.module.items = items;
+
         let mut errors = vec![];
         let mut defs = vec![];
         let mut imports = vec![];
 
-        for def in self.module.items.clone().into_iter() {
-            match &def {
-                &ast::Item::Inductive(ref d) => {
-                    for ctor in &d.ctors {
-                        self.constructors.insert(ctor.0.clone());
-                    }
-                }
-                &ast::Item::Import(ref n) =>
-                    imports.push(try!(self.elaborate_import(n.clone()))),
-                _ => {}
+        for mut def in self.module.items.clone().into_iter() {
+            if let &ast::Item::Import(ref n) = &def {
+                imports.push(try!(self.elaborate_import(n.clone())));
             }
 
 
-            match self.elaborate_def(def) {
+            if let Err(e) = self.elaborate_attributes(&def) {
+                errors.push(e);
+                continue;
+            }
+
+            let doc = def.doc_mut().and_then(|doc| doc.take());
+            let label = item_name(&def).map(|n| format!("{}", n));
+            self.session().enter_definition("elaborating", label.clone());
+            let start = Instant::now();
+            let result = self.elaborate_def(def);
+            self.session().record_time(Phase::Elaborate, label, start);
+
+            defs.extend(self.auxiliary.drain(..));
+
+            match result {
                 Err(e) => errors.push(e),
                 Ok(edef) => match edef {
                     None => {},
                     Some(edef) => {
+                        if let Some(doc) = doc {
+                            self.ty_cx.docs.insert(edef.name().clone(), doc);
+                        }
                         defs.push(edef);
                     }
                 }
             }
         }
 
+        self.session().leave_definition();
+
         if errors.len() > 0 {
             return Err(Error::Many(errors))
         } else {
@@ -137,19 +573,256 @@ impl ElabCx {
 
             try!(self.ty_cx.type_check_module(&module));
 
+            if self.session().options().validate_core {
+                try!(self.ty_cx.validate_module(&module));
+            }
+
+            let start = Instant::now();
+            try!(self.check_with_kernel(&module));
+            self.session().leave_definition();
+            self.session().record_time(Phase::Kernel, None, start);
+
+            try!(lint::check_module(self));
+
+            let interface = self.interface();
+            self.ty_cx.interfaces.insert(module.name.clone(), interface);
+
             Ok(module)
         }
     }
 
+    /// Hand the fully elaborated module to the kernel to be independently
+    /// re-checked.
+    fn check_with_kernel(&mut self, module: &core::Module) -> Result<(), Error> {
+        for item in &module.defs {
+            self.session().enter_definition("kernel checking", Some(format!("{}", item.name())));
+            try!(self.ty_cx.kernel.add_item(item));
+
+            // The type checker generates auxiliary definitions for each inductive
+            // type, these are not trusted so we check them like any other definition.
+            if let &core::Item::Data(ref data) = item {
+                let mut aux_names: Vec<_> =
+                    ["cases_on", "below", "brec_on", "no_confusion_type", "no_confusion"]
+                        .iter()
+                        .map(|aux| data.name.in_scope(aux.to_string()).unwrap())
+                        .collect();
+
+                for ctor in &data.ctors {
+                    aux_names.push(ctor.0.in_scope("inj".to_string()).unwrap());
+                }
+
+                // Not every construction is generated for every type.
+                for name in aux_names {
+                    if let Some(def) = self.ty_cx.definitions.get(&name).cloned() {
+                        try!(self.ty_cx.kernel.add_definition(&def));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record the effect of the attributes attached to `item`.
+    fn elaborate_attributes(&mut self, item: &ast::Item) -> Result<(), Error> {
+        for attr in item.attributes() {
+            match &attr.name.repr {
+                &ast::NameKind::Unqualified(ref n) if n == "deprecated" => {
+                    let mut deprecation = typeck::Deprecation {
+                        message: None,
+                        replacement: None,
+                    };
+
+                    for arg in &attr.args {
+                        match arg {
+                            &ast::AttributeArg::Str(ref msg) if deprecation.message.is_none() =>
+                                deprecation.message = Some(msg.clone()),
+                            &ast::AttributeArg::Name(ref n) if deprecation.replacement.is_none() =>
+                                deprecation.replacement = to_qualified_name(n.clone()),
+                            _ => return Err(Error::MalformedAttribute(attr.clone())),
+                        }
+                    }
+
+                    let name = item_name(item).and_then(|n| to_qualified_name(n.clone()));
+                    if let Some(name) = name {
+                        self.ty_cx.deprecated.insert(name, deprecation);
+                    }
+                }
+                &ast::NameKind::Unqualified(ref n) if n == "instance" => {
+                    let name = item_name(item).and_then(|n| to_qualified_name(n.clone()));
+
+                    match name {
+                        Some(name) if attr.args.is_empty() => self.ty_cx.instances.push(name),
+                        _ => return Err(Error::MalformedAttribute(attr.clone())),
+                    }
+                }
+                &ast::NameKind::Unqualified(ref n) if n == "unify_hint" => {
+                    let names: Vec<_> = attr.args.iter().filter_map(|arg| match arg {
+                        &ast::AttributeArg::Name(ref n) => to_qualified_name(n.clone()),
+                        &ast::AttributeArg::Str(_) => None,
+                    }).collect();
+
+                    let instance = item_name(item).and_then(|n| to_qualified_name(n.clone()));
+
+                    match instance {
+                        Some(instance) if names.len() == 2 && attr.args.len() == 2 =>
+                            self.ty_cx.unify_hints.push(typeck::UnifyHint {
+                                projection: names[0].clone(),
+                                head: names[1].clone(),
+                                instance: instance,
+                            }),
+                        _ => return Err(Error::MalformedAttribute(attr.clone())),
+                    }
+                }
+                &ast::NameKind::Unqualified(ref n) if n == "elab_as" => {
+                    let name = item_name(item).and_then(|n| to_qualified_name(n.clone()));
+
+                    let routine = match (name, attr.args.first()) {
+                        (Some(name), Some(&ast::AttributeArg::Name(ref routine)))
+                            if attr.args.len() == 1 => (name, format!("{}", routine)),
+                        _ => return Err(Error::MalformedAttribute(attr.clone())),
+                    };
+
+                    if !self.elab_routines.contains_key(&routine.1) {
+                        return Err(Error::UnknownElabRoutine(attr.span, routine.1));
+                    }
+
+                    self.ty_cx.elab_as.insert(routine.0, routine.1);
+                }
+                &ast::NameKind::Unqualified(ref n) if n == "export_rust" => {
+                    let name = match item {
+                        &ast::Item::Def(ref def) => match def.name.repr {
+                            ast::NameKind::Qualified(ref components) =>
+                                components.last().cloned().map(|last| (def.name.clone(), last)),
+                            ast::NameKind::Unqualified(ref s) => Some((def.name.clone(), s.clone())),
+                            ast::NameKind::Placeholder => None,
+                        },
+                        _ => None,
+                    };
+
+                    let name = name.and_then(|(name, last)| {
+                        to_qualified_name(name).map(|name| (name, last))
+                    });
+
+                    // The wrapper is named after the last component of the
+                    // definition's name unless a name is given.
+                    match (name, attr.args.first()) {
+                        (Some((name, last)), None) => {
+                            self.ty_cx.rust_exports.insert(name, last);
+                        }
+                        (Some((name, _)), Some(&ast::AttributeArg::Name(ref rust_name)))
+                            if attr.args.len() == 1 => {
+                            self.ty_cx.rust_exports.insert(name, format!("{}", rust_name));
+                        }
+                        _ => return Err(Error::MalformedAttribute(attr.clone())),
+                    }
+                }
+                &ast::NameKind::Unqualified(ref n) if n == "error_message" => {
+                    let name = item_name(item).and_then(|n| to_qualified_name(n.clone()));
+
+                    match (name, attr.args.first()) {
+                        (Some(name), Some(&ast::AttributeArg::Str(ref template)))
+                            if attr.args.len() == 1 =>
+                            self.ty_cx.error_messages.insert(name, template.clone()),
+                        _ => return Err(Error::MalformedAttribute(attr.clone())),
+                    };
+                }
+                // Unconfigured items were removed before elaboration.
+                &ast::NameKind::Unqualified(ref n) if n == "cfg" => {}
+                _ => return Err(Error::UnknownAttribute(attr.clone())),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Make `routine` available to `@[elab_as name]`.
+    pub fn register_elab_routine(&mut self, name: &str, routine: Rc<ElabRoutine>) {
+        self.elab_routines.insert(name.to_string(), routine);
+    }
+
+    /// Load the module `name` from `module` when it is imported, rather than
+    /// elaborating its file again.
+    pub fn provide_import(&mut self, name: core::Name, module: Rc<Elaborated>) {
+        self.provided.insert(name, module);
+    }
+
     pub fn elaborate_import(&mut self, name: ast::Name) -> Result<core::Name, Error> {
-        let core_name = to_qualified_name(name).unwrap();
-        try!(self.ty_cx.load_import(&core_name));
+        let core_name = to_qualified_name(name.clone()).unwrap();
+
+        let before = self.globals_in_scope();
+
+        match self.provided.get(&core_name).cloned() {
+            // A module that failed to elaborate has already been reported.
+            Some(provided) => if let Some(ref module) = provided.module {
+                try!(self.ty_cx.load_elaborated(&provided.ecx.ty_cx, module));
+            },
+            None => try!(self.ty_cx.load_import(&core_name)),
+        }
+
+        let loaded: BTreeSet<_> = self.globals_in_scope()
+                                      .difference(&before)
+                                      .cloned()
+                                      .collect();
+
+        // Modules loaded from source record what they provide, for an
+        // export file we only know what it declared.
+        let provided = match self.ty_cx.interfaces.get(&core_name) {
+            Some(interface) => interface.clone(),
+            None => loaded.clone(),
+        };
+
+        self.imported.extend(loaded.into_iter());
+        self.imports.push((name, provided));
+
         Ok(core_name)
     }
 
+    /// Record the names re-exported by `export`, each must be provided by
+    /// an import of this module.
+    fn elaborate_export(&mut self, export: ast::Export) -> Result<(), Error> {
+        let provided = match self.imports.iter().find(|&&(ref n, _)| *n == export.module) {
+            None => return Err(Error::NotImported(export.module)),
+            Some(&(_, ref provided)) => provided.clone(),
+        };
+
+        for name in export.names {
+            match to_qualified_name(name.clone()) {
+                Some(ref global) if provided.contains(global) => {
+                    // A re-export counts as a use of the import.
+                    self.used_globals.insert(global.clone());
+                    self.exports.insert(global.clone());
+                }
+                _ => return Err(Error::NotExported(export.module, name)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The globals this module provides to its importers, those it declares
+    /// along with those it re-exports.
+    fn interface(&self) -> BTreeSet<core::Name> {
+        self.globals_in_scope()
+            .difference(&self.imported)
+            .chain(self.exports.iter())
+            .cloned()
+            .collect()
+    }
+
+    fn globals_in_scope(&self) -> BTreeSet<core::Name> {
+        self.ty_cx.axioms.keys()
+                  .chain(self.ty_cx.definitions.keys())
+                  .cloned()
+                  .collect()
+    }
+
     pub fn elaborate_def(&mut self, def: ast::Item) -> Result<Option<core::Item>, Error> {
         debug!("elaborate_def: def={:?}", def);
 
+        let mut def = def;
+        try!(self.expand_macros(&mut def));
+
         match def {
             ast::Item::Inductive(d) => {
                 let edata = try!(self.elaborate_data(d));
@@ -157,6 +830,7 @@ impl ElabCx {
                 Ok(Some(core::Item::Data(edata)))
             }
             ast::Item::Def(def) => {
+                let def = variables::add_variables(&self.variables, def);
                 let edef = try!(self.elaborate_fn(def));
                 try!(self.ty_cx.declare_def(&edef));
                 debug!("elaborate_def: def={}", edef);
@@ -168,15 +842,120 @@ impl ElabCx {
                 Ok(Some(core::Item::Axiom(eax)))
             }
             ast::Item::Extern(e) => {
-                let ext = core::Item::Extern(try!(self.elaborate_extern(e)));
-                Ok(Some(ext))
+                let ext = try!(self.elaborate_extern(e));
+                self.ty_cx.declare_extern(&ext);
+                Ok(Some(core::Item::Extern(ext)))
+            }
+            ast::Item::Command(cmd) => {
+                try!(self.run_command(cmd));
+                Ok(None)
+            }
+            ast::Item::Export(export) => {
+                try!(self.elaborate_export(export));
+                Ok(None)
+            }
+            ast::Item::Variables(vars) => {
+                self.variables.extend(vars.binders.into_iter());
+                Ok(None)
+            }
+            ast::Item::Macro(mac) => {
+                try!(self.macros.define(mac));
+                Ok(None)
             }
             ast::Item::Comment(_) |
-            ast::Item::Import(_) => Ok(None),
+            ast::Item::Import(_) |
+            ast::Item::Exit(_) => Ok(None),
+        }
+    }
+
+    /// Expand the applications of macros in `item`, the rules of a macro
+    /// are left as they are written.
+    fn expand_macros(&self, item: &mut ast::Item) -> Result<(), Error> {
+        if let &mut ast::Item::Macro(_) = item {
+            return Ok(());
+        }
+
+        self.macros.expand_item(item, &|n: &ast::Name| resolve_constructor(self, n).is_some())
+    }
+
+    /// Expand the applications of macros in a term elaborated on its own.
+    pub fn expand_macros_in_term(&self, term: &mut ast::Term) -> Result<(), Error> {
+        self.macros.expand_term(term, &|n: &ast::Name| resolve_constructor(self, n).is_some())
+    }
+
+    /// Elaborate and check the term of a command, then print its result.
+    fn run_command(&mut self, cmd: ast::Command) -> Result<(), Error> {
+        if let ast::CommandKind::Print = cmd.kind {
+            return self.print_axioms(cmd.term);
+        }
+
+        let term = try!(LocalElabCx::from_elab_cx(self).elaborate_term(cmd.term));
+        let (term, _) = try!(self.ty_cx.type_check_term(&term, None));
+
+        match cmd.kind {
+            ast::CommandKind::Eval => {
+                let value = try!(self.ty_cx.eval(&term));
+                try!(self.session().print(&self.session().render_in_full(&value)));
+            }
+            ast::CommandKind::Reduce => {
+                let options = self.session().options().reduce;
+                let normal_form = try!(self.ty_cx.reduce(&term, options));
+                try!(self.session().print(&self.session().render_in_full(&normal_form)));
+            }
+            ast::CommandKind::Print => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    /// `#print axioms f`, print the axioms the global `f` depends on.
+    fn print_axioms(&mut self, term: ast::Term) -> Result<(), Error> {
+        let span = term.get_span();
+        let (head, args) = term.uncurry();
+
+        let is_axioms = match head {
+            ast::Term::Var { ref name, .. } =>
+                name.repr == ast::NameKind::Unqualified("axioms".to_string()),
+            _ => false,
+        };
+
+        if !is_axioms || args.len() != 1 {
+            return Err(Error::MalformedPrint(span));
         }
+
+        let name = match args[0] {
+            ast::Term::Var { ref name, .. } => name.clone(),
+            _ => return Err(Error::MalformedPrint(span)),
+        };
+
+        // The name is resolved on its own, an application of it would have
+        // its implicit arguments inserted.
+        let global = match try!(LocalElabCx::from_elab_cx(self).elaborate_name(name)) {
+            core::Term::Var { ref name } if name.is_qual() => name.clone(),
+            _ => return Err(Error::MalformedPrint(span)),
+        };
+
+        let axioms = self.ty_cx.axioms_used(&global);
+        let text = if axioms.is_empty() {
+            format!("`{}` depends on no axioms", global)
+        } else {
+            let axioms: Vec<_> = axioms.iter().map(|axiom| axiom.to_string()).collect();
+            format!("`{}` depends on the axioms: {}", global, axioms.join(", "))
+        };
+
+        try!(self.session().print(&text));
+
+        Ok(())
     }
 
     fn elaborate_data(&mut self, data: ast::Inductive) -> Result<core::Data, Error> {
+        if let Some(ref rec) = data.recursive {
+            let features = self.session().options().features;
+            if !features.is_enabled(Feature::InductionRecursion) {
+                return Err(Error::FeatureGated(rec.span, Feature::InductionRecursion));
+            }
+        }
+
         let ast_rec_name = data.name.in_scope("rec".to_string()).unwrap();
         let ty_name = try!(self.elaborate_global_name(data.name));
 
@@ -189,25 +968,56 @@ impl ElabCx {
 
         let data_ctors = data.ctors;
         let data_ty = data.ty;
+        let data_rec = data.recursive;
 
-        let (ctors, ty, params) = try!(lcx.enter_scope(data.parameters.clone(),
-        |lcx, params| {
-            let ty = core::Term::abstract_pi(
-                params.clone(),
-                try!(lcx.elaborate_term(data_ty)));
+        let (ctors, ty, params, recursive) = try!(lcx.with_telescope(data.parameters.clone(),
+        |lcx, telescope| {
+            let params = telescope.locals().to_vec();
+            let ty = telescope.pi(try!(lcx.elaborate_term(data_ty)));
 
             // TODO: Fix this shouldn't expose so many details,
             // but the elaborator has to interleave with the type
             // checker better.
             lcx.cx.ty_cx.axioms.insert(ty_name.clone(), typeck::Axiom::new(ty.clone()));
 
+            // A function defined with the type must be declared before the
+            // constructors, since they may refer to it.
+            let rec_decl = match data_rec {
+                None => None,
+                Some(rec) => {
+                    let name = try!(lcx.cx.elaborate_global_name(rec.name));
+                    let fn_ty = try!(lcx.elaborate_term(rec.ty));
+                    let full_ty = core::Term::abstract_pi_implicit(params.clone(), fn_ty.clone());
+                    lcx.cx.ty_cx.axioms.insert(name.clone(), typeck::Axiom::new(full_ty.clone()));
+                    Some((name, fn_ty, full_ty, rec.cases))
+                }
+            };
+
             let mut ctors = Vec::new();
             for ctor in data_ctors.into_iter() {
                 let ector = try!(lcx.elaborate_ctor(&params, ctor));
                 ctors.push(ector);
             }
 
-            Ok((ctors, ty, params))
+            let recursive = match rec_decl {
+                None => None,
+                Some((name, fn_ty, full_ty, cases)) => {
+                    let mut equations = vec![];
+                    for case in cases {
+                        let equation = try!(lcx.elaborate_equation(
+                            &name, &params, &ctors, &fn_ty, case));
+                        equations.push(equation);
+                    }
+
+                    Some(core::RecursiveFn {
+                        name: name,
+                        ty: full_ty,
+                        equations: equations,
+                    })
+                }
+            };
+
+            Ok((ctors, ty, params, recursive))
         }));
 
         Ok(core::Data {
@@ -216,21 +1026,32 @@ impl ElabCx {
             parameters: params,
             ty: ty,
             ctors: ctors,
+            recursive: recursive,
         })
     }
 
     fn elaborate_fn(&mut self, fun: ast::Def) -> Result<core::Function, Error> {
         let mut lcx = LocalElabCx::from_elab_cx(self);
 
-        lcx.enter_scope(fun.args.clone(), move |lcx, args| {
+        lcx.with_telescope(fun.args.clone(), move |lcx, telescope| {
+            let args = telescope.locals().to_vec();
             let name = try!(lcx.cx.elaborate_global_name(fun.name));
+            lcx.cx.defining = Some(name.clone());
+
             let ty = try!(lcx.elaborate_term(fun.ty.clone()));
-            let ebody = try!(lcx.elaborate_term(fun.body));
+            let ebody = match fun.body {
+                ast::DefBody::Term(body) =>
+                    try!(lcx.elaborate_term_against(body, Some(ty.clone()))),
+                ast::DefBody::Equations(equations) =>
+                    try!(lcx.elaborate_def_equations(fun.ty.get_span(), equations, ty.clone())),
+                ast::DefBody::Copatterns(copatterns) =>
+                    try!(lcx.elaborate_copatterns(fun.ty.get_span(), copatterns, ty.clone())),
+            };
 
             debug!("elaborate_fn: ty={} body={}", ty, ebody);
 
-            let body = core::Term::abstract_lambda(args.clone(), ebody);
-            let ret_ty = core::Term::abstract_pi(args.clone(), ty);
+            let body = telescope.lambda(ebody);
+            let ret_ty = telescope.pi(ty);
             // Clear up the whole elaboration vs. decl appraochh,
             // not happy with it right now.
             let (body, ret_ty) = try!(lcx.cx.ty_cx.type_check_term(&body, Some(ret_ty)));
@@ -251,7 +1072,7 @@ impl ElabCx {
     }
 
     fn elaborate_axiom(&mut self, ax: ast::Axiom) -> Result<core::Axiom, Error> {
-        let ast::Axiom { span, name, ty } = ax;
+        let ast::Axiom { span, name, ty, .. } = ax;
         Ok(core::Axiom {
             span: span,
             name: try!(self.elaborate_global_name(name)),
@@ -260,39 +1081,194 @@ impl ElabCx {
     }
 
     fn elaborate_extern(&mut self, ext: ast::Extern) -> Result<core::Extern, Error> {
-        let ast::Extern { span, name, term } = ext;
-        Ok(core::Extern {
-            span: span,
-            name: try!(self.elaborate_global_name(name)),
-            term: try!(LocalElabCx::from_elab_cx(self).elaborate_term(term)),
+        let ast::Extern { span, name, args, term, language, symbol, .. } = ext;
+
+        // A function from another crate is named by a path starting with the
+        // crate, which the project declares as one of its Rust dependencies.
+        let crate_name = match (language, symbol.as_ref()) {
+            (None, _) | (_, None) => None,
+            (Some(language), Some(symbol)) => {
+                match language.repr {
+                    ast::NameKind::Unqualified(ref l) if l == "rust" => {}
+                    _ => return Err(Error::UnknownExternLanguage(language.clone())),
+                }
+
+                let components: Vec<_> = symbol.split("::").collect();
+                let is_path = components.len() > 1 && components.iter().all(|c| {
+                    !c.is_empty() && c.chars().all(|c| c.is_alphanumeric() || c == '_')
+                });
+
+                if !is_path || components[0] == "rt" {
+                    return Err(Error::MalformedRustPath(span, symbol.clone()));
+                }
+
+                Some(components[0].to_string())
+            }
+        };
+
+        let mut lcx = LocalElabCx::from_elab_cx(self);
+
+        lcx.with_telescope(args, move |lcx, telescope| {
+            let args = telescope.locals().to_vec();
+            let name = try!(lcx.cx.elaborate_global_name(name));
+            let ty = try!(lcx.elaborate_term(term));
+
+            // Implicit and erased parameters only exist for type checking,
+            // they are not passed to the implementation.
+            let convention = symbol.map(|symbol| core::Convention {
+                symbol: symbol,
+                crate_name: crate_name,
+                arity: args.len(),
+                erased: args.iter()
+                            .enumerate()
+                            .filter(|&(_, arg)| arg.is_implicit() || arg.is_irrelevant())
+                            .map(|(i, _)| i)
+                            .collect(),
+            });
+
+            Ok(core::Extern {
+                span: span,
+                name: name,
+                term: core::Term::abstract_pi(args, ty),
+                convention: convention,
+            })
         })
     }
 
+    /// A fresh name for an auxiliary item of the global being defined.
+    fn auxiliary_name(&self, kind: &str) -> core::Name {
+        let owner = self.defining.as_ref().expect("auxiliary definition outside of a definition");
+        owner.in_scope(format!("{}_{}", kind, self.auxiliary.len() + 1)).unwrap()
+    }
+
     pub fn elaborate_global_name(&mut self, n: ast::Name) -> Result<core::Name, Error> {
-        match n.repr.clone() {
-            ast::NameKind::Qualified(components) => {
-                let qn = core::Name::Qual {
-                    span: n.span,
-                    components: components,
-                };
+        let components = match n.repr.clone() {
+            ast::NameKind::Qualified(components) => components,
+            ast::NameKind::Unqualified(name) => vec![name],
+            ast::NameKind::Placeholder => return Err(Error::UnexpectedQualifiedName),
+        };
 
-                self.globals.insert(n.clone(), qn.clone());
+        let qn = core::Name::Qual {
+            span: n.span,
+            components: components,
+        };
 
-                Ok(qn)
-            }
-            ast::NameKind::Unqualified(name) => {
-                let qn = core::Name::Qual {
-                    span: n.span,
-                    components: vec![name],
-                };
+        if self.session().indexing() {
+            self.ty_cx.index.record_definition(qn.clone(), n.span);
+        }
+
+        self.globals.insert(n, qn.clone());
+
+        Ok(qn)
+    }
+}
+
+/// How the attribute `name` is written, shown when it is malformed.
+fn attribute_usage(name: &ast::Name) -> &'static str {
+    match name.repr {
+        ast::NameKind::Unqualified(ref n) if n == "instance" =>
+            "expected `@[instance]` on a definition or axiom",
+        ast::NameKind::Unqualified(ref n) if n == "unify_hint" =>
+            "expected `@[unify_hint projection head]`",
+        ast::NameKind::Unqualified(ref n) if n == "elab_as" =>
+            "expected `@[elab_as routine]` on a definition or axiom",
+        ast::NameKind::Unqualified(ref n) if n == "export_rust" =>
+            "expected `@[export_rust]` on a definition, optionally followed by \
+             the name of the wrapper",
+        ast::NameKind::Unqualified(ref n) if n == "cfg" =>
+            "expected `@[cfg flag ...]`, the item is only compiled when every \
+             flag is set with `--cfg`",
+        ast::NameKind::Unqualified(ref n) if n == "error_message" =>
+            "expected `@[error_message \"message\"]`, where `{0}`, `{1}`, ... \
+             stand for the arguments of the global",
+        _ => "expected `@[deprecated]`, optionally followed by a message \
+              and the name of a replacement",
+    }
+}
 
-                self.globals.insert(n.clone(), qn.clone());
+/// Whether `item` is compiled with the `--cfg` flags `flags`, that is
+/// whether each flag named by its `@[cfg]` attributes is set.
+pub fn is_configured(item: &ast::Item, flags: &BTreeSet<String>) -> Result<bool, Error> {
+    let mut configured = true;
 
-                Ok(qn)
+    for attr in item.attributes() {
+        match attr.name.repr {
+            ast::NameKind::Unqualified(ref n) if n == "cfg" => {}
+            _ => continue,
+        }
+
+        if attr.args.is_empty() {
+            return Err(Error::MalformedAttribute(attr.clone()));
+        }
+
+        for arg in &attr.args {
+            match arg {
+                &ast::AttributeArg::Name(ref flag) =>
+                    configured = configured && flags.contains(&format!("{}", flag)),
+                &ast::AttributeArg::Str(_) => return Err(Error::MalformedAttribute(attr.clone())),
             }
-            ast::NameKind::Placeholder => Err(Error::UnexpectedQualifiedName),
         }
     }
+
+    Ok(configured)
+}
+
+/// The name declared by an item, if it declares one.
+pub fn item_name(item: &ast::Item) -> Option<&ast::Name> {
+    match item {
+        &ast::Item::Inductive(ref d) => Some(&d.name),
+        &ast::Item::Def(ref d) => Some(&d.name),
+        &ast::Item::Axiom(ref a) => Some(&a.name),
+        &ast::Item::Extern(ref e) => Some(&e.name),
+        &ast::Item::Comment(_) |
+        &ast::Item::Import(_) |
+        &ast::Item::Export(_) |
+        &ast::Item::Variables(_) |
+        &ast::Item::Macro(_) |
+        &ast::Item::Command(_) |
+        &ast::Item::Exit(_) => None,
+    }
+}
+
+/// The type of the body of a function of type `ty` whose arguments are
+/// `locals`, if `ty` has a binder for each of them.
+fn instantiate_binders(ty: core::Term, locals: &[core::Name]) -> Option<core::Term> {
+    let mut ty = ty;
+
+    for local in locals {
+        ty = match ty {
+            core::Term::Forall { term, .. } => term.instantiate(&local.to_term()),
+            _ => return None,
+        };
+    }
+
+    Some(ty)
+}
+
+/// The locals bound by a sequence of binders, each binder's type may refer
+/// to the binders before it.
+pub struct Telescope {
+    locals: Vec<core::Name>,
+    /// The context the telescope was opened in, restored when it is closed.
+    old_locals: HashMap<ast::Name, core::Name>,
+    old_locals_in_order: Vec<core::Name>,
+}
+
+impl Telescope {
+    /// The locals bound so far, in order.
+    pub fn locals(&self) -> &[core::Name] {
+        &self.locals
+    }
+
+    /// The function type from the telescope to `term`.
+    pub fn pi(&self, term: core::Term) -> core::Term {
+        core::Term::abstract_pi(self.locals.clone(), term)
+    }
+
+    /// The function from the telescope whose body is `term`.
+    pub fn lambda(&self, term: core::Term) -> core::Term {
+        core::Term::abstract_lambda(self.locals.clone(), term)
+    }
 }
 
 pub struct LocalElabCx<'ecx> {
@@ -312,53 +1288,280 @@ impl<'ecx> LocalElabCx<'ecx> {
         }
     }
 
-    fn enter_scope<F, R>(&mut self,
-                         binders: Vec<ast::Binder>,
-                         body: F)
-                         -> Result<R, Error>
-        where F: FnOnce(&mut LocalElabCx, Vec<core::Name>) -> Result<R, Error>
-    {
-        let mut locals = vec![];
-
-        let old_context = self.locals.clone();
-        let old_locals_in_order = self.locals_in_order.clone();
+    /// Open a telescope, the locals bound in it stay in scope until it is
+    /// closed.
+    fn open_telescope(&self) -> Telescope {
+        Telescope {
+            locals: vec![],
+            old_locals: self.locals.clone(),
+            old_locals_in_order: self.locals_in_order.clone(),
+        }
+    }
 
+    /// Elaborate the type of `binder` in the scope of the locals bound so
+    /// far, and bind each of its names in `telescope`.
+    fn bind(&mut self, telescope: &mut Telescope, binder: ast::Binder) -> Result<(), Error> {
         // A binder can contain multiple names like so:
         // (A B C : T) will result in a binder with
         // 3 names to bind, so we then do an inner
         // loop.
+        let binder_ty = binder.ty;
+        for name in binder.names.into_iter().rev() {
+            let repr = match name.clone().repr {
+                ast::NameKind::Qualified(..) => return Err(Error::QualifiedBinder(name)),
+                ast::NameKind::Unqualified(s) => s,
+                ast::NameKind::Placeholder => "_".to_string(),
+            };
 
-        for binder in binders {
-            let binder_ty = binder.ty;
-            for name in binder.names.into_iter().rev() {
-                let repr = match name.clone().repr {
-                    ast::NameKind::Qualified(..) => panic!(),
-                    ast::NameKind::Unqualified(s) => s,
-                    ast::NameKind::Placeholder => "_".to_string(),
-                };
+            let eterm = try!(self.elaborate_term(binder_ty.clone().unwrap()));
 
-                let eterm = try!(self.elaborate_term(binder_ty.clone().unwrap()));
+            let binding_info = match binder.mode {
+                ast::BindingMode::Implicit => core::BindingMode::Implicit,
+                ast::BindingMode::Explicit => core::BindingMode::Explicit,
+                ast::BindingMode::Instance => core::BindingMode::Instance,
+            };
 
-                let binding_info = match binder.mode {
-                    ast::BindingMode::Implicit => core::BindingMode::Implicit,
-                    ast::BindingMode::Explicit => core::BindingMode::Explicit,
+            let mut local = self.cx.ty_cx.local_with_repr_and_mode(repr, eterm, binding_info);
+
+            if let core::Name::Local { ref mut relevance, .. } = local {
+                *relevance = match binder.relevance {
+                    ast::Relevance::Relevant => core::Relevance::Relevant,
+                    ast::Relevance::Irrelevant => core::Relevance::Irrelevant,
                 };
+            }
+
+            if self.cx.session().indexing() {
+                self.cx.ty_cx.index.record_definition(local.clone(), name.span);
+            }
+
+            self.bind_local(telescope, Some(name), local);
+        }
 
-                let local = self.cx.ty_cx.local_with_repr_and_mode(repr, eterm, binding_info);
+        Ok(())
+    }
+
+    /// Bind `local`, whose type has already been elaborated, in `telescope`
+    /// under `name` if it has one. This lets a binder be inserted once it
+    /// is known to be needed, such as an implicit argument, or be given a
+    /// type computed from the binders before it.
+    fn bind_local(&mut self, telescope: &mut Telescope, name: Option<ast::Name>, local: core::Name) {
+        if let Some(name) = name {
+            self.locals.insert(name, local.clone());
+        }
+
+        self.locals_in_order.push(local.clone());
+        telescope.locals.push(local);
+    }
+
+    /// Leave the scope of `telescope`, returning its locals.
+    fn close_telescope(&mut self, telescope: Telescope) -> Vec<core::Name> {
+        self.locals = telescope.old_locals;
+        self.locals_in_order = telescope.old_locals_in_order;
+        telescope.locals
+    }
 
-                self.locals.insert(name, local.clone());
-                self.locals_in_order.push(local.clone());
-                locals.push(local);
+    /// Elaborate `binders` as a telescope and run `body` in its scope, which
+    /// is left afterwards whether or not elaboration succeeded. `body` may
+    /// bind further locals in the telescope.
+    fn with_telescope<F, R>(&mut self,
+                            binders: Vec<ast::Binder>,
+                            body: F)
+                            -> Result<R, Error>
+        where F: FnOnce(&mut LocalElabCx, &mut Telescope) -> Result<R, Error>
+    {
+        let _site = stats::Site::enter("with_telescope");
+        let mut telescope = self.open_telescope();
+
+        let mut bound = Ok(());
+        for binder in binders {
+            bound = self.bind(&mut telescope, binder);
+            if bound.is_err() {
+                break;
             }
         }
 
-        let result = try!(body(self, locals));
+        let result = match bound {
+            Ok(()) => body(self, &mut telescope),
+            Err(e) => Err(e),
+        };
+
+        self.close_telescope(telescope);
+
+        result
+    }
+
+    /// Bind `local` while running `body` under a name that can not be
+    /// written in the source, `body` is given a term referring to it.
+    fn with_hidden_local<F, R>(&mut self, local: core::Name, body: F) -> Result<R, Error>
+        where F: FnOnce(&mut LocalElabCx, ast::Term) -> Result<R, Error>
+    {
+        let old_context = self.locals.clone();
+        let old_locals_in_order = self.locals_in_order.clone();
+
+        // `with` is a keyword, so no name in the source refers to it.
+        let name = ast::Name::from_str("with");
+        self.locals.insert(name.clone(), local.clone());
+        self.locals_in_order.push(local);
+
+        let result = body(self, ast::Term::Var { name: name, implicit: true });
 
-        // Restore the previous context.
         self.locals = old_context;
         self.locals_in_order = old_locals_in_order;
 
-        Ok(result)
+        result
+    }
+
+    /// Elaborate the equations of a definition of type `ty` to a function
+    /// matching on its first argument.
+    fn elaborate_def_equations(&mut self,
+                               span: ast::Span,
+                               equations: Vec<ast::Equation>,
+                               ty: core::Term)
+                               -> Result<core::Term, Error> {
+        let arg = match ty {
+            core::Term::Forall { ref binder, .. } => self.cx.ty_cx.local(binder.clone()),
+            _ => return Err(Error::EquationsNotFunction(span, ty.clone())),
+        };
+
+        let body_ty = instantiate_binders(ty, &[arg.clone()]);
+
+        let body = try!(self.with_hidden_local(arg.clone(), move |lcx, scrutinee| {
+            elaborate_equations(lcx, scrutinee, equations, body_ty)
+        }));
+
+        Ok(core::Term::abstract_lambda(vec![arg], body))
+    }
+
+    /// Elaborate copatterns giving the fields of a value of type `ty` to an
+    /// application of the single constructor of `ty`.
+    ///
+    /// The fields are named by the binders of the constructor's type, each
+    /// is elaborated against its type with the earlier fields substituted.
+    fn elaborate_copatterns(&mut self,
+                            span: ast::Span,
+                            copatterns: Vec<ast::Copattern>,
+                            ty: core::Term)
+                            -> Result<core::Term, Error> {
+        let whnf_ty = try!(self.cx.ty_cx.whnf(&ty));
+        let (head, args) = whnf_ty.uncurry();
+
+        let datatype = match head {
+            core::Term::Var { ref name } => self.cx.ty_cx.types.get(name).cloned(),
+            _ => None,
+        };
+
+        let datatype = match datatype {
+            Some(datatype) => if datatype.ctors.len() == 1 {
+                datatype
+            } else {
+                return Err(Error::NotARecord(span, ty));
+            },
+            None => return Err(Error::NotARecord(span, ty)),
+        };
+
+        for (i, copattern) in copatterns.iter().enumerate() {
+            if copatterns[..i].iter().any(|c| c.field == copattern.field) {
+                return Err(Error::DuplicateField(copattern.field.clone()));
+            }
+        }
+
+        let (ctor, mut field_ty) = datatype.ctors[0].clone();
+        let params: Vec<_> = args.into_iter().take(datatype.parameters.len()).collect();
+
+        for param in &params {
+            field_ty = match field_ty {
+                core::Term::Forall { term, .. } => term.instantiate(param),
+                _ => return Err(Error::NotARecord(span, ty)),
+            };
+        }
+
+        let mut copatterns: Vec<_> = copatterns.into_iter().map(Some).collect();
+        let mut fields = vec![];
+
+        while let core::Term::Forall { binder, term, .. } = field_ty {
+            let field = match binder.name {
+                core::Name::DeBruijn { ref repr, .. } => repr.clone(),
+                _ => String::new(),
+            };
+
+            let position = copatterns.iter().position(|c| match c {
+                &Some(ref c) => c.field.repr == ast::NameKind::Unqualified(field.clone()),
+                &None => false,
+            });
+
+            let copattern = match position {
+                Some(i) => copatterns[i].take().unwrap(),
+                None if field == "" || field == "_" => return Err(Error::NotARecord(span, ty)),
+                None => return Err(Error::MissingField(span, field)),
+            };
+
+            let value = try!(self.elaborate_term_against(copattern.rhs, Some(*binder.ty)));
+            field_ty = term.instantiate(&value);
+            fields.push(value);
+        }
+
+        if let Some(copattern) = copatterns.into_iter().filter_map(|c| c).next() {
+            return Err(Error::UnknownField(copattern.field, datatype.name));
+        }
+
+        let mut args = params;
+        args.extend(fields);
+
+        Ok(core::Term::apply_all(ctor.to_term(), args))
+    }
+
+    /// Elaborate the `with` of an equation to a call of an auxiliary
+    /// definition.
+    ///
+    /// The auxiliary definition takes the locals in scope followed by the
+    /// value of `scrutinee`, its type is the expected type with each
+    /// occurrence of `scrutinee` abstracted, and `equations` match on the
+    /// value.
+    pub fn elaborate_with(&mut self,
+                          scrutinee: ast::Term,
+                          equations: Vec<ast::Equation>,
+                          expected: Option<core::Term>)
+                          -> Result<core::Term, Error> {
+        let expected = match expected {
+            None => return Err(Error::UntypedWith(scrutinee.get_span())),
+            Some(expected) => expected,
+        };
+
+        let escrutinee = try!(self.elaborate_term(scrutinee));
+        let (escrutinee, scrutinee_ty) = try!(self.cx.ty_cx.type_check_term(&escrutinee, None));
+
+        let value = self.cx.ty_cx.local_with_repr("w".to_string(), scrutinee_ty);
+        let goal = generalize(&expected, &escrutinee, &value);
+        let params = self.locals_in_order.clone();
+
+        let goal_for_body = goal.clone();
+        let body = try!(self.with_hidden_local(value.clone(), move |lcx, scrutinee| {
+            elaborate_equations(lcx, scrutinee, equations, Some(goal_for_body))
+        }));
+
+        let mut args = params.clone();
+        args.push(value);
+
+        let name = self.cx.auxiliary_name("with");
+        let body = core::Term::abstract_lambda(args.clone(), body);
+        let ty = core::Term::abstract_pi(args.clone(), goal);
+        let (body, ty) = try!(self.cx.ty_cx.type_check_term(&body, Some(ty)));
+
+        let aux = core::Function {
+            name: name.clone(),
+            args: args,
+            ty: ty,
+            body: body,
+            reduction: core::DeltaReduction::Reducible,
+        };
+
+        try!(self.cx.ty_cx.declare_def(&aux));
+        self.cx.auxiliary.push(core::Item::Fn(aux));
+
+        let mut call_args: Vec<_> = params.iter().map(core::Name::to_term).collect();
+        call_args.push(escrutinee);
+
+        Ok(core::Term::apply_all(name.to_term(), call_args))
     }
 
     fn elaborate_ctor(&mut self,
@@ -375,21 +1578,151 @@ impl<'ecx> LocalElabCx<'ecx> {
         Ok((ename, ety))
     }
 
+    /// Elaborate an equation `| C x y => rhs` of the function `fun` defined
+    /// along with an inductive type, `fn_ty` is the type of the function
+    /// with the type's `parameters` in scope.
+    ///
+    /// The right hand side is abstracted over the parameters and the
+    /// constructor's fields.
+    fn elaborate_equation(&mut self,
+                          fun: &core::Name,
+                          parameters: &Vec<core::Name>,
+                          ctors: &Vec<core::Constructor>,
+                          fn_ty: &core::Term,
+                          case: ast::Case)
+                          -> Result<(core::Name, core::Term), Error> {
+        let ast::Case { span, pattern, rhs, .. } = case;
+
+        let (ctor_name, patterns) = match pattern {
+            ast::Pattern::Constructor(n, ps) => (n, ps),
+            _ => return Err(Error::MalformedEquation(span)),
+        };
+
+        let ctor = match self.cx.globals.get(&ctor_name) {
+            None => return Err(Error::UnknownVariable(ctor_name)),
+            Some(n) => n.clone(),
+        };
+
+        let mut ty = match ctors.iter().find(|c| c.0 == ctor) {
+            None => return Err(Error::MalformedEquation(span)),
+            Some(c) => c.1.clone(),
+        };
+
+        for param in parameters {
+            ty = match ty {
+                core::Term::Forall { term, .. } => term.instantiate(&param.to_term()),
+                _ => return Err(Error::MalformedEquation(span)),
+            };
+        }
+
+        // Bind a local for each of the constructor's fields.
+        let mut fields = self.open_telescope();
+        let mut patterns = patterns.into_iter();
+
+        while let core::Term::Forall { binder, term, .. } = ty {
+            let local = match patterns.next() {
+                Some(ast::Pattern::Name(name)) => {
+                    let repr = match name.repr {
+                        ast::NameKind::Unqualified(ref s) => s.clone(),
+                        _ => return Err(Error::MalformedEquation(span)),
+                    };
+
+                    let local = self.cx.ty_cx.local_with_repr(repr, *binder.ty);
+                    self.bind_local(&mut fields, Some(name), local.clone());
+                    local
+                }
+                Some(ast::Pattern::Placeholder) => {
+                    let local = self.cx.ty_cx.local_with_repr("_".to_string(), *binder.ty);
+                    self.bind_local(&mut fields, None, local.clone());
+                    local
+                }
+                _ => return Err(Error::MalformedEquation(span)),
+            };
+
+            ty = term.instantiate(&local.to_term());
+        }
+
+        if patterns.next().is_some() {
+            return Err(Error::MalformedEquation(span));
+        }
+
+        // The equation has the type of the function applied to the indices
+        // of the constructor's type and the constructor itself.
+        let mut args = parameters.clone();
+        args.extend(fields.locals().iter().cloned());
+
+        let ctor_app = core::Term::apply_all(
+            ctor.to_term(),
+            args.iter().map(|a| a.to_term()).collect());
+
+        let (_, ty_args) = ty.uncurry();
+        // The value of the type follows its parameters and indices among
+        // the arguments of the function.
+        let major = ty_args.len();
+        let mut expected = fn_ty.clone();
+
+        for index in ty_args.into_iter().skip(parameters.len()) {
+            expected = match expected {
+                core::Term::Forall { term, .. } => term.instantiate(&index),
+                _ => return Err(Error::MalformedEquation(span)),
+            };
+        }
+
+        let expected = match expected {
+            core::Term::Forall { term, .. } => term.instantiate(&ctor_app),
+            _ => return Err(Error::MalformedEquation(span)),
+        };
+
+        let erhs = try!(self.elaborate_term_against(rhs, Some(expected.clone())));
+
+        let fields = self.close_telescope(fields);
+
+        let body = core::Term::abstract_lambda(args.clone(), erhs);
+        let ty = core::Term::abstract_pi(args, expected);
+        let (body, _) = try!(self.cx.ty_cx.type_check_term(&body, Some(ty)));
+
+        if !core::RecursiveFn::is_structural(fun, major, parameters.len(), fields.len(), &body) {
+            return Err(Error::NotStructural(span, fun.clone()));
+        }
+
+        Ok((ctor, body))
+    }
+
     pub fn apply_implicit_args(&mut self, term: core::Term) -> Result<core::Term, Error> {
         let mut fun_ty =
             try!(self.cx.ty_cx.type_infer_term(&term)).0;
 
+        let span = term.get_span();
+        let fun = match term.head() {
+            Some(core::Term::Var { ref name }) => format!("`{}`", name),
+            _ => "this term".to_string(),
+        };
+
         let mut result = term;
 
         while let core::Term::Forall { binder, term, .. } = fun_ty {
-            if binder.is_implicit() {
+            // Instance arguments are metavariables too, the solver finds
+            // them by instance search.
+            if binder.is_implicit() || binder.is_instance() {
+                let kind = if binder.is_instance() { "instance" } else { "implicit" };
+                let origin = if binder.name.is_placeholder() {
+                    format!("{} argument of {}", kind, fun)
+                } else {
+                    format!("{} argument `{}` of {}", kind, binder.name, fun)
+                };
                 let implicit_arg =
-                    try!(self.implicit_argument(*binder.ty));
+                    try!(self.implicit_argument(*binder.ty, span, origin));
                 // It is important any time we do an application to simulate it
                 // at the type level by instantiating the body of the type,
                 // if not this results in constraints that are not subst.
                 // correctly.
                 fun_ty = term.instantiate(&implicit_arg);
+
+                if self.cx.session().indexing() {
+                    self.cx.ty_cx.index.record_hint(
+                        span, HintKind::ImplicitArgument, implicit_arg.clone());
+                }
+
                 result = core::Term::apply(result, implicit_arg);
             } else {
                 break;
@@ -400,58 +1733,61 @@ impl<'ecx> LocalElabCx<'ecx> {
     }
 
     pub fn elaborate_term(&mut self, term: ast::Term) -> Result<core::Term, Error> {
-        debug!("elaborate_term: term={:?}", term);
+        self.elaborate_term_against(term, None)
+    }
+
+    /// Elaborate `term` knowing that it must have the type `expected`.
+    ///
+    /// The expected type flows into the bodies of lambdas and the arms of
+    /// a `match`, where it becomes the motive of the eliminator instead of
+    /// being left for unification to guess.
+    pub fn elaborate_term_against(&mut self,
+                                  term: ast::Term,
+                                  expected: Option<core::Term>)
+                                  -> Result<core::Term, Error> {
+        debug!("elaborate_term: term={:?} expected={:?}", term, expected);
+
+        if self.cx.session().indexing() {
+            let locals = self.locals_in_order.clone();
+            self.cx.ty_cx.index.record_scope(term.get_span(), locals);
+        }
 
         match term {
             ast::Term::Literal { span, lit } => {
                 panic!()
             }
-            ast::Term::Var { name, .. } => {
-                self.elaborate_name(name)
+            ast::Term::Var { name, implicit } => {
+                // A routine may also elaborate a global that is not applied.
+                let head = ast::Term::Var { name: name.clone(), implicit: implicit };
+
+                match try!(self.elaborate_with_routine(&head, &[], name.span, expected)) {
+                    Some(term) => Ok(term),
+                    None => self.elaborate_name(name),
+                }
             }
             ast::Term::Match { scrutinee, cases, span } => {
-                elaborate_pattern_match(self, *scrutinee, cases)
+                elaborate_pattern_match(self, *scrutinee, cases, expected)
             }
             app @ ast::Term::App { .. } => {
                 let span = app.get_span();
                 let (head, args) = app.uncurry();
 
-                let implicit = match &head {
-                    &ast::Term::Var { implicit, .. } => implicit,
-                    _ => true,
-                };
-
-                let efun = try!(self.elaborate_term(head));
-
-                let mut eargs = vec![];
-
-                for arg in args {
-                    let earg = try!(self.elaborate_term(arg));
-                    eargs.push(try!(self.apply_implicit_args(earg)));
+                match try!(self.elaborate_with_routine(&head, &args, span, expected)) {
+                    Some(term) => Ok(term),
+                    None => self.elaborate_app(head, args, span),
                 }
-
-                let efun = if implicit {
-                    try!(self.apply_implicit_args(efun))
-                } else {
-                    efun
-                };
-
-                let mut app = core::Term::apply_all(efun, eargs);
-
-                app.set_span(span);
-
-                Ok(app)
             }
             ast::Term::Forall { binders, term, .. } => {
-                self.enter_scope(binders, move |lcx, locals| {
+                self.with_telescope(binders, move |lcx, telescope| {
                     let term = try!(lcx.elaborate_term(*term));
-                    Ok(core::Term::abstract_pi(locals, term))
+                    Ok(telescope.pi(term))
                 })
             }
             ast::Term::Lambda { args, body, .. } => {
-                self.enter_scope(args, move |lcx, locals| {
-                    let ebody = try!(lcx.elaborate_term(*body));
-                    Ok(core::Term::abstract_lambda(locals, ebody))
+                self.with_telescope(args, move |lcx, telescope| {
+                    let body_ty = expected.and_then(|ty| instantiate_binders(ty, telescope.locals()));
+                    let ebody = try!(lcx.elaborate_term_against(*body, body_ty));
+                    Ok(telescope.lambda(ebody))
                 })
             }
             ast::Term::Let { bindings, body, span } => {
@@ -467,17 +1803,117 @@ impl<'ecx> LocalElabCx<'ecx> {
                 //     terms.push(term);
                 // }
                 //
-                // self.enter_scope(binders, move |lcx, locals| {
+                // self.with_telescope(binders, move |lcx, telescope| {
                 //     let ebody = try!(lcx.elaborate_term(*body));
                 //     let lambda = core::Term::abstract_lambda(locals, ebody);
                 //     Ok(core::Term::apply_all(lambda, terms))
                 // })
                 panic!("let bindings can not be elaborated")
             },
+            ast::Term::Fix { span, name, args, ret_ty, rec_arg, body } => {
+                self.with_telescope(args, move |lcx, telescope| {
+                    let args = telescope.locals().to_vec();
+                    let position = lcx.locals
+                                      .get(&rec_arg)
+                                      .and_then(|local| args.iter().position(|arg| arg == local));
+
+                    let rec_arg = match position {
+                        Some(position) => position,
+                        None => return Err(Error::FixArgument(rec_arg)),
+                    };
+
+                    let ret_ty = try!(lcx.elaborate_term(*ret_ty));
+                    let ty = telescope.pi(ret_ty.clone());
+
+                    let repr = match name.repr {
+                        ast::NameKind::Unqualified(ref s) => s.clone(),
+                        _ => "_".to_string(),
+                    };
+
+                    // The function is in scope in its own body.
+                    let fix = lcx.cx.ty_cx.local_with_repr_and_mode(
+                        repr, ty.clone(), core::BindingMode::Explicit);
+                    lcx.bind_local(telescope, Some(name), fix.clone());
+
+                    let ebody = try!(lcx.elaborate_term_against(*body, Some(ret_ty)));
+                    let body = core::Term::abstract_lambda(args, ebody);
+
+                    let (binder, body) = match core::Term::abstract_lambda(vec![fix], body) {
+                        core::Term::Lambda { binder, body, .. } => (binder, body),
+                        _ => unreachable!(),
+                    };
+
+                    Ok(core::Term::Fix {
+                        span: span,
+                        binder: binder,
+                        rec_arg: rec_arg,
+                        body: body,
+                    })
+                })
+            }
+            ast::Term::Quote { span, term } => {
+                let reflected = try!(quote::quote(*term, span));
+                self.elaborate_term_against(reflected, expected)
+            }
+            ast::Term::Antiquote { span, .. } => Err(Error::AntiquoteOutsideQuote(span)),
             ast::Term::Type => Ok(core::Term::Type),
         }
     }
 
+    /// Elaborate `head` applied to `args`, inserting implicit arguments.
+    pub fn elaborate_app(&mut self,
+                         head: ast::Term,
+                         args: Vec<ast::Term>,
+                         span: ast::Span) -> Result<core::Term, Error> {
+        let implicit = match &head {
+            &ast::Term::Var { implicit, .. } => implicit,
+            _ => true,
+        };
+
+        let efun = try!(self.elaborate_term(head));
+
+        let mut eargs = vec![];
+        for arg in args {
+            let earg = try!(self.elaborate_term(arg));
+            eargs.push(try!(self.apply_implicit_args(earg)));
+        }
+
+        let efun = if implicit {
+            try!(self.apply_implicit_args(efun))
+        } else {
+            efun
+        };
+
+        let mut app = core::Term::apply_all(efun, eargs);
+        app.set_span(span);
+        Ok(app)
+    }
+
+    /// Hand an application of a global marked `@[elab_as routine]` to its
+    /// routine, `None` if there is no routine or it declined.
+    fn elaborate_with_routine(&mut self,
+                              head: &ast::Term,
+                              args: &[ast::Term],
+                              span: ast::Span,
+                              expected: Option<core::Term>)
+                              -> Result<Option<core::Term>, Error> {
+        let global = match head {
+            &ast::Term::Var { ref name, .. } if !self.locals.contains_key(name) => {
+                self.cx.globals.get(name).cloned().or_else(|| to_qualified_name(name.clone()))
+            }
+            _ => None,
+        };
+
+        let routine = global
+            .and_then(|global| self.cx.ty_cx.elab_as.get(&global).cloned())
+            .and_then(|routine| self.cx.elab_routines.get(&routine).cloned());
+
+        match routine {
+            None => Ok(None),
+            Some(routine) => routine.elaborate(self, head, args, span, expected),
+        }
+    }
+
     fn elaborate_literal(&self, lit: ast::Literal) -> core::Term {
         panic!()
     }
@@ -485,11 +1921,20 @@ impl<'ecx> LocalElabCx<'ecx> {
     fn elaborate_name(&mut self, name: ast::Name) -> Result<core::Term, Error> {
         debug!("elaborate_name: name={}", name);
 
-        // Wish we had seme regions
-        let placeholder = match name.repr {
-            ast::NameKind::Placeholder => Some(try!(self.make_placeholder())),
-            _ => None,
-        };
+        // A placeholder never refers to a binder, even one named `_`, it
+        // is a metavariable that may depend on any local in scope.
+        if let ast::NameKind::Placeholder = name.repr {
+            let mut hole = try!(self.make_placeholder(name.span));
+            hole.set_span(name.span);
+
+            if self.cx.session().indexing() {
+                let context = self.locals_in_order.clone();
+                let kind = HoleKind::Placeholder(hole.clone());
+                self.cx.ty_cx.index.record_hole(name.span, context, kind);
+            }
+
+            return Ok(hole);
+        }
 
         // It is most likely to be a local
         let mut core_name = match self.locals.get(&name) {
@@ -500,8 +1945,9 @@ impl<'ecx> LocalElabCx<'ecx> {
                     // loading into the type context, if not this is an error.
                     None => {
                         match to_qualified_name(name.clone()) {
-                            None => placeholder.unwrap(),
+                            None => return Err(Error::UnknownVariable(name.clone())),
                             Some(ref core_name) if self.cx.ty_cx.in_scope(core_name) => {
+                                self.cx.used_globals.insert(core_name.clone());
                                 core_name.to_term()
                             }
                             Some(_) => {
@@ -515,6 +1961,16 @@ impl<'ecx> LocalElabCx<'ecx> {
             Some(local) => local.to_term(),
         };
 
+        if let &core::Term::Var { name: ref global @ core::Name::Qual { .. } } = &core_name {
+            try!(self.check_deprecated(&name, global));
+        }
+
+        if self.cx.session().indexing() {
+            if let &core::Term::Var { name: ref resolved } = &core_name {
+                self.cx.ty_cx.index.record_resolution(name.span, resolved.clone());
+            }
+        }
+
         // IMPORTANT!: Make sure we update the span here for the precise name being elaborated
         // we store how we choose to translate the name in the tables, but this results in
         // us using the first occurence of `name` s span everywhere for name.
@@ -523,25 +1979,55 @@ impl<'ecx> LocalElabCx<'ecx> {
         Ok(core_name)
     }
 
-    fn implicit_argument(&mut self, ty: core::Term) -> Result<core::Term, Error> {
-        self.meta_in_context(ty)
+    /// Warn about a reference to a deprecated global at the span of `name`.
+    fn check_deprecated(&self, name: &ast::Name, global: &core::Name) -> Result<(), Error> {
+        let deprecation = match self.cx.ty_cx.deprecated.get(global) {
+            None => return Ok(()),
+            Some(d) => d,
+        };
+
+        let mut diagnostic =
+            Diagnostic::warning(format!("use of deprecated `{}`", global))
+                .span(name.span);
+
+        if let Some(ref msg) = deprecation.message {
+            diagnostic = diagnostic.note(msg.clone());
+        }
+
+        if let Some(ref replacement) = deprecation.replacement {
+            diagnostic = diagnostic.help(format!("use `{}` instead", replacement));
+        }
+
+        try!(self.cx.session().lint(Lint::Deprecated, diagnostic));
+
+        Ok(())
     }
 
-    fn make_placeholder(&mut self) -> Result<core::Term, Error> {
-        let meta_no = self.cx.metavar_counter;
+    fn implicit_argument(&mut self, ty: core::Term, span: ast::Span, origin: String)
+                         -> Result<core::Term, Error> {
+        self.meta_in_context(ty, span, origin)
+    }
 
-        let meta_ty = core::Name::Meta {
-            number: meta_no,
-            ty: Box::new(core::Term::Type),
-        };
+    /// A metavariable standing for the `_` at `span`, its type is itself
+    /// a metavariable so both may mention the locals in scope.
+    fn make_placeholder(&mut self, span: ast::Span) -> Result<core::Term, Error> {
+        let ty = try!(self.meta_in_context(
+            core::Term::Type, span, "type of the placeholder".to_string()));
+        let hole = try!(self.meta_in_context(ty, span, "placeholder".to_string()));
 
-        self.cx.metavar_counter += 1;
+        if let Some(core::Term::Var { name: core::Name::Meta { number, .. } }) = hole.head() {
+            self.cx.session().register_placeholder(number, span);
+        }
 
-        self.meta_in_context(meta_ty.to_term())
+        Ok(hole)
     }
 
-    fn meta_in_context(&mut self, ty: core::Term) -> Result<core::Term, Error> {
-        let meta_no = self.cx.metavar_counter;
+    /// A metavariable of type `ty` applied to the locals in scope, created
+    /// at `span` to stand for what `origin` describes.
+    fn meta_in_context(&mut self, ty: core::Term, span: ast::Span, origin: String)
+                       -> Result<core::Term, Error> {
+        let meta_no = self.cx.session().next_meta_number();
+        self.cx.session().register_meta_origin(meta_no, span, origin);
 
         let ty =
             core::Term::abstract_pi(self.locals_in_order.clone(), ty);
@@ -557,7 +2043,7 @@ impl<'ecx> LocalElabCx<'ecx> {
             ty: Box::new(ty),
         };
 
-        self.cx.metavar_counter += 1;
+        self.cx.session().count(Counter::Metas, 1);
 
         Ok(core::Term::apply_all(meta.to_term(), args))
     }
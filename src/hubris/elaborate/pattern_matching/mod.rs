@@ -1,6 +1,9 @@
 use super::super::ast::{self};
 use super::super::core::{self, Term};
-use super::{LocalElabCx, Error};
+use super::super::index::HintKind;
+use super::super::session::HasSession;
+use super::{ElabCx, LocalElabCx, Error};
+use super::util::to_qualified_name;
 
 use std::collections::HashMap;
 
@@ -45,7 +48,15 @@ impl<'ecx, 'cx: 'ecx>PatternMatchCx<'ecx, 'cx> {
             };
 
             let local =
-                self.elab_cx.cx.ty_cx.local_with_repr_and_mode(repr, ty, core::BindingMode::Explicit);
+                self.elab_cx.cx.ty_cx.local_with_repr_and_mode(repr, ty.clone(), core::BindingMode::Explicit);
+
+            // Pattern variables are never annotated, so we show the type
+            // that was inferred for them.
+            if self.elab_cx.cx.session().indexing() {
+                let index = &mut self.elab_cx.cx.ty_cx.index;
+                index.record_definition(local.clone(), name.span);
+                index.record_hint(name.span, HintKind::BinderType, ty);
+            }
 
             self.elab_cx.locals.insert(name, local.clone());
             self.elab_cx.locals_in_order.push(local.clone());
@@ -62,7 +73,12 @@ impl<'ecx, 'cx: 'ecx>PatternMatchCx<'ecx, 'cx> {
     }
 
 
-    fn elaborate_simple_match(&mut self, simple_match: SimpleMatch) -> Result<core::Term, Error> {
+    /// Elaborate a match to an application of `cases_on`, if the type the
+    /// match must have is `expected` the motive is computed from it,
+    /// otherwise it is left as a metavariable.
+    fn elaborate_simple_match(&mut self,
+                              simple_match: SimpleMatch,
+                              expected: Option<core::Term>) -> Result<core::Term, Error> {
         let SimpleMatch {
             scrutinee,
             cases,
@@ -92,10 +108,33 @@ impl<'ecx, 'cx: 'ecx>PatternMatchCx<'ecx, 'cx> {
                     .into_iter()
                     .collect();
 
-        let cases : Vec<_> =
-            try!(cases.into_iter()
-                      .map(|c| self.elaborate_simple_case(c, &scrutinee_ty, &ctor_map))
-                      .collect());
+        let params: Vec<_> =
+            args.into_iter()
+                .take(datatype.parameters.len())
+                .collect();
+
+        // The motive abstracts the scrutinee out of the expected type, when
+        // the scrutinee is a variable the type of each arm can then mention
+        // the constructor it matched.
+        let motive = match expected {
+            None => None,
+            Some(expected) => {
+                let local = match escrutinee {
+                    Term::Var { name: ref name @ core::Name::Local { .. } } => name.clone(),
+                    _ => self.elab_cx.cx.ty_cx.local_with_repr_and_mode(
+                        "x".to_string(), scrutinee_ty.clone(), core::BindingMode::Explicit),
+                };
+
+                Some(Term::abstract_lambda(vec![local], expected))
+            }
+        };
+
+        let mut ecases = vec![];
+
+        for case in cases {
+            ecases.push(try!(self.elaborate_simple_case(
+                case, &scrutinee_ty, &datatype, &ctor_map, &params, motive.as_ref())));
+        }
 
         // let cases : Vec<_> =
         //     cases.into_iter()
@@ -107,9 +146,16 @@ impl<'ecx, 'cx: 'ecx>PatternMatchCx<'ecx, 'cx> {
          match pattern_type  {
              PatternType::Cases => {
                 let cases_on = inductive_ty.in_scope("cases_on".to_string()).unwrap();
-                let head = try!(self.elab_cx.apply_implicit_args(cases_on.to_term()));
+                let head = match motive {
+                    None => try!(self.elab_cx.apply_implicit_args(cases_on.to_term())),
+                    Some(motive) => {
+                        let mut implicit_args = params;
+                        implicit_args.push(motive);
+                        Term::apply_all(cases_on.to_term(), implicit_args)
+                    }
+                };
                 let mut args = vec![escrutinee];
-                args.extend(cases.into_iter());
+                args.extend(ecases.into_iter());
                 let result = Term::apply_all(head, args);
                 debug!("elaborated_match: {}", result);
                 Ok(result)
@@ -120,25 +166,30 @@ impl<'ecx, 'cx: 'ecx>PatternMatchCx<'ecx, 'cx> {
     fn simple_pattern_binders(&mut self,
                               simple_pattern: SimplePattern,
                               scrutinee_ty: &core::Term,
-                              ctor_map: &HashMap<core::Name, core::Term>) -> Result<Vec<(ast::Name, core::Term)>, Error> {
+                              datatype: &core::Data,
+                              ctor_map: &HashMap<core::Name, core::Term>)
+                              -> Result<(Option<core::Name>, Vec<(ast::Name, core::Term)>), Error> {
 
         match simple_pattern {
             SimplePattern::Name(n) => {
-                let elab_name = try!(self.elab_cx.cx.elaborate_global_name(n.clone()));
-
-                match ctor_map.get(&elab_name) {
-                    None => return Ok(vec![(n, scrutinee_ty.clone())]),
-                    Some(ctor_ty) => {
-                        // Need to do error checking here
-                        return Ok(vec![]);
-                    }
+                match constructor_of(self.elab_cx.cx, &n, datatype) {
+                    Some(ctor) => return Ok((Some(ctor), vec![])),
+                    // Only an unqualified name can bind a variable.
+                    None => match n.repr {
+                        ast::NameKind::Qualified(..) =>
+                            return Err(Error::UnknownConstructor(n, datatype.name.clone())),
+                        _ => return Ok((None, vec![(n, scrutinee_ty.clone())])),
+                    },
                 }
             }
             SimplePattern::Constructor(ctor, args) => {
-                let elab_name = try!(self.elab_cx.cx.elaborate_global_name(ctor.clone()));
+                let elab_name = match constructor_of(self.elab_cx.cx, &ctor, datatype) {
+                    None => return Err(Error::UnknownConstructor(ctor, datatype.name.clone())),
+                    Some(elab_name) => elab_name,
+                };
 
                 match ctor_map.get(&elab_name) {
-                    None => panic!("not the right"), // return Ok(vec![(ctor.clone(), scrutinee_ty.clone())]),
+                    None => unreachable!(),
                     Some(ctor_ty) => {
                         debug!("{:?}", ctor_ty.binders());
                         let (inductive_ty, i_args) = scrutinee_ty.uncurry();
@@ -164,7 +215,7 @@ impl<'ecx, 'cx: 'ecx>PatternMatchCx<'ecx, 'cx> {
                                                  (n, t.clone())
                                              }).collect();
 
-                        return Ok(binders);
+                        return Ok((Some(elab_name), binders));
                     }
                 }
             }
@@ -174,7 +225,10 @@ impl<'ecx, 'cx: 'ecx>PatternMatchCx<'ecx, 'cx> {
     fn elaborate_simple_case(&mut self,
                              simple_case: SimpleCase,
                              scrutinee_ty: &core::Term,
-                             ctor_map: &HashMap<core::Name, core::Term>) -> Result<core::Term, Error> {
+                             datatype: &core::Data,
+                             ctor_map: &HashMap<core::Name, core::Term>,
+                             params: &Vec<core::Term>,
+                             motive: Option<&core::Term>) -> Result<core::Term, Error> {
         let SimpleCase {
             pattern,
             rhs,
@@ -182,9 +236,10 @@ impl<'ecx, 'cx: 'ecx>PatternMatchCx<'ecx, 'cx> {
 
         debug!("pattern: {} rhs: {}", pattern, rhs);
 
-        let binders = try!(self.simple_pattern_binders(
+        let (ctor, binders) = try!(self.simple_pattern_binders(
             pattern,
             scrutinee_ty,
+            datatype,
             ctor_map));
 
         for &(ref n, ref ty) in &binders {
@@ -192,22 +247,105 @@ impl<'ecx, 'cx: 'ecx>PatternMatchCx<'ecx, 'cx> {
         }
 
         self.enter_pattern_scope(binders, move |pat_cx, names| {
+            // The arm has the type of the motive at the value it matched.
+            let expected = motive.map(|motive| {
+                let value = match ctor {
+                    Some(ctor) => {
+                        let mut args = params.clone();
+                        args.extend(names.iter().map(core::Name::to_term));
+                        Term::apply_all(ctor.to_term(), args)
+                    }
+                    None => names[0].to_term(),
+                };
+
+                match motive {
+                    &Term::Lambda { ref body, .. } => body.instantiate(&value),
+                    _ => Term::apply(motive.clone(), value),
+                }
+            });
+
             match rhs {
-                SimpleMatchArm::Term(rhs) =>
-                    Ok(Term::abstract_lambda(names, try!(pat_cx.elab_cx.elaborate_term(rhs)))),
+                SimpleMatchArm::Term(rhs) => {
+                    let erhs = try!(pat_cx.elab_cx.elaborate_term_against(rhs, expected));
+                    Ok(Term::abstract_lambda(names, erhs))
+                }
+                SimpleMatchArm::With(scrutinee, equations) => {
+                    let erhs = try!(pat_cx.elab_cx.elaborate_with(scrutinee, equations, expected));
+                    Ok(Term::abstract_lambda(names, erhs))
+                }
                 SimpleMatchArm::Match(mat) =>
-                    pat_cx.elaborate_simple_match(mat)
+                    pat_cx.elaborate_simple_match(mat, expected)
             }
         })
     }
 }
 
+/// The constructor of `datatype` that `name` refers to, if it refers to one.
+///
+/// A constructor may be written as it is declared, or qualified by the name
+/// of its type as in `List.Cons`.
+fn constructor_of(cx: &ElabCx, name: &ast::Name, datatype: &core::Data) -> Option<core::Name> {
+    let mut candidates = vec![];
+    candidates.extend(cx.globals.get(name).cloned());
+    candidates.extend(to_qualified_name(name.clone()));
+
+    if let ast::NameKind::Qualified(ref components) = name.repr {
+        if let Some((last, prefix)) = components.split_last() {
+            let type_name = core::Name::Qual {
+                span: name.span,
+                components: prefix.to_vec(),
+            };
+
+            if type_name == datatype.name {
+                candidates.push(core::Name::Qual {
+                    span: name.span,
+                    components: vec![last.clone()],
+                });
+            }
+        }
+    }
+
+    candidates.into_iter().find(|c| datatype.ctors.iter().any(|ctor| ctor.0 == *c))
+}
+
+/// The constructor of any inductive type in scope that `name` refers to.
+pub fn resolve_constructor(cx: &ElabCx, name: &ast::Name) -> Option<core::Name> {
+    cx.ty_cx.types
+      .values()
+      .filter_map(|datatype| constructor_of(cx, name, datatype))
+      .next()
+}
+
 pub fn elaborate_pattern_match<'ecx>(
         elab_cx: &mut LocalElabCx<'ecx>,
         scrutinee: ast::Term,
-        cases: Vec<ast::Case>) -> Result<Term, Error> {
+        cases: Vec<ast::Case>,
+        expected: Option<Term>) -> Result<Term, Error> {
+    let simplified_match = {
+        let cx = &*elab_cx.cx;
+        simplify_match(scrutinee, cases, &|n: &ast::Name| resolve_constructor(cx, n).is_some())
+    };
+
     let mut pmcx = PatternMatchCx::new(elab_cx);
-    let simplified_match = simplify_match(scrutinee, cases);
     debug!("simplified_match: {}", simplified_match);
-    pmcx.elaborate_simple_match(simplified_match)
+    pmcx.elaborate_simple_match(simplified_match, expected)
+}
+
+/// Elaborate equations matching on `scrutinee` to nested applications of
+/// `cases_on`, as is done for a match.
+pub fn elaborate_equations<'ecx>(
+        elab_cx: &mut LocalElabCx<'ecx>,
+        scrutinee: ast::Term,
+        equations: Vec<ast::Equation>,
+        expected: Option<Term>) -> Result<Term, Error> {
+    let simplified = {
+        let cx = &*elab_cx.cx;
+        simplify_equations(scrutinee,
+                           equations,
+                           &|n: &ast::Name| resolve_constructor(cx, n).is_some())
+    };
+
+    let mut pmcx = PatternMatchCx::new(elab_cx);
+    debug!("simplified_equations: {}", simplified);
+    pmcx.elaborate_simple_match(simplified, expected)
 }
@@ -30,6 +30,14 @@ impl<'v> VisitorMut<'v> for Renamer {
     }
 }
 
+pub fn rename_equation(rename_map: RenameMap, equation: &mut ast::Equation) {
+    let mut renamer = Renamer {
+        rename_map: rename_map,
+    };
+
+    renamer.visit_mut_equation(equation);
+}
+
 pub fn rename_term(rename_map: RenameMap, term: &mut ast::Term) {
     let mut renamer = Renamer {
         rename_map: rename_map,
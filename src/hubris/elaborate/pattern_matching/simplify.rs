@@ -1,5 +1,5 @@
 use super::super::super::ast::{self};
-use super::renamer::{rename_term, RenameMap};
+use super::renamer::{rename_equation, rename_term, RenameMap};
 
 use std::collections::HashMap;
 use std::fmt::{self, Display, Formatter};
@@ -15,6 +15,8 @@ pub enum PatternType {
 pub enum SimpleMatchArm {
     Match(SimpleMatch),
     Term(ast::Term),
+    /// The `with` of an equation, splitting on the value of the term.
+    With(ast::Term, Vec<ast::Equation>),
 }
 
 impl SimpleMatchArm {
@@ -29,6 +31,13 @@ impl SimpleMatchArm {
                 rename_term(rename_map.clone(), &mut t);
                 Term(t)
             }
+            With(mut t, mut equations) => {
+                rename_term(rename_map.clone(), &mut t);
+                for equation in &mut equations {
+                    rename_equation(rename_map.clone(), equation);
+                }
+                With(t, equations)
+            }
         }
     }
 }
@@ -39,7 +48,12 @@ impl Pretty for SimpleMatchArm {
 
         match self {
             &Match(ref m) => m.pretty(),
-            &Term(ref t) => t.pretty()
+            &Term(ref t) => t.pretty(),
+            &With(ref t, ref equations) => {
+                let equations: Vec<_> = equations.iter().map(|eq| eq.pretty()).collect();
+                "with ".pretty() + t.pretty() + "\n".pretty() +
+                    seperate(&equations[..], &"\n".pretty()) + "\nend".pretty()
+            }
         }
     }
 }
@@ -136,12 +150,44 @@ impl Display for SimplePattern {
     }
 }
 
-pub fn simplify_match(scrutinee: ast::Term, cases: Vec<ast::Case>) -> SimpleMatch {
+/// Simplify a match into one whose patterns are not nested, `is_constructor`
+/// tells nullary constructors apart from pattern variables.
+pub fn simplify_match(scrutinee: ast::Term,
+                      cases: Vec<ast::Case>,
+                      is_constructor: &Fn(&ast::Name) -> bool) -> SimpleMatch {
+    let arms = cases.into_iter()
+                    .map(|case| (case.pattern, SimpleMatchArm::Term(case.rhs)))
+                    .collect();
+
+    simplify_arms(scrutinee, arms, is_constructor)
+}
+
+/// Simplify equations matching on `scrutinee` as `simplify_match` does the
+/// cases of a match, the `with` of an equation is kept as its arm.
+pub fn simplify_equations(scrutinee: ast::Term,
+                          equations: Vec<ast::Equation>,
+                          is_constructor: &Fn(&ast::Name) -> bool) -> SimpleMatch {
+    let arms = equations.into_iter()
+                        .map(|equation| {
+                            let rhs = match equation.rhs {
+                                ast::EquationRhs::Term(rhs) => SimpleMatchArm::Term(rhs),
+                                ast::EquationRhs::With(scrutinee, equations) =>
+                                    SimpleMatchArm::With(scrutinee, equations),
+                            };
+                            (equation.pattern, rhs)
+                        })
+                        .collect();
+
+    simplify_arms(scrutinee, arms, is_constructor)
+}
+
+fn simplify_arms(scrutinee: ast::Term,
+                 arms: Vec<(ast::Pattern, SimpleMatchArm)>,
+                 is_constructor: &Fn(&ast::Name) -> bool) -> SimpleMatch {
     let mut simple_cases = vec![];
 
-    for case in cases {
-        let rhs = SimpleMatchArm::Term(case.rhs);
-        simple_cases.push(simplify_pattern(case.pattern, rhs));
+    for (pattern, rhs) in arms {
+        simple_cases.push(simplify_pattern(pattern, rhs));
     }
 
     let simple_match = SimpleMatch {
@@ -150,7 +196,7 @@ pub fn simplify_match(scrutinee: ast::Term, cases: Vec<ast::Case>) -> SimpleMatc
         pattern_type: PatternType::Cases,
     };
 
-    let simple_match = match condense(SimpleMatchArm::Match(simple_match)) {
+    let simple_match = match condense(SimpleMatchArm::Match(simple_match), is_constructor) {
         SimpleMatchArm::Match(m) => m,
         _ => panic!("condensing a match should result in at least one match")
     };
@@ -158,9 +204,12 @@ pub fn simplify_match(scrutinee: ast::Term, cases: Vec<ast::Case>) -> SimpleMatc
     simple_match
 }
 
-pub fn condense(simple_match: SimpleMatchArm) -> SimpleMatchArm {
+pub fn condense(simple_match: SimpleMatchArm,
+                is_constructor: &Fn(&ast::Name) -> bool) -> SimpleMatchArm {
     match simple_match {
         SimpleMatchArm::Term(rhs) => SimpleMatchArm::Term(rhs),
+        SimpleMatchArm::With(scrutinee, equations) =>
+            SimpleMatchArm::With(scrutinee, equations),
         SimpleMatchArm::Match(simple_match) => {
             let SimpleMatch {
                 scrutinee,
@@ -170,39 +219,31 @@ pub fn condense(simple_match: SimpleMatchArm) -> SimpleMatchArm {
 
             // This handles the case in which the simplification pass has generated
             // a simple match like `match a with | b => rhs`, we just simplify to
-            // rhs.
+            // rhs. A variable pattern always matches, a nullary constructor must
+            // still be checked.
             if cases.len() == 1 {
-                let case = cases[0].clone();
-                match case.pattern {
-                    SimplePattern::Name(n) => {
+                if let SimplePattern::Name(ref n) = cases[0].pattern {
+                    if !is_constructor(n) {
                         let mut name_map = HashMap::new();
-                        name_map.insert(n, scrutinee.clone());
-                        condense(case.rhs).rename(&name_map)
-                    }
-                    _ => {
-                        let cases =
-                            cases.into_iter()
-                                 .map(|mut case| {
-                                     case.rhs = condense(case.rhs);
-                                     case
-                                 })
-                                 .collect();
-
-                        SimpleMatchArm::Match(SimpleMatch {
-                            scrutinee: scrutinee,
-                            cases: cases,
-                            pattern_type: pattern_type,
-                        })
+                        name_map.insert(n.clone(), scrutinee.clone());
+                        return condense(cases[0].rhs.clone(), is_constructor).rename(&name_map);
                     }
                 }
-            } else {
-                let new_cases = vec![];
-                SimpleMatchArm::Match(SimpleMatch {
-                    scrutinee: scrutinee,
-                    cases: new_cases,
-                    pattern_type: PatternType::Cases,
-                })
             }
+
+            let cases =
+                cases.into_iter()
+                     .map(|mut case| {
+                         case.rhs = condense(case.rhs, is_constructor);
+                         case
+                     })
+                     .collect();
+
+            SimpleMatchArm::Match(SimpleMatch {
+                scrutinee: scrutinee,
+                cases: cases,
+                pattern_type: pattern_type,
+            })
         }
     }
 }
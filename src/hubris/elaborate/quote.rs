@@ -0,0 +1,129 @@
+//! Quotation, `` `(t) `` is the syntax of `t` as a value of `Expr`, the
+//! reflected syntax type declared in the `Reflect` module of the library.
+//!
+//! A quotation is rewritten to applications of the constructors of `Expr`
+//! before it is elaborated, these must be in scope along with `Nat`, `List`,
+//! and the helpers `bit0` and `bit1`. Inside a quotation `$x` and `$(t)`
+//! splice in a term that is already of type `Expr`.
+//!
+//! A name is reflected as the list of the character codes of its text,
+//! each written in binary with `bit0` and `bit1` to keep quotations small.
+
+use ast::{self, Span, Term};
+
+use super::Error;
+
+fn global(components: &[&str], span: Span, implicit: bool) -> Term {
+    let mut name = if components.len() == 1 {
+        ast::Name::from_str(components[0])
+    } else {
+        ast::Name::qualified(components.iter().map(|c| c.to_string()).collect())
+    };
+
+    name.span = span;
+    Term::Var { name: name, implicit: implicit }
+}
+
+fn constructor(name: &str, span: Span) -> Term {
+    global(&["Expr", name], span, true)
+}
+
+fn apply(fun: Term, args: Vec<Term>, span: Span) -> Term {
+    args.into_iter().fold(fun, |fun, arg| {
+        Term::App {
+            span: span,
+            fun: Box::new(fun),
+            arg: Box::new(arg),
+        }
+    })
+}
+
+/// `n` in binary, the leading bit is always one so it is `S Z` and each
+/// following bit doubles the number with `bit0` or `bit1`.
+fn reflect_nat(n: u32, span: Span) -> Term {
+    if n == 0 {
+        return global(&["Z"], span, true);
+    }
+
+    let bits = 32 - n.leading_zeros();
+    let one = apply(global(&["S"], span, true), vec![global(&["Z"], span, true)], span);
+
+    (0..bits - 1).rev().fold(one, |nat, i| {
+        let bit = if (n >> i) & 1 == 1 { "bit1" } else { "bit0" };
+        apply(global(&[bit], span, true), vec![nat], span)
+    })
+}
+
+fn reflect_name(name: &ast::Name, span: Span) -> Term {
+    let text = match name.repr {
+        ast::NameKind::Unqualified(ref s) => s.clone(),
+        ast::NameKind::Qualified(ref components) => components.join("."),
+        ast::NameKind::Placeholder => "_".to_string(),
+    };
+
+    let nat = global(&["Nat"], span, true);
+    let nil = apply(global(&["Nil"], span, false), vec![nat.clone()], span);
+
+    text.chars().rev().fold(nil, |list, c| {
+        let code = reflect_nat(c as u32, span);
+        apply(global(&["Cons"], span, false), vec![nat.clone(), code, list], span)
+    })
+}
+
+/// The term building the reflected syntax of `term`, which was quoted at
+/// `span`.
+pub fn quote(term: Term, span: Span) -> Result<Term, Error> {
+    match term {
+        Term::Var { name, .. } => match name.repr {
+            ast::NameKind::Placeholder => Ok(constructor("hole", span)),
+            _ => Ok(apply(constructor("var", span), vec![reflect_name(&name, span)], span)),
+        },
+        Term::App { fun, arg, .. } => {
+            let fun = try!(quote(*fun, span));
+            let arg = try!(quote(*arg, span));
+            Ok(apply(constructor("app", span), vec![fun, arg], span))
+        }
+        Term::Forall { binders, term, .. } => {
+            let body = try!(quote(*term, span));
+            quote_binders("pi", binders, body, span)
+        }
+        Term::Lambda { span: lambda_span, args, ret_ty, body } => {
+            if ret_ty.is_some() {
+                return Err(Error::CanNotQuote(lambda_span, "a `fun` with a return type"));
+            }
+
+            let body = try!(quote(*body, span));
+            quote_binders("lam", args, body, span)
+        }
+        Term::Type => Ok(constructor("sort", span)),
+        Term::Antiquote { term, .. } => Ok(*term),
+        Term::Quote { span, .. } => Err(Error::CanNotQuote(span, "a quotation")),
+        Term::Match { span, .. } => Err(Error::CanNotQuote(span, "a `match`")),
+        Term::Let { span, .. } => Err(Error::CanNotQuote(span, "a `let`")),
+        Term::Fix { span, .. } => Err(Error::CanNotQuote(span, "a `fix`")),
+        Term::Literal { span, .. } => Err(Error::CanNotQuote(span, "a literal")),
+    }
+}
+
+/// Wrap `body` in a `lam` or `pi` for each name bound by `binders`, a binder
+/// without a type is given a hole.
+fn quote_binders(ctor: &str, binders: Vec<ast::Binder>, body: Term, span: Span)
+                 -> Result<Term, Error> {
+    let mut bound = vec![];
+
+    // The names of a binder are stored in reverse.
+    for binder in binders {
+        let ty = match binder.ty {
+            None => constructor("hole", span),
+            Some(ty) => try!(quote(ty, span)),
+        };
+
+        for name in binder.names.into_iter().rev() {
+            bound.push((name, ty.clone()));
+        }
+    }
+
+    Ok(bound.into_iter().rev().fold(body, |body, (name, ty)| {
+        apply(constructor(ctor, span), vec![reflect_name(&name, span), ty, body], span)
+    }))
+}
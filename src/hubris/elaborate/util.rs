@@ -13,3 +13,43 @@ pub fn to_qualified_name(name: ast::Name) -> Option<core::Name> {
         span: name.span,
     })
 }
+
+/// Replace each occurrence of `value` in `term` by the local `local`.
+pub fn generalize(term: &core::Term, value: &core::Term, local: &core::Name) -> core::Term {
+    use core::Term::*;
+
+    if term == value {
+        return local.to_term();
+    }
+
+    match term {
+        &App { ref fun, ref arg, span } => App {
+            fun: Box::new(generalize(fun, value, local)),
+            arg: Box::new(generalize(arg, value, local)),
+            span: span,
+        },
+        &Forall { ref binder, ref term, span } => Forall {
+            binder: binder.with_type(generalize(&binder.ty, value, local)),
+            term: Box::new(generalize(term, value, local)),
+            span: span,
+        },
+        &Lambda { ref binder, ref body, span } => Lambda {
+            binder: binder.with_type(generalize(&binder.ty, value, local)),
+            body: Box::new(generalize(body, value, local)),
+            span: span,
+        },
+        &Let { ref binder, value: ref bound, ref body, span } => Let {
+            binder: binder.with_type(generalize(&binder.ty, value, local)),
+            value: Box::new(generalize(bound, value, local)),
+            body: Box::new(generalize(body, value, local)),
+            span: span,
+        },
+        &Fix { ref binder, rec_arg, ref body, span } => Fix {
+            binder: binder.with_type(generalize(&binder.ty, value, local)),
+            rec_arg: rec_arg,
+            body: Box::new(generalize(body, value, local)),
+            span: span,
+        },
+        &Var { .. } | &Type => term.clone(),
+    }
+}
@@ -0,0 +1,149 @@
+//! Section variables, declared with `variables (A : Type) ...`.
+//!
+//! A variable becomes a parameter of each later definition that mentions
+//! it, either directly or through the type of another variable it needs.
+//! Within the definition the variables are fixed, so a recursive reference
+//! is applied to the explicit ones.
+
+use ast::{self, Term};
+use syntax::visit::*;
+
+use std::collections::BTreeSet;
+
+/// Collects the names referred to by terms.
+struct Mentions {
+    names: BTreeSet<ast::Name>,
+}
+
+impl<'v> Visitor<'v> for Mentions {
+    fn visit_term(&mut self, term: &'v Term) {
+        if let &Term::Var { ref name, .. } = term {
+            self.names.insert(name.clone());
+        }
+
+        walk_term(self, term)
+    }
+}
+
+/// Applies references to a definition to the variables it was given.
+struct ApplyVariables<'a> {
+    name: &'a ast::Name,
+    /// The variables the elaborator fills in, implicit and instance, and
+    /// the explicit ones, only the explicit ones are passed unless the
+    /// reference is written `@f`.
+    implicit: &'a [ast::Name],
+    explicit: &'a [ast::Name],
+}
+
+impl<'a, 'v> VisitorMut<'v> for ApplyVariables<'a> {
+    fn visit_mut_term(&mut self, term: &'v mut Term) {
+        let applied = match *term {
+            Term::Var { ref name, implicit } if name == self.name => {
+                // `@f` takes every argument explicitly.
+                let args: Vec<_> = if implicit {
+                    self.explicit.to_vec()
+                } else {
+                    self.implicit.iter().chain(self.explicit.iter()).cloned().collect()
+                };
+
+                let fun = Term::Var { name: name.clone(), implicit: implicit };
+
+                Some(args.into_iter().fold(fun, |fun, arg| {
+                    Term::App {
+                        span: name.span,
+                        fun: Box::new(fun),
+                        arg: Box::new(Term::Var { name: arg, implicit: true }),
+                    }
+                }))
+            }
+            _ => None,
+        };
+
+        match applied {
+            Some(applied) => *term = applied,
+            None => walk_mut_term(self, term),
+        }
+    }
+}
+
+/// Split binders into one binder per name, in the order the names are
+/// bound.
+fn split_binders(binders: &[ast::Binder]) -> Vec<ast::Binder> {
+    let mut split = vec![];
+
+    // The names of a binder are stored in reverse.
+    for binder in binders {
+        for name in binder.names.iter().rev() {
+            split.push(ast::Binder {
+                span: binder.span,
+                names: vec![name.clone()],
+                ty: binder.ty.clone(),
+                mode: binder.mode.clone(),
+                relevance: binder.relevance,
+            });
+        }
+    }
+
+    split
+}
+
+/// Add the variables `def` needs to the front of its parameters.
+pub fn add_variables(variables: &[ast::Binder], mut def: ast::Def) -> ast::Def {
+    if variables.is_empty() {
+        return def;
+    }
+
+    let mut mentions = Mentions { names: BTreeSet::new() };
+
+    for arg in &def.args {
+        mentions.visit_binder(arg);
+    }
+
+    mentions.visit_term(&def.ty);
+    walk_def_body(&mut mentions, &def.body);
+
+    // A parameter of the definition shadows a variable of the same name.
+    let bound: BTreeSet<_> = def.args.iter().flat_map(|b| b.names.iter().cloned()).collect();
+
+    // A variable may only depend on those declared before it, so walking
+    // backwards picks up everything the needed variables mention.
+    let mut needed = vec![];
+    for var in split_binders(variables).into_iter().rev() {
+        let name = var.names[0].clone();
+        if !bound.contains(&name) && mentions.names.contains(&name) {
+            if let Some(ref ty) = var.ty {
+                mentions.visit_term(ty);
+            }
+            needed.push(var);
+        }
+    }
+
+    needed.reverse();
+
+    if needed.is_empty() {
+        return def;
+    }
+
+    let names = |explicit: bool| -> Vec<ast::Name> {
+        needed.iter()
+              .filter(|v| (v.mode == ast::BindingMode::Explicit) == explicit)
+              .map(|v| v.names[0].clone())
+              .collect()
+    };
+
+    let implicit = names(false);
+    let explicit = names(true);
+
+    {
+        let mut apply = ApplyVariables {
+            name: &def.name,
+            implicit: &implicit,
+            explicit: &explicit,
+        };
+
+        walk_mut_def_body(&mut apply, &mut def.body);
+    }
+
+    def.args = needed.iter().cloned().chain(def.args.into_iter()).collect();
+    def
+}
@@ -0,0 +1,58 @@
+//! Unstable language features.
+//!
+//! Features are disabled by default and can be enabled from the command line
+//! with `-Z <feature>`, programs using a disabled feature are rejected.
+
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// Defining a function simultaneously with an inductive type.
+    InductionRecursion,
+}
+
+pub const ALL_FEATURES: &'static [Feature] = &[
+    Feature::InductionRecursion,
+];
+
+impl Feature {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Feature::InductionRecursion => "induction-recursion",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Feature> {
+        ALL_FEATURES.iter().cloned().find(|f| f.name() == s)
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(formatter, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Features {
+    enabled: HashSet<Feature>,
+}
+
+impl Features {
+    /// Enable the feature called `name`, returning `false` if there is no
+    /// such feature.
+    pub fn enable(&mut self, name: &str) -> bool {
+        match Feature::from_str(name) {
+            None => false,
+            Some(feature) => {
+                self.enabled.insert(feature);
+                true
+            }
+        }
+    }
+
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        self.enabled.contains(&feature)
+    }
+}
@@ -0,0 +1,105 @@
+//! Reporting of internal compiler errors.
+//!
+//! A panic that escapes a phase of the compiler is a bug in the compiler, not
+//! in the program being compiled. Instead of letting the raw Rust panic reach
+//! the user it is caught and reported along with the compiler's version, the
+//! definition being processed and the last few events the session traced.
+
+use super::diagnostic::{Diagnostic, Level};
+use super::session::{Reportable, Session};
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::io;
+use std::panic::{self, AssertUnwindSafe, PanicInfo};
+
+const ISSUE_TRACKER: &'static str = "https://github.com/hubris-lang/hubris/issues";
+
+thread_local! {
+    /// The message and location of the last panic on this thread, set by the
+    /// hook installed in `catch_panics`.
+    static LAST_PANIC: RefCell<Option<(String, Option<String>)>> = RefCell::new(None)
+}
+
+#[derive(Clone, Debug)]
+pub struct InternalError {
+    /// The message the compiler panicked with.
+    pub message: String,
+    /// The file and line of the compiler's source that panicked.
+    pub location: Option<String>,
+    /// The definition being processed when the compiler panicked.
+    pub definition: Option<String>,
+    /// The most recent trace events, oldest first.
+    pub trace: Vec<String>,
+}
+
+impl Reportable for InternalError {
+    fn report(self, session: &Session) -> io::Result<()> {
+        let mut diagnostic = Diagnostic::new(Level::InternalError, self.message)
+            .note(format!("hubris {}", env!("CARGO_PKG_VERSION")));
+
+        if let Some(location) = self.location {
+            diagnostic = diagnostic.note(format!("the compiler panicked at {}", location));
+        }
+
+        if let Some(definition) = self.definition {
+            diagnostic = diagnostic.note(format!("while processing the definition `{}`", definition));
+        }
+
+        if !self.trace.is_empty() {
+            let mut events = "the last events before the panic were:".to_string();
+            for event in &self.trace {
+                events.push_str(&format!("\n    {}", event));
+            }
+            diagnostic = diagnostic.note(events);
+        }
+
+        diagnostic
+            .help(format!("this is a bug in the compiler, please file an issue at {} \
+                           including the input that triggered it",
+                          ISSUE_TRACKER))
+            .emit(session)
+    }
+}
+
+fn payload_message(payload: &(Any + Send)) -> String {
+    match payload.downcast_ref::<&'static str>() {
+        Some(s) => s.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(s) => s.clone(),
+            None => "the compiler panicked".to_string(),
+        },
+    }
+}
+
+fn record_panic(info: &PanicInfo) {
+    let message = payload_message(info.payload());
+    let location = info.location().map(|l| format!("{}:{}", l.file(), l.line()));
+    LAST_PANIC.with(|last| *last.borrow_mut() = Some((message, location)));
+}
+
+/// Run `f`, turning a panic into an `InternalError` describing the state of
+/// `session` at the time of the panic.
+pub fn catch_panics<F, R>(session: &Session, f: F) -> Result<R, InternalError>
+    where F: FnOnce() -> R
+{
+    // The default hook prints the raw panic, we only record it.
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(record_panic));
+
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+
+    panic::set_hook(previous_hook);
+
+    result.map_err(|payload| {
+        let (message, location) = LAST_PANIC.with(|last| last.borrow_mut().take())
+            .unwrap_or_else(|| (payload_message(&*payload), None));
+
+        InternalError {
+            message: message,
+            location: location,
+            definition: session.current_definition(),
+            trace: session.recent_trace(),
+        }
+    })
+}
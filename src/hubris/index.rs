@@ -0,0 +1,387 @@
+//! Tables recorded during elaboration for use by editor tooling.
+//!
+//! Recording is only enabled when the session's `index` option is set, as the
+//! tables hold a type for nearly every subterm of the program.
+
+use core::{Name, Term};
+use super::ast::{ModuleId, Span};
+use super::session::Session;
+
+use rustc_serialize::json::Json;
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// A `_` in term position or a use of `sorry`, along with the locals that
+/// were in scope.
+#[derive(Clone, Debug)]
+pub struct Hole {
+    pub span: Span,
+    pub context: Vec<Name>,
+    pub kind: HoleKind,
+}
+
+#[derive(Clone, Debug)]
+pub enum HoleKind {
+    /// A `_`, with the metavariable elaborated for it, replaced by its
+    /// solution once the hole's definition is solved.
+    Placeholder(Term),
+    /// A use of `sorry`, which always stands for a missing proof.
+    Sorry,
+}
+
+impl Hole {
+    /// Whether the hole is still to be filled in by the user.
+    pub fn is_unsolved(&self) -> bool {
+        match self.kind {
+            HoleKind::Placeholder(ref term) => term.head().map_or(false, |head| head.is_meta()),
+            HoleKind::Sorry => true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HintKind {
+    /// An implicit argument inserted by the elaborator.
+    ImplicitArgument,
+    /// The inferred type of a binder written without one.
+    BinderType,
+}
+
+/// Something the elaborator inferred that isn't written in the source, shown
+/// at the end of `span`.
+#[derive(Clone, Debug)]
+pub struct Hint {
+    pub span: Span,
+    pub kind: HintKind,
+    pub term: Term,
+}
+
+/// A use of a name, along with what it resolved to.
+#[derive(Clone, Debug)]
+pub struct Resolution {
+    pub span: Span,
+    pub name: Name,
+    /// The span of the binder or declaration, if the name has one.
+    pub definition: Option<Span>,
+}
+
+#[derive(Default)]
+pub struct Index {
+    /// The span each global and local was introduced at.
+    definitions: BTreeMap<Name, Span>,
+    resolutions: Vec<Resolution>,
+    /// The elaborated type of each term, keyed by the term's span.
+    types: Vec<(Span, Term)>,
+    /// Each elaborated term, keyed by its span.
+    terms: Vec<(Span, Term)>,
+    holes: Vec<Hole>,
+    /// The locals in scope within each term that has any.
+    scopes: Vec<(Span, Vec<Name>)>,
+    hints: Vec<Hint>,
+    /// Entries recorded since the last call to `resolve`, these may still
+    /// mention meta variables.
+    pending_types: Vec<(Span, Term)>,
+    pending_terms: Vec<(Span, Term)>,
+    pending_holes: Vec<Hole>,
+    pending_hints: Vec<Hint>,
+}
+
+impl Index {
+    pub fn new() -> Index {
+        Index::default()
+    }
+
+    /// Record that the term written at `span` elaborated to `term`, whose
+    /// type is `ty`.
+    pub fn record_type(&mut self, span: Span, term: Term, ty: Term) {
+        if span.hi > span.lo {
+            self.pending_terms.push((span, term));
+            self.pending_types.push((span, ty));
+        }
+    }
+
+    pub fn record_definition(&mut self, name: Name, span: Span) {
+        self.definitions.insert(name, span);
+    }
+
+    pub fn record_resolution(&mut self, span: Span, name: Name) {
+        let definition = self.definitions.get(&name).cloned();
+
+        self.resolutions.push(Resolution {
+            span: span,
+            name: name,
+            definition: definition,
+        });
+    }
+
+    pub fn record_hint(&mut self, span: Span, kind: HintKind, term: Term) {
+        if span.hi > span.lo {
+            self.pending_hints.push(Hint {
+                span: span,
+                kind: kind,
+                term: term,
+            });
+        }
+    }
+
+    pub fn record_scope(&mut self, span: Span, locals: Vec<Name>) {
+        if span.hi > span.lo && !locals.is_empty() {
+            self.scopes.push((span, locals));
+        }
+    }
+
+    pub fn record_hole(&mut self, span: Span, context: Vec<Name>, kind: HoleKind) {
+        self.pending_holes.push(Hole {
+            span: span,
+            context: context,
+            kind: kind,
+        });
+    }
+
+    /// Move the pending entries into the tables, applying `subst` to replace
+    /// the meta variables solved since they were recorded.
+    pub fn resolve<F: Fn(Term) -> Term>(&mut self, subst: F) {
+        for (span, ty) in self.pending_types.drain(..) {
+            self.types.push((span, subst(ty)));
+        }
+
+        for (span, term) in self.pending_terms.drain(..) {
+            self.terms.push((span, subst(term)));
+        }
+
+        // A solved implicit argument is a meta applied to the locals in scope,
+        // so we reduce the solution to get back what the user would write.
+        for hint in self.pending_hints.drain(..) {
+            self.hints.push(Hint {
+                span: hint.span,
+                kind: hint.kind,
+                term: head_beta(subst(hint.term)),
+            });
+        }
+
+        for hole in self.pending_holes.drain(..) {
+            let context = hole.context.into_iter().map(|local| {
+                match local {
+                    Name::Local { number, ty, repr, binding_info, relevance } => Name::Local {
+                        number: number,
+                        ty: Box::new(subst(*ty)),
+                        repr: repr,
+                        binding_info: binding_info,
+                        relevance: relevance,
+                    },
+                    n => n,
+                }
+            }).collect();
+
+            let kind = match hole.kind {
+                HoleKind::Placeholder(term) => HoleKind::Placeholder(head_beta(subst(term))),
+                HoleKind::Sorry => HoleKind::Sorry,
+            };
+
+            self.holes.push(Hole {
+                span: hole.span,
+                context: context,
+                kind: kind,
+            });
+        }
+    }
+
+    /// Add the tables of an imported module, the locals of the module are
+    /// numbered independently of ours so only its globals are kept.
+    pub fn merge(&mut self, other: Index) {
+        let Index { definitions, resolutions, types, terms, holes, hints, .. } = other;
+
+        self.definitions.extend(definitions.into_iter().filter(|&(ref n, _)| n.is_qual()));
+        self.resolutions.extend(resolutions.into_iter());
+        self.types.extend(types.into_iter());
+        self.terms.extend(terms.into_iter());
+        self.holes.extend(holes.into_iter());
+        self.hints.extend(hints.into_iter());
+    }
+
+    /// The name used at `offset`.
+    pub fn resolution_at(&self, module_id: ModuleId, offset: usize) -> Option<&Resolution> {
+        innermost(self.resolutions.iter().map(|r| (r.span, r)), module_id, offset)
+            .map(|(_, r)| r)
+    }
+
+    /// The definition at `offset`, either because the offset is within the
+    /// declaration itself or a use of it.
+    pub fn definition_at(&self, module_id: ModuleId, offset: usize) -> Option<Span> {
+        let declared = innermost(
+            self.definitions.values().map(|&span| (span, span)), module_id, offset);
+
+        match declared {
+            Some((span, _)) => Some(span),
+            None => self.resolution_at(module_id, offset).and_then(|r| r.definition),
+        }
+    }
+
+    /// Every use of the definition at `definition`.
+    pub fn references(&self, definition: Span) -> Vec<Span> {
+        self.resolutions.iter()
+                        .filter(|r| r.definition == Some(definition))
+                        .map(|r| r.span)
+                        .collect()
+    }
+
+    pub fn resolutions(&self) -> &[Resolution] {
+        &self.resolutions[..]
+    }
+
+    pub fn definitions(&self) -> &BTreeMap<Name, Span> {
+        &self.definitions
+    }
+
+    pub fn holes(&self) -> &[Hole] {
+        &self.holes[..]
+    }
+
+    pub fn hints(&self) -> &[Hint] {
+        &self.hints[..]
+    }
+
+    /// Write each resolved use as `use: name -> definition`, with locations
+    /// given as `file:line:column`.
+    pub fn write_resolutions<W: Write>(&self, session: &Session, out: &mut W) -> io::Result<()> {
+        for resolution in &self.resolutions {
+            if let Some(definition) = resolution.definition {
+                try!(writeln!(out, "{}: {} -> {}",
+                              session.location(resolution.span),
+                              resolution.name,
+                              session.location(definition)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The unsolved holes in `module` as JSON, each with its file, range,
+    /// goal, and the locals in scope, outermost first. Lines and columns
+    /// count from zero, as in the language server protocol.
+    pub fn holes_to_json(&self, session: &Session, module: ModuleId) -> Vec<Json> {
+        let mut holes: Vec<_> = self.holes.iter().filter(|hole| {
+            hole.span.module_id == module && hole.is_unsolved()
+        }).collect();
+
+        holes.sort_by_key(|hole| hole.span.lo);
+
+        holes.into_iter().filter_map(|hole| {
+            let source_map = match session.source_map_for(hole.span.module_id) {
+                None => return None,
+                Some(source_map) => source_map,
+            };
+
+            let start = source_map.position(Span::new(hole.span.lo, hole.span.lo)).unwrap_or((0, 0));
+            let end = source_map.position(Span::new(hole.span.hi, hole.span.hi)).unwrap_or(start);
+
+            let goal = match self.type_at(hole.span.module_id, hole.span.lo) {
+                None => Json::Null,
+                Some((_, ty)) => Json::String(session.render(ty)),
+            };
+
+            let context = hole.context.iter().map(|local| {
+                let (name, ty) = match local {
+                    &Name::Local { ref repr, ref ty, .. } =>
+                        (repr.clone(), Json::String(session.render(ty))),
+                    n => (n.to_string(), Json::Null),
+                };
+
+                object(vec![
+                    ("name", Json::String(name)),
+                    ("type", ty),
+                ])
+            }).collect();
+
+            let kind = match hole.kind {
+                HoleKind::Placeholder(_) => "placeholder",
+                HoleKind::Sorry => "sorry",
+            };
+
+            Some(object(vec![
+                ("file", Json::String(source_map.file_name.clone())),
+                ("range", object(vec![
+                    ("start", position(start)),
+                    ("end", position(end)),
+                ])),
+                ("kind", Json::String(kind.to_string())),
+                ("goal", goal),
+                ("context", Json::Array(context)),
+            ]))
+        }).collect()
+    }
+
+    /// The type of the innermost term containing `offset`, if a term's type
+    /// was recorded more than once the most recent is used.
+    pub fn type_at(&self, module_id: ModuleId, offset: usize) -> Option<(Span, &Term)> {
+        innermost(self.types.iter().map(|&(span, ref ty)| (span, ty)), module_id, offset)
+    }
+
+    /// The innermost term containing `offset`, as it was elaborated.
+    pub fn term_at(&self, module_id: ModuleId, offset: usize) -> Option<(Span, &Term)> {
+        innermost(self.terms.iter().map(|&(span, ref term)| (span, term)), module_id, offset)
+    }
+
+    /// The locals in scope at `offset`, outermost first.
+    pub fn locals_at(&self, module_id: ModuleId, offset: usize) -> &[Name] {
+        innermost(self.scopes.iter().map(|&(span, ref locals)| (span, locals)), module_id, offset)
+            .map(|(_, locals)| &locals[..])
+            .unwrap_or(&[])
+    }
+
+    pub fn hole_at(&self, module_id: ModuleId, offset: usize) -> Option<&Hole> {
+        innermost(self.holes.iter().map(|h| (h.span, h)), module_id, offset)
+            .map(|(_, h)| h)
+    }
+}
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+fn position((line, character): (usize, usize)) -> Json {
+    object(vec![
+        ("line", Json::U64(line as u64)),
+        ("character", Json::U64(character as u64)),
+    ])
+}
+
+/// Beta reduce the head of `term`.
+fn head_beta(term: Term) -> Term {
+    let (mut head, args) = term.uncurry();
+    let mut args = args.into_iter();
+
+    loop {
+        match head {
+            Term::Lambda { binder, body, span } => match args.next() {
+                Some(arg) => head = body.instantiate(&arg),
+                None => return Term::Lambda { binder: binder, body: body, span: span },
+            },
+            other => return Term::apply_all(other, args.collect()),
+        }
+    }
+}
+
+fn innermost<T, I>(entries: I, module_id: ModuleId, offset: usize) -> Option<(Span, T)>
+    where I: Iterator<Item=(Span, T)>
+{
+    let mut found: Option<(Span, T)> = None;
+
+    for (span, entry) in entries {
+        if span.module_id != module_id || offset < span.lo || offset > span.hi {
+            continue;
+        }
+
+        let is_inner = match found {
+            None => true,
+            Some((outer, _)) => span.hi - span.lo <= outer.hi - outer.lo,
+        };
+
+        if is_inner {
+            found = Some((span, entry));
+        }
+    }
+
+    found
+}
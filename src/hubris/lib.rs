@@ -1,17 +1,21 @@
 #[macro_use]
 extern crate cfg_if;
 extern crate gcc;
-extern crate iron;
+extern crate hubris_kernel;
 extern crate hubris_syntax;
 #[macro_use]
 extern crate log;
 #[cfg(feature = "llvm-backend")]
 extern crate llvm_sys;
-extern crate readline;
-extern crate router;
+#[cfg(not(target_arch = "wasm32"))]
+extern crate memmap;
+extern crate rustc_serialize;
+#[cfg(not(target_arch = "wasm32"))]
+extern crate rustyline;
 extern crate term;
-extern crate urlencoded;
+extern crate toml;
 extern crate pretty;
+extern crate rayon;
 #[macro_use]
 extern crate itertools;
 
@@ -20,8 +24,20 @@ pub mod ast {
 }
 
 pub mod backend;
-pub mod core;
+
+pub mod core {
+    pub use hubris_kernel::core::*;
+}
+
+pub mod diagnostic;
+pub mod display;
+pub mod doc;
 pub mod elaborate;
+pub mod feature;
+pub mod ice;
+pub mod index;
+
+pub mod lint;
 
 #[cfg(feature = "llvm-backend")]
 pub mod llvm;
@@ -30,18 +46,33 @@ pub mod parser {
     pub use hubris_syntax::parser::*;
 }
 
+pub mod playground;
+pub mod printer;
+pub mod project;
+pub mod query;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod renderer;
 pub mod repl;
 pub mod server;
 pub mod session;
+pub mod smt;
+pub mod timing;
 pub mod typeck;
 pub mod syntax;
 pub mod util;
 
+use rustc_serialize::json::Json;
+
+use std::fs::File;
 use std::path::{PathBuf, Path};
-use std::io;
+use std::io::{self, Read, Write};
 
 use self::session::{HasSession, Reportable};
-use self::backend::{Backend, Rust};
+use self::diagnostic::Diagnostic;
+
+/// Stands for standard input as the file to compile, and for standard output
+/// as where to write the result.
+pub const STDIO: &'static str = "-";
 
 #[derive(Debug)]
 pub enum Error {
@@ -49,6 +80,12 @@ pub enum Error {
     Elaborator(elaborate::Error),
     TypeCk(typeck::Error),
     Parser(parser::Error),
+    Query(query::Error),
+    Project(project::Error),
+    /// A directory given to compile holds no source files.
+    NoInput(PathBuf),
+    /// The compiler panicked.
+    Internal(ice::InternalError),
 }
 
 impl From<io::Error> for Error {
@@ -75,6 +112,18 @@ impl From<parser::Error> for Error {
     }
 }
 
+impl From<query::Error> for Error {
+    fn from(err: query::Error) -> Error {
+        Error::Query(err)
+    }
+}
+
+impl From<project::Error> for Error {
+    fn from(err: project::Error) -> Error {
+        Error::Project(err)
+    }
+}
+
 impl Reportable for Error {
     fn report(self, session: &session::Session) -> io::Result<()> {
         use self::Error::*;
@@ -84,37 +133,483 @@ impl Reportable for Error {
             Elaborator(elab_err) => session.report(elab_err),
             TypeCk(ty_cx_err) => session.report(ty_cx_err),
             Parser(parse_err) => session.report(parse_err),
+            Query(query_err) => session.report(query_err),
+            Project(project_err) => session.error(format!("{}", project_err)),
+            NoInput(dir) =>
+                session.error(format!("no Hubris source files found in `{}`", dir.display())),
+            Internal(ice) => session.report(ice),
         }
     }
 }
 
 pub fn compile_file<T: AsRef<Path>>(path: T, output: Option<PathBuf>) -> Result<(), Error> {
-    let module_id = ast::ModuleId(0);
-    let parser = try!(parser::from_file(path.as_ref(), module_id));
-    let module = try!(parser.parse());
+    compile_file_with_options(path, output, session::Options::default())
+}
 
+pub fn compile_file_with_options<T: AsRef<Path>>(path: T,
+                                                 output: Option<PathBuf>,
+                                                 options: session::Options)
+                                                 -> Result<(), Error> {
     let session =
         session::Session::from_root(
             path.as_ref());
 
-    session.add_source_map_for(
-        module_id,
-        parser.source_map);
+    session.set_options(options);
+
+    compile_file_in_session(path, output, session)
+}
+
+/// Compile the program made up of `paths`, each either a file or a
+/// directory whose `.hbr` files are all included. The files are elaborated
+/// in the order they import each other, and the output is named after the
+/// file declaring `main`.
+pub fn compile_files_with_options(paths: &[PathBuf],
+                                  output: Option<PathBuf>,
+                                  options: session::Options)
+                                  -> Result<(), Error> {
+    if paths.len() == 1 && !paths[0].is_dir() {
+        return compile_file_with_options(&paths[0], output, options);
+    }
+
+    let mut files = vec![];
+
+    for path in paths {
+        if path.is_dir() {
+            let before = files.len();
+            try!(source_files(path, &mut files));
+
+            if files.len() == before {
+                return Err(Error::NoInput(path.clone()));
+            }
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    let session = session::Session::from_root(&files[0]);
+    session.set_options(options);
+
+    match ice::catch_panics(&session, || run_program(&files, output, session.clone())) {
+        Err(ice) => Err(Error::Internal(ice)),
+        Ok(result) => result,
+    }
+}
+
+/// Collect the `.hbr` and literate `.hub.md` files under `dir`, in a stable
+/// order.
+fn source_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = vec![];
+
+    for entry in try!(std::fs::read_dir(dir)) {
+        entries.push(try!(entry).path());
+    }
+
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            try!(source_files(&path, files));
+        } else if path.extension().map(|ext| ext == "hbr").unwrap_or(false) ||
+                  parser::is_literate(&path) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Build the binary targets of the project rooted at `project_dir`, if `target`
+/// is `None` every target declared in the manifest is built.
+pub fn build_project<T: AsRef<Path>>(project_dir: T, target: Option<&str>) -> Result<(), Error> {
+    let project = try!(project::Project::from_dir(project_dir.as_ref()));
+    let targets = try!(project.select_targets(target));
+    let dependencies = try!(project::resolve_dependencies(&project));
 
-    let mut ecx =
-        elaborate::ElabCx::from_module(
-            module,
-            session);
+    let output_dir = project.output_dir();
+    try!(std::fs::create_dir_all(&output_dir));
 
-    let core_module = ecx.elaborate_module();
+    for target in targets {
+        let main_file = project.main_file(&target);
+        let session = session::Session::from_project(&project, &dependencies, &main_file);
+        let output = output_dir.join(&target.name);
 
-    match core_module {
-        Err(e) => { try!(ecx.report(e)); },
-        Ok(core_module) => {
-            let main = try!(ecx.ty_cx.get_main()).clone();
-            Rust::create_executable(main.clone(), ecx.ty_cx, output);
-       }
-   }
+        try!(compile_file_in_session(&main_file, Some(output), session));
+    }
+
+    Ok(())
+}
+
+/// Print the location of every use of the name at the one based `line` and
+/// `column` of `path`, across the modules it imports.
+pub fn find_references<T: AsRef<Path>>(path: T, line: usize, column: usize) -> Result<(), Error> {
+    let session = session::Session::from_root(path.as_ref());
+
+    let mut options = session.options();
+    options.index = true;
+    session.set_options(options);
+
+    let mut database = query::Database::new(session.clone());
+    let elaborated = try!(database.elaborated(path.as_ref()));
+
+    let offset = elaborated.source_map.offset(line.saturating_sub(1), column.saturating_sub(1));
+    let index = &elaborated.ecx.ty_cx.index;
+
+    match offset.and_then(|offset| index.definition_at(elaborated.module_id, offset)) {
+        None => try!(session.error(format!("no name found at {}:{}", line, column))),
+        Some(definition) => {
+            println!("{}: definition", session.location(definition));
+
+            for span in index.references(definition) {
+                println!("{}", session.location(span));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the term at the one based `line` and `column` of `path`, and what
+/// it reduces to.
+pub fn normalize_at<T: AsRef<Path>>(path: T,
+                                    line: usize,
+                                    column: usize,
+                                    form: typeck::NormalForm,
+                                    options: typeck::ReduceOptions) -> Result<(), Error> {
+    let session = session::Session::from_root(path.as_ref());
+
+    let mut session_options = session.options();
+    session_options.index = true;
+    session.set_options(session_options);
+
+    let mut database = query::Database::new(session.clone());
+    let elaborated = try!(database.elaborated(path.as_ref()));
+
+    let offset = elaborated.source_map.offset(line.saturating_sub(1), column.saturating_sub(1));
+    let ty_cx = &elaborated.ecx.ty_cx;
+
+    match offset.and_then(|offset| ty_cx.index.term_at(elaborated.module_id, offset)) {
+        None => try!(session.error(format!("no term found at {}:{}", line, column))),
+        Some((span, term)) => {
+            let reduced = try!(ty_cx.reduce_to(term, form, options));
+            println!("{}: {}", session.location(span), session.render(term));
+            println!("{}", session.render(&reduced));
+        }
+    }
+
+    Ok(())
+}
+
+/// Write documentation for the module at `path`, by default next to it with
+/// the extension of `format`.
+pub fn document_file<T: AsRef<Path>>(path: T,
+                                     output: Option<PathBuf>,
+                                     format: doc::Format) -> Result<(), Error> {
+    let session = session::Session::from_root(path.as_ref());
+
+    let mut database = query::Database::new(session);
+    let elaborated = try!(database.elaborated(path.as_ref()));
+
+    if let Some(ref core_module) = elaborated.module {
+        let output = output.unwrap_or(path.as_ref().with_extension(format.extension()));
+        let mut file = try!(File::create(&output));
+        try!(doc::document_module(&elaborated.ecx.ty_cx, core_module, format, &mut file));
+    }
+
+    Ok(())
+}
+
+/// Run the compiler's pipeline on `path`, a panic in any phase is reported as
+/// an internal compiler error.
+pub fn compile_file_in_session<T: AsRef<Path>>(path: T,
+                                           output: Option<PathBuf>,
+                                           session: session::Session) -> Result<(), Error> {
+    match ice::catch_panics(&session, || run_pipeline(path, output, session.clone())) {
+        Err(ice) => Err(Error::Internal(ice)),
+        Ok(result) => result,
+    }
+}
+
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO)
+}
+
+/// Open `output` to write a result to, by default the input file with the
+/// given `extension`. A result is written to standard output if `output` is
+/// `-`, or if it is not given and the input was read from standard input.
+fn create_output(path: &Path, output: Option<PathBuf>, extension: &str) -> io::Result<Box<Write>> {
+    match output {
+        Some(ref output) if is_stdio(output) => return Ok(Box::new(io::stdout())),
+        None if is_stdio(path) => return Ok(Box::new(io::stdout())),
+        _ => {}
+    }
+
+    let output = output.unwrap_or(path.with_extension(extension));
+    Ok(Box::new(try!(File::create(&output))))
+}
+
+fn run_pipeline<T: AsRef<Path>>(path: T,
+                                output: Option<PathBuf>,
+                                session: session::Session) -> Result<(), Error> {
+    let emit = session.options().emit;
+
+    if emit == session::Emit::Resolutions || emit == session::Emit::Holes {
+        let mut options = session.options();
+        options.index = true;
+        session.set_options(options);
+    }
+
+    let to_stdout = match output {
+        Some(ref output) => is_stdio(output),
+        None => is_stdio(path.as_ref()),
+    };
+
+    if to_stdout {
+        match emit {
+            session::Emit::Executable | session::Emit::Library => {
+                try!(session.error(
+                    "executables and libraries can't be written to standard output, \
+                     use `--output` to name a file".to_string()));
+                return Ok(());
+            }
+            session::Emit::Check => {}
+            _ => session.report_to_stderr(),
+        }
+    }
+
+    let mut database = query::Database::new(session.clone());
+
+    // A program read from standard input is given to the database as the
+    // text of the file `-`.
+    if is_stdio(path.as_ref()) {
+        let mut text = String::new();
+        try!(io::stdin().read_to_string(&mut text));
+        database.set_source(path.as_ref(), text);
+    }
+
+    let query::Elaborated { ecx, module, module_id, .. } =
+        try!(database.into_elaborated(path.as_ref()));
+
+    // The holes are listed even when elaboration failed, an unsolved one
+    // is an error.
+    if emit == session::Emit::Holes {
+        let holes = ecx.ty_cx.index.holes_to_json(&session, module_id);
+        try!(write_holes(holes, path.as_ref(), output));
+        try!(session.report_timings());
+        return Ok(());
+    }
+
+    // The errors were reported as the module was elaborated.
+    if let Some(module) = module {
+        try!(emit_program(ecx.ty_cx, vec![module], path.as_ref(), output));
+    }
+
+    try!(session.report_timings());
+
+    Ok(())
+}
+
+/// Run the compiler's pipeline on the program made up of `files`,
+/// elaborating each of them into one typing context.
+fn run_program(files: &[PathBuf],
+               output: Option<PathBuf>,
+               session: session::Session) -> Result<(), Error> {
+    let emit = session.options().emit;
+
+    if emit == session::Emit::Resolutions || emit == session::Emit::Holes {
+        let mut options = session.options();
+        options.index = true;
+        session.set_options(options);
+    }
+
+    let mut database = query::Database::new(session.clone());
+
+    // Each file's module is imported from that file, wherever it is.
+    for file in files {
+        let parsed = match database.parse(file) {
+            // Reported when the file is compiled.
+            Err(query::Error::Parser(_)) => continue,
+            result => try!(result),
+        };
+
+        let module_name = match parsed.module.name.repr {
+            ast::NameKind::Unqualified(ref name) => name.clone(),
+            ast::NameKind::Qualified(ref components) => components.join("."),
+            ast::NameKind::Placeholder => continue,
+        };
+
+        session.map_module(module_name, file.clone());
+    }
+
+    let order = try!(database.import_order(files));
+
+    let mut ecx = elaborate::ElabCx::from_module(ast::Module::empty(), session.clone());
+    let mut modules = vec![];
+    let mut main_file = None;
+    let mut failures = vec![];
+    let mut holes = vec![];
+
+    for file in &order {
+        let errors = session.error_count();
+
+        let name = match database.parse(file) {
+            Err(query::Error::Parser(e)) => {
+                try!(session.report(e));
+                failures.push((file.clone(), Failure::Errors(session.error_count() - errors)));
+                continue;
+            }
+            result => try!(result).module.name.clone(),
+        };
+
+        // The errors of a module importing one that failed would only
+        // follow from that failure.
+        let failed_import = try!(database.resolve(file)).imports.iter().find(|&&(_, ref import)| {
+            failures.iter().any(|&(ref failed, _)| failed == import)
+        }).map(|&(ref name, _)| name.clone());
+
+        if let Some(import) = failed_import {
+            failures.push((file.clone(), Failure::ImportFailed(import)));
+            continue;
+        }
+
+        let elaborated = try!(database.elaborated(file));
+
+        if emit == session::Emit::Holes {
+            holes.extend(elaborated.ecx.ty_cx.index.holes_to_json(&session, elaborated.module_id));
+        }
+
+        let module = match elaborated.module {
+            // The errors were reported as the module was elaborated.
+            None => {
+                failures.push((file.clone(), Failure::Errors(session.error_count() - errors)));
+                continue;
+            }
+            Some(ref module) => module.clone(),
+        };
+
+        if module.defs.iter().any(|item| *item.name() == core::Name::from_str("main")) {
+            main_file = Some(file.clone());
+        }
+
+        ecx.provide_import(elaborate::to_qualified_name(name.clone()).unwrap(),
+                           elaborated.clone());
+        try!(ecx.elaborate_import(name));
+        modules.push(module);
+    }
+
+    if emit == session::Emit::Holes {
+        try!(write_holes(holes, &files[0], output));
+    } else if failures.is_empty() {
+        let path = main_file.unwrap_or(files[0].clone());
+        try!(emit_program(ecx.ty_cx, modules, &path, output));
+    } else {
+        try!(report_failures(&session, &failures, order.len()));
+    }
+
+    try!(session.report_timings());
+
+    Ok(())
+}
+
+/// Why a module of a program was not compiled.
+enum Failure {
+    /// The number of errors reported for the module.
+    Errors(usize),
+    /// The module imports this one, which was not compiled.
+    ImportFailed(core::Name),
+}
+
+/// Summarize which of the `total` modules of a program were not compiled,
+/// after their errors were reported.
+fn report_failures(session: &session::Session,
+                   failures: &[(PathBuf, Failure)],
+                   total: usize) -> io::Result<()> {
+    let mut diagnostic = Diagnostic::error(
+        format!("{} of {} modules could not be compiled", failures.len(), total));
+
+    for &(ref file, ref failure) in failures {
+        diagnostic = diagnostic.note(match failure {
+            &Failure::Errors(1) => format!("`{}`: 1 error", file.display()),
+            &Failure::Errors(count) => format!("`{}`: {} errors", file.display(), count),
+            &Failure::ImportFailed(ref import) =>
+                format!("`{}`: not compiled because its import `{}` failed", file.display(), import),
+        });
+    }
+
+    diagnostic.emit(session)
+}
+
+/// Write `holes`, as computed by `Index::holes_to_json`, as a JSON array.
+/// Unless `output` is given the output is named after `path`.
+fn write_holes(holes: Vec<Json>, path: &Path, output: Option<PathBuf>) -> io::Result<()> {
+    let path = if parser::is_literate(path) { path.with_extension("") } else { path.to_owned() };
+    let mut out = try!(create_output(&path, output, "holes.json"));
+    writeln!(out, "{}", Json::Array(holes).pretty())
+}
+
+/// Write the output the session's options select for the program in
+/// `ty_cx`, made up of `modules`. Unless `output` is given the output is
+/// named after `path`.
+fn emit_program(ty_cx: typeck::TyCtxt,
+                modules: Vec<core::Module>,
+                path: &Path,
+                output: Option<PathBuf>) -> Result<(), Error> {
+    let session = ty_cx.session.clone();
+
+    // Outputs of `tutorial.hub.md` are named `tutorial`, not `tutorial.hub`.
+    let path = if parser::is_literate(path) { path.with_extension("") } else { path.to_owned() };
+
+    if session.lint_errors() > 0 {
+        let count = session.lint_errors();
+        try!(session.error(
+            format!("aborting due to {} denied lint{}",
+                    count,
+                    if count == 1 { "" } else { "s" })));
+        return Ok(());
+    }
+
+    match session.options().emit {
+        session::Emit::Executable => {
+            let backend = session.options().backend.backend();
+            let main = try!(ty_cx.get_main()).clone();
+            try!(ty_cx.check_main(&main));
+            let output = output.unwrap_or(path.with_extension(""));
+            debug!("compile_file: using the {} backend", backend.name());
+            try!(backend.create_executable(main, ty_cx, Some(output)));
+        }
+        session::Emit::Library => {
+            let backend = session.options().backend.backend();
+            let output = output.unwrap_or(path.with_extension(""));
+            debug!("compile_file: using the {} backend", backend.name());
+            try!(backend.create_library(ty_cx, Some(output)));
+        }
+        session::Emit::IrAfter(pass) => {
+            let backend = session.options().backend.backend();
+            let mut out = try!(create_output(&path, output, "ir"));
+            try!(backend.dump_ir(ty_cx, pass, &mut out));
+        }
+        session::Emit::Export => {
+            let mut out = try!(create_output(&path, output, "export"));
+            try!(hubris_kernel::export::export_environment(&ty_cx.kernel, &mut out));
+        }
+        session::Emit::Image => {
+            let mut out = try!(create_output(&path, output, "image"));
+            try!(ty_cx.write_image(&mut out));
+        }
+        session::Emit::Resolutions => {
+            let mut out = try!(create_output(&path, output, "resolutions"));
+            try!(ty_cx.index.write_resolutions(&session, &mut out));
+        }
+        // Listed before the program is emitted, as an unsolved hole fails
+        // elaboration.
+        session::Emit::Holes => unreachable!(),
+        session::Emit::Sexp => {
+            let mut out = try!(create_output(&path, output, "sexp"));
+            for module in &modules {
+                try!(hubris_kernel::sexp::emit_module(module, &mut out));
+            }
+        }
+        session::Emit::Check => {}
+    }
 
-   Ok(())
+    Ok(())
 }
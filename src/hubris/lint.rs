@@ -0,0 +1,105 @@
+//! Lints and the levels they are reported at.
+//!
+//! Lints are reported as warnings by default, and don't fail the build. The
+//! level of each lint can be changed from the command line with `-A`, `-W`,
+//! and `-D`, the name `warnings` refers to every lint.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// A local binder that is never referred to.
+    UnusedVariables,
+    /// An import that none of the module's names are resolved through.
+    UnusedImports,
+    /// A match arm that is covered by the arms before it.
+    UnreachablePatterns,
+    /// A reference to a global marked `@[deprecated]`.
+    Deprecated,
+    /// A use of `sorry`, standing for a proof that is not written yet.
+    Sorry,
+}
+
+pub const ALL_LINTS: &'static [Lint] = &[
+    Lint::UnusedVariables,
+    Lint::UnusedImports,
+    Lint::UnreachablePatterns,
+    Lint::Deprecated,
+    Lint::Sorry,
+];
+
+impl Lint {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Lint::UnusedVariables => "unused-variables",
+            Lint::UnusedImports => "unused-imports",
+            Lint::UnreachablePatterns => "unreachable-patterns",
+            Lint::Deprecated => "deprecated",
+            Lint::Sorry => "sorry",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Lint> {
+        ALL_LINTS.iter().cloned().find(|l| l.name() == s)
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(formatter, "{}", self.name())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl LintLevel {
+    /// The command line flag that sets this level.
+    pub fn flag(&self) -> &'static str {
+        match *self {
+            LintLevel::Allow => "-A",
+            LintLevel::Warn => "-W",
+            LintLevel::Deny => "-D",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LintLevels {
+    levels: HashMap<Lint, LintLevel>,
+}
+
+impl LintLevels {
+    /// Set the level of the lint called `name`, returning `false` if there
+    /// is no such lint.
+    pub fn set(&mut self, name: &str, level: LintLevel) -> bool {
+        if name == "warnings" {
+            for &lint in ALL_LINTS {
+                self.levels.insert(lint, level);
+            }
+            return true;
+        }
+
+        match Lint::from_str(name) {
+            None => false,
+            Some(lint) => {
+                self.levels.insert(lint, level);
+                true
+            }
+        }
+    }
+
+    pub fn level(&self, lint: Lint) -> LintLevel {
+        self.levels.get(&lint).cloned().unwrap_or(LintLevel::Warn)
+    }
+
+    /// Whether `lint` is at its default level.
+    pub fn is_default(&self, lint: Lint) -> bool {
+        !self.levels.contains_key(&lint)
+    }
+}
@@ -0,0 +1,156 @@
+//! Checking a single module given as text, for the playground in the browser.
+//!
+//! The compiler is built for `wasm32-unknown-unknown` with
+//!
+//! ```text
+//! cargo rustc --lib --release --target wasm32-unknown-unknown -- --crate-type cdylib
+//! ```
+//!
+//! which leaves out the REPL. Nothing is read from the filesystem, so a module
+//! checked in the playground can not import others, and the diagnostics are
+//! returned as JSON instead of being printed.
+
+use rustc_serialize::json::Json;
+
+use super::ast::{SourceMap, Span};
+use super::diagnostic::Diagnostic;
+use super::elaborate::ElabCx;
+use super::ice;
+use super::parser::Parser;
+use super::session::{Reportable, Session};
+
+/// The name diagnostics give the module being checked.
+const FILE_NAME: &'static str = "<playground>";
+
+/// Parse and elaborate `source`, returning its diagnostics as a JSON array.
+///
+/// Each diagnostic is an object with the fields `level`, `message`, `range`,
+/// `labels`, `notes` and `help`. A range has a `start` and an `end`, which
+/// are each a `line` and a `character`, or is `null` when the diagnostic is
+/// not about a location. Each label has a `range` and a `message`.
+pub fn compile_and_check(source: &str) -> String {
+    let session = Session::in_memory();
+    let module_id = session.next_module_id();
+
+    let parser = Parser {
+        source_map: SourceMap::from_file(FILE_NAME.to_string(), source.to_string()),
+        id: module_id,
+    };
+
+    // Parse errors are reported against the source map.
+    session.add_source_map_for(module_id, parser.source_map.clone());
+
+    let result = ice::catch_panics(&session, || {
+        let module = match parser.parse() {
+            Err(e) => return session.report(e),
+            Ok(m) => m,
+        };
+
+        let mut ecx = ElabCx::from_module(module, session.clone());
+
+        match ecx.elaborate_module() {
+            Err(e) => ecx.report(e),
+            Ok(_) => Ok(()),
+        }
+    });
+
+    if let Err(ice) = result {
+        ice.report(&session).unwrap();
+    }
+
+    let diagnostics = session.take_diagnostics()
+                             .into_iter()
+                             .map(|d| diagnostic_to_json(&parser.source_map, d))
+                             .collect();
+
+    Json::Array(diagnostics).to_string()
+}
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+fn position((line, character): (usize, usize)) -> Json {
+    object(vec![
+        ("line", Json::U64(line as u64)),
+        ("character", Json::U64(character as u64)),
+    ])
+}
+
+fn range(source_map: &SourceMap, span: Span) -> Json {
+    let start = source_map.position(Span::new(span.lo, span.lo)).unwrap_or((0, 0));
+    let end = source_map.position(Span::new(span.hi, span.hi)).unwrap_or(start);
+
+    object(vec![
+        ("start", position(start)),
+        ("end", position(end)),
+    ])
+}
+
+fn diagnostic_to_json(source_map: &SourceMap, diagnostic: Diagnostic) -> Json {
+    let range_of = |span: Span| {
+        if span == Span::dummy() {
+            Json::Null
+        } else {
+            range(source_map, span)
+        }
+    };
+
+    let labels = diagnostic.labels.into_iter().map(|(span, message)| {
+        object(vec![
+            ("range", range_of(span)),
+            ("message", Json::String(message)),
+        ])
+    }).collect();
+
+    let strings = |strings: Vec<String>| Json::Array(strings.into_iter().map(Json::String).collect());
+
+    object(vec![
+        ("level", Json::String(format!("{}", diagnostic.level))),
+        ("message", Json::String(diagnostic.message)),
+        ("range", diagnostic.primary.map(&range_of).unwrap_or(Json::Null)),
+        ("labels", Json::Array(labels)),
+        ("notes", strings(diagnostic.notes)),
+        ("help", strings(diagnostic.help)),
+    ])
+}
+
+/// The interface JavaScript uses, strings are passed through the module's
+/// memory as UTF-8.
+#[cfg(target_arch = "wasm32")]
+pub mod ffi {
+    use std::slice;
+
+    /// Allocate `len` bytes to write a source string into.
+    #[no_mangle]
+    pub extern "C" fn hubris_alloc(len: usize) -> *mut u8 {
+        let buffer = vec![0u8; len].into_boxed_slice();
+        Box::into_raw(buffer) as *mut u8
+    }
+
+    /// Free `len` bytes allocated by `hubris_alloc` or returned by
+    /// `hubris_compile_and_check`.
+    #[no_mangle]
+    pub unsafe extern "C" fn hubris_free(ptr: *mut u8, len: usize) {
+        drop(Box::from_raw(slice::from_raw_parts_mut(ptr, len)));
+    }
+
+    /// Check the `len` bytes of source at `ptr`, the result is the length of
+    /// the JSON as four little endian bytes followed by the JSON, it must be
+    /// freed with `hubris_free` along with its length.
+    #[no_mangle]
+    pub unsafe extern "C" fn hubris_compile_and_check(ptr: *const u8, len: usize) -> *mut u8 {
+        let source = String::from_utf8_lossy(slice::from_raw_parts(ptr, len));
+        let json = super::compile_and_check(&source);
+
+        let json_len = json.len() as u32;
+        let mut buffer = Vec::with_capacity(4 + json.len());
+
+        for i in 0..4 {
+            buffer.push((json_len >> (8 * i)) as u8);
+        }
+
+        buffer.extend(json.as_bytes());
+        Box::into_raw(buffer.into_boxed_slice()) as *mut u8
+    }
+}
@@ -0,0 +1,300 @@
+//! A line-width aware printer for core terms.
+//!
+//! A term is first turned into a document, text with the places it may be
+//! broken across lines marked, then laid out by the algorithm of Wadler's
+//! "A prettier printer": each group is printed on one line if what remains
+//! of it fits in the width, otherwise each of its breaks starts a new line
+//! indented to its nesting level.
+//!
+//! Parentheses are only written where the precedence of the term's context
+//! requires them. Binders extend as far right as possible, `->` is right
+//! associative and binds looser than application, which is left
+//! associative.
+
+use core::{Binder, Name, Term};
+
+/// A document to lay out.
+#[derive(Clone, Debug)]
+pub enum Doc {
+    Text(String),
+    /// A space if the enclosing group fits on one line, otherwise a new
+    /// line.
+    Line,
+    Concat(Vec<Doc>),
+    /// Lines broken within the document are indented further by the given
+    /// number of spaces.
+    Nest(usize, Box<Doc>),
+    /// A document laid out on one line if it fits.
+    Group(Box<Doc>),
+}
+
+impl Doc {
+    pub fn text<S: Into<String>>(text: S) -> Doc {
+        Doc::Text(text.into())
+    }
+
+    pub fn nest(indent: usize, doc: Doc) -> Doc {
+        Doc::Nest(indent, Box::new(doc))
+    }
+
+    pub fn group(doc: Doc) -> Doc {
+        Doc::Group(Box::new(doc))
+    }
+
+    /// Lay out the document within `width` columns, or on one line if no
+    /// width is given.
+    pub fn render(&self, width: Option<usize>) -> String {
+        let mut out = String::new();
+        let mut column = 0;
+
+        let mode = if width.is_some() { Mode::Break } else { Mode::Flat };
+        let width = width.unwrap_or(0);
+        let mut stack = vec![(0, mode, self)];
+
+        while let Some((indent, mode, doc)) = stack.pop() {
+            match doc {
+                &Doc::Text(ref text) => {
+                    out.push_str(text);
+                    column += text.chars().count();
+                }
+                &Doc::Line => match mode {
+                    Mode::Flat => {
+                        out.push(' ');
+                        column += 1;
+                    }
+                    Mode::Break => {
+                        out.push('\n');
+                        for _ in 0..indent {
+                            out.push(' ');
+                        }
+                        column = indent;
+                    }
+                },
+                &Doc::Concat(ref docs) => {
+                    for doc in docs.iter().rev() {
+                        stack.push((indent, mode, doc));
+                    }
+                }
+                &Doc::Nest(more, ref doc) => stack.push((indent + more, mode, &**doc)),
+                &Doc::Group(ref doc) => {
+                    let remaining = width as isize - column as isize;
+                    let flat = mode == Mode::Flat ||
+                               fits(remaining, (indent, Mode::Flat, &**doc), &stack);
+
+                    stack.push((indent, if flat { Mode::Flat } else { Mode::Break }, &**doc));
+                }
+            }
+        }
+
+        out
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+/// Whether `next`, followed by what is on `rest` up to its first line
+/// break, fits in `remaining` columns.
+fn fits(mut remaining: isize, next: (usize, Mode, &Doc), rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut stack = vec![next];
+    let mut rest = rest.iter().rev();
+
+    while remaining >= 0 {
+        let (indent, mode, doc) = match stack.pop() {
+            Some(entry) => entry,
+            None => match rest.next() {
+                None => return true,
+                Some(&entry) => entry,
+            },
+        };
+
+        match doc {
+            &Doc::Text(ref text) => remaining -= text.chars().count() as isize,
+            &Doc::Line => match mode {
+                Mode::Flat => remaining -= 1,
+                Mode::Break => return true,
+            },
+            &Doc::Concat(ref docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((indent, mode, doc));
+                }
+            }
+            &Doc::Nest(more, ref doc) => stack.push((indent + more, mode, &**doc)),
+            &Doc::Group(ref doc) => stack.push((indent, mode, &**doc)),
+        }
+    }
+
+    false
+}
+
+/// The precedence of the context a term is printed in, a term of lower
+/// precedence is parenthesized.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    /// The body of a binder, or the top level.
+    Binder,
+    /// The result of a `->`.
+    Arrow,
+    /// The function of an application, or the domain of a `->`.
+    App,
+    /// An argument of an application.
+    Atom,
+}
+
+/// The indentation of the lines a term is broken into.
+const INDENT: usize = 2;
+
+/// Prints terms as documents, `is_elided` tells whether a name stands for
+/// a subterm that was cut off.
+pub struct Printer<F> {
+    pub is_elided: F,
+}
+
+impl<F: Fn(&Name) -> bool> Printer<F> {
+    pub fn term(&self, term: &Term) -> Doc {
+        self.term_at(term, Precedence::Binder)
+    }
+
+    fn term_at(&self, term: &Term, precedence: Precedence) -> Doc {
+        let (doc, own) = match term {
+            &Term::Var { ref name } if (self.is_elided)(name) =>
+                (Doc::text("..."), Precedence::Atom),
+            &Term::Var { ref name } => (Doc::text(name.to_string()), Precedence::Atom),
+            &Term::Type => (Doc::text("Type"), Precedence::Atom),
+            &Term::App { .. } => {
+                let (head, args) = term.uncurry();
+                let mut docs = vec![self.term_at(&head, Precedence::App)];
+
+                for arg in &args {
+                    docs.push(Doc::Line);
+                    docs.push(self.term_at(arg, Precedence::Atom));
+                }
+
+                (Doc::group(Doc::nest(INDENT, Doc::Concat(docs))), Precedence::App)
+            }
+            &Term::Forall { ref binder, term: ref body, .. }
+                if binder.name.is_placeholder() && !binder.is_irrelevant() => {
+                let doc = Doc::Concat(vec![
+                    self.term_at(&binder.ty, Precedence::App),
+                    Doc::text(" ->"),
+                    Doc::Line,
+                    self.term_at(body, Precedence::Arrow),
+                ]);
+
+                (Doc::group(doc), Precedence::Arrow)
+            }
+            &Term::Forall { .. } => {
+                let (binders, body) = telescope(term, |t| match t {
+                    &Term::Forall { ref binder, ref term, .. } => Some((binder, &**term)),
+                    _ => None,
+                });
+
+                (self.binding("forall", &binders, ",", body), Precedence::Binder)
+            }
+            &Term::Lambda { .. } => {
+                let (binders, body) = telescope(term, |t| match t {
+                    &Term::Lambda { ref binder, ref body, .. } => Some((binder, &**body)),
+                    _ => None,
+                });
+
+                (self.binding("fun", &binders, " =>", body), Precedence::Binder)
+            }
+            &Term::Let { ref binder, ref value, ref body, .. } => {
+                let doc = Doc::Concat(vec![
+                    Doc::group(Doc::nest(INDENT, Doc::Concat(vec![
+                        Doc::text(format!("let {} :", binder.name)),
+                        Doc::Line,
+                        self.term(&binder.ty),
+                        Doc::text(" :="),
+                        Doc::Line,
+                        self.term(value),
+                        Doc::text(" in"),
+                    ]))),
+                    Doc::Line,
+                    self.term(body),
+                ]);
+
+                (Doc::group(doc), Precedence::Binder)
+            }
+            &Term::Fix { ref binder, rec_arg, ref body, .. } => {
+                let doc = Doc::nest(INDENT, Doc::Concat(vec![
+                    Doc::text(format!("fix {} : ", binder.name)),
+                    self.term(&binder.ty),
+                    Doc::text(format!(" on {} =>", rec_arg)),
+                    Doc::Line,
+                    self.term(body),
+                ]));
+
+                (Doc::group(doc), Precedence::Binder)
+            }
+        };
+
+        if own < precedence {
+            Doc::Concat(vec![Doc::text("("), Doc::nest(1, doc), Doc::text(")")])
+        } else {
+            doc
+        }
+    }
+
+    /// `keyword binders separator body`, with the body on a new line when
+    /// the whole does not fit.
+    fn binding(&self, keyword: &str, binders: &[&Binder], separator: &str, body: &Term) -> Doc {
+        let mut head = vec![Doc::text(keyword)];
+
+        for binder in binders {
+            head.push(Doc::Line);
+            head.push(self.binder(binder));
+        }
+
+        head.push(Doc::text(separator));
+
+        Doc::group(Doc::nest(INDENT, Doc::Concat(vec![
+            Doc::group(Doc::nest(INDENT, Doc::Concat(head))),
+            Doc::Line,
+            self.term(body),
+        ])))
+    }
+
+    fn binder(&self, binder: &Binder) -> Doc {
+        // Irrelevant binders are marked with a leading `.`.
+        let mark = if binder.is_irrelevant() { "." } else { "" };
+
+        let (open, close) = if binder.is_implicit() {
+            ("{", "}")
+        } else if binder.is_instance() {
+            ("[", "]")
+        } else {
+            ("(", ")")
+        };
+
+        Doc::Concat(vec![
+            Doc::text(format!("{}{}{} : ", mark, open, binder.name)),
+            self.term(&binder.ty),
+            Doc::text(close),
+        ])
+    }
+}
+
+/// The binders of the nested terms `binder` matches, up to the first one
+/// without a name, followed by the body under them.
+fn telescope<'a, F>(term: &'a Term, binder: F) -> (Vec<&'a Binder>, &'a Term)
+    where F: Fn(&'a Term) -> Option<(&'a Binder, &'a Term)>
+{
+    let mut binders = vec![];
+    let mut cursor = term;
+
+    while let Some((b, body)) = binder(cursor) {
+        // Only the first binder of the telescope may be unnamed.
+        if !binders.is_empty() && b.name.is_placeholder() {
+            break;
+        }
+
+        binders.push(b);
+        cursor = body;
+    }
+
+    (binders, cursor)
+}
@@ -0,0 +1,146 @@
+use super::{Project, Error};
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A dependency as written in the manifest, exactly one of `git` or `path`
+/// must be provided.
+#[derive(Debug, Clone, RustcDecodable)]
+pub struct Dependency {
+    pub git: Option<String>,
+    /// A branch, tag or commit to check out, defaults to `master`.
+    pub rev: Option<String>,
+    pub path: Option<String>,
+}
+
+/// A dependency that has been located on disk, along with its loaded project.
+#[derive(Debug, Clone)]
+pub struct ResolvedDependency {
+    pub name: String,
+    pub project: Project,
+}
+
+impl Dependency {
+    fn rev(&self) -> &str {
+        self.rev.as_ref().map(|r| &r[..]).unwrap_or("master")
+    }
+}
+
+/// The directory fetched packages are cached in.
+fn package_cache() -> PathBuf {
+    match env::home_dir() {
+        None => env::temp_dir().join("hubris/packages"),
+        Some(home) => home.join(".hubris/packages"),
+    }
+}
+
+/// Convert a git url into a directory name that is safe to use in the cache.
+fn cache_key(name: &str, url: &str, rev: &str) -> String {
+    let sanitized: String =
+        url.chars()
+           .chain("-".chars())
+           .chain(rev.chars())
+           .map(|c| if c.is_alphanumeric() { c } else { '_' })
+           .collect();
+
+    format!("{}-{}", name, sanitized)
+}
+
+fn run_git(name: &str, args: &[&str], cwd: &Path) -> Result<(), Error> {
+    debug!("run_git: {:?} in {}", args, cwd.display());
+
+    let output = try!(Command::new("git")
+                               .args(args)
+                               .current_dir(cwd)
+                               .output()
+                               .map_err(|e| Error::Fetch(name.to_string(), format!("{}", e))));
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        Err(Error::Fetch(name.to_string(), stderr))
+    }
+}
+
+/// Ensure a git dependency is present in the package cache, cloning it if it
+/// has not been fetched before.
+fn fetch_git(name: &str, url: &str, rev: &str) -> Result<PathBuf, Error> {
+    let cache = package_cache();
+    try!(fs::create_dir_all(&cache));
+
+    let key = cache_key(name, url, rev);
+    let checkout = cache.join(&key);
+
+    // The package is cloned next to where it is cached and only moved into
+    // place once checked out, so a failed fetch leaves nothing to be
+    // mistaken for the package.
+    if !checkout.is_dir() {
+        let partial = cache.join(format!("{}.partial", key));
+        if partial.exists() {
+            try!(fs::remove_dir_all(&partial));
+        }
+
+        let partial_str = format!("{}", partial.display());
+        try!(run_git(name, &["clone", "--quiet", url, &partial_str[..]], &cache));
+        try!(run_git(name, &["checkout", "--quiet", rev], &partial));
+        try!(fs::rename(&partial, &checkout));
+    }
+
+    Ok(checkout)
+}
+
+fn locate(project: &Project, name: &str, dep: &Dependency) -> Result<PathBuf, Error> {
+    match (&dep.git, &dep.path) {
+        (&Some(ref url), &None) => fetch_git(name, url, dep.rev()),
+        (&None, &Some(ref path)) => Ok(project.root.join(path)),
+        _ => Err(Error::InvalidDependency(name.to_string())),
+    }
+}
+
+/// Locate and load every dependency of `project` transitively, fetching
+/// packages that are not yet cached. Dependencies are returned in the order
+/// their source roots should be searched.
+pub fn resolve_dependencies(project: &Project) -> Result<Vec<ResolvedDependency>, Error> {
+    let mut resolved = vec![];
+    let mut seen = HashSet::new();
+    let mut stack = vec![project.name().to_string()];
+
+    try!(resolve_into(project, &mut resolved, &mut seen, &mut stack));
+
+    Ok(resolved)
+}
+
+fn resolve_into(project: &Project,
+                resolved: &mut Vec<ResolvedDependency>,
+                seen: &mut HashSet<String>,
+                stack: &mut Vec<String>) -> Result<(), Error> {
+    for (name, dep) in project.dependencies() {
+        if stack.contains(&name) {
+            return Err(Error::DependencyCycle(name));
+        }
+
+        // Diamond dependencies are only resolved once, the first
+        // occurrence wins.
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+
+        let dir = try!(locate(project, &name, &dep));
+        let dep_project = try!(Project::from_dir(&dir));
+
+        stack.push(name.clone());
+        try!(resolve_into(&dep_project, resolved, seen, stack));
+        stack.pop();
+
+        resolved.push(ResolvedDependency {
+            name: name,
+            project: dep_project,
+        });
+    }
+
+    Ok(())
+}
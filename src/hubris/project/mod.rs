@@ -0,0 +1,252 @@
+//! Support for Hubris projects described by a `Hubris.toml` manifest.
+//!
+//! A manifest looks roughly like this:
+//!
+//! ```toml
+//! [package]
+//! name = "example"
+//! version = "0.1.0"
+//! source_roots = ["src"]
+//!
+//! [modules]
+//! "Data.List" = "src/data/List.hbr"
+//!
+//! [[bin]]
+//! name = "example"
+//! main = "src/Main.hbr"
+//!
+//! [dependencies.logic]
+//! git = "https://github.com/hubris-lang/logic"
+//! rev = "master"
+//!
+//! [dependencies.local]
+//! path = "../local"
+//!
+//! [rust_dependencies]
+//! rand = "0.3"
+//! ```
+//!
+//! When building a project we resolve imports by first consulting the
+//! explicit module mapping and then searching each source root in order,
+//! followed by the source roots of every dependency, falling back to the
+//! global library directory.
+
+mod dependency;
+
+pub use self::dependency::{Dependency, ResolvedDependency, resolve_dependencies};
+
+use rustc_serialize::Decodable;
+use toml;
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// The name of the manifest file we look for in a project directory.
+pub const MANIFEST_NAME: &'static str = "Hubris.toml";
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse(PathBuf, Vec<String>),
+    Decode(PathBuf, toml::DecodeError),
+    NoManifest(PathBuf),
+    NoTargets,
+    UnknownTarget(String),
+    InvalidDependency(String),
+    Fetch(String, String),
+    DependencyCycle(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        use self::Error::*;
+
+        match self {
+            &Io(ref e) => write!(formatter, "{}", e),
+            &Parse(ref path, ref errs) =>
+                write!(formatter, "unable to parse {}: {}", path.display(), errs.join("; ")),
+            &Decode(ref path, ref e) =>
+                write!(formatter, "invalid manifest {}: {}", path.display(), e),
+            &NoManifest(ref dir) =>
+                write!(formatter, "could not find `{}` in {}", MANIFEST_NAME, dir.display()),
+            &NoTargets =>
+                write!(formatter, "the manifest does not declare any binary targets"),
+            &UnknownTarget(ref name) =>
+                write!(formatter, "the manifest has no binary target named `{}`", name),
+            &InvalidDependency(ref name) =>
+                write!(formatter, "dependency `{}` must specify exactly one of `git` or `path`",
+                       name),
+            &Fetch(ref name, ref msg) =>
+                write!(formatter, "failed to fetch dependency `{}`: {}", name, msg),
+            &DependencyCycle(ref name) =>
+                write!(formatter, "cyclic dependency on package `{}`", name),
+        }
+    }
+}
+
+/// The raw contents of a manifest, as decoded from TOML.
+#[derive(Debug, Clone, RustcDecodable)]
+pub struct Manifest {
+    pub package: Package,
+    pub modules: Option<HashMap<String, String>>,
+    pub dependencies: Option<HashMap<String, Dependency>>,
+    pub bin: Option<Vec<Target>>,
+    /// Crates added to the Cargo package the Rust backend generates.
+    pub rust_dependencies: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, RustcDecodable)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    pub source_roots: Option<Vec<String>>,
+}
+
+/// A binary target, each target is compiled from its own main file.
+#[derive(Debug, Clone, RustcDecodable)]
+pub struct Target {
+    pub name: String,
+    pub main: String,
+}
+
+/// A loaded project, with all paths made absolute relative to the
+/// directory containing the manifest.
+#[derive(Debug, Clone)]
+pub struct Project {
+    pub root: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl Project {
+    /// Load the project whose manifest lives in `dir`.
+    pub fn from_dir(dir: &Path) -> Result<Project, Error> {
+        let manifest_path = dir.join(MANIFEST_NAME);
+
+        if !manifest_path.is_file() {
+            return Err(Error::NoManifest(dir.to_owned()));
+        }
+
+        let mut contents = String::new();
+        let mut file = try!(File::open(&manifest_path));
+        try!(file.read_to_string(&mut contents));
+
+        let manifest = try!(parse_manifest(&manifest_path, &contents));
+
+        Ok(Project {
+            root: dir.to_owned(),
+            manifest: manifest,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.manifest.package.name[..]
+    }
+
+    /// The directories imports are resolved against, in search order.
+    pub fn source_roots(&self) -> Vec<PathBuf> {
+        match self.manifest.package.source_roots {
+            None => vec![self.root.join("src")],
+            Some(ref roots) =>
+                roots.iter().map(|r| self.root.join(r)).collect(),
+        }
+    }
+
+    /// The explicit mapping from module name to source file.
+    pub fn module_map(&self) -> HashMap<String, PathBuf> {
+        match self.manifest.modules {
+            None => HashMap::new(),
+            Some(ref modules) =>
+                modules.iter()
+                       .map(|(m, p)| (m.clone(), self.root.join(p)))
+                       .collect(),
+        }
+    }
+
+    /// The dependencies declared directly by this project, sorted by name
+    /// so that fetching and search order are reproducible.
+    pub fn dependencies(&self) -> Vec<(String, Dependency)> {
+        let mut deps: Vec<_> = match self.manifest.dependencies {
+            None => vec![],
+            Some(ref deps) =>
+                deps.iter()
+                    .map(|(n, d)| (n.clone(), d.clone()))
+                    .collect(),
+        };
+
+        deps.sort_by(|a, b| a.0.cmp(&b.0));
+        deps
+    }
+
+    /// The crates declared for the Rust backend, sorted by name.
+    pub fn rust_dependencies(&self) -> Vec<(String, String)> {
+        let mut deps: Vec<_> = match self.manifest.rust_dependencies {
+            None => vec![],
+            Some(ref deps) => deps.clone().into_iter().collect(),
+        };
+
+        deps.sort();
+        deps
+    }
+
+    pub fn targets(&self) -> Vec<Target> {
+        self.manifest.bin.clone().unwrap_or(vec![])
+    }
+
+    /// Find the target to build, if no name is given and there is exactly
+    /// one target we use it, otherwise all targets are returned.
+    pub fn select_targets(&self, name: Option<&str>) -> Result<Vec<Target>, Error> {
+        let targets = self.targets();
+
+        if targets.len() == 0 {
+            return Err(Error::NoTargets);
+        }
+
+        match name {
+            None => Ok(targets),
+            Some(name) => {
+                match targets.into_iter().find(|t| t.name == name) {
+                    None => Err(Error::UnknownTarget(name.to_string())),
+                    Some(t) => Ok(vec![t]),
+                }
+            }
+        }
+    }
+
+    pub fn main_file(&self, target: &Target) -> PathBuf {
+        self.root.join(&target.main)
+    }
+
+    /// The directory build products are placed in.
+    pub fn output_dir(&self) -> PathBuf {
+        self.root.join("target")
+    }
+}
+
+pub fn parse_manifest(path: &Path, contents: &str) -> Result<Manifest, Error> {
+    let mut parser = toml::Parser::new(contents);
+
+    let table = match parser.parse() {
+        None => {
+            let errs = parser.errors
+                             .iter()
+                             .map(|e| e.desc.clone())
+                             .collect();
+            return Err(Error::Parse(path.to_owned(), errs));
+        }
+        Some(table) => table,
+    };
+
+    let mut decoder = toml::Decoder::new(toml::Value::Table(table));
+
+    Decodable::decode(&mut decoder)
+        .map_err(|e| Error::Decode(path.to_owned(), e))
+}
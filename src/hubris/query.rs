@@ -0,0 +1,549 @@
+//! The compiler's phases as queries answered on demand.
+//!
+//! A `Database` holds the text of each file a compilation reads, and
+//! memoizes the answers to `parse(file)`, `resolve(file)` and
+//! `elaborated(file)`. The queries `type_of` and `elaborated_def` project a
+//! single global out of the elaboration of a file.
+//!
+//! A file is the unit that is recomputed. Each answer records what it was
+//! computed from: a parse the revision of the file's text, a resolution the
+//! parse, and an elaboration the parse and the fingerprint of each of its
+//! imports. When a file changes only the answers that depend on it are
+//! computed again. An elaboration is fingerprinted by the declarations it
+//! produced rather than by its text, so an edit that leaves those unchanged,
+//! such as to a comment, does not cause the modules importing it to be
+//! elaborated again. The positions an elaboration recorded refer to the text
+//! of its imports when it was computed.
+//!
+//! The driver uses a database for a single compilation, the language server
+//! keeps one across the edits made to each document.
+
+use super::ast::{self, ModuleId, SourceMap};
+use super::core;
+use super::diagnostic::Diagnostic;
+use super::elaborate::{self, ElabCx};
+use super::parser::{self, Parser};
+use super::session::{HasSession, Reportable, Session};
+use super::timing::{Instant, Phase};
+use super::typeck::{self, ImportSource};
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parser(parser::Error),
+    /// A module that imports itself, through each of the files listed.
+    ImportCycle(Vec<PathBuf>),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<parser::Error> for Error {
+    fn from(err: parser::Error) -> Error {
+        Error::Parser(err)
+    }
+}
+
+impl Reportable for Error {
+    fn report(self, session: &Session) -> io::Result<()> {
+        match self {
+            Error::Io(e) => Err(e),
+            Error::Parser(e) => session.report(e),
+            Error::ImportCycle(files) => {
+                let mut diagnostic =
+                    Diagnostic::error(format!("`{}` imports itself", files[0].display()));
+
+                for (i, file) in files.iter().enumerate() {
+                    let next = &files[(i + 1) % files.len()];
+                    diagnostic = diagnostic.note(
+                        format!("`{}` imports `{}`", file.display(), next.display()));
+                }
+
+                diagnostic.emit(session)
+            }
+        }
+    }
+}
+
+/// A point in the history of the inputs, each change advances it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Revision(usize);
+
+struct Source {
+    text: String,
+    /// The revision the text last changed at.
+    changed_at: Revision,
+    /// Whether the text was read from disk, rather than set by `set_source`.
+    on_disk: bool,
+}
+
+/// A memoized answer along with what it was computed from.
+struct Memo<I, T> {
+    inputs: I,
+    /// The revision the answer was last found to be up to date at.
+    verified_at: Revision,
+    value: T,
+}
+
+/// The parse of a file.
+pub struct Parsed {
+    pub module_id: ModuleId,
+    pub source_map: SourceMap,
+    pub module: ast::Module,
+    /// The lossless syntax tree beneath `module`.
+    pub syntax: SyntaxTree,
+}
+
+/// The imports of a module that are elaborated from source.
+pub struct Resolved {
+    /// The name of each of these imports along with its file.
+    pub imports: Vec<(core::Name, PathBuf)>,
+}
+
+/// The elaboration of a file.
+pub struct Elaborated {
+    pub module_id: ModuleId,
+    pub source_map: SourceMap,
+    /// The context the module was elaborated in, which includes its imports.
+    pub ecx: ElabCx,
+    /// The elaborated module, `None` if elaboration failed, the errors
+    /// have been reported when it was computed.
+    pub module: Option<core::Module>,
+    /// A hash of what the module provides to its importers.
+    pub fingerprint: u64,
+}
+
+#[derive(PartialEq, Eq)]
+struct ElabInputs {
+    module_id: ModuleId,
+    /// The file of each import and the fingerprint of its elaboration.
+    imports: Vec<(PathBuf, u64)>,
+}
+
+pub struct Database {
+    session: Session,
+    revision: Revision,
+    sources: HashMap<PathBuf, Source>,
+    parsed: HashMap<PathBuf, Memo<Revision, Result<Rc<Parsed>, parser::Error>>>,
+    resolved: HashMap<PathBuf, Memo<ModuleId, Rc<Resolved>>>,
+    elaborated: HashMap<PathBuf, Memo<ElabInputs, Rc<Elaborated>>>,
+    /// The files being elaborated, innermost last, used to find cycles of
+    /// imports.
+    active: Vec<PathBuf>,
+}
+
+impl HasSession for Database {
+    fn session(&self) -> &Session {
+        &self.session
+    }
+}
+
+impl Database {
+    pub fn new(session: Session) -> Database {
+        Database {
+            session: session,
+            revision: Revision(0),
+            sources: HashMap::new(),
+            parsed: HashMap::new(),
+            resolved: HashMap::new(),
+            elaborated: HashMap::new(),
+            active: vec![],
+        }
+    }
+
+    pub fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    fn advance(&mut self) {
+        self.revision = Revision(self.revision.0 + 1);
+    }
+
+    /// Use `text` as the contents of `path` instead of what is on disk.
+    pub fn set_source(&mut self, path: &Path, text: String) {
+        let unchanged = self.sources.get(path).map(|s| s.text == text).unwrap_or(false);
+
+        if unchanged {
+            if let Some(source) = self.sources.get_mut(path) {
+                source.on_disk = false;
+            }
+            return;
+        }
+
+        self.advance();
+        self.sources.insert(path.to_owned(), Source {
+            text: text,
+            changed_at: self.revision,
+            on_disk: false,
+        });
+    }
+
+    /// Forget the text of `path`, it is read from disk when next needed.
+    pub fn clear_source(&mut self, path: &Path) {
+        if self.sources.remove(path).is_some() {
+            self.advance();
+        }
+    }
+
+    /// Read the files that were taken from disk again, any that changed
+    /// are recomputed when next needed.
+    pub fn reload(&mut self) -> io::Result<()> {
+        let mut changed = vec![];
+
+        for (path, source) in &self.sources {
+            if source.on_disk {
+                let text = try!(read_file(path));
+                if text != source.text {
+                    changed.push((path.clone(), text));
+                }
+            }
+        }
+
+        if !changed.is_empty() {
+            self.advance();
+
+            for (path, text) in changed {
+                self.sources.insert(path, Source {
+                    text: text,
+                    changed_at: self.revision,
+                    on_disk: true,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The revision the text of `path` last changed at.
+    fn source(&mut self, path: &Path) -> Result<Revision, Error> {
+        if !self.sources.contains_key(path) {
+            let text = try!(read_file(path));
+            self.sources.insert(path.to_owned(), Source {
+                text: text,
+                changed_at: self.revision,
+                on_disk: true,
+            });
+        }
+
+        Ok(self.sources[path].changed_at)
+    }
+
+    /// The parse of `path`.
+    pub fn parse(&mut self, path: &Path) -> Result<Rc<Parsed>, Error> {
+        let changed_at = try!(self.source(path));
+
+        if let Some(memo) = self.parsed.get(path) {
+            if memo.inputs == changed_at {
+                return memo.value.clone().map_err(Error::Parser);
+            }
+        }
+
+        let module_id = self.session.next_module_id();
+        self.session.trace(format!("parsing {}", path.display()));
+        let start = Instant::now();
+
+        // Only the code blocks of a literate file are parsed, in place.
+        let text = if parser::is_literate(path) {
+            parser::literate_code(&self.sources[path].text)
+        } else {
+            self.sources[path].text.clone()
+        };
+
+        let parser = Parser {
+            source_map: SourceMap::from_file(format!("{}", path.display()), text),
+            id: module_id,
+        };
+
+        let parsed = parser.parse_lossless().map(|(module, syntax)| {
+            Rc::new(Parsed {
+                module_id: module_id,
+                source_map: parser.source_map.clone(),
+                module: module,
+                syntax: syntax,
+            })
+        });
+
+        self.session.record_time(Phase::Parse, None, start);
+        self.session.add_source_map_for(module_id, parser.source_map);
+
+        self.parsed.insert(path.to_owned(), Memo {
+            inputs: changed_at,
+            verified_at: self.revision,
+            value: parsed.clone(),
+        });
+
+        parsed.map_err(Error::Parser)
+    }
+
+    /// The imports of `path` that are elaborated from source. Those
+    /// provided by export files, or whose file does not exist, are left to
+    /// the elaborator.
+    pub fn resolve(&mut self, path: &Path) -> Result<Rc<Resolved>, Error> {
+        let parsed = try!(self.parse(path));
+
+        if let Some(memo) = self.resolved.get(path) {
+            if memo.inputs == parsed.module_id {
+                return Ok(memo.value.clone());
+            }
+        }
+
+        let mut imports = vec![];
+
+        for item in &parsed.module.items {
+            let name = match item {
+                &ast::Item::Import(ref n) => elaborate::to_qualified_name(n.clone()),
+                _ => None,
+            };
+
+            if let Some(name) = name {
+                if let Ok(ImportSource::Source(file)) = typeck::resolve_import(&self.session, &name) {
+                    if file.is_file() || self.sources.contains_key(&file) {
+                        imports.push((name, file));
+                    }
+                }
+            }
+        }
+
+        let resolved = Rc::new(Resolved { imports: imports });
+
+        self.resolved.insert(path.to_owned(), Memo {
+            inputs: parsed.module_id,
+            verified_at: self.revision,
+            value: resolved.clone(),
+        });
+
+        Ok(resolved)
+    }
+
+    /// The elaboration of `path`. Errors in the module are reported when it
+    /// is elaborated, only those that prevent elaborating it are returned.
+    pub fn elaborated(&mut self, path: &Path) -> Result<Rc<Elaborated>, Error> {
+        if let Some(memo) = self.elaborated.get(path) {
+            if memo.verified_at == self.revision {
+                return Ok(memo.value.clone());
+            }
+        }
+
+        if let Some(i) = self.active.iter().position(|p| p == path) {
+            return Err(Error::ImportCycle(self.active[i..].to_vec()));
+        }
+
+        self.active.push(path.to_owned());
+        let result = self.verify_or_elaborate(path);
+        self.active.pop();
+
+        result
+    }
+
+    fn verify_or_elaborate(&mut self, path: &Path) -> Result<Rc<Elaborated>, Error> {
+        let parsed = try!(self.parse(path));
+        let resolved = try!(self.resolve(path));
+
+        let mut imports = vec![];
+
+        for &(ref name, ref file) in &resolved.imports {
+            imports.push((name.clone(), file.clone(), try!(self.elaborated(file))));
+        }
+
+        let inputs = ElabInputs {
+            module_id: parsed.module_id,
+            imports: imports.iter()
+                            .map(|&(_, ref file, ref import)| (file.clone(), import.fingerprint))
+                            .collect(),
+        };
+
+        let revision = self.revision;
+
+        if let Some(memo) = self.elaborated.get_mut(path) {
+            if memo.inputs == inputs {
+                memo.verified_at = revision;
+                return Ok(memo.value.clone());
+            }
+        }
+
+        let elaborated = Rc::new(try!(self.elaborate(path, &parsed, imports)));
+
+        self.elaborated.insert(path.to_owned(), Memo {
+            inputs: inputs,
+            verified_at: revision,
+            value: elaborated.clone(),
+        });
+
+        Ok(elaborated)
+    }
+
+    fn elaborate(&self,
+                 path: &Path,
+                 parsed: &Parsed,
+                 imports: Vec<(core::Name, PathBuf, Rc<Elaborated>)>) -> Result<Elaborated, Error> {
+        let mut ecx = ElabCx::from_module(parsed.module.clone(), self.session.for_file(path));
+
+        for (name, _, import) in imports {
+            ecx.provide_import(name, import);
+        }
+
+        let module = match ecx.elaborate_module() {
+            Err(e) => {
+                try!(ecx.report(e));
+                None
+            }
+            Ok(module) => Some(module),
+        };
+
+        let fingerprint = fingerprint(module.as_ref(), &ecx);
+
+        Ok(Elaborated {
+            module_id: parsed.module_id,
+            source_map: parsed.source_map.clone(),
+            ecx: ecx,
+            module: module,
+            fingerprint: fingerprint,
+        })
+    }
+
+    /// `paths` ordered so that each file comes after those among them that
+    /// it imports.
+    pub fn import_order(&mut self, paths: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+        let mut order = vec![];
+
+        for path in paths {
+            try!(self.visit_imports(path, paths, &mut order, &mut vec![]));
+        }
+
+        Ok(order)
+    }
+
+    fn visit_imports(&mut self,
+                     path: &Path,
+                     paths: &[PathBuf],
+                     order: &mut Vec<PathBuf>,
+                     active: &mut Vec<PathBuf>) -> Result<(), Error> {
+        if order.iter().any(|p| p == path) {
+            return Ok(());
+        }
+
+        if let Some(i) = active.iter().position(|p| p == path) {
+            return Err(Error::ImportCycle(active[i..].to_vec()));
+        }
+
+        let resolved = match self.resolve(path) {
+            // A file that does not parse imports nothing, its errors are
+            // reported when it is elaborated.
+            Err(Error::Parser(_)) => {
+                order.push(path.to_owned());
+                return Ok(());
+            }
+            result => try!(result),
+        };
+
+        active.push(path.to_owned());
+
+        for &(_, ref file) in &resolved.imports {
+            if paths.contains(file) {
+                try!(self.visit_imports(file, paths, order, active));
+            }
+        }
+
+        active.pop();
+        order.push(path.to_owned());
+
+        Ok(())
+    }
+
+    /// The elaboration of `path`, taken out of the database for a phase
+    /// that consumes it such as code generation.
+    pub fn into_elaborated(mut self, path: &Path) -> Result<Elaborated, Error> {
+        let elaborated = try!(self.elaborated(path));
+        drop(self);
+
+        Ok(Rc::try_unwrap(elaborated).ok().expect("the root module is imported by another module"))
+    }
+
+    /// The declaration of the global `name` elaborated from `path`.
+    pub fn elaborated_def(&mut self, path: &Path, name: &core::Name)
+                          -> Result<Option<core::Item>, Error> {
+        let elaborated = try!(self.elaborated(path));
+
+        let item = elaborated.module.as_ref().and_then(|module| {
+            module.defs.iter().find(|item| item.name() == name).cloned()
+        });
+
+        Ok(item)
+    }
+
+    /// The type of the global `name` as seen from `path`, which may declare
+    /// or import it.
+    pub fn type_of(&mut self, path: &Path, name: &core::Name)
+                   -> Result<Option<core::Term>, Error> {
+        let elaborated = try!(self.elaborated(path));
+        let ty_cx = &elaborated.ecx.ty_cx;
+
+        let ty = ty_cx.definitions.get(name).map(|def| def.ty.clone())
+                      .or_else(|| ty_cx.axioms.get(name).map(|axiom| axiom.ty.clone()));
+
+        Ok(ty)
+    }
+}
+
+fn read_file(path: &Path) -> io::Result<String> {
+    let mut text = String::new();
+    let mut file = try!(File::open(path));
+    try!(file.read_to_string(&mut text));
+    Ok(text)
+}
+
+/// A hash of the declarations of `module` and the tables of attributes its
+/// importers consult, spans are not included.
+fn fingerprint(module: Option<&core::Module>, ecx: &ElabCx) -> u64 {
+    let ty_cx = &ecx.ty_cx;
+    let mut parts = vec![];
+
+    match module {
+        None => parts.push("failed".to_string()),
+        Some(module) => {
+            for item in &module.defs {
+                parts.push(format!("{}", item));
+            }
+        }
+    }
+
+    for name in &ty_cx.instances {
+        parts.push(format!("instance {}", name));
+    }
+
+    for hint in &ty_cx.unify_hints {
+        parts.push(format!("unify_hint {} {} {}", hint.projection, hint.head, hint.instance));
+    }
+
+    for (name, routine) in &ty_cx.elab_as {
+        parts.push(format!("elab_as {} {}", name, routine));
+    }
+
+    for (name, template) in &ty_cx.error_messages {
+        parts.push(format!("error_message {} {}", name, template));
+    }
+
+    for (name, wrapper) in &ty_cx.rust_exports {
+        parts.push(format!("export_rust {} {}", name, wrapper));
+    }
+
+    for (name, interface) in &ty_cx.interfaces {
+        let names: Vec<_> = interface.iter().map(|n| format!("{}", n)).collect();
+        parts.push(format!("interface {} {}", name, names.join(" ")));
+    }
+
+    let mut hasher = DefaultHasher::new();
+    parts.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,205 @@
+//! The outputs diagnostics are rendered to.
+//!
+//! A renderer is written to like any other output, and can in addition be
+//! asked to color what follows and for its width. Color is only used when
+//! the output is a terminal that supports it and `NO_COLOR` is not set,
+//! the width is taken from `COLUMNS`.
+
+use std::cell::RefCell;
+use std::env;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use term::{self, color, Terminal};
+
+pub trait Renderer: Write {
+    /// Write what follows in `color`, if the renderer uses color.
+    fn fg(&mut self, color: color::Color) -> io::Result<()>;
+
+    /// Write what follows in the default color.
+    fn reset(&mut self) -> io::Result<()>;
+
+    /// The number of columns of the output, if it is known.
+    fn width(&self) -> Option<usize>;
+}
+
+/// A renderer for standard output.
+pub fn stdout() -> Box<Renderer> {
+    if use_color() {
+        if let Some(terminal) = term::stdout() {
+            if terminal.supports_color() {
+                return Box::new(Colored { terminal: terminal, width: width() });
+            }
+        }
+    }
+
+    Box::new(Plain { out: io::stdout(), width: width() })
+}
+
+/// A renderer for standard error.
+pub fn stderr() -> Box<Renderer> {
+    if use_color() {
+        if let Some(terminal) = term::stderr() {
+            if terminal.supports_color() {
+                return Box::new(Colored { terminal: terminal, width: width() });
+            }
+        }
+    }
+
+    Box::new(Plain { out: io::stderr(), width: width() })
+}
+
+/// Whether color was not turned off with `NO_COLOR`, see
+/// https://no-color.org.
+fn use_color() -> bool {
+    env::var_os("NO_COLOR").map_or(true, |value| value.is_empty())
+}
+
+/// The width of the terminal as given by `COLUMNS`.
+fn width() -> Option<usize> {
+    env::var("COLUMNS").ok()
+                       .and_then(|columns| columns.parse().ok())
+                       .and_then(|width| if width > 0 { Some(width) } else { None })
+}
+
+/// A terminal written to in color.
+pub struct Colored<T: Terminal + ?Sized> {
+    terminal: Box<T>,
+    width: Option<usize>,
+}
+
+impl<T: Terminal + ?Sized> Write for Colored<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.terminal.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.terminal.flush()
+    }
+}
+
+impl<T: Terminal + ?Sized> Renderer for Colored<T> {
+    fn fg(&mut self, color: color::Color) -> io::Result<()> {
+        try!(self.terminal.fg(color));
+        Ok(())
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        try!(self.terminal.reset());
+        Ok(())
+    }
+
+    fn width(&self) -> Option<usize> {
+        self.width
+    }
+}
+
+/// An output written to without color.
+pub struct Plain<W: Write> {
+    pub out: W,
+    pub width: Option<usize>,
+}
+
+impl<W: Write> Write for Plain<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl<W: Write> Renderer for Plain<W> {
+    fn fg(&mut self, _: color::Color) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn width(&self) -> Option<usize> {
+        self.width
+    }
+}
+
+/// Discards everything written to it, used when diagnostics are reported
+/// by other means, such as in JSON, or not at all.
+pub struct Null;
+
+impl Write for Null {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Renderer for Null {
+    fn fg(&mut self, _: color::Color) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn width(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// An in-memory buffer written to without color, whose contents stay
+/// readable through its clones, used to test diagnostics.
+#[derive(Clone, Debug, Default)]
+pub struct Buffer {
+    contents: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Buffer {
+    pub fn new() -> Buffer {
+        Buffer::default()
+    }
+
+    /// What has been written so far.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.contents.borrow()).into_owned()
+    }
+
+    /// What has been written so far, leaving the buffer empty.
+    pub fn take(&self) -> String {
+        let contents = self.contents();
+        self.contents.borrow_mut().clear();
+        contents
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.contents.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Renderer for Buffer {
+    fn fg(&mut self, _: color::Color) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Unknown, so that what is written does not depend on the terminal
+    /// the tests are run in.
+    fn width(&self) -> Option<usize> {
+        None
+    }
+}
@@ -2,13 +2,22 @@ use super::core;
 use super::elaborate::{self, ElabCx, LocalElabCx};
 use super::parser;
 use super::session::{Session, Reportable, HasSession};
-use super::ast::{self, ModuleId, SourceMap};
+use super::ast::{self, HasSpan, ModuleId, SourceMap};
+use super::syntax::visit::*;
 use super::typeck;
 
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
 use std::error::Error as TraitError;
-use std::io::{self, ErrorKind, stdout};
-use std::path::{PathBuf};
-use readline;
+use std::fs::File;
+use std::io::{self, ErrorKind, Write, stdout};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use rustyline::{self, Editor};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
 
 use super::pretty::*;
 
@@ -16,17 +25,48 @@ use term;
 
 const HELP_MESSAGE: &'static str = r#"
 Commands:
-    :help          Show this message
-    :type <term>   Infer the type of <term>
-    :reload        Reload the session
-    :def           Print a debug representation of a term
-    :import <path> Import the module found at <path>
-    :quit          Exit
+    :help            Show this message
+    :type <term>     Infer the type of <term>
+    :load <path>     Load the file at <path>, replacing the current one
+    :reload          Reload the current file and everything it imports
+    :browse [prefix] List the names in scope, and their types
+    :def <name>      Print the body of a definition
+    :unfold <name>   Print the normal form of a definition
+    :import <path>   Import the module found at <path>
+    :save <path>     Write the declarations entered in this session to <path>
+    :open <path>     Enter each declaration in the file at <path>
+    :quit            Exit
 "#;
 
+/// The commands, completed after a leading `:`.
+const COMMANDS: &'static [&'static str] =
+    &["help", "type", "load", "reload", "browse", "def", "unfold", "import", "save", "open",
+      "quit"];
+
+/// Prepended to the declarations entered at the prompt, so they parse as
+/// the items of a module.
+const DECLARATION_HEADER: &'static str = "module Repl\n";
+
+/// The keywords an item begins with, input starting with one of these is
+/// declared rather than evaluated.
+const DECLARATION_KEYWORDS: &'static [&'static str] =
+    &["def", "inductive", "axiom", "extern", "import", "variables", "macro"];
+
 pub struct Repl {
     elab_cx: ElabCx,
     session: Session,
+    /// The declarations entered in this session, in an order they can be
+    /// checked in. They are entered again on reload, and written out by
+    /// `:save`.
+    transcript: Vec<Declaration>,
+    /// Definitions depending on a name that has been redefined, they are
+    /// checked against the new version when they are next used.
+    stale: BTreeMap<core::Name, core::Definition>,
+}
+
+struct Declaration {
+    name: Option<core::Name>,
+    source: String,
 }
 
 // impl From<parser::Error> for Error {
@@ -79,11 +119,16 @@ pub enum Error {
 #[derive(Debug)]
 enum Command {
     Quit,
+    Load(String),
     Reload,
     Unknown(String),
     TypeOf(String),
+    Browse(String),
     Def(String),
+    Unfold(String),
     Import(String),
+    Save(String),
+    Open(String),
     Help,
 }
 
@@ -103,6 +148,138 @@ fn split_command(command_text: &str) -> (&str, &str) {
     (command_text, "")
 }
 
+/// Whether `input` declares something, rather than being a term to evaluate.
+fn is_declaration(input: &str) -> bool {
+    let first = split_command(input.trim_left()).0;
+    first.starts_with("@[") || DECLARATION_KEYWORDS.contains(&first)
+}
+
+/// Whether the declaration `input` ends before it is complete, and
+/// continues on the next line.
+fn is_incomplete(input: &str) -> bool {
+    let source = format!("{}{}", DECLARATION_HEADER, input);
+
+    match parser::from_string(source, ModuleId(0)).unwrap().parse() {
+        Err(parser::Error::UnexpectedEOF { .. }) => true,
+        _ => false,
+    }
+}
+
+/// Completes commands, and the names of the globals and constructors in
+/// scope, which are updated after each line is handled.
+#[derive(Clone)]
+struct Completions {
+    names: Rc<RefCell<BTreeSet<String>>>,
+}
+
+impl Completions {
+    fn new() -> Completions {
+        Completions { names: Rc::new(RefCell::new(BTreeSet::new())) }
+    }
+
+    fn update(&self, repl: &Repl) {
+        let ty_cx = &repl.elab_cx.ty_cx;
+
+        let ctors = ty_cx.types.values().flat_map(|data| data.ctors.iter().map(|c| &c.0));
+
+        *self.names.borrow_mut() =
+            ty_cx.definitions.keys()
+                 .chain(ty_cx.axioms.keys())
+                 .chain(ty_cx.types.keys())
+                 .chain(ctors)
+                 .chain(repl.stale.keys())
+                 .map(|n| format!("{}", n))
+                 .collect();
+    }
+}
+
+impl Completer for Completions {
+    fn complete(&self, line: &str, pos: usize) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos].char_indices()
+                               .rev()
+                               .find(|&(_, c)| !is_name_char(c))
+                               .map(|(i, c)| i + c.len_utf8())
+                               .unwrap_or(0);
+        let prefix = &line[start..pos];
+
+        let candidates = if start == 1 && line.starts_with(":") {
+            COMMANDS.iter()
+                    .filter(|cmd| cmd.starts_with(prefix))
+                    .map(|cmd| cmd.to_string())
+                    .collect()
+        } else if prefix.is_empty() {
+            vec![]
+        } else {
+            self.names.borrow()
+                .iter()
+                .filter(|name| name.starts_with(prefix))
+                .cloned()
+                .collect()
+        };
+
+        Ok((start, candidates))
+    }
+}
+
+/// Whether `c` can appear in a qualified name.
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.' || c == '\''
+}
+
+/// The file the history is kept in between sessions.
+fn history_file() -> PathBuf {
+    env::home_dir().unwrap_or(PathBuf::from(".")).join(".hubris_history")
+}
+
+/// Collects the global names a term or item refers to.
+struct Mentions {
+    names: BTreeSet<core::Name>,
+}
+
+impl<'v> Visitor<'v> for Mentions {
+    fn visit_term(&mut self, term: &'v ast::Term) {
+        if let &ast::Term::Var { ref name, .. } = term {
+            if let Some(name) = elaborate::to_qualified_name(name.clone()) {
+                self.names.insert(name);
+            }
+        }
+
+        walk_term(self, term)
+    }
+}
+
+/// Add the globals `term` refers to to `names`.
+fn globals(term: &core::Term, names: &mut BTreeSet<core::Name>) {
+    match term {
+        &core::Term::Var { ref name } => if let &core::Name::Qual { .. } = name {
+            names.insert(name.clone());
+        },
+        &core::Term::App { ref fun, ref arg, .. } => {
+            globals(fun, names);
+            globals(arg, names);
+        }
+        &core::Term::Forall { ref binder, term: ref body, .. } |
+        &core::Term::Lambda { ref binder, ref body, .. } |
+        &core::Term::Fix { ref binder, ref body, .. } => {
+            globals(&binder.ty, names);
+            globals(body, names);
+        }
+        &core::Term::Let { ref binder, ref value, ref body, .. } => {
+            globals(&binder.ty, names);
+            globals(value, names);
+            globals(body, names);
+        }
+        &core::Term::Type => {}
+    }
+}
+
+fn definition_globals(def: &core::Definition) -> BTreeSet<core::Name> {
+    let mut names = BTreeSet::new();
+    globals(&def.ty, &mut names);
+    globals(&def.body, &mut names);
+    names
+}
+
 impl Repl {
     pub fn from_session(session: Session) -> Result<Repl, Error> {
         let id = session.next_module_id();
@@ -134,25 +311,48 @@ impl Repl {
         Ok(Repl {
             elab_cx: ecx,
             session: session,
+            transcript: vec![],
+            stale: BTreeMap::new(),
         })
     }
 
     /// Starts the read-eval-print-loop for querying the language.
     pub fn start(mut self) -> Result<(), Error> {
-        readline::read_history(&PathBuf::from("~/.hubris_history"));
+        let completions = Completions::new();
+        let mut editor = Editor::new();
+        editor.set_completer(Some(completions.clone()));
+
+        // There is no history the first time the REPL is run.
+        let history = history_file();
+        let _ = editor.load_history(&history);
 
         loop {
+            completions.update(&self);
+
             // First we grab a line ...
-            let input = match readline::readline("hubris> ") {
-                None => {
+            let mut input = match editor.readline("hubris> ") {
+                // Interrupting abandons the line being edited.
+                Err(ReadlineError::Interrupted) => continue,
+                Err(_) => {
                     println!("");
                     break;
                 }
-                Some(input) => input,
+                Ok(input) => input,
             };
 
+            // ... and the rest of a declaration spanning several.
+            while is_declaration(&input) && is_incomplete(&input) {
+                match editor.readline("   ...> ") {
+                    Err(_) => break,
+                    Ok(line) => {
+                        input.push('\n');
+                        input.push_str(&line);
+                    }
+                }
+            }
+
             // Add it to the history
-            readline::add_history(input.as_ref());
+            editor.add_history_entry(&input);
 
             match self.repl_iteration(input) {
                 Err(e) => {
@@ -167,7 +367,9 @@ impl Repl {
             }
         }
 
-        readline::write_history(&PathBuf::from("~/.hubris_history"));
+        if let Err(e) = editor.save_history(&history) {
+            println!("could not save the history to {}: {}", history.display(), e);
+        }
 
         Ok(())
     }
@@ -179,11 +381,20 @@ impl Repl {
             let cmd = self.parse_command(&input[1..]);
             match cmd {
                 Command::Quit => return Ok(Cont::Quit),
-                Command::Reload => {
-                    let new_repl =
-                        try!(Repl::from_session(self.session.clone()));
-                    *self = new_repl;
+                Command::Load(path) => {
+                    let full_path = PathBuf::from(path.trim());
+
+                    if !full_path.is_file() {
+                        println!("file not found: {}", full_path.display());
+                    } else {
+                        self.session.set_loaded_file(full_path);
+                        try!(self.reload());
+                    }
                 }
+                // Rebuilding the context from scratch throws away every
+                // definition, so anything depending on an edited one is
+                // re-checked against the new version.
+                Command::Reload => try!(self.reload()),
                 Command::Unknown(u) => return Err(Error::UnknownCommand(u)),
                 Command::TypeOf(t) => {
                     let term = try!(self.preprocess_term(t));
@@ -191,6 +402,25 @@ impl Repl {
                     let typed = try!(self.elab_cx.ty_cx.eval(&typed));
                     println!("{}", typed)
                 }
+                Command::Browse(prefix) => self.browse(prefix.trim()),
+                Command::Unfold(name) => {
+                    let name = match try!(self.preprocess_term(name)) {
+                        core::Term::Var { name } => name,
+                        t => {
+                            println!("expected a name, found: {}", t);
+                            return Ok(Cont::Done);
+                        }
+                    };
+
+                    if !self.elab_cx.ty_cx.definitions.contains_key(&name) {
+                        println!("could not find a definition for {}", name);
+                    } else {
+                        let body = try!(self.elab_cx.ty_cx.unfold_name(&name));
+                        let normal_form = try!(self.elab_cx.ty_cx.eval(&body));
+                        try!(Doc::render(&normal_form.pretty(), 80, &mut stdout()));
+                        println!("");
+                    }
+                }
                 Command::Def(name) => {
                     let name = match try!(self.preprocess_term(name)) {
                         core::Term::Var { name } => name,
@@ -234,9 +464,25 @@ impl Repl {
                         Ok(_) => ()
                     }
                 }
+                Command::Save(path) => {
+                    let path = PathBuf::from(path.trim());
+                    try!(self.save(&path));
+                    println!("saved {} declarations to {}", self.transcript.len(), path.display());
+                }
+                Command::Open(path) => {
+                    let path = PathBuf::from(path.trim());
+
+                    if !path.is_file() {
+                        println!("file not found: {}", path.display());
+                    } else {
+                        try!(self.open(&path));
+                    }
+                }
                 Command::Help => println!("{}", HELP_MESSAGE),
                 // Command::Debug =>
             }
+        } else if is_declaration(&input) {
+            try!(self.handle_declarations(input));
         } else {
             try!(self.handle_input(input.to_string()));
         }
@@ -244,13 +490,261 @@ impl Repl {
         Ok(Cont::Done)
     }
 
+    fn reload(&mut self) -> Result<(), Error> {
+        let transcript = mem::replace(&mut self.transcript, vec![]);
+        let new_repl =
+            try!(Repl::from_session(self.session.clone()));
+        *self = new_repl;
+
+        // The declarations entered at the prompt outlive the file, each is
+        // entered again against the reloaded definitions.
+        for declaration in transcript {
+            if let Err(e) = self.handle_declarations(declaration.source) {
+                try!(self.report(e));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Declare the items in `source`, entered at the prompt.
+    fn handle_declarations(&mut self, source: String) -> Result<(), Error> {
+        let id = self.session.next_module_id();
+        let source = format!("{}{}", DECLARATION_HEADER, source);
+        let parser = parser::from_string(source.clone(), id).unwrap();
+        let module = parser.parse();
+        self.session.add_source_map_for(id, parser.source_map);
+
+        for item in try!(module).items {
+            let span = item.get_span();
+            try!(self.declare(item, source[span.lo..span.hi].to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Declare the items of the file at `path`, as though each had been
+    /// entered at the prompt.
+    fn open(&mut self, path: &Path) -> Result<(), Error> {
+        let id = self.session.next_module_id();
+        let parser = try!(parser::from_file(path, id));
+        let source = parser.source_map.source.clone();
+        let module = parser.parse();
+        self.session.add_source_map_for(id, parser.source_map);
+
+        for item in try!(module).items {
+            let span = item.get_span();
+            if let Err(e) = self.declare(item, source[span.lo..span.hi].to_string()) {
+                try!(self.report(e));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the transcript to `path` as a module named after the file.
+    fn save(&self, path: &Path) -> Result<(), Error> {
+        let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("Session");
+        let mut file = try!(File::create(path));
+
+        try!(writeln!(file, "module {}", name));
+
+        for declaration in &self.transcript {
+            try!(write!(file, "\n{}\n", declaration.source));
+        }
+
+        Ok(())
+    }
+
+    /// Elaborate `item`, whose source is `source`, replacing the earlier
+    /// declaration of the name it declares if there is one.
+    ///
+    /// The definitions depending on a replaced name are taken out of scope
+    /// until they are next used, when they are checked against the new
+    /// version. Inductive types are not replaced, as their constructors and
+    /// recursor would need to be as well.
+    fn declare(&mut self, item: ast::Item, source: String) -> Result<(), Error> {
+        if !try!(elaborate::is_configured(&item, &self.session.options().cfg)) {
+            return Ok(());
+        }
+
+        try!(self.recheck_mentioned(|mentions| mentions.visit_item(&item)));
+
+        let name = elaborate::item_name(&item)
+                       .and_then(|name| elaborate::to_qualified_name(name.clone()));
+
+        let (definition, axiom, convention) = match name {
+            None => (None, None, None),
+            Some(ref name) => {
+                let ty_cx = &mut self.elab_cx.ty_cx;
+
+                if ty_cx.types.contains_key(name) {
+                    println!("{} is an inductive type, use :reload to change it", name);
+                    return Ok(());
+                }
+
+                (ty_cx.definitions.remove(name),
+                 ty_cx.axioms.remove(name),
+                 ty_cx.externs.remove(name))
+            }
+        };
+
+        let redefined = definition.is_some() || axiom.is_some();
+
+        if let Err(e) = self.elab_cx.elaborate_def(item) {
+            if let Some(ref name) = name {
+                let ty_cx = &mut self.elab_cx.ty_cx;
+
+                if let Some(def) = definition {
+                    ty_cx.definitions.insert(name.clone(), def);
+                }
+
+                if let Some(ax) = axiom {
+                    ty_cx.axioms.insert(name.clone(), ax);
+                }
+
+                if let Some(convention) = convention {
+                    ty_cx.externs.insert(name.clone(), convention);
+                }
+            }
+
+            return Err(From::from(e));
+        }
+
+        let name = match name {
+            None => {
+                self.transcript.push(Declaration { name: None, source: source });
+                return Ok(());
+            }
+            Some(name) => name,
+        };
+
+        let dependents = if redefined { self.invalidate(&name) } else { BTreeSet::new() };
+
+        // The dependents follow the new version, so the transcript stays in
+        // an order it can be checked in.
+        self.transcript.retain(|d| d.name.as_ref() != Some(&name));
+
+        let (moved, kept): (Vec<_>, Vec<_>) =
+            self.transcript.drain(..).partition(|d| {
+                d.name.as_ref().map(|n| dependents.contains(n)).unwrap_or(false)
+            });
+
+        self.transcript = kept;
+        self.transcript.push(Declaration { name: Some(name.clone()), source: source });
+        self.transcript.extend(moved.into_iter());
+
+        if redefined {
+            println!("redefined {}", name);
+        }
+
+        if !dependents.is_empty() {
+            let names: Vec<_> = dependents.iter().map(|n| format!("{}", n)).collect();
+            println!("re-checking {} when next used", names.join(", "));
+        }
+
+        Ok(())
+    }
+
+    /// Take every definition depending on `name` out of scope, directly or
+    /// through another, and return their names.
+    fn invalidate(&mut self, name: &core::Name) -> BTreeSet<core::Name> {
+        let mut invalid = BTreeSet::new();
+        invalid.insert(name.clone());
+
+        loop {
+            let found: Vec<_> =
+                self.elab_cx.ty_cx.definitions.iter()
+                    .filter(|&(n, def)| {
+                        !invalid.contains(n) &&
+                        definition_globals(def).iter().any(|g| invalid.contains(g))
+                    })
+                    .map(|(n, _)| n.clone())
+                    .collect();
+
+            if found.is_empty() {
+                break;
+            }
+
+            invalid.extend(found.into_iter());
+        }
+
+        invalid.remove(name);
+
+        for dependent in &invalid {
+            if let Some(def) = self.elab_cx.ty_cx.definitions.remove(dependent) {
+                self.stale.insert(dependent.clone(), def);
+            }
+        }
+
+        invalid
+    }
+
+    /// Check the stale definitions that `visit` finds mentioned.
+    fn recheck_mentioned<F>(&mut self, visit: F) -> Result<(), Error>
+        where F: FnOnce(&mut Mentions)
+    {
+        if self.stale.is_empty() {
+            return Ok(());
+        }
+
+        let mut mentions = Mentions { names: BTreeSet::new() };
+        visit(&mut mentions);
+
+        for name in mentions.names {
+            try!(self.recheck(&name));
+        }
+
+        Ok(())
+    }
+
+    /// Check the stale definition `name` and return it to scope, or drop it
+    /// from the session if it no longer type checks.
+    fn recheck(&mut self, name: &core::Name) -> Result<(), Error> {
+        let def = match self.stale.remove(name) {
+            None => return Ok(()),
+            Some(def) => def,
+        };
+
+        for dependency in definition_globals(&def) {
+            try!(self.recheck(&dependency));
+        }
+
+        if let Err(e) = self.elab_cx.ty_cx.declare_def(&def) {
+            try!(self.report(e));
+            println!("{} no longer type checks and has been removed", name);
+            self.transcript.retain(|d| d.name.as_ref() != Some(name));
+        }
+
+        Ok(())
+    }
+
+    /// Print every global whose name starts with `prefix` along with its type.
+    fn browse(&self, prefix: &str) {
+        let ty_cx = &self.elab_cx.ty_cx;
+
+        let globals: BTreeMap<String, &core::Term> =
+            ty_cx.axioms.iter()
+                 .map(|(n, ax)| (n, &ax.ty))
+                 .chain(ty_cx.definitions.iter().map(|(n, def)| (n, &def.ty)))
+                 .map(|(n, ty)| (format!("{}", n), ty))
+                 .filter(|&(ref n, _)| n.starts_with(prefix))
+                 .collect();
+
+        for (name, ty) in globals {
+            println!("{} : {}", name, self.session.render(ty));
+        }
+    }
+
     fn preprocess_term(&mut self, source: String) -> Result<core::Term, Error> {
         let source_copy = source.clone();
 
         self.session.add_source_map_for(ModuleId(0), SourceMap::from_source(source));
 
         let parser = parser::from_string(source_copy, ModuleId(0)).unwrap();
-        let term = try!(parser.parse_term());
+        let mut term = try!(parser.parse_term());
+        try!(self.recheck_mentioned(|mentions| mentions.visit_term(&term)));
+        try!(self.elab_cx.expand_macros_in_term(&mut term));
 
         let mut lcx = LocalElabCx::from_elab_cx(&mut self.elab_cx);
         let term = try!(lcx.elaborate_term(term));
@@ -291,8 +785,14 @@ impl Repl {
             Command::Unknown(command_text.to_string())
         } else if "quit".starts_with(command) {
             Command::Quit
+        } else if "load".starts_with(command) {
+            Command::Load(arg.to_string())
         } else if "reload".starts_with(command) {
             Command::Reload
+        } else if "browse".starts_with(command) {
+            Command::Browse(arg.to_string())
+        } else if "unfold".starts_with(command) {
+            Command::Unfold(arg.to_string())
         } else if "type".starts_with(command) {
             Command::TypeOf(arg.to_string())
         } else if "help".starts_with(command) {
@@ -301,6 +801,10 @@ impl Repl {
             Command::Def(arg.to_string())
         } else if "import".starts_with(command) {
             Command::Import(arg.to_string())
+        } else if "save".starts_with(command) {
+            Command::Save(arg.to_string())
+        } else if "open".starts_with(command) {
+            Command::Open(arg.to_string())
         } else {
             Command::Unknown(command_text.to_string())
         }
@@ -325,6 +829,10 @@ impl Reportable for Error {
             Error::Parser(parser_err) => {
                 parser_err.report(session)
             }
+            Error::Io(err) => {
+                println!("{}", err);
+                Ok(())
+            }
             Error::UnknownCommand(cmd) => {
                 println!("Unknown command \"{}\"", cmd);
                 Ok(())
@@ -0,0 +1,506 @@
+//! A language server for editor integration.
+//!
+//! The server speaks the language server protocol over a TCP connection,
+//! rather than stdin and stdout, as the compiler reports errors on stdout.
+//! Each time a document changes what it invalidated is elaborated again with
+//! indexing enabled, see `query`, and requests are answered from the
+//! resulting tables.
+//!
+//! Besides the standard requests the server answers `hubris/normalForm`,
+//! whose parameters are those of `textDocument/hover` along with an
+//! optional `form`, `"whnf"` or `"full"`, and `steps`. The term at the
+//! position is reduced as by `#reduce`, within the given number of steps.
+
+mod protocol;
+mod semantic_tokens;
+
+use rustc_serialize::json::Json;
+
+use std::collections::HashMap;
+use std::io::{self, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use super::ast::{SourceMap, Span};
+use super::core::{Name, Term};
+use super::index::HintKind;
+use super::query::{Database, Elaborated};
+use super::session::{HasSession, Session};
+use super::typeck::{self, NormalForm, ReduceOptions};
+use self::protocol::*;
+
+const ADDRESS: &'static str = "127.0.0.1:3000";
+
+// The kinds of completion item from the protocol that we use.
+const COMPLETION_FUNCTION: u64 = 3;
+const COMPLETION_VARIABLE: u64 = 6;
+const COMPLETION_TYPE: u64 = 7;
+const COMPLETION_CONSTRUCTOR: u64 = 20;
+
+// The kinds of inlay hint.
+const INLAY_TYPE: u64 = 1;
+const INLAY_PARAMETER: u64 = 2;
+
+/// The state of a document after its last change.
+struct Analysis {
+    elaborated: Rc<Elaborated>,
+}
+
+struct Server {
+    /// The text of the open documents, and what has been computed from
+    /// them and the files they import.
+    database: Database,
+    /// The analysis of each open document, keyed by URI, `None` if the
+    /// document failed to parse.
+    documents: HashMap<String, Option<Analysis>>,
+}
+
+pub fn run() {
+    let listener = TcpListener::bind(ADDRESS).unwrap();
+    println!("Listening on {}", ADDRESS);
+
+    for stream in listener.incoming() {
+        match stream.and_then(serve) {
+            Err(e) => println!("hubris: language server connection failed: {}", e),
+            Ok(()) => return,
+        }
+    }
+}
+
+/// Serve a single client until it sends `exit`.
+fn serve(stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(try!(stream.try_clone()));
+    let mut writer = stream;
+
+    let session = Session::empty();
+
+    let mut options = session.options();
+    options.index = true;
+    session.set_options(options);
+
+    let mut server = Server {
+        database: Database::new(session),
+        documents: HashMap::new(),
+    };
+
+    loop {
+        let message = match try!(read_message(&mut reader)) {
+            None => return Ok(()),
+            Some(m) => m,
+        };
+
+        if message.method == "exit" {
+            return Ok(());
+        }
+
+        try!(server.handle(message, &mut writer));
+    }
+}
+
+fn uri_to_path(uri: &str) -> PathBuf {
+    PathBuf::from(uri.trim_left_matches("file://"))
+}
+
+fn path_to_uri(path: &str) -> String {
+    format!("file://{}", path)
+}
+
+fn range(source_map: &SourceMap, span: Span) -> Json {
+    let start = source_map.position(Span::new(span.lo, span.lo)).unwrap_or((0, 0));
+    let end = source_map.position(Span::new(span.hi, span.hi)).unwrap_or(start);
+
+    object(vec![
+        ("start", position(start.0, start.1)),
+        ("end", position(end.0, end.1)),
+    ])
+}
+
+impl Analysis {
+    fn offset(&self, position: (usize, usize)) -> Option<usize> {
+        self.elaborated.source_map.offset(position.0, position.1)
+    }
+
+    fn range(&self, span: Span) -> Json {
+        range(&self.elaborated.source_map, span)
+    }
+
+    /// The location of `span`, which may be in an imported module.
+    fn location(&self, span: Span) -> Option<Json> {
+        let source_map = match self.elaborated.ecx.session().source_map_for(span.module_id) {
+            None => return None,
+            Some(source_map) => source_map,
+        };
+
+        Some(object(vec![
+            ("uri", Json::String(path_to_uri(&source_map.file_name))),
+            ("range", range(&source_map, span)),
+        ]))
+    }
+
+    /// The identifier characters immediately before the byte `offset`.
+    fn prefix_at(&self, offset: usize) -> String {
+        let before = self.elaborated.source_map.source.get(..offset).unwrap_or("");
+        let start = before.char_indices()
+                          .rev()
+                          .find(|&(_, c)| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                          .map(|(i, c)| i + c.len_utf8())
+                          .unwrap_or(0);
+
+        before[start..].to_string()
+    }
+
+    /// The locals in scope and every global, including those imported and
+    /// constructors, whose name starts with the identifier at the cursor.
+    fn completion(&self, offset: usize) -> Option<Json> {
+        let ty_cx = &self.elaborated.ecx.ty_cx;
+        let session = self.elaborated.ecx.session();
+        let prefix = self.prefix_at(offset);
+
+        let mut items = vec![];
+
+        let mut push = |label: String, kind: u64, ty: &Term| {
+            if label.starts_with(&prefix) {
+                items.push(object(vec![
+                    ("label", Json::String(label)),
+                    ("kind", Json::U64(kind)),
+                    ("detail", Json::String(session.render(ty))),
+                ]));
+            }
+        };
+
+        // Inner locals shadow outer ones, so they are offered first.
+        for local in ty_cx.index.locals_at(self.elaborated.module_id, offset).iter().rev() {
+            if let &Name::Local { ref repr, ref ty, .. } = local {
+                push(repr.clone(), COMPLETION_VARIABLE, ty);
+            }
+        }
+
+        for (name, axiom) in &ty_cx.axioms {
+            let is_ctor = ty_cx.types.values().any(|d| d.ctors.iter().any(|c| &c.0 == name));
+
+            let kind = if ty_cx.types.contains_key(name) {
+                COMPLETION_TYPE
+            } else if is_ctor {
+                COMPLETION_CONSTRUCTOR
+            } else {
+                COMPLETION_FUNCTION
+            };
+
+            push(format!("{}", name), kind, &axiom.ty);
+        }
+
+        for (name, def) in &ty_cx.definitions {
+            push(format!("{}", name), COMPLETION_FUNCTION, &def.ty);
+        }
+
+        Some(Json::Array(items))
+    }
+
+    /// The implicit arguments and binder types the elaborator inferred.
+    fn inlay_hints(&self) -> Json {
+        let session = self.elaborated.ecx.session();
+        let mut hints = vec![];
+
+        for hint in self.elaborated.ecx.ty_cx.index.hints() {
+            if hint.span.module_id != self.elaborated.module_id {
+                continue;
+            }
+
+            let (line, character) = match self.elaborated.source_map.position(Span::new(hint.span.hi, hint.span.hi)) {
+                None => continue,
+                Some(p) => p,
+            };
+
+            let (label, kind) = match hint.kind {
+                HintKind::ImplicitArgument =>
+                    (format!("{{{}}}", session.render(&hint.term)), INLAY_PARAMETER),
+                HintKind::BinderType =>
+                    (format!(": {}", session.render(&hint.term)), INLAY_TYPE),
+            };
+
+            hints.push(object(vec![
+                ("position", position(line, character)),
+                ("label", Json::String(label)),
+                ("kind", Json::U64(kind)),
+                ("paddingLeft", Json::Boolean(hint.kind == HintKind::ImplicitArgument)),
+            ]));
+        }
+
+        Json::Array(hints)
+    }
+
+    fn semantic_tokens(&self) -> Json {
+        let tokens = semantic_tokens::classify(
+            &self.elaborated.ecx.ty_cx,
+            self.elaborated.module_id,
+            &self.elaborated.source_map.source);
+
+        object(vec![("data", semantic_tokens::encode(&self.elaborated.source_map, &tokens))])
+    }
+
+    /// Every use of the name under the cursor, and optionally its definition.
+    fn references(&self, offset: usize, include_declaration: bool) -> Option<Json> {
+        let index = &self.elaborated.ecx.ty_cx.index;
+
+        let definition = match index.definition_at(self.elaborated.module_id, offset) {
+            None => return None,
+            Some(span) => span,
+        };
+
+        let mut spans = index.references(definition);
+
+        if include_declaration {
+            spans.insert(0, definition);
+        }
+
+        let locations = spans.into_iter()
+                             .filter_map(|span| self.location(span))
+                             .collect();
+
+        Some(Json::Array(locations))
+    }
+
+    /// Where the name under the cursor was bound or declared.
+    fn definition(&self, offset: usize) -> Option<Json> {
+        self.elaborated.ecx.ty_cx.index
+            .resolution_at(self.elaborated.module_id, offset)
+            .and_then(|resolution| resolution.definition)
+            .and_then(|span| self.location(span))
+    }
+
+    fn text(&self, span: Span) -> String {
+        self.elaborated.source_map.source[span.lo..span.hi].to_string()
+    }
+
+    /// What the term under the cursor reduces to, or why it could not be
+    /// reduced.
+    fn normal_form(&self, offset: usize, form: NormalForm, options: ReduceOptions)
+                   -> Option<Json> {
+        let ty_cx = &self.elaborated.ecx.ty_cx;
+        let session = self.elaborated.ecx.session();
+
+        let (span, term) = match ty_cx.index.term_at(self.elaborated.module_id, offset) {
+            None => return None,
+            Some(entry) => entry,
+        };
+
+        let result = match ty_cx.reduce_to(term, form, options) {
+            Ok(reduced) => ("term", Json::String(session.render(&reduced))),
+            Err(typeck::Error::ReductionLimit(_, limit)) =>
+                ("error", Json::String(format!("reduction did not finish within {} steps", limit))),
+            Err(_) => ("error", Json::String("the term could not be reduced".to_string())),
+        };
+
+        Some(object(vec![
+            ("range", self.range(span)),
+            ("original", Json::String(session.render(term))),
+            result,
+        ]))
+    }
+
+    /// The type of the term under the cursor, or the goal if it is a hole.
+    fn hover(&self, offset: usize) -> Option<Json> {
+        let index = &self.elaborated.ecx.ty_cx.index;
+        let session = self.elaborated.ecx.session();
+
+        let (span, contents) = match index.hole_at(self.elaborated.module_id, offset) {
+            Some(hole) => {
+                let goal = match index.type_at(self.elaborated.module_id, hole.span.lo) {
+                    None => "_".to_string(),
+                    Some((_, ty)) => session.render(ty),
+                };
+
+                let mut contents = String::new();
+
+                for local in &hole.context {
+                    match local {
+                        &Name::Local { ref repr, ref ty, .. } =>
+                            contents.push_str(&format!("{} : {}\n", repr, session.render(ty))),
+                        n => contents.push_str(&format!("{}\n", n)),
+                    }
+                }
+
+                contents.push_str(&format!("⊢ {}", goal));
+
+                (hole.span, contents)
+            }
+            None => {
+                let (span, ty) = match index.type_at(self.elaborated.module_id, offset) {
+                    None => return None,
+                    Some(entry) => entry,
+                };
+
+                (span, format!("{} : {}", self.text(span), session.render(ty)))
+            }
+        };
+
+        Some(object(vec![
+            ("contents", object(vec![
+                ("kind", Json::String("markdown".to_string())),
+                ("value", Json::String(format!("```hubris\n{}\n```", contents))),
+            ])),
+            ("range", self.range(span)),
+        ]))
+    }
+}
+
+impl Server {
+    fn handle<W: Write>(&mut self, message: Message, writer: &mut W) -> io::Result<()> {
+        let Message { id, method, params } = message;
+
+        let result = match &method[..] {
+            "initialize" => object(vec![
+                ("capabilities", object(vec![
+                    // Documents are always sent in full.
+                    ("textDocumentSync", Json::U64(1)),
+                    ("hoverProvider", Json::Boolean(true)),
+                    ("definitionProvider", Json::Boolean(true)),
+                    ("referencesProvider", Json::Boolean(true)),
+                    ("inlayHintProvider", Json::Boolean(true)),
+                    ("experimental", object(vec![
+                        ("normalFormProvider", Json::Boolean(true)),
+                    ])),
+                    ("completionProvider", object(vec![
+                        ("triggerCharacters", Json::Array(vec![Json::String(".".to_string())])),
+                    ])),
+                    ("semanticTokensProvider", object(vec![
+                        ("legend", object(vec![
+                            ("tokenTypes", Json::Array(
+                                semantic_tokens::LEGEND.iter()
+                                    .map(|t| Json::String(t.to_string()))
+                                    .collect())),
+                            ("tokenModifiers", Json::Array(vec![])),
+                        ])),
+                        ("full", Json::Boolean(true)),
+                    ])),
+                ])),
+            ]),
+            "shutdown" => Json::Null,
+            "textDocument/didOpen" => {
+                self.update(&params, find_string(&params, &["textDocument", "text"]));
+                return Ok(());
+            }
+            "textDocument/didChange" => {
+                let text = params.find("contentChanges")
+                                 .and_then(Json::as_array)
+                                 .and_then(|changes| changes.last())
+                                 .and_then(|change| change.find("text"))
+                                 .and_then(Json::as_string);
+                self.update(&params, text);
+                return Ok(());
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = find_string(&params, &["textDocument", "uri"]) {
+                    self.documents.remove(uri);
+                    self.database.clear_source(&uri_to_path(uri));
+                }
+                return Ok(());
+            }
+            "textDocument/hover" => {
+                self.with_document(&params, |analysis, offset| analysis.hover(offset))
+            }
+            "textDocument/definition" => {
+                self.with_document(&params, |analysis, offset| analysis.definition(offset))
+            }
+            "textDocument/semanticTokens/full" => {
+                match self.document(&params) {
+                    None => Json::Null,
+                    Some(analysis) => analysis.semantic_tokens(),
+                }
+            }
+            "textDocument/inlayHint" => {
+                match self.document(&params) {
+                    None => Json::Null,
+                    Some(analysis) => analysis.inlay_hints(),
+                }
+            }
+            "textDocument/completion" => {
+                self.with_document(&params, |analysis, offset| analysis.completion(offset))
+            }
+            "hubris/normalForm" => {
+                let form = find_string(&params, &["form"])
+                               .and_then(NormalForm::from_str)
+                               .unwrap_or(NormalForm::Full);
+
+                let mut options = self.database.session().options().reduce;
+
+                if let Some(steps) = params.find("steps").and_then(Json::as_u64) {
+                    options.max_steps = Some(steps as usize);
+                }
+
+                self.with_document(&params, |analysis, offset| {
+                    analysis.normal_form(offset, form, options)
+                })
+            }
+            "textDocument/references" => {
+                let include_declaration =
+                    params.find_path(&["context", "includeDeclaration"])
+                          .and_then(Json::as_boolean)
+                          .unwrap_or(false);
+
+                self.with_document(&params, |analysis, offset| {
+                    analysis.references(offset, include_declaration)
+                })
+            }
+            _ => match id {
+                // Notifications we don't understand can be ignored.
+                None => return Ok(()),
+                Some(id) => return write_error(
+                    writer, id, -32601, format!("unsupported method `{}`", method)),
+            }
+        };
+
+        match id {
+            None => Ok(()),
+            Some(id) => write_response(writer, id, result),
+        }
+    }
+
+    fn update(&mut self, params: &Json, text: Option<&str>) {
+        let uri = match find_string(params, &["textDocument", "uri"]) {
+            None => return,
+            Some(uri) => uri,
+        };
+
+        if let Some(text) = text {
+            let path = uri_to_path(uri);
+            self.database.set_source(&path, text.to_string());
+
+            // Only what the change invalidated is elaborated again, the
+            // tables are still useful for the definitions before an error.
+            let analysis = match self.database.elaborated(&path) {
+                Err(e) => {
+                    self.database.report(e).unwrap();
+                    None
+                }
+                Ok(elaborated) => Some(Analysis { elaborated: elaborated }),
+            };
+
+            self.documents.insert(uri.to_string(), analysis);
+        }
+    }
+
+    /// The analysis of the document named in `params`.
+    fn document(&self, params: &Json) -> Option<&Analysis> {
+        find_string(params, &["textDocument", "uri"])
+            .and_then(|uri| self.documents.get(uri))
+            .and_then(|analysis| analysis.as_ref())
+    }
+
+    /// Answer a query about the position in `params`, with `null` if the
+    /// document or position is unknown.
+    fn with_document<F>(&self, params: &Json, query: F) -> Json
+        where F: Fn(&Analysis, usize) -> Option<Json>
+    {
+        let analysis = match self.document(params) {
+            None => return Json::Null,
+            Some(a) => a,
+        };
+
+        find_position(params, "position")
+            .and_then(|p| analysis.offset(p))
+            .and_then(|offset| query(analysis, offset))
+            .unwrap_or(Json::Null)
+    }
+}
@@ -0,0 +1,124 @@
+//! The framing and JSON-RPC messages of the language server protocol.
+
+use rustc_serialize::json::{self, Json};
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+pub type Object = BTreeMap<String, Json>;
+
+/// A request, or a notification if `id` is `None`.
+pub struct Message {
+    pub id: Option<Json>,
+    pub method: String,
+    pub params: Json,
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Read the next message, returning `None` at the end of the stream.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Message>> {
+    let mut content_length = None;
+
+    loop {
+        let mut header = String::new();
+
+        if try!(reader.read_line(&mut header)) == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim();
+
+        if header.is_empty() {
+            break;
+        }
+
+        let mut parts = header.splitn(2, ':');
+        let (key, value) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+        if key.to_lowercase() == "content-length" {
+            content_length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = match content_length {
+        None => return Err(invalid_data("missing Content-Length header".to_string())),
+        Some(l) => l,
+    };
+
+    let mut content = vec![0; length];
+    try!(reader.read_exact(&mut content));
+
+    let content = try!(String::from_utf8(content)
+        .map_err(|e| invalid_data(format!("{}", e))));
+
+    let json = try!(Json::from_str(&content)
+        .map_err(|e| invalid_data(format!("{}", e))));
+
+    let method = match json.find("method").and_then(Json::as_string) {
+        None => return Err(invalid_data(format!("message without a method: {}", json))),
+        Some(m) => m.to_string(),
+    };
+
+    Ok(Some(Message {
+        id: json.find("id").cloned(),
+        method: method,
+        params: json.find("params").cloned().unwrap_or(Json::Null),
+    }))
+}
+
+fn write_json<W: Write>(writer: &mut W, json: Json) -> io::Result<()> {
+    let content = format!("{}", json::as_json(&json));
+    try!(write!(writer, "Content-Length: {}\r\n\r\n{}", content.len(), content));
+    writer.flush()
+}
+
+pub fn write_response<W: Write>(writer: &mut W, id: Json, result: Json) -> io::Result<()> {
+    let mut response = Object::new();
+    response.insert("jsonrpc".to_string(), Json::String("2.0".to_string()));
+    response.insert("id".to_string(), id);
+    response.insert("result".to_string(), result);
+    write_json(writer, Json::Object(response))
+}
+
+pub fn write_error<W: Write>(writer: &mut W, id: Json, code: i64, message: String) -> io::Result<()> {
+    let mut error = Object::new();
+    error.insert("code".to_string(), Json::I64(code));
+    error.insert("message".to_string(), Json::String(message));
+
+    let mut response = Object::new();
+    response.insert("jsonrpc".to_string(), Json::String("2.0".to_string()));
+    response.insert("id".to_string(), id);
+    response.insert("error".to_string(), Json::Object(error));
+    write_json(writer, Json::Object(response))
+}
+
+/// Build a JSON object from its fields.
+pub fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+pub fn position(line: usize, character: usize) -> Json {
+    object(vec![
+        ("line", Json::U64(line as u64)),
+        ("character", Json::U64(character as u64)),
+    ])
+}
+
+/// Find the string at `path` within `json`.
+pub fn find_string<'a>(json: &'a Json, path: &[&str]) -> Option<&'a str> {
+    json.find_path(path).and_then(Json::as_string)
+}
+
+/// The zero based line and character of a `Position` found at `key`.
+pub fn find_position(json: &Json, key: &str) -> Option<(usize, usize)> {
+    let line = json.find_path(&[key, "line"]).and_then(Json::as_u64);
+    let character = json.find_path(&[key, "character"]).and_then(Json::as_u64);
+
+    match (line, character) {
+        (Some(l), Some(c)) => Some((l as usize, c as usize)),
+        _ => None,
+    }
+}
@@ -0,0 +1,153 @@
+//! Classification of the tokens of a document for highlighting.
+//!
+//! Keywords and literals come from the tokenizer, names are classified by
+//! what the resolver bound them to. This lets editors distinguish a
+//! constructor from a pattern variable, which look identical in the source.
+
+use rustc_serialize::json::Json;
+
+use hubris_syntax::tok::{Tok, Tokenizer};
+
+use super::super::ast::{ModuleId, SourceMap, Span};
+use super::super::core::Name;
+use super::super::typeck::TyCtxt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    Type,
+    Constructor,
+    Global,
+    Local,
+    Metavariable,
+    Literal,
+    Comment,
+}
+
+/// The names of each kind, in the order they are numbered in the protocol.
+pub const LEGEND: &'static [&'static str] = &[
+    "keyword",
+    "type",
+    "enumMember",
+    "function",
+    "variable",
+    "metavariable",
+    "string",
+    "comment",
+];
+
+impl TokenKind {
+    fn number(&self) -> u64 {
+        match *self {
+            TokenKind::Keyword => 0,
+            TokenKind::Type => 1,
+            TokenKind::Constructor => 2,
+            TokenKind::Global => 3,
+            TokenKind::Local => 4,
+            TokenKind::Metavariable => 5,
+            TokenKind::Literal => 6,
+            TokenKind::Comment => 7,
+        }
+    }
+}
+
+fn classify_name(ty_cx: &TyCtxt, name: &Name) -> TokenKind {
+    match name {
+        &Name::Local { .. } => TokenKind::Local,
+        &Name::Meta { .. } => TokenKind::Metavariable,
+        _ if ty_cx.types.contains_key(name) => TokenKind::Type,
+        _ if ty_cx.types.values().any(|d| d.ctors.iter().any(|c| &c.0 == name)) =>
+            TokenKind::Constructor,
+        _ => TokenKind::Global,
+    }
+}
+
+/// Classify the tokens of the module `module_id` with source `source`,
+/// sorted by position.
+pub fn classify(ty_cx: &TyCtxt, module_id: ModuleId, source: &str) -> Vec<(Span, TokenKind)> {
+    let mut tokens = vec![];
+
+    for token in Tokenizer::new(source, 0) {
+        let (lo, tok, hi) = match token {
+            // The parser will have reported the error already.
+            Err(_) => break,
+            Ok(t) => t,
+        };
+
+        let kind = match tok {
+            Tok::Def | Tok::Axiom | Tok::End | Tok::Export | Tok::Extern | Tok::Fix |
+            Tok::Forall | Tok::Fun | Tok::Inductive | Tok::In | Tok::Import | Tok::Let |
+            Tok::Macro | Tok::Match | Tok::Module | Tok::On | Tok::Type | Tok::Variables |
+            Tok::With | Tok::Sigma => TokenKind::Keyword,
+            Tok::StringLiteral(_) => TokenKind::Literal,
+            Tok::DocComment(_) => TokenKind::Comment,
+            _ => continue,
+        };
+
+        tokens.push((Span::new(lo, hi), kind));
+    }
+
+    let index = &ty_cx.index;
+    let in_module = |span: &Span| span.module_id == module_id;
+
+    for (name, span) in index.definitions() {
+        if in_module(span) {
+            tokens.push((*span, classify_name(ty_cx, name)));
+        }
+    }
+
+    for resolution in index.resolutions() {
+        if in_module(&resolution.span) {
+            tokens.push((resolution.span, classify_name(ty_cx, &resolution.name)));
+        }
+    }
+
+    for hole in index.holes() {
+        if in_module(&hole.span) {
+            tokens.push((hole.span, TokenKind::Metavariable));
+        }
+    }
+
+    tokens.sort_by_key(|&(span, _)| (span.lo, span.hi));
+
+    // Editors expect tokens not to overlap, so only the first is kept.
+    let mut classified: Vec<(Span, TokenKind)> = vec![];
+
+    for (span, kind) in tokens {
+        let overlaps = classified.last().map(|&(last, _)| span.lo < last.hi).unwrap_or(false);
+
+        if !overlaps {
+            classified.push((span, kind));
+        }
+    }
+
+    classified
+}
+
+/// Encode tokens relative to each other as the protocol expects, each token
+/// is its line and start relative to the previous token, then its length and
+/// kind.
+pub fn encode(source_map: &SourceMap, tokens: &[(Span, TokenKind)]) -> Json {
+    let mut data = vec![];
+    let (mut last_line, mut last_start) = (0, 0);
+
+    for &(span, kind) in tokens {
+        let (line, start) = match source_map.position(span) {
+            None => continue,
+            Some(p) => p,
+        };
+
+        let delta_start = if line == last_line { start - last_start } else { start };
+
+        data.push(Json::U64((line - last_line) as u64));
+        data.push(Json::U64(delta_start as u64));
+        data.push(Json::U64(source_map.columns(span.lo, span.hi) as u64));
+        data.push(Json::U64(kind.number()));
+        data.push(Json::U64(0));
+
+        last_line = line;
+        last_start = start;
+    }
+
+    Json::Array(data)
+}
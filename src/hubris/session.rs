@@ -1,7 +1,17 @@
 use super::ast::{Span, SourceMap, ModuleId};
+use super::backend::{BackendKind, Evaluation, Optimization, Pass};
+use super::project::{Project, ResolvedDependency};
+use super::timing::{Instant, Timings, Phase, Counter};
+use super::core::{stats, Name, Term};
+use super::diagnostic::{Diagnostic, Level};
+use super::display::{self, DisplayOptions};
+use super::feature::Features;
+use super::lint::{Lint, LintLevel, LintLevels};
+use super::renderer::{self, Buffer, Renderer};
+use super::typeck::ReduceOptions;
 
 use std::cell::RefCell;
-use std::collections::{HashSet, HashMap};
+use std::collections::{BTreeSet, HashSet, HashMap, VecDeque};
 use std::env;
 use std::path::{PathBuf, Path};
 use std::process;
@@ -9,7 +19,7 @@ use std::io;
 use std::rc::Rc;
 use std::io::prelude::*;
 
-use term::{self, Terminal, color, StdoutTerminal};
+use term::color;
 
 /// A type that contains a session either directly or
 /// transitively.
@@ -51,18 +61,233 @@ impl Clone for Session {
     }
 }
 
+/// The kind of output the compiler should produce.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Emit {
+    /// A native executable built from `main`.
+    Executable,
+    /// A Rust library crate wrapping the definitions marked
+    /// `@[export_rust]`.
+    Library,
+    /// An s-expression dump of every checked declaration, see
+    /// `hubris_kernel::export` for a description of the format.
+    Export,
+    /// An image of the type checking context, loaded in place of the
+    /// source when the program is imported, see `typeck::image`.
+    Image,
+    /// The declaration each name in the program resolved to.
+    Resolutions,
+    /// The unsolved holes and uses of `sorry` in the program as JSON, each
+    /// with its goal and the locals in scope, for editor plugins.
+    Holes,
+    /// The elaborated module as s-expressions with spans, see
+    /// `hubris_kernel::sexp` for a description of the format.
+    Sexp,
+    /// The backend's intermediate representation once the optimization
+    /// pass has run, for debugging the optimizer.
+    IrAfter(Pass),
+    /// Nothing, the program is only checked.
+    Check,
+}
+
+impl Emit {
+    pub fn from_str(s: &str) -> Option<Emit> {
+        match s {
+            "exe" => Some(Emit::Executable),
+            "lib" => Some(Emit::Library),
+            "export" => Some(Emit::Export),
+            "image" => Some(Emit::Image),
+            "resolutions" => Some(Emit::Resolutions),
+            "holes" => Some(Emit::Holes),
+            "sexp" => Some(Emit::Sexp),
+            "check" => Some(Emit::Check),
+            _ if s.starts_with("ir-after=") =>
+                Pass::from_str(&s["ir-after=".len()..]).map(Emit::IrAfter),
+            _ => None,
+        }
+    }
+}
+
+/// Options controlling a single invocation of the compiler.
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub emit: Emit,
+    /// The backend used to produce executables.
+    pub backend: BackendKind,
+    /// The target triple to build executables for, the host if `None`.
+    pub target: Option<String>,
+    /// How generated code evaluates the arguments of calls.
+    pub evaluation: Evaluation,
+    /// The optimizations the backend performs.
+    pub optimization: Optimization,
+    /// Whether generated programs record the Hubris definitions they are
+    /// evaluating, to print them when they panic.
+    pub debug_info: bool,
+    /// Crates the generated Rust code may use, with their versions.
+    pub rust_dependencies: Vec<(String, String)>,
+    /// Whether to print a report of the time spent in each phase.
+    pub timings: bool,
+    /// Whether to collect and print statistics about core term allocation.
+    pub term_stats: bool,
+    /// Whether to print terms in diagnostics in full.
+    pub verbose_errors: bool,
+    /// The level each lint is reported at.
+    pub lints: LintLevels,
+    /// The unstable features that have been enabled.
+    pub features: Features,
+    /// Whether to record the tables used by editor tooling.
+    pub index: bool,
+    /// Whether to print the implicit arguments of globals.
+    pub show_implicits: bool,
+    /// How `#reduce` normalizes terms.
+    pub reduce: ReduceOptions,
+    /// The flags set with `--cfg`, items marked `@[cfg flag]` are only
+    /// compiled when `flag` is set.
+    pub cfg: BTreeSet<String>,
+    /// The command running the SMT solver `smt` sends its goals to.
+    pub smt_solver: Option<String>,
+    /// Whether to check the invariants of each module once it has been
+    /// type checked, a debugging aid for the compiler.
+    pub validate_core: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            emit: Emit::Executable,
+            backend: BackendKind::default(),
+            target: None,
+            evaluation: Evaluation::default(),
+            optimization: Optimization::default(),
+            debug_info: false,
+            rust_dependencies: vec![],
+            timings: false,
+            term_stats: false,
+            verbose_errors: false,
+            lints: LintLevels::default(),
+            features: Features::default(),
+            index: false,
+            show_implicits: false,
+            reduce: ReduceOptions::default(),
+            cfg: BTreeSet::new(),
+            smt_solver: None,
+            validate_core: false,
+        }
+    }
+}
+
+/// Where a metavariable was created and what it stands for.
+#[derive(Clone, Debug)]
+pub struct MetaOrigin {
+    pub span: Span,
+    /// A description such as "implicit argument `A` of `id`".
+    pub description: String,
+}
+
+/// The number of events kept by `Session::trace`.
+const TRACE_EVENTS: usize = 8;
+
+/// Where a session's diagnostics go.
+pub enum Output {
+    /// Written to standard output as they are emitted.
+    Terminal(Box<Renderer>),
+    /// Written to standard error as they are emitted, used when the
+    /// compiler's output goes to standard output.
+    Stderr(Box<Renderer>),
+    /// Kept to be retrieved with `Session::take_diagnostics`.
+    Collected(Vec<Diagnostic>),
+    /// Written without color to a buffer, as they would be to the terminal.
+    Buffer(Buffer),
+}
+
+impl Default for Output {
+    /// The terminal, except on `wasm32-unknown-unknown` which has none.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn default() -> Output {
+        Output::Terminal(renderer::stdout())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn default() -> Output {
+        Output::Collected(vec![])
+    }
+}
+
 pub struct SessionData {
-    /// The terminal that is used for errors reporting.
-    pub terminal: Box<StdoutTerminal>,
+    /// Where diagnostics are reported.
+    pub output: Output,
     /// A global counter used to track how many module ids
     /// we have handed out.
     module_id_counter: usize,
+    /// The number of the next metavariable, metavariables are numbered
+    /// across modules so they can be told apart in diagnostics.
+    meta_counter: usize,
     /// The set of things that have been imported.
     imported_files: HashSet<PathBuf>,
     /// An index from module id to source map.
     source_maps: HashMap<ModuleId, SourceMap>,
     /// The set of paths to load files from.
     load_paths: Vec<PathBuf>,
+    /// An explicit mapping from module names to files, populated
+    /// from a project manifest.
+    module_map: HashMap<String, PathBuf>,
+    /// The options the compiler was invoked with.
+    options: Options,
+    /// Timings and counters collected across the compilation.
+    timings: Timings,
+    /// The number of leading implicit parameters of each global, used to
+    /// hide implicit arguments when printing terms.
+    implicits: HashMap<Name, usize>,
+    /// Where each metavariable created for a `_` placeholder was written.
+    placeholders: HashMap<usize, Span>,
+    /// The origin of each metavariable the elaborator and type checker
+    /// created.
+    meta_origins: HashMap<usize, MetaOrigin>,
+    /// The number of lints reported at the deny level.
+    lint_errors: usize,
+    /// The number of errors reported, lints included.
+    errors: usize,
+    /// The definition currently being processed.
+    current_definition: Option<String>,
+    /// The most recent events of the compilation, kept to explain internal
+    /// compiler errors.
+    trace: VecDeque<String>,
+}
+
+impl SessionData {
+    fn new(load_paths: Vec<PathBuf>) -> SessionData {
+        SessionData {
+            output: Output::default(),
+            module_id_counter: 0,
+            meta_counter: 0,
+            imported_files: HashSet::new(),
+            source_maps: HashMap::new(),
+            load_paths: load_paths,
+            module_map: HashMap::new(),
+            options: Options::default(),
+            timings: Timings::new(),
+            implicits: HashMap::new(),
+            placeholders: HashMap::new(),
+            meta_origins: HashMap::new(),
+            lint_errors: 0,
+            errors: 0,
+            current_definition: None,
+            trace: VecDeque::new(),
+        }
+    }
+}
+
+/// The global library directory, `~/.hubris/lib`.
+fn default_load_paths() -> Vec<PathBuf> {
+    let home = match env::home_dir() {
+        None => {
+            println!("hubris: unable to locate home directory");
+            process::exit(1);
+        }
+        Some(h) => h
+    };
+
+    vec![home.join(".hubris/lib")]
 }
 
 #[derive(Clone)]
@@ -79,47 +304,114 @@ pub enum SessionType {
 
 impl Session   {
     pub fn empty() -> Session {
-        let home = match env::home_dir() {
-            None => {
-                println!("hubris: unable to locate home directory");
-                process::exit(1);
-            }
-            Some(h) => h
-        };
-
         Session {
-            data: Rc::new(RefCell::new(SessionData {
-                terminal: term::stdout().unwrap(), // Not sure about this, we can revisit it later.
-                module_id_counter: 0,
-                imported_files: HashSet::new(),
-                source_maps: HashMap::new(),
-                load_paths: vec![home.join(".hubris/lib")],
-            })),
+            data: Rc::new(RefCell::new(SessionData::new(default_load_paths()))),
             ty: SessionType::Repl { loaded_file: None },
         }
     }
 
     pub fn from_root(path: &Path) -> Session {
-        let home = match env::home_dir() {
-            None => {
-                println!("hubris: unable to locate home directory");
-                process::exit(1);
+        Session {
+            data: Rc::new(RefCell::new(SessionData::new(default_load_paths()))),
+            ty: SessionType::Compiler { root_file: path.to_owned() }
+        }
+    }
+
+    /// Construct a session for compiling `main_file` as part of `project`,
+    /// imports are resolved using the project's module map and source roots,
+    /// then those of its dependencies, before falling back to the global
+    /// library directory.
+    pub fn from_project(project: &Project,
+                        dependencies: &[ResolvedDependency],
+                        main_file: &Path) -> Session {
+        let session = Session::from_root(main_file);
+
+        {
+            let mut data = session.data.borrow_mut();
+
+            let mut load_paths = project.source_roots();
+            let mut module_map = HashMap::new();
+
+            for dep in dependencies {
+                load_paths.extend(dep.project.source_roots().into_iter());
+                module_map.extend(dep.project.module_map().into_iter());
             }
-            Some(h) => h
-        };
 
+            // The project's own mapping takes priority over its dependencies.
+            module_map.extend(project.module_map().into_iter());
+
+            load_paths.extend(data.load_paths.drain(..));
+            data.load_paths = load_paths;
+            data.module_map = module_map;
+            data.options.rust_dependencies = project.rust_dependencies();
+        }
+
+        session
+    }
+
+    /// A session sharing this session's state whose root file is `path`,
+    /// used to elaborate one of the modules of a compilation.
+    pub fn for_file(&self, path: &Path) -> Session {
         Session {
-            data: Rc::new(RefCell::new(SessionData {
-                terminal: term::stdout().unwrap(), // Not sure about this, we can revisit it later.
-                module_id_counter: 0,
-                imported_files: HashSet::new(),
-                source_maps: HashMap::new(),
-                load_paths: vec![home.join(".hubris/lib")],
-            })),
-            ty: SessionType::Compiler { root_file: path.to_owned() }
+            data: self.data.clone(),
+            ty: SessionType::Compiler { root_file: path.to_owned() },
+        }
+    }
+
+    /// A session that reads nothing from the filesystem, modules may only
+    /// be given as text and diagnostics are collected rather than printed.
+    pub fn in_memory() -> Session {
+        let mut data = SessionData::new(vec![]);
+        data.output = Output::Collected(vec![]);
+
+        Session {
+            data: Rc::new(RefCell::new(data)),
+            ty: SessionType::Repl { loaded_file: None },
         }
     }
 
+    /// A compiler session for `path` whose diagnostics are written, without
+    /// color and independently of the terminal, to the returned buffer.
+    pub fn buffered(path: &Path) -> (Session, Buffer) {
+        let buffer = Buffer::new();
+        let session = Session::from_root(path);
+        session.data.borrow_mut().output = Output::Buffer(buffer.clone());
+        (session, buffer)
+    }
+
+    /// Keep diagnostics emitted from now on instead of writing them to the
+    /// terminal.
+    pub fn collect_diagnostics(&self) {
+        let mut data = self.data.borrow_mut();
+        if let Output::Collected(_) = data.output {
+            return;
+        }
+        data.output = Output::Collected(vec![]);
+    }
+
+    /// Write diagnostics emitted from now on to standard error, leaving
+    /// standard output to the compiler's output.
+    pub fn report_to_stderr(&self) {
+        let mut data = self.data.borrow_mut();
+        if let Output::Terminal(_) = data.output {
+            data.output = Output::Stderr(renderer::stderr());
+        }
+    }
+
+    /// The diagnostics collected since the last call.
+    pub fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        match self.data.borrow_mut().output {
+            Output::Collected(ref mut diagnostics) => diagnostics.drain(..).collect(),
+            Output::Terminal(_) | Output::Stderr(_) | Output::Buffer(_) => vec![],
+        }
+    }
+
+    /// Make `path` the file loaded into this REPL session, it is elaborated
+    /// the next time the session's context is rebuilt.
+    pub fn set_loaded_file(&mut self, path: PathBuf) {
+        self.ty = SessionType::Repl { loaded_file: Some(path) };
+    }
+
     /// Return the root file if this is a compiler session.
     pub fn root_file(&self) -> PathBuf {
         match self.ty {
@@ -132,104 +424,320 @@ impl Session   {
         }
     }
 
+    pub fn options(&self) -> Options {
+        self.data.borrow().options.clone()
+    }
+
+    pub fn set_options(&self, options: Options) {
+        if options.term_stats {
+            stats::enable();
+        }
+
+        self.data.borrow_mut().options = options;
+    }
+
+    /// Whether the tables used by editor tooling should be recorded, this is
+    /// checked for every subterm so avoids copying the options.
+    pub fn indexing(&self) -> bool {
+        self.data.borrow().options.index
+    }
+
+    /// Record the time elapsed since `start` as part of `phase`.
+    pub fn record_time(&self, phase: Phase, label: Option<String>, start: Instant) {
+        match label {
+            None => self.trace(format!("finished {}", phase)),
+            Some(ref label) => self.trace(format!("finished {} of `{}`", phase, label)),
+        }
+
+        let mut data = self.data.borrow_mut();
+        if data.options.timings {
+            data.timings.record(phase, label, start.elapsed());
+        }
+    }
+
+    /// Record an event of the compilation, only the most recent events are
+    /// kept.
+    pub fn trace(&self, event: String) {
+        let mut data = self.data.borrow_mut();
+        if data.trace.len() == TRACE_EVENTS {
+            data.trace.pop_front();
+        }
+        data.trace.push_back(event);
+    }
+
+    pub fn recent_trace(&self) -> Vec<String> {
+        self.data.borrow().trace.iter().cloned().collect()
+    }
+
+    /// Record that `phase` has started processing the definition `name`.
+    pub fn enter_definition(&self, phase: &str, name: Option<String>) {
+        if let Some(ref name) = name {
+            self.trace(format!("{} `{}`", phase, name));
+        }
+        self.data.borrow_mut().current_definition = name;
+    }
+
+    /// Record that no definition is being processed.
+    pub fn leave_definition(&self) {
+        self.data.borrow_mut().current_definition = None;
+    }
+
+    pub fn current_definition(&self) -> Option<String> {
+        self.data.borrow().current_definition.clone()
+    }
+
+    pub fn count(&self, counter: Counter, n: usize) {
+        let mut data = self.data.borrow_mut();
+        if data.options.timings {
+            data.timings.count(counter, n);
+        }
+    }
+
+    /// Print the timing and term statistics reports if they were requested.
+    pub fn report_timings(&self) -> io::Result<()> {
+        let mut session_data = self.data.borrow_mut();
+        let &mut SessionData {
+            ref mut output,
+            ref timings,
+            ref options,
+            .. } = &mut *session_data;
+
+        let mut terminal: &mut Write = match output {
+            &mut Output::Terminal(ref mut terminal) => terminal,
+            &mut Output::Stderr(ref mut terminal) => terminal,
+            &mut Output::Buffer(ref mut buffer) => buffer,
+            &mut Output::Collected(_) => return Ok(()),
+        };
+
+        if options.timings {
+            try!(timings.report(&mut terminal));
+            try!(terminal.flush());
+        }
+
+        if options.term_stats {
+            try!(writeln!(terminal, ""));
+            try!(stats::report(&mut terminal));
+            try!(terminal.flush());
+        }
+
+        Ok(())
+    }
+
+    /// Write `text`, the result of a command such as `#eval`, on a line of
+    /// its own wherever diagnostics are written, so the two stay in order.
+    /// A session collecting diagnostics has nowhere to write it.
+    pub fn print(&self, text: &str) -> io::Result<()> {
+        let mut data = self.data.borrow_mut();
+
+        let terminal: &mut Write = match &mut data.output {
+            &mut Output::Terminal(ref mut terminal) => terminal,
+            &mut Output::Stderr(ref mut terminal) => terminal,
+            &mut Output::Buffer(ref mut buffer) => buffer,
+            &mut Output::Collected(_) => return Ok(()),
+        };
+
+        try!(writeln!(terminal, "{}", text));
+        terminal.flush()
+    }
+
     pub fn next_module_id(&self) -> ModuleId {
         let id = self.data.borrow().module_id_counter;
         self.data.borrow_mut().module_id_counter += 1;
         ModuleId(id)
     }
 
+    pub fn next_meta_number(&self) -> usize {
+        let number = self.data.borrow().meta_counter;
+        self.data.borrow_mut().meta_counter += 1;
+        number
+    }
+
     pub fn add_source_map_for(&self, id: ModuleId, source_map: SourceMap) {
         let mut data = self.data.borrow_mut();
         data.source_maps.insert(id, source_map);
     }
 
-    /// Reports a message at a given location. Underlines the Span.
-    pub fn span_error(&self,
-                      span: Span,
-                      message: String) -> io::Result<()> {
+    pub fn source_map_for(&self, id: ModuleId) -> Option<SourceMap> {
+        self.data.borrow().source_maps.get(&id).cloned()
+    }
 
-        let mut session_data = self.data.borrow_mut();
-        let &mut SessionData {
-            ref mut terminal,
-            ref mut source_maps,
-            .. } = &mut *session_data;
+    /// Format the start of `span` as `file:line:column`, counting from one.
+    pub fn location(&self, span: Span) -> String {
+        let data = self.data.borrow();
 
-        let module_id = span.module_id;
-        let emp = SourceMap::empty();
-        let source_map = source_maps.get(&module_id).unwrap_or(&emp);
+        match data.source_maps.get(&span.module_id) {
+            None => format!("<unknown>:{}", span.lo),
+            Some(source_map) => {
+                let (line, column) = source_map.position(span).unwrap_or((0, 0));
+                format!("{}:{}:{}", source_map.file_name, line + 1, column + 1)
+            }
+        }
+    }
 
-        // TODO: We need to know if we wrap around to more then one line.
-        let (line_no, col_no) = source_map.position(span)
-                                          .unwrap_or((0,0));
+    /// Record the implicit parameters of the global `name` with type `ty`.
+    pub fn register_implicits(&self, name: &Name, ty: &Term) {
+        let arity = display::implicit_arity(ty);
+        if arity > 0 {
+            self.data.borrow_mut().implicits.insert(name.clone(), arity);
+        }
+    }
 
-        let (line_with_padding, marker) = source_map
-                                              .underline_span(span)
-                                              .unwrap_or((format!("??"),format!("??")));
+    /// Record that the metavariable `number` stands for a `_` written at
+    /// `span`, so it can be reported there if it is never solved.
+    pub fn register_placeholder(&self, number: usize, span: Span) {
+        self.data.borrow_mut().placeholders.insert(number, span);
+    }
 
-        let filename_str = format!("{}:{}:{}: {}:{} ",
-            source_map.file_name,
-            line_no,
-            col_no,
-            line_no, // this should be the line where we end, not the same line
-            col_no + (span.hi - span.lo)); // this should be the column we end at
+    pub fn placeholder_span(&self, number: usize) -> Option<Span> {
+        self.data.borrow().placeholders.get(&number).cloned()
+    }
 
-        try!(write!(terminal, "{}", filename_str));
+    /// Record that the metavariable `number` was created at `span` to
+    /// stand for what `description` says.
+    pub fn register_meta_origin(&self, number: usize, span: Span, description: String) {
+        self.data.borrow_mut().meta_origins.insert(number, MetaOrigin {
+            span: span,
+            description: description,
+        });
+    }
 
-        try!(terminal.fg(color::RED));
-        try!(write!(terminal, "error: "));
-        try!(terminal.reset());
-        try!(writeln!(terminal, "{}", message));
+    pub fn meta_origin(&self, number: usize) -> Option<MetaOrigin> {
+        self.data.borrow().meta_origins.get(&number).cloned()
+    }
 
-        let file_str_simple =
-            format!("{}:{}: ",
-                source_map.file_name,
-                line_no);
+    /// Label `diagnostic` with the origin of each metavariable appearing in
+    /// `terms`, which are printed as `?n` in the message.
+    pub fn explain_metas(&self, mut diagnostic: Diagnostic, terms: &[&Term]) -> Diagnostic {
+        let mut seen = vec![];
 
-        try!(write!(terminal, "{} {}", file_str_simple, line_with_padding));
+        for term in terms {
+            for number in display::metas(term) {
+                if seen.contains(&number) {
+                    continue;
+                }
 
-        let mut marker_padding = "".to_string();
+                seen.push(number);
 
-        for _ in 0..file_str_simple.len() {
-            marker_padding.push(' ');
+                if let Some(origin) = self.meta_origin(number) {
+                    diagnostic = diagnostic.label(
+                        origin.span, format!("`?{}` is the {}", number, origin.description));
+                }
+            }
         }
 
-        try!(write!(terminal, "{}", marker_padding));
-        try!(terminal.fg(color::RED));
-        try!(writeln!(terminal, "{}", marker));
-        try!(terminal.reset());
-        try!(terminal.flush());
+        diagnostic
+    }
 
-        Ok(())
+    pub fn display_options(&self) -> DisplayOptions {
+        let data = self.data.borrow();
+        let options = &data.options;
+
+        let mut display = if options.verbose_errors {
+            DisplayOptions::verbose()
+        } else {
+            DisplayOptions::default()
+        };
+
+        if options.show_implicits {
+            display.hide_implicits = false;
+        }
+
+        // Terms are laid out to fit the output when its width is known.
+        let width = match data.output {
+            Output::Terminal(ref renderer) | Output::Stderr(ref renderer) => renderer.width(),
+            Output::Buffer(ref buffer) => buffer.width(),
+            Output::Collected(_) => None,
+        };
+
+        if let Some(width) = width {
+            display.line_width = Some(width);
+        }
+
+        display
     }
 
-    pub fn error(&self, message: String) -> io::Result<()> {
-        let mut session_data = self.data.borrow_mut();
-        let &mut SessionData {
-            ref mut terminal,
-            ref mut source_maps,
-            .. } = &mut *session_data;
+    /// Render a term for use in a diagnostic, eliding parts of it unless
+    /// `--verbose-errors` was passed.
+    pub fn render(&self, term: &Term) -> String {
+        let options = self.display_options();
+        display::render(term, options, &self.data.borrow().implicits)
+    }
 
-        try!(terminal.fg(color::RED));
-        try!(write!(terminal, "error: "));
-        try!(terminal.reset());
-        try!(writeln!(terminal, "{}", message));
-        try!(terminal.flush());
-        Ok(())
+    /// Render a term in full, as the output of a command is.
+    pub fn render_in_full(&self, term: &Term) -> String {
+        display::render(term, DisplayOptions::verbose(), &self.data.borrow().implicits)
+    }
+
+    /// Report `diagnostic` at the level set for `lint`, nothing is
+    /// reported if the lint is allowed.
+    pub fn lint(&self, lint: Lint, mut diagnostic: Diagnostic) -> io::Result<()> {
+        let (level, is_default) = {
+            let lints = &self.data.borrow().options.lints;
+            (lints.level(lint), lints.is_default(lint))
+        };
+
+        match level {
+            LintLevel::Allow => return Ok(()),
+            LintLevel::Warn => diagnostic.level = Level::Warning,
+            LintLevel::Deny => {
+                diagnostic.level = Level::Error;
+                self.data.borrow_mut().lint_errors += 1;
+            }
+        }
+
+        if is_default {
+            diagnostic = diagnostic.note(format!("`{} {}` is on by default", level.flag(), lint));
+        }
+
+        self.emit(diagnostic)
+    }
+
+    /// The number of lints that were reported as errors.
+    pub fn lint_errors(&self) -> usize {
+        self.data.borrow().lint_errors
+    }
+
+    /// The number of errors reported so far.
+    pub fn error_count(&self) -> usize {
+        self.data.borrow().errors
+    }
+
+    pub fn error(&self, message: String) -> io::Result<()> {
+        Diagnostic::error(message).emit(self)
     }
 
     pub fn internal_error(&self, message: String) -> io::Result<()> {
+        Diagnostic::new(Level::InternalError, message).emit(self)
+    }
+
+    /// Write a diagnostic to the terminal, the primary span is underlined
+    /// in the color of the diagnostic's level, secondary spans are
+    /// underlined in blue and followed by their label. When diagnostics
+    /// are being collected it is kept instead.
+    pub fn emit(&self, diagnostic: Diagnostic) -> io::Result<()> {
         let mut session_data = self.data.borrow_mut();
         let &mut SessionData {
-            ref mut terminal,
-            ref mut source_maps,
+            ref mut output,
+            ref source_maps,
+            ref mut errors,
             .. } = &mut *session_data;
 
-        try!(terminal.fg(color::RED));
-        try!(write!(terminal, "internal error: "));
-        try!(terminal.reset());
-        try!(writeln!(terminal, "{}", message));
-        try!(terminal.flush());
-        Ok(())
+        if diagnostic.level != Level::Warning {
+            *errors += 1;
+        }
+
+        match output {
+            &mut Output::Terminal(ref mut terminal) =>
+                write_diagnostic(&mut **terminal, source_maps, diagnostic),
+            &mut Output::Stderr(ref mut terminal) =>
+                write_diagnostic(&mut **terminal, source_maps, diagnostic),
+            &mut Output::Buffer(ref mut buffer) =>
+                write_diagnostic(buffer, source_maps, diagnostic),
+            &mut Output::Collected(ref mut diagnostics) => {
+                diagnostics.push(diagnostic);
+                Ok(())
+            }
+        }
     }
 
     pub fn is_loaded(&self, path: &Path) -> bool {
@@ -237,8 +745,50 @@ impl Session   {
         session_data.imported_files.contains(path)
     }
 
+    /// Record that the file at `path` has been loaded, so loading it again
+    /// does nothing.
+    pub fn mark_loaded(&self, path: &Path) {
+        self.data.borrow_mut().imported_files.insert(path.to_owned());
+    }
+
+    /// Resolve a relative path against the load paths, returning the first
+    /// candidate that exists, or a path in the global library directory if
+    /// none do. A session without load paths leaves `path` as it is.
     pub fn resolve_path(&self, path: &Path) -> PathBuf {
-        self.data.borrow().load_paths[0].join(path)
+        let data = self.data.borrow();
+
+        for load_path in &data.load_paths {
+            let candidate = load_path.join(path);
+            if candidate.is_file() {
+                return candidate;
+            }
+        }
+
+        match data.load_paths.last() {
+            Some(load_path) => load_path.join(path),
+            None => path.to_owned(),
+        }
+    }
+
+    /// Search `path` for imports before the other load paths.
+    pub fn add_load_path(&self, path: PathBuf) {
+        self.data.borrow_mut().load_paths.insert(0, path);
+    }
+
+    /// Resolve the file for `module_name`, consulting the module map before
+    /// searching the load paths for `path`.
+    pub fn resolve_module(&self, module_name: &str, path: &Path) -> PathBuf {
+        match self.data.borrow().module_map.get(module_name) {
+            Some(file) => return file.clone(),
+            None => {}
+        }
+
+        self.resolve_path(path)
+    }
+
+    /// Resolve imports of `module_name` to `file`.
+    pub fn map_module(&self, module_name: String, file: PathBuf) {
+        self.data.borrow_mut().module_map.insert(module_name, file);
     }
 }
 
@@ -247,3 +797,107 @@ impl HasSession for Session {
         self
     }
 }
+
+/// Write `diagnostic` to the terminal as described by `Session::emit`.
+fn write_diagnostic<T: Renderer + ?Sized>(terminal: &mut T,
+                                          source_maps: &HashMap<ModuleId, SourceMap>,
+                                          diagnostic: Diagnostic) -> io::Result<()> {
+    let level_color = match diagnostic.level {
+        Level::Warning => color::YELLOW,
+        Level::Error | Level::InternalError => color::RED,
+    };
+
+    let emp = SourceMap::empty();
+
+    match diagnostic.primary {
+        Some(span) => {
+            let source_map = source_maps.get(&span.module_id).unwrap_or(&emp);
+
+            // TODO: We need to know if we wrap around to more then one line.
+            let (line_no, col_no) = source_map.position(span)
+                                              .unwrap_or((0,0));
+
+            let filename_str = format!("{}:{}:{}: {}:{} ",
+                source_map.file_name,
+                line_no,
+                col_no,
+                line_no, // this should be the line where we end, not the same line
+                col_no + source_map.columns(span.lo, span.hi)); // this should be the column we end at
+
+            try!(write!(terminal, "{}", filename_str));
+            try!(terminal.fg(level_color));
+            try!(write!(terminal, "{}: ", diagnostic.level));
+            try!(terminal.reset());
+            try!(writeln!(terminal, "{}", diagnostic.message));
+
+            try!(write_snippet(terminal, source_map, span, level_color, None));
+        }
+        None => {
+            try!(terminal.fg(level_color));
+            try!(write!(terminal, "{}: ", diagnostic.level));
+            try!(terminal.reset());
+            try!(writeln!(terminal, "{}", diagnostic.message));
+        }
+    }
+
+    for (span, label) in diagnostic.labels {
+        let source_map = source_maps.get(&span.module_id).unwrap_or(&emp);
+        try!(write_snippet(terminal, source_map, span, color::BRIGHT_BLUE, Some(label)));
+    }
+
+    for note in diagnostic.notes {
+        try!(terminal.fg(color::BRIGHT_BLUE));
+        try!(write!(terminal, "note: "));
+        try!(terminal.reset());
+        try!(writeln!(terminal, "{}", note));
+    }
+
+    for help in diagnostic.help {
+        try!(terminal.fg(color::BRIGHT_GREEN));
+        try!(write!(terminal, "help: "));
+        try!(terminal.reset());
+        try!(writeln!(terminal, "{}", help));
+    }
+
+    try!(terminal.flush());
+
+    Ok(())
+}
+
+/// Write the source line containing `span` with the span underlined, and
+/// optionally a label after the underline.
+fn write_snippet<T: Renderer + ?Sized>(terminal: &mut T,
+                                       source_map: &SourceMap,
+                                       span: Span,
+                                       marker_color: color::Color,
+                                       label: Option<String>) -> io::Result<()> {
+    let (line_no, _) = source_map.position(span)
+                                 .unwrap_or((0,0));
+
+    let (line_with_padding, marker) = source_map
+                                          .underline_span(span)
+                                          .unwrap_or((format!("??"),format!("??")));
+
+    let file_str_simple =
+        format!("{}:{}: ",
+            source_map.file_name,
+            line_no);
+
+    try!(write!(terminal, "{} {}", file_str_simple, line_with_padding));
+
+    let mut marker_padding = "".to_string();
+
+    for _ in 0..file_str_simple.len() {
+        marker_padding.push(' ');
+    }
+
+    try!(write!(terminal, "{}", marker_padding));
+    try!(terminal.fg(marker_color));
+    match label {
+        None => try!(writeln!(terminal, "{}", marker)),
+        Some(label) => try!(writeln!(terminal, "{} {}", marker.trim_right(), label)),
+    }
+    try!(terminal.reset());
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+//! Proving goals with an external SMT solver.
+//!
+//! A goal is translated to an SMT-LIB script asserting the hypotheses in
+//! scope and the negation of the goal, which is piped to the solver given
+//! with `--smt-solver`. When the solver finds the script unsatisfiable the
+//! goal holds. Its reasoning is not checked, so the elaborator declares the
+//! goal as an axiom of the definition using it, where it is listed with
+//! every other assumption the program makes.
+
+mod translate;
+
+pub use self::translate::{translate, Script};
+
+use core;
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Stdio};
+
+/// What the solver found.
+pub enum Answer {
+    /// The hypotheses and the negated goal are contradictory, so the goal
+    /// holds.
+    Unsat,
+    /// There is a counterexample, the model the solver printed.
+    Sat(String),
+    /// The solver gave up.
+    Unknown,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// No solver was given with `--smt-solver`.
+    NoSolver,
+    /// The goal, or this part of it, has no SMT-LIB counterpart.
+    Untranslatable(core::Term),
+    /// The solver could not be started.
+    Spawn(String, io::Error),
+    /// The solver exited before answering, with the output it wrote to
+    /// standard error.
+    Crashed(String, String),
+    /// The solver answered with something other than `sat`, `unsat` or
+    /// `unknown`.
+    UnexpectedOutput(String, String),
+}
+
+/// A solver reading SMT-LIB from its standard input.
+pub struct Solver {
+    command: String,
+    program: String,
+    args: Vec<String>,
+}
+
+impl Solver {
+    /// The solver run by `command`, a program followed by its arguments,
+    /// such as `z3 -in`.
+    pub fn from_command(command: &str) -> Option<Solver> {
+        let mut words = command.split_whitespace().map(|w| w.to_string());
+
+        words.next().map(|program| {
+            Solver {
+                command: command.to_string(),
+                program: program,
+                args: words.collect(),
+            }
+        })
+    }
+
+    /// Run `script`, which must end with `(check-sat)`, and ask for a model
+    /// if it is satisfiable.
+    pub fn check(&self, script: &Script) -> Result<Answer, Error> {
+        let mut child = try!(Command::new(&self.program)
+                                 .args(&self.args)
+                                 .stdin(Stdio::piped())
+                                 .stdout(Stdio::piped())
+                                 .stderr(Stdio::piped())
+                                 .spawn()
+                                 .map_err(|e| Error::Spawn(self.command.clone(), e)));
+
+        let result = self.converse(&mut child, script);
+        let _ = child.kill();
+        let _ = child.wait();
+        result
+    }
+
+    fn converse(&self, child: &mut Child, script: &Script) -> Result<Answer, Error> {
+        let mut stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+        if stdin.write_all(script.text.as_bytes()).and_then(|_| stdin.flush()).is_err() {
+            return Err(self.crashed(child));
+        }
+
+        let mut answer = String::new();
+        match stdout.read_line(&mut answer) {
+            Ok(n) if n > 0 => {}
+            _ => return Err(self.crashed(child)),
+        }
+
+        match answer.trim() {
+            "unsat" => Ok(Answer::Unsat),
+            "unknown" => Ok(Answer::Unknown),
+            "sat" => {
+                let _ = stdin.write_all(b"(get-model)\n(exit)\n");
+                drop(stdin);
+
+                let mut model = String::new();
+                let _ = stdout.read_to_string(&mut model);
+                Ok(Answer::Sat(model.trim().to_string()))
+            }
+            other => Err(Error::UnexpectedOutput(self.command.clone(), other.to_string())),
+        }
+    }
+
+    fn crashed(&self, child: &mut Child) -> Error {
+        let mut stderr = String::new();
+
+        if let Some(ref mut err) = child.stderr {
+            let _ = err.read_to_string(&mut stderr);
+        }
+
+        Error::Crashed(self.command.clone(), stderr.trim().to_string())
+    }
+}
@@ -0,0 +1,172 @@
+//! Translating goals to SMT-LIB.
+//!
+//! Propositions built from `True`, `False`, `And`, `Or`, implication and
+//! equations between natural numbers are translated to formulas, and the
+//! numbers built from `Z`, `S`, `add` and `mul` to integer terms. Any other
+//! number is an atom, a constant known only to be non-negative, and the
+//! same term is always the same atom.
+//!
+//! The goal is declared as an axiom once the solver proves it, so these
+//! globals are only given a meaning when they are declared as in the
+//! prelude, see `TyCtxt::is_prelude`. A `def add (n m : Nat) : Nat := n`
+//! shadowing the prelude's would otherwise let `smt` prove
+//! `Eq Nat (add n (S Z)) (S n)`, from which `False` follows.
+
+use core::{Name, Term};
+use typeck::{NormalForm, ReduceOptions, TyCtxt};
+
+use super::Error;
+
+/// A script checking whether `goal` can fail while the hypotheses hold.
+pub struct Script {
+    pub text: String,
+    /// The term each atom stands for.
+    pub atoms: Vec<(String, Term)>,
+}
+
+/// The script asserting the hypotheses that can be translated and the
+/// negation of `goal`.
+pub fn translate(ty_cx: &TyCtxt,
+                 options: ReduceOptions,
+                 hypotheses: &[Term],
+                 goal: &Term) -> Result<Script, Error> {
+    let mut translator = Translator {
+        ty_cx: ty_cx,
+        options: options,
+        prelude: GLOBALS.iter().cloned().filter(|global| ty_cx.is_prelude(global)).collect(),
+        atoms: vec![],
+    };
+
+    let goal = try!(translator.formula(goal));
+
+    let mut assertions = vec![];
+    for hypothesis in hypotheses {
+        let atoms = translator.atoms.len();
+
+        match translator.formula(hypothesis) {
+            Ok(formula) => assertions.push(formula),
+            // A hypothesis outside of the fragment is left out, it can only
+            // make fewer goals provable.
+            Err(_) => translator.atoms.truncate(atoms),
+        }
+    }
+
+    let mut text = String::new();
+    text.push_str("(set-logic ALL)\n");
+    text.push_str("(set-option :produce-models true)\n");
+
+    let atoms: Vec<_> = translator.atoms.into_iter().enumerate().map(|(i, term)| {
+        (format!("a{}", i), term)
+    }).collect();
+
+    for &(ref atom, ref term) in &atoms {
+        text.push_str(&format!("; {} is `{}`\n", atom, term));
+        text.push_str(&format!("(declare-const {} Int)\n", atom));
+        text.push_str(&format!("(assert (>= {} 0))\n", atom));
+    }
+
+    for assertion in assertions {
+        text.push_str(&format!("(assert {})\n", assertion));
+    }
+
+    text.push_str(&format!("(assert (not {}))\n", goal));
+    text.push_str("(check-sat)\n");
+
+    Ok(Script {
+        text: text,
+        atoms: atoms,
+    })
+}
+
+struct Translator<'a> {
+    ty_cx: &'a TyCtxt,
+    options: ReduceOptions,
+    /// The globals in scope that are declared as in the prelude.
+    prelude: Vec<&'static str>,
+    atoms: Vec<Term>,
+}
+
+impl<'a> Translator<'a> {
+    fn formula(&mut self, term: &Term) -> Result<String, Error> {
+        let term = try!(self.ty_cx
+                            .reduce_to(term, NormalForm::WeakHead, self.options)
+                            .map_err(|_| Error::Untranslatable(term.clone())));
+
+        // An implication is a function whose result does not depend on its
+        // argument.
+        if let Term::Forall { ref binder, term: ref body, .. } = term {
+            let x = self.ty_cx.local_with_repr("x".to_string(), (*binder.ty).clone());
+            let body = body.instantiate(&x.to_term());
+
+            if body.abstr(&x) != body {
+                return Err(Error::Untranslatable(term.clone()));
+            }
+
+            let premise = try!(self.formula(&binder.ty));
+            let conclusion = try!(self.formula(&body));
+            return Ok(format!("(=> {} {})", premise, conclusion));
+        }
+
+        let (head, args) = term.uncurry();
+
+        let connective = match (self.global(&head), args.len()) {
+            (Some("True"), 0) => return Ok("true".to_string()),
+            (Some("False"), 0) => return Ok("false".to_string()),
+            (Some("And"), 2) => "and",
+            (Some("Or"), 2) => "or",
+            (Some("Eq"), 3) if self.global(&args[0]) == Some("Nat") => {
+                let lhs = self.number(&args[1]);
+                let rhs = self.number(&args[2]);
+                return Ok(format!("(= {} {})", lhs, rhs));
+            }
+            _ => return Err(Error::Untranslatable(term.clone())),
+        };
+
+        let lhs = try!(self.formula(&args[0]));
+        let rhs = try!(self.formula(&args[1]));
+        Ok(format!("({} {} {})", connective, lhs, rhs))
+    }
+
+    fn number(&mut self, term: &Term) -> String {
+        let (head, args) = term.uncurry();
+
+        match (self.global(&head), args.len()) {
+            (Some("Z"), 0) => "0".to_string(),
+            (Some("S"), 1) => format!("(+ {} 1)", self.number(&args[0])),
+            (Some("add"), 2) => format!("(+ {} {})", self.number(&args[0]), self.number(&args[1])),
+            (Some("mul"), 2) => format!("(* {} {})", self.number(&args[0]), self.number(&args[1])),
+            _ => {
+                let atom = match self.atoms.iter().position(|atom| atom == term) {
+                    Some(atom) => atom,
+                    None => {
+                        self.atoms.push(term.clone());
+                        self.atoms.len() - 1
+                    }
+                };
+
+                format!("a{}", atom)
+            }
+        }
+    }
+
+    /// The name of the prelude's global `term` refers to, if it does.
+    fn global(&self, term: &Term) -> Option<&'static str> {
+        global_name(term).and_then(|name| {
+            self.prelude.iter().cloned().find(|&global| global == name)
+        })
+    }
+}
+
+/// The globals the translation gives a meaning to.
+const GLOBALS: &'static [&'static str] = &[
+    "Nat", "Z", "S", "add", "mul", "Eq", "True", "False", "And", "Or",
+];
+
+/// The name of the global `term` refers to, if it has a single component.
+fn global_name(term: &Term) -> Option<&str> {
+    match term {
+        &Term::Var { name: Name::Qual { ref components, .. } } if components.len() == 1 =>
+            Some(&components[0][..]),
+        _ => None,
+    }
+}
@@ -1,4 +1,5 @@
 use super::parser::{Error};
+use diagnostic::Diagnostic;
 use session::{Session, Reportable};
 use std::io;
 
@@ -8,18 +9,26 @@ impl Reportable for Error {
     fn report(self, session: &Session) -> io::Result<()> {
         match self {
             Error::InvalidToken { location } =>
-                session.span_error(location, format!("invalid token")),
+                Diagnostic::error(format!("invalid token"))
+                    .span(location)
+                    .emit(session),
             Error::UnrecognizedToken { location, token, expected } =>
-                session.span_error(location,
-                    format!("unrecognized token {}; expected {:?}", token, expected)),
+                Diagnostic::error(format!("unrecognized token {}", token))
+                    .span(location)
+                    .note(format!("expected one of {:?}", expected))
+                    .emit(session),
             Error::UnexpectedEOF { expected } =>
                 session.error(format!("unexpected end of file expected {:?}", expected)),
             Error::UserError { error } =>
                 session.error(format!("user error: {:?}", error)),
             Error::ExtraTokens { location, token } =>
-                session.span_error(location, format!("extra tokens {:?}", token)),
+                Diagnostic::error(format!("extra tokens {:?}", token))
+                    .span(location)
+                    .emit(session),
             Error::TokenizerError { location, message } =>
-                session.span_error(location, message)
+                Diagnostic::error(message)
+                    .span(location)
+                    .emit(session)
         }
     }
 }
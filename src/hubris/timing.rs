@@ -0,0 +1,166 @@
+//! Collection of per-phase timings and counters, reported when the compiler
+//! is invoked with `--timings`.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// The time a phase started, the clock is not available on
+/// `wasm32-unknown-unknown` so there every phase takes no time.
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+#[derive(Copy, Clone, Debug)]
+pub struct Instant;
+
+#[cfg(target_arch = "wasm32")]
+impl Instant {
+    pub fn now() -> Instant {
+        Instant
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::new(0, 0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Phase {
+    Parse,
+    Elaborate,
+    Solve,
+    Kernel,
+    Erasure,
+    Optimize,
+    Codegen,
+}
+
+impl Display for Phase {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        use self::Phase::*;
+
+        let name = match *self {
+            Parse => "parse",
+            Elaborate => "elaborate",
+            Solve => "solve",
+            Kernel => "kernel check",
+            Erasure => "erasure",
+            Optimize => "optimize",
+            Codegen => "codegen",
+        };
+
+        write!(formatter, "{}", name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Counter {
+    Constraints,
+    Metas,
+    Reductions,
+}
+
+impl Display for Counter {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        use self::Counter::*;
+
+        let name = match *self {
+            Constraints => "constraints generated",
+            Metas => "metavariables created",
+            Reductions => "reductions performed",
+        };
+
+        write!(formatter, "{}", name)
+    }
+}
+
+/// A single timed event, `label` is used to distinguish events in the same
+/// phase, for example the name of the definition being elaborated.
+#[derive(Clone, Debug)]
+struct Entry {
+    phase: Phase,
+    label: Option<String>,
+    elapsed: Duration,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Timings {
+    entries: Vec<Entry>,
+    counters: HashMap<Counter, usize>,
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + d.subsec_nanos() as f64 / 1_000_000.0
+}
+
+impl Timings {
+    pub fn new() -> Timings {
+        Timings::default()
+    }
+
+    pub fn record(&mut self, phase: Phase, label: Option<String>, elapsed: Duration) {
+        self.entries.push(Entry {
+            phase: phase,
+            label: label,
+            elapsed: elapsed,
+        });
+    }
+
+    pub fn count(&mut self, counter: Counter, n: usize) {
+        *self.counters.entry(counter).or_insert(0) += n;
+    }
+
+    pub fn counter(&self, counter: Counter) -> usize {
+        self.counters.get(&counter).cloned().unwrap_or(0)
+    }
+
+    fn total(&self, phase: Phase) -> (Duration, usize) {
+        self.entries
+            .iter()
+            .filter(|e| e.phase == phase)
+            .fold((Duration::new(0, 0), 0), |(d, n), e| (d + e.elapsed, n + 1))
+    }
+
+    /// Write a table summarizing the time spent in each phase, followed by
+    /// the slowest labeled events, and finally the counters.
+    pub fn report<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        use self::Phase::*;
+
+        try!(writeln!(out, "{:<24} {:>12} {:>8}", "phase", "time (ms)", "count"));
+
+        for &phase in &[Parse, Elaborate, Solve, Kernel, Erasure, Optimize, Codegen] {
+            let (elapsed, count) = self.total(phase);
+            try!(writeln!(out, "{:<24} {:>12.3} {:>8}",
+                          format!("{}", phase), millis(elapsed), count));
+        }
+
+        let mut labeled: Vec<_> =
+            self.entries
+                .iter()
+                .filter(|e| e.label.is_some())
+                .collect();
+
+        if labeled.len() > 0 {
+            labeled.sort_by(|a, b| b.elapsed.cmp(&a.elapsed));
+
+            try!(writeln!(out, ""));
+            try!(writeln!(out, "{:<40} {:>12}", "slowest", "time (ms)"));
+
+            for entry in labeled.iter().take(10) {
+                try!(writeln!(out, "{:<40} {:>12.3}",
+                              format!("{} {}", entry.phase, entry.label.as_ref().unwrap()),
+                              millis(entry.elapsed)));
+            }
+        }
+
+        try!(writeln!(out, ""));
+
+        for &counter in &[Counter::Constraints, Counter::Metas, Counter::Reductions] {
+            try!(writeln!(out, "{:<24} {:>12}", format!("{}", counter), self.counter(counter)));
+        }
+
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@
 
 
 use std::cmp::{PartialOrd, Ordering};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::{self, Debug, Formatter, Display};
 use std::rc::Rc;
 
@@ -16,6 +16,49 @@ pub type ConstraintSeq = Vec<Constraint>;
 pub enum Constraint {
     Unification(Term, Term, Justification),
     Choice(Term, Term, ChoiceProcedure, Justification),
+    /// An application whose function type is stuck on a metavariable, the
+    /// argument is checked once the metavariable has been solved.
+    Application(DelayedApplication, Justification),
+    /// An instance argument, solved by searching the instances in scope.
+    Instance(InstanceGoal, Justification),
+}
+
+/// A metavariable standing for an instance argument of type `ty`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InstanceGoal {
+    /// The application the argument was inserted into.
+    pub span: Span,
+    pub meta: Term,
+    pub ty: Term,
+    /// The number of instances whose arguments led to this goal.
+    pub depth: usize,
+    /// The locals in scope where the argument was inserted, its instance
+    /// arguments and hypotheses are candidates along with the global
+    /// instances.
+    pub context: Vec<Name>,
+}
+
+/// The locals in scope where the metavariable `meta` was created, which it
+/// is applied to.
+pub fn local_context(meta: &Term) -> Vec<Name> {
+    meta.args().unwrap_or(vec![]).into_iter().filter_map(|arg| match arg {
+        Term::Var { name: name @ Name::Local { .. } } => Some(name),
+        _ => None,
+    }).collect()
+}
+
+/// The argument of an application `f a` that could not be checked when
+/// the type of `f` was inferred.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DelayedApplication {
+    /// The function `f`.
+    pub fun: Term,
+    /// The type inferred for `f`, which does not yet reduce to a `forall`.
+    pub fun_ty: Term,
+    pub arg: Term,
+    pub arg_ty: Term,
+    /// The metavariable standing for the type of `f a`.
+    pub result_ty: Term,
 }
 
 impl PartialEq for Constraint {
@@ -26,6 +69,12 @@ impl PartialEq for Constraint {
             (&Unification(ref t1, ref u1, ref j1), &Unification(ref t2, ref u2, ref j2)) => {
                 t1 == t2 && u1 == u2 && j1 == j2
             },
+            (&Application(ref a1, ref j1), &Application(ref a2, ref j2)) => {
+                a1 == a2 && j1 == j2
+            },
+            (&Instance(ref g1, ref j1), &Instance(ref g2, ref j2)) => {
+                g1 == g2 && j1 == j2
+            },
             (_, _) => false,
         }
     }
@@ -34,7 +83,7 @@ impl PartialEq for Constraint {
 impl Eq for Constraint {}
 
 #[derive(Clone)]
-struct ChoiceProcedure(Rc<Fn(Term, Term, HashMap<Name, (Term, Justification)>) -> ()>);
+struct ChoiceProcedure(Rc<Fn(Term, Term, BTreeMap<Name, (Term, Justification)>) -> ()>);
 
 impl Debug for ChoiceProcedure {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
@@ -110,6 +159,16 @@ impl Constraint {
                     panic!("not sure how to categorize constraint")
                 }
             }
+            Application(app, j) => CategorizedConstraint {
+                constraint: Application(app, j),
+                category: Postponed,
+            },
+            // Instances are searched for once unification has solved as
+            // much of their types as it can.
+            Instance(goal, j) => CategorizedConstraint {
+                constraint: Instance(goal, j),
+                category: Postponed,
+            },
             Choice(..)=> panic!(),
         }
     }
@@ -120,6 +179,10 @@ impl Display for Constraint {
         match self {
             &Constraint::Unification(ref t, ref u, ref j) =>
                 write!(formatter, "{} = {} by {:?}", t, u, j),
+            &Constraint::Application(ref app, ref j) =>
+                write!(formatter, "({}) {} : {} by {:?}", app.fun_ty, app.arg, app.result_ty, j),
+            &Constraint::Instance(ref goal, ref j) =>
+                write!(formatter, "{} : [{}] by {:?}", goal.meta, goal.ty, j),
             _ => panic!()
         }
     }
@@ -149,7 +212,9 @@ pub enum Justification {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum AssertedBy {
     Application(Span, Term, Term),
-    ExpectedFound(Term, Term),
+    /// The term at the span was found to have the first type, but its
+    /// annotation expects the second.
+    ExpectedFound(Span, Term, Term),
 }
 
 impl Display for AssertedBy {
@@ -159,7 +224,7 @@ impl Display for AssertedBy {
         match self {
             &Application(span, ref u, ref t) =>
                 write!(formatter, "applied {} to {}", u, t),
-            &ExpectedFound(ref ty, ref infer_ty) =>
+            &ExpectedFound(_, ref ty, ref infer_ty) =>
                 write!(formatter, "expected {} found {}", ty, infer_ty),
         }
     }
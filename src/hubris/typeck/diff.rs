@@ -0,0 +1,97 @@
+//! A structural diff of two terms, used when reporting a mismatch between an
+//! expected and found type so that only the parts that disagree are shown.
+
+use core::{Binder, Name, Term};
+
+/// The result of comparing two terms, subterms that the two sides agree on
+/// have been replaced by `_`.
+pub struct TermDiff {
+    pub expected: Term,
+    pub found: Term,
+    /// Whether any part of the terms was elided.
+    pub elided: bool,
+}
+
+pub fn diff(expected: &Term, found: &Term) -> TermDiff {
+    if expected == found {
+        return TermDiff {
+            expected: expected.clone(),
+            found: found.clone(),
+            elided: false,
+        };
+    }
+
+    let mut elided = false;
+    let (expected, found) = diff_terms(expected, found, &mut elided);
+
+    TermDiff {
+        expected: expected,
+        found: found,
+        elided: elided,
+    }
+}
+
+fn elision() -> Term {
+    Name::from_str("_").to_term()
+}
+
+fn diff_binders(b1: &Binder, b2: &Binder, elided: &mut bool) -> (Binder, Binder) {
+    let (ty1, ty2) = diff_terms(&b1.ty, &b2.ty, elided);
+
+    let mut b1 = b1.clone();
+    let mut b2 = b2.clone();
+    b1.ty = Box::new(ty1);
+    b2.ty = Box::new(ty2);
+
+    (b1, b2)
+}
+
+fn diff_terms(expected: &Term, found: &Term, elided: &mut bool) -> (Term, Term) {
+    use core::Term::*;
+
+    if expected == found {
+        *elided = true;
+        return (elision(), elision());
+    }
+
+    match (expected, found) {
+        (&App { .. }, &App { .. }) => {
+            let (e_head, e_args) = expected.uncurry();
+            let (f_head, f_args) = found.uncurry();
+
+            // We only descend into applications of the same head, otherwise
+            // eliding the arguments would hide the actual difference.
+            if e_head != f_head || e_args.len() != f_args.len() {
+                return (expected.clone(), found.clone());
+            }
+
+            let mut es = vec![];
+            let mut fs = vec![];
+
+            for (e, f) in e_args.iter().zip(f_args.iter()) {
+                let (e, f) = diff_terms(e, f, elided);
+                es.push(e);
+                fs.push(f);
+            }
+
+            (Term::apply_all(e_head, es), Term::apply_all(f_head, fs))
+        }
+        (&Forall { span: s1, binder: ref b1, term: ref t1 },
+         &Forall { span: s2, binder: ref b2, term: ref t2 }) if b1.mode == b2.mode => {
+            let (b1, b2) = diff_binders(b1, b2, elided);
+            let (t1, t2) = diff_terms(t1, t2, elided);
+
+            (Forall { span: s1, binder: b1, term: Box::new(t1) },
+             Forall { span: s2, binder: b2, term: Box::new(t2) })
+        }
+        (&Lambda { span: s1, binder: ref b1, body: ref t1 },
+         &Lambda { span: s2, binder: ref b2, body: ref t2 }) if b1.mode == b2.mode => {
+            let (b1, b2) = diff_binders(b1, b2, elided);
+            let (t1, t2) = diff_terms(t1, t2, elided);
+
+            (Lambda { span: s1, binder: b1, body: Box::new(t1) },
+             Lambda { span: s2, binder: b2, body: Box::new(t2) })
+        }
+        _ => (expected.clone(), found.clone()),
+    }
+}
@@ -1,25 +1,59 @@
 use super::super::ast::{Span, HasSpan};
 use super::super::core::{Term, Name};
+use super::super::diagnostic::{Diagnostic, Level};
 use super::super::session::{Reportable, HasSession, Session};
 use parser;
 use super::solver;
+use super::validate::Violation;
 
 use std::io;
+use std::path::PathBuf;
 use term;
+use hubris_kernel;
 
 #[derive(Debug)]
 pub enum Error {
     ExpectedFunction(Span, Term),
+    /// Reducing the term at `span` took more than the given number of steps.
+    ReductionLimit(Span, usize),
     ApplicationMismatch(Span, Term, Term, Term, Term),
+    /// A function applied to more arguments than its type allows, `span`
+    /// is the location of the first extra argument.
+    ArityMismatch {
+        span: Span,
+        fun: Term,
+        ty: Term,
+        expected: usize,
+        found: usize,
+    },
     DefUnequal(Span, Term, Term, Vec<(Term, Term)>),
+    /// An erased variable of `definition` used where its value is needed.
+    ErasedUse {
+        span: Span,
+        name: String,
+        definition: Name,
+    },
     UnknownVariable(Name),
+    /// An import of a name that can not be the name of a module.
+    InvalidImport(Name),
     NameExists(Name),
     NoMain,
+    /// `main`, declared at `span` with the given type, can not be the entry
+    /// point of an executable.
+    MainType(Span, Term),
+    /// The `fix` at `span` is not structurally recursive.
+    Unguarded(Span, hubris_kernel::guard::Unguarded),
     Many(Vec<Error>),
     Io(io::Error),
     Parser(parser::Error),
     Term(term::Error),
     Solver(solver::Error),
+    Kernel(hubris_kernel::Error),
+    Import(hubris_kernel::import::Error),
+    /// The image at the path could not be decoded.
+    Image(PathBuf, String),
+    /// An elaborated module breaks the invariants later phases rely on.
+    Invalid(Vec<Violation>),
 }
 
 impl From<io::Error> for Error {
@@ -46,44 +80,91 @@ impl From<solver::Error> for Error {
     }
 }
 
+impl From<hubris_kernel::Error> for Error {
+    fn from(err: hubris_kernel::Error) -> Error {
+        Error::Kernel(err)
+    }
+}
+
+impl From<hubris_kernel::import::Error> for Error {
+    fn from(err: hubris_kernel::import::Error) -> Error {
+        Error::Import(err)
+    }
+}
+
 impl Reportable for Error {
     fn report(self, cx: &Session) -> io::Result<()> {
         match self {
             Error::UnknownVariable(name) => {
-                cx.span_error(name.get_span(),
-                    format!("unknown variable `{}`", name))
+                Diagnostic::error(format!("unknown variable `{}`", name))
+                    .span(name.get_span())
+                    .emit(cx)
+            }
+            Error::InvalidImport(name) => {
+                Diagnostic::error(format!("`{}` is not the name of a module", name))
+                    .span(name.get_span())
+                    .emit(cx)
             }
             Error::DefUnequal(span, t1, t2, disequalities) => {
-                let msg = format!("the term `{}` is not equivalent to `{}`", t1, t2);
+                let msg = format!("the term `{}` is not equivalent to `{}`",
+                                  cx.render(&t1), cx.render(&t2));
 
-                try!(cx.span_error(span, msg));
+                let mut diagnostic = Diagnostic::error(msg).span(span);
 
-                // if disequalities.len() > 1 {
-                //     try!(writeln!(cx.get_terminal(), "in particular:"));
-                //
-                //     for (t, u) in disequalities {
-                //         try!(writeln!(cx.get_terminal(), "    {} not equal to {}", t, u));
-                //     }
-                // }
+                if disequalities.len() > 1 {
+                    for (t, u) in disequalities {
+                        diagnostic = diagnostic.note(
+                            format!("in particular `{}` is not equal to `{}`",
+                                    cx.render(&t), cx.render(&u)));
+                    }
+                }
 
-                Ok(())
+                diagnostic.emit(cx)
             }
             Error::ApplicationMismatch(span, t, u, ty_of_t, ty_of_u) => {
                 let msg = format!(
                     "can not apply `{}` with type `{}`\n \
                      to `{}`` with type `{}`",
-                    t, u, ty_of_t, ty_of_u);
+                    cx.render(&t), cx.render(&u), cx.render(&ty_of_t), cx.render(&ty_of_u));
 
-                try!(cx.span_error(span, msg));
+                Diagnostic::error(msg).span(span).emit(cx)
+            }
+            Error::ArityMismatch { span, fun, ty, expected, found } => {
+                let msg = format!(
+                    "function `{}` expects {} argument{} but got {}",
+                    cx.render(&fun),
+                    expected,
+                    if expected == 1 { "" } else { "s" },
+                    found);
 
-                Ok(())
+                Diagnostic::error(msg)
+                    .span(span)
+                    .label(fun.get_span(), "this function")
+                    .note(format!("`{}` has type `{}`", cx.render(&fun), cx.render(&ty)))
+                    .emit(cx)
+            }
+            Error::ErasedUse { span, name, definition } => {
+                Diagnostic::error(format!("the erased variable `{}` is used at runtime", name))
+                    .span(span)
+                    .note(format!("in the definition of `{}`", definition))
+                    .note("an erased variable may only appear in types and in arguments to \
+                           erased parameters".to_string())
+                    .help(format!("remove the `.` from the binder of `{}`, or mark the \
+                                   parameter it is passed to as erased", name))
+                    .emit(cx)
             }
             Error::ExpectedFunction(span, f) => {
                 let msg = format!(
                     "can not apply term with type `{}` to arguments,
-                     only terms with function types can be applied", f);
+                     only terms with function types can be applied", cx.render(&f));
 
-                cx.span_error(span, msg)
+                Diagnostic::error(msg).span(span).emit(cx)
+            }
+            Error::ReductionLimit(span, limit) => {
+                Diagnostic::error(format!("reduction did not finish within {} steps", limit))
+                    .span(span)
+                    .help("the limit can be raised with `--reduce-steps`".to_string())
+                    .emit(cx)
             }
             Error::Many(errs) => {
                 for err in errs {
@@ -92,10 +173,58 @@ impl Reportable for Error {
                 Ok(())
             }
             Error::NameExists(_) => panic!(),
-            Error::NoMain => panic!(),
+            Error::NoMain => {
+                Diagnostic::error("no `main` definition found".to_string())
+                    .note("an executable runs by evaluating the definition `main`".to_string())
+                    .help("add a `main`, or use `--emit=lib` or `hubris check` for a \
+                           program without one".to_string())
+                    .emit(cx)
+            }
+            Error::MainType(span, ty) => {
+                Diagnostic::error(format!("`main` has type `{}`, which can not be the \
+                                           entry point of an executable", cx.render(&ty)))
+                    .span(span)
+                    .note("the program evaluates `main` without arguments, so its type \
+                           must be a data type such as `Unit` or `Nat`, or a type declared \
+                           with `axiom` such as `UInt32`".to_string())
+                    .emit(cx)
+            }
+            Error::Unguarded(span, unguarded) => {
+                let call_span = unguarded.call.get_span();
+                let span = if call_span == Span::dummy() { span } else { call_span };
+
+                Diagnostic::error(format!("`{}` is not a call on a strict subterm of the \
+                                           recursive argument of `{}`",
+                                          cx.render(&unguarded.call), unguarded.fix))
+                    .span(span)
+                    .note("a `fix` may only call itself on a variable bound by matching \
+                           on its recursive argument, so that it terminates".to_string())
+                    .emit(cx)
+            }
             Error::Parser(e) => cx.report(e),
             Error::Term(t) => Err(From::from(t)),
             Error::Solver(s) => cx.report(s),
+            Error::Kernel(k) => cx.error(format!("kernel rejected declaration: {}", k)),
+            Error::Import(i) => cx.error(format!("unable to import export file: {}", i)),
+            Error::Image(path, msg) => {
+                Diagnostic::error(format!("unable to load the image `{}`: {}",
+                                          path.display(), msg))
+                    .help("delete the image, or write it again with `--emit=image`".to_string())
+                    .emit(cx)
+            }
+            Error::Invalid(violations) => {
+                for violation in violations {
+                    let mut diagnostic = Diagnostic::new(Level::InternalError, violation.message)
+                        .note(format!("in the elaborated item `{}`", violation.item));
+
+                    if violation.span != Span::dummy() {
+                        diagnostic = diagnostic.span(violation.span);
+                    }
+
+                    try!(diagnostic.emit(cx));
+                }
+                Ok(())
+            }
             Error::Io(e) => Err(e),
         }
     }
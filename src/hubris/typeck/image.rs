@@ -0,0 +1,954 @@
+//! Images of a type checking context.
+//!
+//! An image holds everything a `TyCtxt` learned while elaborating a
+//! development: the kernel's declarations, the inductive types, axioms,
+//! externs and definitions, and the tables the elaborator consults, such as
+//! the instances, unification hints and attributes. An import of a module
+//! for which `Module.image` exists loads the image rather than elaborating
+//! the source, so a large library loads without being elaborated again.
+//! Images are written with `--emit=image`, one older than the source next
+//! to it is ignored and the source elaborated instead, as is one whose
+//! imports were loaded from files that have changed since, each image
+//! recording those files along with a hash of their contents.
+//!
+//! The file is memory-mapped and decoded in a single pass. It starts with
+//! `IMAGE_MAGIC`, the version of the format and the files the image depends
+//! on, followed by each table as a count and its entries. Integers are
+//! unsigned LEB128, strings their length followed by their UTF-8 bytes.
+//! Spans are not kept, as the sources of an image need not be around when
+//! it is loaded.
+//!
+//! The kernel checks each declaration again on loading, as it does those of
+//! an export file, so a corrupted or hand-written image can not introduce
+//! anything the kernel would reject. The types, axioms and definitions the
+//! rest of the compiler reads are taken from the declarations the kernel
+//! accepted rather than from the image, which only has to agree with the
+//! kernel on what kind of declaration each is. The tables of the elaborator
+//! are only consulted while elaborating, and the computation rules of the
+//! recursors, which are closures, are rebuilt.
+
+use ast::Span;
+use core::{Binder, BindingMode, Convention, Data, Definition, DeltaReduction, Name,
+           RecursiveFn, Relevance, Term};
+use hubris_kernel::Declaration;
+#[cfg(not(target_arch = "wasm32"))]
+use memmap;
+
+use super::{inductive, Axiom, Deprecation, Error, TyCtxt, UnifyHint};
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The first bytes of every image.
+pub const IMAGE_MAGIC: &'static [u8] = b"hubris-image";
+
+/// The version of the format, bumped whenever it changes.
+pub const IMAGE_VERSION: u64 = 2;
+
+/// The contents of an image, decoded before any of it is added to the
+/// context.
+struct Image {
+    dependencies: Vec<(PathBuf, u64)>,
+    declarations: Vec<(Name, Declaration)>,
+    types: Vec<Data>,
+    /// The axioms other than the types, constructors and recursors, with
+    /// the calling convention of those that are externs.
+    axioms: Vec<(Name, Term, Option<Convention>)>,
+    definitions: Vec<Definition>,
+    deprecated: Vec<(Name, Deprecation)>,
+    docs: Vec<(Name, String)>,
+    interfaces: Vec<(Name, BTreeSet<Name>)>,
+    unify_hints: Vec<UnifyHint>,
+    instances: Vec<Name>,
+    elab_as: Vec<(Name, String)>,
+    error_messages: Vec<(Name, String)>,
+    rust_exports: Vec<(Name, String)>,
+}
+
+impl TyCtxt {
+    /// Write an image of this context to `out`.
+    pub fn write_image<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut encoder = Encoder { bytes: IMAGE_MAGIC.to_vec() };
+        encoder.uint(IMAGE_VERSION);
+
+        encoder.uint(self.dependencies.len() as u64);
+        for (path, &hash) in &self.dependencies {
+            encoder.string(&path.to_string_lossy());
+            encoder.uint(hash);
+        }
+
+        let declarations = self.kernel.declarations();
+        encoder.uint(declarations.len() as u64);
+        for (name, decl) in declarations {
+            encoder.name(name);
+            encoder.declaration(decl);
+        }
+
+        // The constants declared along with a type are declared again when
+        // the type is loaded.
+        let mut generated = BTreeSet::new();
+
+        encoder.uint(self.types.len() as u64);
+        for data in self.types.values() {
+            encoder.data(data);
+
+            generated.insert(data.name.clone());
+            generated.extend(data.ctors.iter().map(|ctor| ctor.0.clone()));
+            generated.extend(data.name.in_scope("rec".to_string()));
+            generated.extend(data.recursive.iter().map(|rec| rec.name.clone()));
+        }
+
+        let axioms: Vec<_> = self.axioms
+                                 .iter()
+                                 .filter(|&(name, _)| !generated.contains(name))
+                                 .collect();
+
+        encoder.uint(axioms.len() as u64);
+        for (name, axiom) in axioms {
+            encoder.name(name);
+            encoder.term(&axiom.ty);
+
+            match self.externs.get(name) {
+                None => encoder.uint(0),
+                Some(convention) => {
+                    encoder.uint(1);
+                    encoder.convention(convention);
+                }
+            }
+        }
+
+        encoder.uint(self.definitions.len() as u64);
+        for def in self.definitions.values() {
+            encoder.definition(def);
+        }
+
+        encoder.uint(self.deprecated.len() as u64);
+        for (name, deprecation) in &self.deprecated {
+            encoder.name(name);
+            encoder.optional_string(deprecation.message.as_ref());
+
+            match deprecation.replacement {
+                None => encoder.uint(0),
+                Some(ref replacement) => {
+                    encoder.uint(1);
+                    encoder.name(replacement);
+                }
+            }
+        }
+
+        encoder.strings(&self.docs);
+
+        encoder.uint(self.interfaces.len() as u64);
+        for (name, interface) in &self.interfaces {
+            encoder.name(name);
+            encoder.uint(interface.len() as u64);
+            for global in interface {
+                encoder.name(global);
+            }
+        }
+
+        encoder.uint(self.unify_hints.len() as u64);
+        for hint in &self.unify_hints {
+            encoder.name(&hint.projection);
+            encoder.name(&hint.head);
+            encoder.name(&hint.instance);
+        }
+
+        encoder.names(&self.instances);
+        encoder.strings(&self.elab_as);
+        encoder.strings(&self.error_messages);
+        encoder.strings(&self.rust_exports);
+
+        out.write_all(&encoder.bytes)
+    }
+
+    /// Load the image at `path` into the context. A global the context
+    /// already has, because the image and an earlier import share a
+    /// dependency, is kept as it is.
+    pub fn load_image(&mut self, path: &Path) -> Result<(), Error> {
+        debug!("load_image: path={}", path.display());
+
+        let image = {
+            let bytes = try!(Bytes::open(path));
+            let mut decoder = Decoder {
+                bytes: bytes.as_slice(),
+                position: 0,
+                counter: &self.local_counter,
+                locals: HashMap::new(),
+            };
+
+            try!(decoder.image().map_err(|msg| Error::Image(path.to_owned(), msg)))
+        };
+
+        // An image of this context depends on whatever this image did, and
+        // on the source the image was written from.
+        self.dependencies.extend(image.dependencies.into_iter());
+        for source in super::image_sources(path) {
+            if let Some(hash) = super::file_hash(&source) {
+                self.dependencies.insert(source, hash);
+            }
+        }
+
+        for (name, decl) in image.declarations {
+            try!(self.kernel.add_declaration(name, decl));
+        }
+
+        for data in image.types {
+            if self.types.contains_key(&data.name) {
+                continue;
+            }
+
+            let name = data.name.clone();
+            let decl = Declaration::Inductive(data);
+            let data = match try!(self.checked_declaration(&name, decl)) {
+                Declaration::Inductive(data) => data,
+                _ => return Err(mismatch(path, &name)),
+            };
+
+            self.declare_type_constants(&data);
+            try!(inductive::restore_recursor(self, &data));
+        }
+
+        for (name, ty, convention) in image.axioms {
+            if self.axioms.contains_key(&name) {
+                continue;
+            }
+
+            let ty = match try!(self.checked_declaration(&name, Declaration::Axiom(ty))) {
+                Declaration::Axiom(ty) => ty,
+                _ => return Err(mismatch(path, &name)),
+            };
+
+            self.session.register_implicits(&name, &ty);
+            self.axioms.insert(name.clone(), Axiom::new(ty));
+
+            if let Some(convention) = convention {
+                self.externs.insert(name, convention);
+            }
+        }
+
+        for mut def in image.definitions {
+            if self.definitions.contains_key(&def.name) {
+                continue;
+            }
+
+            let decl = Declaration::Definition(def.ty.clone(), def.body.clone());
+            match try!(self.checked_declaration(&def.name, decl)) {
+                Declaration::Definition(ty, body) => {
+                    def.ty = ty;
+                    def.body = body;
+                }
+                _ => return Err(mismatch(path, &def.name)),
+            }
+
+            self.session.register_implicits(&def.name, &def.ty);
+            self.definitions.insert(def.name.clone(), def);
+        }
+
+        for hint in image.unify_hints {
+            let present = self.unify_hints.iter().any(|h| {
+                h.projection == hint.projection && h.head == hint.head &&
+                h.instance == hint.instance
+            });
+
+            if !present {
+                self.unify_hints.push(hint);
+            }
+        }
+
+        for instance in image.instances {
+            if !self.instances.contains(&instance) {
+                self.instances.push(instance);
+            }
+        }
+
+        self.deprecated.extend(image.deprecated.into_iter());
+        self.docs.extend(image.docs.into_iter());
+        self.interfaces.extend(image.interfaces.into_iter());
+        self.elab_as.extend(image.elab_as.into_iter());
+        self.error_messages.extend(image.error_messages.into_iter());
+        self.rust_exports.extend(image.rust_exports.into_iter());
+
+        Ok(())
+    }
+
+    /// The declaration of `name` the kernel accepted, checking `decl`, the
+    /// image's declaration of it, if the kernel has none yet.
+    fn checked_declaration(&mut self, name: &Name, decl: Declaration)
+                           -> Result<Declaration, Error> {
+        match self.kernel.lookup(name).cloned() {
+            Some(checked) => Ok(checked),
+            None => {
+                try!(self.kernel.add_declaration(name.clone(), decl.clone()));
+                Ok(decl)
+            }
+        }
+    }
+}
+
+fn mismatch(path: &Path, name: &Name) -> Error {
+    Error::Image(path.to_owned(),
+                 format!("`{}` is not the kind of declaration the kernel accepted", name))
+}
+
+/// The files the image at `path` depends on, with the hash of their contents
+/// when it was written.
+pub fn dependencies(path: &Path) -> Result<Vec<(PathBuf, u64)>, String> {
+    let bytes = try!(Bytes::open(path).map_err(|err| err.to_string()));
+    let counter = RefCell::new(0);
+    let mut decoder = Decoder {
+        bytes: bytes.as_slice(),
+        position: 0,
+        counter: &counter,
+        locals: HashMap::new(),
+    };
+
+    try!(decoder.header());
+    decoder.dependencies()
+}
+
+/// The bytes of an image file, memory-mapped where the platform allows it.
+#[cfg(not(target_arch = "wasm32"))]
+struct Bytes(memmap::Mmap);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Bytes {
+    fn open(path: &Path) -> io::Result<Bytes> {
+        memmap::Mmap::open_path(path, memmap::Protection::Read).map(Bytes)
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        // The image is only read while it is loaded, a compiler writing it
+        // at the same time is the user's mistake.
+        unsafe { self.0.as_slice() }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+struct Bytes(Vec<u8>);
+
+#[cfg(target_arch = "wasm32")]
+impl Bytes {
+    fn open(path: &Path) -> io::Result<Bytes> {
+        use std::fs::File;
+        use std::io::Read;
+
+        let mut bytes = vec![];
+        try!(try!(File::open(path)).read_to_end(&mut bytes));
+        Ok(Bytes(bytes))
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn uint(&mut self, mut n: u64) {
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+
+            if n == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    fn string(&mut self, s: &str) {
+        self.uint(s.len() as u64);
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    fn optional_string(&mut self, s: Option<&String>) {
+        match s {
+            None => self.uint(0),
+            Some(s) => {
+                self.uint(1);
+                self.string(s);
+            }
+        }
+    }
+
+    fn strings<'a, I>(&mut self, table: I)
+        where I: IntoIterator<Item = (&'a Name, &'a String)>,
+              I::IntoIter: ExactSizeIterator
+    {
+        let table = table.into_iter();
+        self.uint(table.len() as u64);
+
+        for (name, s) in table {
+            self.name(name);
+            self.string(s);
+        }
+    }
+
+    fn names(&mut self, names: &[Name]) {
+        self.uint(names.len() as u64);
+        for name in names {
+            self.name(name);
+        }
+    }
+
+    fn name(&mut self, name: &Name) {
+        match name {
+            &Name::DeBruijn { index, ref repr, .. } => {
+                self.uint(0);
+                self.uint(index as u64);
+                self.string(repr);
+            }
+            &Name::Local { number, ref repr, ref ty, binding_info, relevance } => {
+                self.uint(1);
+                self.uint(number as u64);
+                self.string(repr);
+                self.term(ty);
+                self.mode(binding_info);
+                self.relevance(relevance);
+            }
+            &Name::Qual { ref components, .. } => {
+                self.uint(2);
+                self.uint(components.len() as u64);
+                for component in components {
+                    self.string(component);
+                }
+            }
+            &Name::Meta { number, ref ty } => {
+                self.uint(3);
+                self.uint(number as u64);
+                self.term(ty);
+            }
+        }
+    }
+
+    fn mode(&mut self, mode: BindingMode) {
+        self.uint(match mode {
+            BindingMode::Explicit => 0,
+            BindingMode::Implicit => 1,
+            BindingMode::Instance => 2,
+        });
+    }
+
+    fn relevance(&mut self, relevance: Relevance) {
+        self.uint(match relevance {
+            Relevance::Relevant => 0,
+            Relevance::Irrelevant => 1,
+        });
+    }
+
+    fn binder(&mut self, binder: &Binder) {
+        self.name(&binder.name);
+        self.term(&binder.ty);
+        self.mode(binder.mode);
+        self.relevance(binder.relevance);
+    }
+
+    fn term(&mut self, term: &Term) {
+        match term {
+            &Term::Var { ref name } => {
+                self.uint(0);
+                self.name(name);
+            }
+            &Term::App { ref fun, ref arg, .. } => {
+                self.uint(1);
+                self.term(fun);
+                self.term(arg);
+            }
+            &Term::Forall { ref binder, ref term, .. } => {
+                self.uint(2);
+                self.binder(binder);
+                self.term(term);
+            }
+            &Term::Lambda { ref binder, ref body, .. } => {
+                self.uint(3);
+                self.binder(binder);
+                self.term(body);
+            }
+            &Term::Let { ref binder, ref value, ref body, .. } => {
+                self.uint(4);
+                self.binder(binder);
+                self.term(value);
+                self.term(body);
+            }
+            &Term::Fix { ref binder, rec_arg, ref body, .. } => {
+                self.uint(5);
+                self.binder(binder);
+                self.uint(rec_arg as u64);
+                self.term(body);
+            }
+            &Term::Type => self.uint(6),
+        }
+    }
+
+    fn data(&mut self, data: &Data) {
+        self.name(&data.name);
+        self.names(&data.parameters);
+        self.term(&data.ty);
+
+        self.uint(data.ctors.len() as u64);
+        for &(ref name, ref ty) in &data.ctors {
+            self.name(name);
+            self.term(ty);
+        }
+
+        match data.recursive {
+            None => self.uint(0),
+            Some(ref rec) => {
+                self.uint(1);
+                self.name(&rec.name);
+                self.term(&rec.ty);
+
+                self.uint(rec.equations.len() as u64);
+                for &(ref ctor, ref rhs) in &rec.equations {
+                    self.name(ctor);
+                    self.term(rhs);
+                }
+            }
+        }
+    }
+
+    fn definition(&mut self, def: &Definition) {
+        self.name(&def.name);
+        self.names(&def.args);
+        self.term(&def.ty);
+        self.term(&def.body);
+        self.uint(match def.reduction {
+            DeltaReduction::Reducible => 0,
+            DeltaReduction::Semireducible => 1,
+            DeltaReduction::Irreducible => 2,
+        });
+    }
+
+    fn convention(&mut self, convention: &Convention) {
+        self.string(&convention.symbol);
+        self.optional_string(convention.crate_name.as_ref());
+        self.uint(convention.arity as u64);
+        self.uint(convention.erased.len() as u64);
+        for &position in &convention.erased {
+            self.uint(position as u64);
+        }
+    }
+
+    fn declaration(&mut self, decl: &Declaration) {
+        match decl {
+            &Declaration::Axiom(ref ty) => {
+                self.uint(0);
+                self.term(ty);
+            }
+            &Declaration::Definition(ref ty, ref body) => {
+                self.uint(1);
+                self.term(ty);
+                self.term(body);
+            }
+            &Declaration::Inductive(ref data) => {
+                self.uint(2);
+                self.data(data);
+            }
+            &Declaration::Recursor(ref ty, ref data) => {
+                self.uint(3);
+                self.term(ty);
+                self.name(data);
+            }
+            &Declaration::RecursiveFn(ref ty, ref data) => {
+                self.uint(4);
+                self.term(ty);
+                self.name(data);
+            }
+        }
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// The counter the renumbered locals are taken from.
+    counter: &'a RefCell<usize>,
+    /// The number each local of the image was given.
+    locals: HashMap<usize, usize>,
+}
+
+impl<'a> Decoder<'a> {
+    fn image(&mut self) -> Result<Image, String> {
+        try!(self.header());
+
+        let dependencies = try!(self.dependencies());
+
+        let declarations = try!(self.seq(|d| {
+            let name = try!(d.name());
+            let decl = try!(d.declaration());
+            Ok((name, decl))
+        }));
+
+        let types = try!(self.seq(|d| d.data()));
+
+        let axioms = try!(self.seq(|d| {
+            let name = try!(d.name());
+            let ty = try!(d.term());
+            let convention = match try!(d.uint()) {
+                0 => None,
+                _ => Some(try!(d.convention())),
+            };
+            Ok((name, ty, convention))
+        }));
+
+        let definitions = try!(self.seq(|d| d.definition()));
+
+        let deprecated = try!(self.seq(|d| {
+            let name = try!(d.name());
+            let message = try!(d.optional_string());
+            let replacement = match try!(d.uint()) {
+                0 => None,
+                _ => Some(try!(d.name())),
+            };
+
+            Ok((name, Deprecation {
+                message: message,
+                replacement: replacement,
+            }))
+        }));
+
+        let docs = try!(self.strings());
+
+        let interfaces = try!(self.seq(|d| {
+            let name = try!(d.name());
+            let interface = try!(d.seq(|d| d.name()));
+            Ok((name, interface.into_iter().collect()))
+        }));
+
+        let unify_hints = try!(self.seq(|d| {
+            Ok(UnifyHint {
+                projection: try!(d.name()),
+                head: try!(d.name()),
+                instance: try!(d.name()),
+            })
+        }));
+
+        let instances = try!(self.seq(|d| d.name()));
+        let elab_as = try!(self.strings());
+        let error_messages = try!(self.strings());
+        let rust_exports = try!(self.strings());
+
+        if self.position != self.bytes.len() {
+            return Err(format!("{} bytes follow the end of the image",
+                               self.bytes.len() - self.position));
+        }
+
+        Ok(Image {
+            dependencies: dependencies,
+            declarations: declarations,
+            types: types,
+            axioms: axioms,
+            definitions: definitions,
+            deprecated: deprecated,
+            docs: docs,
+            interfaces: interfaces,
+            unify_hints: unify_hints,
+            instances: instances,
+            elab_as: elab_as,
+            error_messages: error_messages,
+            rust_exports: rust_exports,
+        })
+    }
+
+    /// Check the magic bytes and the version of the format.
+    fn header(&mut self) -> Result<(), String> {
+        if !self.bytes.starts_with(IMAGE_MAGIC) {
+            return Err("the file is not an image".to_string());
+        }
+
+        self.position = IMAGE_MAGIC.len();
+
+        let version = try!(self.uint());
+        if version != IMAGE_VERSION {
+            return Err(format!("the image was written in version {} of the format, this \
+                                compiler reads version {}",
+                               version,
+                               IMAGE_VERSION));
+        }
+
+        Ok(())
+    }
+
+    fn dependencies(&mut self) -> Result<Vec<(PathBuf, u64)>, String> {
+        self.seq(|d| {
+            let path = PathBuf::from(try!(d.string()));
+            let hash = try!(d.uint());
+            Ok((path, hash))
+        })
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        match self.bytes.get(self.position) {
+            None => Err("the image ends unexpectedly".to_string()),
+            Some(&byte) => {
+                self.position += 1;
+                Ok(byte)
+            }
+        }
+    }
+
+    fn uint(&mut self) -> Result<u64, String> {
+        let mut n = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = try!(self.byte());
+
+            if shift >= 64 {
+                return Err(format!("an integer at byte {} is too large", self.position));
+            }
+
+            n |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+    }
+
+    fn usize(&mut self) -> Result<usize, String> {
+        self.uint().map(|n| n as usize)
+    }
+
+    /// A tag no larger than `max`.
+    fn tag(&mut self, max: u64, what: &str) -> Result<u64, String> {
+        let position = self.position;
+        let tag = try!(self.uint());
+
+        if tag > max {
+            Err(format!("unknown {} {} at byte {}", what, tag, position))
+        } else {
+            Ok(tag)
+        }
+    }
+
+    fn seq<T, F>(&mut self, mut element: F) -> Result<Vec<T>, String>
+        where F: FnMut(&mut Decoder<'a>) -> Result<T, String>
+    {
+        let len = try!(self.usize());
+
+        // The length is not trusted for the allocation, a corrupt image
+        // should fail to decode rather than exhaust memory.
+        let mut elements = Vec::with_capacity(cmp::min(len, self.bytes.len()));
+        for _ in 0..len {
+            elements.push(try!(element(self)));
+        }
+
+        Ok(elements)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = try!(self.usize());
+        let start = self.position;
+
+        if len > self.bytes.len() - start {
+            return Err("the image ends unexpectedly".to_string());
+        }
+
+        self.position += len;
+        String::from_utf8(self.bytes[start..self.position].to_vec())
+            .map_err(|_| format!("the string at byte {} is not UTF-8", start))
+    }
+
+    fn optional_string(&mut self) -> Result<Option<String>, String> {
+        match try!(self.tag(1, "option")) {
+            0 => Ok(None),
+            _ => self.string().map(Some),
+        }
+    }
+
+    fn strings(&mut self) -> Result<Vec<(Name, String)>, String> {
+        self.seq(|d| {
+            let name = try!(d.name());
+            let s = try!(d.string());
+            Ok((name, s))
+        })
+    }
+
+    fn name(&mut self) -> Result<Name, String> {
+        Ok(match try!(self.tag(3, "name")) {
+            0 => Name::DeBruijn {
+                index: try!(self.usize()),
+                span: Span::dummy(),
+                repr: try!(self.string()),
+            },
+            1 => {
+                let number = try!(self.usize());
+                let number = self.local(number);
+
+                Name::Local {
+                    number: number,
+                    repr: try!(self.string()),
+                    ty: Box::new(try!(self.term())),
+                    binding_info: try!(self.mode()),
+                    relevance: try!(self.relevance()),
+                }
+            }
+            2 => Name::Qual {
+                span: Span::dummy(),
+                components: try!(self.seq(|d| d.string())),
+            },
+            _ => Name::Meta {
+                number: try!(self.usize()),
+                ty: Box::new(try!(self.term())),
+            },
+        })
+    }
+
+    /// The number of the local numbered `number` in the image.
+    fn local(&mut self, number: usize) -> usize {
+        let counter = self.counter;
+
+        *self.locals.entry(number).or_insert_with(|| {
+            let fresh = *counter.borrow();
+            *counter.borrow_mut() += 1;
+            fresh
+        })
+    }
+
+    fn mode(&mut self) -> Result<BindingMode, String> {
+        Ok(match try!(self.tag(2, "binding mode")) {
+            0 => BindingMode::Explicit,
+            1 => BindingMode::Implicit,
+            _ => BindingMode::Instance,
+        })
+    }
+
+    fn relevance(&mut self) -> Result<Relevance, String> {
+        Ok(match try!(self.tag(1, "relevance")) {
+            0 => Relevance::Relevant,
+            _ => Relevance::Irrelevant,
+        })
+    }
+
+    fn binder(&mut self) -> Result<Binder, String> {
+        Ok(Binder {
+            name: try!(self.name()),
+            ty: Box::new(try!(self.term())),
+            mode: try!(self.mode()),
+            relevance: try!(self.relevance()),
+        })
+    }
+
+    fn term(&mut self) -> Result<Term, String> {
+        let span = Span::dummy();
+
+        Ok(match try!(self.tag(6, "term")) {
+            0 => Term::Var { name: try!(self.name()) },
+            1 => Term::App {
+                span: span,
+                fun: Box::new(try!(self.term())),
+                arg: Box::new(try!(self.term())),
+            },
+            2 => Term::Forall {
+                span: span,
+                binder: try!(self.binder()),
+                term: Box::new(try!(self.term())),
+            },
+            3 => Term::Lambda {
+                span: span,
+                binder: try!(self.binder()),
+                body: Box::new(try!(self.term())),
+            },
+            4 => Term::Let {
+                span: span,
+                binder: try!(self.binder()),
+                value: Box::new(try!(self.term())),
+                body: Box::new(try!(self.term())),
+            },
+            5 => Term::Fix {
+                span: span,
+                binder: try!(self.binder()),
+                rec_arg: try!(self.usize()),
+                body: Box::new(try!(self.term())),
+            },
+            _ => Term::Type,
+        })
+    }
+
+    fn data(&mut self) -> Result<Data, String> {
+        let name = try!(self.name());
+        let parameters = try!(self.seq(|d| d.name()));
+        let ty = try!(self.term());
+        let ctors = try!(self.seq(|d| {
+            let name = try!(d.name());
+            let ty = try!(d.term());
+            Ok((name, ty))
+        }));
+
+        let recursive = match try!(self.tag(1, "option")) {
+            0 => None,
+            _ => Some(RecursiveFn {
+                name: try!(self.name()),
+                ty: try!(self.term()),
+                equations: try!(self.seq(|d| {
+                    let ctor = try!(d.name());
+                    let rhs = try!(d.term());
+                    Ok((ctor, rhs))
+                })),
+            }),
+        };
+
+        Ok(Data {
+            span: Span::dummy(),
+            name: name,
+            parameters: parameters,
+            ty: ty,
+            ctors: ctors,
+            recursive: recursive,
+        })
+    }
+
+    fn definition(&mut self) -> Result<Definition, String> {
+        Ok(Definition {
+            name: try!(self.name()),
+            args: try!(self.seq(|d| d.name())),
+            ty: try!(self.term()),
+            body: try!(self.term()),
+            reduction: match try!(self.tag(2, "reduction")) {
+                0 => DeltaReduction::Reducible,
+                1 => DeltaReduction::Semireducible,
+                _ => DeltaReduction::Irreducible,
+            },
+        })
+    }
+
+    fn convention(&mut self) -> Result<Convention, String> {
+        Ok(Convention {
+            symbol: try!(self.string()),
+            crate_name: try!(self.optional_string()),
+            arity: try!(self.usize()),
+            erased: try!(self.seq(|d| d.usize())),
+        })
+    }
+
+    fn declaration(&mut self) -> Result<Declaration, String> {
+        Ok(match try!(self.tag(4, "declaration")) {
+            0 => Declaration::Axiom(try!(self.term())),
+            1 => {
+                let ty = try!(self.term());
+                let body = try!(self.term());
+                Declaration::Definition(ty, body)
+            }
+            2 => Declaration::Inductive(try!(self.data())),
+            3 => {
+                let ty = try!(self.term());
+                Declaration::Recursor(ty, try!(self.name()))
+            }
+            _ => {
+                let ty = try!(self.term());
+                Declaration::RecursiveFn(ty, try!(self.name()))
+            }
+        })
+    }
+}
@@ -293,10 +293,12 @@ impl<'i, 'tcx> InductiveCx<'i, 'tcx> {
             // BUG WARNING: this code IS NOT general enough
             debug!("term {}", term);
             let (head, args) = term.uncurry();
+            // The recursor of `T` is `T.rec`, where `T` may itself be a
+            // qualified name such as `Syntax.Expr`.
             let ty_name = match &head {
                 &Term::Var { ref name } => match name {
                     &Name::Qual { ref components, span } => Name::Qual {
-                        components: vec![components[0].clone()],
+                        components: components[..components.len() - 1].to_vec(),
                         span: span,
                     },
                     _ => panic!()
@@ -305,99 +307,95 @@ impl<'i, 'tcx> InductiveCx<'i, 'tcx> {
             };
 
             debug!("ty_name: {}", ty_name);
-            let scrutinee = &args[args.len() - 1];
-            let scrutinee = try!(cx.eval(scrutinee));
-            debug!("scrutinee: {}", scrutinee);
-            let (scrut_ctor, scrut_args) = scrutinee.uncurry();
 
-            match cx.types.get(&ty_name) {
+            let dt = match cx.types.get(&ty_name) {
                 None => panic!("type checking bug: can not find inductive type {}", ty_name),
-                Some(dt) => {
-                    let params = &dt.parameters;
+                Some(dt) => dt,
+            };
 
-                    let motif_and_premise : Vec<_> =
-                        args.iter()
-                            .skip(params.len())
-                            .cloned()
-                            .collect();
+            let num_params = dt.parameters.len();
+            let num_indices = dt.ty.binders().map_or(0, |bs| bs.len()) - num_params;
+            let num_minors = dt.ctors.len();
+            let major_idx = num_params + 1 + num_minors + num_indices;
 
-                    let motif = motif_and_premise[0].clone();
+            // The recursor only computes once it is applied to a constructor.
+            if args.len() <= major_idx {
+                return Ok(term);
+            }
 
-                    let premises : Vec<_> =
-                        motif_and_premise.iter()
-                                         .skip(1)
-                                         .cloned()
-                                         .collect();
+            let scrutinee = try!(cx.eval(&args[major_idx]));
+            debug!("scrutinee: {}", scrutinee);
+            let (scrut_ctor, scrut_args) = scrutinee.uncurry();
 
-                    for (i, ctor) in dt.ctors.iter().enumerate() {
-                        let name = &ctor.0;
-                        let ctor_ty = &ctor.1;
-
-                        if scrut_ctor == name.to_term() {
-                            let premise = premises[i].clone();
-
-                            let is_recursive =
-                                cx.is_recursive_ctor(&ty_name, ctor_ty);
-
-                            if !is_recursive {
-                                // Remember to remove the parameters, since
-                                // the premise is not parametrized by them.
-                                let args : Vec<_> =
-                                    scrut_args.iter()
-                                              .skip(dt.parameters.len())
-                                              .cloned()
-                                              .collect();
-
-                                return cx.eval(&Term::apply_all(premise, args));
-                            } else {
-                                panic!()
-                                // let args: Vec<_> =
-                                //     scrutinee.args()
-                                //              .unwrap();
-                            //
-                            // // Need to skip the parameters
-                            // let args =
-                            //     args.iter()
-                            //         .skip(dt.parameters.len());
-                            //
-                            // let tys =
-                            //     premise.binders()
-                            //            .unwrap();
-                            //
-                            // debug!("premise: {}", premise);
-                            // debug!("scurtinee: {}", scrutinee);
-                            //
-                            // let mut term_args = vec![];
-                            // let mut recursor_args = vec![];
-                            //
-                            // for (arg, ty) in args.zip(tys.into_iter()) {
-                            //     debug!("arg : {}", arg);
-                            //     debug!("ty : {}", ty);
-                            //     if ty.head().unwrap() == ty_name.to_term() {
-                            //         let rec =
-                            //         Recursor(
-                            //             ty_name.clone(),
-                            //             premises.clone(),
-                            //             Box::new(arg.clone()));
-                            //             recursor_args.push(rec);
-                            //     }
-                            //
-                            //     term_args.push(arg.clone());
-                            // }
-                            //
-                            // let mut args = term_args;
-                            // args.extend(recursor_args.into_iter());
-                            //
-                            // return self.eval(&Term::apply_all(premise.clone(), args));
-                            }
+            let ctor_no = match dt.ctors.iter().position(|c| c.0.to_term() == scrut_ctor) {
+                None => return Ok(term),
+                Some(i) => i,
+            };
+
+            let premise = args[num_params + 1 + ctor_no].clone();
+            let rec_prefix = &args[..num_params + 1 + num_minors];
+
+            // Remember to remove the parameters, since the premise is not
+            // parametrized by them.
+            let fields: Vec<_> =
+                scrut_args.iter()
+                          .skip(num_params)
+                          .cloned()
+                          .collect();
+
+            // Each recursive field is followed by its induction hypothesis,
+            // the recursor applied to the field.
+            let mut ctor_ty = dt.ctors[ctor_no].1.clone();
+            for param in scrut_args.iter().take(num_params) {
+                ctor_ty = match ctor_ty {
+                    Term::Forall { term, .. } => term.instantiate(param),
+                    t => panic!("type checking bug: expected a parameter in {}", t),
+                };
+            }
+
+            let mut hyps = vec![];
+            for field in &fields {
+                ctor_ty = match ctor_ty {
+                    Term::Forall { binder, term, .. } => {
+                        if binder.ty.head() == Some(ty_name.to_term()) {
+                            let mut rec_args = rec_prefix.to_vec();
+                            rec_args.extend(
+                                binder.ty.args()
+                                         .unwrap_or(vec![])
+                                         .into_iter()
+                                         .skip(num_params));
+                            rec_args.push(field.clone());
+                            hyps.push(Term::apply_all(head.clone(), rec_args));
                         }
+
+                        term.instantiate(field)
                     }
-                }
+                    t => panic!("type checking bug: expected a field in {}", t),
+                };
             }
-            panic!("this shouldn't happen")
+
+            let mut premise_args = fields;
+            premise_args.extend(hyps.into_iter());
+            premise_args.extend(args[major_idx + 1..].iter().cloned());
+
+            cx.eval(&Term::apply_all(premise, premise_args))
         }))
     }
 
+    /// Add an axiom with the recursor type, and the associated computation rule.
+    fn declare_recursor(&mut self) -> Result<(), Error> {
+        let recursor = try!(self.recursor());
+
+        self.ty_cx
+            .axioms
+            .insert(recursor.name, super::Axiom {
+                ty: recursor.ty,
+                computation_rule: Some(recursor.computation_rule),
+            });
+
+        Ok(())
+    }
+
     fn recursor(&mut self) -> Result<Recursor, Error> {
         let motif = self.ind_hyp.clone();
 
@@ -442,42 +440,121 @@ impl<'i, 'tcx> InductiveCx<'i, 'tcx> {
         })
     }
 
-    pub fn make_below(&mut self) -> Result<(), Error> {
-        let name = self.inductive_ty
-                       .name
-                       .in_scope("below".to_string())
-                       .unwrap();
-
-        let params : Vec<_> = self.inductive_ty
-                         .parameters
-                         .clone();
-
-        let params_as_terms : Vec<_> =
-            params.clone()
-                  .iter()
-                  .map(|p| p.to_term())
-                  .collect();
-
-        let ty =
-            Term::abstract_pi_implicit(
-                params.clone(),
-                Term::apply_all(
-                    self.inductive_ty.name.to_term(),
-                    params_as_terms.clone()));
+    fn local(&self, repr: &str, ty: Term) -> Name {
+        self.ty_cx.local_with_repr(repr.to_string(), ty)
+    }
 
-        let rec =
-            self.inductive_ty
-                .name
-                .in_scope("rec".to_string())
-                .unwrap();
+    fn local_ty(local: &Name) -> Term {
+        match local {
+            &Name::Local { ref ty, .. } => (**ty).clone(),
+            n => panic!("expected a local, found {}", n),
+        }
+    }
 
-        let body =
-            Term::abstract_lambda(
-                params.clone(),
-                Term::apply_all(
-                    rec.to_term(),
-                    params_as_terms));
+    fn params_as_terms(&self) -> Vec<Term> {
+        self.inductive_ty
+            .parameters
+            .iter()
+            .map(|p| p.to_term())
+            .collect()
+    }
+
+    /// Whether the type has indices, the auxiliary constructions are only
+    /// generated for types without them.
+    fn has_indices(&self) -> bool {
+        let num_binders = self.inductive_ty.ty.binders().map_or(0, |bs| bs.len());
+        num_binders > self.inductive_ty.parameters.len()
+    }
+
+    /// Open the fields of `ctor` applied to the parameters, returning a local
+    /// for each field along with whether it is a recursive argument.
+    fn ctor_fields(&self, ctor: &Constructor, prefix: &str) -> Vec<(Name, bool)> {
+        let mut ty = self.with_params(ctor.1.clone());
+        let mut fields = vec![];
+
+        while let Term::Forall { binder, term, .. } = ty {
+            let is_recursive = self.is_recursive_arg(&binder.ty);
+            let local = self.local(&format!("{}{}", prefix, fields.len()), *binder.ty);
+            ty = term.instantiate(&local.to_term());
+            fields.push((local, is_recursive));
+        }
+
+        fields
+    }
+
+    /// Whether the type of each field of `ctor` is independent of the fields
+    /// before it.
+    fn has_independent_fields(&self, ctor: &Constructor) -> bool {
+        let fields = self.ctor_fields(ctor, "a");
+
+        fields.iter().enumerate().all(|(i, &(ref field, _))| {
+            fields[i + 1..].iter().all(|&(ref later, _)| {
+                let ty = InductiveCx::local_ty(later);
+                ty.abstr(field) == ty
+            })
+        })
+    }
+
+    /// Whether `ctor` has a field that is a function returning `T`, the
+    /// kernel's recursor gives these fields an induction hypothesis but ours
+    /// does not, so no constructions built from the recursor are generated.
+    fn has_functional_recursion(&self, ctor: &Constructor) -> bool {
+        self.ctor_fields(ctor, "a").iter().any(|&(ref field, _)| {
+            let mut ty = InductiveCx::local_ty(field);
+            let is_function = ty.is_forall();
+
+            while let Term::Forall { term, .. } = ty {
+                ty = *term;
+            }
+
+            is_function && self.is_recursive_arg(&ty)
+        })
+    }
+
+    /// An application of the recursor with the given motive and minor premises.
+    fn rec_app(&self, motive: Term, minors: Vec<Term>, major: Term) -> Term {
+        let rec = self.inductive_ty.name.in_scope("rec".to_string()).unwrap();
+        let mut args = self.params_as_terms();
+        args.push(motive);
+        args.extend(minors.into_iter());
+        args.push(major);
+        Term::apply_all(rec.to_term(), args)
+    }
+
+    /// A minor premise for `ctor`, `body` is given the constructor applied to
+    /// its fields, and the fields paired with the induction hypothesis of
+    /// each recursive field, whose type is computed by `hyp_ty`.
+    fn minor_premise<H, B>(&self, ctor: &Constructor, hyp_ty: H, body: B) -> Term
+        where H: Fn(&Term) -> Term,
+              B: FnOnce(Term, &[(Name, Option<Name>)]) -> Term
+    {
+        let fields: Vec<_> =
+            self.ctor_fields(ctor, "a")
+                .into_iter()
+                .map(|(field, is_recursive)| {
+                    let hyp = if is_recursive {
+                        Some(self.local("ih", hyp_ty(&field.to_term())))
+                    } else {
+                        None
+                    };
+
+                    (field, hyp)
+                })
+                .collect();
+
+        let ctor_app = Term::apply_all(
+            self.with_params(ctor.0.to_term()),
+            fields.iter().map(|f| f.0.to_term()).collect());
+
+        let body = body(ctor_app, &fields[..]);
 
+        let mut binders: Vec<_> = fields.iter().map(|f| f.0.clone()).collect();
+        binders.extend(fields.iter().filter_map(|f| f.1.clone()));
+
+        Term::abstract_lambda(binders, body)
+    }
+
+    fn declare_aux(&mut self, name: Name, ty: Term, body: Term) -> Result<(), Error> {
         let def = Function {
             name: name,
             args: vec![],
@@ -488,7 +565,343 @@ impl<'i, 'tcx> InductiveCx<'i, 'tcx> {
 
         debug!("{}", def);
 
-        try!(self.ty_cx.declare_def(&def));
+        self.ty_cx.declare_def(&def)
+    }
+
+    // There is no built in product, unit, or equality type so the auxiliary
+    // constructions use their Church encodings.
+
+    /// `forall (R : Type), (A -> B -> R) -> R`
+    fn pair_ty(&self, a: Term, b: Term) -> Term {
+        let r = self.local("R", Term::Type);
+        let f = self.local("f", Term::abstract_pi(
+            vec![self.local("", a), self.local("", b)],
+            r.to_term()));
+
+        Term::abstract_pi(vec![r.clone(), f], r.to_term())
+    }
+
+    fn pair(&self, a: Term, b: Term, x: Term, y: Term) -> Term {
+        let r = self.local("R", Term::Type);
+        let f = self.local("f", Term::abstract_pi(
+            vec![self.local("", a), self.local("", b)],
+            r.to_term()));
+
+        Term::abstract_lambda(
+            vec![r, f.clone()],
+            Term::apply_all(f.to_term(), vec![x, y]))
+    }
+
+    fn fst(&self, a: Term, b: Term, p: Term) -> Term {
+        let x = self.local("x", a.clone());
+        let y = self.local("y", b);
+        Term::apply_all(p, vec![a, Term::abstract_lambda(vec![x.clone(), y], x.to_term())])
+    }
+
+    /// `forall (R : Type), R -> R`
+    fn unit_ty(&self) -> Term {
+        let r = self.local("R", Term::Type);
+        Term::abstract_pi(vec![r.clone(), self.local("", r.to_term())], r.to_term())
+    }
+
+    fn unit(&self) -> Term {
+        let r = self.local("R", Term::Type);
+        let x = self.local("x", r.to_term());
+        Term::abstract_lambda(vec![r, x.clone()], x.to_term())
+    }
+
+    /// Leibniz equality `forall (Q : A -> Type), Q a -> Q b`.
+    fn eq_ty(&self, ty: Term, a: Term, b: Term) -> Term {
+        let q = self.local("Q", Term::abstract_pi(vec![self.local("", ty)], Term::Type));
+        let qa = self.local("", Term::apply(q.to_term(), a));
+        Term::abstract_pi(vec![q.clone(), qa], Term::apply(q.to_term(), b))
+    }
+
+    fn eq_refl(&self, ty: Term, a: Term) -> Term {
+        let q = self.local("Q", Term::abstract_pi(vec![self.local("", ty)], Term::Type));
+        let qa = self.local("q", Term::apply(q.to_term(), a));
+        Term::abstract_lambda(vec![q, qa.clone()], qa.to_term())
+    }
+
+    /// `T.below : forall {P} (C : T P -> Type), T P -> Type` pairs `C x` with
+    /// `T.below C x` for every recursive field `x` of a value.
+    pub fn make_below(&mut self) -> Result<(), Error> {
+        let name = self.inductive_ty.name.in_scope("below".to_string()).unwrap();
+        let params = self.inductive_ty.parameters.clone();
+        let ty_with_params = self.with_params(self.inductive_ty.name.to_term());
+
+        let c = self.local("C", Term::abstract_pi(
+            vec![self.local("", ty_with_params.clone())],
+            Term::Type));
+        let v = self.local("v", ty_with_params.clone());
+        let motive = Term::abstract_lambda(vec![self.local("x", ty_with_params)], Term::Type);
+
+        let minors: Vec<_> =
+            self.inductive_ty.ctors.iter().map(|ctor| {
+                self.minor_premise(ctor, |_| Term::Type, |_, fields| {
+                    fields.iter().rev().fold(self.unit_ty(), |rest, field| match field {
+                        &(ref x, Some(ref ih)) => {
+                            let component = self.pair_ty(
+                                Term::apply(c.to_term(), x.to_term()),
+                                ih.to_term());
+                            self.pair_ty(component, rest)
+                        }
+                        _ => rest,
+                    })
+                })
+            }).collect();
+
+        let mut binders = params.clone();
+        binders.push(c.clone());
+        binders.push(v.clone());
+
+        let ty = Term::abstract_pi_implicit(
+            params,
+            Term::abstract_pi(vec![c, v.clone()], Term::Type));
+        let body = Term::abstract_lambda(binders, self.rec_app(motive, minors, v.to_term()));
+
+        self.declare_aux(name, ty, body)
+    }
+
+    /// `T.brec_on : forall {P} (C : T P -> Type) (v : T P),
+    ///     (forall (x : T P), T.below C x -> C x) -> C v`
+    /// allows structural recursion on any subterm of `v`.
+    pub fn make_brec_on(&mut self) -> Result<(), Error> {
+        let name = self.inductive_ty.name.in_scope("brec_on".to_string()).unwrap();
+        let below = self.inductive_ty.name.in_scope("below".to_string()).unwrap();
+        let params = self.inductive_ty.parameters.clone();
+        let ty_with_params = self.with_params(self.inductive_ty.name.to_term());
+
+        let c = self.local("C", Term::abstract_pi(
+            vec![self.local("", ty_with_params.clone())],
+            Term::Type));
+        let v = self.local("v", ty_with_params.clone());
+
+        let mut below_args = self.params_as_terms();
+        below_args.push(c.to_term());
+        let below_c = Term::apply_all(below.to_term(), below_args);
+
+        let c_of = |x: &Term| Term::apply(c.to_term(), x.clone());
+        let below_of = |x: &Term| Term::apply(below_c.clone(), x.clone());
+
+        let x = self.local("x", ty_with_params.clone());
+        let f = self.local("F", Term::abstract_pi(
+            vec![x.clone(), self.local("", below_of(&x.to_term()))],
+            c_of(&x.to_term())));
+
+        // Compute both `C x` and `T.below C x` by recursion, the minor premise
+        // for each constructor builds the `below` value from the induction
+        // hypotheses and passes it to `F`.
+        let motive = Term::abstract_lambda(
+            vec![x.clone()],
+            self.pair_ty(c_of(&x.to_term()), below_of(&x.to_term())));
+
+        let minors: Vec<_> =
+            self.inductive_ty.ctors.iter().map(|ctor| {
+                let hyp_ty = |y: &Term| self.pair_ty(c_of(y), below_of(y));
+
+                self.minor_premise(ctor, &hyp_ty, |ctor_app, fields| {
+                    let init = (self.unit(), self.unit_ty());
+                    let (b, _) = fields.iter().rev().fold(init, |(rest, rest_ty), field| {
+                        match field {
+                            &(ref y, Some(ref ih)) => {
+                                let component_ty = hyp_ty(&y.to_term());
+                                let b_ty = self.pair_ty(component_ty.clone(), rest_ty.clone());
+                                (self.pair(component_ty, rest_ty, ih.to_term(), rest), b_ty)
+                            }
+                            _ => (rest, rest_ty),
+                        }
+                    });
+
+                    let result = Term::apply_all(f.to_term(), vec![ctor_app.clone(), b.clone()]);
+                    self.pair(c_of(&ctor_app), below_of(&ctor_app), result, b)
+                })
+            }).collect();
+
+        let go = self.rec_app(motive, minors, v.to_term());
+        let body = self.fst(c_of(&v.to_term()), below_of(&v.to_term()), go);
+
+        let mut binders = params.clone();
+        binders.extend(vec![c.clone(), v.clone(), f.clone()]);
+
+        let ty = Term::abstract_pi_implicit(
+            params,
+            Term::abstract_pi(vec![c.clone(), v.clone(), f], c_of(&v.to_term())));
+
+        self.declare_aux(name, ty, Term::abstract_lambda(binders, body))
+    }
+
+    /// `T.no_confusion_type : forall {P}, Type -> T P -> T P -> Type`
+    /// computes, for two values of `T`, the type of the consequences of their
+    /// equality. Values built from different constructors are never equal, and
+    /// values built by the same constructor have equal fields.
+    pub fn make_no_confusion_type(&mut self) -> Result<(), Error> {
+        let name = self.inductive_ty.name.in_scope("no_confusion_type".to_string()).unwrap();
+        let params = self.inductive_ty.parameters.clone();
+        let ty_with_params = self.with_params(self.inductive_ty.name.to_term());
+
+        let c = self.local("C", Term::Type);
+        let v1 = self.local("v1", ty_with_params.clone());
+        let v2 = self.local("v2", ty_with_params.clone());
+        let motive = Term::abstract_lambda(vec![self.local("x", ty_with_params)], Term::Type);
+
+        let ctors = self.inductive_ty.ctors.clone();
+
+        let minors: Vec<_> = ctors.iter().enumerate().map(|(i, ctor_i)| {
+            self.minor_premise(ctor_i, |_| Term::Type, |_, fields_a| {
+                let inner: Vec<_> = ctors.iter().enumerate().map(|(j, ctor_j)| {
+                    self.minor_premise(ctor_j, |_| Term::Type, |_, fields_b| {
+                        if i != j {
+                            return c.to_term();
+                        }
+
+                        let eqs: Vec<_> =
+                            fields_a.iter().zip(fields_b.iter()).map(|(a, b)| {
+                                let ty = InductiveCx::local_ty(&a.0);
+                                self.local("", self.eq_ty(ty, a.0.to_term(), b.0.to_term()))
+                            }).collect();
+
+                        let k = self.local("k", Term::abstract_pi(eqs, c.to_term()));
+                        Term::abstract_pi(vec![k], c.to_term())
+                    })
+                }).collect();
+
+                self.rec_app(motive.clone(), inner, v2.to_term())
+            })
+        }).collect();
+
+        let mut binders = params.clone();
+        binders.extend(vec![c.clone(), v1.clone(), v2.clone()]);
+
+        let ty = Term::abstract_pi_implicit(
+            params,
+            Term::abstract_pi(vec![c, v1.clone(), v2], Term::Type));
+        let body = Term::abstract_lambda(binders, self.rec_app(motive.clone(), minors, v1.to_term()));
+
+        self.declare_aux(name, ty, body)
+    }
+
+    /// `T.no_confusion : forall {P} {C : Type} (v1 v2 : T P),
+    ///     v1 = v2 -> T.no_confusion_type C v1 v2`
+    pub fn make_no_confusion(&mut self) -> Result<(), Error> {
+        let name = self.inductive_ty.name.in_scope("no_confusion".to_string()).unwrap();
+        let nct = self.inductive_ty.name.in_scope("no_confusion_type".to_string()).unwrap();
+        let params = self.inductive_ty.parameters.clone();
+        let ty_with_params = self.with_params(self.inductive_ty.name.to_term());
+
+        let c = self.local("C", Term::Type);
+        let v1 = self.local("v1", ty_with_params.clone());
+        let v2 = self.local("v2", ty_with_params.clone());
+        let h = self.local("h", self.eq_ty(ty_with_params.clone(), v1.to_term(), v2.to_term()));
+
+        let mut nct_args = self.params_as_terms();
+        nct_args.push(c.to_term());
+        let nct_c = Term::apply_all(nct.to_term(), nct_args);
+        let nct_of = |x: &Term, y: &Term| Term::apply_all(nct_c.clone(), vec![x.clone(), y.clone()]);
+
+        // Every value is related to itself by `no_confusion_type`, since the
+        // equalities between its fields all hold by reflexivity.
+        let x = self.local("x", ty_with_params.clone());
+        let diag_motive = Term::abstract_lambda(
+            vec![x.clone()],
+            nct_of(&x.to_term(), &x.to_term()));
+
+        let minors: Vec<_> =
+            self.inductive_ty.ctors.iter().map(|ctor| {
+                self.minor_premise(ctor, |y| nct_of(y, y), |_, fields| {
+                    let eqs: Vec<_> = fields.iter().map(|f| {
+                        let ty = InductiveCx::local_ty(&f.0);
+                        self.local("", self.eq_ty(ty, f.0.to_term(), f.0.to_term()))
+                    }).collect();
+
+                    let refls: Vec<_> = fields.iter().map(|f| {
+                        self.eq_refl(InductiveCx::local_ty(&f.0), f.0.to_term())
+                    }).collect();
+
+                    let k = self.local("k", Term::abstract_pi(eqs, c.to_term()));
+                    Term::abstract_lambda(vec![k.clone()], Term::apply_all(k.to_term(), refls))
+                })
+            }).collect();
+
+        let diag = self.rec_app(diag_motive, minors, v1.to_term());
+
+        // Transport the reflexive case along `h`.
+        let y = self.local("y", ty_with_params);
+        let transport_motive = Term::abstract_lambda(
+            vec![y.clone()],
+            nct_of(&v1.to_term(), &y.to_term()));
+        let body = Term::apply_all(h.to_term(), vec![transport_motive, diag]);
+
+        let mut implicits = params.clone();
+        implicits.push(c.clone());
+
+        let mut binders = implicits.clone();
+        binders.extend(vec![v1.clone(), v2.clone(), h.clone()]);
+
+        let ty = Term::abstract_pi_implicit(
+            implicits,
+            Term::abstract_pi(vec![v1.clone(), v2.clone(), h],
+                              nct_of(&v1.to_term(), &v2.to_term())));
+
+        self.declare_aux(name, ty, Term::abstract_lambda(binders, body))
+    }
+
+    /// `c.inj : forall {P} {C : Type} (a b : fields of c), c a = c b ->
+    ///     (a_0 = b_0 -> ... -> a_n = b_n -> C) -> C` for each constructor
+    /// `c` with at least one field.
+    pub fn make_injectivity_lemmas(&mut self) -> Result<(), Error> {
+        let no_confusion = self.inductive_ty.name.in_scope("no_confusion".to_string()).unwrap();
+        let ty_with_params = self.with_params(self.inductive_ty.name.to_term());
+
+        for ctor in self.inductive_ty.ctors.clone() {
+            let fields_a: Vec<_> = self.ctor_fields(&ctor, "a").into_iter().map(|f| f.0).collect();
+            let fields_b: Vec<_> = self.ctor_fields(&ctor, "b").into_iter().map(|f| f.0).collect();
+
+            if fields_a.len() == 0 {
+                continue;
+            }
+
+            let name = ctor.0.in_scope("inj".to_string()).unwrap();
+            let params = self.inductive_ty.parameters.clone();
+
+            let ctor_with_params = self.with_params(ctor.0.to_term());
+            let app_a = Term::apply_all(
+                ctor_with_params.clone(),
+                fields_a.iter().map(|f| f.to_term()).collect());
+            let app_b = Term::apply_all(
+                ctor_with_params,
+                fields_b.iter().map(|f| f.to_term()).collect());
+
+            let c = self.local("C", Term::Type);
+            let h = self.local("h", self.eq_ty(ty_with_params.clone(), app_a.clone(), app_b.clone()));
+
+            let eqs: Vec<_> = fields_a.iter().zip(fields_b.iter()).map(|(a, b)| {
+                let ty = InductiveCx::local_ty(a);
+                self.local("", self.eq_ty(ty, a.to_term(), b.to_term()))
+            }).collect();
+            let k = self.local("k", Term::abstract_pi(eqs, c.to_term()));
+
+            let mut nc_args = self.params_as_terms();
+            nc_args.extend(vec![c.to_term(), app_a, app_b, h.to_term(), k.to_term()]);
+            let body = Term::apply_all(no_confusion.to_term(), nc_args);
+
+            let mut implicits = params;
+            implicits.push(c.clone());
+
+            let mut explicits = fields_a.clone();
+            explicits.extend(fields_b.into_iter());
+            explicits.push(h);
+            explicits.push(k.clone());
+
+            let mut binders = implicits.clone();
+            binders.extend(explicits.clone());
+
+            let ty = Term::abstract_pi_implicit(
+                implicits,
+                Term::abstract_pi(explicits, c.to_term()));
+
+            try!(self.declare_aux(name, ty, Term::abstract_lambda(binders, body)));
+        }
 
         Ok(())
     }
@@ -602,18 +1015,31 @@ impl<'i, 'tcx> InductiveCx<'i, 'tcx> {
 /// Construct a recursor for `data_type`.
 pub fn make_recursor(ty_cx: &mut TyCtxt, data_type: &Data) -> Result<(), Error> {
     let mut rcx = InductiveCx::new(ty_cx, data_type);
-    let recursor = try!(rcx.recursor());
-
-    // Add an axiom with the recursor type, and the associated computation rule.
-    rcx.ty_cx
-       .axioms
-       .insert(recursor.name, super::Axiom {
-           ty: recursor.ty,
-           computation_rule: Some(recursor.computation_rule),
-       });
+    try!(rcx.declare_recursor());
 
     // Now setup all the automatically generated constructs.
     try!(rcx.make_cases_on());
 
+    let ctors = rcx.inductive_ty.ctors.clone();
+
+    if !rcx.has_indices() && !ctors.iter().any(|c| rcx.has_functional_recursion(c)) {
+        try!(rcx.make_below());
+        try!(rcx.make_brec_on());
+
+        // Equalities between fields can only be stated when their types do
+        // not depend on earlier fields.
+        if ctors.iter().all(|c| rcx.has_independent_fields(c)) {
+            try!(rcx.make_no_confusion_type());
+            try!(rcx.make_no_confusion());
+            try!(rcx.make_injectivity_lemmas());
+        }
+    }
+
     Ok(())
 }
+
+/// Declare only the recursor of `data_type`, for a type loaded from an
+/// image, which already holds the definitions `make_recursor` derives.
+pub fn restore_recursor(ty_cx: &mut TyCtxt, data_type: &Data) -> Result<(), Error> {
+    InductiveCx::new(ty_cx, data_type).declare_recursor()
+}
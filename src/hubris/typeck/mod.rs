@@ -1,23 +1,38 @@
 mod constraint;
+mod diff;
 mod error;
+mod image;
 mod inductive;
+mod prelude;
+mod reduce;
+mod relevance;
 mod solver;
+mod validate;
 
 use core::{
     self, Name,
     Term, Binder, Item, Definition, Data,
-    Module, Extern, BindingMode, DeltaReduction};
+    Module, Extern, Convention, BindingMode, Relevance, DeltaReduction};
 use super::ast::{Span, HasSpan};
 use super::parser;
 use super::session::{HasSession, Session, Reportable};
+use super::timing::{Instant, Phase, Counter};
 use super::elaborate::{self};
+use super::index::Index;
 pub use self::error::Error;
+pub use self::reduce::{NormalForm, ReduceOptions, Transparency};
+pub use self::validate::Violation;
 use self::constraint::*;
-use self::solver::replace_metavars;
-use term::{stdout, StdoutTerminal};
+use self::solver::{replace_metavars, replace_metavars_with_err};
+use hubris_kernel;
+use hubris_kernel::guard;
 
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::Read;
 use std::path::{PathBuf, Path};
 
 pub type ComputationRule = Box<Fn(&TyCtxt, Term) -> Result<Term, Error>>;
@@ -38,16 +53,67 @@ impl Axiom {
     }
 }
 
+/// The information attached to a global marked `@[deprecated]`.
+#[derive(Clone, Debug)]
+pub struct Deprecation {
+    pub message: Option<String>,
+    /// The name users should refer to instead.
+    pub replacement: Option<Name>,
+}
+
+/// A hint declared with `@[unify_hint projection head]`, telling the
+/// solver that `projection ... ?M =?= head ...` is solved by `?M := instance`.
+#[derive(Clone, Debug)]
+pub struct UnifyHint {
+    pub projection: Name,
+    pub head: Name,
+    pub instance: Name,
+}
+
 /// A global context for type checking containing the necessary information
 /// needed across type checking all definitions.
 pub struct TyCtxt {
     // We keep these around right now, but I'm not sure if we should.
-    pub types: HashMap<Name, Data>,
-    pub axioms: HashMap<Name, Axiom>,
-    pub definitions: HashMap<Name, Definition>,
+    pub types: BTreeMap<Name, Data>,
+    pub axioms: BTreeMap<Name, Axiom>,
+    /// The calling conventions of externs declared with a symbol.
+    pub externs: BTreeMap<Name, Convention>,
+    pub definitions: BTreeMap<Name, Definition>,
+    /// Globals that have been marked as deprecated.
+    pub deprecated: BTreeMap<Name, Deprecation>,
+    /// The doc comments of documented globals.
+    pub docs: BTreeMap<Name, String>,
+    /// The globals each module loaded from source provides to its
+    /// importers, those it declares and those it re-exports.
+    pub interfaces: BTreeMap<Name, BTreeSet<Name>>,
+    /// The unification hints in scope, in the order they were declared.
+    pub unify_hints: Vec<UnifyHint>,
+    /// The globals marked `@[instance]`, in the order they were declared.
+    pub instances: Vec<Name>,
+    /// The globals marked `@[elab_as routine]`, with the routine their
+    /// applications are elaborated by.
+    pub elab_as: BTreeMap<Name, String>,
+    /// The messages given with `@[error_message]`, reported in place of
+    /// the usual one when resolving or unifying an application of the
+    /// global fails.
+    pub error_messages: BTreeMap<Name, String>,
+    /// The definitions marked `@[export_rust]`, with the name of the
+    /// wrapper generated for them in a library.
+    pub rust_exports: BTreeMap<Name, String>,
+    /// The files the imports of this context were loaded from, with a hash
+    /// of their contents, an image records them to notice when one of them
+    /// changes.
+    pub dependencies: BTreeMap<PathBuf, u64>,
+    /// The instances found for instance arguments of a given type.
+    pub instance_cache: HashMap<Term, Term>,
     pub session: Session,
     local_counter: RefCell<usize>,
-    pub terminal: Box<StdoutTerminal>,
+    /// The kernel environment, every declaration accepted by the elaborator
+    /// is re-checked and recorded here.
+    pub kernel: hubris_kernel::Environment,
+    /// The types of terms elaborated in this context, recorded only when
+    /// the session is indexing.
+    pub index: Index,
 }
 
 pub type CkResult = Result<(Term, ConstraintSeq), Error>;
@@ -55,12 +121,24 @@ pub type CkResult = Result<(Term, ConstraintSeq), Error>;
 impl TyCtxt {
     pub fn empty() -> TyCtxt {
         TyCtxt {
-            types: HashMap::new(),
-            axioms: HashMap::new(),
-            definitions: HashMap::new(),
-            session: Session::empty(),
+            types: BTreeMap::new(),
+            axioms: BTreeMap::new(),
+            externs: BTreeMap::new(),
+            definitions: BTreeMap::new(),
+            deprecated: BTreeMap::new(),
+            docs: BTreeMap::new(),
+            interfaces: BTreeMap::new(),
+            unify_hints: vec![],
+            instances: vec![],
+            elab_as: BTreeMap::new(),
+            error_messages: BTreeMap::new(),
+            rust_exports: BTreeMap::new(),
+            dependencies: BTreeMap::new(),
+            instance_cache: HashMap::new(),
+            session: Session::in_memory(),
             local_counter: RefCell::new(0),
-            terminal: stdout().unwrap(),
+            kernel: hubris_kernel::Environment::new(),
+            index: Index::new(),
         }
     }
 
@@ -83,6 +161,8 @@ impl TyCtxt {
         // }
 
         for def in &module.defs {
+            self.session.enter_definition("type checking", Some(format!("{}", def.name())));
+
             match def {
                 &Item::Data(ref d) => try!(self.declare_datatype(d)),
                 &Item::Fn(ref f) => try!(self.declare_def(f)),
@@ -93,6 +173,7 @@ impl TyCtxt {
             try!(self.type_check_def(def));
         }
 
+        self.session.leave_definition();
 
         Ok(())
     }
@@ -103,20 +184,80 @@ impl TyCtxt {
 
     pub fn load_import(&mut self, name: &Name) -> Result<(), Error> {
         debug!("load_import: module_name={}", name);
-        let file_suffix = match name_to_path(name) {
-            None => panic!(),
-            Some(f) => f,
-        };
 
-        let file_to_load = self.session.resolve_path(&file_suffix);
+        match try!(resolve_import(&self.session, name)) {
+            ImportSource::ExportDir(dir) => self.load_export_dir(&dir),
+            ImportSource::Image(file) => self.load_image(&file),
+            ImportSource::Export(file) => self.load_export(&file),
+            ImportSource::Source(file) => self.load_import_from_path(&file),
+        }
+    }
+
+    /// Load every export file in `dir`, in order of file name.
+    pub fn load_export_dir(&mut self, dir: &Path) -> Result<(), Error> {
+        let mut files = vec![];
+
+        for entry in try!(fs::read_dir(dir)) {
+            let path = try!(entry).path();
+            if path.extension().map(|e| e == "export").unwrap_or(false) {
+                files.push(path);
+            }
+        }
+
+        files.sort();
+
+        for file in files {
+            try!(self.load_export(&file));
+        }
+
+        Ok(())
+    }
+
+    /// Load a file in the export format directly into the context, the
+    /// declarations are checked by the kernel but not re-elaborated.
+    pub fn load_export(&mut self, path: &Path) -> Result<(), Error> {
+        debug!("load_export: path={}", path.display());
+
+        if self.session.is_loaded(path) {
+            return Ok(());
+        }
+
+        let mut source = String::new();
+        let mut file = try!(File::open(path));
+        try!(file.read_to_string(&mut source));
+        self.dependencies.insert(path.to_owned(), content_hash(source.as_bytes()));
+
+        let items = try!(hubris_kernel::import::import_items(
+            &source[..],
+            |repr, ty| self.local_with_repr(repr, ty)));
 
-        self.load_import_from_path(&file_to_load)
+        for item in items {
+            try!(self.kernel.add_item(&item));
+
+            match item {
+                Item::Data(ref d) => try!(self.declare_datatype(d)),
+                Item::Fn(f) => {
+                    self.session.register_implicits(&f.name, &f.ty);
+                    self.definitions.insert(f.name.clone(), f);
+                }
+                Item::Axiom(ref ax) => self.declare_axiom(ax),
+                Item::Extern(ref e) => self.declare_extern(e),
+            }
+        }
+
+        self.session.mark_loaded(path);
+
+        Ok(())
     }
 
     pub fn load_import_from_path(&mut self, file_to_load: &Path) -> Result<(), Error> {
         debug!("load_import_from_path: file_to_load={}", file_to_load.display());
 
         if !self.session.is_loaded(&file_to_load) {
+            if let Some(hash) = file_hash(file_to_load) {
+                self.dependencies.insert(file_to_load.to_owned(), hash);
+            }
+
             let id = self.session.next_module_id();
             let parser = try!(parser::from_file(&file_to_load, id));
             let module = try!(parser.parse());
@@ -140,24 +281,62 @@ impl TyCtxt {
                     // We should return an import error here
                     Ok(())
                 },
-                Ok(emodule) => {
-                    let ty_cx = try!(TyCtxt::from_module(&emodule, self.session.clone()));
-                    self.merge(ty_cx)
-                }
+                Ok(emodule) => self.load_elaborated(&ecx.ty_cx, &emodule),
             }
         } else {
             Ok(())
         }
     }
 
+    /// Add the declarations of `module`, which was elaborated in `ty_cx`,
+    /// along with the tables its importers rely on.
+    pub fn load_elaborated(&mut self, ty_cx: &TyCtxt, module: &Module) -> Result<(), Error> {
+        let mut imported = try!(TyCtxt::from_module(module, self.session.clone()));
+        // The kernel environment was populated during elaboration.
+        imported.kernel = ty_cx.kernel.clone();
+        imported.interfaces = ty_cx.interfaces.clone();
+        imported.unify_hints = ty_cx.unify_hints.clone();
+        imported.instances = ty_cx.instances.clone();
+        imported.elab_as = ty_cx.elab_as.clone();
+        imported.error_messages = ty_cx.error_messages.clone();
+        imported.rust_exports = ty_cx.rust_exports.clone();
+        imported.dependencies = ty_cx.dependencies.clone();
+        self.merge(imported)
+    }
+
     pub fn merge(&mut self, ty_cx: TyCtxt) -> Result<(), Error> {
         let TyCtxt {
             types,
             axioms,
+            externs,
             definitions,
+            deprecated,
+            docs,
+            interfaces,
+            unify_hints,
+            instances,
+            elab_as,
+            error_messages,
+            rust_exports,
+            dependencies,
+            kernel,
+            index,
             ..
         } = ty_cx;
 
+        self.dependencies.extend(dependencies.into_iter());
+        self.kernel.merge(kernel);
+        self.index.merge(index);
+        self.deprecated.extend(deprecated.into_iter());
+        self.externs.extend(externs.into_iter());
+        self.docs.extend(docs.into_iter());
+        self.interfaces.extend(interfaces.into_iter());
+        self.unify_hints.extend(unify_hints.into_iter());
+        self.instances.extend(instances.into_iter());
+        self.elab_as.extend(elab_as.into_iter());
+        self.error_messages.extend(error_messages.into_iter());
+        self.rust_exports.extend(rust_exports.into_iter());
+
         let mut errors = vec![];
 
         for (n, ty) in types {
@@ -193,7 +372,45 @@ impl TyCtxt {
         }
     }
 
+    /// Check that `main` can be the entry point of an executable. The
+    /// generated program evaluates `main` without arguments and discards
+    /// its value, so its type must be a data type, that is an inductive
+    /// type or a type declared with `axiom`, such as `UInt32`, applied to
+    /// any arguments it takes. A definition of a type is unfolded first.
+    pub fn check_main(&self, main: &Definition) -> Result<(), Error> {
+        let (ty, _) = try!(self.whnf(&main.ty));
+
+        let is_data_type = match ty.head() {
+            Some(Term::Var { ref name }) => self.axioms.get(name).map_or(false, |axiom| {
+                let mut result = &axiom.ty;
+                while let &Term::Forall { ref term, .. } = result {
+                    result = &**term;
+                }
+                result.is_sort()
+            }),
+            _ => false,
+        };
+
+        if is_data_type {
+            Ok(())
+        } else {
+            Err(Error::MainType(main.name.get_span(), main.ty.clone()))
+        }
+    }
+
     pub fn declare_datatype(&mut self, data_type: &Data) -> Result<(), Error> {
+        self.declare_type_constants(data_type);
+        inductive::make_recursor(self, data_type)
+
+        // let mut generated_definitions = vec![];
+        // try!(inductive::make_recursor(self, data_type, &mut generated_definitions))
+        //
+        // for def in generated_definitions {}
+    }
+
+    /// Declare `data_type`, its constructors, and the function defined
+    /// along with it, but not its recursor.
+    fn declare_type_constants(&mut self, data_type: &Data) {
         // Currently we use types/functions for metadata, do we need them?
         self.types.insert(data_type.name.clone(), data_type.clone());
 
@@ -205,25 +422,48 @@ impl TyCtxt {
         for ctor in &data_type.ctors {
             let name = ctor.0.clone();
             let ty = ctor.1.clone();
+            self.session.register_implicits(&name, &ty);
             let axiom = Axiom::new(ty);
             self.axioms.insert(name, axiom);
         }
 
-        inductive::make_recursor(self, data_type)
+        // A function defined with the type computes by its equations once its
+        // argument, following the type's parameters and indices, is a constructor.
+        if let Some(ref rec) = data_type.recursive {
+            self.session.register_implicits(&rec.name, &rec.ty);
 
-        // let mut generated_definitions = vec![];
-        // try!(inductive::make_recursor(self, data_type, &mut generated_definitions))
-        //
-        // for def in generated_definitions {}
+            let mut major = 0;
+            let mut ty = &data_type.ty;
+            while let &Term::Forall { ref term, .. } = ty {
+                major += 1;
+                ty = term;
+            }
+
+            let equations = rec.clone();
+            let computation_rule: ComputationRule = Box::new(move |cx: &TyCtxt, term: Term| {
+                let (_, args) = term.uncurry();
+                match equations.unfold(major, &args) {
+                    None => Ok(term),
+                    Some(reduct) => cx.eval(&reduct),
+                }
+            });
+
+            self.axioms.insert(rec.name.clone(), Axiom {
+                ty: rec.ty.clone(),
+                computation_rule: Some(computation_rule),
+            });
+        }
     }
 
     pub fn declare_def(&mut self, def: &Definition) -> Result<(), Error> {
         let (term, ty) = try!(self.type_check_term(&def.body, Some(def.ty.clone())));
+        try!(self.check_relevance(&def.name, &term));
 
         let mut def = def.clone();
         def.body = term;
         def.ty = ty;
 
+        self.session.register_implicits(&def.name, &def.ty);
         self.definitions.insert(def.name.clone(), def);
 
         Ok(())
@@ -232,15 +472,21 @@ impl TyCtxt {
     /// Declaring an external function creates an axiom in the type checker
     /// with the appropriate type.
     ///
-    /// During code generation we will deal with creating a symbol for this
-    /// function.
+    /// During code generation the extern is bound to the runtime function
+    /// named by its calling convention.
     pub fn declare_extern(&mut self, e: &Extern) {
         let axiom = Axiom::new(e.term.clone());
+        self.session.register_implicits(&e.name, &e.term);
         self.axioms.insert(e.name.clone(), axiom);
+
+        if let Some(ref convention) = e.convention {
+            self.externs.insert(e.name.clone(), convention.clone());
+        }
     }
 
     pub fn declare_axiom(&mut self, e: &core::Axiom) {
         let axiom = Axiom::new(e.ty.clone());
+        self.session.register_implicits(&e.name, &e.ty);
         self.axioms.insert(e.name.clone(), axiom);
     }
 
@@ -283,7 +529,13 @@ impl TyCtxt {
             _ => panic!("creating local {:?}", binder.name),
         };
 
-        self.local_with_repr_and_mode(repr.clone(), *binder.ty, binder.mode)
+        let mut local = self.local_with_repr_and_mode(repr.clone(), *binder.ty, binder.mode);
+
+        if let Name::Local { ref mut relevance, .. } = local {
+            *relevance = binder.relevance;
+        }
+
+        local
     }
 
     pub fn local_with_repr(&self, repr: String, ty: Term) -> Name {
@@ -292,6 +544,7 @@ impl TyCtxt {
             ty: Box::new(ty),
             repr: repr.clone(),
             binding_info: BindingMode::Explicit,
+            relevance: Relevance::Relevant,
         };
 
         *self.local_counter.borrow_mut() += 1;
@@ -305,6 +558,7 @@ impl TyCtxt {
             ty: Box::new(ty),
             repr: repr.clone(),
             binding_info: mode,
+            relevance: Relevance::Relevant,
         };
 
         *self.local_counter.borrow_mut() += 1;
@@ -342,6 +596,71 @@ impl TyCtxt {
         Ok(t)
     }
 
+    /// Whether `name` is a constructor of one of the declared types.
+    pub fn is_constructor(&self, name: &Name) -> bool {
+        self.types.values().any(|data| data.ctors.iter().any(|c| &c.0 == name))
+    }
+
+    /// The axioms the global `name` depends on, through its type, its body
+    /// and those of the globals it refers to. The constants generated for a
+    /// type depend on the type, which depends on what its constructors and
+    /// the function defined along with it refer to. Externs and axioms
+    /// added by elaboration routines count as axioms.
+    pub fn axioms_used(&self, name: &Name) -> BTreeSet<Name> {
+        let mut owners = BTreeMap::new();
+        for data in self.types.values() {
+            let rec_name = data.name.in_scope("rec".to_string()).unwrap();
+            owners.insert(rec_name, data.name.clone());
+
+            for ctor in &data.ctors {
+                owners.insert(ctor.0.clone(), data.name.clone());
+            }
+
+            if let Some(ref rec) = data.recursive {
+                owners.insert(rec.name.clone(), data.name.clone());
+            }
+        }
+
+        let mut axioms = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![name.clone()];
+
+        while let Some(name) = stack.pop() {
+            let name = owners.get(&name).cloned().unwrap_or(name);
+
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+
+            let mut names = BTreeSet::new();
+
+            if let Some(def) = self.definitions.get(&name) {
+                globals(&def.ty, &mut names);
+                globals(&def.body, &mut names);
+            } else if let Some(data) = self.types.get(&name) {
+                globals(&data.ty, &mut names);
+
+                for ctor in &data.ctors {
+                    globals(&ctor.1, &mut names);
+                }
+
+                if let Some(ref rec) = data.recursive {
+                    globals(&rec.ty, &mut names);
+                    for &(_, ref rhs) in &rec.equations {
+                        globals(rhs, &mut names);
+                    }
+                }
+            } else if let Some(axiom) = self.axioms.get(&name) {
+                globals(&axiom.ty, &mut names);
+                axioms.insert(name.clone());
+            }
+
+            stack.extend(names);
+        }
+
+        axioms
+    }
+
     /// Checks whether a constructor's type is recursive
     pub fn is_recursive_ctor(&self, ty_name: &Name, mut ctor_ty: &Term) -> bool {
         let mut is_rec = false;
@@ -370,6 +689,7 @@ impl TyCtxt {
 
                 match efun {
                     Term::Lambda { ref body, .. } => {
+                        self.session.count(Counter::Reductions, 1);
                         self.whnf(&body.instantiate(&earg))
                     }
                     f => Ok((Term::App {
@@ -379,6 +699,10 @@ impl TyCtxt {
                     }, vec![]))
                 }
             }
+            &Term::Let { .. } => {
+                self.session.count(Counter::Reductions, 1);
+                self.whnf(&term.zeta().unwrap())
+            }
             _ => Ok((term.clone(), vec![]))
         }
     }
@@ -407,6 +731,7 @@ impl TyCtxt {
                         for earg in eargs {
                             match lambda {
                                 Term::Lambda { body, .. } => {
+                                    self.session.count(Counter::Reductions, 1);
                                     lambda = body.instantiate(&earg);
                                 }
                                 _ => panic!("evaluation error")
@@ -414,6 +739,19 @@ impl TyCtxt {
                         }
                         Ok(try!(self.eval(&lambda)))
                     }
+                    fix @ Term::Fix { .. } => {
+                        match fix.unfold_fix(&eargs, |n| self.is_constructor(n)) {
+                            Some(reduct) => {
+                                self.session.count(Counter::Reductions, 1);
+                                self.eval(&reduct)
+                            }
+                            None => {
+                                let mut t = Term::apply_all(fix, eargs);
+                                t.set_span(span);
+                                Ok(t)
+                            }
+                        }
+                    }
                     Term::Var { ref name } => {
                         if let Some(comp_rule) = self.computation_rule(name) {
                             let t = Term::apply_all(Term::Var { name: name.clone() }, eargs);
@@ -432,7 +770,7 @@ impl TyCtxt {
                 let eterm = try!(self.eval(term));
 
                 Ok(Forall {
-                    binder: Binder::with_mode(binder.name.clone(), ety, binder.mode.clone()),
+                    binder: binder.with_type(ety),
                     term: Box::new(eterm),
                     span: span,
                 })
@@ -442,11 +780,18 @@ impl TyCtxt {
                 let eterm = try!(self.eval(body));
 
                 Ok(Lambda {
-                    binder: Binder::with_mode(binder.name.clone(), ety, binder.mode.clone()),
+                    binder: binder.with_type(ety),
                     body: Box::new(eterm),
                     span: span,
                 })
             }
+            &Term::Let { .. } => {
+                self.session.count(Counter::Reductions, 1);
+                self.eval(&term.zeta().unwrap())
+            }
+            // The body is only evaluated once the fixpoint is unfolded,
+            // evaluating it here would unfold the recursive calls forever.
+            &Term::Fix { .. } => Ok(term.clone()),
             &Term::Var { ref name } => self.unfold_name(name),
             &Term::Type => Ok(Term::Type)
         };
@@ -509,6 +854,7 @@ impl TyCtxt {
                 let just =
                     Justification::Asserted(
                         AssertedBy::ExpectedFound(
+                            term.get_span(),
                             infer_ty.clone(),
                             ty.clone()));
 
@@ -522,14 +868,28 @@ impl TyCtxt {
             }
         }
 
+        self.session.count(Counter::Constraints, infer_cs.len());
+
+        let session = self.session.clone();
+        let start = Instant::now();
+        let _site = core::stats::Site::enter("solve");
+
         let solver = try!(solver::Solver::new(self, infer_cs));
 
         let solutions = try!(solver.solve());
 
+        session.record_time(Phase::Solve, None, start);
+
         for (meta, sol) in &solutions {
             debug!("solutions: meta={} {}", meta, sol.0);
         }
 
+        // Metas solved by a later constraint set are left in place, as are
+        // those that are never solved so their holes can still be listed.
+        if self.session.indexing() {
+            self.index.resolve(|ty| replace_metavars_with_err(ty, &solutions, &mut vec![]));
+        }
+
         // Finally use the solutions given to us by the solver or
         // throw an error if there is not a solution for a meta-var
         // occurring in them
@@ -537,11 +897,112 @@ impl TyCtxt {
 
         debug!("term={}\nnew_term={}", term, new_term);
 
+        // An unguarded `fix` would be rejected by the kernel, this reports it
+        // where it was written instead.
+        try!(self.check_fixpoints(&new_term));
+
         let infer_ty = try!(replace_metavars(infer_ty.clone(), &solutions));
 
         Ok((new_term, expected_ty.unwrap_or(infer_ty)))
     }
 
+    /// Check the guard condition of every `fix` in `term`, the same
+    /// condition the kernel checks.
+    fn check_fixpoints(&self, term: &Term) -> Result<(), Error> {
+        match term {
+            &Term::Var { .. } | &Term::Type => Ok(()),
+            &Term::App { ref fun, ref arg, .. } => {
+                try!(self.check_fixpoints(fun));
+                self.check_fixpoints(arg)
+            }
+            &Term::Forall { ref binder, term: ref body, .. } |
+            &Term::Lambda { ref binder, ref body, .. } => {
+                try!(self.check_fixpoints(&binder.ty));
+                self.check_fixpoints(body)
+            }
+            &Term::Let { ref binder, ref value, ref body, .. } => {
+                try!(self.check_fixpoints(&binder.ty));
+                try!(self.check_fixpoints(value));
+                self.check_fixpoints(body)
+            }
+            &Term::Fix { ref binder, rec_arg, ref body, span } => {
+                try!(self.check_fixpoints(&binder.ty));
+                let local = self.local(binder.clone());
+                let body = body.instantiate(&local.to_term());
+
+                try!(guard::check_fix(self, &local, rec_arg, &body)
+                         .map_err(|unguarded| Error::Unguarded(span, unguarded)));
+
+                self.check_fixpoints(&body)
+            }
+        }
+    }
+
+    /// A metavariable of type `ty` that does not depend on any locals,
+    /// created at `span` to stand for what `origin` describes.
+    pub fn fresh_meta(&self, ty: Term, span: Span, origin: String) -> Term {
+        let number = self.session.next_meta_number();
+        self.session.register_meta_origin(number, span, origin);
+
+        let meta = Name::Meta {
+            number: number,
+            ty: Box::new(ty),
+        };
+
+        self.session.count(Counter::Metas, 1);
+
+        meta.to_term()
+    }
+
+    /// A metavariable of type `ty` applied to the locals `term` mentions,
+    /// along with those their types mention, so that whatever it is solved
+    /// with may mention them too.
+    fn meta_in_scope_of(&self, term: &Term, ty: Term, span: Span, origin: String) -> Term {
+        fn collect(term: &Term, locals: &mut Vec<Name>) {
+            match term {
+                &Term::Var { name: ref local @ Name::Local { .. } } => {
+                    if !locals.contains(local) {
+                        locals.push(local.clone());
+                        if let &Name::Local { ref ty, .. } = local {
+                            collect(ty, locals);
+                        }
+                    }
+                }
+                &Term::Var { .. } | &Term::Type => {}
+                &Term::App { ref fun, ref arg, .. } => {
+                    collect(fun, locals);
+                    collect(arg, locals);
+                }
+                &Term::Forall { ref binder, term: ref body, .. } |
+                &Term::Lambda { ref binder, ref body, .. } |
+                &Term::Fix { ref binder, ref body, .. } => {
+                    collect(&binder.ty, locals);
+                    collect(body, locals);
+                }
+                &Term::Let { ref binder, ref value, ref body, .. } => {
+                    collect(&binder.ty, locals);
+                    collect(value, locals);
+                    collect(body, locals);
+                }
+            }
+        }
+
+        let mut locals = vec![];
+        collect(term, &mut locals);
+
+        // Locals are numbered as they are created, so a local's type only
+        // mentions those before it.
+        locals.sort_by_key(|local| match local {
+            &Name::Local { number, .. } => number,
+            _ => 0,
+        });
+
+        let args = locals.iter().map(Name::to_term).collect();
+        let meta = self.fresh_meta(Term::abstract_pi(locals, ty), span, origin);
+
+        Term::apply_all(meta, args)
+    }
+
     pub fn ensure_sort(&self, term: Term) -> CkResult {
         if term.is_sort() {
             return Ok(constrain(term, vec![]));
@@ -557,15 +1018,64 @@ impl TyCtxt {
 
         let (tp, cs) = try!(self.whnf(&term));
 
-        if tp.is_forall() {
+        // A type stuck on a metavariable may still turn out to be a
+        // function type, the caller has to postpone using it.
+        if tp.is_forall() || tp.is_stuck().is_some() {
             Ok((tp, cs))
-        } else if let Some(_) = tp.is_stuck() {
-            panic!()
         } else {
             Err(Error::ExpectedFunction(sp, term))
         }
     }
 
+    /// Construct the error for applying `fun`, which is an application whose
+    /// type is no longer a function type, to the extra argument `arg`.
+    fn arity_mismatch(&mut self, fun: &Term, arg: &Term) -> Error {
+        let (head, args) = fun.uncurry();
+
+        let head_ty = match self.type_infer_term(&head) {
+            Ok((ty, _)) => ty,
+            Err(e) => return e,
+        };
+
+        // Implicit arguments are inserted by the elaborator, so we only count
+        // the explicit ones. The type is reduced before each binder, a
+        // definition may unfold to more of them.
+        let mut expected = 0;
+        let mut given = 0;
+        let mut cursor = head_ty.clone();
+        let mut position = 0;
+
+        loop {
+            cursor = match self.whnf(&cursor) {
+                Ok((Term::Forall { binder, term, .. }, _)) => {
+                    if binder.mode == BindingMode::Explicit {
+                        expected += 1;
+                        if position < args.len() {
+                            given += 1;
+                        }
+                    }
+
+                    let value = match args.get(position) {
+                        Some(arg) => arg.clone(),
+                        None => self.local(binder).to_term(),
+                    };
+
+                    position += 1;
+                    term.instantiate(&value)
+                }
+                _ => break,
+            };
+        }
+
+        Error::ArityMismatch {
+            span: arg.get_span(),
+            fun: head,
+            ty: head_ty,
+            expected: expected,
+            found: given + 1,
+        }
+    }
+
     pub fn type_infer_term(&mut self, term: &Term) -> CkResult {
         debug!("type_infer_term: term={}", term);
         let result = match term {
@@ -589,11 +1099,25 @@ impl TyCtxt {
             &Term::App { ref fun, ref arg, span } => {
                 let mut constraints = vec![];
 
-                let (pi_type, pi_cs) =
-                    try!(self.type_infer_term(fun));
+                // If the function was already applied to too many arguments
+                // this argument is one more.
+                let (pi_type, pi_cs) = match self.type_infer_term(fun) {
+                    Err(Error::ArityMismatch { span, fun, ty, expected, found }) =>
+                        return Err(Error::ArityMismatch {
+                            span: span,
+                            fun: fun,
+                            ty: ty,
+                            expected: expected,
+                            found: found + 1,
+                        }),
+                    result => try!(result),
+                };
 
-                let (pi_type, ensure_cs) =
-                    try!(self.ensure_forall(pi_type, fun.get_span()));
+                let (pi_type, ensure_cs) = match self.ensure_forall(pi_type, fun.get_span()) {
+                    Err(Error::ExpectedFunction(..)) if fun.is_app() =>
+                        return Err(self.arity_mismatch(fun, arg)),
+                    result => try!(result),
+                };
 
                 constraints.extend(pi_cs.into_iter());
                 constraints.extend(ensure_cs.into_iter());
@@ -617,6 +1141,21 @@ impl TyCtxt {
 
                         debug!("{} = {}", arg_ty, binder.ty);
 
+                        // An instance argument left for the elaborator to
+                        // fill in is found by instance search.
+                        if binder.is_instance() && arg.is_stuck().is_some() {
+                            constraints.push(
+                                Constraint::Instance(
+                                    InstanceGoal {
+                                        span: fun.get_span(),
+                                        meta: *arg.clone(),
+                                        ty: (*binder.ty).clone(),
+                                        depth: 0,
+                                        context: local_context(arg),
+                                    },
+                                    just.clone()));
+                        }
+
                         constraints.push(
                             Constraint::Unification(
                                 arg_ty,
@@ -626,6 +1165,43 @@ impl TyCtxt {
                         // TODO: add type checking obliation here
                         Ok(constrain(term, constraints))
                     }
+                    // The argument can only be checked once we know what
+                    // the function expects, so the type of the application
+                    // is a fresh metavariable until the solver finds out.
+                    t if t.is_stuck().is_some() => {
+                        let (arg_ty, arg_cs) =
+                            try!(self.type_infer_term(arg));
+
+                        constraints.extend(arg_cs.into_iter());
+
+                        // The type may depend on the locals in scope, which
+                        // are at least those the application mentions.
+                        let result_ty = self.meta_in_scope_of(
+                            term,
+                            Term::Type,
+                            span,
+                            "type of this application".to_string());
+
+                        let just =
+                            Justification::Asserted(
+                                AssertedBy::Application(
+                                    fun.get_span(),
+                                    t.clone(),
+                                    arg_ty.clone()));
+
+                        constraints.push(
+                            Constraint::Application(
+                                DelayedApplication {
+                                    fun: *fun.clone(),
+                                    fun_ty: t,
+                                    arg: *arg.clone(),
+                                    arg_ty: arg_ty,
+                                    result_ty: result_ty.clone(),
+                                },
+                                just));
+
+                        Ok(constrain(result_ty, constraints))
+                    }
                     t => Err(Error::ApplicationMismatch(
                         span,
                         *fun.clone(),
@@ -681,12 +1257,75 @@ impl TyCtxt {
 
                 Ok(constrain(forall, constraints))
             }
+            &Term::Let { ref binder, ref value, ref body, .. } => {
+                let mut constraints = vec![];
+
+                let (sort, ty_cs) = try!(self.type_infer_term(&binder.ty));
+                let (_, sort_cs) = try!(self.ensure_sort(sort));
+                constraints.extend(ty_cs.into_iter());
+                constraints.extend(sort_cs.into_iter());
+
+                let (value_ty, value_cs) = try!(self.type_infer_term(value));
+                constraints.extend(value_cs.into_iter());
+                let just =
+                    Justification::Asserted(
+                        AssertedBy::ExpectedFound(
+                            value.get_span(),
+                            value_ty.clone(),
+                            (*binder.ty).clone()));
+
+                constraints.push(
+                    Constraint::Unification(
+                        value_ty,
+                        (*binder.ty).clone(),
+                        just));
+
+                let (body_ty, body_cs) =
+                    try!(self.type_infer_term(&body.instantiate(value)));
+                constraints.extend(body_cs.into_iter());
+
+                Ok(constrain(body_ty, constraints))
+            }
+            &Term::Fix { ref binder, ref body, .. } => {
+                let mut constraints = vec![];
+
+                let (sort, ty_cs) = try!(self.type_infer_term(&binder.ty));
+                let (_, sort_cs) = try!(self.ensure_sort(sort));
+                constraints.extend(ty_cs.into_iter());
+                constraints.extend(sort_cs.into_iter());
+
+                let local = self.local(binder.clone());
+                let body = body.instantiate(&local.to_term());
+
+                let (body_ty, body_cs) = try!(self.type_infer_term(&body));
+                constraints.extend(body_cs.into_iter());
+
+                let just =
+                    Justification::Asserted(
+                        AssertedBy::ExpectedFound(
+                            body.get_span(),
+                            body_ty.clone(),
+                            (*binder.ty).clone()));
+
+                constraints.push(
+                    Constraint::Unification(
+                        body_ty,
+                        (*binder.ty).clone(),
+                        just));
+
+                Ok(constrain((*binder.ty).clone(), constraints))
+            }
             &Term::Type =>
                 Ok(constrain(Term::Type, vec![])),
         };
 
         let (t, cs) = try!(result);
         debug!("type_infer_term: term={}, infer_ty={}", term, t);
+
+        if self.session.indexing() {
+            self.index.record_type(term.get_span(), term.clone(), t.clone());
+        }
+
         Ok((t, cs))
     }
 
@@ -695,6 +1334,27 @@ impl TyCtxt {
     }
 }
 
+impl guard::Globals for TyCtxt {
+    fn recursor_of(&self, name: &Name) -> Option<&Data> {
+        match name {
+            &Name::Qual { ref components, .. }
+                if components.len() > 1 && components.last().map_or(false, |c| c == "rec") => {
+                let owner = Name::qualified(components[..components.len() - 1].to_vec());
+                self.types.get(&owner)
+            }
+            _ => None,
+        }
+    }
+
+    fn definition(&self, name: &Name) -> Option<&Term> {
+        self.definitions.get(name).map(|def| &def.body)
+    }
+
+    fn fresh_local(&self, binder: &Binder) -> Name {
+        self.local(binder.clone())
+    }
+}
+
 fn def_eq_modulo(
     t1: &Term,
     t2: &Term,
@@ -754,6 +1414,100 @@ fn def_eq_name_modulo(n1: &Name, n2: &Name) -> bool {
     }
 }
 
+/// Where the module an import names is loaded from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportSource {
+    /// A directory of export files.
+    ExportDir(PathBuf),
+    /// An image of the context the module was elaborated in, see `image`.
+    Image(PathBuf),
+    /// A single export file.
+    Export(PathBuf),
+    /// A source file, which is elaborated.
+    Source(PathBuf),
+}
+
+/// Find the files providing the module `name`, a module may also be
+/// provided as a pre-checked export, either as a single file, or a
+/// directory of export files, or as an image.
+pub fn resolve_import(session: &Session, name: &Name) -> Result<ImportSource, Error> {
+    let file_suffix = match name_to_path(name) {
+        None => return Err(Error::InvalidImport(name.clone())),
+        Some(f) => f,
+    };
+
+    let module_name = match name {
+        &Name::Qual { ref components, .. } => components.join("."),
+        _ => return Err(Error::InvalidImport(name.clone())),
+    };
+
+    let export_dir = session.resolve_path(&file_suffix.with_extension(""));
+    let export_file = session.resolve_path(&file_suffix.with_extension("export"));
+    let image = session.resolve_path(&file_suffix.with_extension("image"));
+
+    let import = if export_dir.is_dir() {
+        ImportSource::ExportDir(export_dir)
+    } else if image.is_file() && is_current(&image) {
+        ImportSource::Image(image)
+    } else if export_file.is_file() {
+        ImportSource::Export(export_file)
+    } else {
+        let source = session.resolve_module(&module_name, &file_suffix);
+        let literate =
+            session.resolve_path(&file_suffix.with_extension(parser::LITERATE_EXTENSION));
+
+        // A module is only read from a literate file if there is no plain one.
+        if !source.is_file() && literate.is_file() {
+            ImportSource::Source(literate)
+        } else {
+            ImportSource::Source(source)
+        }
+    };
+
+    Ok(import)
+}
+
+/// Whether the image at `image` was written no earlier than the source next
+/// to it was last changed, an image with no source next to it is current,
+/// and none of the files its imports were loaded from have changed since.
+fn is_current(image: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let written = modified(image);
+
+    let unchanged = image_sources(image).iter()
+                                        .filter_map(|source| modified(source.as_path()))
+                                        .all(|changed| {
+                                            written.map_or(false, |written| written >= changed)
+                                        });
+
+    unchanged && match self::image::dependencies(image) {
+        Ok(dependencies) => dependencies.iter().all(|&(ref path, hash)| {
+            file_hash(path) == Some(hash)
+        }),
+        Err(_) => false,
+    }
+}
+
+/// The files an image at `image` may have been written from.
+fn image_sources(image: &Path) -> Vec<PathBuf> {
+    vec![image.with_extension("hbr"), image.with_extension(parser::LITERATE_EXTENSION)]
+}
+
+/// A hash of the contents of the file at `path`, if it can be read.
+fn file_hash(path: &Path) -> Option<u64> {
+    let mut contents = vec![];
+    match File::open(path).and_then(|mut file| file.read_to_end(&mut contents)) {
+        Ok(_) => Some(content_hash(&contents)),
+        Err(_) => None,
+    }
+}
+
+fn content_hash(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
 fn name_to_path(name: &Name) -> Option<PathBuf> {
     match name {
         &Name::Qual { ref components, .. } => {
@@ -779,3 +1533,28 @@ fn test_is_bi_reducible() {
     let ty_cx = TyCtxt::new();
     panic!()
 }
+
+/// Add the globals `term` refers to to `names`.
+fn globals(term: &Term, names: &mut BTreeSet<Name>) {
+    match term {
+        &Term::Var { ref name } => if let &Name::Qual { .. } = name {
+            names.insert(name.clone());
+        },
+        &Term::App { ref fun, ref arg, .. } => {
+            globals(fun, names);
+            globals(arg, names);
+        }
+        &Term::Forall { ref binder, term: ref body, .. } |
+        &Term::Lambda { ref binder, ref body, .. } |
+        &Term::Fix { ref binder, ref body, .. } => {
+            globals(&binder.ty, names);
+            globals(body, names);
+        }
+        &Term::Let { ref binder, ref value, ref body, .. } => {
+            globals(&binder.ty, names);
+            globals(value, names);
+            globals(body, names);
+        }
+        &Term::Type => {}
+    }
+}
@@ -0,0 +1,170 @@
+//! Recognizing the declarations of the prelude.
+//!
+//! A few globals of `lib` are given a meaning beyond their definitions, the
+//! SMT translation interprets the logical connectives and the arithmetic on
+//! `Nat`, and the backends compile `Nat` to the runtime's numbers. Globals
+//! are unqualified, so a program may well declare an `add` of its own, which
+//! must not be mistaken for the prelude's. A type is recognized by its
+//! constructors, and a function on `Nat` by computing like the prelude's,
+//! `add Z m` and `add (S n) m` reducing to `m` and `S (add n m)`, equations
+//! which determine the function by induction.
+
+use core::{Data, Name, Term};
+
+use super::{NormalForm, ReduceOptions, TyCtxt};
+
+impl TyCtxt {
+    /// Whether the global `name` is declared as the global of that name in
+    /// the prelude, which is only known of `Nat`, `Z`, `S`, `pred`, `add`,
+    /// `mul`, `Eq`, `True`, `False`, `And` and `Or`.
+    pub fn is_prelude(&self, name: &str) -> bool {
+        match name {
+            "Nat" | "Z" | "S" => self.is_prelude_nat(),
+            "pred" | "add" => self.is_prelude_nat() && self.is_prelude_function(name),
+            "mul" => self.is_prelude("add") && self.is_prelude_function(name),
+            "Eq" => {
+                let a = self.prelude_local("A", &Term::Type);
+                let x = self.prelude_local("x", &a.to_term());
+                let ty = Term::abstract_pi(vec![a.clone()],
+                                           self.arrow(&a.to_term(),
+                                                      self.arrow(&a.to_term(), Term::Type)));
+                let refl = Term::abstract_pi(vec![a.clone(), x.clone()],
+                                             apply("Eq", vec![a.to_term(), x.to_term(),
+                                                              x.to_term()]));
+
+                self.prelude_type("Eq", 1, &ty, &[refl]).is_some()
+            }
+            "True" => self.prelude_type("True", 0, &Term::Type, &[global("True")]).is_some(),
+            "False" => self.prelude_type("False", 0, &Term::Type, &[]).is_some(),
+            "And" | "Or" => {
+                let p = self.prelude_local("P", &Term::Type);
+                let q = self.prelude_local("Q", &Term::Type);
+                let params = vec![p.clone(), q.clone()];
+                let ctor = |fields: &[&Name]| {
+                    let result = apply(name, vec![p.to_term(), q.to_term()]);
+                    let ty = fields.iter().rev().fold(result, |ty, field| {
+                        self.arrow(&field.to_term(), ty)
+                    });
+                    Term::abstract_pi(params.clone(), ty)
+                };
+
+                let ctors = if name == "And" {
+                    vec![ctor(&[&p, &q])]
+                } else {
+                    vec![ctor(&[&p]), ctor(&[&q])]
+                };
+
+                let ty = Term::abstract_pi(params.clone(), Term::Type);
+                self.prelude_type(name, 2, &ty, &ctors).is_some()
+            }
+            _ => false,
+        }
+    }
+
+    fn is_prelude_nat(&self) -> bool {
+        let nat = global("Nat");
+        let ctors = [nat.clone(), self.arrow(&nat, nat.clone())];
+
+        self.prelude_type("Nat", 0, &Term::Type, &ctors).map_or(false, |data| {
+            data.ctors[0].0 == Name::from_str("Z") && data.ctors[1].0 == Name::from_str("S")
+        })
+    }
+
+    /// Whether the function `name` on `Nat` computes like the prelude's.
+    fn is_prelude_function(&self, name: &str) -> bool {
+        let nat = global("Nat");
+        let n = self.prelude_local("n", &nat).to_term();
+        let m = self.prelude_local("m", &nat).to_term();
+        let zero = global("Z");
+        let succ = |n: Term| apply("S", vec![n]);
+
+        let (ty, equations) = match name {
+            "pred" => (self.arrow(&nat, nat.clone()), vec![
+                (apply(name, vec![zero.clone()]), zero.clone()),
+                (apply(name, vec![succ(n.clone())]), n.clone()),
+            ]),
+            "add" => (self.arrow(&nat, self.arrow(&nat, nat.clone())), vec![
+                (apply(name, vec![zero.clone(), m.clone()]), m.clone()),
+                (apply(name, vec![succ(n.clone()), m.clone()]),
+                 succ(apply(name, vec![n.clone(), m.clone()]))),
+            ]),
+            "mul" => (self.arrow(&nat, self.arrow(&nat, nat.clone())), vec![
+                (apply(name, vec![zero.clone(), m.clone()]), zero.clone()),
+                (apply(name, vec![succ(n.clone()), m.clone()]),
+                 apply("add", vec![m.clone(), apply(name, vec![n.clone(), m.clone()])])),
+            ]),
+            _ => return false,
+        };
+
+        let has_type = self.lookup_global(&Name::from_str(name)).ok().map_or(false, |actual| {
+            same(actual, &ty)
+        });
+
+        has_type && equations.iter().all(|&(ref lhs, ref rhs)| self.computes_to(lhs, rhs))
+    }
+
+    /// The inductive type `name`, if it has `params` parameters, the type
+    /// `ty` and constructors of the types `ctors`.
+    fn prelude_type(&self, name: &str, params: usize, ty: &Term, ctors: &[Term]) -> Option<&Data> {
+        self.types.get(&Name::from_str(name)).and_then(|data| {
+            let is_same = data.parameters.len() == params &&
+                          same(&data.ty, ty) &&
+                          data.ctors.len() == ctors.len() &&
+                          data.ctors.iter().zip(ctors).all(|(ctor, ty)| same(&ctor.1, ty));
+
+            if is_same { Some(data) } else { None }
+        })
+    }
+
+    /// Whether `term` and `expected` have the same weak head normal form,
+    /// reached in no more steps than reduction normally takes.
+    fn computes_to(&self, term: &Term, expected: &Term) -> bool {
+        let options = ReduceOptions::default();
+        let term = self.reduce_to(term, NormalForm::WeakHead, options);
+        let expected = self.reduce_to(expected, NormalForm::WeakHead, options);
+
+        match (term, expected) {
+            (Ok(term), Ok(expected)) => same(&term, &expected),
+            _ => false,
+        }
+    }
+
+    fn prelude_local(&self, repr: &str, ty: &Term) -> Name {
+        self.local_with_repr(repr.to_string(), ty.clone())
+    }
+
+    fn arrow(&self, ty: &Term, result: Term) -> Term {
+        Term::abstract_pi(vec![self.prelude_local("_", ty)], result)
+    }
+}
+
+fn global(name: &str) -> Term {
+    Name::from_str(name).to_term()
+}
+
+fn apply(fun: &str, args: Vec<Term>) -> Term {
+    Term::apply_all(global(fun), args)
+}
+
+/// Whether `t` and `u` are the same term, regardless of where they occur in
+/// the source.
+fn same(t: &Term, u: &Term) -> bool {
+    match (t, u) {
+        (&Term::Var { name: ref n }, &Term::Var { name: ref m }) => n == m,
+        (&Term::App { fun: ref f, arg: ref a, .. }, &Term::App { fun: ref g, arg: ref b, .. }) =>
+            same(f, g) && same(a, b),
+        (&Term::Forall { binder: ref b1, term: ref t1, .. },
+         &Term::Forall { binder: ref b2, term: ref t2, .. }) |
+        (&Term::Lambda { binder: ref b1, body: ref t1, .. },
+         &Term::Lambda { binder: ref b2, body: ref t2, .. }) =>
+            same(&b1.ty, &b2.ty) && same(t1, t2),
+        (&Term::Let { binder: ref b1, value: ref v1, body: ref t1, .. },
+         &Term::Let { binder: ref b2, value: ref v2, body: ref t2, .. }) =>
+            same(&b1.ty, &b2.ty) && same(v1, v2) && same(t1, t2),
+        (&Term::Fix { binder: ref b1, rec_arg: r1, body: ref t1, .. },
+         &Term::Fix { binder: ref b2, rec_arg: r2, body: ref t2, .. }) =>
+            r1 == r2 && same(&b1.ty, &b2.ty) && same(t1, t2),
+        (&Term::Type, &Term::Type) => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,317 @@
+//! Reduction of terms to their full normal form, used by `#reduce`, or to
+//! weak head normal form.
+//!
+//! Unlike `TyCtxt::eval` the amount of work done is bounded, and which
+//! definitions are unfolded can be controlled, which makes it useful for
+//! finding out why two terms are or aren't definitionally equal.
+
+use core::{Binder, DeltaReduction, Name, Term};
+use super::super::ast::{HasSpan, Span};
+use super::{Error, TyCtxt};
+
+/// Which definitions are unfolded during reduction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Transparency {
+    /// Unfold every definition.
+    All,
+    /// Only unfold definitions marked as reducible.
+    Reducible,
+    /// Never unfold definitions, only perform beta and iota reduction.
+    None,
+}
+
+impl Transparency {
+    pub fn from_str(s: &str) -> Option<Transparency> {
+        match s {
+            "all" => Some(Transparency::All),
+            "reducible" => Some(Transparency::Reducible),
+            "none" => Some(Transparency::None),
+            _ => None,
+        }
+    }
+}
+
+/// How far a term is reduced.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NormalForm {
+    /// Until its head is a binder, a sort, or can't be reduced, the
+    /// arguments and bodies are left as they are.
+    WeakHead,
+    /// Until nothing can be reduced, except under a fixpoint.
+    Full,
+}
+
+impl NormalForm {
+    pub fn from_str(s: &str) -> Option<NormalForm> {
+        match s {
+            "whnf" => Some(NormalForm::WeakHead),
+            "full" => Some(NormalForm::Full),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ReduceOptions {
+    pub transparency: Transparency,
+    /// The maximum number of reduction steps before giving up.
+    pub max_steps: Option<usize>,
+}
+
+impl Default for ReduceOptions {
+    fn default() -> ReduceOptions {
+        ReduceOptions {
+            transparency: Transparency::All,
+            max_steps: Some(100000),
+        }
+    }
+}
+
+struct Reducer<'tcx> {
+    ty_cx: &'tcx TyCtxt,
+    options: ReduceOptions,
+    span: Span,
+    steps: usize,
+}
+
+impl<'tcx> Reducer<'tcx> {
+    fn step(&mut self) -> Result<(), Error> {
+        self.steps += 1;
+
+        match self.options.max_steps {
+            Some(max) if self.steps > max => Err(Error::ReductionLimit(self.span, max)),
+            _ => Ok(()),
+        }
+    }
+
+    fn unfold(&self, name: &Name) -> Option<Term> {
+        let def = match self.ty_cx.definitions.get(name) {
+            None => return None,
+            Some(def) => def,
+        };
+
+        let transparent = match self.options.transparency {
+            Transparency::All => true,
+            Transparency::Reducible => def.reduction == DeltaReduction::Reducible,
+            Transparency::None => false,
+        };
+
+        if transparent { Some(def.body.clone()) } else { None }
+    }
+
+    fn binder(&mut self, binder: &Binder) -> Result<Binder, Error> {
+        let ty = try!(self.normalize(&binder.ty));
+        Ok(binder.with_type(ty))
+    }
+
+    fn weak_head(&mut self, term: &Term) -> Result<Term, Error> {
+        match term {
+            &Term::App { .. } => {
+                let span = term.get_span();
+                let (head, args) = term.uncurry();
+
+                match try!(self.weak_head(&head)) {
+                    Term::Lambda { body, .. } => {
+                        try!(self.step());
+                        let mut args = args.into_iter();
+                        let first = args.next().unwrap();
+                        let reduced = Term::apply_all(body.instantiate(&first), args.collect());
+                        self.weak_head(&reduced)
+                    }
+                    // The recursive argument, or the major premise of a
+                    // recursor, has to be a constructor application to
+                    // reduce, so the arguments are reduced first.
+                    fix @ Term::Fix { .. } => {
+                        let mut wargs = vec![];
+                        for arg in &args {
+                            wargs.push(try!(self.weak_head(arg)));
+                        }
+
+                        match fix.unfold_fix(&wargs, |n| self.ty_cx.is_constructor(n)) {
+                            Some(reduct) => {
+                                try!(self.step());
+                                self.weak_head(&reduct)
+                            }
+                            None => {
+                                let mut t = Term::apply_all(fix, args);
+                                t.set_span(span);
+                                Ok(t)
+                            }
+                        }
+                    }
+                    Term::Var { name } => {
+                        let mut stuck = Term::apply_all(name.to_term(), args.clone());
+                        stuck.set_span(span);
+
+                        let rule = match self.ty_cx.computation_rule(&name) {
+                            None => return Ok(stuck),
+                            Some(rule) => rule,
+                        };
+
+                        let mut wargs = vec![];
+                        for arg in &args {
+                            wargs.push(try!(self.weak_head(arg)));
+                        }
+
+                        let major = Term::apply_all(name.to_term(), wargs);
+                        let reduced = try!(rule(self.ty_cx, major.clone()));
+
+                        // A rule returns its input if the major premise
+                        // isn't a constructor.
+                        if reduced == major {
+                            Ok(stuck)
+                        } else {
+                            try!(self.step());
+                            self.weak_head(&reduced)
+                        }
+                    }
+                    head => {
+                        let mut t = Term::apply_all(head, args);
+                        t.set_span(span);
+                        Ok(t)
+                    }
+                }
+            }
+            &Term::Let { .. } => {
+                try!(self.step());
+                self.weak_head(&term.zeta().unwrap())
+            }
+            &Term::Var { ref name } => {
+                match self.unfold(name) {
+                    None => Ok(term.clone()),
+                    Some(body) => {
+                        try!(self.step());
+                        self.weak_head(&body)
+                    }
+                }
+            }
+            _ => Ok(term.clone()),
+        }
+    }
+
+    fn normalize(&mut self, term: &Term) -> Result<Term, Error> {
+        match term {
+            &Term::App { .. } => {
+                let span = term.get_span();
+                let (head, args) = term.uncurry();
+
+                let head = try!(self.normalize(&head));
+
+                let mut nargs = vec![];
+                for arg in args {
+                    nargs.push(try!(self.normalize(&arg)));
+                }
+
+                match head {
+                    Term::Lambda { body, .. } => {
+                        try!(self.step());
+                        let mut nargs = nargs.into_iter();
+                        let first = nargs.next().unwrap();
+                        let reduced = Term::apply_all(body.instantiate(&first), nargs.collect());
+                        self.normalize(&reduced)
+                    }
+                    fix @ Term::Fix { .. } => {
+                        match fix.unfold_fix(&nargs, |n| self.ty_cx.is_constructor(n)) {
+                            Some(reduct) => {
+                                try!(self.step());
+                                self.normalize(&reduct)
+                            }
+                            None => {
+                                let mut t = Term::apply_all(fix, nargs);
+                                t.set_span(span);
+                                Ok(t)
+                            }
+                        }
+                    }
+                    Term::Var { name } => {
+                        let mut stuck = Term::apply_all(name.to_term(), nargs);
+                        stuck.set_span(span);
+
+                        match self.ty_cx.computation_rule(&name) {
+                            None => Ok(stuck),
+                            Some(rule) => {
+                                let reduced = try!(rule(self.ty_cx, stuck.clone()));
+
+                                // A rule returns its input if the major premise
+                                // isn't a constructor.
+                                if reduced == stuck {
+                                    Ok(stuck)
+                                } else {
+                                    try!(self.step());
+                                    self.normalize(&reduced)
+                                }
+                            }
+                        }
+                    }
+                    head => {
+                        let mut t = Term::apply_all(head, nargs);
+                        t.set_span(span);
+                        Ok(t)
+                    }
+                }
+            }
+            &Term::Forall { ref binder, ref term, span } => {
+                Ok(Term::Forall {
+                    binder: try!(self.binder(binder)),
+                    term: Box::new(try!(self.normalize(term))),
+                    span: span,
+                })
+            }
+            &Term::Lambda { ref binder, ref body, span } => {
+                Ok(Term::Lambda {
+                    binder: try!(self.binder(binder)),
+                    body: Box::new(try!(self.normalize(body))),
+                    span: span,
+                })
+            }
+            &Term::Let { .. } => {
+                try!(self.step());
+                self.normalize(&term.zeta().unwrap())
+            }
+            // Normalizing under a fixpoint would unfold it without bound.
+            &Term::Fix { .. } => Ok(term.clone()),
+            &Term::Var { ref name } => {
+                match self.unfold(name) {
+                    None => Ok(term.clone()),
+                    Some(body) => {
+                        try!(self.step());
+                        self.normalize(&body)
+                    }
+                }
+            }
+            &Term::Type => Ok(Term::Type),
+        }
+    }
+}
+
+impl TyCtxt {
+    /// Reduce `term` to its normal form.
+    pub fn reduce(&self, term: &Term, options: ReduceOptions) -> Result<Term, Error> {
+        let mut reducer = Reducer {
+            ty_cx: self,
+            options: options,
+            span: term.get_span(),
+            steps: 0,
+        };
+
+        reducer.normalize(term)
+    }
+
+    /// Reduce `term` to `form`.
+    pub fn reduce_to(&self, term: &Term, form: NormalForm, options: ReduceOptions)
+                     -> Result<Term, Error> {
+        match form {
+            NormalForm::Full => self.reduce(term, options),
+            NormalForm::WeakHead => {
+                let mut reducer = Reducer {
+                    ty_cx: self,
+                    options: options,
+                    span: term.get_span(),
+                    steps: 0,
+                };
+
+                reducer.weak_head(term)
+            }
+        }
+    }
+}
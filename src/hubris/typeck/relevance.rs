@@ -0,0 +1,144 @@
+//! Checking that erased variables are only used where they are erased.
+//!
+//! A binder written with a leading `.`, as in `.{n : Nat}` or `.(n : Nat)`,
+//! binds a value that is not passed at runtime. Its variable may appear in
+//! types, and be passed on to other erased binders, but any other use would
+//! need the value. Such uses are rejected here, so the backend never
+//! refers to an erased variable.
+
+use core::{Binder, Name, Term};
+use super::super::ast::{HasSpan, Span};
+use super::{Error, TyCtxt};
+
+impl TyCtxt {
+    /// Check that the body of the definition `name` only uses its erased
+    /// variables in erased positions.
+    pub fn check_relevance(&self, name: &Name, body: &Term) -> Result<(), Error> {
+        let mut checker = RelevanceChecker {
+            ty_cx: self,
+            definition: name,
+            binders: vec![],
+        };
+
+        checker.check(body, name.get_span())
+    }
+}
+
+struct RelevanceChecker<'tcx> {
+    ty_cx: &'tcx TyCtxt,
+    definition: &'tcx Name,
+    /// The binders in scope, innermost last, de Bruijn indices count back
+    /// from the end.
+    binders: Vec<Binder>,
+}
+
+impl<'tcx> RelevanceChecker<'tcx> {
+    fn bound(&self, index: usize) -> Option<&Binder> {
+        if index < self.binders.len() {
+            Some(&self.binders[self.binders.len() - 1 - index])
+        } else {
+            None
+        }
+    }
+
+    fn is_erased(&self, name: &Name) -> bool {
+        match name {
+            &Name::DeBruijn { index, .. } =>
+                self.bound(index).map(|b| b.is_irrelevant()).unwrap_or(false),
+            n => n.is_irrelevant(),
+        }
+    }
+
+    /// Whether each argument `head` takes is erased, as far as its type
+    /// says. The backend drops the same arguments.
+    fn erased_arguments(&self, head: &Term) -> Vec<bool> {
+        let mut ty = match head {
+            &Term::Var { name: Name::DeBruijn { index, .. } } => match self.bound(index) {
+                Some(binder) => (*binder.ty).clone(),
+                None => return vec![],
+            },
+            &Term::Var { name: Name::Local { ref ty, .. } } => (**ty).clone(),
+            &Term::Var { ref name } => match self.ty_cx.lookup_global(name) {
+                Ok(ty) => ty.clone(),
+                Err(_) => return vec![],
+            },
+            _ => return vec![],
+        };
+
+        let mut erased = vec![];
+
+        while let Term::Forall { binder, term, .. } = ty {
+            erased.push(binder.is_irrelevant());
+            ty = *term;
+        }
+
+        erased
+    }
+
+    fn under<F>(&mut self, binder: &Binder, f: F) -> Result<(), Error>
+        where F: FnOnce(&mut RelevanceChecker<'tcx>) -> Result<(), Error>
+    {
+        self.binders.push(binder.clone());
+        let result = f(self);
+        self.binders.pop();
+        result
+    }
+
+    /// Check `term`, whose value is needed at runtime, `span` is the
+    /// nearest location an error can be reported at.
+    fn check(&mut self, term: &Term, span: Span) -> Result<(), Error> {
+        match term {
+            &Term::Var { ref name } => {
+                if self.is_erased(name) {
+                    let repr = match name {
+                        &Name::DeBruijn { ref repr, .. } |
+                        &Name::Local { ref repr, .. } => repr.clone(),
+                        n => format!("{}", n),
+                    };
+
+                    Err(Error::ErasedUse {
+                        span: span,
+                        name: repr,
+                        definition: self.definition.clone(),
+                    })
+                } else {
+                    Ok(())
+                }
+            }
+            &Term::App { span: app_span, .. } => {
+                let span = if app_span == Span::dummy() { span } else { app_span };
+
+                let mut head = term;
+                let mut args = vec![];
+
+                while let &Term::App { ref fun, ref arg, .. } = head {
+                    args.push(&**arg);
+                    head = &**fun;
+                }
+
+                args.reverse();
+
+                let erased = self.erased_arguments(head);
+                try!(self.check(head, span));
+
+                for (i, arg) in args.into_iter().enumerate() {
+                    if !erased.get(i).cloned().unwrap_or(false) {
+                        try!(self.check(arg, span));
+                    }
+                }
+
+                Ok(())
+            }
+            &Term::Lambda { ref binder, ref body, .. } |
+            &Term::Fix { ref binder, ref body, .. } =>
+                self.under(binder, |checker| checker.check(body, span)),
+            &Term::Let { ref binder, ref value, ref body, .. } => {
+                try!(self.check(value, span));
+                self.under(binder, |checker| checker.check(body, span))
+            }
+            // Types are erased.
+            &Term::Forall { .. } |
+            &Term::Type => Ok(()),
+        }
+    }
+}
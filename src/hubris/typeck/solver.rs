@@ -1,29 +1,54 @@
-use hubris_syntax::ast::HasSpan;
+use hubris_syntax::ast::{HasSpan, Span};
 use super::TyCtxt;
 use super::constraint::*;
+use super::diff;
+use super::super::diagnostic::Diagnostic;
 use super::super::session::{HasSession, Session, Reportable};
-use core::{Term, Binder, Name};
+use core::{Term, Binder, BindingMode, DeltaReduction, Name};
 use util::*;
 
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, BTreeMap, BTreeSet};
 use std::io;
+use std::mem;
 use std::rc::Rc;
 
+/// Instance arguments of instances are searched for recursively, up to
+/// this depth.
+const MAX_INSTANCE_DEPTH: usize = 32;
+
+/// The state of the solver before a choice was made, the alternative is
+/// tried from it if the choice leads to a failure.
 pub struct Choice {
     constraints: BinaryHeap<CategorizedConstraint>,
-    constraint_mapping: HashMap<Name, Vec<CategorizedConstraint>>,
-    solution_mapping: HashMap<Name, (Term, Justification)>,
-    assumption_justification: Justification,
-    constraint_justification: Justification,
-    list: (),
+    constraint_mapping: BTreeMap<Name, Vec<CategorizedConstraint>>,
+    solution_mapping: BTreeMap<Name, (Term, Justification)>,
+    resolved: usize,
+    resumed: BTreeSet<Name>,
+    justification: Justification,
+    alternative: Alternative,
+}
+
+/// What is left to try at a choice point.
+pub enum Alternative {
+    /// The candidates left for an instance goal, the last one first.
+    Instances(InstanceGoal, Vec<Term>),
+    /// Unfold the head shared by both sides of a delta constraint, rather
+    /// than equating their arguments.
+    Unfold(Term, Term),
 }
 
 pub struct Solver<'tcx> {
     ty_cx: &'tcx mut TyCtxt,
     constraints: BinaryHeap<CategorizedConstraint>,
-    constraint_mapping: HashMap<Name, Vec<CategorizedConstraint>>,
-    pub solution_mapping: HashMap<Name, (Term, Justification)>,
+    constraint_mapping: BTreeMap<Name, Vec<CategorizedConstraint>>,
+    pub solution_mapping: BTreeMap<Name, (Term, Justification)>,
     choice_stack: Vec<Choice>,
+    /// The instances found by searching the global instances, along with
+    /// the type they were found for.
+    resolved: Vec<(Term, Term)>,
+    /// The delayed applications that have been checked, each named by the
+    /// metavariable for its result type.
+    resumed: BTreeSet<Name>,
 }
 
 #[derive(Debug)]
@@ -32,9 +57,62 @@ pub enum Error {
     Justification(Justification),
     TypeCk(Box<super::Error>),
     NoSolution(Vec<Name>, Term),
+    /// No instance of the type was found for the instance argument of the
+    /// application at the span.
+    NoInstance(Span, Term),
+    /// A failure involving an application of a global marked
+    /// `@[error_message]`, the message's template and the arguments of the
+    /// application, followed by the failure it replaces.
+    Custom(Span, String, Vec<Term>, Box<Error>),
     Many(Vec<Error>),
 }
 
+/// Fill in the `{0}`, `{1}`, ... of an `@[error_message]` template with
+/// `args`, a placeholder without an argument is left as written.
+fn fill_template(template: &str, args: &[String]) -> String {
+    let mut message = String::new();
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        message.push_str(&rest[..open]);
+        rest = &rest[open..];
+
+        let arg = rest.find('}').and_then(|close| {
+            rest[1..close].parse::<usize>().ok()
+                .and_then(|i| args.get(i))
+                .map(|arg| (arg, close))
+        });
+
+        match arg {
+            Some((arg, close)) => {
+                message.push_str(arg);
+                rest = &rest[close + 1..];
+            }
+            None => {
+                message.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    message.push_str(rest);
+    message
+}
+
+/// How the failure replaced by a custom message is described in a note.
+fn describe_failure(err: &Error, cx: &Session) -> Option<String> {
+    match err {
+        &Error::NoInstance(_, ref ty) =>
+            Some(format!("could not find an instance of `{}`", cx.render(ty))),
+        &Error::Justification(Justification::Asserted(AssertedBy::ExpectedFound(_, ref found, ref expected))) =>
+            Some(format!("expected type `{}` found `{}`", cx.render(expected), cx.render(found))),
+        &Error::Justification(Justification::Asserted(AssertedBy::Application(_, ref u, ref t))) =>
+            Some(format!("a term with type `{}` can not be applied to an argument with type `{}`",
+                         cx.render(u), cx.render(t))),
+        _ => None,
+    }
+}
+
 impl From<super::Error> for Error {
     fn from(err: super::Error) -> Error {
         Error::TypeCk(Box::new(err))
@@ -46,20 +124,90 @@ impl Reportable for Error {
         match self {
             Error::Justification(j) => match j {
                 Justification::Asserted(by) => match by {
-                    AssertedBy::Application(span, u, t) =>
-                        cx.span_error(span,
+                    AssertedBy::Application(span, u, t) => {
+                        let diagnostic = Diagnostic::error(
                             format!("a term with type `{}` can not be applied to an argument with \
-                                     type `{}`", u, t)),
-                    AssertedBy::ExpectedFound(infer_ty, ty) =>
-                        cx.span_error(ty.get_span(),
-                            format!("expected type `{}` found `{}`", ty, infer_ty)),
+                                     type `{}`", cx.render(&u), cx.render(&t)))
+                            .span(span);
+
+                        cx.explain_metas(diagnostic, &[&u, &t]).emit(cx)
+                    }
+                    AssertedBy::ExpectedFound(span, infer_ty, ty) => {
+                        let diff = diff::diff(&ty, &infer_ty);
+
+                        let mut diagnostic =
+                            Diagnostic::error(
+                                format!("expected type `{}` found `{}`",
+                                        cx.render(&diff.expected), cx.render(&diff.found)));
+
+                        // Fall back to pointing at the annotation when the
+                        // term has no location of its own.
+                        diagnostic = if span == Span::dummy() {
+                            diagnostic.span(ty.get_span())
+                        } else {
+                            diagnostic.span(span)
+                                      .label(ty.get_span(), "expected because of this annotation")
+                        };
+
+                        if diff.elided {
+                            diagnostic = diagnostic.note("parts of the types that agree are shown as `_`");
+                        }
+
+                        cx.explain_metas(diagnostic, &[&diff.expected, &diff.found]).emit(cx)
+                    }
                 },
                 Justification::Assumption => cx.error("assumption".to_string()),
                 j @ Justification::Join(_, _) => panic!(), // cx.error(format!("{}", j)),
             },
             Error::NoSolution(ns, term) => {
-                // TODO: fix this
-                cx.error(format!("unable to find a solution for {} in {}", ns[0], term))
+                // A placeholder the user wrote is reported where it was
+                // written, other metavariables are an artifact of elaboration.
+                let placeholder = ns.iter().filter_map(|n| match n {
+                    &Name::Meta { number, .. } => cx.placeholder_span(number),
+                    _ => None,
+                }).next();
+
+                match placeholder {
+                    Some(span) =>
+                        Diagnostic::error("unable to infer the value of this placeholder".to_string())
+                            .span(span)
+                            .note(format!("in `{}`", cx.render(&term)))
+                            .emit(cx),
+                    None => {
+                        let origin = match &ns[0] {
+                            &Name::Meta { number, .. } => cx.meta_origin(number),
+                            _ => None,
+                        };
+
+                        match origin {
+                            Some(origin) => {
+                                let message = format!("unable to infer the {}", origin.description);
+                                Diagnostic::error(message)
+                                    .span(origin.span)
+                                    .note(format!("in `{}`", cx.render(&term)))
+                                    .emit(cx)
+                            }
+                            None => cx.error(format!("unable to find a solution for {} in {}",
+                                                     ns[0], cx.render(&term))),
+                        }
+                    }
+                }
+            }
+            Error::NoInstance(span, ty) =>
+                Diagnostic::error(format!("could not find an instance of `{}`", cx.render(&ty)))
+                    .span(span)
+                    .help("instances are declared with `@[instance]`")
+                    .emit(cx),
+            Error::Custom(span, template, args, cause) => {
+                let args: Vec<_> = args.iter().map(|arg| cx.render(arg)).collect();
+                let mut diagnostic = Diagnostic::error(fill_template(&template, &args))
+                    .span(span);
+
+                if let Some(failure) = describe_failure(&cause, cx) {
+                    diagnostic = diagnostic.note(failure);
+                }
+
+                diagnostic.emit(cx)
             }
             Error::Many(errs) => {
                 for err in errs {
@@ -78,9 +226,11 @@ impl<'tcx> Solver<'tcx> {
         Solver {
             ty_cx: ty_cx,
             constraints: BinaryHeap::new(),
-            constraint_mapping: HashMap::new(),
-            solution_mapping: HashMap::new(),
+            constraint_mapping: BTreeMap::new(),
+            solution_mapping: BTreeMap::new(),
             choice_stack: vec![],
+            resolved: vec![],
+            resumed: BTreeSet::new(),
         }
     }
 
@@ -98,7 +248,9 @@ impl<'tcx> Solver<'tcx> {
                         try!(solver.visit(sc));
                     }
                 },
-                &Constraint::Choice(..) => {
+                &Constraint::Choice(..) |
+                &Constraint::Application(..) |
+                &Constraint::Instance(..) => {
                     try!(solver.visit(c.clone().categorize()))
                 }
             }
@@ -113,10 +265,95 @@ impl<'tcx> Solver<'tcx> {
         } = c;
 
         match constraint {
-            Constraint::Unification(t, u, j) =>
-                self.visit_unification(t, u, j, category),
+            // The choice between the first-order approximation and unfolding
+            // is made once the constraint is taken off the queue.
+            Constraint::Unification(t, u, j) => if category == ConstraintCategory::Delta {
+                self.constraints.push(CategorizedConstraint {
+                    category: category,
+                    constraint: Constraint::Unification(t, u, j),
+                });
+
+                Ok(())
+            } else {
+                self.visit_unification(t, u, j, category)
+            },
             Constraint::Choice(..) =>
                 panic!("choice constraints aren't impl"),
+            Constraint::Instance(goal, j) => {
+                self.constraints.push(CategorizedConstraint {
+                    category: category,
+                    constraint: Constraint::Instance(goal, j),
+                });
+
+                Ok(())
+            }
+            Constraint::Application(app, j) => {
+                match try!(self.resume_application(app.clone(), j.clone())) {
+                    None => Ok(()),
+                    // Wait for the metavariable, solving it revisits the
+                    // constraint.
+                    Some(meta) => {
+                        let cat_constraint = CategorizedConstraint {
+                            category: category,
+                            constraint: Constraint::Application(app, j),
+                        };
+
+                        self.constraint_mapping
+                            .entry(meta)
+                            .or_insert(vec![])
+                            .push(cat_constraint.clone());
+
+                        self.constraints.push(cat_constraint);
+
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    /// Check the argument of a delayed application if the type of its
+    /// function is now known, otherwise return the metavariable it is still
+    /// waiting on.
+    fn resume_application(&mut self, app: DelayedApplication, j: Justification)
+                          -> Result<Option<Name>, Error> {
+        let fun_ty = replace_metavars_with_err(
+            app.fun_ty, &self.solution_mapping, &mut vec![]);
+
+        let fun_ty = try!(self.ty_cx.whnf(&fun_ty)).0;
+
+        match fun_ty {
+            Term::Forall { binder, term, .. } => {
+                // An application waiting on more than one metavariable is
+                // revisited as each is solved, but only checked once.
+                if let Some(id) = app.result_ty.is_stuck() {
+                    if !self.resumed.insert(id) {
+                        return Ok(None);
+                    }
+                }
+
+                let mut cs = try!(self.simplify(app.arg_ty, *binder.ty, j.clone()));
+                cs.extend(try!(self.simplify(app.result_ty, term.instantiate(&app.arg), j)));
+
+                for c in cs {
+                    try!(self.visit(c));
+                }
+
+                Ok(None)
+            }
+            t => match t.is_stuck() {
+                Some(meta) => Ok(Some(meta)),
+                // The function was already applied to all the arguments it
+                // takes, which only shows once its type is known.
+                None if app.fun.is_app() => {
+                    let err = self.ty_cx.arity_mismatch(&app.fun, &app.arg);
+                    Err(Error::TypeCk(Box::new(err)))
+                }
+                None => {
+                    let j = try!(self.eval_justification(j));
+                    Err(self.with_custom_message(Error::Justification(j)))
+                }
+            }
         }
     }
 
@@ -216,13 +453,15 @@ impl<'tcx> Solver<'tcx> {
 
             self.solution_mapping.insert(meta.clone(), (solution, j));
 
-            let cs = match self.constraint_mapping.get(&meta) {
+            // The constraints still waiting after being revisited are added
+            // back under the metavariable they are now waiting on.
+            let cs = match self.constraint_mapping.remove(&meta) {
                 None => vec![],
-                Some(cs) => cs.clone(),
+                Some(cs) => cs,
             };
 
             for c in cs {
-                try!(self.visit(c.clone()));
+                try!(self.visit(c));
             }
 
             Ok(())
@@ -259,7 +498,16 @@ impl<'tcx> Solver<'tcx> {
             return Ok(vec![]);
         }
 
-        // Case 2: if t can beta/iota reduce to then
+        // Case 2: a `let` is definitionally equal to its body with the
+        // value substituted in, so we zeta reduce before comparing.
+        else if t.is_let() || u.is_let() {
+            debug!("simplify: zeta case");
+            let t = t.zeta().unwrap_or(t);
+            let u = u.zeta().unwrap_or(u);
+            self.simplify(t, u, j)
+        }
+
+        // Case 3: if t can beta/iota reduce to then
         // we reduce t ==> t' and create a constraint
         // between t' and u (t' = u).
         else if self.ty_cx.is_bi_reducible(&t) &&
@@ -275,7 +523,7 @@ impl<'tcx> Solver<'tcx> {
             self.simplify(t, try!(self.ty_cx.eval(&u)), j)
         }
 
-        // Case 3: if the head of t and u are constants
+        // Case 4: if the head of t and u are constants
         // we should generate constraints between each of their
         // arguments for example l s_1 .. s_n = l t_1 .. t_n
         // creates (s_1 = t_1, j) ... (s_n = t_n, j).
@@ -299,37 +547,43 @@ impl<'tcx> Solver<'tcx> {
             debug!("head is global");
 
             let (f, f_args) = t.uncurry();
-            let (g, g_args) = u.uncurry();
+            let (_, g_args) = u.uncurry();
 
+            // The same global on its own is equal to itself.
             if f_args.len() == 0 && g_args.len() == 0 {
-                panic!()
-            }
-
-            // THERE IS A BUG HERE!
-            let t_args_meta_free =
-                f_args.iter().all(|a| !a.is_meta());
-
-            let u_args_meta_free =
-                g_args.iter().all(|a| !a.is_meta());
-
-            if (self.ty_cx.is_bi_reducible(&t) ||
-                self.ty_cx.is_bi_reducible(&u))  &&
-               t_args_meta_free &&
-               u_args_meta_free {
-                panic!("var are free")
-                    //      self.simplify(t.unfold(f) = u.unfold(f))
-                    // } else if !f.reducible() {
-                    //     t.args = u.args
-                    // } else { panic!() }
-            } else if !self.ty_cx.is_bi_reducible(&f) && f == g {
-                let mut cs = vec![];
-                for (t_i, s_i) in f_args.into_iter().zip(g_args.into_iter()) {
-                    debug!("arg_equal {} {}", t_i, s_i);
-                    cs.extend(try!(self.simplify(t_i, s_i, j.clone())).into_iter());
+                return Ok(vec![]);
+            }
+
+            let body = match f {
+                Term::Var { ref name } => self.unfoldable(name),
+                _ => None,
+            };
+
+            match body {
+                // An opaque head is only equal to itself, so the arguments
+                // must be equal.
+                None => self.simplify_args(f_args, g_args, j),
+                // The arguments being equal is sufficient but not necessary,
+                // if simplifying them fails right away we unfold the
+                // definition. Otherwise the choice between this first-order
+                // approximation and unfolding is left to the solver, which
+                // can come back to unfold if the approximation leads to a
+                // failure later on.
+                Some(body) => {
+                    match self.simplify_args(f_args.clone(), g_args.clone(), j.clone()) {
+                        Ok(ref cs) if cs.is_empty() => Ok(vec![]),
+                        Ok(_) => Ok(vec![CategorizedConstraint {
+                            category: ConstraintCategory::Delta,
+                            constraint: Constraint::Unification(t, u, j),
+                        }]),
+                        Err(err) => {
+                            debug!("simplify: first-order approximation failed: {:?}", err);
+                            let t = try!(self.ty_cx.eval(&Term::apply_all(body.clone(), f_args)));
+                            let u = try!(self.ty_cx.eval(&Term::apply_all(body, g_args)));
+                            self.simplify(t, u, j)
+                        }
+                    }
                 }
-                Ok(cs)
-            } else {
-                panic!("f is reducible but metavars are ")
             }
         }
 
@@ -368,14 +622,127 @@ impl<'tcx> Solver<'tcx> {
             if t.is_stuck().is_some() ||
                u.is_stuck().is_some() {
                 Ok(vec![Constraint::Unification(t, u, j).categorize()])
+            } else if let Some(cs) = try!(self.apply_unify_hints(&t, &u, &j)) {
+                debug!("simplify: hint case");
+                Ok(cs)
+            } else if let Some(t) = try!(self.unfold_head(&t)) {
+                debug!("simplify: delta case (t)");
+                self.simplify(t, u, j)
+            } else if let Some(u) = try!(self.unfold_head(&u)) {
+                debug!("simplify: delta case (u)");
+                self.simplify(t, u, j)
             } else {
                 let j = try!(self.eval_justification(j));
-                panic!("{} {}", t, u);
-                Err(Error::Justification(j))
+                Err(self.with_custom_message(Error::Justification(j)))
             }
         }
     }
 
+    /// Simplify the constraints that the arguments of two applications of
+    /// the same head are pairwise equal.
+    fn simplify_args(&self, t_args: Vec<Term>, u_args: Vec<Term>, j: Justification)
+                     -> Result<Vec<CategorizedConstraint>, Error> {
+        let mut cs = vec![];
+        for (t_i, s_i) in t_args.into_iter().zip(u_args.into_iter()) {
+            debug!("arg_equal {} {}", t_i, s_i);
+            cs.extend(try!(self.simplify(t_i, s_i, j.clone())).into_iter());
+        }
+        Ok(cs)
+    }
+
+    /// The body of the definition `name` if unification may unfold it.
+    fn unfoldable(&self, name: &Name) -> Option<Term> {
+        self.ty_cx.definitions.get(name).and_then(|def| {
+            if def.reduction == DeltaReduction::Irreducible {
+                None
+            } else {
+                Some(def.body.clone())
+            }
+        })
+    }
+
+    /// Solve `t = u` using a unification hint, when one side is a projection
+    /// whose last argument is an unsolved metavariable and a hint names the
+    /// projection and the head of the other side.
+    ///
+    /// The instance arguments in scope where the metavariable was created
+    /// are tried first, innermost first, then the hints in the order they
+    /// were declared. The first instance that projects to the other side is
+    /// used.
+    fn apply_unify_hints(&self, t: &Term, u: &Term, j: &Justification)
+                         -> Result<Option<Vec<CategorizedConstraint>>, Error> {
+        for &(lhs, rhs) in &[(t, u), (u, t)] {
+            let (projection, args) = lhs.uncurry();
+            let head = rhs.uncurry().0;
+
+            let (projection, head) = match (projection, head) {
+                (Term::Var { name: projection }, Term::Var { name: head }) => (projection, head),
+                _ => continue,
+            };
+
+            let structure = match args.last() {
+                Some(arg) if arg.is_stuck().is_some() => arg.clone(),
+                _ => continue,
+            };
+
+            let locals = local_context(&structure).into_iter().rev().filter(|local| {
+                match local {
+                    &Name::Local { binding_info: BindingMode::Instance, .. } => true,
+                    _ => false,
+                }
+            });
+
+            let hints = self.ty_cx.unify_hints.iter().filter(|hint| {
+                hint.projection == projection && hint.head == head
+            }).map(|hint| hint.instance.clone());
+
+            for instance_name in locals.chain(hints) {
+                let instance = instance_name.to_term();
+                let mut instance_args = args.clone();
+                instance_args.pop();
+                instance_args.push(instance.clone());
+
+                let projected = try!(self.ty_cx.eval(
+                    &Term::apply_all(projection.to_term(), instance_args)));
+
+                // A local instance is only used when it projects to a term
+                // with the same head, there is no hint declaring it does.
+                if instance_name.is_local() && projected.uncurry().0 != rhs.uncurry().0 {
+                    continue;
+                }
+
+                let attempt = self.simplify(projected, rhs.clone(), j.clone()).and_then(|mut cs| {
+                    cs.extend(try!(self.simplify(structure.clone(), instance, j.clone())));
+                    Ok(cs)
+                });
+
+                match attempt {
+                    Ok(cs) => return Ok(Some(cs)),
+                    Err(err) => debug!("apply_unify_hints: {} does not apply: {:?}",
+                                       instance_name, err),
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Unfold the definition at the head of `t` and reduce the result, if
+    /// the head is a definition unification may unfold.
+    fn unfold_head(&self, t: &Term) -> Result<Option<Term>, Error> {
+        let (head, args) = t.uncurry();
+
+        let body = match head {
+            Term::Var { ref name } => self.unfoldable(name),
+            _ => None,
+        };
+
+        match body {
+            None => Ok(None),
+            Some(body) => Ok(Some(try!(self.ty_cx.eval(&Term::apply_all(body, args))))),
+        }
+    }
+
     /// Will take the justification that was created at constraint generation time, and substitute
     /// all known meta-variable solutions and then simplify it. This is particularly useful in
     /// error reporting where we want to show the simplest term possible.
@@ -392,11 +759,11 @@ impl<'tcx> Solver<'tcx> {
 
                     AssertedBy::Application(span, t, u)
                 }
-                AssertedBy::ExpectedFound(t, u) => {
+                AssertedBy::ExpectedFound(span, t, u) => {
                     let t = try!(self.ty_cx.eval(&replace_metavars_with_err(t, &self.solution_mapping, &mut errs)));
                     let u = try!(self.ty_cx.eval(&replace_metavars_with_err(u, &self.solution_mapping, &mut errs)));
 
-                    AssertedBy::ExpectedFound(t, u)
+                    AssertedBy::ExpectedFound(span, t, u)
                 }
             }),
             Assumption => Assumption,
@@ -417,26 +784,60 @@ impl<'tcx> Solver<'tcx> {
         }
     }
 
-    // /// Create a case split (choice point) by storing the current solver state with
-    // /// the arguments provided.
-    // fn case_split(&mut self, j_a: Justification, j_c: Justification, list: () ) {
-    //
-    // }
-    pub fn solve(mut self) -> Result<HashMap<Name, (Term, Justification)>, Error> {
+    pub fn solve(mut self) -> Result<BTreeMap<Name, (Term, Justification)>, Error> {
+        let mut result = self.solve_constraints();
+
+        // A failure after a choice was made may be caused by the choice, so
+        // we go back to the most recent one and try its alternative.
+        while let Err(err) = result {
+            let choice = match self.choice_stack.pop() {
+                None => return Err(err),
+                Some(choice) => choice,
+            };
+
+            debug!("Solver::solve: backtracking after {:?}", err);
+
+            let (j, alternative) = self.restore(choice);
+            let chosen = match alternative {
+                Alternative::Instances(goal, candidates) =>
+                    self.choose_instance(goal, j, candidates),
+                Alternative::Unfold(t, u) =>
+                    self.unfold_delta(t, u, j),
+            };
+
+            result = match chosen {
+                Ok(()) => self.solve_constraints(),
+                Err(err) => Err(err),
+            };
+        }
+
+        self.cache_instances();
+
+        Ok(self.solution_mapping)
+    }
+
+    fn solve_constraints(&mut self) -> Result<(), Error> {
         while let Some(c) = self.constraints.pop() {
             debug!("Solver::solve: constraint={}", c.constraint);
             match c.constraint {
                 Constraint::Choice(term, ty, f, j) =>
                     // self.process(f(term, ty, subst), j)
                     panic!(),
+                // Every other constraint has been processed, if the function
+                // type is still unknown it never will be.
+                Constraint::Application(app, j) => {
+                    let fun_ty = app.fun_ty.clone();
+                    if let Some(meta) = try!(self.resume_application(app, j)) {
+                        return Err(Error::NoSolution(vec![meta], fun_ty));
+                    }
+                }
+                Constraint::Instance(goal, j) => try!(self.solve_instance(goal, j)),
                 Constraint::Unification(t, u, j) => {
                     for (m, s) in &self.solution_mapping {
                         debug!("{} {}", m, s.0)
                     }
                     match c.category {
-                        ConstraintCategory::Delta => {
-                            panic!("can't handle delta constraints yet")
-                        }
+                        ConstraintCategory::Delta => try!(self.solve_delta(t, u, j)),
                         ConstraintCategory::QuasiPattern |
                         ConstraintCategory::FlexRigid => {
                             let (t_head, t_args) = t.uncurry();
@@ -512,7 +913,277 @@ impl<'tcx> Solver<'tcx> {
                 }
             }
         }
-        Ok(self.solution_mapping)
+        Ok(())
+    }
+
+    /// Solve `t = u` where both sides apply the same definition, by equating
+    /// the arguments first and saving the choice to unfold the definition
+    /// instead.
+    fn solve_delta(&mut self, t: Term, u: Term, j: Justification) -> Result<(), Error> {
+        let choice = self.choice_point(j.clone(), Alternative::Unfold(t.clone(), u.clone()));
+
+        let (_, t_args) = t.uncurry();
+        let (_, u_args) = u.uncurry();
+
+        let approximated = match self.simplify_args(t_args, u_args, j.clone()) {
+            Ok(cs) => cs.into_iter().map(|c| self.visit(c)).collect(),
+            Err(err) => Err(err),
+        };
+
+        match approximated {
+            Ok(()) => {
+                self.choice_stack.push(choice);
+                Ok(())
+            }
+            Err(err) => {
+                debug!("solve_delta: first-order approximation failed: {:?}", err);
+                self.restore(choice);
+                self.unfold_delta(t, u, j)
+            }
+        }
+    }
+
+    /// Unfold the definition at the head of both sides of `t = u`.
+    fn unfold_delta(&mut self, t: Term, u: Term, j: Justification) -> Result<(), Error> {
+        let t = try!(self.unfold_head(&t)).unwrap_or(t);
+        let u = try!(self.unfold_head(&u)).unwrap_or(u);
+
+        for c in try!(self.simplify(t, u, j)) {
+            try!(self.visit(c));
+        }
+
+        Ok(())
+    }
+
+    /// Find an instance for `goal`, unless unification already found the
+    /// argument.
+    fn solve_instance(&mut self, goal: InstanceGoal, j: Justification) -> Result<(), Error> {
+        match goal.meta.is_stuck() {
+            Some(ref meta) if self.solution_for(meta).is_none() => {}
+            _ => return Ok(()),
+        }
+
+        let ty = replace_metavars_with_err(goal.ty.clone(), &self.solution_mapping, &mut vec![]);
+        let ty = try!(self.ty_cx.eval(&ty));
+
+        if let Some(instance) = self.ty_cx.instance_cache.get(&ty).cloned() {
+            debug!("solve_instance: cached {} for {}", instance, ty);
+            for c in try!(self.simplify(goal.meta.clone(), instance, j)) {
+                try!(self.visit(c));
+            }
+
+            return Ok(());
+        }
+
+        let candidates = self.instance_candidates(&goal.context, &ty);
+
+        let goal = InstanceGoal {
+            ty: ty,
+            .. goal
+        };
+
+        self.choose_instance(goal, j, candidates)
+    }
+
+    /// The terms that may be an instance of `ty`, the last one is tried
+    /// first.
+    ///
+    /// The locals of `context` whose type is of the class of `ty` take
+    /// priority over global instances, instance arguments over other
+    /// hypotheses, and among each of these later declarations take priority
+    /// over earlier ones.
+    fn instance_candidates(&self, context: &[Name], ty: &Term) -> Vec<Term> {
+        let class = ty.uncurry().0;
+
+        let mut candidates: Vec<_> =
+            self.ty_cx.instances
+                .iter()
+                .filter(|instance| match self.ty_cx.lookup_global(instance) {
+                    Ok(instance_ty) => conclusion(instance_ty).uncurry().0 == class,
+                    Err(_) => false,
+                })
+                .map(Name::to_term)
+                .collect();
+
+        let mut instances = vec![];
+
+        for local in context {
+            if let &Name::Local { binding_info, ref ty, .. } = local {
+                if ty.uncurry().0 != class {
+                    continue;
+                }
+
+                if binding_info == BindingMode::Instance {
+                    instances.push(local.to_term());
+                } else {
+                    candidates.push(local.to_term());
+                }
+            }
+        }
+
+        candidates.extend(instances);
+        candidates
+    }
+
+    /// Commit to the first of the `candidates` that is an instance of the
+    /// goal's type, saving the rest on the choice stack.
+    fn choose_instance(&mut self,
+                       goal: InstanceGoal,
+                       j: Justification,
+                       mut candidates: Vec<Term>) -> Result<(), Error> {
+        if goal.depth > MAX_INSTANCE_DEPTH {
+            return Err(self.with_custom_message(Error::NoInstance(goal.span, goal.ty)));
+        }
+
+        while let Some(candidate) = candidates.pop() {
+            let mut choice = self.choice_point(j.clone(), Alternative::Instances(goal.clone(), vec![]));
+            let applied = self.apply_instance(&goal, candidate, j.clone());
+
+            match applied {
+                Ok(()) => {
+                    if !candidates.is_empty() {
+                        choice.alternative = Alternative::Instances(goal, candidates);
+                        self.choice_stack.push(choice);
+                    }
+
+                    return Ok(());
+                }
+                Err(err) => {
+                    debug!("choose_instance: candidate rejected: {:?}", err);
+                    self.restore(choice);
+                }
+            }
+        }
+
+        Err(self.with_custom_message(Error::NoInstance(goal.span, goal.ty)))
+    }
+
+    /// Replace the message of `err` with the `@[error_message]` of the
+    /// head of a type it involves, the expected type is tried first.
+    fn with_custom_message(&self, err: Error) -> Error {
+        let (span, tys) = match &err {
+            &Error::NoInstance(span, ref ty) => (span, vec![ty.clone()]),
+            &Error::Justification(Justification::Asserted(AssertedBy::ExpectedFound(span, ref found, ref expected))) => {
+                let span = if span == Span::dummy() { expected.get_span() } else { span };
+                (span, vec![expected.clone(), found.clone()])
+            }
+            &Error::Justification(Justification::Asserted(AssertedBy::Application(span, ref u, ref t))) =>
+                (span, vec![u.clone(), t.clone()]),
+            _ => (Span::dummy(), vec![]),
+        };
+
+        let custom = tys.iter().filter_map(|ty| {
+            let (head, args) = ty.uncurry();
+            match head {
+                Term::Var { ref name } => self.ty_cx.error_messages.get(name)
+                    .map(|template| (template.clone(), args.clone())),
+                _ => None,
+            }
+        }).next();
+
+        match custom {
+            Some((template, args)) => Error::Custom(span, template, args, Box::new(err)),
+            None => err,
+        }
+    }
+
+    /// Solve `goal` with `candidate` applied to metavariables for each of
+    /// its arguments, its instance arguments become new goals.
+    fn apply_instance(&mut self, goal: &InstanceGoal, candidate: Term, j: Justification)
+                      -> Result<(), Error> {
+        let mut ty = try!(self.ty_cx.type_infer_term(&candidate)).0;
+        let mut instance = candidate.clone();
+        let mut subgoals = vec![];
+
+        while let Term::Forall { binder, term, .. } = ty {
+            let arg = self.ty_cx.fresh_meta(
+                (*binder.ty).clone(),
+                goal.span,
+                format!("argument `{}` of the instance `{}`", binder.name, candidate));
+
+            if binder.is_instance() {
+                subgoals.push(InstanceGoal {
+                    span: goal.span,
+                    meta: arg.clone(),
+                    ty: *binder.ty,
+                    depth: goal.depth + 1,
+                    context: goal.context.clone(),
+                });
+            }
+
+            ty = term.instantiate(&arg);
+            instance = Term::apply(instance, arg);
+        }
+
+        let mut cs = try!(self.simplify(ty, goal.ty.clone(), j.clone()));
+        cs.extend(try!(self.simplify(goal.meta.clone(), instance.clone(), j.clone())));
+
+        for c in cs {
+            try!(self.visit(c));
+        }
+
+        // Only global instances are valid outside of this term.
+        if let Term::Var { name: Name::Qual { .. } } = candidate {
+            self.resolved.push((goal.ty.clone(), instance));
+        }
+
+        for subgoal in subgoals {
+            try!(self.visit(Constraint::Instance(subgoal, j.clone()).categorize()));
+        }
+
+        Ok(())
+    }
+
+    /// Save the current state of the solver in a choice, with the
+    /// alternative to try if it leads to a failure.
+    fn choice_point(&self, j: Justification, alternative: Alternative) -> Choice {
+        Choice {
+            constraints: self.constraints.clone(),
+            constraint_mapping: self.constraint_mapping.clone(),
+            solution_mapping: self.solution_mapping.clone(),
+            resolved: self.resolved.len(),
+            resumed: self.resumed.clone(),
+            justification: j,
+            alternative: alternative,
+        }
+    }
+
+    /// Return to the state saved in `choice`, giving back the alternative
+    /// left to try.
+    fn restore(&mut self, choice: Choice) -> (Justification, Alternative) {
+        let Choice {
+            constraints,
+            constraint_mapping,
+            solution_mapping,
+            resolved,
+            resumed,
+            justification,
+            alternative,
+        } = choice;
+
+        self.constraints = constraints;
+        self.constraint_mapping = constraint_mapping;
+        self.solution_mapping = solution_mapping;
+        self.resolved.truncate(resolved);
+        self.resumed = resumed;
+
+        (justification, alternative)
+    }
+
+    /// Remember the instances found for types without metavariables, so
+    /// later searches for the same type can reuse them.
+    fn cache_instances(&mut self) {
+        let resolved = mem::replace(&mut self.resolved, vec![]);
+
+        for (ty, instance) in resolved {
+            let ty = replace_metavars(ty, &self.solution_mapping).ok()
+                .and_then(|ty| self.ty_cx.eval(&ty).ok());
+            let instance = replace_metavars(instance, &self.solution_mapping).ok();
+
+            if let (Some(ty), Some(instance)) = (ty, instance) {
+                self.ty_cx.instance_cache.insert(ty, instance);
+            }
+        }
     }
 
     pub fn resolve(&self, just: Justification) -> Result<(), Error> {
@@ -520,9 +1191,21 @@ impl<'tcx> Solver<'tcx> {
     }
 }
 
+/// The type of a term of type `ty` once it is applied to all of its
+/// arguments.
+fn conclusion(ty: &Term) -> &Term {
+    let mut ty = ty;
+
+    while let &Term::Forall { ref term, .. } = ty {
+        ty = &**term;
+    }
+
+    ty
+}
+
 pub fn replace_metavars(
         term: Term,
-        subst_map: &HashMap<Name, (Term, Justification)>) -> Result<Term, Error> {
+        subst_map: &BTreeMap<Name, (Term, Justification)>) -> Result<Term, Error> {
     let mut errs = vec![];
     let term = replace_metavars_with_err(term, subst_map, &mut errs);
 
@@ -534,7 +1217,7 @@ pub fn replace_metavars(
 }
 
 pub fn replace_metavars_with_err(
-        t: Term, subst_map: &HashMap<Name, (Term, Justification)>,
+        t: Term, subst_map: &BTreeMap<Name, (Term, Justification)>,
         errs: &mut Vec<Name>) -> Term {
     use core::Term::*;
 
@@ -571,6 +1254,22 @@ pub fn replace_metavars_with_err(
             }
 
         }
+        Let { binder, value, body, span } => {
+            Let {
+                binder: subst_meta_binder(binder, subst_map, errs),
+                value: Box::new(replace_metavars_with_err(*value, subst_map, errs)),
+                body: Box::new(replace_metavars_with_err(*body, subst_map, errs)),
+                span: span,
+            }
+        }
+        Fix { binder, rec_arg, body, span } => {
+            Fix {
+                binder: subst_meta_binder(binder, subst_map, errs),
+                rec_arg: rec_arg,
+                body: Box::new(replace_metavars_with_err(*body, subst_map, errs)),
+                span: span,
+            }
+        }
         v @ Var { .. } => v,
         Type => Type,
     }
@@ -578,7 +1277,7 @@ pub fn replace_metavars_with_err(
 
 pub fn subst_meta_binder(
         mut b: Binder,
-        subst_map: &HashMap<Name, (Term, Justification)>,
+        subst_map: &BTreeMap<Name, (Term, Justification)>,
         errs: &mut Vec<Name>) -> Binder {
     b.ty = Box::new(replace_metavars_with_err(*b.ty, subst_map, errs));
     b
@@ -0,0 +1,192 @@
+//! Checking the invariants of elaborated modules.
+//!
+//! Later phases assume that the items of an elaborated module are closed
+//! terms without metavariables, that every global they refer to has been
+//! declared, that the recursors of its inductive types exist, and that its
+//! items carry the spans diagnostics point at. A module breaking one of
+//! these is a bug in the elaborator or type checker, which otherwise shows
+//! up as a panic far from its cause. With `--validate-core` each module is
+//! checked once it has been type checked.
+
+use core::{Binder, Item, Module, Name, Term};
+use super::super::ast::{HasSpan, Span};
+use super::{Error, TyCtxt};
+
+/// A broken invariant of an elaborated item.
+#[derive(Debug)]
+pub struct Violation {
+    /// The item the violation is in.
+    pub item: Name,
+    /// The nearest location to the violation, possibly a dummy span.
+    pub span: Span,
+    pub message: String,
+}
+
+impl TyCtxt {
+    /// Check the invariants of `module`, which must have been type checked
+    /// in this context.
+    pub fn validate_module(&self, module: &Module) -> Result<(), Error> {
+        let mut validator = Validator {
+            ty_cx: self,
+            item: None,
+            depth: 0,
+            violations: vec![],
+        };
+
+        for item in &module.defs {
+            validator.item(item);
+        }
+
+        if validator.violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Invalid(validator.violations))
+        }
+    }
+}
+
+struct Validator<'tcx> {
+    ty_cx: &'tcx TyCtxt,
+    /// The item being checked.
+    item: Option<&'tcx Name>,
+    /// The number of binders in scope.
+    depth: usize,
+    violations: Vec<Violation>,
+}
+
+impl<'tcx> Validator<'tcx> {
+    fn violation(&mut self, span: Span, message: String) {
+        let item = self.item.expect("a violation outside of an item").clone();
+
+        self.violations.push(Violation {
+            item: item,
+            span: span,
+            message: message,
+        });
+    }
+
+    fn item(&mut self, item: &'tcx Item) {
+        let name = item.name();
+        self.item = Some(name);
+
+        let span = match item {
+            &Item::Data(ref data) => data.span,
+            &Item::Axiom(ref axiom) => axiom.span,
+            &Item::Extern(ref ext) => ext.span,
+            // A definition is only located by its name.
+            &Item::Fn(_) => name.get_span(),
+        };
+
+        if span == Span::dummy() {
+            self.violation(span, format!("`{}` has no span", name));
+        }
+
+        if !self.ty_cx.in_scope(name) {
+            self.violation(span, format!("`{}` was not declared", name));
+        }
+
+        match item {
+            &Item::Data(ref data) => {
+                self.term(&data.ty, span);
+
+                let rec = data.name.in_scope("rec".to_string()).unwrap();
+                if !self.ty_cx.in_scope(&rec) {
+                    self.violation(span, format!("the recursor `{}` was not declared", rec));
+                }
+
+                for &(ref ctor, ref ty) in &data.ctors {
+                    if !self.ty_cx.in_scope(ctor) {
+                        self.violation(span, format!("the constructor `{}` was not declared", ctor));
+                    }
+                    self.term(ty, span);
+                }
+
+                if let Some(ref recursive) = data.recursive {
+                    self.term(&recursive.ty, span);
+                    for &(_, ref rhs) in &recursive.equations {
+                        self.term(rhs, span);
+                    }
+                }
+            }
+            &Item::Fn(ref def) => {
+                self.term(&def.ty, span);
+                self.term(&def.body, span);
+            }
+            &Item::Axiom(ref axiom) => self.term(&axiom.ty, span),
+            &Item::Extern(ref ext) => self.term(&ext.term, span),
+        }
+    }
+
+    /// Check `term`, `span` is the nearest location a violation can be
+    /// reported at.
+    fn term(&mut self, term: &Term, span: Span) {
+        match term {
+            &Term::Var { ref name } => self.name(name, span),
+            &Term::App { span: app_span, ref fun, ref arg } => {
+                let span = if app_span == Span::dummy() { span } else { app_span };
+                self.term(fun, span);
+                self.term(arg, span);
+            }
+            &Term::Forall { ref binder, ref term, .. } => self.under(binder, term, span),
+            &Term::Lambda { ref binder, ref body, .. } => self.under(binder, body, span),
+            &Term::Let { ref binder, ref value, ref body, .. } => {
+                self.term(value, span);
+                self.under(binder, body, span);
+            }
+            &Term::Fix { ref binder, rec_arg, ref body, .. } => {
+                let mut arity = 0;
+                let mut ty = &*binder.ty;
+                while let &Term::Forall { ref term, .. } = ty {
+                    arity += 1;
+                    ty = &**term;
+                }
+
+                if rec_arg >= arity {
+                    self.violation(span, format!(
+                        "`fix {}` recurses on argument {} but takes {}",
+                        binder.name, rec_arg, arity));
+                }
+
+                self.under(binder, body, span);
+            }
+            &Term::Type => {}
+        }
+    }
+
+    fn under(&mut self, binder: &Binder, body: &Term, span: Span) {
+        self.term(&binder.ty, span);
+        self.depth += 1;
+        self.term(body, span);
+        self.depth -= 1;
+    }
+
+    fn name(&mut self, name: &Name, span: Span) {
+        match name {
+            &Name::DeBruijn { index, .. } => if index >= self.depth {
+                self.violation(span, format!(
+                    "de Bruijn index {} is not bound, {} binders are in scope",
+                    index, self.depth));
+            },
+            &Name::Local { ref repr, number, .. } => {
+                self.violation(span, format!("the local `{}` (#{}) was not abstracted", repr, number));
+            }
+            &Name::Meta { number, .. } => {
+                self.violation(span, format!("the metavariable `?{}` was not solved", number));
+            }
+            &Name::Qual { ref components, .. } => if !self.ty_cx.in_scope(name) {
+                let is_recursor = components.len() > 1 &&
+                                  components.last().map_or(false, |c| c == "rec");
+
+                let message = if is_recursor {
+                    let owner = Name::qualified(components[..components.len() - 1].to_vec());
+                    format!("the recursor `{}` refers to `{}`, which is not an inductive type",
+                            name, owner)
+                } else {
+                    format!("the global `{}` was not declared", name)
+                };
+
+                self.violation(span, message);
+            },
+        }
+    }
+}
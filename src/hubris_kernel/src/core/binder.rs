@@ -6,7 +6,17 @@ use super::super::pretty::*;
 #[derive(Debug, Copy, Clone, PartialEq, Hash, Eq)]
 pub enum BindingMode {
     Implicit,
-    Explicit
+    Explicit,
+    /// Instance-implicit, the argument is found by instance search.
+    Instance,
+}
+
+/// Whether the value bound by a binder is needed at runtime, irrelevant
+/// arguments are erased before code generation.
+#[derive(Debug, Copy, Clone, PartialEq, Hash, Eq)]
+pub enum Relevance {
+    Relevant,
+    Irrelevant,
 }
 
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
@@ -14,6 +24,7 @@ pub struct Binder {
     pub name: Name,
     pub ty: Box<Term>,
     pub mode: BindingMode,
+    pub relevance: Relevance,
 }
 
 impl Binder {
@@ -22,6 +33,7 @@ impl Binder {
             name: name,
             ty: Box::new(ty),
             mode: BindingMode::Explicit,
+            relevance: Relevance::Relevant,
         }
 
     }
@@ -31,6 +43,7 @@ impl Binder {
             name: name,
             ty: Box::new(ty),
             mode: BindingMode::Implicit,
+            relevance: Relevance::Relevant,
         }
     }
 
@@ -40,6 +53,18 @@ impl Binder {
             name: name,
             ty: Box::new(ty),
             mode: mode,
+            relevance: Relevance::Relevant,
+        }
+    }
+
+    /// A binder with the same name, mode and relevance binding a value of
+    /// type `ty`.
+    pub fn with_type(&self, ty: Term) -> Binder {
+        Binder {
+            name: self.name.clone(),
+            ty: Box::new(ty),
+            mode: self.mode,
+            relevance: self.relevance,
         }
     }
 
@@ -48,6 +73,7 @@ impl Binder {
             name: self.name.clone(),
             ty: Box::new(self.ty.abst(index, x)),
             mode: self.mode.clone(),
+            relevance: self.relevance,
         }
     }
 
@@ -56,6 +82,7 @@ impl Binder {
             name: self.name.clone(),
             ty: Box::new(self.ty.replace(index, subst)),
             mode: self.mode.clone(),
+            relevance: self.relevance,
         }
     }
 
@@ -65,12 +92,24 @@ impl Binder {
             _ => false
         }
     }
+
+    pub fn is_instance(&self) -> bool {
+        self.mode == BindingMode::Instance
+    }
+
+    pub fn is_irrelevant(&self) -> bool {
+        self.relevance == Relevance::Irrelevant
+    }
 }
 
 impl Pretty for Binder {
     fn pretty(&self) -> Doc {
-        if self.is_implicit() {
+        // Irrelevant binders are marked with a leading `.`.
+        let mark = if self.is_irrelevant() { "." } else { "" };
+        mark.pretty() + if self.is_implicit() {
             braces(self.name.pretty() + " : ".pretty() + self.ty.pretty())
+        } else if self.is_instance() {
+            "[".pretty() + self.name.pretty() + " : ".pretty() + self.ty.pretty() + "]".pretty()
         } else {
             parens(self.name.pretty() + " : ".pretty() + self.ty.pretty())
         }
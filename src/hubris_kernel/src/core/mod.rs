@@ -0,0 +1,254 @@
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::hash::Hasher;
+
+use super::ast::Span;
+
+use super::pretty::*;
+
+pub mod binder;
+pub mod name;
+pub mod stats;
+pub mod term;
+// pub mod visit;
+// pub mod validate;
+pub use self::binder::*;
+pub use self::name::*;
+pub use self::term::*;
+
+#[derive(Debug)]
+pub struct Module {
+    // Eventually we should use ID's that map to files
+    pub file_name: PathBuf,
+    pub name: Name,
+    pub imports: Vec<Name>,
+    pub defs: Vec<Item>,
+}
+
+impl Module {
+    pub fn file_name(&self) -> &Path {
+        &self.file_name
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Data {
+    pub span: Span,
+    pub name: Name,
+    // Not sure if this is the best choice, should it be a binder?
+    // we try to strip as much high level structure as possible.
+    pub parameters: Vec<Name>,
+    pub ty: Term,
+    pub ctors: Vec<Constructor>,
+    /// A function defined simultaneously with the type (induction-recursion).
+    pub recursive: Option<RecursiveFn>,
+}
+
+pub type Constructor = (Name, Term);
+
+/// A function defined simultaneously with an inductive type, the
+/// constructors of the type may refer to it.
+///
+/// The function takes the parameters and indices of the type followed by a
+/// value of the type, and is defined by a single equation per constructor.
+#[derive(PartialEq, Clone, Debug)]
+pub struct RecursiveFn {
+    pub name: Name,
+    pub ty: Term,
+    /// The right hand side for each constructor, abstracted over all of the
+    /// constructor's arguments including the parameters of the type.
+    pub equations: Vec<(Name, Term)>,
+}
+
+impl RecursiveFn {
+    /// Unfold an application of the function to `args`, this succeeds only
+    /// when `args[major]`, the value of the inductive type, is a constructor
+    /// application.
+    pub fn unfold(&self, major: usize, args: &[Term]) -> Option<Term> {
+        if args.len() <= major {
+            return None;
+        }
+
+        let (ctor, ctor_args) = args[major].uncurry();
+
+        self.equations
+            .iter()
+            .find(|eq| eq.0.to_term() == ctor)
+            .map(|&(_, ref rhs)| {
+                let reduct = Term::apply_all(rhs.clone(), ctor_args);
+                Term::apply_all(reduct, args[major + 1..].to_vec())
+            })
+    }
+
+    /// Whether `fun` is only called on a field of the constructor in `rhs`,
+    /// the right hand side of its equation for a constructor with `params`
+    /// parameters and `fields` fields. A field applied to arguments counts
+    /// as well, as in `El (b x)` for a field `b : El a -> U`. `major` is the
+    /// position of the value of the inductive type among the arguments of
+    /// `fun`.
+    ///
+    /// Calling the function on anything else, such as the constructor
+    /// applied to its fields, would unfold forever.
+    pub fn is_structural(fun: &Name,
+                         major: usize,
+                         params: usize,
+                         fields: usize,
+                         rhs: &Term) -> bool {
+        let mut rhs = rhs;
+        let mut bound = 0;
+
+        while let &Term::Lambda { ref body, .. } = rhs {
+            if bound == params + fields {
+                break;
+            }
+
+            rhs = body;
+            bound += 1;
+        }
+
+        calls_on_fields(fun, major, bound.saturating_sub(params), 0, rhs)
+    }
+}
+
+/// Whether every call of `fun` in `term` is on one of the `fields`
+/// innermost variables bound outside of `term`, which is under `depth`
+/// further binders.
+fn calls_on_fields(fun: &Name, major: usize, fields: usize, depth: usize, term: &Term) -> bool {
+    let on_fields = |depth: usize, term: &Term| calls_on_fields(fun, major, fields, depth, term);
+
+    match term {
+        &Term::App { .. } => {
+            let (head, args) = term.uncurry();
+
+            let head_ok = match head {
+                Term::Var { ref name } if name == fun => {
+                    args.len() > major && match args[major].uncurry().0 {
+                        Term::Var { name: Name::DeBruijn { index, .. } } =>
+                            index >= depth && index < depth + fields,
+                        _ => false,
+                    }
+                }
+                ref head => on_fields(depth, head),
+            };
+
+            head_ok && args.iter().all(|arg| on_fields(depth, arg))
+        }
+        &Term::Var { ref name } => name != fun,
+        &Term::Forall { ref binder, term: ref body, .. } |
+        &Term::Lambda { ref binder, ref body, .. } |
+        &Term::Fix { ref binder, ref body, .. } =>
+            on_fields(depth, &binder.ty) && on_fields(depth + 1, body),
+        &Term::Let { ref binder, ref value, ref body, .. } =>
+            on_fields(depth, &binder.ty) && on_fields(depth, value) && on_fields(depth + 1, body),
+        &Term::Type => true,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Axiom {
+    pub span: Span,
+    pub name: Name,
+    pub ty: Term,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extern {
+    pub span: Span,
+    pub name: Name,
+    pub term: Term,
+    /// How the backend calls the extern, if the user gave a symbol for it.
+    pub convention: Option<Convention>,
+}
+
+/// The calling convention of an extern, validated by the backend against
+/// the implementations the runtime provides.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Convention {
+    /// The path to the implementation in the generated crate.
+    pub symbol: String,
+    /// The crate the implementation is in, if it is not in the runtime.
+    pub crate_name: Option<String>,
+    /// The number of parameters the extern was declared with.
+    pub arity: usize,
+    /// The positions of the parameters that are erased, these are not
+    /// passed to the implementation.
+    pub erased: Vec<usize>,
+}
+
+impl Convention {
+    /// The number of arguments passed to the implementation.
+    pub fn runtime_arity(&self) -> usize {
+        self.arity - self.erased.len()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Data(Data),
+    Fn(Function),
+    Axiom(Axiom),
+    Extern(Extern),
+}
+
+impl Item {
+    /// The name of the global the item declares.
+    pub fn name(&self) -> &Name {
+        use self::Item::*;
+
+        match self {
+            &Data(ref data) => &data.name,
+            &Fn(ref fun) => &fun.name,
+            &Axiom(ref a) => &a.name,
+            &Extern(ref e) => &e.name,
+        }
+    }
+}
+
+impl Display for Item {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        use self::Item::*;
+
+        match self {
+            &Fn(ref fun) => write!(formatter, "{}", fun),
+            d => write!(formatter, "{:?}", d),
+        }
+    }
+}
+
+pub type Function = Definition;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: Name,
+    pub args: Vec<Name>,
+    pub ty: Term,
+    pub body: Term,
+    pub reduction: DeltaReduction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeltaReduction {
+    Reducible,
+    Semireducible,
+    Irreducible,
+}
+
+impl Pretty for Function {
+    fn pretty(&self) -> Doc {
+        let &Function {
+            ref name,
+            ref ty,
+            ref body,
+            ..
+        } = self;
+        // TODO: Would you fix this TK?
+        Pretty::pretty("def ") + Pretty::pretty(name) + Pretty::pretty(" : ") + Pretty::pretty(ty) +
+        Pretty::pretty(" := \n") + Pretty::pretty(body) + Pretty::pretty("\nend")
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        format(self, formatter)
+    }
+}
@@ -1,12 +1,14 @@
 use super::super::ast::{Span, HasSpan};
 
+use std::cmp::Ordering;
 use std::fmt::{self, Display, Formatter};
 use std::hash::{Hash, Hasher};
 
 use super::Term;
-use super::BindingMode;
+use super::{BindingMode, Relevance};
 
 use super::super::pretty::*;
+use hubris_syntax::tok::escape_identifier;
 
 #[derive(Clone, Debug, Eq)]
 pub enum Name {
@@ -20,6 +22,7 @@ pub enum Name {
         repr: String,
         ty: Box<Term>,
         binding_info: BindingMode,
+        relevance: Relevance,
     },
     Qual {
         span: Span,
@@ -109,6 +112,13 @@ impl Name {
         }
     }
 
+    pub fn is_irrelevant(&self) -> bool {
+        match self {
+            &Name::Local { relevance: Relevance::Irrelevant, .. } => true,
+            _ => false,
+        }
+    }
+
     pub fn with_repr(mut self, new_repr: String) -> Name {
         use self::Name::*;
 
@@ -173,6 +183,44 @@ impl Hash for Name {
     }
 }
 
+impl Name {
+    /// The rank of each variant, used to order names of different kinds.
+    fn kind_rank(&self) -> usize {
+        use self::Name::*;
+
+        match self {
+            &DeBruijn { .. } => 0,
+            &Qual { .. } => 1,
+            &Meta { .. } => 2,
+            &Local { .. } => 3,
+        }
+    }
+}
+
+// Ordering agrees with equality, it only looks at the identifying part of
+// each name, so ordered maps keyed by names iterate deterministically.
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Name) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Name {
+    fn cmp(&self, other: &Name) -> Ordering {
+        use self::Name::*;
+
+        match (self, other) {
+            (&DeBruijn { index: index1, .. }, &DeBruijn { index: index2, .. }) =>
+                index1.cmp(&index2),
+            (&Qual { components: ref components1, .. },
+             &Qual { components: ref components2, .. }) => components1.cmp(components2),
+            (&Local { number: n1, .. }, &Local { number: n2, .. }) |
+            (&Meta { number: n1, .. }, &Meta { number: n2, .. }) => n1.cmp(&n2),
+            _ => self.kind_rank().cmp(&other.kind_rank()),
+        }
+    }
+}
+
 impl Pretty for Name {
     fn pretty(&self) -> Doc {
         use self::Name::*;
@@ -181,9 +229,10 @@ impl Pretty for Name {
             &DeBruijn { ref repr, ref index, .. } => repr.pretty(), // Doc::text(format!("{}", index)),
             &Qual { ref components, .. } => {
                 if components.len() == 1 {
-                    components[0].pretty()
+                    Doc::text(escape_identifier(&components[0]).into_owned())
                 } else {
-                    seperate(components.iter().map(|x| x.pretty())
+                    seperate(components.iter()
+                                       .map(|x| Doc::text(escape_identifier(x).into_owned()))
                                  .collect::<Vec<Doc>>().as_slice(),
                              &Doc::text("."))
                 }
@@ -0,0 +1,184 @@
+//! Optional statistics about the allocation and cloning of core terms.
+//!
+//! Collection is disabled by default and costs a single atomic load per
+//! event when off. When enabled, every node allocated or cloned is counted and
+//! attributed to the innermost active `Site` of the thread doing it, which
+//! lets us see where the compiler spends its time copying terms. The counts
+//! are shared by every thread, so work the backend does on a thread pool is
+//! counted along with the rest.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+
+use super::Term;
+
+static ENABLED: AtomicBool = ATOMIC_BOOL_INIT;
+
+thread_local! {
+    static SITES: RefCell<Vec<&'static str>> = RefCell::new(vec![]);
+}
+
+/// The statistics of every thread.
+fn stats() -> &'static Mutex<TermStats> {
+    static INIT: Once = ONCE_INIT;
+    static mut STATS: *const Mutex<TermStats> = 0 as *const Mutex<TermStats>;
+
+    unsafe {
+        INIT.call_once(|| {
+            STATS = Box::into_raw(Box::new(Mutex::new(TermStats::default())));
+        });
+
+        &*STATS
+    }
+}
+
+/// Update the statistics with `f`, a thread that panicked while holding
+/// them can only have left a count short.
+fn update<F: FnOnce(&mut TermStats)>(f: F) {
+    let mut stats = match stats().lock() {
+        Ok(stats) => stats,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    f(&mut stats)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TermStats {
+    pub allocations: usize,
+    pub clones: usize,
+    pub peak_size: usize,
+    /// Nodes cloned, attributed to the site active at the time.
+    pub clones_by_site: HashMap<&'static str, usize>,
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Record the allocation of `n` term nodes.
+#[inline]
+pub fn record_allocations(n: usize) {
+    if is_enabled() {
+        update(|s| s.allocations += n);
+    }
+}
+
+/// Record the cloning of a single term node.
+#[inline]
+pub fn record_clone() {
+    if is_enabled() {
+        let site = SITES.with(|s| s.borrow().last().cloned().unwrap_or("<unattributed>"));
+        update(|s| {
+            s.clones += 1;
+            s.allocations += 1;
+            *s.clones_by_site.entry(site).or_insert(0) += 1;
+        });
+    }
+}
+
+/// Record the size of a term, keeping track of the largest seen.
+#[inline]
+pub fn record_size(term: &Term) {
+    if is_enabled() {
+        let size = term.size();
+        update(|s| {
+            if size > s.peak_size {
+                s.peak_size = size;
+            }
+        });
+    }
+}
+
+pub fn snapshot() -> TermStats {
+    let mut stats = None;
+    update(|s| stats = Some(s.clone()));
+    stats.unwrap()
+}
+
+/// A guard attributing clones to a named site for as long as it lives.
+pub struct Site;
+
+impl Site {
+    pub fn enter(name: &'static str) -> Site {
+        if is_enabled() {
+            SITES.with(|s| s.borrow_mut().push(name));
+        }
+        Site
+    }
+}
+
+impl Drop for Site {
+    fn drop(&mut self) {
+        if is_enabled() {
+            SITES.with(|s| { s.borrow_mut().pop(); });
+        }
+    }
+}
+
+pub fn report<W: Write>(out: &mut W) -> io::Result<()> {
+    let stats = snapshot();
+
+    try!(writeln!(out, "{:<24} {:>12}", "term nodes allocated", stats.allocations));
+    try!(writeln!(out, "{:<24} {:>12}", "term nodes cloned", stats.clones));
+    try!(writeln!(out, "{:<24} {:>12}", "largest term (nodes)", stats.peak_size));
+
+    let mut sites: Vec<_> = stats.clones_by_site.into_iter().collect();
+    sites.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    if sites.len() > 0 {
+        try!(writeln!(out, ""));
+        try!(writeln!(out, "{:<24} {:>12}", "clones by site", "nodes"));
+        for (site, count) in sites {
+            try!(writeln!(out, "{:<24} {:>12}", site, count));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn counts() -> (usize, usize) {
+        let stats = snapshot();
+        (stats.allocations, stats.clones)
+    }
+
+    fn work() {
+        for _ in 0..100 {
+            record_allocations(3);
+            record_clone();
+        }
+    }
+
+    #[test]
+    fn work_on_other_threads_is_counted() {
+        enable();
+
+        let before = counts();
+        for _ in 0..4 {
+            work();
+        }
+        let sequential = counts();
+
+        let workers: Vec<_> = (0..4).map(|_| thread::spawn(work)).collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        let parallel = counts();
+
+        assert_eq!((sequential.0 - before.0, sequential.1 - before.1),
+                   (parallel.0 - sequential.0, parallel.1 - sequential.1));
+    }
+}
@@ -4,11 +4,12 @@ use std::fmt::{self, Display, Formatter};
 use std::hash::{Hash, Hasher};
 
 use super::Name;
+use super::stats;
 use super::{Binder, BindingMode, pretty_binders};
 
 use super::super::pretty::*;
 
-#[derive(Debug, Clone, Eq)]
+#[derive(Debug, Eq)]
 pub enum Term {
     Var {
         name: Name,
@@ -28,28 +29,106 @@ pub enum Term {
         binder: Binder,
         body: Box<Term>,
     },
+    /// A local definition, `body` refers to `value` through the de Bruijn
+    /// index bound by `binder`, whose type is the type of `value`.
+    Let {
+        span: Span,
+        binder: Binder,
+        value: Box<Term>,
+        body: Box<Term>,
+    },
+    /// A structurally recursive function, `body` refers to the function
+    /// itself through the de Bruijn index bound by `binder`. The function only
+    /// unfolds once its `rec_arg`th argument is a constructor application,
+    /// which keeps reduction during type checking terminating.
+    Fix {
+        span: Span,
+        binder: Binder,
+        rec_arg: usize,
+        body: Box<Term>,
+    },
     Type,
 }
 
+impl Clone for Term {
+    fn clone(&self) -> Term {
+        use self::Term::*;
+
+        stats::record_clone();
+
+        match self {
+            &Var { ref name } => Var { name: name.clone() },
+            &App { span, ref fun, ref arg } => App {
+                span: span,
+                fun: fun.clone(),
+                arg: arg.clone(),
+            },
+            &Forall { span, ref binder, ref term } => Forall {
+                span: span,
+                binder: binder.clone(),
+                term: term.clone(),
+            },
+            &Lambda { span, ref binder, ref body } => Lambda {
+                span: span,
+                binder: binder.clone(),
+                body: body.clone(),
+            },
+            &Let { span, ref binder, ref value, ref body } => Let {
+                span: span,
+                binder: binder.clone(),
+                value: value.clone(),
+                body: body.clone(),
+            },
+            &Fix { span, ref binder, rec_arg, ref body } => Fix {
+                span: span,
+                binder: binder.clone(),
+                rec_arg: rec_arg,
+                body: body.clone(),
+            },
+            &Type => Type,
+        }
+    }
+}
+
 impl Term {
+    /// The number of nodes in the term, not counting the types stored
+    /// inside of names.
+    pub fn size(&self) -> usize {
+        use self::Term::*;
+
+        match self {
+            &Var { .. } | &Type => 1,
+            &App { ref fun, ref arg, .. } => 1 + fun.size() + arg.size(),
+            &Forall { ref binder, ref term, .. } => 1 + binder.ty.size() + term.size(),
+            &Lambda { ref binder, ref body, .. } => 1 + binder.ty.size() + body.size(),
+            &Let { ref binder, ref value, ref body, .. } =>
+                1 + binder.ty.size() + value.size() + body.size(),
+            &Fix { ref binder, ref body, .. } => 1 + binder.ty.size() + body.size(),
+        }
+    }
+
     pub fn abstract_lambda(locals: Vec<Name>, t: Term) -> Term {
         let mut result = t;
         for local in locals.into_iter().rev() {
             let body = result.abstr(&local);
 
-            let (repr, ty) = match local {
-                Name::Local { repr, ty, .. } => (repr, ty),
+            let (repr, ty, mode, relevance) = match local {
+                Name::Local { repr, ty, binding_info, relevance, .. } =>
+                    (repr, ty, binding_info, relevance),
                 n => panic!("trying to abstract over {:?}", n),
             };
 
             result = Term::Lambda {
-                binder: Binder::explicit(
-                    Name::DeBruijn {
+                binder: Binder {
+                    name: Name::DeBruijn {
                         index: 0,
                         repr: repr,
                         span: Span::dummy(),
                     },
-                    *ty),
+                    ty: ty,
+                    mode: mode,
+                    relevance: relevance,
+                },
                 body: Box::new(body),
                 span: Span::dummy(),
             };
@@ -74,10 +153,10 @@ impl Term {
         for local in locals.into_iter().rev() {
             let body = result.abstr(&local);
 
-            let (repr, ty, mode) = match local {
-                Name::Local { repr, ty, binding_info, .. } => match binding_override {
-                    None => (repr, ty, binding_info),
-                    Some(bi_override) => (repr, ty, bi_override),
+            let (repr, ty, mode, relevance) = match local {
+                Name::Local { repr, ty, binding_info, relevance, .. } => match binding_override {
+                    None => (repr, ty, binding_info, relevance),
+                    Some(bi_override) => (repr, ty, bi_override, relevance),
                 },
                 _ => panic!("internal invariant violated: tried to abstract a name that is \
                              not a local constant"),
@@ -93,6 +172,7 @@ impl Term {
                 name: name,
                 ty: ty,
                 mode: mode,
+                relevance: relevance,
             };
 
             result = Term::Forall {
@@ -108,6 +188,7 @@ impl Term {
     pub fn abstr(&self, name: &Name) -> Term {
         debug!("before Term::abstr: name={} in self={}", name, self);
         let result = self.abst(0, name);
+        stats::record_size(&result);
         debug!("after Term::abstr: result={}", result);
         result
     }
@@ -117,6 +198,7 @@ impl Term {
         use super::Name::*;
 
         // debug!("subst: {} with {}", index, replacement);
+        stats::record_allocations(1);
 
         match self {
             &Var { name: ref vname } => {
@@ -158,6 +240,22 @@ impl Term {
                     span: span,
                 }
             }
+            &Let { ref binder, ref value, ref body, span } => {
+                Let {
+                    binder: binder.abst(index, x),
+                    value: Box::new(value.abst(index, x)),
+                    body: Box::new(body.abst(index + 1, x)),
+                    span: span,
+                }
+            }
+            &Fix { ref binder, rec_arg, ref body, span } => {
+                Fix {
+                    binder: binder.abst(index, x),
+                    rec_arg: rec_arg,
+                    body: Box::new(body.abst(index + 1, x)),
+                    span: span,
+                }
+            }
             &Type => Type,
         }
     }
@@ -165,7 +263,9 @@ impl Term {
     pub fn instantiate(&self, subst: &Term) -> Term {
         debug!("instantaite: self={} subst={}", self, subst);
         // assert!(subst.is_closed());
-        self.replace(0, subst)
+        let result = self.replace(0, subst);
+        stats::record_size(&result);
+        result
     }
 
     pub fn replace(&self, index: usize, subst: &Term) -> Term {
@@ -173,6 +273,7 @@ impl Term {
         use super::Name::*;
 
         debug!("replace: {} with {}", index, subst);
+        stats::record_allocations(1);
 
         match self {
             &Var { ref name } => {
@@ -208,6 +309,22 @@ impl Term {
                     span: span,
                 }
             }
+            &Let { ref binder, ref value, ref body, span } => {
+                Let {
+                    binder: binder.replace(index, subst),
+                    value: Box::new(value.replace(index, subst)),
+                    body: Box::new(body.replace(index + 1, subst)),
+                    span: span,
+                }
+            }
+            &Fix { ref binder, rec_arg, ref body, span } => {
+                Fix {
+                    binder: binder.replace(index, subst),
+                    rec_arg: rec_arg,
+                    body: Box::new(body.replace(index + 1, subst)),
+                    span: span,
+                }
+            }
             &Type => Type,
         }
     }
@@ -231,7 +348,67 @@ impl Term {
     //     }
     // }
 
+    /// Bind `value` to the local `name` in `body`.
+    pub fn let_in(name: Name, value: Term, body: Term) -> Term {
+        let body = body.abstr(&name);
+
+        let (repr, ty) = match name {
+            Name::Local { repr, ty, .. } => (repr, ty),
+            n => panic!("trying to abstract over {:?}", n),
+        };
+
+        Term::Let {
+            binder: Binder::explicit(
+                Name::DeBruijn {
+                    index: 0,
+                    repr: repr,
+                    span: Span::dummy(),
+                },
+                *ty),
+            value: Box::new(value),
+            body: Box::new(body),
+            span: Span::dummy(),
+        }
+    }
+
+    /// Zeta reduce the outermost `let` of the term, substituting its value
+    /// into the body, returns `None` if the term is not a `let`.
+    pub fn zeta(&self) -> Option<Term> {
+        match self {
+            &Term::Let { ref value, ref body, .. } => Some(body.instantiate(value)),
+            _ => None,
+        }
+    }
+
+    /// Unfold a fixpoint applied to `args`, this only happens when the
+    /// recursive argument is headed by a constructor according to `is_ctor`.
+    pub fn unfold_fix<F: Fn(&Name) -> bool>(&self, args: &[Term], is_ctor: F) -> Option<Term> {
+        match self {
+            &Term::Fix { rec_arg, ref body, .. } if args.len() > rec_arg => {
+                let guarded = match args[rec_arg].head() {
+                    Some(Term::Var { ref name }) => is_ctor(name),
+                    _ => false,
+                };
+
+                if guarded {
+                    Some(Term::apply_all(body.instantiate(self), args.to_vec()))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_let(&self) -> bool {
+        match self {
+            &Term::Let { .. } => true,
+            _ => false,
+        }
+    }
+
     pub fn apply(t: Term, u: Term) -> Term {
+        stats::record_allocations(1);
         Term::App {
             fun: Box::new(t),
             arg: Box::new(u),
@@ -240,6 +417,7 @@ impl Term {
     }
 
     pub fn apply_all(fun: Term, args: Vec<Term>) -> Term {
+        stats::record_allocations(args.len());
         let mut result = fun;
         for arg in args {
             result = Term::App {
@@ -326,6 +504,8 @@ impl Term {
             }
             f @ &Forall { .. } => Some(f.clone()),
             l @ &Lambda { .. } => Some(l.clone()),
+            l @ &Let { .. } => Some(l.clone()),
+            f @ &Fix { .. } => Some(f.clone()),
             v @ &Var { .. } => Some(v.clone()),
             &Type => Some(Type),
         }
@@ -484,6 +664,15 @@ impl Term {
                     binder.ty.replace_term(&replacement, pred);
                     body.replace_term(&replacement, pred);
                 }
+                &mut Let { ref mut binder, ref mut value, ref mut body, .. } => {
+                    binder.ty.replace_term(&replacement, pred);
+                    value.replace_term(&replacement, pred);
+                    body.replace_term(&replacement, pred);
+                }
+                &mut Fix { ref mut binder, ref mut body, .. } => {
+                    binder.ty.replace_term(&replacement, pred);
+                    body.replace_term(&replacement, pred);
+                }
                 _ => {}
             }
         }
@@ -509,6 +698,14 @@ impl PartialEq for Term {
              &Lambda { binder: ref binder2, body: ref body2, ..}) => {
                 binder1 == binder2 && body1 == body2
             }
+            (&Let { binder: ref binder1, value: ref value1, body: ref body1, .. },
+             &Let { binder: ref binder2, value: ref value2, body: ref body2, .. }) => {
+                binder1 == binder2 && value1 == value2 && body1 == body2
+            }
+            (&Fix { binder: ref binder1, rec_arg: rec_arg1, body: ref body1, .. },
+             &Fix { binder: ref binder2, rec_arg: rec_arg2, body: ref body2, .. }) => {
+                binder1 == binder2 && rec_arg1 == rec_arg2 && body1 == body2
+            }
             (&Type, &Type) => true,
             _ => false,
         }
@@ -545,6 +742,18 @@ impl Hash for Term {
             &Type => {
                 4.hash(state);
             }
+            &Let { ref binder, ref value, ref body, .. } => {
+                5.hash(state);
+                binder.hash(state);
+                value.hash(state);
+                body.hash(state);
+            }
+            &Fix { ref binder, rec_arg, ref body, .. } => {
+                6.hash(state);
+                binder.hash(state);
+                rec_arg.hash(state);
+                body.hash(state);
+            }
         }
     }
 }
@@ -557,7 +766,9 @@ impl Pretty for Term {
             &Var { ref name, .. } => name.pretty(),
             &App { ref fun, ref arg, .. } => {
                 let pretty_fun = match &**fun {
-                    complex @ &Term::Lambda { .. } =>
+                    complex @ &Term::Lambda { .. } |
+                    complex @ &Term::Let { .. } |
+                    complex @ &Term::Fix { .. } =>
                         parens(complex.pretty()),
                     t => t.pretty()
                 };
@@ -568,7 +779,7 @@ impl Pretty for Term {
                 }
             }
             &Forall { ref binder, ref term, .. } => {
-                if binder.name.is_placeholder() {
+                if binder.name.is_placeholder() && !binder.is_irrelevant() {
                     let p = match &*binder.ty {
                         &Forall {..} => parens(binder.ty.pretty()) + " -> ".pretty(),
                         _ => binder.ty.pretty() + " -> ".pretty(),
@@ -642,6 +853,14 @@ impl Pretty for Term {
 
                 "fun ".pretty() + pretty_binders(binders.as_slice()) + " => ".pretty() + cursor.pretty()
             }
+            &Let { ref binder, ref value, ref body, .. } => {
+                "let ".pretty() + binder.name.pretty() + " : ".pretty() + binder.ty.pretty() +
+                    " := ".pretty() + value.pretty() + " in ".pretty() + body.pretty()
+            }
+            &Fix { ref binder, rec_arg, ref body, .. } => {
+                "fix ".pretty() + binder.name.pretty() + " : ".pretty() + binder.ty.pretty() +
+                    Doc::text(format!(" on {}", rec_arg)) + " => ".pretty() + body.pretty()
+            }
             &Type => Doc::text("Type"),
         }
     }
@@ -671,6 +890,8 @@ impl HasSpan for Term {
             &App { span, .. } => span,
             &Forall { span, .. } => span,
             &Lambda { span, .. } => span,
+            &Let { span, .. } => span,
+            &Fix { span, .. } => span,
             &Type => Span::dummy(),
         }
     }
@@ -683,6 +904,8 @@ impl HasSpan for Term {
             &mut App { ref mut span, .. } => *span = sp,
             &mut Forall { ref mut span, .. } => *span = sp,
             &mut Lambda { ref mut span, .. } => *span = sp,
+            &mut Let { ref mut span, .. } => *span = sp,
+            &mut Fix { ref mut span, .. } => *span = sp,
             &mut Type => {}
         }
     }
@@ -0,0 +1,540 @@
+use core::{Name, Term, Binder, BindingMode, Relevance, Data, Item, Module, Definition};
+use super::guard::{self, Globals};
+use super::inductive;
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    UnknownConstant(Name),
+    AlreadyDeclared(Name),
+    UnexpectedMeta(Name),
+    LooseBoundVariable(Name),
+    ExpectedSort(Term),
+    ExpectedFunction(Term, Term),
+    TypeMismatch {
+        term: Term,
+        expected: Term,
+        found: Term,
+    },
+    BadConstructor(Name, Name),
+    NotPositive(Name, Name),
+    MissingEquation(Name, Name),
+    /// The equation of the function for the constructor calls the function
+    /// on something other than a field of the constructor.
+    NotStructural(Name, Name),
+    /// A fixpoint used other than by a call on a strict subterm of its
+    /// recursive argument.
+    Unguarded(guard::Unguarded),
+}
+
+impl Error {
+    /// The name of the declaration most closely associated with this error,
+    /// used for reporting a location.
+    pub fn name(&self) -> Option<&Name> {
+        use self::Error::*;
+
+        match self {
+            &UnknownConstant(ref n) |
+            &AlreadyDeclared(ref n) |
+            &UnexpectedMeta(ref n) |
+            &LooseBoundVariable(ref n) |
+            &BadConstructor(_, ref n) |
+            &NotPositive(_, ref n) |
+            &MissingEquation(ref n, _) |
+            &NotStructural(ref n, _) => Some(n),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        use self::Error::*;
+
+        match self {
+            &UnknownConstant(ref n) =>
+                write!(formatter, "unknown constant `{}`", n),
+            &AlreadyDeclared(ref n) =>
+                write!(formatter, "`{}` has already been declared", n),
+            &UnexpectedMeta(ref n) =>
+                write!(formatter, "the kernel was given a term containing the meta-variable `{}`",
+                       n),
+            &LooseBoundVariable(ref n) =>
+                write!(formatter, "the kernel was given a term with the unbound variable `{}`", n),
+            &ExpectedSort(ref t) =>
+                write!(formatter, "expected `{}` to be a type", t),
+            &ExpectedFunction(ref f, ref ty) =>
+                write!(formatter, "`{}` has type `{}` and can not be applied", f, ty),
+            &TypeMismatch { ref term, ref expected, ref found } =>
+                write!(formatter, "`{}` was expected to have type `{}` but has type `{}`",
+                       term, expected, found),
+            &BadConstructor(ref ty, ref ctor) =>
+                write!(formatter, "the constructor `{}` does not construct a value of `{}`",
+                       ctor, ty),
+            &NotPositive(ref ty, ref ctor) =>
+                write!(formatter, "`{}` occurs in a non-positive position in the constructor `{}`",
+                       ty, ctor),
+            &MissingEquation(ref f, ref ctor) =>
+                write!(formatter, "`{}` has no equation for the constructor `{}`", f, ctor),
+            &NotStructural(ref f, ref ctor) =>
+                write!(formatter, "the equation of `{}` for the constructor `{}` calls `{}` on \
+                                   something other than a field of `{}`", f, ctor, f, ctor),
+            &Unguarded(ref unguarded) =>
+                write!(formatter, "the fixpoint `{}` is used in `{}`, which is not a call on \
+                                   a strict subterm of its recursive argument",
+                       unguarded.fix, unguarded.call),
+        }
+    }
+}
+
+/// A declaration that has been accepted by the kernel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Declaration {
+    /// A constant with no definition, this includes axioms, externs, and
+    /// the constructors of inductive types.
+    Axiom(Term),
+    /// A constant along with its definition, definitions are unfolded
+    /// during reduction.
+    Definition(Term, Term),
+    /// An inductive type along with its schema, used to compute
+    /// the reduction rule of its recursor.
+    Inductive(Data),
+    /// The recursor of the named inductive type.
+    Recursor(Term, Name),
+    /// A function defined along with the named inductive type, it reduces
+    /// by the equations stored with the type.
+    RecursiveFn(Term, Name),
+}
+
+impl Declaration {
+    pub fn ty(&self) -> &Term {
+        match self {
+            &Declaration::Axiom(ref ty) |
+            &Declaration::Definition(ref ty, _) |
+            &Declaration::Recursor(ref ty, _) |
+            &Declaration::RecursiveFn(ref ty, _) => ty,
+            &Declaration::Inductive(ref data) => &data.ty,
+        }
+    }
+}
+
+/// The kernel's global environment, a mapping from names to checked
+/// declarations.
+#[derive(Clone)]
+pub struct Environment {
+    declarations: HashMap<Name, Declaration>,
+    /// The names of all declarations in the order they were added.
+    order: Vec<Name>,
+    local_counter: Cell<usize>,
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment {
+            declarations: HashMap::new(),
+            order: vec![],
+            local_counter: Cell::new(0),
+        }
+    }
+
+    pub fn lookup(&self, name: &Name) -> Option<&Declaration> {
+        self.declarations.get(name)
+    }
+
+    pub fn contains(&self, name: &Name) -> bool {
+        self.declarations.contains_key(name)
+    }
+
+    /// All declarations in the order they were added to the environment,
+    /// dependencies always precede the declarations that use them.
+    pub fn declarations(&self) -> Vec<(&Name, &Declaration)> {
+        self.order
+            .iter()
+            .map(|n| (n, &self.declarations[n]))
+            .collect()
+    }
+
+    fn insert(&mut self, name: Name, decl: Declaration) {
+        if self.declarations.insert(name.clone(), decl).is_none() {
+            self.order.push(name);
+        }
+    }
+
+    /// Check and add a declaration read back from an image, one that is
+    /// already present is kept as is. The constructors, recursor and
+    /// function defined along with an inductive type are checked and added
+    /// with the type, which precedes them.
+    pub fn add_declaration(&mut self, name: Name, decl: Declaration) -> Result<(), Error> {
+        if self.declarations.contains_key(&name) {
+            return Ok(());
+        }
+
+        match decl {
+            Declaration::Axiom(ty) => self.add_axiom(&name, &ty),
+            Declaration::Definition(ty, body) => {
+                try!(self.ensure_type(&ty));
+                try!(self.check(&body, &ty));
+                self.insert(name, Declaration::Definition(ty, body));
+                Ok(())
+            }
+            Declaration::Inductive(data) => self.add_inductive(&data),
+            Declaration::Recursor(_, ty_name) |
+            Declaration::RecursiveFn(_, ty_name) => Err(Error::UnknownConstant(ty_name)),
+        }
+    }
+
+    /// Merge the declarations of `other` into this environment, a
+    /// declaration that is already present is kept as is.
+    pub fn merge(&mut self, other: Environment) {
+        let Environment { mut declarations, order, .. } = other;
+
+        for name in order {
+            let decl = declarations.remove(&name).unwrap();
+            if !self.declarations.contains_key(&name) {
+                self.insert(name, decl);
+            }
+        }
+    }
+
+    /// Check and add every item in `module`.
+    pub fn add_module(&mut self, module: &Module) -> Result<(), Error> {
+        for item in &module.defs {
+            try!(self.add_item(item));
+        }
+
+        Ok(())
+    }
+
+    pub fn add_item(&mut self, item: &Item) -> Result<(), Error> {
+        match item {
+            &Item::Data(ref data) => self.add_inductive(data),
+            &Item::Fn(ref def) => self.add_definition(def),
+            &Item::Axiom(ref ax) => self.add_axiom(&ax.name, &ax.ty),
+            &Item::Extern(ref ext) => self.add_axiom(&ext.name, &ext.term),
+        }
+    }
+
+    pub fn add_axiom(&mut self, name: &Name, ty: &Term) -> Result<(), Error> {
+        debug!("kernel: add_axiom {} : {}", name, ty);
+        try!(self.ensure_fresh(name));
+        try!(self.ensure_type(ty));
+        self.insert(name.clone(), Declaration::Axiom(ty.clone()));
+        Ok(())
+    }
+
+    pub fn add_definition(&mut self, def: &Definition) -> Result<(), Error> {
+        debug!("kernel: add_definition {} : {}", def.name, def.ty);
+        try!(self.ensure_fresh(&def.name));
+        try!(self.ensure_type(&def.ty));
+        try!(self.check(&def.body, &def.ty));
+
+        self.insert(
+            def.name.clone(),
+            Declaration::Definition(def.ty.clone(), def.body.clone()));
+
+        Ok(())
+    }
+
+    /// Check an inductive type is well formed, and then declare the type,
+    /// its constructors and its recursor.
+    pub fn add_inductive(&mut self, data: &Data) -> Result<(), Error> {
+        debug!("kernel: add_inductive {}", data.name);
+        try!(self.ensure_fresh(&data.name));
+        try!(self.ensure_type(&data.ty));
+
+        // The type must be in scope while checking the constructors.
+        self.insert(data.name.clone(), Declaration::Inductive(data.clone()));
+
+        // As must a function defined with the type, though its equations
+        // can only be checked once the constructors exist.
+        if let Some(ref rec) = data.recursive {
+            try!(self.ensure_fresh(&rec.name));
+            try!(self.ensure_type(&rec.ty));
+            self.insert(rec.name.clone(),
+                        Declaration::RecursiveFn(rec.ty.clone(), data.name.clone()));
+        }
+
+        for &(ref ctor, ref ctor_ty) in &data.ctors {
+            try!(self.ensure_fresh(ctor));
+            try!(self.ensure_type(ctor_ty));
+            try!(inductive::check_constructor(self, data, ctor, ctor_ty));
+            self.insert(ctor.clone(), Declaration::Axiom(ctor_ty.clone()));
+        }
+
+        if let Some(ref rec) = data.recursive {
+            try!(inductive::check_equations(self, data, rec));
+        }
+
+        let rec_name = data.name.in_scope("rec".to_string()).unwrap();
+        let rec_ty = inductive::recursor_type(self, data);
+
+        debug!("kernel: recursor {} : {}", rec_name, rec_ty);
+
+        self.insert(rec_name, Declaration::Recursor(rec_ty, data.name.clone()));
+
+        Ok(())
+    }
+
+    fn ensure_fresh(&self, name: &Name) -> Result<(), Error> {
+        if self.declarations.contains_key(name) {
+            Err(Error::AlreadyDeclared(name.clone()))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn fresh_local(&self, binder: &Binder) -> Name {
+        let repr = match &binder.name {
+            &Name::DeBruijn { ref repr, .. } => repr.clone(),
+            &Name::Local { ref repr, .. } => repr.clone(),
+            _ => "_".to_string(),
+        };
+
+        let mut local = self.local(repr, (*binder.ty).clone(), binder.mode);
+
+        if let Name::Local { ref mut relevance, .. } = local {
+            *relevance = binder.relevance;
+        }
+
+        local
+    }
+
+    pub fn local(&self, repr: String, ty: Term, mode: BindingMode) -> Name {
+        let number = self.local_counter.get();
+        self.local_counter.set(number + 1);
+
+        Name::Local {
+            number: number,
+            repr: repr,
+            ty: Box::new(ty),
+            binding_info: mode,
+            relevance: Relevance::Relevant,
+        }
+    }
+
+    /// Ensure that `ty` is a type, i.e. that its type is a sort.
+    pub fn ensure_type(&self, ty: &Term) -> Result<(), Error> {
+        let sort = try!(self.infer(ty));
+        self.ensure_sort(&sort)
+    }
+
+    fn ensure_sort(&self, sort: &Term) -> Result<(), Error> {
+        if self.whnf(sort).is_sort() {
+            Ok(())
+        } else {
+            Err(Error::ExpectedSort(sort.clone()))
+        }
+    }
+
+    pub fn check(&self, term: &Term, expected: &Term) -> Result<(), Error> {
+        let found = try!(self.infer(term));
+
+        if self.def_eq(&found, expected) {
+            Ok(())
+        } else {
+            Err(Error::TypeMismatch {
+                term: term.clone(),
+                expected: expected.clone(),
+                found: found,
+            })
+        }
+    }
+
+    /// Infer the type of a closed, meta-variable free term.
+    pub fn infer(&self, term: &Term) -> Result<Term, Error> {
+        match term {
+            &Term::Var { ref name } => match name {
+                &Name::Qual { .. } => match self.declarations.get(name) {
+                    None => Err(Error::UnknownConstant(name.clone())),
+                    Some(decl) => Ok(decl.ty().clone()),
+                },
+                &Name::Local { ref ty, .. } => Ok((**ty).clone()),
+                &Name::Meta { .. } => Err(Error::UnexpectedMeta(name.clone())),
+                &Name::DeBruijn { .. } => Err(Error::LooseBoundVariable(name.clone())),
+            },
+            &Term::App { ref fun, ref arg, .. } => {
+                let fun_ty = try!(self.infer(fun));
+
+                match self.whnf(&fun_ty) {
+                    Term::Forall { binder, term: body, .. } => {
+                        try!(self.check(arg, &binder.ty));
+                        Ok(body.instantiate(arg))
+                    }
+                    _ => Err(Error::ExpectedFunction((**fun).clone(), fun_ty)),
+                }
+            }
+            &Term::Forall { ref binder, ref term, .. } => {
+                try!(self.ensure_type(&binder.ty));
+                let local = self.fresh_local(binder);
+                try!(self.ensure_type(&term.instantiate(&local.to_term())));
+                Ok(Term::Type)
+            }
+            &Term::Lambda { ref binder, ref body, span } => {
+                try!(self.ensure_type(&binder.ty));
+                let local = self.fresh_local(binder);
+                let body_ty = try!(self.infer(&body.instantiate(&local.to_term())));
+
+                Ok(Term::Forall {
+                    span: span,
+                    binder: binder.clone(),
+                    term: Box::new(body_ty.abstr(&local)),
+                })
+            }
+            &Term::Let { ref binder, ref value, ref body, .. } => {
+                try!(self.ensure_type(&binder.ty));
+                try!(self.check(value, &binder.ty));
+                self.infer(&body.instantiate(value))
+            }
+            &Term::Fix { ref binder, rec_arg, ref body, .. } => {
+                try!(self.ensure_type(&binder.ty));
+                let local = self.fresh_local(binder);
+                let body = body.instantiate(&local.to_term());
+                try!(self.check(&body, &binder.ty));
+                try!(guard::check_fix(self, &local, rec_arg, &body).map_err(Error::Unguarded));
+                Ok((*binder.ty).clone())
+            }
+            &Term::Type => Ok(Term::Type),
+        }
+    }
+
+    /// Whether `name` is the constructor of an inductive type.
+    pub fn is_constructor(&self, name: &Name) -> bool {
+        self.declarations.values().any(|decl| match decl {
+            &Declaration::Inductive(ref data) => data.ctors.iter().any(|c| &c.0 == name),
+            _ => false,
+        })
+    }
+
+    /// Reduce a term to weak head normal form using beta, delta, iota and
+    /// zeta reduction, fixpoints are unfolded once their recursive argument
+    /// reduces to a constructor.
+    pub fn whnf(&self, term: &Term) -> Term {
+        let (head, args) = term.uncurry();
+
+        match head {
+            Term::Lambda { .. } if args.len() > 0 => {
+                let mut result = head;
+                let mut args = args.into_iter();
+
+                while let Some(arg) = args.next() {
+                    result = match result {
+                        Term::Lambda { body, .. } => body.instantiate(&arg),
+                        other => {
+                            let mut rest = vec![arg];
+                            rest.extend(args);
+                            return self.whnf(&Term::apply_all(other, rest));
+                        }
+                    };
+                }
+
+                self.whnf(&result)
+            }
+            Term::Let { .. } => {
+                let reduct = head.zeta().unwrap();
+                self.whnf(&Term::apply_all(reduct, args))
+            }
+            Term::Fix { rec_arg, .. } if args.len() > rec_arg => {
+                let mut args = args;
+                args[rec_arg] = self.whnf(&args[rec_arg]);
+
+                match head.unfold_fix(&args, |n| self.is_constructor(n)) {
+                    None => Term::apply_all(head, args),
+                    Some(reduct) => self.whnf(&reduct),
+                }
+            }
+            Term::Var { ref name } if name.is_qual() => {
+                match self.declarations.get(name) {
+                    Some(&Declaration::Definition(_, ref body)) =>
+                        self.whnf(&Term::apply_all(body.clone(), args)),
+                    Some(&Declaration::Recursor(_, ref ty_name)) => {
+                        match self.declarations.get(ty_name) {
+                            Some(&Declaration::Inductive(ref data)) =>
+                                match inductive::iota_reduce(self, data, name, &args) {
+                                    None => term.clone(),
+                                    Some(reduct) => self.whnf(&reduct),
+                                },
+                            _ => term.clone(),
+                        }
+                    }
+                    Some(&Declaration::RecursiveFn(_, ref ty_name)) => {
+                        let data = match self.declarations.get(ty_name) {
+                            Some(&Declaration::Inductive(ref data)) => data,
+                            _ => return term.clone(),
+                        };
+
+                        let major = inductive::major_index(data);
+                        if args.len() <= major {
+                            return term.clone();
+                        }
+
+                        let mut args = args;
+                        args[major] = self.whnf(&args[major]);
+
+                        match data.recursive.as_ref().and_then(|rec| rec.unfold(major, &args)) {
+                            None => Term::apply_all(head.clone(), args),
+                            Some(reduct) => self.whnf(&reduct),
+                        }
+                    }
+                    _ => term.clone(),
+                }
+            }
+            _ => term.clone(),
+        }
+    }
+
+    /// Check whether two terms are definitionally equal.
+    pub fn def_eq(&self, t: &Term, u: &Term) -> bool {
+        if t == u {
+            return true;
+        }
+
+        let t = self.whnf(t);
+        let u = self.whnf(u);
+
+        match (&t, &u) {
+            (&Term::Var { name: ref n1 }, &Term::Var { name: ref n2 }) => n1 == n2,
+            (&Term::App { fun: ref f1, arg: ref a1, .. },
+             &Term::App { fun: ref f2, arg: ref a2, .. }) =>
+                self.def_eq(f1, f2) && self.def_eq(a1, a2),
+            (&Term::Forall { binder: ref b1, term: ref t1, .. },
+             &Term::Forall { binder: ref b2, term: ref t2, .. }) |
+            (&Term::Lambda { binder: ref b1, body: ref t1, .. },
+             &Term::Lambda { binder: ref b2, body: ref t2, .. }) => {
+                if !self.def_eq(&b1.ty, &b2.ty) {
+                    return false;
+                }
+
+                let local = self.fresh_local(b1).to_term();
+                self.def_eq(&t1.instantiate(&local), &t2.instantiate(&local))
+            }
+            (&Term::Type, &Term::Type) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Globals for Environment {
+    fn recursor_of(&self, name: &Name) -> Option<&Data> {
+        match self.declarations.get(name) {
+            Some(&Declaration::Recursor(_, ref ty_name)) => match self.declarations.get(ty_name) {
+                Some(&Declaration::Inductive(ref data)) => Some(data),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn definition(&self, name: &Name) -> Option<&Term> {
+        match self.declarations.get(name) {
+            Some(&Declaration::Definition(_, ref body)) => Some(body),
+            _ => None,
+        }
+    }
+
+    fn fresh_local(&self, binder: &Binder) -> Name {
+        Environment::fresh_local(self, binder)
+    }
+}
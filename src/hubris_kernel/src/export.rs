@@ -0,0 +1,188 @@
+//! Export of a checked environment as s-expressions.
+//!
+//! The export format is a sequence of declarations, one per line, in
+//! dependency order:
+//!
+//! ```text
+//! (axiom <name> <term>)
+//! (def <name> <type> <body>)
+//! (inductive <name> <num-params> <type> (<name> <type>)* <with>?)
+//! (with <name> <type> (<name> <term>)*)
+//! ```
+//!
+//! Constructors and recursors are not exported separately, a checker is
+//! expected to derive them from the `inductive` declaration. A function
+//! defined along with the type is exported as a trailing `with`, giving its
+//! type and the right hand side for each constructor. Terms are
+//! written using de Bruijn indices:
+//!
+//! ```text
+//! (const <name>)
+//! (bvar <index>)
+//! (app <term> <term>)
+//! (pi <name> <mode> <term> <term>)
+//! (lam <name> <mode> <term> <term>)
+//! (let <name> <type> <value> <body>)
+//! (fix <name> <type> <rec-arg> <body>)
+//! (type)
+//! ```
+//!
+//! Names are written as double quoted, `.` separated strings, components
+//! that are keywords or not identifiers are escaped as `«component»`.
+//! `<mode>` is one of `explicit`, `implicit` or `instance`, the mode of an
+//! irrelevant binder is wrapped as `(erased <mode>)`.
+
+use core::{Name, Term, Binder, BindingMode, Data, RecursiveFn};
+use super::environment::{Environment, Declaration};
+use hubris_syntax::tok::escape_identifier;
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+/// The first line of every export file, bumped whenever the format changes.
+pub const EXPORT_HEADER: &'static str = "(hubris-export 5)";
+
+pub fn export_environment<W: Write>(env: &Environment, out: &mut W) -> io::Result<()> {
+    try!(writeln!(out, "{}", EXPORT_HEADER));
+
+    let declarations = env.declarations();
+    let ctors = constructors(&declarations);
+
+    for (name, decl) in declarations {
+        match decl {
+            &Declaration::Axiom(ref ty) => {
+                // Constructors are exported as part of their inductive type.
+                if !ctors.contains(name) {
+                    try!(writeln!(out, "(axiom {} {})", export_name(name), export_term(ty)));
+                }
+            }
+            &Declaration::Definition(ref ty, ref body) => {
+                try!(writeln!(out, "(def {} {} {})",
+                              export_name(name),
+                              export_term(ty),
+                              export_term(body)));
+            }
+            &Declaration::Inductive(ref data) =>
+                try!(writeln!(out, "{}", export_inductive(data))),
+            &Declaration::Recursor(..) |
+            &Declaration::RecursiveFn(..) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// The constructors of the inductive types among `declarations`.
+fn constructors<'e>(declarations: &[(&'e Name, &'e Declaration)]) -> HashSet<&'e Name> {
+    let mut ctors = HashSet::new();
+
+    for &(_, decl) in declarations {
+        if let &Declaration::Inductive(ref data) = decl {
+            ctors.extend(data.ctors.iter().map(|c| &c.0));
+        }
+    }
+
+    ctors
+}
+
+fn export_inductive(data: &Data) -> String {
+    let mut ctors: Vec<_> =
+        data.ctors
+            .iter()
+            .map(|&(ref n, ref ty)| format!("({} {})", export_name(n), export_term(ty)))
+            .collect();
+
+    if let Some(ref rec) = data.recursive {
+        ctors.push(export_recursive_fn(rec));
+    }
+
+    format!("(inductive {} {} {} {})",
+            export_name(&data.name),
+            data.parameters.len(),
+            export_term(&data.ty),
+            ctors.join(" "))
+}
+
+fn export_recursive_fn(rec: &RecursiveFn) -> String {
+    let equations: Vec<_> =
+        rec.equations
+           .iter()
+           .map(|&(ref n, ref rhs)| format!("({} {})", export_name(n), export_term(rhs)))
+           .collect();
+
+    format!("(with {} {} {})",
+            export_name(&rec.name),
+            export_term(&rec.ty),
+            equations.join(" "))
+}
+
+pub fn export_name(name: &Name) -> String {
+    let repr = match name {
+        &Name::Qual { ref components, .. } =>
+            components.iter()
+                      .map(|c| escape_identifier(c))
+                      .collect::<Vec<_>>()
+                      .join("."),
+        &Name::DeBruijn { ref repr, .. } |
+        &Name::Local { ref repr, .. } => repr.clone(),
+        &Name::Meta { number, .. } => format!("?{}", number),
+    };
+
+    format!("{:?}", repr)
+}
+
+pub fn export_mode(binder: &Binder) -> String {
+    let mode = match binder.mode {
+        BindingMode::Explicit => "explicit",
+        BindingMode::Implicit => "implicit",
+        BindingMode::Instance => "instance",
+    };
+
+    if binder.is_irrelevant() {
+        format!("(erased {})", mode)
+    } else {
+        mode.to_string()
+    }
+}
+
+fn export_binder(kind: &str, binder: &Binder, body: &Term) -> String {
+    format!("({} {} {} {} {})",
+            kind,
+            export_name(&binder.name),
+            export_mode(binder),
+            export_term(&binder.ty),
+            export_term(body))
+}
+
+pub fn export_term(term: &Term) -> String {
+    match term {
+        &Term::Var { ref name } => match name {
+            &Name::DeBruijn { index, .. } => format!("(bvar {})", index),
+            &Name::Qual { .. } => format!("(const {})", export_name(name)),
+            // The kernel only accepts closed terms, so these should never be
+            // exported, but we would rather produce a file that fails to check
+            // than crash.
+            &Name::Local { number, .. } => format!("(local {})", number),
+            &Name::Meta { number, .. } => format!("(meta {})", number),
+        },
+        &Term::App { ref fun, ref arg, .. } =>
+            format!("(app {} {})", export_term(fun), export_term(arg)),
+        &Term::Forall { ref binder, ref term, .. } =>
+            export_binder("pi", binder, term),
+        &Term::Lambda { ref binder, ref body, .. } =>
+            export_binder("lam", binder, body),
+        &Term::Let { ref binder, ref value, ref body, .. } =>
+            format!("(let {} {} {} {})",
+                    export_name(&binder.name),
+                    export_term(&binder.ty),
+                    export_term(value),
+                    export_term(body)),
+        &Term::Fix { ref binder, rec_arg, ref body, .. } =>
+            format!("(fix {} {} {} {})",
+                    export_name(&binder.name),
+                    export_term(&binder.ty),
+                    rec_arg,
+                    export_term(body)),
+        &Term::Type => "(type)".to_string(),
+    }
+}
@@ -0,0 +1,249 @@
+//! The guard condition of `fix`.
+//!
+//! A fixpoint may only call itself on a strict subterm of its recursive
+//! argument, otherwise `fix f : Nat -> Empty on 0 => f` would be a proof of
+//! `Empty`. A variable is a strict subterm of the recursive argument when it
+//! is bound to a field of the constructor the argument, or one of its strict
+//! subterms, was matched against. That is when it is bound by a minor premise
+//! of a recursor applied to the argument. Definitions such as `cases_on` are
+//! unfolded when the fixpoint is passed to them, to find the recursor they
+//! are defined with.
+//!
+//! The check is conservative, a call on anything but such a variable is
+//! rejected, as is any use of the fixpoint other than a call.
+
+use core::{Binder, Data, Name, Term};
+use super::inductive;
+
+/// The globals the guard condition looks through.
+pub trait Globals {
+    /// The inductive type `name` is the recursor of, if it is a recursor.
+    fn recursor_of(&self, name: &Name) -> Option<&Data>;
+
+    /// The body of `name`, if it is a definition.
+    fn definition(&self, name: &Name) -> Option<&Term>;
+
+    fn fresh_local(&self, binder: &Binder) -> Name;
+}
+
+/// A use of a fixpoint that is not a call on a strict subterm of its
+/// recursive argument.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unguarded {
+    /// The local standing for the fixpoint in its body.
+    pub fix: Name,
+    /// The offending use, along with the arguments it is applied to.
+    pub call: Term,
+}
+
+/// Check the body of a `fix` recursing on its `rec_arg`th argument, `body`
+/// must have been instantiated with the local `fix`.
+pub fn check_fix<G: Globals>(globals: &G,
+                             fix: &Name,
+                             rec_arg: usize,
+                             body: &Term) -> Result<(), Unguarded> {
+    let mut guard = Guard {
+        globals: globals,
+        fix: fix,
+        rec_arg: rec_arg,
+        argument: None,
+        subterms: vec![],
+    };
+
+    // Open the binders up to and including the recursive argument, a body
+    // with fewer binders can not call the fixpoint at all.
+    let mut body = body.clone();
+    let mut params = 0;
+
+    while params <= rec_arg {
+        let opened = match &body {
+            &Term::Lambda { ref binder, body: ref inner, .. } => {
+                try!(guard.term(&binder.ty));
+                let local = globals.fresh_local(binder);
+                let opened = inner.instantiate(&local.to_term());
+                if params == rec_arg {
+                    guard.argument = Some(local);
+                }
+                opened
+            }
+            _ => break,
+        };
+
+        body = opened;
+        params += 1;
+    }
+
+    guard.term(&body)
+}
+
+struct Guard<'g, G: 'g> {
+    globals: &'g G,
+    fix: &'g Name,
+    rec_arg: usize,
+    /// The recursive argument.
+    argument: Option<Name>,
+    /// The variables known to be strict subterms of the recursive argument.
+    subterms: Vec<Name>,
+}
+
+impl<'g, G: Globals> Guard<'g, G> {
+    fn unguarded(&self, term: &Term) -> Unguarded {
+        Unguarded {
+            fix: self.fix.clone(),
+            call: term.clone(),
+        }
+    }
+
+    fn mentions_fix(&self, term: &Term) -> bool {
+        inductive::occurs(self.fix, term)
+    }
+
+    fn term(&mut self, term: &Term) -> Result<(), Unguarded> {
+        if !self.mentions_fix(term) {
+            return Ok(());
+        }
+
+        match term {
+            &Term::Var { .. } => Err(self.unguarded(term)),
+            &Term::App { .. } => self.app(term),
+            &Term::Forall { ref binder, ref term, .. } => self.under(binder, term),
+            &Term::Lambda { ref binder, ref body, .. } => self.under(binder, body),
+            &Term::Let { ref binder, ref value, ref body, .. } => {
+                try!(self.term(&binder.ty));
+                try!(self.term(value));
+                self.term(&body.instantiate(value))
+            }
+            &Term::Fix { ref binder, ref body, .. } => self.under(binder, body),
+            &Term::Type => Ok(()),
+        }
+    }
+
+    fn under(&mut self, binder: &Binder, body: &Term) -> Result<(), Unguarded> {
+        try!(self.term(&binder.ty));
+        let local = self.globals.fresh_local(binder);
+        self.term(&body.instantiate(&local.to_term()))
+    }
+
+    fn all(&mut self, terms: &[Term]) -> Result<(), Unguarded> {
+        for term in terms {
+            try!(self.term(term));
+        }
+
+        Ok(())
+    }
+
+    fn is_subterm(&self, term: &Term) -> bool {
+        match term {
+            &Term::Var { ref name } => self.subterms.contains(name),
+            _ => false,
+        }
+    }
+
+    fn app(&mut self, term: &Term) -> Result<(), Unguarded> {
+        let (head, args) = term.uncurry();
+        let globals = self.globals;
+
+        match head {
+            Term::Var { ref name } if name == self.fix => {
+                if args.len() <= self.rec_arg || !self.is_subterm(&args[self.rec_arg]) {
+                    return Err(self.unguarded(term));
+                }
+
+                self.all(&args)
+            }
+            Term::Var { ref name } => {
+                if let Some(data) = globals.recursor_of(name) {
+                    return self.recursor(data, &args);
+                }
+
+                match globals.definition(name) {
+                    Some(body) => self.term(&beta(body.clone(), args)),
+                    None => self.all(&args),
+                }
+            }
+            head @ Term::Lambda { .. } => self.term(&beta(head, args)),
+            head => {
+                try!(self.term(&head));
+                self.all(&args)
+            }
+        }
+    }
+
+    /// Check an application of the recursor of `data`, when its major
+    /// premise is the recursive argument or one of its subterms the fields
+    /// bound by each minor premise are subterms as well.
+    fn recursor(&mut self, data: &Data, args: &[Term]) -> Result<(), Unguarded> {
+        let num_params = data.parameters.len();
+        let num_minors = data.ctors.len();
+        let num_indices = inductive::major_index(data) - num_params;
+        let major = num_params + 1 + num_minors + num_indices;
+
+        let on_argument = args.len() > major && (self.is_subterm(&args[major]) || {
+            match (&args[major], &self.argument) {
+                (&Term::Var { ref name }, &Some(ref argument)) => name == argument,
+                _ => false,
+            }
+        });
+
+        if !on_argument {
+            return self.all(args);
+        }
+
+        for (i, arg) in args.iter().enumerate() {
+            if i > num_params && i <= num_params + num_minors {
+                let ctor_ty = &data.ctors[i - num_params - 1].1;
+                let fields = inductive::count_binders(ctor_ty) - num_params;
+                try!(self.minor(arg, fields));
+            } else {
+                try!(self.term(arg));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn minor(&mut self, minor: &Term, fields: usize) -> Result<(), Unguarded> {
+        let depth = self.subterms.len();
+        let mut minor = minor.clone();
+        let mut opened = 0;
+
+        while opened < fields {
+            let body = match &minor {
+                &Term::Lambda { ref binder, ref body, .. } => {
+                    try!(self.term(&binder.ty));
+                    let local = self.globals.fresh_local(binder);
+                    let body = body.instantiate(&local.to_term());
+                    self.subterms.push(local);
+                    body
+                }
+                _ => break,
+            };
+
+            minor = body;
+            opened += 1;
+        }
+
+        let result = self.term(&minor);
+        self.subterms.truncate(depth);
+        result
+    }
+}
+
+/// Apply `fun` to `args`, substituting the arguments of leading lambdas.
+fn beta(fun: Term, args: Vec<Term>) -> Term {
+    let mut fun = fun;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        fun = match fun {
+            Term::Lambda { body, .. } => body.instantiate(&arg),
+            other => {
+                let mut rest = vec![arg];
+                rest.extend(args);
+                return Term::apply_all(other, rest);
+            }
+        };
+    }
+
+    fun
+}
@@ -0,0 +1,361 @@
+//! Import of declarations written in the export format described in
+//! `export`, this allows a development to be loaded without re-elaborating
+//! its source.
+
+use ast::Span;
+use hubris_syntax::tok::split_qualified;
+use core::{self, Name, Term, Binder, BindingMode, Relevance, Data, Item, Definition, DeltaReduction};
+use super::export::EXPORT_HEADER;
+
+use std::fmt::{self, Display, Formatter};
+use std::str::CharIndices;
+use std::iter::Peekable;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A syntax error at the given byte offset.
+    Syntax(usize, String),
+    /// A well formed s-expression that is not a valid declaration or term.
+    Malformed(String),
+    BadHeader,
+}
+
+impl Display for Error {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        match self {
+            &Error::Syntax(offset, ref msg) =>
+                write!(formatter, "syntax error at byte {}: {}", offset, msg),
+            &Error::Malformed(ref sexp) =>
+                write!(formatter, "malformed declaration or term `{}`", sexp),
+            &Error::BadHeader =>
+                write!(formatter, "missing or unsupported export header, expected `{}`",
+                       EXPORT_HEADER),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SExp {
+    Atom(String),
+    Str(String),
+    List(Vec<SExp>),
+}
+
+impl Display for SExp {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        match self {
+            &SExp::Atom(ref a) => write!(formatter, "{}", a),
+            &SExp::Str(ref s) => write!(formatter, "{:?}", s),
+            &SExp::List(ref xs) => {
+                let xs: Vec<_> = xs.iter().map(|x| format!("{}", x)).collect();
+                write!(formatter, "({})", xs.join(" "))
+            }
+        }
+    }
+}
+
+struct Reader<'src> {
+    chars: Peekable<CharIndices<'src>>,
+}
+
+impl<'src> Reader<'src> {
+    fn skip_whitespace(&mut self) {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if c.is_whitespace() {
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn read(&mut self) -> Result<Option<SExp>, Error> {
+        self.skip_whitespace();
+
+        let (offset, c) = match self.chars.peek() {
+            None => return Ok(None),
+            Some(&p) => p,
+        };
+
+        match c {
+            '(' => {
+                self.chars.next();
+                let mut items = vec![];
+                loop {
+                    self.skip_whitespace();
+                    match self.chars.peek() {
+                        None => return Err(Error::Syntax(offset, "unclosed list".to_string())),
+                        Some(&(_, ')')) => {
+                            self.chars.next();
+                            return Ok(Some(SExp::List(items)));
+                        }
+                        Some(_) => items.push(try!(self.read()).unwrap()),
+                    }
+                }
+            }
+            ')' => Err(Error::Syntax(offset, "unexpected `)`".to_string())),
+            '"' => {
+                self.chars.next();
+                let mut s = String::new();
+                loop {
+                    match self.chars.next() {
+                        None =>
+                            return Err(Error::Syntax(offset, "unterminated string".to_string())),
+                        Some((_, '"')) => return Ok(Some(SExp::Str(s))),
+                        Some((i, '\\')) => match self.chars.next() {
+                            Some((_, 'n')) => s.push('\n'),
+                            Some((_, 't')) => s.push('\t'),
+                            Some((_, c)) if c == '"' || c == '\\' || c == '\'' => s.push(c),
+                            _ => return Err(Error::Syntax(i, "invalid escape".to_string())),
+                        },
+                        Some((_, c)) => s.push(c),
+                    }
+                }
+            }
+            _ => {
+                let mut atom = String::new();
+                while let Some(&(_, c)) = self.chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    atom.push(c);
+                    self.chars.next();
+                }
+                Ok(Some(SExp::Atom(atom)))
+            }
+        }
+    }
+}
+
+/// Parse a sequence of s-expressions.
+pub fn parse_sexps(source: &str) -> Result<Vec<SExp>, Error> {
+    let mut reader = Reader { chars: source.char_indices().peekable() };
+    let mut sexps = vec![];
+
+    while let Some(sexp) = try!(reader.read()) {
+        sexps.push(sexp);
+    }
+
+    Ok(sexps)
+}
+
+fn malformed(sexp: &SExp) -> Error {
+    Error::Malformed(format!("{}", sexp))
+}
+
+fn import_name(sexp: &SExp) -> Result<Name, Error> {
+    match sexp {
+        &SExp::Str(ref s) =>
+            Ok(Name::qualified(split_qualified(s))),
+        _ => Err(malformed(sexp)),
+    }
+}
+
+/// Import the trailing `(with <name> <type> (<name> <term>)*)` of an
+/// inductive declaration.
+fn import_recursive_fn(items: &[SExp]) -> Result<core::RecursiveFn, Error> {
+    let mut equations = vec![];
+    for eq in &items[3..] {
+        match eq {
+            &SExp::List(ref e) if e.len() == 2 =>
+                equations.push((try!(import_name(&e[0])), try!(import_term(&e[1])))),
+            _ => return Err(malformed(eq)),
+        }
+    }
+
+    Ok(core::RecursiveFn {
+        name: try!(import_name(&items[1])),
+        ty: try!(import_term(&items[2])),
+        equations: equations,
+    })
+}
+
+fn import_binder_name(sexp: &SExp) -> Result<Name, Error> {
+    match sexp {
+        &SExp::Str(ref s) => Ok(Name::DeBruijn {
+            index: 0,
+            span: Span::dummy(),
+            repr: s.clone(),
+        }),
+        _ => Err(malformed(sexp)),
+    }
+}
+
+fn import_mode(sexp: &SExp) -> Result<(BindingMode, Relevance), Error> {
+    match sexp {
+        &SExp::Atom(ref a) if a == "explicit" => Ok((BindingMode::Explicit, Relevance::Relevant)),
+        &SExp::Atom(ref a) if a == "implicit" => Ok((BindingMode::Implicit, Relevance::Relevant)),
+        &SExp::Atom(ref a) if a == "instance" => Ok((BindingMode::Instance, Relevance::Relevant)),
+        &SExp::List(ref items)
+            if items.len() == 2 && items[0] == SExp::Atom("erased".to_string()) => {
+            let (mode, _) = try!(import_mode(&items[1]));
+            Ok((mode, Relevance::Irrelevant))
+        }
+        _ => Err(malformed(sexp)),
+    }
+}
+
+pub fn import_term(sexp: &SExp) -> Result<Term, Error> {
+    let items = match sexp {
+        &SExp::List(ref items) if items.len() > 0 => items,
+        _ => return Err(malformed(sexp)),
+    };
+
+    let tag = match &items[0] {
+        &SExp::Atom(ref tag) => &tag[..],
+        _ => return Err(malformed(sexp)),
+    };
+
+    match (tag, items.len()) {
+        ("const", 2) => Ok(try!(import_name(&items[1])).to_term()),
+        ("bvar", 2) => match &items[1] {
+            &SExp::Atom(ref i) => match i.parse() {
+                Ok(index) => Ok(Name::DeBruijn {
+                    index: index,
+                    span: Span::dummy(),
+                    repr: format!("x{}", index),
+                }.to_term()),
+                Err(_) => Err(malformed(sexp)),
+            },
+            _ => Err(malformed(sexp)),
+        },
+        ("app", 3) =>
+            Ok(Term::apply(try!(import_term(&items[1])), try!(import_term(&items[2])))),
+        ("pi", 5) | ("lam", 5) => {
+            let (mode, relevance) = try!(import_mode(&items[2]));
+            let mut binder = Binder::with_mode(
+                try!(import_binder_name(&items[1])),
+                try!(import_term(&items[3])),
+                mode);
+            binder.relevance = relevance;
+
+            let body = Box::new(try!(import_term(&items[4])));
+
+            if tag == "pi" {
+                Ok(Term::Forall { span: Span::dummy(), binder: binder, term: body })
+            } else {
+                Ok(Term::Lambda { span: Span::dummy(), binder: binder, body: body })
+            }
+        }
+        ("let", 5) => Ok(Term::Let {
+            span: Span::dummy(),
+            binder: Binder::explicit(
+                try!(import_binder_name(&items[1])),
+                try!(import_term(&items[2]))),
+            value: Box::new(try!(import_term(&items[3]))),
+            body: Box::new(try!(import_term(&items[4]))),
+        }),
+        ("fix", 5) => Ok(Term::Fix {
+            span: Span::dummy(),
+            binder: Binder::explicit(
+                try!(import_binder_name(&items[1])),
+                try!(import_term(&items[2]))),
+            rec_arg: match &items[3] {
+                &SExp::Atom(ref i) => match i.parse() {
+                    Ok(rec_arg) => rec_arg,
+                    Err(_) => return Err(malformed(sexp)),
+                },
+                _ => return Err(malformed(sexp)),
+            },
+            body: Box::new(try!(import_term(&items[4]))),
+        }),
+        ("type", 1) => Ok(Term::Type),
+        _ => Err(malformed(sexp)),
+    }
+}
+
+/// Import the declarations in `source`, `make_param` is used to construct
+/// the local constants representing the parameters of inductive types.
+pub fn import_items<F>(source: &str, make_param: F) -> Result<Vec<Item>, Error>
+    where F: Fn(String, Term) -> Name
+{
+    let sexps = try!(parse_sexps(source));
+    let mut sexps = sexps.into_iter();
+
+    match sexps.next() {
+        Some(ref header) if format!("{}", header) == EXPORT_HEADER => {}
+        _ => return Err(Error::BadHeader),
+    }
+
+    let mut items = vec![];
+
+    for sexp in sexps {
+        let decl = match &sexp {
+            &SExp::List(ref decl) if decl.len() > 0 => decl.clone(),
+            _ => return Err(malformed(&sexp)),
+        };
+
+        let tag = match &decl[0] {
+            &SExp::Atom(ref tag) => tag.clone(),
+            _ => return Err(malformed(&sexp)),
+        };
+
+        let item = match (&tag[..], decl.len()) {
+            ("axiom", 3) => Item::Axiom(core::Axiom {
+                span: Span::dummy(),
+                name: try!(import_name(&decl[1])),
+                ty: try!(import_term(&decl[2])),
+            }),
+            ("def", 4) => Item::Fn(Definition {
+                name: try!(import_name(&decl[1])),
+                args: vec![],
+                ty: try!(import_term(&decl[2])),
+                body: try!(import_term(&decl[3])),
+                reduction: DeltaReduction::Reducible,
+            }),
+            ("inductive", n) if n >= 4 => {
+                let num_params: usize = match &decl[2] {
+                    &SExp::Atom(ref n) => try!(n.parse().map_err(|_| malformed(&sexp))),
+                    _ => return Err(malformed(&sexp)),
+                };
+
+                let ty = try!(import_term(&decl[3]));
+
+                let mut parameters = vec![];
+                let mut cursor = ty.clone();
+                for _ in 0..num_params {
+                    cursor = match cursor {
+                        Term::Forall { binder, term, .. } => {
+                            let repr = match binder.name {
+                                Name::DeBruijn { ref repr, .. } => repr.clone(),
+                                _ => "_".to_string(),
+                            };
+                            let param = make_param(repr, *binder.ty);
+                            let next = term.instantiate(&param.to_term());
+                            parameters.push(param);
+                            next
+                        }
+                        _ => return Err(malformed(&sexp)),
+                    };
+                }
+
+                let mut ctors = vec![];
+                let mut recursive = None;
+                for ctor in &decl[4..] {
+                    match ctor {
+                        &SExp::List(ref c) if c.len() >= 3 &&
+                                              c[0] == SExp::Atom("with".to_string()) =>
+                            recursive = Some(try!(import_recursive_fn(c))),
+                        &SExp::List(ref c) if c.len() == 2 =>
+                            ctors.push((try!(import_name(&c[0])), try!(import_term(&c[1])))),
+                        _ => return Err(malformed(ctor)),
+                    }
+                }
+
+                Item::Data(Data {
+                    span: Span::dummy(),
+                    name: try!(import_name(&decl[1])),
+                    parameters: parameters,
+                    ty: ty,
+                    ctors: ctors,
+                    recursive: recursive,
+                })
+            }
+            _ => return Err(malformed(&sexp)),
+        };
+
+        items.push(item);
+    }
+
+    Ok(items)
+}
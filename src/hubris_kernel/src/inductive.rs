@@ -0,0 +1,350 @@
+//! Checking of inductive declarations, along with the construction
+//! and reduction of their recursors.
+//!
+//! The recursor for an inductive type `T` with parameters `P`, indices `I`
+//! and constructors `c_1 ... c_n` has the form:
+//!
+//! ```text
+//! T.rec : forall {P}, (C : forall I, T P I -> Type),
+//!         (m_1 : minor premise for c_1) ... (m_n : minor premise for c_n),
+//!         forall I (x : T P I), C I x
+//! ```
+//!
+//! This mirrors the recursor generated by the elaborator, but is
+//! computed independently here.
+//!
+//! An inductive type may also be declared along with a function over it
+//! (induction-recursion), the function is declared before the constructors
+//! so they may refer to it, and its equations are checked once the
+//! constructors have been declared.
+
+use core::{Name, Term, Data, BindingMode, RecursiveFn};
+use super::environment::{Environment, Error};
+
+/// Instantiate the leading `params.len()` binders of `ty` with `params`.
+fn instantiate_params(ty: &Term, params: &[Term]) -> Term {
+    let mut ty = ty.clone();
+
+    for param in params {
+        ty = match ty {
+            Term::Forall { term, .. } => term.instantiate(param),
+            t => panic!("kernel: expected a parameter binder in {}", t),
+        };
+    }
+
+    ty
+}
+
+/// Open every leading binder of `ty` creating a local for each one.
+fn telescope(env: &Environment, ty: &Term, prefix: &str) -> (Vec<Name>, Term) {
+    let mut locals = vec![];
+    let mut ty = ty.clone();
+
+    while let Term::Forall { binder, term, .. } = ty {
+        let local = env.local(
+            format!("{}{}", prefix, locals.len()),
+            *binder.ty,
+            BindingMode::Explicit);
+
+        ty = term.instantiate(&local.to_term());
+        locals.push(local);
+    }
+
+    (locals, ty)
+}
+
+fn to_terms(names: &[Name]) -> Vec<Term> {
+    names.iter().map(|n| n.to_term()).collect()
+}
+
+/// Returns true if the constant `name` occurs anywhere in `term`.
+pub fn occurs(name: &Name, term: &Term) -> bool {
+    match term {
+        &Term::Var { name: ref n } => n == name,
+        &Term::App { ref fun, ref arg, .. } => occurs(name, fun) || occurs(name, arg),
+        &Term::Forall { ref binder, ref term, .. } =>
+            occurs(name, &binder.ty) || occurs(name, term),
+        &Term::Lambda { ref binder, ref body, .. } =>
+            occurs(name, &binder.ty) || occurs(name, body),
+        &Term::Let { ref binder, ref value, ref body, .. } =>
+            occurs(name, &binder.ty) || occurs(name, value) || occurs(name, body),
+        &Term::Fix { ref binder, ref body, .. } =>
+            occurs(name, &binder.ty) || occurs(name, body),
+        &Term::Type => false,
+    }
+}
+
+fn is_inductive_app(data: &Data, ty: &Term) -> bool {
+    let (head, args) = ty.uncurry();
+    head == data.name.to_term() && !args.iter().any(|a| occurs(&data.name, a))
+}
+
+/// Check that `data` only occurs strictly positively in `arg_ty`.
+fn is_positive(env: &Environment, data: &Data, arg_ty: &Term) -> bool {
+    if !occurs(&data.name, arg_ty) {
+        return true;
+    }
+
+    let (locals, result) = telescope(env, &env.whnf(arg_ty), "y");
+
+    locals.iter().all(|l| match l {
+        &Name::Local { ref ty, .. } => !occurs(&data.name, ty),
+        _ => true,
+    }) && is_inductive_app(data, &result)
+}
+
+/// If `arg_ty` is (a function returning) an application of the inductive type
+/// being defined, return the binders of the function along with the indices.
+fn recursive_arg(env: &Environment, data: &Data, arg_ty: &Term) -> Option<(Vec<Name>, Vec<Term>)> {
+    let (locals, result) = telescope(env, arg_ty, "y");
+
+    if is_inductive_app(data, &result) {
+        let (_, args) = result.uncurry();
+        let indices = args.into_iter().skip(data.parameters.len()).collect();
+        Some((locals, indices))
+    } else {
+        None
+    }
+}
+
+pub fn check_constructor(env: &Environment,
+                         data: &Data,
+                         ctor: &Name,
+                         ctor_ty: &Term) -> Result<(), Error> {
+    let (params, _) = telescope(env, &data.ty, "p");
+    let params: Vec<_> = to_terms(&params[..data.parameters.len()]);
+
+    let mut ty = instantiate_params(ctor_ty, &params);
+
+    while let Term::Forall { binder, term, .. } = ty {
+        if !is_positive(env, data, &binder.ty) {
+            return Err(Error::NotPositive(data.name.clone(), ctor.clone()));
+        }
+
+        let local = env.fresh_local(&binder);
+        ty = term.instantiate(&local.to_term());
+    }
+
+    let (head, args) = ty.uncurry();
+
+    let returns_params =
+        args.len() >= params.len() &&
+        args.iter().zip(params.iter()).all(|(a, p)| a == p);
+
+    if head == data.name.to_term() && returns_params {
+        Ok(())
+    } else {
+        Err(Error::BadConstructor(data.name.clone(), ctor.clone()))
+    }
+}
+
+/// The position of the value of the inductive type among the arguments of a
+/// function defined with it, following the parameters and indices.
+pub fn major_index(data: &Data) -> usize {
+    count_binders(&data.ty)
+}
+
+/// Check the function defined along with `data` has exactly one well typed
+/// equation for each constructor, which only calls the function on the
+/// constructor's fields.
+pub fn check_equations(env: &Environment,
+                       data: &Data,
+                       rec: &RecursiveFn) -> Result<(), Error> {
+    for eq in &rec.equations {
+        if !data.ctors.iter().any(|c| c.0 == eq.0) {
+            return Err(Error::BadConstructor(data.name.clone(), eq.0.clone()));
+        }
+    }
+
+    for &(ref ctor, ref ctor_ty) in &data.ctors {
+        let rhs = match rec.equations.iter().find(|eq| &eq.0 == ctor) {
+            None => return Err(Error::MissingEquation(rec.name.clone(), ctor.clone())),
+            Some(&(_, ref rhs)) => rhs,
+        };
+
+        // This is checked first, as checking the equation's type could
+        // unfold a call that is not on a field forever.
+        let num_params = data.parameters.len();
+        let fields = count_binders(ctor_ty) - num_params;
+
+        if !RecursiveFn::is_structural(&rec.name, major_index(data), num_params, fields, rhs) {
+            return Err(Error::NotStructural(rec.name.clone(), ctor.clone()));
+        }
+
+        // Open the constructor, the type of the equation is the type of the
+        // function applied to the constructor's parameters, indices, and
+        // the constructor itself.
+        let (args, result) = telescope(env, ctor_ty, "a");
+        let ctor_app = Term::apply_all(ctor.to_term(), to_terms(&args));
+        let (_, ty_args) = result.uncurry();
+
+        let expected = match instantiate_params(&rec.ty, &ty_args) {
+            Term::Forall { term, .. } => term.instantiate(&ctor_app),
+            _ => return Err(Error::ExpectedFunction(rec.name.to_term(), rec.ty.clone())),
+        };
+
+        try!(env.check(&Term::apply_all(rhs.clone(), to_terms(&args)), &expected));
+    }
+
+    Ok(())
+}
+
+pub fn recursor_type(env: &Environment, data: &Data) -> Term {
+    let num_params = data.parameters.len();
+    let (mut params, _) = telescope(env, &data.ty, "p");
+    params.truncate(num_params);
+    let param_terms = to_terms(&params);
+
+    let indexed_ty = instantiate_params(&data.ty, &param_terms);
+    let ty_with_params = Term::apply_all(data.name.to_term(), param_terms.clone());
+
+    // The motive `C : forall I, T P I -> Type`.
+    let (motive_indices, _) = telescope(env, &indexed_ty, "a");
+    let motive_scrut = env.local(
+        "x".to_string(),
+        Term::apply_all(ty_with_params.clone(), to_terms(&motive_indices)),
+        BindingMode::Explicit);
+
+    let mut motive_binders = motive_indices;
+    motive_binders.push(motive_scrut);
+
+    let motive = env.local(
+        "C".to_string(),
+        Term::abstract_pi(motive_binders, Term::Type),
+        BindingMode::Explicit);
+
+    let minor_premises: Vec<_> =
+        data.ctors.iter().map(|&(ref ctor, ref ctor_ty)| {
+            let ctor_ty = instantiate_params(ctor_ty, &param_terms);
+            let (fields, result) = telescope(env, &ctor_ty, "f");
+
+            let mut hyps = vec![];
+            for field in &fields {
+                let field_ty = match field {
+                    &Name::Local { ref ty, .. } => (**ty).clone(),
+                    _ => unreachable!(),
+                };
+
+                if let Some((ys, indices)) = recursive_arg(env, data, &field_ty) {
+                    let mut motive_args = indices;
+                    motive_args.push(Term::apply_all(field.to_term(), to_terms(&ys)));
+
+                    let hyp_ty = Term::abstract_pi(
+                        ys,
+                        Term::apply_all(motive.to_term(), motive_args));
+
+                    hyps.push(env.local("ih".to_string(), hyp_ty, BindingMode::Explicit));
+                }
+            }
+
+            let (_, result_args) = result.uncurry();
+            let mut motive_args: Vec<_> =
+                result_args.into_iter().skip(num_params).collect();
+
+            motive_args.push(
+                Term::apply_all(
+                    Term::apply_all(ctor.to_term(), param_terms.clone()),
+                    to_terms(&fields)));
+
+            let minor_ty = Term::abstract_pi(
+                fields,
+                Term::abstract_pi(
+                    hyps,
+                    Term::apply_all(motive.to_term(), motive_args)));
+
+            env.local("m".to_string(), minor_ty, BindingMode::Explicit)
+        }).collect();
+
+    // The major premise `forall I (x : T P I), C I x`.
+    let (indices, _) = telescope(env, &indexed_ty, "i");
+    let major = env.local(
+        "c".to_string(),
+        Term::apply_all(ty_with_params, to_terms(&indices)),
+        BindingMode::Explicit);
+
+    let mut major_binders = indices;
+    major_binders.push(major);
+
+    let conclusion = Term::apply_all(motive.to_term(), to_terms(&major_binders));
+
+    Term::abstract_pi_implicit(
+        params,
+        Term::abstract_pi(
+            vec![motive],
+            Term::abstract_pi(
+                minor_premises,
+                Term::abstract_pi(major_binders, conclusion))))
+}
+
+pub fn count_binders(ty: &Term) -> usize {
+    let mut count = 0;
+    let mut ty = ty;
+
+    while let &Term::Forall { ref term, .. } = ty {
+        count += 1;
+        ty = term;
+    }
+
+    count
+}
+
+/// Attempt to reduce an application of a recursor, this succeeds only when the
+/// major premise is (or reduces to) a constructor application.
+pub fn iota_reduce(env: &Environment,
+                   data: &Data,
+                   rec_name: &Name,
+                   args: &[Term]) -> Option<Term> {
+    let num_params = data.parameters.len();
+    let num_minors = data.ctors.len();
+    let num_indices = count_binders(&data.ty) - num_params;
+    let major_idx = num_params + 1 + num_minors + num_indices;
+
+    if args.len() <= major_idx {
+        return None;
+    }
+
+    let major = env.whnf(&args[major_idx]);
+    let (ctor_head, ctor_args) = major.uncurry();
+
+    let (ctor_no, ctor_ty) =
+        match data.ctors.iter().position(|c| c.0.to_term() == ctor_head) {
+            None => return None,
+            Some(i) => (i, &data.ctors[i].1),
+        };
+
+    let params = &args[..num_params];
+    // The arguments up to, but not including the indices.
+    let rec_prefix = &args[..num_params + 1 + num_minors];
+    let minor = args[num_params + 1 + ctor_no].clone();
+
+    let fields: Vec<_> = ctor_args.into_iter().skip(num_params).collect();
+
+    // Compute the induction hypotheses for each recursive field.
+    let mut hyps = vec![];
+    let mut ty = instantiate_params(ctor_ty, params);
+
+    for field in &fields {
+        let (binder, body) = match ty {
+            Term::Forall { binder, term, .. } => (binder, term),
+            _ => return None,
+        };
+
+        if let Some((ys, indices)) = recursive_arg(env, data, &binder.ty) {
+            let mut rec_args = rec_prefix.to_vec();
+            rec_args.extend(indices.into_iter());
+            rec_args.push(Term::apply_all(field.clone(), to_terms(&ys)));
+
+            hyps.push(Term::abstract_lambda(
+                ys,
+                Term::apply_all(rec_name.to_term(), rec_args)));
+        }
+
+        ty = body.instantiate(field);
+    }
+
+    let mut minor_args = fields;
+    minor_args.extend(hyps.into_iter());
+    minor_args.extend(args[major_idx + 1..].iter().cloned());
+
+    Some(Term::apply_all(minor, minor_args))
+}
@@ -0,0 +1,27 @@
+//! The trusted kernel of Hubris.
+//!
+//! This crate contains the core term representation along with a small,
+//! independent type checker for fully elaborated terms. The kernel knows
+//! nothing about surface syntax, meta-variables, or constraint solving, every
+//! term handed to it must be closed and fully explicit. Anything accepted by
+//! the elaborator is re-checked here, so this crate (along with the parts of
+//! `hubris_syntax` it uses for spans) is the trusted computing base.
+
+#[macro_use]
+extern crate log;
+extern crate hubris_syntax;
+extern crate pretty;
+
+pub mod ast {
+    pub use hubris_syntax::ast::*;
+}
+
+pub mod core;
+pub mod environment;
+pub mod export;
+pub mod guard;
+pub mod import;
+pub mod sexp;
+mod inductive;
+
+pub use self::environment::{Environment, Declaration, Error};
@@ -0,0 +1,154 @@
+//! Emission of elaborated modules as s-expressions.
+//!
+//! Unlike the export format this is a dump of the elaborator's output rather
+//! than the kernel's environment, it is meant for external tools and for
+//! diffing the elaborator's behaviour, so every declaration and term carries
+//! its span:
+//!
+//! ```text
+//! (module <name> <item>*)
+//! (inductive <name> <span> <num-params> <type> (ctor <name> <span> <type>)* <with>?)
+//! (with <name> <span> <type> (eq <name> <term>)*)
+//! (def <name> <span> <type> <body>)
+//! (axiom <name> <span> <type>)
+//! (extern <name> <span> <type>)
+//! ```
+//!
+//! Terms are written as in the export format with a span after the tag:
+//!
+//! ```text
+//! (const <span> <name>)
+//! (bvar <span> <index>)
+//! (local <span> <name> <number>)
+//! (meta <span> <number>)
+//! (app <span> <term> <term>)
+//! (pi <span> <name> <mode> <term> <term>)
+//! (lam <span> <name> <mode> <term> <term>)
+//! (let <span> <name> <type> <value> <body>)
+//! (fix <span> <name> <type> <rec-arg> <body>)
+//! (type)
+//! ```
+//!
+//! A span is written `(span <module-id> <lo> <hi>)`.
+
+use ast::{HasSpan, Span};
+use core::{Binder, Item, Module, Name, Term};
+use super::export::{export_mode, export_name};
+
+use std::io::{self, Write};
+
+pub fn emit_module<W: Write>(module: &Module, out: &mut W) -> io::Result<()> {
+    try!(writeln!(out, "(module {}", export_name(&module.name)));
+
+    for item in &module.defs {
+        try!(writeln!(out, "  {}", emit_item(item)));
+    }
+
+    writeln!(out, ")")
+}
+
+pub fn emit_item(item: &Item) -> String {
+    match item {
+        &Item::Data(ref data) => {
+            let mut ctors: Vec<_> =
+                data.ctors
+                    .iter()
+                    .map(|&(ref n, ref ty)| {
+                        format!("(ctor {} {} {})",
+                                export_name(n),
+                                emit_span(n.get_span()),
+                                emit_term(ty))
+                    })
+                    .collect();
+
+            if let Some(ref rec) = data.recursive {
+                let equations: Vec<_> =
+                    rec.equations
+                       .iter()
+                       .map(|&(ref n, ref rhs)| {
+                           format!("(eq {} {})", export_name(n), emit_term(rhs))
+                       })
+                       .collect();
+
+                ctors.push(format!("(with {} {} {} {})",
+                                   export_name(&rec.name),
+                                   emit_span(rec.name.get_span()),
+                                   emit_term(&rec.ty),
+                                   equations.join(" ")));
+            }
+
+            format!("(inductive {} {} {} {} {})",
+                    export_name(&data.name),
+                    emit_span(data.span),
+                    data.parameters.len(),
+                    emit_term(&data.ty),
+                    ctors.join(" "))
+        }
+        &Item::Fn(ref def) =>
+            format!("(def {} {} {} {})",
+                    export_name(&def.name),
+                    emit_span(def.name.get_span()),
+                    emit_term(&def.ty),
+                    emit_term(&def.body)),
+        &Item::Axiom(ref ax) =>
+            format!("(axiom {} {} {})",
+                    export_name(&ax.name),
+                    emit_span(ax.span),
+                    emit_term(&ax.ty)),
+        &Item::Extern(ref ext) =>
+            format!("(extern {} {} {})",
+                    export_name(&ext.name),
+                    emit_span(ext.span),
+                    emit_term(&ext.term)),
+    }
+}
+
+fn emit_span(span: Span) -> String {
+    format!("(span {} {} {})", span.module_id.0, span.lo, span.hi)
+}
+
+
+fn emit_binder(kind: &str, span: Span, binder: &Binder, body: &Term) -> String {
+    format!("({} {} {} {} {} {})",
+            kind,
+            emit_span(span),
+            export_name(&binder.name),
+            export_mode(binder),
+            emit_term(&binder.ty),
+            emit_term(body))
+}
+
+pub fn emit_term(term: &Term) -> String {
+    let span = emit_span(term.get_span());
+
+    match term {
+        &Term::Var { ref name } => match name {
+            &Name::DeBruijn { index, .. } => format!("(bvar {} {})", span, index),
+            &Name::Qual { .. } => format!("(const {} {})", span, export_name(name)),
+            &Name::Local { number, .. } =>
+                format!("(local {} {} {})", span, export_name(name), number),
+            &Name::Meta { number, .. } => format!("(meta {} {})", span, number),
+        },
+        &Term::App { ref fun, ref arg, .. } =>
+            format!("(app {} {} {})", span, emit_term(fun), emit_term(arg)),
+        &Term::Forall { ref binder, ref term, span } =>
+            emit_binder("pi", span, binder, term),
+        &Term::Lambda { ref binder, ref body, span } =>
+            emit_binder("lam", span, binder, body),
+        &Term::Let { ref binder, ref value, ref body, span } =>
+            format!("(let {} {} {} {} {})",
+                    emit_span(span),
+                    export_name(&binder.name),
+                    emit_term(&binder.ty),
+                    emit_term(value),
+                    emit_term(body)),
+        &Term::Fix { ref binder, rec_arg, ref body, span } =>
+            format!("(fix {} {} {} {} {})",
+                    emit_span(span),
+                    export_name(&binder.name),
+                    emit_term(&binder.ty),
+                    rec_arg,
+                    emit_term(body)),
+        &Term::Type => "(type)".to_string(),
+    }
+}
@@ -0,0 +1,17 @@
+// The types of the runtime of the JavaScript backend, see `index.js`.
+
+export type Obj = unknown;
+
+export class Data {
+    constructor(tag: number, fields: Obj[]);
+    tag: number;
+    fields: Obj[];
+}
+
+export function Type(): typeof Type;
+
+export class HubrisPanic extends Error {}
+
+export function construct(tag: number, fields: Obj[]): Data;
+export function call(f: Function, args: Obj[]): Obj;
+export function panic(message: string): never;
@@ -0,0 +1,112 @@
+// The runtime of the JavaScript backend.
+//
+// Generated code represents the values of an inductive type as `Data`, the
+// position of their constructor and its relevant arguments, except that a
+// type with a single constructor is an array of its fields and a type whose
+// constructors store no fields is the number of the constructor. Functions
+// are JavaScript functions, called through `call` when the number of
+// arguments they take is not known. The standard library's `Nat` is a
+// `BigInt`, and `UInt32` and `UInt64` are a number and a `BigInt`.
+//
+// The compiler copies this file next to the code it generates, the same
+// file is published as the `hubris-runtime` package.
+
+"use strict";
+
+class Data {
+    constructor(tag, fields) {
+        this.tag = tag;
+        this.fields = fields;
+    }
+}
+
+// The value built by the constructor numbered `tag` from `fields`.
+function construct(tag, fields) {
+    return new Data(tag, fields);
+}
+
+// Call `f` with `args`, returning a function waiting for the rest of its
+// arguments when given too few, and calling the result with the remaining
+// ones when given too many.
+function call(f, args) {
+    for (;;) {
+        if (args.length === f.length) {
+            return f(...args);
+        }
+
+        if (args.length < f.length) {
+            return (...rest) => call(f, args.concat(rest));
+        }
+
+        const taken = f.length;
+        f = f(...args.slice(0, taken));
+        args = args.slice(taken);
+    }
+}
+
+// The value passed for a type, which is never inspected.
+function Type() {
+    return Type;
+}
+
+class HubrisPanic extends Error {}
+
+// Stop the program, a definition reached a case it does not handle.
+function panic(message) {
+    throw new HubrisPanic(message);
+}
+
+const nat = {
+    zero: () => 0n,
+    succ: (n) => n + 1n,
+    // Zero has no predecessor, so is its own.
+    pred: (n) => n === 0n ? 0n : n - 1n,
+    // The tag of the constructor `n` would be built with, `Z` or `S`.
+    tag: (n) => n === 0n ? 0 : 1,
+    add: (a, b) => a + b,
+    // Subtraction stops at zero.
+    sub: (a, b) => a > b ? a - b : 0n,
+    mul: (a, b) => a * b,
+};
+
+// The operations on unsigned integers of `bits` bits, wrapping operations
+// compute modulo 2^bits and checked ones panic on overflow. `from` and `to`
+// convert between the representation and a `BigInt`.
+function uint(name, bits, from, to) {
+    const max = (1n << BigInt(bits)) - 1n;
+
+    const checked = (op, what) => (a, b) => {
+        const result = op(to(a), to(b));
+        if (result === null || result < 0n || result > max) {
+            panic(`hubris: ${name} ${what} overflowed`);
+        }
+        return from(result);
+    };
+
+    return {
+        zero: () => from(0n),
+        one: () => from(1n),
+        add_wrapping: (a, b) => from(BigInt.asUintN(bits, to(a) + to(b))),
+        sub_wrapping: (a, b) => from(BigInt.asUintN(bits, to(a) - to(b))),
+        mul_wrapping: (a, b) => from(BigInt.asUintN(bits, to(a) * to(b))),
+        add_checked: checked((a, b) => a + b, "addition"),
+        sub_checked: checked((a, b) => a - b, "subtraction"),
+        mul_checked: checked((a, b) => a * b, "multiplication"),
+        // Division panics when dividing by zero, it can not overflow.
+        div_checked: checked((a, b) => b === 0n ? null : a / b, "division"),
+    };
+}
+
+const uint32 = uint("UInt32", 32, (n) => Number(n), (n) => BigInt(n));
+const uint64 = uint("UInt64", 64, (n) => n, (n) => n);
+
+module.exports = {
+    Data,
+    Type,
+    HubrisPanic,
+    construct,
+    call,
+    panic,
+    nat,
+    uint: { uint32, uint64 },
+};
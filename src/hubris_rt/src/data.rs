@@ -0,0 +1,52 @@
+//! Values of inductive types.
+//!
+//! A value records which constructor built it, by the position of the
+//! constructor in the type's declaration, along with the constructor's
+//! relevant arguments. The parameters of the type are not stored.
+//!
+//! A value of a type with a single constructor needs no tag, the compiler
+//! declares a struct for the type holding its fields instead, or when there
+//! is a single field represents the value by the field. A value of a type
+//! whose constructors store no fields is just its tag, see `tag`.
+
+use std::cell::RefCell;
+
+use super::Obj;
+
+pub struct Data {
+    pub tag: usize,
+    pub fields: Vec<Obj>,
+}
+
+/// The value built by the constructor numbered `tag` from `fields`.
+pub fn construct(tag: usize, fields: Vec<Obj>) -> Obj {
+    Obj::from(Data {
+        tag: tag,
+        fields: fields,
+    })
+}
+
+thread_local! {
+    static TAGS: RefCell<Vec<Obj>> = RefCell::new(vec![]);
+}
+
+/// The value built by the constructor numbered `tag` of a type whose
+/// constructors store no fields. The values are shared, so building one
+/// does not allocate.
+pub fn tag(tag: usize) -> Obj {
+    TAGS.with(|tags| {
+        let mut tags = tags.borrow_mut();
+
+        while tags.len() <= tag {
+            let next = tags.len();
+            tags.push(Obj::from(next));
+        }
+
+        tags[tag].clone()
+    })
+}
+
+/// The tag of a value built by `tag`.
+pub fn tag_of(obj: &Obj) -> usize {
+    *obj.unbox::<usize>()
+}
@@ -0,0 +1,62 @@
+//! The Hubris definitions being evaluated, reported when a program panics.
+//!
+//! A program compiled with `-g` enters a frame naming the definition and
+//! its location in the Hubris source at the start of each generated
+//! function, and leaves it when the function returns. The panic hook
+//! prints these frames after the panic message, as Rust's own backtrace
+//! only shows mangled identifiers and closures.
+
+use std::cell::RefCell;
+use std::panic;
+
+use super::target;
+
+thread_local! {
+    static FRAMES: RefCell<Vec<(&'static str, &'static str)>> = RefCell::new(vec![]);
+}
+
+/// A frame that has been entered, it is left when this is dropped.
+pub struct Frame {
+    _private: (),
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        FRAMES.with(|frames| {
+            frames.borrow_mut().pop();
+        });
+    }
+}
+
+/// Enter the frame of the definition `name`, found at `location`.
+pub fn enter(name: &'static str, location: &'static str) -> Frame {
+    FRAMES.with(|frames| frames.borrow_mut().push((name, location)));
+    Frame { _private: () }
+}
+
+/// The frames that have been entered and not yet left, innermost first.
+pub fn backtrace() -> Vec<(&'static str, &'static str)> {
+    FRAMES.with(|frames| frames.borrow().iter().rev().cloned().collect())
+}
+
+/// Print the frames on panic, after the message.
+pub fn install_panic_hook() {
+    let report = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        report(info);
+
+        let frames = backtrace();
+
+        if frames.is_empty() {
+            return;
+        }
+
+        target::print_error_line("hubris backtrace:");
+
+        for (i, &(name, location)) in frames.iter().enumerate() {
+            target::print_error_line(&format!("  {}: {}", i, name));
+            target::print_error_line(&format!("        at {}", location));
+        }
+    }));
+}
@@ -1,10 +1,21 @@
+pub mod data;
+pub mod frame;
+pub mod marshal;
+pub mod nat;
+pub mod target;
+pub mod thunk;
+pub mod uint;
+
 use std::rc::Rc;
 use std::mem::transmute;
 
+use thunk::Thunk;
+
 struct ObjValue {
     ptr: *mut usize,
 }
 
+#[derive(Clone)]
 pub struct Obj(Rc<ObjValue>);
 
 impl Obj {
@@ -24,4 +35,14 @@ impl Obj {
         let ptr: *mut usize = self.0.ptr;
         unsafe { transmute(ptr) }
     }
+
+    /// Suspend the computation of a value until it is forced.
+    pub fn delay<F: FnOnce() -> Obj + 'static>(f: F) -> Obj {
+        Obj::from(Thunk::new(f))
+    }
+
+    /// Force an object created by `delay`.
+    pub fn force(&self) -> Obj {
+        self.unbox::<Thunk>().force()
+    }
 }
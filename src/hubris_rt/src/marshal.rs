@@ -0,0 +1,54 @@
+//! Conversions between Rust values and objects.
+//!
+//! The wrappers of a library generated from Hubris code take and return
+//! ordinary Rust values, converting them with this trait at the boundary, as
+//! do calls of externs implemented in other crates. The generated crate
+//! implements it for the enums and structs it declares for Hubris data types.
+
+use super::Obj;
+
+pub trait Marshal {
+    fn from_obj(obj: &Obj) -> Self;
+    fn into_obj(self) -> Obj;
+}
+
+impl Marshal for Obj {
+    fn from_obj(obj: &Obj) -> Obj {
+        obj.clone()
+    }
+
+    fn into_obj(self) -> Obj {
+        self
+    }
+}
+
+impl Marshal for u32 {
+    fn from_obj(obj: &Obj) -> u32 {
+        *obj.unbox::<u32>()
+    }
+
+    fn into_obj(self) -> Obj {
+        Obj::from(self)
+    }
+}
+
+impl Marshal for u64 {
+    fn from_obj(obj: &Obj) -> u64 {
+        *obj.unbox::<u64>()
+    }
+
+    fn into_obj(self) -> Obj {
+        Obj::from(self)
+    }
+}
+
+/// Recursive data types hold themselves in a box.
+impl<T: Marshal> Marshal for Box<T> {
+    fn from_obj(obj: &Obj) -> Box<T> {
+        Box::new(T::from_obj(obj))
+    }
+
+    fn into_obj(self) -> Obj {
+        (*self).into_obj()
+    }
+}
@@ -0,0 +1,242 @@
+//! Natural numbers, the representation of the standard library's `Nat`.
+//!
+//! Instead of a chain of `S` constructors a number that fits in a `u64` is
+//! stored directly, and a larger one as its digits in base 2^32, least
+//! significant first. Each operation takes the `u64` path when it can.
+//!
+//! The compiler builds `Z` and `S` with `zero` and `succ`, matches on a
+//! number with `tag`, and calls the operations here in place of the
+//! definitions in `lib/Nat.hbr`.
+
+use std::cmp::{self, Ordering};
+
+use super::Obj;
+use super::data;
+use super::marshal::Marshal;
+
+/// A natural number. `Big` only holds numbers that do not fit in a `u64`,
+/// and has no leading zeros, so each number has a single representation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Nat {
+    Small(u64),
+    Big(Vec<u32>),
+}
+
+impl Nat {
+    /// The number whose digits are `digits`, which may have leading zeros.
+    fn from_digits(mut digits: Vec<u32>) -> Nat {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+
+        if digits.len() <= 2 {
+            let mut n = 0;
+            for (i, &digit) in digits.iter().enumerate() {
+                n |= (digit as u64) << (32 * i);
+            }
+            Nat::Small(n)
+        } else {
+            Nat::Big(digits)
+        }
+    }
+
+    fn digits(&self) -> Vec<u32> {
+        match *self {
+            Nat::Small(n) => vec![n as u32, (n >> 32) as u32],
+            Nat::Big(ref digits) => digits.clone(),
+        }
+    }
+
+    fn add(&self, other: &Nat) -> Nat {
+        if let (&Nat::Small(a), &Nat::Small(b)) = (self, other) {
+            if let Some(n) = a.checked_add(b) {
+                return Nat::Small(n);
+            }
+        }
+
+        let (a, b) = (self.digits(), other.digits());
+        let len = cmp::max(a.len(), b.len());
+        let mut digits = Vec::with_capacity(len + 1);
+        let mut carry = 0;
+
+        for i in 0..len {
+            let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+            digits.push(sum as u32);
+            carry = sum >> 32;
+        }
+
+        digits.push(carry as u32);
+        Nat::from_digits(digits)
+    }
+
+    /// `self - other`, or zero when `other` is larger.
+    fn sub(&self, other: &Nat) -> Nat {
+        if let (&Nat::Small(a), &Nat::Small(b)) = (self, other) {
+            return Nat::Small(a.saturating_sub(b));
+        }
+
+        if self <= other {
+            return Nat::Small(0);
+        }
+
+        let (a, b) = (self.digits(), other.digits());
+        let mut digits = Vec::with_capacity(a.len());
+        let mut borrow = 0;
+
+        for i in 0..a.len() {
+            let diff = a[i] as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+
+            if diff < 0 {
+                digits.push((diff + (1 << 32)) as u32);
+                borrow = 1;
+            } else {
+                digits.push(diff as u32);
+                borrow = 0;
+            }
+        }
+
+        Nat::from_digits(digits)
+    }
+
+    fn mul(&self, other: &Nat) -> Nat {
+        if let (&Nat::Small(a), &Nat::Small(b)) = (self, other) {
+            if let Some(n) = a.checked_mul(b) {
+                return Nat::Small(n);
+            }
+        }
+
+        let (a, b) = (self.digits(), other.digits());
+        let mut digits = vec![0; a.len() + b.len()];
+
+        for i in 0..a.len() {
+            let mut carry = 0;
+
+            for j in 0..b.len() {
+                let product = digits[i + j] as u64 + a[i] as u64 * b[j] as u64 + carry;
+                digits[i + j] = product as u32;
+                carry = product >> 32;
+            }
+
+            digits[i + b.len()] = carry as u32;
+        }
+
+        Nat::from_digits(digits)
+    }
+}
+
+impl PartialOrd for Nat {
+    fn partial_cmp(&self, other: &Nat) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Nat {
+    fn cmp(&self, other: &Nat) -> Ordering {
+        match (self, other) {
+            (&Nat::Small(a), &Nat::Small(b)) => a.cmp(&b),
+            (&Nat::Small(_), &Nat::Big(_)) => Ordering::Less,
+            (&Nat::Big(_), &Nat::Small(_)) => Ordering::Greater,
+            (&Nat::Big(ref a), &Nat::Big(ref b)) =>
+                a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev())),
+        }
+    }
+}
+
+impl From<u64> for Nat {
+    fn from(n: u64) -> Nat {
+        Nat::Small(n)
+    }
+}
+
+impl Marshal for Nat {
+    fn from_obj(obj: &Obj) -> Nat {
+        value(obj).clone()
+    }
+
+    fn into_obj(self) -> Obj {
+        Obj::from(self)
+    }
+}
+
+fn value(obj: &Obj) -> &Nat {
+    obj.unbox::<Nat>()
+}
+
+pub fn zero() -> Obj {
+    Obj::from(Nat::Small(0))
+}
+
+pub fn succ(n: Obj) -> Obj {
+    Obj::from(value(&n).add(&Nat::Small(1)))
+}
+
+/// The number before `n`, zero has none so is its own predecessor.
+pub fn pred(n: Obj) -> Obj {
+    Obj::from(value(&n).sub(&Nat::Small(1)))
+}
+
+/// The tag of the constructor `n` would be built with, `Z` or `S`.
+pub fn tag(n: Obj) -> Obj {
+    match *value(&n) {
+        Nat::Small(0) => data::tag(0),
+        _ => data::tag(1),
+    }
+}
+
+pub fn add(a: Obj, b: Obj) -> Obj {
+    Obj::from(value(&a).add(value(&b)))
+}
+
+/// Subtraction stops at zero.
+pub fn sub(a: Obj, b: Obj) -> Obj {
+    Obj::from(value(&a).sub(value(&b)))
+}
+
+pub fn mul(a: Obj, b: Obj) -> Obj {
+    Obj::from(value(&a).mul(value(&b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Nat;
+
+    const MAX: u64 = ::std::u64::MAX;
+
+    #[test]
+    fn add_carries_across_limbs() {
+        assert_eq!(Nat::Small(MAX).add(&Nat::Small(1)), Nat::Big(vec![0, 0, 1]));
+        assert_eq!(Nat::Big(vec![::std::u32::MAX, ::std::u32::MAX, 1]).add(&Nat::Small(1)),
+                   Nat::Big(vec![0, 0, 2]));
+    }
+
+    #[test]
+    fn mul_overflows_into_big() {
+        assert_eq!(Nat::Small(1 << 32).mul(&Nat::Small(1 << 32)), Nat::Big(vec![0, 0, 1]));
+        assert_eq!(Nat::Small(MAX).mul(&Nat::Small(MAX)),
+                   Nat::Big(vec![1, 0, ::std::u32::MAX - 1, ::std::u32::MAX]));
+    }
+
+    #[test]
+    fn sub_truncates_to_zero() {
+        assert_eq!(Nat::Small(3).sub(&Nat::Small(5)), Nat::Small(0));
+        assert_eq!(Nat::Small(MAX).sub(&Nat::Big(vec![0, 0, 1])), Nat::Small(0));
+        assert_eq!(Nat::Big(vec![0, 0, 1]).sub(&Nat::Big(vec![0, 0, 1])), Nat::Small(0));
+    }
+
+    #[test]
+    fn big_is_normalised_to_small() {
+        assert_eq!(Nat::Big(vec![0, 0, 1]).sub(&Nat::Small(1)), Nat::Small(MAX));
+        assert_eq!(Nat::from_digits(vec![5, 0, 0, 0]), Nat::Small(5));
+    }
+
+    #[test]
+    fn small_and_big_compare() {
+        let big = Nat::Small(MAX).add(&Nat::Small(1));
+
+        assert!(Nat::Small(MAX) < big);
+        assert!(big > Nat::Small(0));
+        assert!(Nat::Big(vec![0, 0, 1]) < Nat::Big(vec![0, 0, 2]));
+        assert!(Nat::Big(vec![::std::u32::MAX, 0, 1]) < Nat::Big(vec![0, 1, 1]));
+        assert!(Nat::Big(vec![0, 0, 0, 1]) > Nat::Big(vec![::std::u32::MAX; 3]));
+    }
+}
@@ -0,0 +1,52 @@
+//! Target-specific configuration of the runtime.
+//!
+//! Generated programs only talk to the outside world through this module, so
+//! porting the runtime to a new target means providing these functions.
+
+/// Write `s` followed by a newline to the program's output.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn print_line(s: &str) {
+    println!("{}", s);
+}
+
+/// Write `s` followed by a newline to the program's output.
+///
+/// There is no standard output on wasm, so the embedder must provide
+/// `hubris_print`.
+#[cfg(target_arch = "wasm32")]
+pub fn print_line(s: &str) {
+    extern "C" {
+        fn hubris_print(ptr: *const u8, len: usize);
+    }
+
+    unsafe { hubris_print(s.as_ptr(), s.len()) }
+}
+
+/// Write `s` followed by a newline to the program's error output.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn print_error_line(s: &str) {
+    use std::io::{self, Write};
+    let _ = writeln!(io::stderr(), "{}", s);
+}
+
+/// Write `s` followed by a newline to the program's error output, which on
+/// wasm is its only output.
+#[cfg(target_arch = "wasm32")]
+pub fn print_error_line(s: &str) {
+    print_line(s)
+}
+
+/// Stop the program after an unrecoverable error.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn abort(msg: &str) -> ! {
+    panic!("{}", msg)
+}
+
+/// Stop the program after an unrecoverable error.
+///
+/// Unwinding is not supported on wasm, so we report the message and trap.
+#[cfg(target_arch = "wasm32")]
+pub fn abort(msg: &str) -> ! {
+    print_line(msg);
+    ::std::process::abort()
+}
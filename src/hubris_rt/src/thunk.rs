@@ -0,0 +1,44 @@
+//! Suspended computations used by code generated with lazy evaluation.
+//!
+//! In lazy mode every argument is wrapped in a thunk by the caller and forced
+//! where it is used, a thunk runs its computation at most once and shares the
+//! result between every use.
+
+use std::cell::RefCell;
+use std::mem;
+use super::Obj;
+
+enum State {
+    Delayed(Box<FnOnce() -> Obj>),
+    /// The thunk is being forced, forcing it again means the value depends
+    /// on itself.
+    Forcing,
+    Forced(Obj),
+}
+
+pub struct Thunk {
+    state: RefCell<State>,
+}
+
+impl Thunk {
+    pub fn new<F: FnOnce() -> Obj + 'static>(f: F) -> Thunk {
+        Thunk {
+            state: RefCell::new(State::Delayed(Box::new(f))),
+        }
+    }
+
+    /// Compute the value of the thunk, or return it if it has already been
+    /// computed.
+    pub fn force(&self) -> Obj {
+        let state = mem::replace(&mut *self.state.borrow_mut(), State::Forcing);
+
+        let value = match state {
+            State::Delayed(f) => f(),
+            State::Forcing => panic!("hubris: a lazy value depends on itself"),
+            State::Forced(value) => value,
+        };
+
+        *self.state.borrow_mut() = State::Forced(value.clone());
+        value
+    }
+}
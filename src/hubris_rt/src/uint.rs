@@ -0,0 +1,65 @@
+//! Fixed-width unsigned integers.
+//!
+//! The operations declared in `lib/UInt.hbr` are implemented here. Each
+//! arithmetic operation comes in two forms: `wrapping` operations compute
+//! modulo 2^n, and `checked` operations abort the program on overflow.
+
+macro_rules! uint_primitives {
+    ($module:ident, $ty:ty, $name:expr) => {
+        pub mod $module {
+            use super::super::Obj;
+            use super::super::target::abort;
+
+            fn value(obj: &Obj) -> $ty {
+                *obj.unbox::<$ty>()
+            }
+
+            fn checked(result: Option<$ty>, op: &str) -> Obj {
+                match result {
+                    Some(n) => Obj::from(n),
+                    None => abort(&format!("hubris: {} {} overflowed", $name, op)),
+                }
+            }
+
+            pub fn zero() -> Obj {
+                Obj::from(0 as $ty)
+            }
+
+            pub fn one() -> Obj {
+                Obj::from(1 as $ty)
+            }
+
+            pub fn add_wrapping(a: Obj, b: Obj) -> Obj {
+                Obj::from(value(&a).wrapping_add(value(&b)))
+            }
+
+            pub fn sub_wrapping(a: Obj, b: Obj) -> Obj {
+                Obj::from(value(&a).wrapping_sub(value(&b)))
+            }
+
+            pub fn mul_wrapping(a: Obj, b: Obj) -> Obj {
+                Obj::from(value(&a).wrapping_mul(value(&b)))
+            }
+
+            pub fn add_checked(a: Obj, b: Obj) -> Obj {
+                checked(value(&a).checked_add(value(&b)), "addition")
+            }
+
+            pub fn sub_checked(a: Obj, b: Obj) -> Obj {
+                checked(value(&a).checked_sub(value(&b)), "subtraction")
+            }
+
+            pub fn mul_checked(a: Obj, b: Obj) -> Obj {
+                checked(value(&a).checked_mul(value(&b)), "multiplication")
+            }
+
+            /// Division aborts when dividing by zero, it can not overflow.
+            pub fn div_checked(a: Obj, b: Obj) -> Obj {
+                checked(value(&a).checked_div(value(&b)), "division")
+            }
+        }
+    }
+}
+
+uint_primitives!(uint32, u32, "UInt32");
+uint_primitives!(uint64, u64, "UInt64");
@@ -1,10 +1,12 @@
 use std::fmt::{self, Display, Formatter};
+use std::cmp::Ordering;
 use std::hash::{Hash, Hasher};
 use std::path::PathBuf;
 
 use pretty::*;
 
 pub use parser::SourceMap;
+use tok::escape_identifier;
 
 pub trait HasSpan {
     fn get_span(&self) -> Span;
@@ -37,7 +39,7 @@ impl Span {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, Eq)]
 pub struct Name {
     pub span: Span,
     pub repr: NameKind,
@@ -99,13 +101,30 @@ impl Hash for Name {
     }
 }
 
+// Like equality, ordering ignores the span so that names can be used as
+// keys in ordered maps.
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Name) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Name {
+    fn cmp(&self, other: &Name) -> Ordering {
+        self.repr.cmp(&other.repr)
+    }
+}
+
 impl Pretty for Name {
     fn pretty(&self) -> Doc {
         use self::NameKind::*;
 
         let s = match &self.repr {
-            &Unqualified(ref s) => s.clone(),
-            &Qualified(ref qn) => format!("{:?}", qn),
+            &Unqualified(ref s) => escape_identifier(s).into_owned(),
+            &Qualified(ref qn) => qn.iter()
+                                    .map(|c| escape_identifier(c))
+                                    .collect::<Vec<_>>()
+                                    .join("."),
             &Placeholder => String::from("_"),
         };
         Doc::text(s)
@@ -128,12 +147,14 @@ impl HasSpan for Name {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Module {
     pub id: ModuleId,
     pub span: Span,
     pub name: Name,
     pub items: Vec<Item>,
+    /// Comments that do not precede an item that can hold them.
+    pub comments: Vec<LineComment>,
 }
 
 impl Module {
@@ -147,6 +168,7 @@ impl Module {
             span: Span::dummy(),
             name: Name::from_str("REPL"),
             items: vec![],
+            comments: vec![],
         }
     }
 }
@@ -159,6 +181,22 @@ pub enum Item {
     Extern(Extern),
     Comment(String),
     Import(Name),
+    Export(Export),
+    Variables(Variables),
+    Macro(Macro),
+    Command(Command),
+    /// `#exit`, the items following it are parsed but not elaborated.
+    Exit(Span),
+}
+
+/// A `--` comment, the text excludes the leading dashes.
+///
+/// The tokenizer skips these, they are attached to the item or match arm
+/// they precede after parsing.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LineComment {
+    pub span: Span,
+    pub text: String,
 }
 
 impl HasSpan for Item {
@@ -171,7 +209,12 @@ impl HasSpan for Item {
             &Axiom(ref a) => a.span,
             &Extern(ref ext) => ext.span,
             &Comment(_) => Span::dummy(),
-            &Import(_) => Span::dummy(),
+            &Import(ref name) => name.span,
+            &Export(ref export) => export.span,
+            &Variables(ref vars) => vars.span,
+            &Macro(ref mac) => mac.span,
+            &Command(ref cmd) => cmd.span,
+            &Exit(span) => span,
         }
     }
 
@@ -189,14 +232,193 @@ impl HasSpan for Item {
                 ext.span = sp,
             &mut Comment(_) => {},
             &mut Import(_) => {},
+            &mut Export(ref mut export) =>
+                export.span = sp,
+            &mut Variables(ref mut vars) =>
+                vars.span = sp,
+            &mut Macro(ref mut mac) =>
+                mac.span = sp,
+            &mut Command(ref mut cmd) =>
+                cmd.span = sp,
+            &mut Exit(ref mut span) =>
+                *span = sp,
+        }
+    }
+}
+
+impl Item {
+    /// The attributes attached to the item.
+    pub fn attributes(&self) -> &[Attribute] {
+        use self::Item::*;
+
+        match self {
+            &Inductive(ref data) => &data.attributes,
+            &Def(ref def) => &def.attributes,
+            &Axiom(ref a) => &a.attributes,
+            &Extern(ref ext) => &ext.attributes,
+            &Comment(_) |
+            &Import(_) |
+            &Export(_) |
+            &Variables(_) |
+            &Macro(_) |
+            &Command(_) |
+            &Exit(_) => &[],
+        }
+    }
+
+    /// The comments preceding the item, imports and doc comments can not
+    /// hold comments.
+    pub fn comments_mut(&mut self) -> Option<&mut Vec<LineComment>> {
+        use self::Item::*;
+
+        match self {
+            &mut Inductive(ref mut data) => Some(&mut data.comments),
+            &mut Def(ref mut def) => Some(&mut def.comments),
+            &mut Axiom(ref mut a) => Some(&mut a.comments),
+            &mut Extern(ref mut ext) => Some(&mut ext.comments),
+            &mut Export(ref mut export) => Some(&mut export.comments),
+            &mut Variables(ref mut vars) => Some(&mut vars.comments),
+            &mut Macro(ref mut mac) => Some(&mut mac.comments),
+            &mut Command(ref mut cmd) => Some(&mut cmd.comments),
+            &mut Comment(_) |
+            &mut Import(_) |
+            &mut Exit(_) => None,
+        }
+    }
+
+    /// The doc comment of the item, only definitions, inductive types,
+    /// axioms and externs can be documented.
+    pub fn doc_mut(&mut self) -> Option<&mut Option<String>> {
+        use self::Item::*;
+
+        match self {
+            &mut Inductive(ref mut data) => Some(&mut data.doc),
+            &mut Def(ref mut def) => Some(&mut def.doc),
+            &mut Axiom(ref mut a) => Some(&mut a.doc),
+            &mut Extern(ref mut ext) => Some(&mut ext.doc),
+            &mut Comment(_) |
+            &mut Import(_) |
+            &mut Export(_) |
+            &mut Variables(_) |
+            &mut Macro(_) |
+            &mut Command(_) |
+            &mut Exit(_) => None,
+        }
+    }
+
+    /// Attach attributes to the item, comments, imports, and commands can
+    /// not have attributes so they are dropped.
+    pub fn set_attributes(&mut self, attrs: Vec<Attribute>) {
+        use self::Item::*;
+
+        match self {
+            &mut Inductive(ref mut data) => data.attributes = attrs,
+            &mut Def(ref mut def) => def.attributes = attrs,
+            &mut Axiom(ref mut a) => a.attributes = attrs,
+            &mut Extern(ref mut ext) => ext.attributes = attrs,
+            &mut Comment(_) |
+            &mut Import(_) |
+            &mut Export(_) |
+            &mut Variables(_) |
+            &mut Macro(_) |
+            &mut Command(_) |
+            &mut Exit(_) => {}
         }
     }
 }
 
+/// A re-export, `export M (a, b)` makes the globals `a` and `b` that this
+/// module imports from `M` part of its own interface.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Export {
+    pub span: Span,
+    pub module: Name,
+    pub names: Vec<Name>,
+    pub comments: Vec<LineComment>,
+}
+
+/// Section variables, `variables (A : Type)` adds `A` as a parameter to each
+/// later definition in the module that mentions it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Variables {
+    pub span: Span,
+    pub binders: Vec<Binder>,
+    pub comments: Vec<LineComment>,
+}
+
+/// A macro, each application of `m` matching one of its rules is rewritten
+/// to the rule's template before elaboration:
+///
+/// ```text
+/// macro unless
+///   | unless b t e => if_then_else b e t
+/// end
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Macro {
+    pub span: Span,
+    pub name: Name,
+    pub rules: Vec<MacroRule>,
+    pub comments: Vec<LineComment>,
+}
+
+/// A rule of a macro, the pattern is an application of the macro.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MacroRule {
+    pub span: Span,
+    pub pattern: Pattern,
+    pub template: Term,
+}
+
+/// A top-level command, such as `#eval t`, run while elaborating the module.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Command {
+    pub span: Span,
+    pub kind: CommandKind,
+    pub term: Term,
+    pub comments: Vec<LineComment>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommandKind {
+    /// Evaluate the term and print its value.
+    Eval,
+    /// Print the normal form of the term.
+    Reduce,
+    /// `#print axioms f`, print the axioms the global `f` depends on. The
+    /// term is the application of `axioms` to `f`.
+    Print,
+}
+
+/// An attribute attached to an item, written `@[name args*]`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Attribute {
+    pub span: Span,
+    pub name: Name,
+    pub args: Vec<AttributeArg>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AttributeArg {
+    Str(String),
+    Name(Name),
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum BindingMode {
     Explicit,
     Implicit,
+    /// An argument written `[x : C A]`, found by searching the instances of
+    /// `C` instead of by unification.
+    Instance,
+}
+
+/// Whether the value bound is needed at runtime, a binder written with a
+/// leading `.`, as in `.{n : Nat}` or `.(n : Nat)`, is erased.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Relevance {
+    Relevant,
+    Irrelevant,
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -205,40 +427,135 @@ pub struct Binder {
     pub names: Vec<Name>,
     pub ty: Option<Term>,
     pub mode: BindingMode,
+    pub relevance: Relevance,
+}
+
+impl Pretty for Binder {
+    fn pretty(&self) -> Doc {
+        let (open, close) = match (&self.mode, self.relevance) {
+            (&BindingMode::Explicit, Relevance::Relevant) => ("(", ")"),
+            (&BindingMode::Explicit, Relevance::Irrelevant) => (".(", ")"),
+            (&BindingMode::Implicit, Relevance::Relevant) => ("{", "}"),
+            (&BindingMode::Implicit, Relevance::Irrelevant) => (".{", "}"),
+            (&BindingMode::Instance, _) => ("[", "]"),
+        };
+
+        // The parser collects the names last first.
+        let names: Vec<_> = self.names.iter().rev().map(|n| n.pretty()).collect();
+        let names = seperate(&names[..], &" ".pretty());
+
+        let inner = match self.ty {
+            Some(ref ty) => names + " : ".pretty() + ty.pretty(),
+            None => names,
+        };
+
+        open.pretty() + inner + close.pretty()
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Inductive {
     pub span: Span,
+    pub attributes: Vec<Attribute>,
     pub name: Name,
     pub parameters: Vec<Binder>,
     pub ty: Term,
-    pub ctors: Vec<Constructor>
+    pub ctors: Vec<Constructor>,
+    /// A function defined together with the type, only accepted when the
+    /// `induction-recursion` feature is enabled.
+    pub recursive: Option<RecursiveFn>,
+    pub comments: Vec<LineComment>,
+    /// The doc comment preceding the item.
+    pub doc: Option<String>,
 }
 
 pub type Constructor = (Name, Term);
 
+/// A function defined by one case per constructor of the inductive type it
+/// is declared with, the constructors may refer to the function.
+#[derive(PartialEq, Debug, Clone)]
+pub struct RecursiveFn {
+    pub span: Span,
+    pub name: Name,
+    pub ty: Term,
+    pub cases: Vec<Case>,
+}
+
+/// A constant implemented outside of Hubris.
+///
+/// The parameters describe how the extern is called, implicit parameters are
+/// erased and the rest are passed to the function named by `symbol`.
 #[derive(Debug, PartialEq, Clone)]
 pub struct Extern {
     pub span: Span,
+    pub attributes: Vec<Attribute>,
     pub name: Name,
+    pub args: Vec<Binder>,
     pub term: Term,
+    /// The language written before the symbol, `rust` names a function in
+    /// another crate rather than in the runtime.
+    pub language: Option<Name>,
+    pub symbol: Option<String>,
+    pub comments: Vec<LineComment>,
+    /// The doc comment preceding the item.
+    pub doc: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Def {
     pub span: Span,
+    pub attributes: Vec<Attribute>,
     pub name: Name,
     pub args: Vec<Binder>,
     pub ty: Term,
-    pub body: Term,
+    pub body: DefBody,
+    pub comments: Vec<LineComment>,
+    /// The doc comment preceding the item.
+    pub doc: Option<String>,
+}
+
+/// The body of a definition, either a term, equations matching on the
+/// first argument of its type, or copatterns giving each of its fields.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DefBody {
+    Term(Term),
+    Equations(Vec<Equation>),
+    Copatterns(Vec<Copattern>),
+}
+
+/// A copattern `| .field := rhs`, giving the value of a field of a type
+/// with a single constructor.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Copattern {
+    pub span: Span,
+    pub field: Name,
+    pub rhs: Term,
+}
+
+/// An equation `| pattern => rhs` of a definition, or `| pattern with e`
+/// followed by equations matching on the value of `e` and closed by `end`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Equation {
+    pub span: Span,
+    pub pattern: Pattern,
+    pub rhs: EquationRhs,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum EquationRhs {
+    Term(Term),
+    With(Term, Vec<Equation>),
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Axiom {
     pub span: Span,
+    pub attributes: Vec<Attribute>,
     pub name: Name,
     pub ty: Term,
+    pub comments: Vec<LineComment>,
+    /// The doc comment preceding the item.
+    pub doc: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -250,10 +567,84 @@ pub enum Term {
     Forall { span: Span, binders: Vec<Binder>, term: Box<Term> },
     Lambda { span: Span, args: Vec<Binder>, ret_ty: Box<Option<Term>>, body: Box<Term> },
     Let { span: Span, bindings: Vec<(Binder, Term)>, body: Box<Term> },
+    /// `fix f (x : A) ... : B on x => body`, a function recursing on `x`,
+    /// `body` may call `f` on the strict subterms of `x`.
+    Fix {
+        span: Span,
+        name: Name,
+        args: Vec<Binder>,
+        ret_ty: Box<Term>,
+        rec_arg: Name,
+        body: Box<Term>,
+    },
+    /// `` `(t) ``, the syntax of `t` as a value of the reflected syntax type.
+    Quote { span: Span, term: Box<Term> },
+    /// `$x` or `$(t)` inside a quotation, splices in a term whose value is
+    /// reflected syntax.
+    Antiquote { span: Span, term: Box<Term> },
     Type,
 }
 
 impl Term {
+    /// `A × B`, notation for `Prod A B` which must be in scope.
+    pub fn product(lhs: Term, rhs: Term) -> Term {
+        let span = Span::new(lhs.get_span().lo, rhs.get_span().hi);
+        let prod = Term::global("Prod", span);
+        Term::apply(Term::apply(prod, lhs), rhs)
+    }
+
+    /// `Σ (x : A), B`, notation for `Sigma A (fun (x : A) => B)` which
+    /// must be in scope, with one `Sigma` for each name bound.
+    pub fn sigma(binders: Vec<Binder>, body: Term) -> Term {
+        let mut bound = vec![];
+
+        // The names of a binder are stored in reverse.
+        for binder in binders {
+            for name in binder.names.into_iter().rev() {
+                let ty = binder.ty.clone().unwrap_or_else(|| Term::placeholder(name.span));
+                bound.push((binder.span, name, ty));
+            }
+        }
+
+        bound.into_iter().rev().fold(body, |body, (span, name, ty)| {
+            let family = Term::Lambda {
+                span: Span::new(span.lo, body.get_span().hi),
+                args: vec![Binder {
+                    span: span,
+                    names: vec![name],
+                    ty: Some(ty.clone()),
+                    mode: BindingMode::Explicit,
+                    relevance: Relevance::Relevant,
+                }],
+                ret_ty: Box::new(None),
+                body: Box::new(body),
+            };
+
+            Term::apply(Term::apply(Term::global("Sigma", span), ty), family)
+        })
+    }
+
+    fn global(name: &str, span: Span) -> Term {
+        let mut name = Name::from_str(name);
+        name.span = span;
+        Term::Var { name: name, implicit: true }
+    }
+
+    fn placeholder(span: Span) -> Term {
+        Term::Var {
+            name: Name { span: span, repr: NameKind::Placeholder },
+            implicit: true,
+        }
+    }
+
+    fn apply(fun: Term, arg: Term) -> Term {
+        Term::App {
+            span: Span::new(fun.get_span().lo, arg.get_span().hi),
+            fun: Box::new(fun),
+            arg: Box::new(arg),
+        }
+    }
+
     pub fn uncurry(&self) -> (Term, Vec<Term>) {
         use self::Term::*;
 
@@ -327,12 +718,20 @@ impl Pretty for Term {
                 // "fun ".pretty() + pretty_binders(binders.as_slice()) + " => ".pretty() + cursor.pretty()
             }
             &Let { .. } => panic!(),
+            &Fix { ref name, ref args, ref ret_ty, ref rec_arg, ref body, .. } => {
+                let args: Vec<_> = args.iter().map(|b| b.pretty()).collect();
+                "fix ".pretty() + name.pretty() + " ".pretty() +
+                seperate(&args[..], &" ".pretty()) + " : ".pretty() + ret_ty.pretty() +
+                " on ".pretty() + rec_arg.pretty() + " => ".pretty() + body.pretty()
+            }
             &Match { ref scrutinee, ref cases, .. } => {
                 let cases : Vec<_> = cases.iter().map(|x| x.pretty()).collect();
                 "match ".pretty() + scrutinee.pretty() + " with\n".pretty() +
                 seperate(&cases[..], &"\n".pretty()) + "\nend".pretty()
             }
             &Literal { .. } => panic!(),
+            &Quote { ref term, .. } => "`(".pretty() + term.pretty() + ")".pretty(),
+            &Antiquote { ref term, .. } => "$".pretty() + parens(term.pretty()),
             &Type => Doc::text("Type"),
         }
     }
@@ -350,6 +749,9 @@ impl HasSpan for Term {
             &Forall { span, .. } => span,
             &Lambda { span, .. } => span,
             &Let { span, .. } => span,
+            &Fix { span, .. } => span,
+            &Quote { span, .. } => span,
+            &Antiquote { span, .. } => span,
             &Type => Span::dummy(),
         }
     }
@@ -365,6 +767,9 @@ impl HasSpan for Term {
             &mut Forall { ref mut span, .. } => *span = sp,
             &mut Lambda { ref mut span, .. } => *span = sp,
             &mut Let { ref mut span, .. } => *span = sp,
+            &mut Fix { ref mut span, .. } => *span = sp,
+            &mut Quote { ref mut span, .. } => *span = sp,
+            &mut Antiquote { ref mut span, .. } => *span = sp,
             &mut Type => {},
         }
     }
@@ -375,6 +780,7 @@ pub struct Case {
     pub span: Span,
     pub pattern: Pattern,
     pub rhs: Term,
+    pub comments: Vec<LineComment>,
 }
 
 impl Display for Case {
@@ -390,6 +796,27 @@ impl Pretty for Case {
     }
 }
 
+impl Display for Equation {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        format(self, formatter)
+    }
+}
+
+impl Pretty for Equation {
+    fn pretty(&self) -> Doc {
+        let lhs = "| ".pretty() + self.pattern.pretty();
+
+        match self.rhs {
+            EquationRhs::Term(ref rhs) => lhs + " => ".pretty() + rhs.pretty(),
+            EquationRhs::With(ref scrutinee, ref equations) => {
+                let equations: Vec<_> = equations.iter().map(|eq| eq.pretty()).collect();
+                lhs + " with ".pretty() + scrutinee.pretty() + "\n".pretty() +
+                    seperate(&equations[..], &"\n".pretty()) + "\nend".pretty()
+            }
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Pattern {
     Name(Name),
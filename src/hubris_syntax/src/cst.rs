@@ -0,0 +1,375 @@
+//! A lossless syntax tree beneath the AST.
+//!
+//! The AST drops what does not change the meaning of a program: whitespace,
+//! comments, parentheses, and the exact spelling of tokens such as `→` and
+//! `->`. Tooling that rewrites source, a formatter, renaming a global, or
+//! reporting the expansion of a macro, instead works on the syntax tree,
+//! which keeps every byte of the source.
+//!
+//! Each token owns the trivia, whitespace and `--` comments, that precedes
+//! it, the trivia after the last token belongs to the tree. The nodes of the
+//! tree are the items, terms, binders, cases, equations and names of the
+//! AST, found by their spans, and a token belongs to the innermost node
+//! whose span contains it. Writing out the trivia and text of every token
+//! in order gives back the source exactly.
+
+use ast::{HasSpan, Module, ModuleId, Span, Binder, Case, Equation, Item, Name, Term};
+use tok::{self, Tok, Tokenizer};
+use visit::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    /// A `--` comment, up to but not including the end of its line.
+    Comment,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    /// An identifier, including one written `r#name` or `«name»`.
+    Identifier,
+    StringLiteral,
+    /// A `--|` or `/-- -/` doc comment, which the parser sees as a token.
+    DocComment,
+    /// A command such as `#eval`.
+    Command,
+    Punctuation,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+    /// The trivia between the previous token and this one.
+    pub leading: Vec<Trivia>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Module,
+    Item,
+    Binder,
+    Term,
+    Case,
+    Equation,
+    Name,
+}
+
+/// A child of a node, an index into the nodes or tokens of the tree.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Child {
+    Node(usize),
+    Token(usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub kind: NodeKind,
+    /// The span of the AST node, from its first token to its last.
+    pub span: Span,
+    pub children: Vec<Child>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SyntaxTree {
+    source: String,
+    tokens: Vec<Token>,
+    /// The trivia following the last token.
+    trailing: Vec<Trivia>,
+    /// The nodes of the tree, the module is the first.
+    nodes: Vec<Node>,
+}
+
+impl SyntaxTree {
+    /// The syntax tree of `source`, which `module` was parsed from. The
+    /// spans of the tree are in the module `id`.
+    pub fn new(source: &str, module: &Module, id: ModuleId) -> Result<SyntaxTree, tok::Error> {
+        let mut tokenizer = Tokenizer::new(source, 0);
+        let mut spanned = vec![];
+
+        while let Some(token) = tokenizer.next() {
+            let (lo, tok, hi) = try!(token);
+            spanned.push((lo, token_kind(&tok), hi));
+        }
+
+        let span = |lo, hi| Span { module_id: id, lo: lo, hi: hi };
+
+        let mut comments = tokenizer.comments().iter().map(|&(lo, _, hi)| span(lo, hi)).peekable();
+        let mut tokens = vec![];
+        let mut offset = 0;
+
+        for (lo, kind, hi) in spanned {
+            let mut leading = vec![];
+
+            // Every byte between two tokens is either in a comment or is
+            // whitespace.
+            while comments.peek().map_or(false, |comment| comment.hi <= lo) {
+                let comment = comments.next().unwrap();
+                whitespace(source, span(offset, comment.lo), &mut leading);
+                leading.push(Trivia { kind: TriviaKind::Comment, span: comment });
+                offset = comment.hi;
+            }
+
+            whitespace(source, span(offset, lo), &mut leading);
+
+            tokens.push(Token {
+                kind: kind,
+                span: span(lo, hi),
+                leading: leading,
+            });
+
+            offset = hi;
+        }
+
+        let mut trailing = vec![];
+        for comment in comments {
+            whitespace(source, span(offset, comment.lo), &mut trailing);
+            trailing.push(Trivia { kind: TriviaKind::Comment, span: comment });
+            offset = comment.hi;
+        }
+        whitespace(source, span(offset, source.len()), &mut trailing);
+
+        let mut collector = Spans { spans: vec![] };
+        collector.visit_module(module);
+
+        // In source order, a node before the nodes it contains.
+        let mut spans: Vec<_> = collector.spans.into_iter().filter(|&(_, s)| {
+            s.lo < s.hi && s.hi <= source.len()
+        }).collect();
+        spans.sort_by(|&(_, a), &(_, b)| (a.lo, b.hi).cmp(&(b.lo, a.hi)));
+
+        let mut tree = SyntaxTree {
+            source: source.to_string(),
+            tokens: tokens,
+            trailing: trailing,
+            nodes: vec![Node {
+                kind: NodeKind::Module,
+                span: span(0, source.len()),
+                children: vec![],
+            }],
+        };
+
+        tree.build(spans);
+
+        Ok(tree)
+    }
+
+    /// Nest the nodes with the given spans, in order, and place each token
+    /// in the innermost node containing it. A span that overlaps the one
+    /// before it without nesting in it is left out.
+    fn build(&mut self, spans: Vec<(NodeKind, Span)>) {
+        let module_id = self.nodes[0].span.module_id;
+        let mut spans = spans.into_iter().peekable();
+
+        // The nodes containing what is placed next, innermost last, each
+        // with the end of the last node placed in it.
+        let mut open = vec![(0, 0)];
+
+        for token in 0..self.tokens.len() {
+            let token_span = self.tokens[token].span;
+
+            while spans.peek().map_or(false, |&(_, span)| span.lo <= token_span.lo) {
+                let (kind, span) = spans.next().unwrap();
+
+                while !contains(self.nodes[open.last().unwrap().0].span, span) {
+                    open.pop();
+                }
+
+                let parent = {
+                    let &mut (parent, ref mut last_hi) = open.last_mut().unwrap();
+                    if *last_hi > span.lo {
+                        continue;
+                    }
+                    *last_hi = span.hi;
+                    parent
+                };
+
+                let node = self.nodes.len();
+                self.nodes.push(Node {
+                    kind: kind,
+                    span: Span { module_id: module_id, lo: span.lo, hi: span.hi },
+                    children: vec![],
+                });
+                self.nodes[parent].children.push(Child::Node(node));
+                open.push((node, 0));
+            }
+
+            while !contains(self.nodes[open.last().unwrap().0].span, token_span) {
+                open.pop();
+            }
+
+            let parent = open.last().unwrap().0;
+            self.nodes[parent].children.push(Child::Token(token));
+        }
+    }
+
+    /// The source the tree was built from, which is also what writing out
+    /// the tree gives.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The source written out from the tokens and trivia of the tree.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+
+        for token in &self.tokens {
+            for trivia in &token.leading {
+                text.push_str(self.text_of(trivia.span));
+            }
+            text.push_str(self.text_of(token.span));
+        }
+
+        for trivia in &self.trailing {
+            text.push_str(self.text_of(trivia.span));
+        }
+
+        text
+    }
+
+    /// The source text within `span`.
+    pub fn text_of(&self, span: Span) -> &str {
+        &self.source[span.lo..span.hi]
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn trailing(&self) -> &[Trivia] {
+        &self.trailing
+    }
+
+    /// The tree's root, the node of the module.
+    pub fn root(&self) -> &Node {
+        &self.nodes[0]
+    }
+
+    pub fn node(&self, index: usize) -> &Node {
+        &self.nodes[index]
+    }
+
+    /// The tokens within `span`, in order.
+    pub fn tokens_in(&self, span: Span) -> &[Token] {
+        let len = self.tokens.len();
+        let lo = self.tokens.iter().position(|t| t.span.lo >= span.lo).unwrap_or(len);
+        let hi = self.tokens[lo..].iter().position(|t| t.span.hi > span.hi).map_or(len, |i| lo + i);
+        &self.tokens[lo..hi]
+    }
+
+    /// The token at `offset`, if it is not in trivia.
+    pub fn token_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.iter().find(|t| t.span.lo <= offset && offset < t.span.hi)
+    }
+
+    /// The innermost node containing `offset`.
+    pub fn node_at(&self, offset: usize) -> &Node {
+        let mut node = &self.nodes[0];
+
+        loop {
+            let child = node.children.iter().filter_map(|&child| match child {
+                Child::Node(n) => Some(&self.nodes[n]),
+                Child::Token(_) => None,
+            }).find(|n| n.span.lo <= offset && offset < n.span.hi);
+
+            match child {
+                None => return node,
+                Some(child) => node = child,
+            }
+        }
+    }
+
+    /// The source with the text within each span replaced, the rest of the
+    /// source, trivia included, is kept as it is. The spans must not
+    /// overlap.
+    pub fn replace(&self, edits: &[(Span, String)]) -> String {
+        let mut edits: Vec<_> = edits.iter().collect();
+        edits.sort_by_key(|&&(span, _)| span.lo);
+
+        let mut text = String::new();
+        let mut offset = 0;
+
+        for &&(span, ref replacement) in &edits {
+            text.push_str(&self.source[offset..span.lo]);
+            text.push_str(replacement);
+            offset = span.hi;
+        }
+
+        text.push_str(&self.source[offset..]);
+        text
+    }
+}
+
+fn contains(outer: Span, inner: Span) -> bool {
+    outer.lo <= inner.lo && inner.hi <= outer.hi
+}
+
+/// Add the whitespace within `span` to `trivia`, if there is any.
+fn whitespace(source: &str, span: Span, trivia: &mut Vec<Trivia>) {
+    if span.lo < span.hi {
+        debug_assert!(source[span.lo..span.hi].chars().all(char::is_whitespace));
+        trivia.push(Trivia { kind: TriviaKind::Whitespace, span: span });
+    }
+}
+
+fn token_kind(tok: &Tok) -> TokenKind {
+    use tok::Tok::*;
+
+    match tok {
+        &Def | &Axiom | &End | &Export | &Extern | &Forall | &Fun | &Inductive | &In |
+        &Import | &Let | &Macro | &Match | &Module | &Type | &Variables | &With =>
+            TokenKind::Keyword,
+        &HashEval | &HashExit | &HashPrint | &HashReduce => TokenKind::Command,
+        &Id(_) => TokenKind::Identifier,
+        &DocComment(_) => TokenKind::DocComment,
+        &StringLiteral(_) => TokenKind::StringLiteral,
+        _ => TokenKind::Punctuation,
+    }
+}
+
+/// Collects the span of each node of the AST the tree keeps.
+struct Spans {
+    spans: Vec<(NodeKind, Span)>,
+}
+
+impl<'v> Visitor<'v> for Spans {
+    fn visit_item(&mut self, item: &'v Item) {
+        self.spans.push((NodeKind::Item, item.get_span()));
+        walk_item(self, item)
+    }
+
+    fn visit_binder(&mut self, binder: &'v Binder) {
+        self.spans.push((NodeKind::Binder, binder.span));
+        walk_binder(self, binder)
+    }
+
+    fn visit_term(&mut self, term: &'v Term) {
+        // A variable is the node of its name.
+        if let &Term::Var { .. } = term {} else {
+            self.spans.push((NodeKind::Term, term.get_span()));
+        }
+        walk_term(self, term)
+    }
+
+    fn visit_case(&mut self, case: &'v Case) {
+        self.spans.push((NodeKind::Case, case.span));
+        walk_case(self, case)
+    }
+
+    fn visit_equation(&mut self, equation: &'v Equation) {
+        self.spans.push((NodeKind::Equation, equation.span));
+        walk_equation(self, equation)
+    }
+
+    fn visit_name(&mut self, name: &'v Name) {
+        self.spans.push((NodeKind::Name, name.span));
+        walk_name(self, name)
+    }
+}
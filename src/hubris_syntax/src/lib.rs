@@ -4,6 +4,7 @@ extern crate unicode_xid;
 extern crate pretty;
 
 pub mod ast;
+pub mod cst;
 pub mod parser;
 pub mod tok;
 pub mod visit;
@@ -0,0 +1,72 @@
+//! A pass that attaches the comments skipped by the tokenizer to the AST.
+//!
+//! A comment belongs to the item it precedes, or to a match arm if it sits
+//! between the arm and the one before it. Comments elsewhere inside an item
+//! are kept with the item, and comments after the last item with the module.
+
+use super::super::ast::{HasSpan, LineComment, Module, Span, Term};
+use super::super::visit::*;
+
+struct CommentAttacher {
+    /// The comments inside the item being visited, in source order.
+    comments: Vec<LineComment>,
+}
+
+impl CommentAttacher {
+    /// Remove the comments between the offsets `lo` and `hi`.
+    fn take_between(&mut self, lo: usize, hi: usize) -> Vec<LineComment> {
+        let (taken, rest) =
+            self.comments.drain(..).partition(|c| c.span.lo >= lo && c.span.hi <= hi);
+        self.comments = rest;
+        taken
+    }
+}
+
+impl<'v> VisitorMut<'v> for CommentAttacher {
+    fn visit_mut_term(&mut self, term: &'v mut Term) {
+        if let &mut Term::Match { ref scrutinee, ref mut cases, .. } = term {
+            let mut lo = scrutinee.get_span().hi;
+            for case in cases.iter_mut() {
+                case.comments = self.take_between(lo, case.span.lo);
+                lo = case.span.hi;
+            }
+        }
+
+        walk_mut_term(self, term)
+    }
+}
+
+pub fn attach_comments(module: &mut Module, comments: Vec<LineComment>) {
+    let mut comments = comments.into_iter().peekable();
+
+    for item in &mut module.items {
+        let span = item.get_span();
+
+        if span == Span::dummy() {
+            continue;
+        }
+
+        let mut leading = vec![];
+        let mut inner = vec![];
+
+        while comments.peek().map_or(false, |c| c.span.hi <= span.hi) {
+            let comment = comments.next().unwrap();
+            if comment.span.lo < span.lo {
+                leading.push(comment);
+            } else {
+                inner.push(comment);
+            }
+        }
+
+        let mut attacher = CommentAttacher { comments: inner };
+        attacher.visit_mut_item(item);
+        leading.extend(attacher.comments);
+
+        match item.comments_mut() {
+            Some(item_comments) => *item_comments = leading,
+            None => module.comments.extend(leading),
+        }
+    }
+
+    module.comments.extend(comments);
+}
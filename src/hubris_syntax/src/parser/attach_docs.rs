@@ -0,0 +1,35 @@
+//! A pass that binds doc comments to the items they document.
+//!
+//! The parser produces a doc comment as an item of its own, one that
+//! directly precedes a definition, inductive type, axiom or extern is moved
+//! into that item. Doc comments that document nothing are left in place.
+
+use super::super::ast::{Item, Module};
+
+pub fn attach_docs(module: &mut Module) {
+    let items = ::std::mem::replace(&mut module.items, vec![]);
+    let mut pending: Option<String> = None;
+
+    for mut item in items {
+        if let Item::Comment(text) = item {
+            if let Some(previous) = pending.take() {
+                module.items.push(Item::Comment(previous));
+            }
+            pending = Some(text);
+            continue;
+        }
+
+        if let Some(text) = pending.take() {
+            match item.doc_mut() {
+                Some(doc) => *doc = Some(text),
+                None => module.items.push(Item::Comment(text)),
+            }
+        }
+
+        module.items.push(item);
+    }
+
+    if let Some(text) = pending {
+        module.items.push(Item::Comment(text));
+    }
+}
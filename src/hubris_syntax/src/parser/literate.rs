@@ -0,0 +1,85 @@
+//! Literate source files, markdown documents whose code blocks hold the
+//! program.
+//!
+//! A file ending in `.hub.md` is compiled from its fenced code blocks that
+//! are either untagged or tagged `hubris`, blocks in other languages are
+//! left out. Everything else, including the fences, is replaced by spaces
+//! with the line breaks kept, so offsets into the program are offsets into
+//! the markdown and diagnostics point at its lines.
+
+use std::path::Path;
+
+/// The extension of literate files.
+pub const LITERATE_EXTENSION: &'static str = "hub.md";
+
+/// Whether `path` names a literate file.
+pub fn is_literate(path: &Path) -> bool {
+    path.to_str()
+        .map(|p| p.ends_with(&format!(".{}", LITERATE_EXTENSION)[..]))
+        .unwrap_or(false)
+}
+
+/// The program in the markdown `text`, each line outside of the compiled
+/// code blocks is blanked.
+pub fn literate_code(text: &str) -> String {
+    let mut code = String::with_capacity(text.len());
+    // The character and length of the fence that opened the current code
+    // block, and whether the block is compiled.
+    let mut open: Option<(char, usize, bool)> = None;
+
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            code.push('\n');
+        }
+
+        match (open, fence(line)) {
+            (None, Some((c, len, info))) => {
+                let language = info.split_whitespace().next().unwrap_or("");
+                open = Some((c, len, language == "" || language == "hubris"));
+                blank(line, &mut code);
+            }
+            (None, None) => blank(line, &mut code),
+            (Some((c, len, _)), Some((d, closing, info)))
+                if c == d && closing >= len && info == "" => {
+                open = None;
+                blank(line, &mut code);
+            }
+            (Some((_, _, true)), _) => code.push_str(line),
+            (Some((_, _, false)), _) => blank(line, &mut code),
+        }
+    }
+
+    code
+}
+
+/// The fence on `line`, if it opens or closes a code block, as its
+/// character, its length, and the info string following it.
+fn fence(line: &str) -> Option<(char, usize, &str)> {
+    let rest = line.trim_left_matches(' ');
+
+    if line.len() - rest.len() > 3 {
+        return None;
+    }
+
+    let c = match rest.chars().next() {
+        Some(c) if c == '`' || c == '~' => c,
+        _ => return None,
+    };
+
+    let len = rest.len() - rest.trim_left_matches(c).len();
+
+    if len < 3 {
+        None
+    } else {
+        Some((c, len, rest[len..].trim()))
+    }
+}
+
+/// Replace `line` by spaces, one for each byte so offsets are unchanged.
+fn blank(line: &str, code: &mut String) {
+    for c in line.chars() {
+        for _ in 0..c.len_utf8() {
+            code.push(' ');
+        }
+    }
+}
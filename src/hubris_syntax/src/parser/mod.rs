@@ -2,22 +2,32 @@ use std::path::Path;
 use std::fs::File;
 use std::io;
 use std::io::Read;
-use ast::{Span, ModuleId};
+use ast::{Span, ModuleId, LineComment};
+use cst::SyntaxTree;
 
 // A pass that adds module ids to every span.
 mod annotate_module_id;
+// A pass that attaches comments to the items and match arms they precede.
+mod attach_comments;
+// A pass that binds doc comments to the items they document.
+mod attach_docs;
 // A way to verify the parser is not producing dummy spans
 // in debug mode, need to wrap this with cfg enable at some point.
 mod dummy_span_debug;
 // The LALRPOP parser for our language.
 mod hubris;
+// Extracting the program from a literate markdown file.
+mod literate;
 mod source_map;
 
 use lalrpop_util::ParseError;
 pub use self::source_map::SourceMap;
+pub use self::literate::{LITERATE_EXTENSION, is_literate, literate_code};
 pub use super::tok;
 use self::dummy_span_debug::*;
 use self::annotate_module_id::*;
+use self::attach_comments::*;
+use self::attach_docs::*;
 
 pub struct Parser {
     pub source_map: SourceMap,
@@ -52,18 +62,40 @@ pub enum Error {
 
 impl Parser {
     pub fn parse(&self) -> Result<super::ast::Module, Error> {
-        let tokenizer = tok::Tokenizer::new(&self.source_map.source[..], 0);
-        let mut module = try!(hubris::parse_Module(&self.source_map.source[..], tokenizer)
+        let mut tokenizer = self.tokens();
+        let mut module = try!(hubris::parse_Module(&self.source_map.source[..], &mut tokenizer)
                               .map_err(Parser::translate_error));
         if cfg!(debug_assertions) {
             ensure_no_dummy_spans(&module);
         }
+
+        let comments =
+            tokenizer.into_inner()
+                     .comments()
+                     .iter()
+                     .map(|&(lo, text, hi)| LineComment {
+                         span: Span::new(lo, hi),
+                         text: text.to_string(),
+                     })
+                     .collect();
+
+        attach_comments(&mut module, comments);
+        attach_docs(&mut module);
         annotate_module_id(&mut module, self.id);
         Ok(module)
     }
 
+    /// Parse the module along with its lossless syntax tree, for tooling
+    /// that has to reproduce the source.
+    pub fn parse_lossless(&self) -> Result<(super::ast::Module, SyntaxTree), Error> {
+        let module = try!(self.parse());
+        let tree = try!(SyntaxTree::new(&self.source_map.source[..], &module, self.id)
+                            .map_err(|error| Parser::translate_error(ParseError::User { error: error })));
+        Ok((module, tree))
+    }
+
     pub fn parse_term(&self) -> Result<super::ast::Term, Error> {
-        let tokenizer = tok::Tokenizer::new(&self.source_map.source[..], 0);
+        let tokenizer = self.tokens();
         hubris::parse_Term(&self.source_map.source[..], tokenizer)
             .map_err(Parser::translate_error)
     }
@@ -74,6 +106,12 @@ impl Parser {
             .map_err(Parser::translate_error)
     }
 
+    /// The tokens of the source with the offside rule applied.
+    fn tokens(&self) -> tok::Layout<tok::Tokenizer> {
+        let source = &self.source_map.source[..];
+        tok::Layout::new(source, tok::Tokenizer::new(source, 0))
+    }
+
     pub fn translate_error<'input>(error: ParseError<usize, tok::Tok<'input>, tok::Error>) -> Error {
         match error {
             ParseError::InvalidToken { location } =>
@@ -112,6 +150,10 @@ pub fn from_file<T: AsRef<Path>>(path: T, id: ModuleId) -> io::Result<Parser> {
 
     try!(file.read_to_string(&mut contents));
 
+    if is_literate(path) {
+        contents = literate_code(&contents);
+    }
+
     Ok(Parser {
         source_map: SourceMap::from_file(
             format!("{}", path.to_owned().display()),
@@ -4,21 +4,21 @@ use super::super::ast::Span;
 pub struct SourceMap {
     pub file_name: String,
     pub source: String, // source code of the file
-    lines: Vec<(usize, usize)> // mapping from line number to line offset
+    // mapping from line number to the byte offsets of its start and end,
+    // spans are byte offsets while columns count characters
+    lines: Vec<(usize, usize)>
 }
 
 impl SourceMap {
     pub fn from_file(file_name: String, source: String) -> SourceMap {
         let mut line_start = 0;
-        let mut pos = 0;
         let mut lines = Vec::new();
 
-        for c in source.chars() {
+        for (pos, c) in source.char_indices() {
             if c == '\n' {
                 lines.push((line_start, pos));
                 line_start = pos + 1;
             }
-            pos += 1;
         }
 
         SourceMap {
@@ -36,17 +36,38 @@ impl SourceMap {
     pub fn position(&self, span: Span) -> Option<(usize, usize)> {
         for (i, line) in self.lines.iter().enumerate() {
             if span.lo >= line.0 && span.lo <= line.1 {
-                return Some((i, span.lo - line.0))
+                return Some((i, self.columns(line.0, span.lo)))
             }
         }
 
         return None;
     }
 
+    /// The offset of the given zero based line and column, the inverse of
+    /// `position`.
+    pub fn offset(&self, line: usize, column: usize) -> Option<usize> {
+        let start = if line < self.lines.len() {
+            self.lines[line].0
+        } else if line == self.lines.len() {
+            // The final line has no terminating newline so isn't recorded.
+            self.lines.last().map(|l| l.1 + 1).unwrap_or(0)
+        } else {
+            return None;
+        };
+
+        let rest = self.source.get(start..).unwrap_or("");
+        Some(start + rest.char_indices().nth(column).map(|(i, _)| i).unwrap_or(rest.len()))
+    }
+
+    /// The number of characters between the byte offsets `lo` and `hi`.
+    pub fn columns(&self, lo: usize, hi: usize) -> usize {
+        self.source.get(lo..hi).map(|s| s.chars().count()).unwrap_or(hi - lo)
+    }
+
     pub fn find_line(&self, index: usize) -> Option<(usize, &str)> {
         for line in &self.lines {
             if index >= line.0 && index <= line.1 {
-                return Some((index - line.0, &self.source[line.0..line.1]));
+                return Some((self.columns(line.0, index), &self.source[line.0..line.1]));
             }
         }
 
@@ -65,7 +86,7 @@ impl SourceMap {
 
             // Mark the start of the span.
             let mut marker = "^".to_string();
-            for _ in 0..(span.hi - span.lo) {
+            for _ in 0..self.columns(span.lo, span.hi) {
                 marker.push('~');
             }
 
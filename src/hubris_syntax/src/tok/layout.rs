@@ -0,0 +1,192 @@
+//! The offside rule, applied to the token stream before it reaches the
+//! parser.
+//!
+//! The arms of a `match` form a block indented to the column of its first
+//! `|`, a token starting a line to the left of that column closes the block
+//! as if `end` had been written before it:
+//!
+//! ```text
+//! def pred (n : Nat) : Nat :=
+//!   match n with
+//!   | Z => Z
+//!   | S m => m
+//! end
+//! ```
+//!
+//! An explicit `end` is still accepted, an `end` starting a line at or to
+//! the right of the line the `match` began on closes the `match`, one
+//! further left closes the enclosing item.
+//!
+//! The equations following a `with` in a definition by equations are
+//! always closed explicitly by their own `end`.
+
+use super::{Error, Spanned, Tok};
+
+/// A construct that is closed by `end`.
+enum Block {
+    /// An `inductive` or `macro`, these must always be closed explicitly.
+    Item,
+    /// A `def`, also closed explicitly, whose equations may open a `with`
+    /// block.
+    Def,
+    /// The equations of a `with` in a definition by equations.
+    With,
+    Match {
+        /// The indentation of the line the `match` appears on.
+        line_indent: usize,
+        /// The column of the first arm, unknown until it is seen.
+        arms: Option<usize>,
+    },
+}
+
+pub struct Layout<'input, I> {
+    text: &'input str,
+    tokens: I,
+    blocks: Vec<Block>,
+    /// A token read while closing blocks, returned once they are closed.
+    pending: Option<Spanned<Tok<'input>>>,
+    /// The column a pending token starting a line appears at.
+    pending_column: Option<usize>,
+}
+
+impl<'input, I> Layout<'input, I>
+    where I: Iterator<Item=Result<Spanned<Tok<'input>>, Error>>
+{
+    pub fn new(text: &'input str, tokens: I) -> Layout<'input, I> {
+        Layout {
+            text: text,
+            tokens: tokens,
+            blocks: vec![],
+            pending: None,
+            pending_column: None,
+        }
+    }
+
+    fn line_start(&self, lo: usize) -> usize {
+        self.text[..lo].rfind('\n').map(|i| i + 1).unwrap_or(0)
+    }
+
+    pub fn into_inner(self) -> I {
+        self.tokens
+    }
+
+    /// The column of the token starting at `lo` if it is the first token on
+    /// its line.
+    fn starts_line(&self, lo: usize) -> Option<usize> {
+        let prefix = &self.text[self.line_start(lo)..lo];
+
+        if prefix.chars().all(char::is_whitespace) {
+            Some(prefix.chars().count())
+        } else {
+            None
+        }
+    }
+
+    /// The indentation of the line containing the byte offset `lo`.
+    fn line_indent(&self, lo: usize) -> usize {
+        self.text[self.line_start(lo)..]
+            .chars()
+            .take_while(|c| *c != '\n' && c.is_whitespace())
+            .count()
+    }
+
+    /// Whether a token at the start of a line at `column` closes the
+    /// innermost block without consuming the token.
+    fn is_offside(&self, tok: &Tok<'input>, column: usize) -> bool {
+        match self.blocks.last() {
+            Some(&Block::Match { line_indent, arms }) => match tok {
+                &Tok::End => column < line_indent,
+                _ => arms.map_or(false, |arms| column < arms),
+            },
+            _ => false,
+        }
+    }
+
+    /// Record the effect of `tok` on the open blocks.
+    fn enter(&mut self, lo: usize, tok: &Tok<'input>) {
+        match tok {
+            &Tok::Def => self.blocks.push(Block::Def),
+            &Tok::Inductive | &Tok::Macro => self.blocks.push(Block::Item),
+            // The `with` of a `match` or of an inductive type's function
+            // shares the `end` of the enclosing block.
+            &Tok::With => match self.blocks.last() {
+                Some(&Block::Def) | Some(&Block::With) => self.blocks.push(Block::With),
+                _ => {}
+            },
+            &Tok::Match => {
+                let line_indent = self.line_indent(lo);
+                self.blocks.push(Block::Match { line_indent: line_indent, arms: None });
+            }
+            &Tok::End => {
+                self.blocks.pop();
+            }
+            &Tok::Bar => {
+                let column = self.column(lo);
+                if let Some(&mut Block::Match { ref mut arms, .. }) = self.blocks.last_mut() {
+                    if arms.is_none() {
+                        *arms = Some(column);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn column(&self, lo: usize) -> usize {
+        self.text[self.line_start(lo)..lo].chars().count()
+    }
+
+    /// Close the innermost block if `tok` is offside, returning the `end`
+    /// that closes it.
+    fn close(&mut self, lo: usize, tok: &Tok<'input>, column: usize)
+             -> Option<Spanned<Tok<'input>>> {
+        if self.is_offside(tok, column) {
+            self.blocks.pop();
+            Some((lo, Tok::End, lo))
+        } else {
+            None
+        }
+    }
+}
+
+impl<'input, I> Iterator for Layout<'input, I>
+    where I: Iterator<Item=Result<Spanned<Tok<'input>>, Error>>
+{
+    type Item = Result<Spanned<Tok<'input>>, Error>;
+
+    fn next(&mut self) -> Option<Result<Spanned<Tok<'input>>, Error>> {
+        let (lo, tok, hi) = match self.pending.take() {
+            Some(spanned) => spanned,
+            None => match self.tokens.next() {
+                Some(Ok(spanned)) => {
+                    self.pending_column = self.starts_line(spanned.0);
+                    spanned
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                // Every `match` still open is closed at the end of the input.
+                None => {
+                    let end = self.text.len();
+                    return match self.blocks.pop() {
+                        Some(Block::Match { .. }) => Some(Ok((end, Tok::End, end))),
+                        _ => None,
+                    };
+                }
+            },
+        };
+
+        // Doc comments are items of their own and never close a block.
+        if let Tok::DocComment(_) = tok {
+            return Some(Ok((lo, tok, hi)));
+        }
+
+        if let Some(column) = self.pending_column {
+            if let Some(end) = self.close(lo, &tok, column) {
+                self.pending = Some((lo, tok, hi));
+                return Some(Ok(end));
+            }
+        }
+
+        self.enter(lo, &tok);
+        Some(Ok((lo, tok, hi)))
+    }
+}
@@ -1,5 +1,6 @@
 //! A tokenizer for use in LALRPOP itself.
 
+use std::borrow::Cow;
 use std::fmt::{self, Display};
 use std::str::CharIndices;
 use unicode_xid::UnicodeXID;
@@ -7,6 +8,10 @@ use unicode_xid::UnicodeXID;
 use self::ErrorCode::*;
 use self::Tok::*;
 
+mod layout;
+
+pub use self::layout::Layout;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Error {
     pub location: usize,
@@ -21,6 +26,8 @@ pub enum ErrorCode {
     UnterminatedCode,
     ExpectedStringLiteral,
     UnfinishedComment,
+    UnknownCommand,
+    UnterminatedIdentifier,
 }
 
 fn error<T>(c: ErrorCode, l: usize) -> Result<T,Error> {
@@ -33,18 +40,29 @@ pub enum Tok<'input> {
     Def,
     Axiom,
     End,
+    Export,
     Extern,
+    Fix,
     Forall,
     Fun,
     Inductive,
     In,
     Import,
     Let,
+    Macro,
     Match,
     Module,
+    On,
     Type,
+    Variables,
     With,
 
+    // Top-level commands, written `#eval`.
+    HashEval,
+    HashExit,
+    HashPrint,
+    HashReduce,
+
     // Identifiers of various kinds:
     // SimpleName: String = {
     //     <s: r"[a-zA-Z_][a-zA-Z0-9_]*"> => s.to_string()
@@ -56,12 +74,15 @@ pub enum Tok<'input> {
 
     Arrow,
     At,
+    AtLeftBracket,
     Bar,
+    BacktickLeftParen,
     BangEquals,
     Colon,
     ColonColon,
     ColonEq,
     Comma,
+    Dollar,
     DotDot,
     Equals,
     EqualsEquals,
@@ -73,14 +94,18 @@ pub enum Tok<'input> {
     LeftParen,
     LessThan,
     Period,
+    PeriodLeftBrace,
+    PeriodLeftParen,
     Plus,
     Question,
     RightBrace,
     RightBracket,
     RightParen,
     Semi,
+    Sigma,
     Star,
     TildeTilde,
+    Times,
     Underscore,
 }
 
@@ -92,47 +117,63 @@ impl<'input> Display for Tok<'input> {
             &Def => write!(formatter, "def"),
             &Axiom => write!(formatter, "axiom"),
             &End => write!(formatter, "end"),
+            &Export => write!(formatter, "export"),
             &Extern => write!(formatter, "extern"),
+            &Fix => write!(formatter, "fix"),
             &Forall => write!(formatter, "forall"),
             &Fun => write!(formatter, "fun"),
             &Inductive => write!(formatter, "inductive"),
             &In => write!(formatter, "in"),
             &Import => write!(formatter, "import"),
             &Let => write!(formatter, "let"),
+            &Macro => write!(formatter, "macro"),
             &Match => write!(formatter, "match"),
             &Module => write!(formatter, "module"),
+            &On => write!(formatter, "on"),
             &Type => write!(formatter, "type"),
+            &Variables => write!(formatter, "variables"),
             &With => write!(formatter, "with"),
             &Id(id) => write!(formatter, "{}", id),
             &DocComment(_) => panic!(),
             &StringLiteral(lit) => write!(formatter, "{}", lit),
             &At => write!(formatter, "@"),
+            &AtLeftBracket => write!(formatter, "@["),
             &Arrow => write!(formatter, "->"),
             &Bar => write!(formatter, "|"),
+            &BacktickLeftParen => write!(formatter, "`("),
             &BangEquals => write!(formatter, "!="),
             &Colon => write!(formatter, ":"),
             &ColonColon => write!(formatter, "::"),
             &ColonEq => write!(formatter, ":="),
             &Comma => write!(formatter, ","),
+            &Dollar => write!(formatter, "$"),
             &DotDot => write!(formatter, ".."),
             &Equals => write!(formatter, "="),
             &EqualsEquals => write!(formatter, "=="),
             &FatArrow => write!(formatter, "=>"),
             &Hash => write!(formatter, "#"),
+            &HashEval => write!(formatter, "#eval"),
+            &HashExit => write!(formatter, "#exit"),
+            &HashPrint => write!(formatter, "#print"),
+            &HashReduce => write!(formatter, "#reduce"),
             &GreaterThan => write!(formatter, ">"),
             &LeftBrace => write!(formatter, "{{"),
             &LeftBracket => write!(formatter, "["),
             &LeftParen => write!(formatter, "("),
             &LessThan => write!(formatter, "<"),
             &Period => write!(formatter, "."),
+            &PeriodLeftBrace => write!(formatter, ".{{"),
+            &PeriodLeftParen => write!(formatter, ".("),
             &Plus => write!(formatter, "+"),
             &Question => write!(formatter, "?"),
             &RightBrace => write!(formatter, "}}"),
             &RightBracket => write!(formatter, "]"),
             &RightParen => write!(formatter, ")"),
             &Semi => write!(formatter, ";"),
+            &Sigma => write!(formatter, "Σ"),
             &Star => write!(formatter, "*"),
             &TildeTilde => write!(formatter, "~~"),
+            &Times => write!(formatter, "×"),
             &Underscore => write!(formatter, "_"),
         }
     }
@@ -143,6 +184,8 @@ pub struct Tokenizer<'input> {
     chars: CharIndices<'input>,
     lookahead: Option<(usize, char)>,
     shift: usize, // offset into the input
+    /// The `--` comments skipped so far, without their leading dashes.
+    comments: Vec<Spanned<&'input str>>,
 }
 
 macro_rules! eof {
@@ -157,19 +200,44 @@ const KEYWORDS: &'static [(&'static str, Tok<'static>)] = &[
     ("def", Def),
     ("axiom", Axiom),
     ("end", End),
+    ("export", Export),
     ("extern", Extern),
+    ("fix", Fix),
     ("forall", Forall),
     ("fun", Fun),
     ("in", In),
     ("inductive", Inductive),
     ("import", Import),
     ("let", Let),
+    ("macro", Macro),
     ("match", Match),
     ("module", Module),
+    ("on", On),
     ("Type", Type),
+    ("variables", Variables),
     ("with", With),
 ];
 
+/// Unicode symbols that are read as a single token, `→`, `∀`, and `λ` are
+/// alternatives to `->`, `forall`, and `fun`.
+///
+/// Both `λ` and `Σ` may start an identifier so these are checked first,
+/// they can still appear later in an identifier.
+const SYMBOLS: &'static [(char, Tok<'static>)] = &[
+    ('→', Arrow),
+    ('∀', Forall),
+    ('λ', Fun),
+    ('Σ', Sigma),
+    ('×', Times),
+];
+
+const COMMANDS: &'static [(&'static str, Tok<'static>)] = &[
+    ("eval", HashEval),
+    ("exit", HashExit),
+    ("print", HashPrint),
+    ("reduce", HashReduce),
+];
+
 impl<'input> Tokenizer<'input> {
     pub fn new(text: &'input str, shift: usize) -> Tokenizer<'input> {
         let mut t = Tokenizer {
@@ -177,6 +245,7 @@ impl<'input> Tokenizer<'input> {
             chars: text.char_indices(),
             lookahead: None,
             shift: shift,
+            comments: vec![],
         };
         t.bump();
         t
@@ -221,6 +290,15 @@ impl<'input> Tokenizer<'input> {
                             self.bump();
                             Some(Ok((idx0, DotDot, idx1+1)))
                         }
+                        // The start of an erased binder.
+                        Some((idx1, '(')) => {
+                            self.bump();
+                            Some(Ok((idx0, PeriodLeftParen, idx1+1)))
+                        }
+                        Some((idx1, '{')) => {
+                            self.bump();
+                            Some(Ok((idx0, PeriodLeftBrace, idx1+1)))
+                        }
                         _ => {
                             Some(Ok((idx0, Period, idx0+1)))
                         }
@@ -242,8 +320,14 @@ impl<'input> Tokenizer<'input> {
                     }
                 }
                 Some((idx0, '#')) => {
-                    self.bump();
-                    Some(Ok((idx0, Hash, idx0+1)))
+                    match self.bump() {
+                        Some((idx1, c)) if is_identifier_start(c) => {
+                            Some(self.command(idx0, idx1))
+                        }
+                        _ => {
+                            Some(Ok((idx0, Hash, idx0+1)))
+                        }
+                    }
                 }
                 Some((idx0, '>')) => {
                     self.bump();
@@ -266,8 +350,15 @@ impl<'input> Tokenizer<'input> {
                     Some(Ok((idx0, LessThan, idx0+1)))
                 }
                 Some((idx0, '@')) => {
-                    self.bump();
-                    Some(Ok((idx0, At, idx0+1)))
+                    match self.bump() {
+                        Some((idx1, '[')) => {
+                            self.bump();
+                            Some(Ok((idx0, AtLeftBracket, idx1+1)))
+                        }
+                        _ => {
+                            Some(Ok((idx0, At, idx0+1)))
+                        }
+                    }
                 }
                 Some((idx0, '+')) => {
                     self.bump();
@@ -287,7 +378,12 @@ impl<'input> Tokenizer<'input> {
                                 // This case makes it feel like we will need to move away
                                 // from LALRPOP at some point towards a custom parser.
                                 _ => {
-                                    self.take_until(|c| c == '\n');
+                                    let end = self.take_until(|c| c == '\n')
+                                                  .unwrap_or(self.text.len());
+                                    let comment = (idx0 + self.shift,
+                                                   &self.text[idx0 + 2..end],
+                                                   end + self.shift);
+                                    self.comments.push(comment);
                                     continue;
                                 }
                             }
@@ -297,6 +393,31 @@ impl<'input> Tokenizer<'input> {
                         }
                     }
                 }
+                Some((idx0, '/')) => {
+                    match (self.bump(), self.bump()) {
+                        (Some((_, '-')), Some((idx1, '-'))) => {
+                            Some(self.block_doc_comment(idx0, idx1 + 1))
+                        }
+                        _ => {
+                            Some(error(UnrecognizedToken, idx0))
+                        }
+                    }
+                }
+                Some((idx0, '`')) => {
+                    match self.bump() {
+                        Some((idx1, '(')) => {
+                            self.bump();
+                            Some(Ok((idx0, BacktickLeftParen, idx1+1)))
+                        }
+                        _ => {
+                            Some(error(UnrecognizedToken, idx0))
+                        }
+                    }
+                }
+                Some((idx0, '$')) => {
+                    self.bump();
+                    Some(Ok((idx0, Dollar, idx0+1)))
+                }
                 Some((idx0, '?')) => {
                     self.bump();
                     Some(Ok((idx0, Question, idx0+1)))
@@ -344,6 +465,13 @@ impl<'input> Tokenizer<'input> {
                     self.bump();
                     Some(Ok((idx0, Bar, idx0+1)))
                 }
+                Some((idx0, '«')) => {
+                    self.bump();
+                    Some(self.escaped_identifier(idx0))
+                }
+                Some((idx0, c)) if is_symbol(c) => {
+                    Some(self.symbol(idx0, c))
+                }
                 Some((idx0, c)) if is_identifier_start(c) => {
                     Some(self.identifierish(idx0))
                 }
@@ -361,24 +489,32 @@ impl<'input> Tokenizer<'input> {
         }
     }
 
+    /// The `--` comments in the input read so far.
+    pub fn comments(&self) -> &[Spanned<&'input str>] {
+        &self.comments
+    }
+
     // Pops one character of input
     fn bump(&mut self) -> Option<(usize, char)> {
         self.lookahead = self.chars.next();
         self.lookahead
     }
 
-    // parses a multiline doc comment starting at idx0
+    // parses a multiline doc comment whose text starts at idx0, just after
+    // the `--|`, the token spans every line but not the final newline
     // --| This is an
     // -- example doc comment
     fn doc_comment(&mut self, idx0: usize) -> Result<Spanned<Tok<'input>>, Error> {
         let mut comment = String::new();
         let mut off = idx0;
+        let mut hi = idx0;
         match self.take_until(|c| c == '\n') {
                 Some(idx1) => {
                     self.bump(); // get rid of the newline
                     let r = &self.text[off .. idx1];
                     comment.push_str(r);
                     off = idx1 + 1;
+                    hi = idx1;
                 },
                 None => {
                     try!(error(UnfinishedComment, idx0));
@@ -395,8 +531,10 @@ impl<'input> Tokenizer<'input> {
                 Some(idx1) => {
                     self.bump(); // get rid of the newline
                     let r = &self.text[idx0 .. idx1];
+                    comment.push('\n');
                     comment.push_str(r);
                     off = idx1 +1;
+                    hi = idx1;
                 },
                 None => {
                     try!(error(UnfinishedComment, idx0));
@@ -404,7 +542,33 @@ impl<'input> Tokenizer<'input> {
             }
         }
 
-        Ok((0, DocComment(comment), 1))
+        Ok((idx0 - "--|".len(), DocComment(comment), hi))
+    }
+
+    // parses a block doc comment, `/--` has been read and the text of the
+    // comment starts at `start`
+    // /-- This is an
+    //     example doc comment -/
+    fn block_doc_comment(&mut self, idx0: usize, start: usize)
+                         -> Result<Spanned<Tok<'input>>, Error> {
+        self.bump(); // skip the second -
+
+        loop {
+            match self.take_until(|c| c == '-') {
+                None => return error(UnfinishedComment, idx0),
+                Some(idx1) => {
+                    if let Some((idx2, '/')) = self.bump() {
+                        self.bump();
+                        let comment = self.text[start..idx1]
+                                          .lines()
+                                          .map(|line| line.trim())
+                                          .collect::<Vec<_>>()
+                                          .join("\n");
+                        return Ok((idx0, DocComment(comment), idx2 + 1));
+                    }
+                }
+            }
+        }
     }
 
     fn string_literal(&mut self, idx0: usize) -> Result<Spanned<Tok<'input>>, Error> {
@@ -434,9 +598,50 @@ impl<'input> Tokenizer<'input> {
         }
     }
 
+    /// A token spelt with a single unicode character, its span covers each
+    /// of the character's bytes.
+    fn symbol(&mut self, idx0: usize, c: char) -> Result<Spanned<Tok<'input>>, Error> {
+        self.bump();
+
+        match SYMBOLS.iter().find(|&&(s, _)| s == c) {
+            None => error(UnrecognizedToken, idx0),
+            Some(&(_, ref tok)) => Ok((idx0, tok.clone(), idx0 + c.len_utf8())),
+        }
+    }
+
+    /// An identifier written between `«` and `»`, which may be a keyword or
+    /// contain any character other than `»` and a newline.
+    fn escaped_identifier(&mut self, idx0: usize) -> Result<Spanned<Tok<'input>>, Error> {
+        let start = idx0 + '«'.len_utf8();
+
+        match self.take_until(|c| c == '»' || c == '\n') {
+            Some(idx1) if self.text[idx1..].starts_with('»') && idx1 > start => {
+                self.bump();
+                Ok((idx0, Id(&self.text[start..idx1]), idx1 + '»'.len_utf8()))
+            }
+            _ => error(UnterminatedIdentifier, idx0),
+        }
+    }
+
+    /// The character after the lookahead.
+    fn peek(&self) -> Option<char> {
+        self.chars.clone().next().map(|(_, c)| c)
+    }
+
     fn identifierish(&mut self, idx0: usize) -> Result<Spanned<Tok<'input>>, Error> {
         let (start, word, end) = self.word(idx0);
 
+        // A raw identifier, `r#match`, is never a keyword.
+        if word == "r" {
+            if let Some((idx1, '#')) = self.lookahead {
+                if self.peek().map_or(false, is_identifier_start) {
+                    self.bump();
+                    let (_, raw, end) = self.word(idx1 + 1);
+                    return Ok((start, Id(raw), end));
+                }
+            }
+        }
+
         let tok =
             KEYWORDS.iter()
                     .filter(|&&(w, _)| w == word)
@@ -449,6 +654,17 @@ impl<'input> Tokenizer<'input> {
         Ok((start, tok, end))
     }
 
+    /// A command such as `#eval`, the `#` is at `idx0` and the command's
+    /// name starts at `idx1`.
+    fn command(&mut self, idx0: usize, idx1: usize) -> Result<Spanned<Tok<'input>>, Error> {
+        let (_, word, end) = self.word(idx1);
+
+        match COMMANDS.iter().find(|&&(w, _)| w == word) {
+            None => error(UnknownCommand, idx0),
+            Some(&(_, ref tok)) => Ok((idx0, tok.clone(), end)),
+        }
+    }
+
     fn word(&mut self, idx0: usize) -> Spanned<&'input str> {
         match self.take_while(is_identifier_continue) {
             Some(end) => (idx0, &self.text[idx0..end], end),
@@ -497,6 +713,54 @@ impl<'input> Iterator for Tokenizer<'input> {
     }
 }
 
+fn is_symbol(c: char) -> bool {
+    SYMBOLS.iter().any(|&(s, _)| s == c)
+}
+
+/// Whether `s` can be written as an identifier without escaping it.
+fn is_plain_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+
+    let starts_identifier = match chars.next() {
+        Some(c) => is_identifier_start(c) && !is_symbol(c),
+        None => false,
+    };
+
+    starts_identifier &&
+        chars.all(is_identifier_continue) &&
+        !KEYWORDS.iter().any(|&(w, _)| w == s)
+}
+
+/// Write a component of a name so that it reads back as the same component,
+/// keywords and components that are not identifiers, such as those of
+/// generated names, are escaped as `«component»`.
+pub fn escape_identifier(s: &str) -> Cow<str> {
+    if is_plain_identifier(s) {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(format!("«{}»", s))
+    }
+}
+
+/// Split a dotted name written with `escape_identifier` into its components.
+pub fn split_qualified(s: &str) -> Vec<String> {
+    let mut components = vec![];
+    let mut component = String::new();
+    let mut escaped = false;
+
+    for c in s.chars() {
+        match c {
+            '«' if !escaped => escaped = true,
+            '»' if escaped => escaped = false,
+            '.' if !escaped => components.push(::std::mem::replace(&mut component, String::new())),
+            c => component.push(c),
+        }
+    }
+
+    components.push(component);
+    components
+}
+
 fn is_identifier_start(c: char) -> bool {
     UnicodeXID::is_xid_start(c)
 }
@@ -39,6 +39,10 @@ pub trait Visitor<'v> : Sized {
         walk_case(self, case)
     }
 
+    fn visit_equation(&mut self, equation: &'v Equation) {
+        walk_equation(self, equation)
+    }
+
     fn visit_pattern(&mut self, pattern: &'v Pattern) {
         walk_pattern(self, pattern)
     }
@@ -66,8 +70,35 @@ pub fn walk_item<'v, V: Visitor<'v>>(visitor: &mut V, item: &'v Item) {
         &Item::Def(ref def) => visitor.visit_def(def),
         &Item::Axiom(ref a) => visitor.visit_axiom(a),
         &Item::Extern(ref ext) => visitor.visit_extern(ext),
-        &Item::Comment(ref _s) => panic!(),
+        &Item::Comment(_) => {}
         &Item::Import(ref n) => visitor.visit_name(n),
+        &Item::Export(ref export) => {
+            visitor.visit_span(&export.span);
+            visitor.visit_name(&export.module);
+            for name in &export.names {
+                visitor.visit_name(name);
+            }
+        }
+        &Item::Variables(ref vars) => {
+            visitor.visit_span(&vars.span);
+            for binder in &vars.binders {
+                visitor.visit_binder(binder);
+            }
+        }
+        &Item::Macro(ref mac) => {
+            visitor.visit_span(&mac.span);
+            visitor.visit_name(&mac.name);
+            for rule in &mac.rules {
+                visitor.visit_span(&rule.span);
+                visitor.visit_pattern(&rule.pattern);
+                visitor.visit_term(&rule.template);
+            }
+        }
+        &Item::Command(ref cmd) => {
+            visitor.visit_span(&cmd.span);
+            visitor.visit_term(&cmd.term);
+        }
+        &Item::Exit(ref span) => visitor.visit_span(span),
     }
 }
 
@@ -85,6 +116,16 @@ pub fn walk_inductive<'v, V: Visitor<'v>>(visitor: &mut V, inductive: &'v Induct
         visitor.visit_name(n);
         visitor.visit_term(t);
     }
+
+    if let Some(ref rec) = inductive.recursive {
+        visitor.visit_span(&rec.span);
+        visitor.visit_name(&rec.name);
+        visitor.visit_term(&rec.ty);
+
+        for case in &rec.cases {
+            visitor.visit_case(case);
+        }
+    }
 }
 
 pub fn walk_def<'v, V: Visitor<'v>>(visitor: &mut V, def: &'v Def) {
@@ -96,7 +137,24 @@ pub fn walk_def<'v, V: Visitor<'v>>(visitor: &mut V, def: &'v Def) {
     }
 
     visitor.visit_term(&def.ty);
-    visitor.visit_term(&def.body);
+    walk_def_body(visitor, &def.body);
+}
+
+pub fn walk_def_body<'v, V: Visitor<'v>>(visitor: &mut V, body: &'v DefBody) {
+    match body {
+        &DefBody::Term(ref term) => visitor.visit_term(term),
+        &DefBody::Equations(ref equations) => {
+            for equation in equations {
+                visitor.visit_equation(equation);
+            }
+        }
+        &DefBody::Copatterns(ref copatterns) => {
+            for copattern in copatterns {
+                visitor.visit_span(&copattern.span);
+                visitor.visit_term(&copattern.rhs);
+            }
+        }
+    }
 }
 
 pub fn walk_axiom<'v, V: Visitor<'v>>(visitor: &mut V, a: &'v Axiom) {
@@ -108,6 +166,11 @@ pub fn walk_axiom<'v, V: Visitor<'v>>(visitor: &mut V, a: &'v Axiom) {
 pub fn walk_extern<'v, V: Visitor<'v>>(visitor: &mut V, ext: &'v Extern) {
     visitor.visit_span(&ext.span);
     visitor.visit_name(&ext.name);
+
+    for binder in &ext.args {
+        visitor.visit_binder(binder);
+    }
+
     visitor.visit_term(&ext.term);
 }
 
@@ -157,7 +220,28 @@ pub fn walk_term<'v, V: Visitor<'v>>(visitor: &mut V, term: &'v Term) {
         }
         &Let { ref span, ref bindings, ref body } => {
             visitor.visit_span(span);
-            panic!()
+            for &(ref binder, ref value) in bindings {
+                visitor.visit_binder(binder);
+                visitor.visit_term(value);
+            }
+
+            visitor.visit_term(body);
+        }
+        &Fix { ref span, ref name, ref args, ref ret_ty, ref rec_arg, ref body } => {
+            visitor.visit_span(span);
+            visitor.visit_name(name);
+            for binder in args {
+                visitor.visit_binder(binder);
+            }
+
+            visitor.visit_term(ret_ty);
+            visitor.visit_name(rec_arg);
+            visitor.visit_term(body);
+        }
+        &Quote { ref span, ref term } |
+        &Antiquote { ref span, ref term } => {
+            visitor.visit_span(span);
+            visitor.visit_term(term);
         }
         &Type => {}
     }
@@ -168,6 +252,7 @@ pub fn walk_case<'v, V: Visitor<'v>>(visitor: &mut V, case: &'v Case) {
         ref span,
         ref pattern,
         ref rhs,
+        ..
     } = case;
 
     visitor.visit_span(span);
@@ -175,6 +260,22 @@ pub fn walk_case<'v, V: Visitor<'v>>(visitor: &mut V, case: &'v Case) {
     visitor.visit_term(rhs);
 }
 
+pub fn walk_equation<'v, V: Visitor<'v>>(visitor: &mut V, equation: &'v Equation) {
+    visitor.visit_span(&equation.span);
+    visitor.visit_pattern(&equation.pattern);
+
+    match equation.rhs {
+        EquationRhs::Term(ref rhs) => visitor.visit_term(rhs),
+        EquationRhs::With(ref scrutinee, ref equations) => {
+            visitor.visit_term(scrutinee);
+
+            for equation in equations {
+                visitor.visit_equation(equation);
+            }
+        }
+    }
+}
+
 pub fn walk_pattern<'v, V: Visitor<'v>>(visitor: &mut V, pattern: &'v Pattern) {
     use ast::Pattern::*;
 
@@ -39,6 +39,10 @@ pub trait VisitorMut<'v> : Sized {
         walk_mut_case(self, case);
     }
 
+    fn visit_mut_equation(&mut self, equation: &'v mut Equation) {
+        walk_mut_equation(self, equation);
+    }
+
     fn visit_mut_pattern(&mut self, pattern: &'v mut Pattern) {
         walk_mut_pattern(self, pattern)
     }
@@ -64,9 +68,36 @@ pub fn walk_mut_item<'v, V: VisitorMut<'v>>(visitor: &mut V, item: &'v mut Item)
         &mut Item::Inductive(ref mut d) => visitor.visit_mut_data(d),
         &mut Item::Def(ref mut def) => visitor.visit_mut_def(def),
         &mut Item::Axiom(ref mut a) => visitor.visi_mut_axiom(a),
-        &mut Item::Extern(ref mut ext) => panic!(),
-        &mut Item::Comment(ref mut s) => panic!(),
+        &mut Item::Extern(ref mut ext) => visitor.visit_mut_extern(ext),
+        &mut Item::Comment(_) => {}
         &mut Item::Import(ref mut n) => visitor.visit_mut_name(n),
+        &mut Item::Export(ref mut export) => {
+            visitor.visit_mut_span(&mut export.span);
+            visitor.visit_mut_name(&mut export.module);
+            for name in &mut export.names {
+                visitor.visit_mut_name(name);
+            }
+        }
+        &mut Item::Variables(ref mut vars) => {
+            visitor.visit_mut_span(&mut vars.span);
+            for binder in &mut vars.binders {
+                visitor.visit_mut_binder(binder);
+            }
+        }
+        &mut Item::Macro(ref mut mac) => {
+            visitor.visit_mut_span(&mut mac.span);
+            visitor.visit_mut_name(&mut mac.name);
+            for rule in &mut mac.rules {
+                visitor.visit_mut_span(&mut rule.span);
+                visitor.visit_mut_pattern(&mut rule.pattern);
+                visitor.visit_mut_term(&mut rule.template);
+            }
+        }
+        &mut Item::Command(ref mut cmd) => {
+            visitor.visit_mut_span(&mut cmd.span);
+            visitor.visit_mut_term(&mut cmd.term);
+        }
+        &mut Item::Exit(ref mut span) => visitor.visit_mut_span(span),
     }
 }
 
@@ -84,6 +115,16 @@ pub fn walk_mut_inductive<'v, V: VisitorMut<'v>>(visitor: &mut V, inductive: &'v
         visitor.visit_mut_name(n);
         visitor.visit_mut_term(t);
     }
+
+    if let Some(ref mut rec) = inductive.recursive {
+        visitor.visit_mut_span(&mut rec.span);
+        visitor.visit_mut_name(&mut rec.name);
+        visitor.visit_mut_term(&mut rec.ty);
+
+        for case in &mut rec.cases {
+            visitor.visit_mut_case(case);
+        }
+    }
 }
 
 pub fn walk_mut_def<'v, V: VisitorMut<'v>>(visitor: &mut V, def: &'v mut Def) {
@@ -95,7 +136,24 @@ pub fn walk_mut_def<'v, V: VisitorMut<'v>>(visitor: &mut V, def: &'v mut Def) {
     }
 
     visitor.visit_mut_term(&mut def.ty);
-    visitor.visit_mut_term(&mut def.body);
+    walk_mut_def_body(visitor, &mut def.body);
+}
+
+pub fn walk_mut_def_body<'v, V: VisitorMut<'v>>(visitor: &mut V, body: &'v mut DefBody) {
+    match body {
+        &mut DefBody::Term(ref mut term) => visitor.visit_mut_term(term),
+        &mut DefBody::Equations(ref mut equations) => {
+            for equation in equations {
+                visitor.visit_mut_equation(equation);
+            }
+        }
+        &mut DefBody::Copatterns(ref mut copatterns) => {
+            for copattern in copatterns {
+                visitor.visit_mut_span(&mut copattern.span);
+                visitor.visit_mut_term(&mut copattern.rhs);
+            }
+        }
+    }
 }
 
 pub fn walk_mut_axiom<'v, V: VisitorMut<'v>>(visitor: &mut V, a: &'v mut Axiom) {
@@ -107,6 +165,11 @@ pub fn walk_mut_axiom<'v, V: VisitorMut<'v>>(visitor: &mut V, a: &'v mut Axiom)
 pub fn walk_mut_extern<'v, V: VisitorMut<'v>>(visitor: &mut V, ext: &'v mut Extern) {
     visitor.visit_mut_span(&mut ext.span);
     visitor.visit_mut_name(&mut ext.name);
+
+    for binder in &mut ext.args {
+        visitor.visit_mut_binder(binder);
+    }
+
     visitor.visit_mut_term(&mut ext.term);
 }
 
@@ -157,7 +220,29 @@ pub fn walk_mut_term<'v, V: VisitorMut<'v>>(visitor: &mut V, term: &'v mut Term)
         }
         &mut Let { ref mut span, ref mut bindings, ref mut body } => {
             visitor.visit_mut_span(span);
-            panic!()
+            for &mut (ref mut binder, ref mut value) in bindings {
+                visitor.visit_mut_binder(binder);
+                visitor.visit_mut_term(value);
+            }
+
+            visitor.visit_mut_term(body);
+        }
+        &mut Fix { ref mut span, ref mut name, ref mut args, ref mut ret_ty,
+                   ref mut rec_arg, ref mut body } => {
+            visitor.visit_mut_span(span);
+            visitor.visit_mut_name(name);
+            for binder in args {
+                visitor.visit_mut_binder(binder);
+            }
+
+            visitor.visit_mut_term(ret_ty);
+            visitor.visit_mut_name(rec_arg);
+            visitor.visit_mut_term(body);
+        }
+        &mut Quote { ref mut span, ref mut term } |
+        &mut Antiquote { ref mut span, ref mut term } => {
+            visitor.visit_mut_span(span);
+            visitor.visit_mut_term(term);
         }
         &mut Type => {}
     }
@@ -168,6 +253,7 @@ pub fn walk_mut_case<'v, V: VisitorMut<'v>>(visitor: &mut V, case: &'v mut Case)
         ref mut span,
         ref mut pattern,
         ref mut rhs,
+        ..
     } = case;
 
     visitor.visit_mut_span(span);
@@ -175,6 +261,22 @@ pub fn walk_mut_case<'v, V: VisitorMut<'v>>(visitor: &mut V, case: &'v mut Case)
     visitor.visit_mut_term(rhs);
 }
 
+pub fn walk_mut_equation<'v, V: VisitorMut<'v>>(visitor: &mut V, equation: &'v mut Equation) {
+    visitor.visit_mut_span(&mut equation.span);
+    visitor.visit_mut_pattern(&mut equation.pattern);
+
+    match equation.rhs {
+        EquationRhs::Term(ref mut rhs) => visitor.visit_mut_term(rhs),
+        EquationRhs::With(ref mut scrutinee, ref mut equations) => {
+            visitor.visit_mut_term(scrutinee);
+
+            for equation in equations {
+                visitor.visit_mut_equation(equation);
+            }
+        }
+    }
+}
+
 pub fn walk_mut_pattern<'v, V: VisitorMut<'v>>(visitor: &mut V, pattern: &'v mut Pattern) {
     use ast::Pattern::*;
 